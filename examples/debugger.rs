@@ -0,0 +1,107 @@
+use deciders_rs::debug::Debugger;
+
+mod bulb {
+    use deciders_rs::deciders::Decider;
+
+    pub enum Command {}
+
+    #[derive(Debug)]
+    pub enum Event {
+        Fitted { max_uses: u64 },
+        SwitchedOn,
+        SwitchedOff,
+    }
+
+    #[derive(Clone, Debug)]
+    pub enum State {
+        NotFitted,
+        Working { on: bool, remaining_uses: u64 },
+    }
+
+    pub struct Bulb;
+
+    // The history is already recorded, so only `evolve` is ever called by the debugger.
+    impl Decider<Command, Event, State, State> for Bulb {
+        fn decide(command: &Command, _state: &State) -> Vec<Event> {
+            match *command {}
+        }
+
+        fn evolve(state: &State, event: &Event) -> State {
+            match (state, event) {
+                (State::NotFitted, Event::Fitted { max_uses }) => State::Working {
+                    on: false,
+                    remaining_uses: *max_uses,
+                },
+                (State::Working { remaining_uses, .. }, Event::SwitchedOn) => State::Working {
+                    on: true,
+                    // A bug: a bulb with no uses left wraps around instead of blowing.
+                    remaining_uses: remaining_uses.wrapping_sub(1),
+                },
+                (State::Working { remaining_uses, .. }, Event::SwitchedOff) => State::Working {
+                    on: false,
+                    remaining_uses: *remaining_uses,
+                },
+                _ => state.clone(),
+            }
+        }
+
+        fn initial_state() -> State {
+            State::NotFitted
+        }
+
+        fn is_terminal(_state: &State) -> bool {
+            false
+        }
+    }
+}
+
+fn main() {
+    use bulb::{Event, State};
+
+    // A recorded history in which the bulb was switched on once more than it should have been.
+    let mut history = vec![Event::Fitted { max_uses: 3 }];
+    for _ in 0..4 {
+        history.extend([Event::SwitchedOn, Event::SwitchedOff]);
+    }
+
+    let mut debugger = Debugger::<bulb::Command, Event, State, bulb::Bulb>::new(&history);
+    debugger.break_when(|state, event| {
+        matches!(
+            (state, event),
+            (
+                State::Working {
+                    on: false,
+                    remaining_uses: 0
+                },
+                Event::SwitchedOn
+            )
+        )
+    });
+
+    match debugger.run_until_break() {
+        Some(index) => {
+            println!(
+                "Breakpoint hit at event {index}: {:?}",
+                debugger.current_event()
+            );
+            println!("  state before: {:?}", debugger.current_state());
+            debugger.step_forward();
+            println!("  state after:  {:?}", debugger.current_state());
+
+            // Step back past the breakpoint to look at the events that led up to it.
+            debugger.run_to(index);
+            while debugger.step_back() && debugger.position() + 3 > index {
+                println!(
+                    "  event {}: {:?} applied to {:?}",
+                    debugger.position(),
+                    debugger.current_event(),
+                    debugger.current_state()
+                );
+            }
+        }
+        None => println!(
+            "No breakpoint hit, final state: {:?}",
+            debugger.current_state()
+        ),
+    }
+}
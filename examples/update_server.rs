@@ -55,6 +55,8 @@ mod update_decider {
         DownloadUpdate { desired_version: String },
     }
 
+    // The payloads of some states are only ever produced, never read, by this example.
+    #[allow(dead_code)]
     #[derive(Clone)]
     pub enum State {
         NewConnection,
@@ -0,0 +1,22 @@
+//! Decodes untrusted bytes as a stream of events that may contain unknown events, which must
+//! return an error rather than panic, and checks that decoded streams encode back to the same
+//! events.
+
+#![no_main]
+
+use deciders_rs::utilities::{fold_known, try_fold_known, MaybeUnknown};
+use deciders_rs_fuzz::{Bulb, Command, Event, State};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(events) = serde_json::from_slice::<Vec<MaybeUnknown<Event>>>(data) else {
+        return;
+    };
+    let _ = fold_known::<Command, Event, State, Bulb>(&events, |_, _| {});
+    let _ = try_fold_known::<Command, Event, State, Bulb>(&events);
+
+    let encoded = serde_json::to_vec(&events).expect("decoded events encode");
+    let decoded: Vec<MaybeUnknown<Event>> =
+        serde_json::from_slice(&encoded).expect("encoded events decode");
+    assert_eq!(decoded, events);
+});
@@ -0,0 +1,44 @@
+//! Verifies and restores arbitrary snapshots against arbitrary, possibly shuffled, event streams,
+//! which must always produce a report or a state rather than panic.
+//!
+//! A snapshot of the current schema that verifies as consistent must restore to the same state
+//! as a full replay, and any stored JSON that cannot be used must fall back to a full replay.
+
+#![no_main]
+
+use deciders_rs::deciders::{Decider, Snapshot, SnapshotSchema};
+use deciders_rs::stores::{
+    verify_snapshot, EventStore, InMemoryEventStore, InMemorySnapshotStore, SnapshotStore,
+};
+use deciders_rs::utilities::{InMemoryRunner, StreamVersion};
+use deciders_rs_fuzz::{Bulb, BulbSnapshot, Command, Event, State};
+use libfuzzer_sys::fuzz_target;
+
+type Runner = InMemoryRunner<Command, Event, State, Bulb>;
+
+fuzz_target!(|input: (Snapshot<BulbSnapshot>, Vec<Event>, String)| {
+    let (snapshot, events, raw) = input;
+    let replayed = Bulb::fold(&events);
+
+    let mut store = InMemoryEventStore::new();
+    let mut snapshots = InMemorySnapshotStore::new();
+    store
+        .append("bulb", StreamVersion::INITIAL, &events)
+        .expect("an empty stream accepts any events");
+    snapshots
+        .save("bulb", &snapshot)
+        .expect("snapshots are saved in memory");
+    let report = verify_snapshot::<Command, Event, State, Bulb, _, _>(&store, &snapshots, "bulb")
+        .expect("in-memory stores do not fail");
+    let stored = serde_json::to_value(&snapshot).expect("snapshots encode");
+    let restored = Runner::restore(&stored, &events);
+    if report.is_consistent() && snapshot.schema_version == Bulb::SCHEMA_VERSION {
+        assert_eq!(*restored.get_state(), replayed);
+    }
+
+    if let Ok(stored) = serde_json::from_str(&raw) {
+        let _ = Runner::restore(&stored, &events);
+    }
+    let unusable = serde_json::Value::String(raw);
+    assert_eq!(*Runner::restore(&unusable, &events).get_state(), replayed);
+});
@@ -0,0 +1,22 @@
+//! Folds arbitrary streams mixing old and current events through an `UpcastingDecider`, which
+//! must give the same state as folding the upcast events directly.
+
+#![no_main]
+
+use deciders_rs::deciders::{Decider, EventUpcaster, UpcastingDecider};
+use deciders_rs::utilities::Either;
+use deciders_rs_fuzz::{Bulb, Command, Event, EventV1, State, V1ToCurrent};
+use libfuzzer_sys::fuzz_target;
+
+type UpcastingBulb = UpcastingDecider<Bulb, V1ToCurrent, Command, EventV1, Event, State, State>;
+
+fuzz_target!(|events: Vec<Either<EventV1, Event>>| {
+    let upcast: Vec<Event> = events
+        .iter()
+        .map(|event| match event {
+            Either::Left(old) => V1ToCurrent::upcast(old),
+            Either::Right(event) => *event,
+        })
+        .collect();
+    assert_eq!(UpcastingBulb::fold(&events), Bulb::fold(&upcast));
+});
@@ -0,0 +1,16 @@
+//! Feeds untrusted JSON commands to a `WasmRunner`, which must reject the ones it cannot decode
+//! with an error rather than panic.
+
+#![no_main]
+
+use deciders_rs::wasm::WasmRunner;
+use deciders_rs_fuzz::{Bulb, Command, Event, State};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|commands: Vec<String>| {
+    let mut runner = WasmRunner::<Command, Event, State, Bulb>::new();
+    for command in &commands {
+        let _ = runner.command(command);
+    }
+    runner.state().expect("the state encodes");
+});
@@ -0,0 +1,163 @@
+//! The bulb decider from the integration tests, shared by the fuzz targets.
+//!
+//! Unlike the bulb in the tests, this one never panics: fuzzed streams can fit a bulb twice or
+//! switch it on with no uses left, so `decide` ignores commands that do not apply and `evolve`
+//! saturates the remaining uses instead of underflowing.
+
+use arbitrary::Arbitrary;
+use deciders_rs::decider;
+use deciders_rs::deciders::{
+    EventUpcaster, MigrateError, SnapshotMigrator, SnapshotSchema, Snapshotting,
+};
+
+#[derive(Arbitrary, Copy, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Command {
+    Fit { max_uses: u64 },
+    SwitchOn,
+    SwitchOff,
+}
+
+#[derive(Arbitrary, Copy, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Event {
+    Fitted { max_uses: u64 },
+    SwitchedOn,
+    SwitchedOff,
+    Blew,
+}
+
+#[derive(
+    Arbitrary, Copy, Clone, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum Status {
+    On,
+    Off,
+}
+
+#[derive(
+    Arbitrary, Copy, Clone, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum State {
+    NotFitted,
+    Working { status: Status, remaining_uses: u64 },
+    Blown,
+}
+
+pub struct Bulb;
+
+decider! {
+    Bulb[Command, Event, State];
+    decide(command, state) {
+        (Command::Fit { max_uses }, State::NotFitted) => {
+            vec![Event::Fitted {
+                max_uses: *max_uses,
+            }]
+        }
+        (
+            Command::SwitchOn,
+            State::Working {
+                status: Status::Off,
+                remaining_uses,
+            },
+        ) => {
+            if *remaining_uses > 0 {
+                vec![Event::SwitchedOn]
+            } else {
+                vec![Event::Blew]
+            }
+        }
+        (
+            Command::SwitchOff,
+            State::Working {
+                status: Status::On, ..
+            },
+        ) => vec![Event::SwitchedOff],
+        _ => Vec::new(),
+    }
+    evolve(state, event) {
+        (State::NotFitted, Event::Fitted { max_uses }) => State::Working {
+            status: Status::Off,
+            remaining_uses: *max_uses,
+        },
+        (State::Working { remaining_uses, .. }, Event::SwitchedOn) => State::Working {
+            status: Status::On,
+            remaining_uses: remaining_uses.saturating_sub(1),
+        },
+        (State::Working { remaining_uses, .. }, Event::SwitchedOff) => State::Working {
+            status: Status::Off,
+            remaining_uses: *remaining_uses,
+        },
+        (State::Working { .. }, Event::Blew) => State::Blown,
+        _ => *state,
+    }
+    initial_state() {
+        State::NotFitted
+    }
+    is_terminal(state) {
+        matches!(*state, State::Blown)
+    }
+}
+
+/// The snapshot of a bulb: whether it is on and how many uses it has left if it is working, or
+/// whether it has blown otherwise.
+pub type BulbSnapshot = Result<(bool, u64), bool>;
+
+impl Snapshotting<State> for Bulb {
+    type Snapshot = BulbSnapshot;
+
+    fn to_snapshot(state: &State) -> Self::Snapshot {
+        match *state {
+            State::NotFitted => Err(false),
+            State::Working {
+                status,
+                remaining_uses,
+            } => Ok((status == Status::On, remaining_uses)),
+            State::Blown => Err(true),
+        }
+    }
+
+    fn from_snapshot(snapshot: &Self::Snapshot) -> State {
+        match *snapshot {
+            Err(false) => State::NotFitted,
+            Ok((on, remaining_uses)) => State::Working {
+                status: if on { Status::On } else { Status::Off },
+                remaining_uses,
+            },
+            Err(true) => State::Blown,
+        }
+    }
+}
+
+impl SnapshotSchema<State> for Bulb {
+    const SCHEMA_VERSION: u32 = 2;
+}
+
+/// The first schema stored the remaining uses of working bulbs only.
+impl SnapshotMigrator<State> for Bulb {
+    fn migrate(version: u32, raw: serde_json::Value) -> Result<Self::Snapshot, MigrateError> {
+        let uses: Option<u64> = serde_json::from_value(raw).map_err(|e| MigrateError {
+            schema_version: version,
+            reason: e.to_string(),
+        })?;
+        Ok(uses.map(|uses| (false, uses)).ok_or(false))
+    }
+}
+
+/// The first schema of the events, which had no maximum number of uses.
+#[derive(Arbitrary, Copy, Clone, Debug, PartialEq)]
+pub enum EventV1 {
+    Installed,
+    TurnedOn,
+    TurnedOff,
+}
+
+pub struct V1ToCurrent;
+
+impl EventUpcaster<EventV1, Event> for V1ToCurrent {
+    fn upcast(event: &EventV1) -> Event {
+        match event {
+            EventV1::Installed => Event::Fitted { max_uses: 3 },
+            EventV1::TurnedOn => Event::SwitchedOn,
+            EventV1::TurnedOff => Event::SwitchedOff,
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::{fmt::Debug, marker::PhantomData};
+
+use crate::deciders::Decider;
+
+/// How many events apart the states cached by a [`Debugger`] are.
+const CHECKPOINT_INTERVAL: usize = 32;
+
+/// A breakpoint of a [`Debugger`], given the state before an event and the event.
+type Breakpoint<'a, S, E> = Box<dyn Fn(&S, &E) -> bool + 'a>;
+
+/// A step debugger for folding a recorded list of events into the state of the decider `D`.
+///
+/// The debugger has a position in the list of events: every event before the position has been
+/// applied to the current state, and the event at the position, the current event, is the next
+/// one to be applied. It can be moved forwards and backwards, and run until a breakpoint matches.
+///
+/// To keep stepping backwards cheap on long histories, the debugger caches the state every few
+/// events, and moving backwards replays events from the closest cached state instead of from the
+/// beginning.
+pub struct Debugger<'a, C, E, S, D>
+where
+    D: Decider<C, E, S, S>,
+{
+    events: &'a [E],
+    position: usize,
+    state: S,
+    checkpoints: Vec<S>,
+    breakpoints: Vec<Breakpoint<'a, S, E>>,
+    stopped_at: Option<usize>,
+    command: PhantomData<C>,
+    decider: PhantomData<D>,
+}
+
+impl<'a, C, E, S, D> Debugger<'a, C, E, S, D>
+where
+    S: Clone,
+    D: Decider<C, E, S, S>,
+{
+    /// Constructs a new `Debugger` over the events `events`, positioned before the first event,
+    /// with the initial state of the decider.
+    pub fn new(events: &'a [E]) -> Self {
+        let state = D::initial_state();
+        Self {
+            events,
+            position: 0,
+            checkpoints: vec![state.clone()],
+            state,
+            breakpoints: vec![],
+            stopped_at: None,
+            command: PhantomData,
+            decider: PhantomData,
+        }
+    }
+
+    /// Returns the number of events that have been applied to the current state.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Returns the state after applying every event before the current position.
+    pub fn current_state(&self) -> &S {
+        &self.state
+    }
+
+    /// Returns the event at the current position, which is the next event to be applied, or
+    /// `None` if every event has been applied.
+    pub fn current_event(&self) -> Option<&'a E> {
+        self.events.get(self.position)
+    }
+
+    /// Applies the current event and moves to the next one.
+    ///
+    /// Returns `false` without doing anything if every event has already been applied.
+    pub fn step_forward(&mut self) -> bool {
+        let Some(event) = self.current_event() else {
+            return false;
+        };
+        self.state = D::evolve(&self.state, event);
+        self.position += 1;
+        if self.position.is_multiple_of(CHECKPOINT_INTERVAL)
+            && self.checkpoints.len() == self.position / CHECKPOINT_INTERVAL
+        {
+            self.checkpoints.push(self.state.clone());
+        }
+        true
+    }
+
+    /// Moves back to the previous event, restoring the state from before it was applied.
+    ///
+    /// Returns `false` without doing anything if no event has been applied.
+    pub fn step_back(&mut self) -> bool {
+        if self.position == 0 {
+            return false;
+        }
+        self.run_to(self.position - 1);
+        true
+    }
+
+    /// Moves to the position `index`, so that the events before it are applied to the current
+    /// state and the event at `index` is the current event.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the number of events.
+    pub fn run_to(&mut self, index: usize) {
+        assert!(
+            index <= self.events.len(),
+            "cannot run to position {index} of {} events",
+            self.events.len()
+        );
+        if index < self.position {
+            let checkpoint = (index / CHECKPOINT_INTERVAL).min(self.checkpoints.len() - 1);
+            self.state = self.checkpoints[checkpoint].clone();
+            self.position = checkpoint * CHECKPOINT_INTERVAL;
+        }
+        while self.position < index {
+            self.step_forward();
+        }
+    }
+
+    /// Adds a breakpoint, which matches an event when `predicate` returns `true` for the state
+    /// before the event and the event.
+    pub fn break_when(&mut self, predicate: impl Fn(&S, &E) -> bool + 'a) {
+        self.breakpoints.push(Box::new(predicate));
+    }
+
+    /// Removes every breakpoint.
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Applies events until the current event matches a breakpoint, stopping before it is
+    /// applied, and returns its index. If the debugger is already stopped at a breakpoint, that
+    /// event is applied first.
+    ///
+    /// Returns `None` if no breakpoint matched, in which case every event has been applied.
+    pub fn run_until_break(&mut self) -> Option<usize> {
+        if self.stopped_at == Some(self.position) {
+            self.step_forward();
+        }
+        while let Some(event) = self.current_event() {
+            if self.breakpoints.iter().any(|b| b(&self.state, event)) {
+                self.stopped_at = Some(self.position);
+                return self.stopped_at;
+            }
+            self.step_forward();
+        }
+        self.stopped_at = None;
+        None
+    }
+}
+
+impl<C, E, S, D> Debug for Debugger<'_, C, E, S, D>
+where
+    S: Debug,
+    D: Decider<C, E, S, S>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Debugger")
+            .field("position", &self.position)
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
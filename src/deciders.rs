@@ -1,6 +1,16 @@
-use std::{collections::HashMap, marker::PhantomData};
+use std::{
+    any::{Any, TypeId},
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    marker::PhantomData,
+};
 
-use crate::utilities::{Either, FallibleConverter, InfallibleConverter};
+use crate::utilities::{
+    Either, FallibleConverter, InfallibleConverter, OneOf3, OneOf4, StreamVersion,
+};
+
+pub mod laws;
 
 /// A trait representing a Decider
 ///
@@ -19,6 +29,11 @@ pub trait Decider<C, E, So, Si> {
     fn decide(command: &C, state: &Si) -> Vec<E>;
 
     /// Given the current state `state` and an event `event`, return the new state of this decider.
+    ///
+    /// Events are read back from storage, which can hold events this decider would not decide in
+    /// `state`, such as after a bug or a manual repair of a stream. `evolve` should not panic on
+    /// them: use checked or saturating arithmetic, and return the state unchanged for events that
+    /// do not apply to it.
     fn evolve(state: &Si, event: &E) -> So;
 
     /// Returns the initial state of this decider.
@@ -27,327 +42,4344 @@ pub trait Decider<C, E, So, Si> {
     /// Given the current state of this decider `state`, return whether or not this decider has
     /// reached an end state.
     fn is_terminal(state: &Si) -> bool;
-}
 
-/// A type that combines two deciders, `D1` and `D2`, into a single decider.
-///
-/// The commands and events become instances of the Either type, allowing for passing commands and
-/// events, and receiving events from either decider. The states become tuples of both deciders'
-/// states, in order to keep the states of both at the same time.
-///
-/// Each decider must have a singular state type, i.e. `Si` == `So` for both deciders, but those
-/// types can be different between both deciders.
-pub struct ComposedDeciders<D1, C1, E1, S1, D2, C2, E2, S2> {
-    decider1: PhantomData<D1>,
-    command1: PhantomData<C1>,
-    event1: PhantomData<E1>,
-    state1: PhantomData<S1>,
-    decider2: PhantomData<D2>,
-    command2: PhantomData<C2>,
-    event2: PhantomData<E2>,
-    state2: PhantomData<S2>,
-}
+    /// Given an incoming command `command` and the current state of this decider `state`, returns
+    /// an iterator over the events that the command and state combination generates.
+    ///
+    /// By default this iterates over the vector returned by [`Decider::decide`]. Combinators
+    /// decide through this method, so that the events of nested deciders are only collected into
+    /// a vector once, by the outermost one.
+    ///
+    /// The iterator does not borrow `command` or `state`, so adapters can decide with converted
+    /// commands and states that they own without collecting the events. Implementations declare
+    /// this with `use<..>`, listing the type parameters of the implementation.
+    fn decide_iter(command: &C, state: &Si) -> impl Iterator<Item = E> + use<Self, C, E, So, Si> {
+        Self::decide(command, state).into_iter()
+    }
 
-impl<D1, C1, E1, S1, D2, C2, E2, S2> Decider<Either<C1, C2>, Either<E1, E2>, (S1, S2), (S1, S2)>
-    for ComposedDeciders<D1, C1, E1, S1, D2, C2, E2, S2>
-where
-    S1: Copy + Clone,
-    S2: Copy + Clone,
-    D1: Decider<C1, E1, S1, S1>,
-    D2: Decider<C2, E2, S2, S2>,
-{
-    fn decide(command: &Either<C1, C2>, state: &(S1, S2)) -> Vec<Either<E1, E2>> {
-        match command {
-            Either::Left(l) => D1::decide(l, &state.0)
-                .into_iter()
-                .map(Either::Left)
-                .collect(),
-            Either::Right(r) => D2::decide(r, &state.1)
-                .into_iter()
-                .map(Either::Right)
-                .collect(),
-        }
+    /// Given the current state `state`, which it takes ownership of, and an event `event`, return
+    /// the new state of this decider.
+    ///
+    /// By default this calls [`Decider::evolve`] with a reference to `state`. Deciders with large
+    /// states can override it to update `state` in place instead of copying it for every event;
+    /// [`fold`](Decider::fold), [`fold_events`] and the runners evolve through this method.
+    fn evolve_owned(state: Si, event: &E) -> So {
+        Self::evolve(&state, event)
     }
 
-    fn evolve(state: &(S1, S2), event: &Either<E1, E2>) -> (S1, S2) {
-        match event {
-            Either::Left(e) => (D1::evolve(&state.0, e), state.1),
-            Either::Right(e) => (state.0, D2::evolve(&state.1, e)),
-        }
+    /// Evolves the state `state` in place with the event `event`.
+    ///
+    /// By default this replaces `state` with the result of [`Decider::evolve`], so `state` is left
+    /// unchanged if evolving panics. Deciders whose states should never be copied, such as very
+    /// large ones, can override it, usually alongside [`Decider::evolve_owned`]. The runners and
+    /// [`decide_and_evolve`](Decider::decide_and_evolve) evolve through this method.
+    fn evolve_in_place(state: &mut Si, event: &E)
+    where
+        So: Into<Si>,
+    {
+        *state = Self::evolve(state, event).into();
     }
 
-    fn initial_state() -> (S1, S2) {
-        (D1::initial_state(), D2::initial_state())
+    /// Decides each command in `commands` in order, starting from the state `state` and evolving
+    /// it with the events of each command before deciding the next, and returns the events of
+    /// each command.
+    ///
+    /// Commands after the decider has reached a terminal state are still decided against that
+    /// state, which usually generates no events.
+    fn decide_all(commands: &[C], state: &Si) -> Vec<Vec<E>>
+    where
+        So: Into<Si>,
+        Si: Clone,
+    {
+        let mut state = state.clone();
+        commands
+            .iter()
+            .map(|command| {
+                let events = Self::decide(command, &state);
+                evolve_all_in_place(&mut state, &events, Self::evolve_in_place);
+                events
+            })
+            .collect()
     }
 
-    fn is_terminal(state: &(S1, S2)) -> bool {
-        D1::is_terminal(&state.0) && D2::is_terminal(&state.1)
+    /// Returns the state of this decider after evolving its initial state with every event in
+    /// `events`, in order.
+    ///
+    /// If `events` is empty, this is the initial state.
+    fn fold(events: &[E]) -> Si
+    where
+        Self: Sized,
+        So: Into<Si>,
+    {
+        fold_events::<C, E, So, Si, Self>(Self::initial_state().into(), events)
+    }
+
+    /// Given an incoming command `command` and the current state `state`, returns the events the
+    /// command generates along with the state after evolving `state` with each of them, in order.
+    ///
+    /// If the command generates no events, the returned state is a copy of `state`.
+    fn decide_and_evolve(command: &C, state: &Si) -> (Vec<E>, Si)
+    where
+        Self: Sized,
+        So: Into<Si>,
+        Si: Clone,
+    {
+        let events = Self::decide(command, state);
+        let mut state = state.clone();
+        evolve_all_in_place(&mut state, &events, Self::evolve_in_place);
+        (events, state)
     }
 }
 
-/// A type for using `N` instances of the same decider type, giving each a name.
+/// A trait representing a Decider that can reject commands with an error.
 ///
-/// This allows for running an indeterminate number of the same decider in parallel, where each has
-/// a simple string name to refer to it. The commands and events become tuples of the name and
-/// command or event, while the state becomes a HashMap mapping each name to the current state of
-/// the decider with that name.
-pub struct ManyDecider<D, C, E, S>
-where
-    S: Copy + Clone,
-    D: Decider<C, E, S, S>,
-{
+/// This is the same as [`Decider`], except that deciding returns a [`Result`], so a command that
+/// is invalid for the current state can be rejected with a typed error instead of generating no
+/// events. Any [`Decider`] can be used as a `TryDecider` by wrapping it in [`Lifted`].
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait TryDecider<C, E, So, Si> {
+    /// The type of error returned when a command is rejected.
+    type Error;
+
+    /// Given an incoming command `command` and the current state of this decider `state`, output a
+    /// vector of events that the command and state combination generates, or an error if the
+    /// command is rejected.
+    fn decide(command: &C, state: &Si) -> Result<Vec<E>, Self::Error>;
+
+    /// Given the current state `state` and an event `event`, return the new state of this decider.
+    fn evolve(state: &Si, event: &E) -> So;
+
+    /// Returns the initial state of this decider.
+    fn initial_state() -> So;
+
+    /// Given the current state of this decider `state`, return whether or not this decider has
+    /// reached an end state.
+    fn is_terminal(state: &Si) -> bool;
+
+    /// Evolves the state `state` in place with the event `event`, as in
+    /// [`Decider::evolve_in_place`].
+    fn evolve_in_place(state: &mut Si, event: &E)
+    where
+        So: Into<Si>,
+    {
+        *state = Self::evolve(state, event).into();
+    }
+}
+
+/// Implements [`TryDecider`] for the decider `D`, which never rejects a command.
+pub struct Lifted<D> {
     decider: PhantomData<D>,
-    command: PhantomData<C>,
-    event: PhantomData<E>,
-    state: PhantomData<S>,
 }
 
-impl<D, C, E, S> Decider<(String, C), (String, E), HashMap<String, S>, HashMap<String, S>>
-    for ManyDecider<D, C, E, S>
+impl<D, C, E, So, Si> TryDecider<C, E, So, Si> for Lifted<D>
 where
-    S: Copy + Clone,
-    D: Decider<C, E, S, S>,
+    D: Decider<C, E, So, Si>,
 {
-    fn decide((id, command): &(String, C), states: &HashMap<String, S>) -> Vec<(String, E)> {
-        let state = match states.get(id) {
-            Some(s) => *s,
-            None => D::initial_state(),
-        };
-        D::decide(command, &state)
-            .into_iter()
-            .map(|e| (id.clone(), e))
-            .collect()
+    type Error = std::convert::Infallible;
+
+    fn decide(command: &C, state: &Si) -> Result<Vec<E>, Self::Error> {
+        Ok(D::decide(command, state))
     }
 
-    fn evolve(states: &HashMap<String, S>, (id, event): &(String, E)) -> HashMap<String, S> {
-        let state = match states.get(id) {
-            Some(s) => *s,
-            None => D::initial_state(),
-        };
-        let new_state = D::evolve(&state, event);
-        let mut states = states.clone();
-        states.insert(id.clone(), new_state);
-        states
+    fn evolve(state: &Si, event: &E) -> So {
+        D::evolve(state, event)
     }
 
-    fn initial_state() -> HashMap<String, S> {
-        HashMap::new()
+    fn initial_state() -> So {
+        D::initial_state()
     }
 
-    fn is_terminal(states: &HashMap<String, S>) -> bool {
-        states.values().all(|s| D::is_terminal(s))
+    fn is_terminal(state: &Si) -> bool {
+        D::is_terminal(state)
+    }
+
+    fn evolve_in_place(state: &mut Si, event: &E)
+    where
+        So: Into<Si>,
+    {
+        D::evolve_in_place(state, event)
     }
 }
 
-/// Adapts a decider to use different input and output types.
+/// A trait for rejecting commands of type `C` that are invalid for a decider in the state `S`,
+/// with a rejection of type `R`, for a [`RejectingDecider`].
 ///
-/// This type allows for converting a decider that implements `Decider<Cd, Ed, Sd, Sd>` into a
-/// decider that implements `Decider<Cn, En, Sd, Sn>`.
-///
-/// It does this by taking 4 additional "converter" types, that can convert to and from the
-/// decider's native types into the new types:
-///
-/// - `CC` - The command converter that implements [`FallibleConverter`], which converts the *new* command
-///   type into the decider's *native* command type, with the option of failure.
-/// - `ENC` - The event converter that implements [`FallibleConverter`], which converts the *new* event
-///   type into the decider's *native* event type, with the option of failure.
-/// - `EDC` - The event converter that implements [`InfallibleConverter`], which converts the decider's
-///   *native* event type into the *new* event type.
-/// - `SC` - The state converter that implements [`InfallibleConverter`], which converts the *new*
-///   state type into the decider's *native* state type.
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait Validator<C, R, S> {
+    /// Returns a rejection if the command `command` is invalid in the state `state`.
+    fn validate(command: &C, state: &S) -> Result<(), R>;
+}
+
+/// Wraps the decider `D`, which validates its own commands with [`Validator`], so that rejected
+/// commands produce a rejection of type `R` instead of no events.
 ///
-/// > *NOTE*: The output states are still in the decider's native state type. If this is not desired,
-/// > then this can be combines with [`MappedDecider`] to modify the output type to the desired type.
-pub struct AdaptedDecider<D, CC, ENC, EDC, SC, En, Ed, Cn, Cd, Sn, Sd>
-where
-    D: Decider<Cd, Ed, Sd, Sd>,
-    CC: FallibleConverter<Cn, Cd>,
-    ENC: FallibleConverter<En, Ed>,
-    EDC: InfallibleConverter<Ed, En>,
-    SC: InfallibleConverter<Sn, Sd>,
-{
+/// Events are output as [`Either::Left`] and rejections as [`Either::Right`], so rejections
+/// travel alongside events, but are never evolved: evolving a rejection leaves the state
+/// unchanged. A command is only decided by `D` if it is valid.
+pub struct RejectingDecider<D, R> {
     decider: PhantomData<D>,
-    command_converter: PhantomData<CC>,
-    event_new_converter: PhantomData<ENC>,
-    event_decider_converter: PhantomData<EDC>,
-    state_converter: PhantomData<SC>,
-    event_new: PhantomData<En>,
-    event_decider: PhantomData<Ed>,
-    command_new: PhantomData<Cn>,
-    command_decider: PhantomData<Cd>,
-    state_new: PhantomData<Sn>,
-    state_decider: PhantomData<Sd>,
+    rejection: PhantomData<R>,
 }
 
-impl<D, CC, ENC, EDC, SC, En, Ed, Cn, Cd, Sn, Sd> Decider<Cn, En, Sd, Sn>
-    for AdaptedDecider<D, CC, ENC, EDC, SC, En, Ed, Cn, Cd, Sn, Sd>
+impl<D, C, E, R, S> Decider<C, Either<E, R>, S, S> for RejectingDecider<D, R>
 where
-    D: Decider<Cd, Ed, Sd, Sd>,
-    CC: FallibleConverter<Cn, Cd>,
-    ENC: FallibleConverter<En, Ed>,
-    EDC: InfallibleConverter<Ed, En>,
-    SC: InfallibleConverter<Sn, Sd>,
+    D: Decider<C, E, S, S> + Validator<C, R, S>,
+    S: Clone,
 {
-    fn decide(command: &Cn, state: &Sn) -> Vec<En> {
-        match CC::convert(command) {
-            Some(c) => D::decide(&c, &SC::convert(state))
+    fn decide(command: &C, state: &S) -> Vec<Either<E, R>> {
+        match D::validate(command, state) {
+            Ok(()) => D::decide(command, state)
                 .into_iter()
-                .map(|e| EDC::convert(&e))
+                .map(Either::Left)
                 .collect(),
-            None => vec![],
+            Err(rejection) => vec![Either::Right(rejection)],
         }
     }
 
-    fn evolve(state: &Sn, event: &En) -> Sd {
-        match ENC::convert(event) {
-            Some(e) => D::evolve(&SC::convert(state), &e),
-            None => SC::convert(state),
+    fn evolve(state: &S, event: &Either<E, R>) -> S {
+        match event {
+            Either::Left(e) => D::evolve(state, e),
+            Either::Right(_) => state.clone(),
         }
     }
 
-    fn initial_state() -> Sd {
+    fn initial_state() -> S {
         D::initial_state()
     }
 
-    fn is_terminal(state: &Sn) -> bool {
-        D::is_terminal(&SC::convert(state))
+    fn is_terminal(state: &S) -> bool {
+        D::is_terminal(state)
+    }
+
+    fn evolve_owned(state: S, event: &Either<E, R>) -> S {
+        match event {
+            Either::Left(e) => D::evolve_owned(state, e),
+            Either::Right(_) => state,
+        }
+    }
+
+    fn evolve_in_place(state: &mut S, event: &Either<E, R>) {
+        if let Either::Left(e) = event {
+            D::evolve_in_place(state, e);
+        }
     }
 }
 
-/// A type to modify the output state type of a decider
+/// A trait for choosing which commands of type `C` a [`FilteredDecider`] passes on to its decider.
 ///
-/// This type takes a Decider and an additional `SC` type, which implements the
-/// [`InfallibleConverter`] trait to convert the decider's native output state type into a new
-/// output state type.
-pub struct MappedDecider<D, SC, C, E, Sn, Sdo, Sdi>
-where
-    D: Decider<C, E, Sdo, Sdi>,
-    SC: InfallibleConverter<Sdo, Sn>,
-{
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait Predicate<C> {
+    /// Returns whether the command `command` should be decided.
+    fn accept(command: &C) -> bool;
+}
+
+/// Wraps the decider `D`, deciding no events for the commands the [`Predicate`] `P` does not
+/// accept, without reaching `D`.
+///
+/// Events are evolved as usual, whichever commands they were decided for, so filtering commands
+/// never changes how a stream of events is replayed.
+pub struct FilteredDecider<D, P> {
     decider: PhantomData<D>,
-    state_converter: PhantomData<SC>,
-    command: PhantomData<C>,
-    event: PhantomData<E>,
-    state_new: PhantomData<Sn>,
-    state_decider_out: PhantomData<Sdo>,
-    state_decider_in: PhantomData<Sdi>,
+    predicate: PhantomData<P>,
 }
 
-impl<D, SC, C, E, Sn, Sdo, Sdi> Decider<C, E, Sn, Sdi> for MappedDecider<D, SC, C, E, Sn, Sdo, Sdi>
+impl<D, P, C, E, So, Si> Decider<C, E, So, Si> for FilteredDecider<D, P>
 where
-    D: Decider<C, E, Sdo, Sdi>,
-    SC: InfallibleConverter<Sdo, Sn>,
+    D: Decider<C, E, So, Si>,
+    P: Predicate<C>,
 {
-    fn decide(command: &C, state: &Sdi) -> Vec<E> {
+    fn decide(command: &C, state: &Si) -> Vec<E> {
+        if !P::accept(command) {
+            return vec![];
+        }
         D::decide(command, state)
     }
 
-    fn evolve(state: &Sdi, event: &E) -> Sn {
-        SC::convert(&D::evolve(state, event))
+    fn evolve(state: &Si, event: &E) -> So {
+        D::evolve(state, event)
     }
 
-    fn initial_state() -> Sn {
-        SC::convert(&D::initial_state())
+    fn initial_state() -> So {
+        D::initial_state()
     }
 
-    fn is_terminal(state: &Sdi) -> bool {
+    fn is_terminal(state: &Si) -> bool {
         D::is_terminal(state)
     }
+
+    fn evolve_owned(state: Si, event: &E) -> So {
+        D::evolve_owned(state, event)
+    }
+
+    fn evolve_in_place(state: &mut Si, event: &E)
+    where
+        So: Into<Si>,
+    {
+        D::evolve_in_place(state, event)
+    }
 }
 
-/// A type to combine two deciders that take the same input types, and transform their output types
-/// into a common output type.
+/// The reason a [`Guard`] refused a command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GuardFailure {
+    /// Why the command was refused.
+    pub reason: String,
+}
+
+impl GuardFailure {
+    /// Constructs a new `GuardFailure` with the reason `reason`.
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self {
+            reason: reason.into(),
+        }
+    }
+}
+
+impl Display for GuardFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "guard failed: {}", self.reason)
+    }
+}
+
+impl std::error::Error for GuardFailure {}
+
+/// A trait for checking a precondition on commands of type `C` in the state `S` before they reach
+/// the decider of a [`GuardedDecider`].
 ///
-/// This type combines two decider types, simply concatenating the outputs of their decide method.
+/// Guards can be chained with [`AndGuard`].
 ///
-/// It also takes an `SC` type, which must implement [`InfallibleConverter`], which takes as input
-/// the tuple of both decider's output state types, and return a new state type, which is what the
-/// output state type of this decider will be.
-pub struct Map2Deciders<D1, D2, SC, C, E, Si, S1, S2, So>
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait Guard<C, S> {
+    /// Returns a failure if the command `command` must not be decided in the state `state`.
+    fn check(command: &C, state: &S) -> Result<(), GuardFailure>;
+}
+
+/// A guard that passes only when both `G1` and `G2` pass, returning the failure of `G1` first.
+pub struct AndGuard<G1, G2> {
+    first: PhantomData<G1>,
+    second: PhantomData<G2>,
+}
+
+impl<G1, G2, C, S> Guard<C, S> for AndGuard<G1, G2>
 where
-    D1: Decider<C, E, S1, Si>,
-    D2: Decider<C, E, S2, Si>,
-    SC: InfallibleConverter<(S1, S2), So>,
+    G1: Guard<C, S>,
+    G2: Guard<C, S>,
 {
-    decider1: PhantomData<D1>,
-    decider2: PhantomData<D2>,
-    state_combiner: PhantomData<SC>,
-    command: PhantomData<C>,
-    event: PhantomData<E>,
-    state_initial: PhantomData<Si>,
-    state_decider_1: PhantomData<S1>,
-    state_decider_2: PhantomData<S2>,
-    state_output: PhantomData<So>,
+    fn check(command: &C, state: &S) -> Result<(), GuardFailure> {
+        G1::check(command, state)?;
+        G2::check(command, state)
+    }
+}
+
+/// Wraps the decider `D`, only deciding the commands that pass the [`Guard`] `G`.
+///
+/// As a [`Decider`], commands failing the guard produce no events, without reaching `D`. As a
+/// [`TryDecider`], such as in a
+/// [`FallibleInMemoryRunner`](crate::utilities::FallibleInMemoryRunner), they are rejected with
+/// the [`GuardFailure`] instead. Events are evolved as usual.
+pub struct GuardedDecider<D, G> {
+    decider: PhantomData<D>,
+    guard: PhantomData<G>,
 }
 
-impl<D1, D2, SC, C, E, Si, S1, S2, So> Decider<C, E, So, Si>
-    for Map2Deciders<D1, D2, SC, C, E, Si, S1, S2, So>
+impl<D, G, C, E, So, Si> Decider<C, E, So, Si> for GuardedDecider<D, G>
 where
-    D1: Decider<C, E, S1, Si>,
-    D2: Decider<C, E, S2, Si>,
-    SC: InfallibleConverter<(S1, S2), So>,
+    D: Decider<C, E, So, Si>,
+    G: Guard<C, Si>,
 {
     fn decide(command: &C, state: &Si) -> Vec<E> {
-        D1::decide(command, state)
-            .into_iter()
-            .chain(D2::decide(command, state))
-            .collect()
+        match G::check(command, state) {
+            Ok(()) => D::decide(command, state),
+            Err(_) => vec![],
+        }
     }
 
     fn evolve(state: &Si, event: &E) -> So {
-        let s1 = D1::evolve(state, event);
-        let s2 = D2::evolve(state, event);
-        SC::convert(&(s1, s2))
+        D::evolve(state, event)
     }
 
     fn initial_state() -> So {
-        SC::convert(&(D1::initial_state(), D2::initial_state()))
+        D::initial_state()
     }
 
     fn is_terminal(state: &Si) -> bool {
-        D1::is_terminal(state) && D2::is_terminal(state)
+        D::is_terminal(state)
+    }
+
+    fn evolve_owned(state: Si, event: &E) -> So {
+        D::evolve_owned(state, event)
+    }
+
+    fn evolve_in_place(state: &mut Si, event: &E)
+    where
+        So: Into<Si>,
+    {
+        D::evolve_in_place(state, event)
     }
 }
 
-/// This type takes a decider, whose output state is a function, and applies that function to the
-/// output state of a second decider.
-pub struct AppliedDecider<FD, D, C, E, Si, Sd, So>
+impl<D, G, C, E, So, Si> TryDecider<C, E, So, Si> for GuardedDecider<D, G>
 where
-    FD: Decider<C, E, fn(Sd) -> So, Si>,
-    D: Decider<C, E, Sd, Si>,
+    D: Decider<C, E, So, Si>,
+    G: Guard<C, Si>,
 {
-    function_decider: PhantomData<FD>,
-    decider: PhantomData<D>,
+    type Error = GuardFailure;
+
+    fn decide(command: &C, state: &Si) -> Result<Vec<E>, GuardFailure> {
+        G::check(command, state)?;
+        Ok(D::decide(command, state))
+    }
+
+    fn evolve(state: &Si, event: &E) -> So {
+        D::evolve(state, event)
+    }
+
+    fn initial_state() -> So {
+        D::initial_state()
+    }
+
+    fn is_terminal(state: &Si) -> bool {
+        D::is_terminal(state)
+    }
+
+    fn evolve_in_place(state: &mut Si, event: &E)
+    where
+        So: Into<Si>,
+    {
+        D::evolve_in_place(state, event)
+    }
+}
+
+/// A trait representing a Decider that needs a context of type `Ctx`, such as the current time or
+/// a source of fresh ids, to decide commands.
+///
+/// This is the same as [`Decider`], except that deciding also takes the context, so that it does
+/// not have to be smuggled into the commands. A `ContextDecider` can be used as a plain
+/// [`Decider`] by wrapping it in [`ProvidedContext`], which gets the context from a
+/// [`ContextProvider`].
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait ContextDecider<Ctx, C, E, So, Si> {
+    /// Given an incoming command `command`, the current state of this decider `state` and the
+    /// context `context`, output a vector of events that the command and state combination
+    /// generates.
+    fn decide(command: &C, state: &Si, context: &Ctx) -> Vec<E>;
+
+    /// Given the current state `state` and an event `event`, return the new state of this decider.
+    fn evolve(state: &Si, event: &E) -> So;
+
+    /// Returns the initial state of this decider.
+    fn initial_state() -> So;
+
+    /// Given the current state of this decider `state`, return whether or not this decider has
+    /// reached an end state.
+    fn is_terminal(state: &Si) -> bool;
+
+    /// Given the current state `state`, which it takes ownership of, and an event `event`, return
+    /// the new state of this decider, as in [`Decider::evolve_owned`].
+    fn evolve_owned(state: Si, event: &E) -> So {
+        Self::evolve(&state, event)
+    }
+
+    /// Evolves the state `state` in place with the event `event`, as in
+    /// [`Decider::evolve_in_place`].
+    fn evolve_in_place(state: &mut Si, event: &E)
+    where
+        So: Into<Si>,
+    {
+        *state = Self::evolve(state, event).into();
+    }
+}
+
+/// A trait for providing the context of type `Ctx` for a [`ContextDecider`].
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait ContextProvider<Ctx> {
+    /// Returns the current context.
+    fn context() -> Ctx;
+}
+
+/// Implements [`Decider`] for the [`ContextDecider`] `D`, deciding every command with the context
+/// returned by the provider `P` at that time.
+pub struct ProvidedContext<D, P, Ctx> {
+    decider: PhantomData<D>,
+    provider: PhantomData<P>,
+    context: PhantomData<Ctx>,
+}
+
+impl<D, P, Ctx, C, E, So, Si> Decider<C, E, So, Si> for ProvidedContext<D, P, Ctx>
+where
+    D: ContextDecider<Ctx, C, E, So, Si>,
+    P: ContextProvider<Ctx>,
+{
+    fn decide(command: &C, state: &Si) -> Vec<E> {
+        D::decide(command, state, &P::context())
+    }
+
+    fn evolve(state: &Si, event: &E) -> So {
+        D::evolve(state, event)
+    }
+
+    fn initial_state() -> So {
+        D::initial_state()
+    }
+
+    fn is_terminal(state: &Si) -> bool {
+        D::is_terminal(state)
+    }
+
+    fn evolve_owned(state: Si, event: &E) -> So {
+        D::evolve_owned(state, event)
+    }
+
+    fn evolve_in_place(state: &mut Si, event: &E)
+    where
+        So: Into<Si>,
+    {
+        D::evolve_in_place(state, event)
+    }
+}
+
+/// Wraps the decider `D` so that it generates no events once it has reached a terminal state, as
+/// given by [`Decider::is_terminal`].
+///
+/// Events are still evolved as usual, so replaying events written before this guard was added
+/// gives the same state. Wrapping a process in a `TerminalGuard` stops it from reacting once it
+/// has reached a terminal state, such as in a
+/// [`CombinedProcessDecider`](crate::processes::CombinedProcessDecider). Use
+/// [`StrictTerminalGuard`] to reject commands sent to a terminal decider with an error instead.
+pub struct TerminalGuard<D> {
+    decider: PhantomData<D>,
+}
+
+impl<D, C, E, So, Si> Decider<C, E, So, Si> for TerminalGuard<D>
+where
+    D: Decider<C, E, So, Si>,
+{
+    fn decide(command: &C, state: &Si) -> Vec<E> {
+        if D::is_terminal(state) {
+            return vec![];
+        }
+        D::decide(command, state)
+    }
+
+    fn evolve(state: &Si, event: &E) -> So {
+        D::evolve(state, event)
+    }
+
+    fn initial_state() -> So {
+        D::initial_state()
+    }
+
+    fn is_terminal(state: &Si) -> bool {
+        D::is_terminal(state)
+    }
+
+    fn evolve_owned(state: Si, event: &E) -> So {
+        D::evolve_owned(state, event)
+    }
+
+    fn evolve_in_place(state: &mut Si, event: &E)
+    where
+        So: Into<Si>,
+    {
+        D::evolve_in_place(state, event)
+    }
+}
+
+/// An error returned by a [`StrictTerminalGuard`] when a command is sent to a decider that has
+/// reached a terminal state.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TerminalError;
+
+impl Display for TerminalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the decider has reached a terminal state")
+    }
+}
+
+impl std::error::Error for TerminalError {}
+
+/// Implements [`TryDecider`] for the decider `D`, rejecting every command with a
+/// [`TerminalError`] once it has reached a terminal state.
+pub struct StrictTerminalGuard<D> {
+    decider: PhantomData<D>,
+}
+
+impl<D, C, E, So, Si> TryDecider<C, E, So, Si> for StrictTerminalGuard<D>
+where
+    D: Decider<C, E, So, Si>,
+{
+    type Error = TerminalError;
+
+    fn decide(command: &C, state: &Si) -> Result<Vec<E>, TerminalError> {
+        if D::is_terminal(state) {
+            return Err(TerminalError);
+        }
+        Ok(D::decide(command, state))
+    }
+
+    fn evolve(state: &Si, event: &E) -> So {
+        D::evolve(state, event)
+    }
+
+    fn initial_state() -> So {
+        D::initial_state()
+    }
+
+    fn is_terminal(state: &Si) -> bool {
+        D::is_terminal(state)
+    }
+
+    fn evolve_in_place(state: &mut Si, event: &E)
+    where
+        So: Into<Si>,
+    {
+        D::evolve_in_place(state, event)
+    }
+}
+
+/// Wraps the decider `D`, keeping at most `MAX` of the events it decides for each command.
+///
+/// When `D` decides more than `MAX` events, the first `MAX` events are kept, in order, and the
+/// rest are dropped without being decided if `D` decides them lazily with
+/// [`Decider::decide_iter`]. Events are evolved as usual. Wrapping a process in a
+/// `BoundedDecider` keeps at most `MAX` of the commands it issues in each call to `react` or
+/// `resume` in the same way, so that a runaway process cannot flood a
+/// [`CombinedProcessDecider`](crate::processes::CombinedProcessDecider). Use
+/// [`StrictBoundedDecider`] to reject such commands with an error instead.
+pub struct BoundedDecider<D, const MAX: usize> {
+    decider: PhantomData<D>,
+}
+
+impl<D, C, E, So, Si, const MAX: usize> Decider<C, E, So, Si> for BoundedDecider<D, MAX>
+where
+    D: Decider<C, E, So, Si>,
+{
+    fn decide(command: &C, state: &Si) -> Vec<E> {
+        Self::decide_iter(command, state).collect()
+    }
+
+    fn decide_iter(command: &C, state: &Si) -> impl Iterator<Item = E> + use<D, C, E, So, Si, MAX> {
+        D::decide_iter(command, state).take(MAX)
+    }
+
+    fn evolve(state: &Si, event: &E) -> So {
+        D::evolve(state, event)
+    }
+
+    fn initial_state() -> So {
+        D::initial_state()
+    }
+
+    fn is_terminal(state: &Si) -> bool {
+        D::is_terminal(state)
+    }
+
+    fn evolve_owned(state: Si, event: &E) -> So {
+        D::evolve_owned(state, event)
+    }
+
+    fn evolve_in_place(state: &mut Si, event: &E)
+    where
+        So: Into<Si>,
+    {
+        D::evolve_in_place(state, event)
+    }
+}
+
+/// An error returned by a [`StrictBoundedDecider`] when a command is decided into more events than
+/// its limit.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TooManyEvents {
+    /// The most events the decider may decide for one command.
+    pub limit: usize,
+    /// The number of events the command was decided into.
+    pub decided: usize,
+}
+
+impl Display for TooManyEvents {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the decider decided {} events, more than its limit of {}",
+            self.decided, self.limit
+        )
+    }
+}
+
+impl std::error::Error for TooManyEvents {}
+
+/// Implements [`TryDecider`] for the decider `D`, rejecting every command decided into more than
+/// `MAX` events with a [`TooManyEvents`] error, in which case none of the events are kept.
+pub struct StrictBoundedDecider<D, const MAX: usize> {
+    decider: PhantomData<D>,
+}
+
+impl<D, C, E, So, Si, const MAX: usize> TryDecider<C, E, So, Si> for StrictBoundedDecider<D, MAX>
+where
+    D: Decider<C, E, So, Si>,
+{
+    type Error = TooManyEvents;
+
+    fn decide(command: &C, state: &Si) -> Result<Vec<E>, TooManyEvents> {
+        let events = D::decide(command, state);
+        if events.len() > MAX {
+            return Err(TooManyEvents {
+                limit: MAX,
+                decided: events.len(),
+            });
+        }
+        Ok(events)
+    }
+
+    fn evolve(state: &Si, event: &E) -> So {
+        D::evolve(state, event)
+    }
+
+    fn initial_state() -> So {
+        D::initial_state()
+    }
+
+    fn is_terminal(state: &Si) -> bool {
+        D::is_terminal(state)
+    }
+
+    fn evolve_in_place(state: &mut Si, event: &E)
+    where
+        So: Into<Si>,
+    {
+        D::evolve_in_place(state, event)
+    }
+}
+
+/// Wraps the decider `D`, dropping every event it decides that is equal to the event decided
+/// right before it for the same command, so that repeated events collapse into one.
+///
+/// Events equal to events decided for earlier commands are kept. Use [`DedupLastDecider`] to
+/// also drop events equal to the last event evolved.
+pub struct DedupDecider<D> {
+    decider: PhantomData<D>,
+}
+
+impl<D, C, E, So, Si> Decider<C, E, So, Si> for DedupDecider<D>
+where
+    D: Decider<C, E, So, Si>,
+    E: PartialEq,
+{
+    fn decide(command: &C, state: &Si) -> Vec<E> {
+        let mut events = D::decide(command, state);
+        events.dedup();
+        events
+    }
+
+    fn evolve(state: &Si, event: &E) -> So {
+        D::evolve(state, event)
+    }
+
+    fn initial_state() -> So {
+        D::initial_state()
+    }
+
+    fn is_terminal(state: &Si) -> bool {
+        D::is_terminal(state)
+    }
+
+    fn evolve_owned(state: Si, event: &E) -> So {
+        D::evolve_owned(state, event)
+    }
+
+    fn evolve_in_place(state: &mut Si, event: &E)
+    where
+        So: Into<Si>,
+    {
+        D::evolve_in_place(state, event)
+    }
+}
+
+/// Wraps the decider `D` like [`DedupDecider`], also dropping the events equal to the last event
+/// evolved, even if it was decided for an earlier command.
+///
+/// The state of this decider is the state of `D` along with the last event evolved, which is
+/// `None` in the initial state. `evolve` records each event it is given, so replaying the events
+/// decided by this decider restores the same last event.
+pub struct DedupLastDecider<D> {
+    decider: PhantomData<D>,
+}
+
+impl<D, C, E, S> Decider<C, E, (S, Option<E>), (S, Option<E>)> for DedupLastDecider<D>
+where
+    D: Decider<C, E, S, S>,
+    E: Clone + PartialEq,
+{
+    fn decide(command: &C, (state, last): &(S, Option<E>)) -> Vec<E> {
+        let mut events = DedupDecider::<D>::decide(command, state);
+        if events.first().is_some_and(|e| last.as_ref() == Some(e)) {
+            events.remove(0);
+        }
+        events
+    }
+
+    fn evolve((state, _last): &(S, Option<E>), event: &E) -> (S, Option<E>) {
+        (D::evolve(state, event), Some(event.clone()))
+    }
+
+    fn initial_state() -> (S, Option<E>) {
+        (D::initial_state(), None)
+    }
+
+    fn is_terminal((state, _last): &(S, Option<E>)) -> bool {
+        D::is_terminal(state)
+    }
+
+    fn evolve_owned((state, _last): (S, Option<E>), event: &E) -> (S, Option<E>) {
+        (D::evolve_owned(state, event), Some(event.clone()))
+    }
+
+    fn evolve_in_place((state, last): &mut (S, Option<E>), event: &E) {
+        D::evolve_in_place(state, event);
+        *last = Some(event.clone());
+    }
+}
+
+/// Wraps the decider `D`, reporting what it does to [`tracing`](https://docs.rs/tracing).
+///
+/// Every call to `decide` runs in a `decide` span, with the fields `decider`, holding the type name
+/// of `D`, `command`, holding the command's [`Debug`](std::fmt::Debug) representation, and
+/// `events`, holding the number of events decided. Every call to `evolve` emits an `evolve` event
+/// with the `decider` and `event` fields, followed by a `terminal` event with the `decider` and
+/// `state` fields if it moved the decider into a terminal state.
+///
+/// Requires the `tracing` feature. Without it, `InstrumentedDecider<D>` is `D` itself, so it adds
+/// no overhead.
+#[cfg(feature = "tracing")]
+pub struct InstrumentedDecider<D> {
+    decider: PhantomData<D>,
+}
+
+/// Wraps the decider `D`, reporting what it does to [`tracing`](https://docs.rs/tracing).
+///
+/// Requires the `tracing` feature. Without it, `InstrumentedDecider<D>` is `D` itself, so
+/// it adds no overhead.
+#[cfg(not(feature = "tracing"))]
+pub type InstrumentedDecider<D> = D;
+
+/// Reports an event evolved by an [`InstrumentedDecider`], and whether it moved the decider into
+/// a terminal state.
+#[cfg(feature = "tracing")]
+fn trace_evolve<D, C, E, S>(event: &E, was_terminal: bool, state: &S)
+where
+    D: Decider<C, E, S, S>,
+    E: std::fmt::Debug,
+    S: std::fmt::Debug,
+{
+    let decider = std::any::type_name::<D>();
+    tracing::debug!(name: "evolve", decider, event = ?event);
+    if !was_terminal && D::is_terminal(state) {
+        tracing::info!(
+            name: "terminal",
+            decider,
+            state = ?state,
+            "decider reached a terminal state"
+        );
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<D, C, E, S> Decider<C, E, S, S> for InstrumentedDecider<D>
+where
+    D: Decider<C, E, S, S>,
+    C: std::fmt::Debug,
+    E: std::fmt::Debug,
+    S: std::fmt::Debug,
+{
+    fn decide(command: &C, state: &S) -> Vec<E> {
+        let span = tracing::debug_span!(
+            "decide",
+            decider = std::any::type_name::<D>(),
+            command = ?command,
+            events = tracing::field::Empty,
+        );
+        let _entered = span.enter();
+        let events = D::decide(command, state);
+        span.record("events", events.len());
+        events
+    }
+
+    fn evolve(state: &S, event: &E) -> S {
+        let evolved = D::evolve(state, event);
+        trace_evolve::<D, C, E, S>(event, D::is_terminal(state), &evolved);
+        evolved
+    }
+
+    fn initial_state() -> S {
+        D::initial_state()
+    }
+
+    fn is_terminal(state: &S) -> bool {
+        D::is_terminal(state)
+    }
+
+    fn evolve_owned(state: S, event: &E) -> S {
+        let was_terminal = D::is_terminal(&state);
+        let evolved = D::evolve_owned(state, event);
+        trace_evolve::<D, C, E, S>(event, was_terminal, &evolved);
+        evolved
+    }
+
+    fn evolve_in_place(state: &mut S, event: &E) {
+        let was_terminal = D::is_terminal(state);
+        D::evolve_in_place(state, event);
+        trace_evolve::<D, C, E, S>(event, was_terminal, state);
+    }
+}
+
+/// Counts of what a [`CountingDecider`] has done, returned by [`CountingDecider::stats`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeciderStats {
+    /// The number of commands decided.
+    pub commands: u64,
+    /// The number of events decided.
+    pub events: u64,
+    /// The number of commands that were decided into no events.
+    pub empty_decisions: u64,
+    /// The number of events evolved.
+    pub evolves: u64,
+}
+
+thread_local! {
+    static DECIDER_STATS: RefCell<HashMap<TypeId, DeciderStats>> = RefCell::new(HashMap::new());
+}
+
+/// Wraps the decider `D`, counting the commands it decides, the events it decides, the commands
+/// that were decided into no events, and the events it evolves.
+///
+/// Since deciders have no instances, the counts are kept per decider type `D`, and are read with
+/// [`CountingDecider::stats`]. They are kept per thread, so that deciders running on other
+/// threads, such as other tests, do not change them. A `CountingDecider` implements [`Decider`]
+/// with the same types as `D`, so it can be used inside [`ComposedDeciders`] or [`ManyDecider`]
+/// wherever `D` would be.
+pub struct CountingDecider<D> {
+    decider: PhantomData<D>,
+}
+
+impl<D: 'static> CountingDecider<D> {
+    /// Returns the counts for the decider `D` on this thread.
+    pub fn stats() -> DeciderStats {
+        DECIDER_STATS.with(|stats| {
+            stats
+                .borrow()
+                .get(&TypeId::of::<D>())
+                .copied()
+                .unwrap_or_default()
+        })
+    }
+
+    /// Resets the counts for the decider `D` on this thread to zero.
+    pub fn reset() {
+        DECIDER_STATS.with(|stats| stats.borrow_mut().remove(&TypeId::of::<D>()));
+    }
+
+    /// Updates the counts for the decider `D` on this thread.
+    fn count(update: impl FnOnce(&mut DeciderStats)) {
+        DECIDER_STATS
+            .with(|stats| update(stats.borrow_mut().entry(TypeId::of::<D>()).or_default()));
+    }
+}
+
+impl<D, C, E, So, Si> Decider<C, E, So, Si> for CountingDecider<D>
+where
+    D: Decider<C, E, So, Si> + 'static,
+{
+    fn decide(command: &C, state: &Si) -> Vec<E> {
+        let events = D::decide(command, state);
+        Self::count(|stats| {
+            stats.commands += 1;
+            stats.events += events.len() as u64;
+            if events.is_empty() {
+                stats.empty_decisions += 1;
+            }
+        });
+        events
+    }
+
+    fn evolve(state: &Si, event: &E) -> So {
+        Self::count(|stats| stats.evolves += 1);
+        D::evolve(state, event)
+    }
+
+    fn initial_state() -> So {
+        D::initial_state()
+    }
+
+    fn is_terminal(state: &Si) -> bool {
+        D::is_terminal(state)
+    }
+
+    fn evolve_owned(state: Si, event: &E) -> So {
+        Self::count(|stats| stats.evolves += 1);
+        D::evolve_owned(state, event)
+    }
+
+    fn evolve_in_place(state: &mut Si, event: &E)
+    where
+        So: Into<Si>,
+    {
+        Self::count(|stats| stats.evolves += 1);
+        D::evolve_in_place(state, event)
+    }
+}
+
+/// A trait for deciders whose state of type `S` can be saved as a snapshot, so that it can be
+/// restored without replaying every event since the beginning.
+///
+/// The snapshot can be a different type than the state, such as a serializable one, and
+/// restoring a snapshot must give back the same state it was taken from. Combinators propagate
+/// snapshots: the snapshot of [`ComposedDeciders`] is the tuple of both deciders' snapshots, and
+/// the snapshot of [`ManyDecider`] is the map of every decider's snapshot.
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait Snapshotting<S> {
+    /// The type of snapshots of this decider's state.
+    type Snapshot;
+
+    /// Returns a snapshot of the state `state`.
+    fn to_snapshot(state: &S) -> Self::Snapshot;
+
+    /// Returns the state the snapshot `snapshot` was taken from.
+    fn from_snapshot(snapshot: &Self::Snapshot) -> S;
+}
+
+/// A trait for deciders whose snapshots are persisted, giving the version of the snapshot's
+/// schema, so that snapshots written before the schema changed can be told apart.
+///
+/// The version should be increased whenever [`Snapshotting::Snapshot`] changes in a way that
+/// stops older snapshots from deserializing into it.
+pub trait SnapshotSchema<S>: Snapshotting<S> {
+    /// The version of the schema of the snapshots this decider currently writes.
+    const SCHEMA_VERSION: u32;
+}
+
+/// A snapshot of a decider's state as it is persisted: the snapshot itself, the version of its
+/// schema, and the version of the stream it was taken at, which is the number of events it
+/// includes.
+///
+/// With the `serde` feature, a stored snapshot can be read back with `InMemoryRunner::restore`,
+/// which converts snapshots written with an older schema through a `SnapshotMigrator`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot<S> {
+    /// The version of the schema the snapshot was written with.
+    pub schema_version: u32,
+    /// The version of the stream the snapshot was taken at.
+    pub taken_at_version: StreamVersion,
+    /// The snapshot of the state.
+    pub state: S,
+}
+
+impl<S> Snapshot<S> {
+    /// Returns a snapshot of the state `state` of the decider `D`, taken at the stream version
+    /// `taken_at_version`, with the decider's current schema version.
+    pub fn of<D, St>(state: &St, taken_at_version: StreamVersion) -> Self
+    where
+        D: SnapshotSchema<St, Snapshot = S>,
+    {
+        Self {
+            schema_version: D::SCHEMA_VERSION,
+            taken_at_version,
+            state: D::to_snapshot(state),
+        }
+    }
+}
+
+/// An error returned when a snapshot written with an older schema cannot be converted to the
+/// current one.
+///
+/// Requires the `serde` feature.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct MigrateError {
+    /// The schema version of the snapshot that could not be converted.
+    pub schema_version: u32,
+    /// Why the snapshot could not be converted.
+    pub reason: String,
+}
+
+#[cfg(feature = "serde")]
+impl Display for MigrateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "cannot migrate a snapshot of schema version {}: {}",
+            self.schema_version, self.reason
+        )
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for MigrateError {}
+
+/// A trait for deciders that can convert snapshots written with an older schema into their
+/// current [`Snapshotting::Snapshot`].
+///
+/// A snapshot that cannot be converted is not lost data, since the state can always be rebuilt
+/// from the events, so runners fall back to replaying the whole stream when `migrate` returns an
+/// error.
+///
+/// Requires the `serde` feature.
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+#[cfg(feature = "serde")]
+pub trait SnapshotMigrator<S>: SnapshotSchema<S> {
+    /// Converts the snapshot `raw`, written with the older schema version `version`, into a
+    /// snapshot of the current schema.
+    fn migrate(version: u32, raw: serde_json::Value) -> Result<Self::Snapshot, MigrateError>;
+}
+
+/// Returns the state of the decider `D` after evolving the state `initial` with every event in
+/// `events`, in order, such as to bring a snapshot up to date.
+///
+/// When the decider's input and output states differ, each output state is converted back into an
+/// input state before the next event. If `events` is empty, this is `initial`.
+pub fn fold_events<C, E, So, Si, D>(initial: Si, events: &[E]) -> Si
+where
+    So: Into<Si>,
+    D: Decider<C, E, So, Si>,
+{
+    events
+        .iter()
+        .fold(initial, |state, event| D::evolve_owned(state, event).into())
+}
+
+/// Evolves the state `state` in place with every event in `events`, in order, using
+/// `evolve_in_place`, which is the `evolve_in_place` method of a decider.
+///
+/// Every runner and [`Decider::decide_and_evolve`] evolve their state through this function, so
+/// they all apply events the same way.
+pub(crate) fn evolve_all_in_place<S, E>(
+    state: &mut S,
+    events: &[E],
+    evolve_in_place: impl Fn(&mut S, &E),
+) {
+    for event in events {
+        evolve_in_place(state, event);
+    }
+}
+
+/// A trait representing a Decider, with its types given as associated types instead of generic
+/// parameters.
+///
+/// Since a type can only implement this trait once, its command, event and state types are known
+/// from the type alone, so combinators built on this trait, such as [`Composed`], only need the
+/// deciders themselves as parameters.
+///
+/// Every type implementing this trait also implements [`Decider`] with the same types, so it can
+/// be used anywhere a [`Decider`] is expected. A [`Decider`] can be used where a `DeciderAt` is
+/// expected by wrapping it in [`Bridged`].
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait DeciderAt {
+    /// The type of commands this decider takes.
+    type Command;
+    /// The type of events this decider generates.
+    type Event;
+    /// The type of states this decider evolves into.
+    type StateOut;
+    /// The type of states this decider decides and evolves from.
+    type StateIn;
+
+    /// Given an incoming command `command` and the current state of this decider `state`, output a
+    /// vector of events that the command and state combination generates.
+    fn decide(command: &Self::Command, state: &Self::StateIn) -> Vec<Self::Event>;
+
+    /// Given the current state `state` and an event `event`, return the new state of this decider.
+    fn evolve(state: &Self::StateIn, event: &Self::Event) -> Self::StateOut;
+
+    /// Returns the initial state of this decider.
+    fn initial_state() -> Self::StateOut;
+
+    /// Given the current state of this decider `state`, return whether or not this decider has
+    /// reached an end state.
+    fn is_terminal(state: &Self::StateIn) -> bool;
+
+    /// Given an incoming command `command` and the current state of this decider `state`, returns
+    /// an iterator over the events that the command and state combination generates, as in
+    /// [`Decider::decide_iter`].
+    fn decide_iter(
+        command: &Self::Command,
+        state: &Self::StateIn,
+    ) -> impl Iterator<Item = Self::Event> + use<Self> {
+        Self::decide(command, state).into_iter()
+    }
+
+    /// Given the current state `state`, which it takes ownership of, and an event `event`, return
+    /// the new state of this decider, as in [`Decider::evolve_owned`].
+    fn evolve_owned(state: Self::StateIn, event: &Self::Event) -> Self::StateOut {
+        Self::evolve(&state, event)
+    }
+
+    /// Evolves the state `state` in place with the event `event`, as in
+    /// [`Decider::evolve_in_place`].
+    fn evolve_in_place(state: &mut Self::StateIn, event: &Self::Event)
+    where
+        Self::StateOut: Into<Self::StateIn>,
+    {
+        *state = Self::evolve(state, event).into();
+    }
+}
+
+/// Implements [`DeciderAt`] for a decider from its `decide` and `evolve` match arms.
+///
+/// `decider!` takes the decider type with its command, event and state types in brackets,
+/// followed by four clauses, each naming its parameters. The arms of `decide` match on the tuple
+/// `(command, state)` and the arms of `evolve` on the tuple `(state, event)`, both of references,
+/// exactly as they would in a hand-written implementation. Since the arms are spliced into an
+/// ordinary `match`, arms that are missing are reported at the macro call site.
+///
+/// ```
+/// use deciders_rs::{decider, deciders::Decider};
+///
+/// pub enum Command { Toggle }
+/// pub enum Event { Toggled }
+/// #[derive(Clone, Copy, Debug, PartialEq)]
+/// pub enum State { Off, On }
+///
+/// pub struct Switch;
+///
+/// decider! {
+///     Switch[Command, Event, State];
+///     decide(command, state) {
+///         (Command::Toggle, _) => vec![Event::Toggled],
+///     }
+///     evolve(state, event) {
+///         (State::Off, Event::Toggled) => State::On,
+///         (State::On, Event::Toggled) => State::Off,
+///     }
+///     initial_state() { State::Off }
+///     is_terminal(state) { false }
+/// }
+///
+/// assert_eq!(Switch::fold(&[Event::Toggled]), State::On);
+/// ```
+#[macro_export]
+macro_rules! decider {
+    (
+        $decider:ty [$command_ty:ty, $event_ty:ty, $state_ty:ty];
+        decide($command:ident, $decide_state:ident) { $($decide_arms:tt)* }
+        evolve($evolve_state:ident, $event:ident) { $($evolve_arms:tt)* }
+        initial_state() $initial_state:block
+        is_terminal($terminal_state:ident) $is_terminal:block
+    ) => {
+        impl $crate::deciders::DeciderAt for $decider {
+            type Command = $command_ty;
+            type Event = $event_ty;
+            type StateOut = $state_ty;
+            type StateIn = $state_ty;
+
+            fn decide(
+                $command: &$command_ty,
+                $decide_state: &$state_ty,
+            ) -> ::std::vec::Vec<$event_ty> {
+                match ($command, $decide_state) {
+                    $($decide_arms)*
+                }
+            }
+
+            fn evolve($evolve_state: &$state_ty, $event: &$event_ty) -> $state_ty {
+                match ($evolve_state, $event) {
+                    $($evolve_arms)*
+                }
+            }
+
+            fn initial_state() -> $state_ty $initial_state
+
+            #[allow(unused_variables)]
+            fn is_terminal($terminal_state: &$state_ty) -> bool $is_terminal
+        }
+    };
+}
+
+/// Composes any number of deciders into one, with a flat command enum, a flat event enum and a
+/// struct of states.
+///
+/// `compose!` declares the command enum, the event enum, the state struct and the decider
+/// struct, in that order, with any attributes and visibility given to them. Each composed decider
+/// is listed inside the decider struct as `Variant(field): Decider[Command, Event, State]`, and
+/// gets a variant named `Variant` in both enums, wrapping its commands and events, and a field
+/// named `field` in the state struct. Each composed decider must implement
+/// `Decider<Command, Event, State, State>`, and its state must implement [`Clone`].
+///
+/// The composed decider routes each command to the decider of its variant, and evolves only that
+/// decider's state with each event. It has reached an end state when all of the composed deciders
+/// have. This behaves the same as nesting [`ComposedDeciders`], but with readable types.
+///
+/// ```
+/// use deciders_rs::{compose, decider, deciders::Decider};
+///
+/// pub struct Counter;
+/// decider! {
+///     Counter[(), (), u32];
+///     decide(command, state) { _ => vec![()] }
+///     evolve(state, event) { (count, _) => count + 1 }
+///     initial_state() { 0 }
+///     is_terminal(state) { *state >= 2 }
+/// }
+///
+/// compose! {
+///     pub enum Command;
+///     pub enum Event;
+///     #[derive(Clone, Debug, PartialEq)]
+///     pub struct State;
+///     pub struct Counters {
+///         Left(left): Counter[(), (), u32],
+///         Right(right): Counter[(), (), u32],
+///     }
+/// }
+///
+/// let events = Counters::decide(&Command::Left(()), &Counters::initial_state());
+/// let state = Counters::fold(&events);
+/// assert_eq!(state, State { left: 1, right: 0 });
+/// assert!(!Counters::is_terminal(&state));
+/// ```
+#[macro_export]
+macro_rules! compose {
+    (
+        $(#[$command_meta:meta])*
+        $command_vis:vis enum $command:ident;
+        $(#[$event_meta:meta])*
+        $event_vis:vis enum $event:ident;
+        $(#[$state_meta:meta])*
+        $state_vis:vis struct $state:ident;
+        $(#[$decider_meta:meta])*
+        $decider_vis:vis struct $decider:ident {
+            $(
+                $variant:ident($field:ident):
+                    $sub:ty [$sub_command:ty, $sub_event:ty, $sub_state:ty]
+            ),+
+            $(,)?
+        }
+    ) => {
+        $(#[$command_meta])*
+        $command_vis enum $command {
+            $($variant($sub_command),)+
+        }
+
+        $(#[$event_meta])*
+        $event_vis enum $event {
+            $($variant($sub_event),)+
+        }
+
+        $(#[$state_meta])*
+        $state_vis struct $state {
+            $($state_vis $field: $sub_state,)+
+        }
+
+        $(#[$decider_meta])*
+        $decider_vis struct $decider;
+
+        impl $crate::deciders::Decider<$command, $event, $state, $state> for $decider {
+            fn decide(command: &$command, state: &$state) -> ::std::vec::Vec<$event> {
+                match command {
+                    $($command::$variant(command) => {
+                        <$sub as $crate::deciders::Decider<
+                            $sub_command,
+                            $sub_event,
+                            $sub_state,
+                            $sub_state,
+                        >>::decide_iter(command, &state.$field)
+                        .map($event::$variant)
+                        .collect()
+                    })+
+                }
+            }
+
+            fn evolve(state: &$state, event: &$event) -> $state {
+                $state {
+                    $($field: match event {
+                        $event::$variant(event) => <$sub as $crate::deciders::Decider<
+                            $sub_command,
+                            $sub_event,
+                            $sub_state,
+                            $sub_state,
+                        >>::evolve(&state.$field, event),
+                        #[allow(unreachable_patterns)]
+                        _ => ::std::clone::Clone::clone(&state.$field),
+                    },)+
+                }
+            }
+
+            fn initial_state() -> $state {
+                $state {
+                    $($field: <$sub as $crate::deciders::Decider<
+                        $sub_command,
+                        $sub_event,
+                        $sub_state,
+                        $sub_state,
+                    >>::initial_state(),)+
+                }
+            }
+
+            fn is_terminal(state: &$state) -> bool {
+                true $(&& <$sub as $crate::deciders::Decider<
+                    $sub_command,
+                    $sub_event,
+                    $sub_state,
+                    $sub_state,
+                >>::is_terminal(&state.$field))+
+            }
+        }
+    };
+}
+
+impl<D> Decider<D::Command, D::Event, D::StateOut, D::StateIn> for D
+where
+    D: DeciderAt,
+{
+    fn decide(command: &D::Command, state: &D::StateIn) -> Vec<D::Event> {
+        <D as DeciderAt>::decide(command, state)
+    }
+
+    fn evolve(state: &D::StateIn, event: &D::Event) -> D::StateOut {
+        <D as DeciderAt>::evolve(state, event)
+    }
+
+    fn initial_state() -> D::StateOut {
+        <D as DeciderAt>::initial_state()
+    }
+
+    fn is_terminal(state: &D::StateIn) -> bool {
+        <D as DeciderAt>::is_terminal(state)
+    }
+
+    fn decide_iter(
+        command: &D::Command,
+        state: &D::StateIn,
+    ) -> impl Iterator<Item = D::Event> + use<D> {
+        <D as DeciderAt>::decide_iter(command, state)
+    }
+
+    fn evolve_owned(state: D::StateIn, event: &D::Event) -> D::StateOut {
+        <D as DeciderAt>::evolve_owned(state, event)
+    }
+
+    fn evolve_in_place(state: &mut D::StateIn, event: &D::Event)
+    where
+        D::StateOut: Into<D::StateIn>,
+    {
+        <D as DeciderAt>::evolve_in_place(state, event)
+    }
+}
+
+/// Implements [`DeciderAt`] for the decider `D`, using the types `C`, `E`, `So` and `Si` it
+/// implements [`Decider`] with.
+pub struct Bridged<D, C, E, So, Si>
+where
+    D: Decider<C, E, So, Si>,
+{
+    decider: PhantomData<D>,
+    command: PhantomData<C>,
+    event: PhantomData<E>,
+    state_out: PhantomData<So>,
+    state_in: PhantomData<Si>,
+}
+
+impl<D, C, E, So, Si> DeciderAt for Bridged<D, C, E, So, Si>
+where
+    D: Decider<C, E, So, Si>,
+{
+    type Command = C;
+    type Event = E;
+    type StateOut = So;
+    type StateIn = Si;
+
+    fn decide(command: &C, state: &Si) -> Vec<E> {
+        D::decide(command, state)
+    }
+
+    fn evolve(state: &Si, event: &E) -> So {
+        D::evolve(state, event)
+    }
+
+    fn initial_state() -> So {
+        D::initial_state()
+    }
+
+    fn is_terminal(state: &Si) -> bool {
+        D::is_terminal(state)
+    }
+
+    fn decide_iter(command: &C, state: &Si) -> impl Iterator<Item = E> + use<D, C, E, So, Si> {
+        D::decide_iter(command, state)
+    }
+
+    fn evolve_owned(state: Si, event: &E) -> So {
+        D::evolve_owned(state, event)
+    }
+
+    fn evolve_in_place(state: &mut Si, event: &E)
+    where
+        So: Into<Si>,
+    {
+        D::evolve_in_place(state, event)
+    }
+}
+
+/// The [`DeciderAt`] counterpart of [`ComposedDeciders`], combining the deciders `D1` and `D2`.
+///
+/// Each decider must have a singular state type, i.e. `StateIn` == `StateOut` for both deciders.
+/// Every method is forwarded to the equivalent [`ComposedDeciders`], including its
+/// [`TerminalPolicy`] `T`.
+pub struct Composed<D1, D2, T = AllTerminal> {
+    decider1: PhantomData<D1>,
+    decider2: PhantomData<D2>,
+    terminal_policy: PhantomData<T>,
+}
+
+type ComposedAt<D1, D2, T> = ComposedDeciders<
+    D1,
+    <D1 as DeciderAt>::Command,
+    <D1 as DeciderAt>::Event,
+    <D1 as DeciderAt>::StateIn,
+    D2,
+    <D2 as DeciderAt>::Command,
+    <D2 as DeciderAt>::Event,
+    <D2 as DeciderAt>::StateIn,
+    T,
+>;
+
+impl<D1, D2, S1, S2, T> DeciderAt for Composed<D1, D2, T>
+where
+    S1: Clone,
+    S2: Clone,
+    T: TerminalPolicy,
+    D1: DeciderAt<StateIn = S1, StateOut = S1>,
+    D2: DeciderAt<StateIn = S2, StateOut = S2>,
+{
+    type Command = Either<D1::Command, D2::Command>;
+    type Event = Either<D1::Event, D2::Event>;
+    type StateOut = (S1, S2);
+    type StateIn = (S1, S2);
+
+    fn decide(command: &Self::Command, state: &(S1, S2)) -> Vec<Self::Event> {
+        ComposedAt::<D1, D2, T>::decide(command, state)
+    }
+
+    fn decide_iter(
+        command: &Self::Command,
+        state: &(S1, S2),
+    ) -> impl Iterator<Item = Self::Event> + use<D1, D2, S1, S2, T> {
+        ComposedAt::<D1, D2, T>::decide_iter(command, state)
+    }
+
+    fn evolve(state: &(S1, S2), event: &Self::Event) -> (S1, S2) {
+        ComposedAt::<D1, D2, T>::evolve(state, event)
+    }
+
+    fn evolve_owned(state: (S1, S2), event: &Self::Event) -> (S1, S2) {
+        ComposedAt::<D1, D2, T>::evolve_owned(state, event)
+    }
+
+    fn evolve_in_place(state: &mut (S1, S2), event: &Self::Event) {
+        ComposedAt::<D1, D2, T>::evolve_in_place(state, event)
+    }
+
+    fn initial_state() -> (S1, S2) {
+        ComposedAt::<D1, D2, T>::initial_state()
+    }
+
+    fn is_terminal(state: &(S1, S2)) -> bool {
+        ComposedAt::<D1, D2, T>::is_terminal(state)
+    }
+}
+
+/// The neutral decider, with unit commands, events and states, which never generates events and
+/// is always terminal.
+///
+/// It is the identity for [`ComposedDeciders`]: composing a decider `D` with `NeutralDecider`
+/// behaves like `D`, with its commands and events wrapped in [`Either::Left`]. Use [`NeutralFor`]
+/// for a neutral decider with other types.
+pub struct NeutralDecider;
+
+impl Decider<(), (), (), ()> for NeutralDecider {
+    fn decide(_command: &(), _state: &()) -> Vec<()> {
+        vec![]
+    }
+
+    fn evolve(_state: &(), _event: &()) {}
+
+    fn initial_state() {}
+
+    fn is_terminal(_state: &()) -> bool {
+        true
+    }
+}
+
+/// A neutral decider and process with any command and event types, whose state of type `S` is
+/// always the default one.
+///
+/// It never generates events or commands, leaves its state unchanged, and is always terminal, so
+/// it can be used as the other side of a composition when only one side matters, such as the
+/// process of a [`CombinedProcessDecider`](crate::processes::CombinedProcessDecider).
+pub struct NeutralFor<S> {
+    state: PhantomData<S>,
+}
+
+impl<C, E, S> Decider<C, E, S, S> for NeutralFor<S>
+where
+    S: Clone + Default,
+{
+    fn decide(_command: &C, _state: &S) -> Vec<E> {
+        vec![]
+    }
+
+    fn evolve(state: &S, _event: &E) -> S {
+        state.clone()
+    }
+
+    fn initial_state() -> S {
+        S::default()
+    }
+
+    fn is_terminal(_state: &S) -> bool {
+        true
+    }
+}
+
+/// A policy deciding whether a combination of two deciders is terminal, given whether each of
+/// them is terminal, for combinators such as [`ComposedDeciders`] and [`Map2Deciders`].
+///
+/// Combinations of more than two deciders, such as [`Composed3Deciders`], apply the policy to
+/// each decider in turn, so they are terminal when the policy holds for the combination of the
+/// first two deciders and the third.
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait TerminalPolicy {
+    /// Returns whether the combination is terminal, given whether the first decider is terminal,
+    /// `first`, and whether the second decider is terminal, `second`.
+    fn is_terminal(first: bool, second: bool) -> bool;
+}
+
+/// The default [`TerminalPolicy`], where a combination is terminal only when both deciders are
+/// terminal.
+pub struct AllTerminal;
+
+impl TerminalPolicy for AllTerminal {
+    fn is_terminal(first: bool, second: bool) -> bool {
+        first && second
+    }
+}
+
+/// A [`TerminalPolicy`] where a combination is terminal as soon as either decider is terminal.
+pub struct AnyTerminal;
+
+impl TerminalPolicy for AnyTerminal {
+    fn is_terminal(first: bool, second: bool) -> bool {
+        first || second
+    }
+}
+
+/// A type that combines two deciders, `D1` and `D2`, into a single decider.
+///
+/// The commands and events become instances of the Either type, allowing for passing commands and
+/// events, and receiving events from either decider. The states become tuples of both deciders'
+/// states, in order to keep the states of both at the same time.
+///
+/// Each decider must have a singular state type, i.e. `Si` == `So` for both deciders, but those
+/// types can be different between both deciders.
+///
+/// By default, the composed decider is terminal only when both deciders are terminal. This can be
+/// changed with the [`TerminalPolicy`] `T`, e.g. to [`AnyTerminal`] to finish as soon as either
+/// decider is terminal.
+pub struct ComposedDeciders<D1, C1, E1, S1, D2, C2, E2, S2, T = AllTerminal> {
+    decider1: PhantomData<D1>,
+    command1: PhantomData<C1>,
+    event1: PhantomData<E1>,
+    state1: PhantomData<S1>,
+    decider2: PhantomData<D2>,
+    command2: PhantomData<C2>,
+    event2: PhantomData<E2>,
+    state2: PhantomData<S2>,
+    terminal_policy: PhantomData<T>,
+}
+
+impl<D1, C1, E1, S1, D2, C2, E2, S2, T> Decider<Either<C1, C2>, Either<E1, E2>, (S1, S2), (S1, S2)>
+    for ComposedDeciders<D1, C1, E1, S1, D2, C2, E2, S2, T>
+where
+    S1: Clone,
+    S2: Clone,
+    T: TerminalPolicy,
+    D1: Decider<C1, E1, S1, S1>,
+    D2: Decider<C2, E2, S2, S2>,
+{
+    fn decide(command: &Either<C1, C2>, state: &(S1, S2)) -> Vec<Either<E1, E2>> {
+        Self::decide_iter(command, state).collect()
+    }
+
+    fn decide_iter(
+        command: &Either<C1, C2>,
+        state: &(S1, S2),
+    ) -> impl Iterator<Item = Either<E1, E2>> + use<D1, C1, E1, S1, D2, C2, E2, S2, T> {
+        let (left, right) = match command {
+            Either::Left(l) => (Some(D1::decide_iter(l, &state.0)), None),
+            Either::Right(r) => (None, Some(D2::decide_iter(r, &state.1))),
+        };
+        left.into_iter()
+            .flatten()
+            .map(Either::Left)
+            .chain(right.into_iter().flatten().map(Either::Right))
+    }
+
+    fn evolve(state: &(S1, S2), event: &Either<E1, E2>) -> (S1, S2) {
+        match event {
+            Either::Left(e) => (D1::evolve(&state.0, e), state.1.clone()),
+            Either::Right(e) => (state.0.clone(), D2::evolve(&state.1, e)),
+        }
+    }
+
+    fn evolve_in_place(state: &mut (S1, S2), event: &Either<E1, E2>) {
+        match event {
+            Either::Left(e) => D1::evolve_in_place(&mut state.0, e),
+            Either::Right(e) => D2::evolve_in_place(&mut state.1, e),
+        }
+    }
+
+    fn initial_state() -> (S1, S2) {
+        (D1::initial_state(), D2::initial_state())
+    }
+
+    fn is_terminal(state: &(S1, S2)) -> bool {
+        T::is_terminal(D1::is_terminal(&state.0), D2::is_terminal(&state.1))
+    }
+}
+
+impl<D1, C1, E1, S1, D2, C2, E2, S2, T>
+    TryDecider<Either<C1, C2>, Either<E1, E2>, (S1, S2), (S1, S2)>
+    for ComposedDeciders<D1, C1, E1, S1, D2, C2, E2, S2, T>
+where
+    S1: Clone,
+    S2: Clone,
+    T: TerminalPolicy,
+    D1: TryDecider<C1, E1, S1, S1>,
+    D2: TryDecider<C2, E2, S2, S2>,
+{
+    type Error = Either<D1::Error, D2::Error>;
+
+    fn decide(
+        command: &Either<C1, C2>,
+        state: &(S1, S2),
+    ) -> Result<Vec<Either<E1, E2>>, Self::Error> {
+        match command {
+            Either::Left(l) => match D1::decide(l, &state.0) {
+                Ok(events) => Ok(events.into_iter().map(Either::Left).collect()),
+                Err(error) => Err(Either::Left(error)),
+            },
+            Either::Right(r) => match D2::decide(r, &state.1) {
+                Ok(events) => Ok(events.into_iter().map(Either::Right).collect()),
+                Err(error) => Err(Either::Right(error)),
+            },
+        }
+    }
+
+    fn evolve(state: &(S1, S2), event: &Either<E1, E2>) -> (S1, S2) {
+        match event {
+            Either::Left(e) => (D1::evolve(&state.0, e), state.1.clone()),
+            Either::Right(e) => (state.0.clone(), D2::evolve(&state.1, e)),
+        }
+    }
+
+    fn initial_state() -> (S1, S2) {
+        (D1::initial_state(), D2::initial_state())
+    }
+
+    fn is_terminal(state: &(S1, S2)) -> bool {
+        T::is_terminal(D1::is_terminal(&state.0), D2::is_terminal(&state.1))
+    }
+
+    fn evolve_in_place(state: &mut (S1, S2), event: &Either<E1, E2>) {
+        match event {
+            Either::Left(e) => D1::evolve_in_place(&mut state.0, e),
+            Either::Right(e) => D2::evolve_in_place(&mut state.1, e),
+        }
+    }
+}
+
+impl<D1, C1, E1, S1, D2, C2, E2, S2, T> Snapshotting<(S1, S2)>
+    for ComposedDeciders<D1, C1, E1, S1, D2, C2, E2, S2, T>
+where
+    D1: Snapshotting<S1>,
+    D2: Snapshotting<S2>,
+{
+    type Snapshot = (D1::Snapshot, D2::Snapshot);
+
+    fn to_snapshot((s1, s2): &(S1, S2)) -> Self::Snapshot {
+        (D1::to_snapshot(s1), D2::to_snapshot(s2))
+    }
+
+    fn from_snapshot((s1, s2): &Self::Snapshot) -> (S1, S2) {
+        (D1::from_snapshot(s1), D2::from_snapshot(s2))
+    }
+}
+
+/// A type that combines three deciders, `D1`, `D2` and `D3`, into a single decider.
+///
+/// This is the same as [`ComposedDeciders`], but for three deciders, without nesting [`Either`]
+/// types. The commands and events become instances of [`OneOf3`], and the state is the flat tuple
+/// of all three deciders' states. As with [`ComposedDeciders`], the [`TerminalPolicy`] `T` decides
+/// whether it is terminal, which by default is only when all three deciders are terminal.
+pub struct Composed3Deciders<D1, C1, E1, S1, D2, C2, E2, S2, D3, C3, E3, S3, T = AllTerminal> {
+    decider1: PhantomData<D1>,
+    command1: PhantomData<C1>,
+    event1: PhantomData<E1>,
+    state1: PhantomData<S1>,
+    decider2: PhantomData<D2>,
+    command2: PhantomData<C2>,
+    event2: PhantomData<E2>,
+    state2: PhantomData<S2>,
+    decider3: PhantomData<D3>,
+    command3: PhantomData<C3>,
+    event3: PhantomData<E3>,
+    state3: PhantomData<S3>,
+    terminal_policy: PhantomData<T>,
+}
+
+impl<D1, C1, E1, S1, D2, C2, E2, S2, D3, C3, E3, S3, T>
+    Decider<OneOf3<C1, C2, C3>, OneOf3<E1, E2, E3>, (S1, S2, S3), (S1, S2, S3)>
+    for Composed3Deciders<D1, C1, E1, S1, D2, C2, E2, S2, D3, C3, E3, S3, T>
+where
+    S1: Clone,
+    S2: Clone,
+    S3: Clone,
+    T: TerminalPolicy,
+    D1: Decider<C1, E1, S1, S1>,
+    D2: Decider<C2, E2, S2, S2>,
+    D3: Decider<C3, E3, S3, S3>,
+{
+    fn decide(command: &OneOf3<C1, C2, C3>, state: &(S1, S2, S3)) -> Vec<OneOf3<E1, E2, E3>> {
+        match command {
+            OneOf3::First(c) => D1::decide_iter(c, &state.0).map(OneOf3::First).collect(),
+            OneOf3::Second(c) => D2::decide_iter(c, &state.1).map(OneOf3::Second).collect(),
+            OneOf3::Third(c) => D3::decide_iter(c, &state.2).map(OneOf3::Third).collect(),
+        }
+    }
+
+    fn evolve(state: &(S1, S2, S3), event: &OneOf3<E1, E2, E3>) -> (S1, S2, S3) {
+        let (s1, s2, s3) = state;
+        match event {
+            OneOf3::First(e) => (D1::evolve(s1, e), s2.clone(), s3.clone()),
+            OneOf3::Second(e) => (s1.clone(), D2::evolve(s2, e), s3.clone()),
+            OneOf3::Third(e) => (s1.clone(), s2.clone(), D3::evolve(s3, e)),
+        }
+    }
+
+    fn evolve_in_place((s1, s2, s3): &mut (S1, S2, S3), event: &OneOf3<E1, E2, E3>) {
+        match event {
+            OneOf3::First(e) => D1::evolve_in_place(s1, e),
+            OneOf3::Second(e) => D2::evolve_in_place(s2, e),
+            OneOf3::Third(e) => D3::evolve_in_place(s3, e),
+        }
+    }
+
+    fn initial_state() -> (S1, S2, S3) {
+        (
+            D1::initial_state(),
+            D2::initial_state(),
+            D3::initial_state(),
+        )
+    }
+
+    fn is_terminal(state: &(S1, S2, S3)) -> bool {
+        let first_two = T::is_terminal(D1::is_terminal(&state.0), D2::is_terminal(&state.1));
+        T::is_terminal(first_two, D3::is_terminal(&state.2))
+    }
+}
+
+/// A type that combines four deciders, `D1`, `D2`, `D3` and `D4`, into a single decider.
+///
+/// This is the same as [`Composed3Deciders`], but for four deciders, with commands and events
+/// that are instances of [`OneOf4`], and the flat tuple of all four deciders' states as the state.
+pub struct Composed4Deciders<
+    D1,
+    C1,
+    E1,
+    S1,
+    D2,
+    C2,
+    E2,
+    S2,
+    D3,
+    C3,
+    E3,
+    S3,
+    D4,
+    C4,
+    E4,
+    S4,
+    T = AllTerminal,
+> {
+    decider1: PhantomData<D1>,
+    command1: PhantomData<C1>,
+    event1: PhantomData<E1>,
+    state1: PhantomData<S1>,
+    decider2: PhantomData<D2>,
+    command2: PhantomData<C2>,
+    event2: PhantomData<E2>,
+    state2: PhantomData<S2>,
+    decider3: PhantomData<D3>,
+    command3: PhantomData<C3>,
+    event3: PhantomData<E3>,
+    state3: PhantomData<S3>,
+    decider4: PhantomData<D4>,
+    command4: PhantomData<C4>,
+    event4: PhantomData<E4>,
+    state4: PhantomData<S4>,
+    terminal_policy: PhantomData<T>,
+}
+
+impl<D1, C1, E1, S1, D2, C2, E2, S2, D3, C3, E3, S3, D4, C4, E4, S4, T>
+    Decider<OneOf4<C1, C2, C3, C4>, OneOf4<E1, E2, E3, E4>, (S1, S2, S3, S4), (S1, S2, S3, S4)>
+    for Composed4Deciders<D1, C1, E1, S1, D2, C2, E2, S2, D3, C3, E3, S3, D4, C4, E4, S4, T>
+where
+    S1: Clone,
+    S2: Clone,
+    S3: Clone,
+    S4: Clone,
+    T: TerminalPolicy,
+    D1: Decider<C1, E1, S1, S1>,
+    D2: Decider<C2, E2, S2, S2>,
+    D3: Decider<C3, E3, S3, S3>,
+    D4: Decider<C4, E4, S4, S4>,
+{
+    fn decide(
+        command: &OneOf4<C1, C2, C3, C4>,
+        state: &(S1, S2, S3, S4),
+    ) -> Vec<OneOf4<E1, E2, E3, E4>> {
+        match command {
+            OneOf4::First(c) => D1::decide_iter(c, &state.0).map(OneOf4::First).collect(),
+            OneOf4::Second(c) => D2::decide_iter(c, &state.1).map(OneOf4::Second).collect(),
+            OneOf4::Third(c) => D3::decide_iter(c, &state.2).map(OneOf4::Third).collect(),
+            OneOf4::Fourth(c) => D4::decide_iter(c, &state.3).map(OneOf4::Fourth).collect(),
+        }
+    }
+
+    fn evolve(state: &(S1, S2, S3, S4), event: &OneOf4<E1, E2, E3, E4>) -> (S1, S2, S3, S4) {
+        let (s1, s2, s3, s4) = state;
+        match event {
+            OneOf4::First(e) => (D1::evolve(s1, e), s2.clone(), s3.clone(), s4.clone()),
+            OneOf4::Second(e) => (s1.clone(), D2::evolve(s2, e), s3.clone(), s4.clone()),
+            OneOf4::Third(e) => (s1.clone(), s2.clone(), D3::evolve(s3, e), s4.clone()),
+            OneOf4::Fourth(e) => (s1.clone(), s2.clone(), s3.clone(), D4::evolve(s4, e)),
+        }
+    }
+
+    fn evolve_in_place((s1, s2, s3, s4): &mut (S1, S2, S3, S4), event: &OneOf4<E1, E2, E3, E4>) {
+        match event {
+            OneOf4::First(e) => D1::evolve_in_place(s1, e),
+            OneOf4::Second(e) => D2::evolve_in_place(s2, e),
+            OneOf4::Third(e) => D3::evolve_in_place(s3, e),
+            OneOf4::Fourth(e) => D4::evolve_in_place(s4, e),
+        }
+    }
+
+    fn initial_state() -> (S1, S2, S3, S4) {
+        (
+            D1::initial_state(),
+            D2::initial_state(),
+            D3::initial_state(),
+            D4::initial_state(),
+        )
+    }
+
+    fn is_terminal(state: &(S1, S2, S3, S4)) -> bool {
+        let first_two = T::is_terminal(D1::is_terminal(&state.0), D2::is_terminal(&state.1));
+        let first_three = T::is_terminal(first_two, D3::is_terminal(&state.2));
+        T::is_terminal(first_three, D4::is_terminal(&state.3))
+    }
+}
+
+/// A type that combines two deciders, `D1` and `D2`, into a single decider taking commands of one
+/// type `C`, routed to either decider by `R`.
+///
+/// This is the same as [`ComposedDeciders`], except that commands are not wrapped in [`Either`]
+/// by the caller: the router `R` implements [`FallibleConverter`] to convert each command into a
+/// command for `D1`, as [`Either::Left`], or for `D2`, as [`Either::Right`]. Commands it converts
+/// into `None` produce no events. Events are instances of [`Either`], and the state is the tuple
+/// of both deciders' states, each evolved only by its own decider's events. The
+/// [`TerminalPolicy`] `T` decides whether it is terminal, as in [`ComposedDeciders`].
+pub struct SplitDecider<D1, C1, E1, S1, D2, C2, E2, S2, R, T = AllTerminal> {
+    decider1: PhantomData<D1>,
+    command1: PhantomData<C1>,
+    event1: PhantomData<E1>,
+    state1: PhantomData<S1>,
+    decider2: PhantomData<D2>,
+    command2: PhantomData<C2>,
+    event2: PhantomData<E2>,
+    state2: PhantomData<S2>,
+    router: PhantomData<R>,
+    terminal_policy: PhantomData<T>,
+}
+
+impl<D1, C1, E1, S1, D2, C2, E2, S2, R, T, C> Decider<C, Either<E1, E2>, (S1, S2), (S1, S2)>
+    for SplitDecider<D1, C1, E1, S1, D2, C2, E2, S2, R, T>
+where
+    S1: Clone,
+    S2: Clone,
+    T: TerminalPolicy,
+    D1: Decider<C1, E1, S1, S1>,
+    D2: Decider<C2, E2, S2, S2>,
+    R: FallibleConverter<C, Either<C1, C2>>,
+{
+    fn decide(command: &C, state: &(S1, S2)) -> Vec<Either<E1, E2>> {
+        match R::convert(command) {
+            Some(Either::Left(command)) => D1::decide_iter(&command, &state.0)
+                .map(Either::Left)
+                .collect(),
+            Some(Either::Right(command)) => D2::decide_iter(&command, &state.1)
+                .map(Either::Right)
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    fn evolve(state: &(S1, S2), event: &Either<E1, E2>) -> (S1, S2) {
+        match event {
+            Either::Left(e) => (D1::evolve(&state.0, e), state.1.clone()),
+            Either::Right(e) => (state.0.clone(), D2::evolve(&state.1, e)),
+        }
+    }
+
+    fn evolve_in_place(state: &mut (S1, S2), event: &Either<E1, E2>) {
+        match event {
+            Either::Left(e) => D1::evolve_in_place(&mut state.0, e),
+            Either::Right(e) => D2::evolve_in_place(&mut state.1, e),
+        }
+    }
+
+    fn initial_state() -> (S1, S2) {
+        (D1::initial_state(), D2::initial_state())
+    }
+
+    fn is_terminal(state: &(S1, S2)) -> bool {
+        T::is_terminal(D1::is_terminal(&state.0), D2::is_terminal(&state.1))
+    }
+}
+
+/// A type that combines two deciders, `D1` and `D2`, into a single decider giving each command of
+/// type `C` to both deciders.
+///
+/// The command converters `CC1` and `CC2` implement [`FallibleConverter`] to convert each command
+/// into a command for `D1` and a command for `D2`, or into `None` to skip that decider. The events
+/// of `D1` come first, as [`Either::Left`], followed by the events of `D2`, as [`Either::Right`].
+/// The state is the tuple of both deciders' states, each evolved only by its own decider's events,
+/// and the [`TerminalPolicy`] `T` decides whether it is terminal, as in [`ComposedDeciders`].
+pub struct BroadcastDecider<D1, C1, E1, S1, D2, C2, E2, S2, CC1, CC2, T = AllTerminal> {
+    decider1: PhantomData<D1>,
+    command1: PhantomData<C1>,
+    event1: PhantomData<E1>,
+    state1: PhantomData<S1>,
+    decider2: PhantomData<D2>,
+    command2: PhantomData<C2>,
+    event2: PhantomData<E2>,
+    state2: PhantomData<S2>,
+    command_converter1: PhantomData<CC1>,
+    command_converter2: PhantomData<CC2>,
+    terminal_policy: PhantomData<T>,
+}
+
+impl<D1, C1, E1, S1, D2, C2, E2, S2, CC1, CC2, T, C> Decider<C, Either<E1, E2>, (S1, S2), (S1, S2)>
+    for BroadcastDecider<D1, C1, E1, S1, D2, C2, E2, S2, CC1, CC2, T>
+where
+    S1: Clone,
+    S2: Clone,
+    T: TerminalPolicy,
+    D1: Decider<C1, E1, S1, S1>,
+    D2: Decider<C2, E2, S2, S2>,
+    CC1: FallibleConverter<C, C1>,
+    CC2: FallibleConverter<C, C2>,
+{
+    fn decide(command: &C, state: &(S1, S2)) -> Vec<Either<E1, E2>> {
+        Self::decide_iter(command, state).collect()
+    }
+
+    fn decide_iter(
+        command: &C,
+        state: &(S1, S2),
+    ) -> impl Iterator<Item = Either<E1, E2>> + use<D1, C1, E1, S1, D2, C2, E2, S2, C, CC1, CC2, T>
+    {
+        let left = CC1::convert(command).map(|c| D1::decide_iter(&c, &state.0));
+        let right = CC2::convert(command).map(|c| D2::decide_iter(&c, &state.1));
+        left.into_iter()
+            .flatten()
+            .map(Either::Left)
+            .chain(right.into_iter().flatten().map(Either::Right))
+    }
+
+    fn evolve(state: &(S1, S2), event: &Either<E1, E2>) -> (S1, S2) {
+        match event {
+            Either::Left(e) => (D1::evolve(&state.0, e), state.1.clone()),
+            Either::Right(e) => (state.0.clone(), D2::evolve(&state.1, e)),
+        }
+    }
+
+    fn evolve_in_place(state: &mut (S1, S2), event: &Either<E1, E2>) {
+        match event {
+            Either::Left(e) => D1::evolve_in_place(&mut state.0, e),
+            Either::Right(e) => D2::evolve_in_place(&mut state.1, e),
+        }
+    }
+
+    fn initial_state() -> (S1, S2) {
+        (D1::initial_state(), D2::initial_state())
+    }
+
+    fn is_terminal(state: &(S1, S2)) -> bool {
+        T::is_terminal(D1::is_terminal(&state.0), D2::is_terminal(&state.1))
+    }
+}
+
+/// The state of a [`SequencedDecider`]: the state of its first decider until that decider reaches
+/// a terminal state, then the state of its second decider.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Phase<S1, S2> {
+    /// The first decider is running, in this state.
+    Phase1(S1),
+    /// The first decider has reached a terminal state, and the second decider is running, in this
+    /// state.
+    Phase2(S2),
+}
+
+/// A state converter for a [`SequencedDecider`] that starts its second decider `D` from its
+/// initial state, whatever the terminal state of the first decider was.
+pub struct InitialStateOf<D, C, E> {
+    decider: PhantomData<D>,
+    command: PhantomData<C>,
+    event: PhantomData<E>,
+}
+
+impl<D, C, E, S1, S2> InfallibleConverter<S1, S2> for InitialStateOf<D, C, E>
+where
+    D: Decider<C, E, S2, S2>,
+{
+    fn convert(_input: &S1) -> S2 {
+        D::initial_state()
+    }
+}
+
+/// A type that runs the decider `D2` after the decider `D1` has reached a terminal state, such as
+/// a checkout followed by its fulfillment.
+///
+/// The state is a [`Phase`], starting in [`Phase::Phase1`] with the initial state of `D1`. When
+/// an event moves `D1` into a terminal state, the state moves into [`Phase::Phase2`], with the
+/// state given by the state converter `SC`, which implements [`InfallibleConverter`] to seed the
+/// state of `D2` from the terminal state of `D1`. Use [`InitialStateOf`] to start `D2` from its
+/// initial state instead.
+///
+/// Commands and events are instances of [`Either`], [`Either::Left`] for `D1` and
+/// [`Either::Right`] for `D2`. Commands and events for the decider that is not running are
+/// ignored. This decider has reached an end state only when `D2` has.
+pub struct SequencedDecider<D1, C1, E1, S1, D2, C2, E2, S2, SC> {
+    decider1: PhantomData<D1>,
+    command1: PhantomData<C1>,
+    event1: PhantomData<E1>,
+    state1: PhantomData<S1>,
+    decider2: PhantomData<D2>,
+    command2: PhantomData<C2>,
+    event2: PhantomData<E2>,
+    state2: PhantomData<S2>,
+    state_converter: PhantomData<SC>,
+}
+
+impl<D1, C1, E1, S1, D2, C2, E2, S2, SC> SequencedDecider<D1, C1, E1, S1, D2, C2, E2, S2, SC>
+where
+    D1: Decider<C1, E1, S1, S1>,
+    SC: InfallibleConverter<S1, S2>,
+{
+    /// Returns the phase for the state `state` of `D1`, moving on to the second phase if it is
+    /// terminal.
+    fn phase(state: S1) -> Phase<S1, S2> {
+        if D1::is_terminal(&state) {
+            Phase::Phase2(SC::convert(&state))
+        } else {
+            Phase::Phase1(state)
+        }
+    }
+}
+
+impl<D1, C1, E1, S1, D2, C2, E2, S2, SC>
+    Decider<Either<C1, C2>, Either<E1, E2>, Phase<S1, S2>, Phase<S1, S2>>
+    for SequencedDecider<D1, C1, E1, S1, D2, C2, E2, S2, SC>
+where
+    S1: Clone,
+    S2: Clone,
+    D1: Decider<C1, E1, S1, S1>,
+    D2: Decider<C2, E2, S2, S2>,
+    SC: InfallibleConverter<S1, S2>,
+{
+    fn decide(command: &Either<C1, C2>, state: &Phase<S1, S2>) -> Vec<Either<E1, E2>> {
+        match (command, state) {
+            (Either::Left(command), Phase::Phase1(state)) => {
+                D1::decide_iter(command, state).map(Either::Left).collect()
+            }
+            (Either::Right(command), Phase::Phase2(state)) => {
+                D2::decide_iter(command, state).map(Either::Right).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    fn evolve(state: &Phase<S1, S2>, event: &Either<E1, E2>) -> Phase<S1, S2> {
+        match (state, event) {
+            (Phase::Phase1(state), Either::Left(event)) => Self::phase(D1::evolve(state, event)),
+            (Phase::Phase2(state), Either::Right(event)) => Phase::Phase2(D2::evolve(state, event)),
+            _ => state.clone(),
+        }
+    }
+
+    fn evolve_owned(state: Phase<S1, S2>, event: &Either<E1, E2>) -> Phase<S1, S2> {
+        match (state, event) {
+            (Phase::Phase1(state), Either::Left(event)) => {
+                Self::phase(D1::evolve_owned(state, event))
+            }
+            (Phase::Phase2(state), Either::Right(event)) => {
+                Phase::Phase2(D2::evolve_owned(state, event))
+            }
+            (state, _) => state,
+        }
+    }
+
+    fn evolve_in_place(state: &mut Phase<S1, S2>, event: &Either<E1, E2>) {
+        match (&mut *state, event) {
+            (Phase::Phase1(first), Either::Left(event)) => {
+                D1::evolve_in_place(first, event);
+                if D1::is_terminal(first) {
+                    let second = SC::convert(first);
+                    *state = Phase::Phase2(second);
+                }
+            }
+            (Phase::Phase2(second), Either::Right(event)) => D2::evolve_in_place(second, event),
+            _ => {}
+        }
+    }
+
+    fn initial_state() -> Phase<S1, S2> {
+        Self::phase(D1::initial_state())
+    }
+
+    fn is_terminal(state: &Phase<S1, S2>) -> bool {
+        match state {
+            Phase::Phase1(_) => false,
+            Phase::Phase2(state) => D2::is_terminal(state),
+        }
+    }
+}
+
+/// Wraps the decider `D`, which is cyclic, moving it back to its initial state whenever an event
+/// moves it into a terminal state, such as a vending machine starting over after each sale.
+///
+/// Only events moving `D` from a state that is not terminal into a terminal state restart it, so
+/// a decider whose initial state is terminal is not restarted over and over. This decider never
+/// reaches an end state. Use [`MarkedRestartingDecider`] to record each restart as an event.
+pub struct RestartingDecider<D> {
+    decider: PhantomData<D>,
+}
+
+impl<D, C, E, S> Decider<C, E, S, S> for RestartingDecider<D>
+where
+    D: Decider<C, E, S, S>,
+{
+    fn decide(command: &C, state: &S) -> Vec<E> {
+        D::decide(command, state)
+    }
+
+    fn evolve(state: &S, event: &E) -> S {
+        let evolved = D::evolve(state, event);
+        if !D::is_terminal(state) && D::is_terminal(&evolved) {
+            return D::initial_state();
+        }
+        evolved
+    }
+
+    fn initial_state() -> S {
+        D::initial_state()
+    }
+
+    fn is_terminal(_state: &S) -> bool {
+        false
+    }
+
+    fn evolve_in_place(state: &mut S, event: &E) {
+        let was_terminal = D::is_terminal(state);
+        D::evolve_in_place(state, event);
+        if !was_terminal && D::is_terminal(state) {
+            *state = D::initial_state();
+        }
+    }
+}
+
+/// The event marking that a [`MarkedRestartingDecider`] moved back to its initial state.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Restarted;
+
+/// Wraps the decider `D` like [`RestartingDecider`], but records each restart as a [`Restarted`]
+/// event, so that the restarts can be seen in the stream of events.
+///
+/// Events of `D` are output as [`Either::Left`]. When the events decided for a command move `D`
+/// from a state that is not terminal into a terminal state, they are followed by
+/// [`Either::Right(Restarted)`](Restarted), and evolving that event moves `D` back to its initial
+/// state. This decider never reaches an end state.
+pub struct MarkedRestartingDecider<D> {
+    decider: PhantomData<D>,
+}
+
+impl<D, C, E, S> Decider<C, Either<E, Restarted>, S, S> for MarkedRestartingDecider<D>
+where
+    D: Decider<C, E, S, S>,
+{
+    fn decide(command: &C, state: &S) -> Vec<Either<E, Restarted>> {
+        let events = D::decide(command, state);
+        let restarts = match events.split_first() {
+            Some((first, rest)) => {
+                let evolved = fold_events::<C, E, S, S, D>(D::evolve(state, first), rest);
+                !D::is_terminal(state) && D::is_terminal(&evolved)
+            }
+            None => false,
+        };
+        let mut events: Vec<_> = events.into_iter().map(Either::Left).collect();
+        if restarts {
+            events.push(Either::Right(Restarted));
+        }
+        events
+    }
+
+    fn evolve(state: &S, event: &Either<E, Restarted>) -> S {
+        match event {
+            Either::Left(event) => D::evolve(state, event),
+            Either::Right(Restarted) => D::initial_state(),
+        }
+    }
+
+    fn initial_state() -> S {
+        D::initial_state()
+    }
+
+    fn is_terminal(_state: &S) -> bool {
+        false
+    }
+
+    fn evolve_in_place(state: &mut S, event: &Either<E, Restarted>) {
+        match event {
+            Either::Left(event) => D::evolve_in_place(state, event),
+            Either::Right(Restarted) => *state = D::initial_state(),
+        }
+    }
+}
+
+/// A trait naming the stream the events of a [`TaggedDecider`] belong to.
+///
+/// This is a trait with an associated constant instead of a constant generic parameter, since
+/// string constant parameters are not available on stable Rust.
+pub trait StreamTag {
+    /// The name of the stream.
+    const STREAM: &'static str;
+}
+
+/// An event of type `E`, labelled with the name of the stream it belongs to by a
+/// [`TaggedDecider`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Tagged<E> {
+    /// The name of the stream the event belongs to.
+    pub stream: &'static str,
+    /// The event.
+    pub event: E,
+}
+
+/// A trait for events that know the name of the stream they belong to, such as the events of a
+/// [`TaggedDecider`], so that they can be stored in separate streams.
+///
+/// It is implemented for [`Either`] of two such events, so that the events of composed tagged
+/// deciders can be routed without matching on their [`Either`] structure.
+pub trait Streamed {
+    /// Returns the name of the stream this event belongs to.
+    fn stream(&self) -> &'static str;
+}
+
+impl<E> Streamed for Tagged<E> {
+    fn stream(&self) -> &'static str {
+        self.stream
+    }
+}
+
+impl<L, R> Streamed for Either<L, R>
+where
+    L: Streamed,
+    R: Streamed,
+{
+    fn stream(&self) -> &'static str {
+        match self {
+            Either::Left(l) => l.stream(),
+            Either::Right(r) => r.stream(),
+        }
+    }
+}
+
+/// Wraps the decider `D`, labelling every event it decides with the name of the stream given by
+/// the [`StreamTag`] `T`, as a [`Tagged`] event.
+///
+/// Commands and states are left untouched, and the events are evolved by `D` whatever stream they
+/// are labelled with.
+pub struct TaggedDecider<D, T> {
+    decider: PhantomData<D>,
+    tag: PhantomData<T>,
+}
+
+impl<D, T, C, E, So, Si> Decider<C, Tagged<E>, So, Si> for TaggedDecider<D, T>
+where
+    D: Decider<C, E, So, Si>,
+    T: StreamTag,
+{
+    fn decide(command: &C, state: &Si) -> Vec<Tagged<E>> {
+        Self::decide_iter(command, state).collect()
+    }
+
+    fn decide_iter(
+        command: &C,
+        state: &Si,
+    ) -> impl Iterator<Item = Tagged<E>> + use<D, T, C, E, So, Si> {
+        D::decide_iter(command, state).map(|event| Tagged {
+            stream: T::STREAM,
+            event,
+        })
+    }
+
+    fn evolve(state: &Si, event: &Tagged<E>) -> So {
+        D::evolve(state, &event.event)
+    }
+
+    fn initial_state() -> So {
+        D::initial_state()
+    }
+
+    fn is_terminal(state: &Si) -> bool {
+        D::is_terminal(state)
+    }
+
+    fn evolve_owned(state: Si, event: &Tagged<E>) -> So {
+        D::evolve_owned(state, &event.event)
+    }
+
+    fn evolve_in_place(state: &mut Si, event: &Tagged<E>)
+    where
+        So: Into<Si>,
+    {
+        D::evolve_in_place(state, &event.event)
+    }
+}
+
+/// A type for using `N` instances of the same decider type, giving each a name.
+///
+/// This allows for running an indeterminate number of the same decider in parallel, where each has
+/// a simple string name to refer to it. The commands and events become tuples of the name and
+/// command or event, while the state becomes a HashMap mapping each name to the current state of
+/// the decider with that name.
+pub struct ManyDecider<D, C, E, S>
+where
+    S: Clone,
+    D: Decider<C, E, S, S>,
+{
+    decider: PhantomData<D>,
+    command: PhantomData<C>,
+    event: PhantomData<E>,
+    state: PhantomData<S>,
+}
+
+impl<D, C, E, S> Decider<(String, C), (String, E), HashMap<String, S>, HashMap<String, S>>
+    for ManyDecider<D, C, E, S>
+where
+    S: Clone,
+    D: Decider<C, E, S, S>,
+{
+    fn decide((id, command): &(String, C), states: &HashMap<String, S>) -> Vec<(String, E)> {
+        let events = match states.get(id) {
+            Some(state) => D::decide(command, state),
+            None => D::decide(command, &D::initial_state()),
+        };
+        events.into_iter().map(|e| (id.clone(), e)).collect()
+    }
+
+    fn evolve(states: &HashMap<String, S>, event: &(String, E)) -> HashMap<String, S> {
+        let mut states = states.clone();
+        Self::evolve_in_place(&mut states, event);
+        states
+    }
+
+    fn initial_state() -> HashMap<String, S> {
+        HashMap::new()
+    }
+
+    fn is_terminal(states: &HashMap<String, S>) -> bool {
+        states.values().all(|s| D::is_terminal(s))
+    }
+
+    fn evolve_owned(mut states: HashMap<String, S>, event: &(String, E)) -> HashMap<String, S> {
+        Self::evolve_in_place(&mut states, event);
+        states
+    }
+
+    fn evolve_in_place(states: &mut HashMap<String, S>, (id, event): &(String, E)) {
+        // Only the state of the decider the event belongs to changes, so update it in place.
+        let state = states.entry(id.clone()).or_insert_with(D::initial_state);
+        D::evolve_in_place(state, event);
+    }
+}
+
+impl<D, C, E, S> Snapshotting<HashMap<String, S>> for ManyDecider<D, C, E, S>
+where
+    S: Clone,
+    D: Decider<C, E, S, S> + Snapshotting<S>,
+{
+    type Snapshot = HashMap<String, D::Snapshot>;
+
+    fn to_snapshot(states: &HashMap<String, S>) -> Self::Snapshot {
+        states
+            .iter()
+            .map(|(id, state)| (id.clone(), D::to_snapshot(state)))
+            .collect()
+    }
+
+    fn from_snapshot(snapshots: &Self::Snapshot) -> HashMap<String, S> {
+        snapshots
+            .iter()
+            .map(|(id, snapshot)| (id.clone(), D::from_snapshot(snapshot)))
+            .collect()
+    }
+}
+
+/// Adapts a decider to use different input and output types.
+///
+/// This type allows for converting a decider that implements `Decider<Cd, Ed, Sd, Sd>` into a
+/// decider that implements `Decider<Cn, En, Sd, Sn>`.
+///
+/// It does this by taking 4 additional "converter" types, that can convert to and from the
+/// decider's native types into the new types:
+///
+/// - `CC` - The command converter that implements [`FallibleConverter`], which converts the *new* command
+///   type into the decider's *native* command type, with the option of failure.
+/// - `ENC` - The event converter that implements [`FallibleConverter`], which converts the *new* event
+///   type into the decider's *native* event type, with the option of failure.
+/// - `EDC` - The event converter that implements [`InfallibleConverter`], which converts the decider's
+///   *native* event type into the *new* event type.
+/// - `SC` - The state converter that implements [`InfallibleConverter`], which converts the *new*
+///   state type into the decider's *native* state type.
+///
+/// > *NOTE*: The output states are still in the decider's native state type. If this is not desired,
+/// > then this can be combines with [`MappedDecider`] to modify the output type to the desired type.
+///
+/// [`Adapted`] does the same with a single [`Adapter`] type instead of the converters and types.
+pub struct AdaptedDecider<D, CC, ENC, EDC, SC, En, Ed, Cn, Cd, Sn, Sd>
+where
+    D: Decider<Cd, Ed, Sd, Sd>,
+    CC: FallibleConverter<Cn, Cd>,
+    ENC: FallibleConverter<En, Ed>,
+    EDC: InfallibleConverter<Ed, En>,
+    SC: InfallibleConverter<Sn, Sd>,
+{
+    decider: PhantomData<D>,
+    command_converter: PhantomData<CC>,
+    event_new_converter: PhantomData<ENC>,
+    event_decider_converter: PhantomData<EDC>,
+    state_converter: PhantomData<SC>,
+    event_new: PhantomData<En>,
+    event_decider: PhantomData<Ed>,
+    command_new: PhantomData<Cn>,
+    command_decider: PhantomData<Cd>,
+    state_new: PhantomData<Sn>,
+    state_decider: PhantomData<Sd>,
+}
+
+impl<D, CC, ENC, EDC, SC, En, Ed, Cn, Cd, Sn, Sd> Adapter
+    for AdaptedDecider<D, CC, ENC, EDC, SC, En, Ed, Cn, Cd, Sn, Sd>
+where
+    D: Decider<Cd, Ed, Sd, Sd>,
+    CC: FallibleConverter<Cn, Cd>,
+    ENC: FallibleConverter<En, Ed>,
+    EDC: InfallibleConverter<Ed, En>,
+    SC: InfallibleConverter<Sn, Sd>,
+{
+    type Command = Cn;
+    type Event = En;
+    type State = Sn;
+    type DeciderCommand = Cd;
+    type DeciderEvent = Ed;
+    type DeciderState = Sd;
+
+    fn convert_command(command: &Cn) -> Option<Cd> {
+        CC::convert(command)
+    }
+
+    fn convert_event_in(event: &En) -> Option<Ed> {
+        ENC::convert(event)
+    }
+
+    fn convert_event_out(event: &Ed) -> En {
+        EDC::convert(event)
+    }
+
+    fn convert_state(state: &Sn) -> Sd {
+        SC::convert(state)
+    }
+}
+
+impl<D, CC, ENC, EDC, SC, En, Ed, Cn, Cd, Sn, Sd> Decider<Cn, En, Sd, Sn>
+    for AdaptedDecider<D, CC, ENC, EDC, SC, En, Ed, Cn, Cd, Sn, Sd>
+where
+    D: Decider<Cd, Ed, Sd, Sd>,
+    CC: FallibleConverter<Cn, Cd>,
+    ENC: FallibleConverter<En, Ed>,
+    EDC: InfallibleConverter<Ed, En>,
+    SC: InfallibleConverter<Sn, Sd>,
+{
+    fn decide(command: &Cn, state: &Sn) -> Vec<En> {
+        Adapted::<D, Self>::decide(command, state)
+    }
+
+    fn decide_iter(
+        command: &Cn,
+        state: &Sn,
+    ) -> impl Iterator<Item = En> + use<D, CC, ENC, EDC, SC, En, Ed, Cn, Cd, Sn, Sd> {
+        Adapted::<D, Self>::decide_iter(command, state)
+    }
+
+    fn evolve(state: &Sn, event: &En) -> Sd {
+        Adapted::<D, Self>::evolve(state, event)
+    }
+
+    fn initial_state() -> Sd {
+        Adapted::<D, Self>::initial_state()
+    }
+
+    fn is_terminal(state: &Sn) -> bool {
+        Adapted::<D, Self>::is_terminal(state)
+    }
+
+    fn evolve_in_place(state: &mut Sn, event: &En)
+    where
+        Sd: Into<Sn>,
+    {
+        Adapted::<D, Self>::evolve_in_place(state, event)
+    }
+}
+
+/// Adapts a decider to use different input and output types, where not every state of the new
+/// state type has a corresponding state of the decider.
+///
+/// This is the same as [`AdaptedDecider`], except that the state converter `SC` implements
+/// [`FallibleConverter`], and that the output states are converted back into the *new* state type
+/// by `SO`, which implements [`InfallibleConverter`], so this type implements
+/// `Decider<Cn, En, Sn, Sn>`.
+///
+/// For a state that `SC` cannot convert:
+///
+/// - `decide` returns no events, for any command.
+/// - `evolve` returns a clone of the state, for any event.
+/// - `is_terminal` returns `true`, since this decider can never leave that state.
+///
+/// For every other state, this behaves as [`AdaptedDecider`] followed by [`MappedDecider`] with
+/// `SO`. The initial state is the decider's initial state, converted by `SO`.
+pub struct TryAdaptedDecider<D, CC, ENC, EDC, SC, SO, En, Ed, Cn, Cd, Sn, Sd>
+where
+    D: Decider<Cd, Ed, Sd, Sd>,
+    CC: FallibleConverter<Cn, Cd>,
+    ENC: FallibleConverter<En, Ed>,
+    EDC: InfallibleConverter<Ed, En>,
+    SC: FallibleConverter<Sn, Sd>,
+    SO: InfallibleConverter<Sd, Sn>,
+{
+    decider: PhantomData<D>,
+    command_converter: PhantomData<CC>,
+    event_new_converter: PhantomData<ENC>,
+    event_decider_converter: PhantomData<EDC>,
+    state_converter: PhantomData<SC>,
+    state_output_converter: PhantomData<SO>,
+    event_new: PhantomData<En>,
+    event_decider: PhantomData<Ed>,
+    command_new: PhantomData<Cn>,
+    command_decider: PhantomData<Cd>,
+    state_new: PhantomData<Sn>,
+    state_decider: PhantomData<Sd>,
+}
+
+impl<D, CC, ENC, EDC, SC, SO, En, Ed, Cn, Cd, Sn, Sd> Decider<Cn, En, Sn, Sn>
+    for TryAdaptedDecider<D, CC, ENC, EDC, SC, SO, En, Ed, Cn, Cd, Sn, Sd>
+where
+    D: Decider<Cd, Ed, Sd, Sd>,
+    CC: FallibleConverter<Cn, Cd>,
+    ENC: FallibleConverter<En, Ed>,
+    EDC: InfallibleConverter<Ed, En>,
+    SC: FallibleConverter<Sn, Sd>,
+    SO: InfallibleConverter<Sd, Sn>,
+    Sn: Clone,
+{
+    fn decide(command: &Cn, state: &Sn) -> Vec<En> {
+        Self::decide_iter(command, state).collect()
+    }
+
+    fn decide_iter(
+        command: &Cn,
+        state: &Sn,
+    ) -> impl Iterator<Item = En> + use<D, CC, ENC, EDC, SC, SO, En, Ed, Cn, Cd, Sn, Sd> {
+        CC::convert(command)
+            .zip(SC::convert(state))
+            .map(|(c, s)| D::decide_iter(&c, &s))
+            .into_iter()
+            .flatten()
+            .map(|e| EDC::convert(&e))
+    }
+
+    fn evolve(state: &Sn, event: &En) -> Sn {
+        match (SC::convert(state), ENC::convert(event)) {
+            (Some(s), Some(e)) => SO::convert(&D::evolve_owned(s, &e)),
+            _ => state.clone(),
+        }
+    }
+
+    fn evolve_owned(state: Sn, event: &En) -> Sn {
+        match (SC::convert(&state), ENC::convert(event)) {
+            (Some(s), Some(e)) => SO::convert(&D::evolve_owned(s, &e)),
+            _ => state,
+        }
+    }
+
+    fn evolve_in_place(state: &mut Sn, event: &En) {
+        if let (Some(mut s), Some(e)) = (SC::convert(state), ENC::convert(event)) {
+            D::evolve_in_place(&mut s, &e);
+            *state = SO::convert(&s);
+        }
+    }
+
+    fn initial_state() -> Sn {
+        SO::convert(&D::initial_state())
+    }
+
+    fn is_terminal(state: &Sn) -> bool {
+        match SC::convert(state) {
+            Some(s) => D::is_terminal(&s),
+            None => true,
+        }
+    }
+}
+
+/// A trait bundling the four conversions needed to adapt a decider to different types, for use
+/// with [`Adapted`].
+///
+/// The decider being adapted uses the `Decider*` types, and the adapted decider uses the
+/// `Command`, `Event` and `State` types. As with [`AdaptedDecider`], the output state of the
+/// adapted decider is still `DeciderState`.
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait Adapter {
+    /// The command type of the adapted decider.
+    type Command;
+    /// The event type of the adapted decider.
+    type Event;
+    /// The input state type of the adapted decider.
+    type State;
+    /// The command type of the decider being adapted.
+    type DeciderCommand;
+    /// The event type of the decider being adapted.
+    type DeciderEvent;
+    /// The state type of the decider being adapted.
+    type DeciderState;
+
+    /// Converts a command into the decider's command type, or `None` if the decider does not
+    /// handle it.
+    fn convert_command(command: &Self::Command) -> Option<Self::DeciderCommand>;
+
+    /// Converts an incoming event into the decider's event type, or `None` if the decider does
+    /// not handle it.
+    fn convert_event_in(event: &Self::Event) -> Option<Self::DeciderEvent>;
+
+    /// Converts an event decided by the decider into the adapted event type.
+    fn convert_event_out(event: &Self::DeciderEvent) -> Self::Event;
+
+    /// Converts a state into the decider's state type.
+    fn convert_state(state: &Self::State) -> Self::DeciderState;
+}
+
+/// Adapts the decider `D` to use different input and output types, using the conversions of the
+/// [`Adapter`] `A`.
+///
+/// This behaves the same as [`AdaptedDecider`], but only needs two type parameters, since all of
+/// the types are given by the adapter.
+pub struct Adapted<D, A> {
+    decider: PhantomData<D>,
+    adapter: PhantomData<A>,
+}
+
+impl<D, A> Decider<A::Command, A::Event, A::DeciderState, A::State> for Adapted<D, A>
+where
+    A: Adapter,
+    D: Decider<A::DeciderCommand, A::DeciderEvent, A::DeciderState, A::DeciderState>,
+{
+    fn decide(command: &A::Command, state: &A::State) -> Vec<A::Event> {
+        Self::decide_iter(command, state).collect()
+    }
+
+    fn decide_iter(
+        command: &A::Command,
+        state: &A::State,
+    ) -> impl Iterator<Item = A::Event> + use<D, A> {
+        A::convert_command(command)
+            .map(|c| D::decide_iter(&c, &A::convert_state(state)))
+            .into_iter()
+            .flatten()
+            .map(|e| A::convert_event_out(&e))
+    }
+
+    fn evolve(state: &A::State, event: &A::Event) -> A::DeciderState {
+        match A::convert_event_in(event) {
+            Some(e) => D::evolve(&A::convert_state(state), &e),
+            None => A::convert_state(state),
+        }
+    }
+
+    fn initial_state() -> A::DeciderState {
+        D::initial_state()
+    }
+
+    fn is_terminal(state: &A::State) -> bool {
+        D::is_terminal(&A::convert_state(state))
+    }
+
+    fn evolve_in_place(state: &mut A::State, event: &A::Event)
+    where
+        A::DeciderState: Into<A::State>,
+    {
+        // The wrapped decider evolves the converted state in place, so it is only converted once
+        // each way.
+        let mut converted = A::convert_state(state);
+        if let Some(e) = A::convert_event_in(event) {
+            D::evolve_in_place(&mut converted, &e);
+        }
+        *state = converted.into();
+    }
+}
+
+/// The most dead letters kept for each [`Reporting`] adapter, after which the oldest are dropped.
+pub const MAX_DEAD_LETTERS: usize = 1000;
+
+/// A command or event that an [`Adapter`] could not convert, and so was dropped.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeadLetter<C, E> {
+    /// A command that could not be converted, so decided no events.
+    Command(C),
+    /// An event that could not be converted, so left the state unchanged.
+    Event(E),
+}
+
+/// The dead letters recorded for a [`Reporting`] adapter.
+struct DeadLetters<C, E> {
+    dropped: u64,
+    letters: VecDeque<DeadLetter<C, E>>,
+}
+
+thread_local! {
+    static DEAD_LETTERS: RefCell<HashMap<TypeId, Box<dyn Any>>> = RefCell::new(HashMap::new());
+}
+
+/// Wraps the adapter `A`, recording every command and event that it cannot convert as a
+/// [`DeadLetter`], instead of dropping them silently.
+///
+/// Use it in place of `A`, e.g. `Adapted<D, Reporting<A>>`, or `Adapted<D, Reporting<Self>>` for
+/// an [`AdaptedDecider`] `Self`. Adapters that are not wrapped record nothing. As with
+/// [`CountingDecider`], the dead letters are kept per adapter type `A` and per thread. Up to
+/// [`MAX_DEAD_LETTERS`] dead letters are kept, while [`Reporting::dropped`] counts all of them.
+pub struct Reporting<A> {
+    adapter: PhantomData<A>,
+}
+
+impl<A> Reporting<A>
+where
+    A: Adapter + 'static,
+    A::Command: Clone + 'static,
+    A::Event: Clone + 'static,
+{
+    /// Returns how many commands and events `A` has dropped on this thread.
+    pub fn dropped() -> u64 {
+        Self::with_dead_letters(|dead_letters| dead_letters.dropped)
+    }
+
+    /// Returns the dead letters kept for `A` on this thread, oldest first, and removes them.
+    ///
+    /// The count returned by [`Reporting::dropped`] is not reset.
+    pub fn drained_dead_letters() -> Vec<DeadLetter<A::Command, A::Event>> {
+        Self::with_dead_letters(|dead_letters| dead_letters.letters.drain(..).collect())
+    }
+
+    /// Removes the dead letters kept for `A` on this thread, and resets the count to zero.
+    pub fn reset() {
+        DEAD_LETTERS.with(|dead_letters| dead_letters.borrow_mut().remove(&TypeId::of::<A>()));
+    }
+
+    /// Records the dead letter `letter` for `A` on this thread.
+    fn report(letter: DeadLetter<A::Command, A::Event>) {
+        Self::with_dead_letters(|dead_letters| {
+            dead_letters.dropped += 1;
+            if dead_letters.letters.len() == MAX_DEAD_LETTERS {
+                dead_letters.letters.pop_front();
+            }
+            dead_letters.letters.push_back(letter);
+        })
+    }
+
+    fn with_dead_letters<T>(f: impl FnOnce(&mut DeadLetters<A::Command, A::Event>) -> T) -> T {
+        DEAD_LETTERS.with(|dead_letters| {
+            let mut dead_letters = dead_letters.borrow_mut();
+            let entry = dead_letters.entry(TypeId::of::<A>()).or_insert_with(|| {
+                Box::new(DeadLetters::<A::Command, A::Event> {
+                    dropped: 0,
+                    letters: VecDeque::new(),
+                })
+            });
+            f(entry
+                .downcast_mut()
+                .expect("dead letters are only recorded with the types of their adapter"))
+        })
+    }
+}
+
+impl<A> Adapter for Reporting<A>
+where
+    A: Adapter + 'static,
+    A::Command: Clone + 'static,
+    A::Event: Clone + 'static,
+{
+    type Command = A::Command;
+    type Event = A::Event;
+    type State = A::State;
+    type DeciderCommand = A::DeciderCommand;
+    type DeciderEvent = A::DeciderEvent;
+    type DeciderState = A::DeciderState;
+
+    fn convert_command(command: &A::Command) -> Option<A::DeciderCommand> {
+        let converted = A::convert_command(command);
+        if converted.is_none() {
+            Self::report(DeadLetter::Command(command.clone()));
+        }
+        converted
+    }
+
+    fn convert_event_in(event: &A::Event) -> Option<A::DeciderEvent> {
+        let converted = A::convert_event_in(event);
+        if converted.is_none() {
+            Self::report(DeadLetter::Event(event.clone()));
+        }
+        converted
+    }
+
+    fn convert_event_out(event: &A::DeciderEvent) -> A::Event {
+        A::convert_event_out(event)
+    }
+
+    fn convert_state(state: &A::State) -> A::DeciderState {
+        A::convert_state(state)
+    }
+}
+
+/// A trait for upgrading events written with an older schema `EOld` into the current event type
+/// `ENew`, such as by filling in fields that were added since with defaults, or by mapping
+/// renamed variants.
+///
+/// Upcasters for consecutive schema versions can be chained with [`ChainedUpcaster`].
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait EventUpcaster<EOld, ENew> {
+    /// Upgrades the old event `event` into the new event type.
+    fn upcast(event: &EOld) -> ENew;
+}
+
+/// An upcaster that upgrades events from `E1` to `E3` by first upcasting them to `E2` with `U1`,
+/// then upcasting the result with `U2`.
+///
+/// Chains can be nested to upgrade events across any number of versions, such as
+/// `ChainedUpcaster<ChainedUpcaster<V1ToV2, V2ToV3, V2>, V3ToV4, V3>`.
+pub struct ChainedUpcaster<U1, U2, E2> {
+    first: PhantomData<U1>,
+    second: PhantomData<U2>,
+    middle: PhantomData<E2>,
+}
+
+impl<U1, U2, E1, E2, E3> EventUpcaster<E1, E3> for ChainedUpcaster<U1, U2, E2>
+where
+    U1: EventUpcaster<E1, E2>,
+    U2: EventUpcaster<E2, E3>,
+{
+    fn upcast(event: &E1) -> E3 {
+        U2::upcast(&U1::upcast(event))
+    }
+}
+
+/// A type for evolving a decider with events written with an older schema.
+///
+/// This type takes a decider that implements `Decider<C, E, So, Si>` and an upcaster `U` that
+/// implements [`EventUpcaster<EOld, E>`], and implements `Decider<C, Either<EOld, E>, So, Si>`, so
+/// that a stream can hold both old and current events. Old events are upcast before being given
+/// to the wrapped decider's `evolve`, while current events are given to it unchanged. Decided
+/// events are always of the current type, in [`Either::Right`].
+///
+/// This is a specialized form of [`AdaptedDecider`], which can also convert incoming events, but
+/// which cannot tell old events apart from current ones.
+pub struct UpcastingDecider<D, U, C, EOld, E, So, Si>
+where
+    D: Decider<C, E, So, Si>,
+    U: EventUpcaster<EOld, E>,
+{
+    decider: PhantomData<D>,
+    upcaster: PhantomData<U>,
+    command: PhantomData<C>,
+    event_old: PhantomData<EOld>,
+    event: PhantomData<E>,
+    state_out: PhantomData<So>,
+    state_in: PhantomData<Si>,
+}
+
+impl<D, U, C, EOld, E, So, Si> Decider<C, Either<EOld, E>, So, Si>
+    for UpcastingDecider<D, U, C, EOld, E, So, Si>
+where
+    D: Decider<C, E, So, Si>,
+    U: EventUpcaster<EOld, E>,
+{
+    fn decide(command: &C, state: &Si) -> Vec<Either<EOld, E>> {
+        Self::decide_iter(command, state).collect()
+    }
+
+    fn decide_iter(
+        command: &C,
+        state: &Si,
+    ) -> impl Iterator<Item = Either<EOld, E>> + use<D, U, C, EOld, E, So, Si> {
+        D::decide_iter(command, state).map(Either::Right)
+    }
+
+    fn evolve(state: &Si, event: &Either<EOld, E>) -> So {
+        match event {
+            Either::Left(old) => D::evolve(state, &U::upcast(old)),
+            Either::Right(event) => D::evolve(state, event),
+        }
+    }
+
+    fn evolve_owned(state: Si, event: &Either<EOld, E>) -> So {
+        match event {
+            Either::Left(old) => D::evolve_owned(state, &U::upcast(old)),
+            Either::Right(event) => D::evolve_owned(state, event),
+        }
+    }
+
+    fn evolve_in_place(state: &mut Si, event: &Either<EOld, E>)
+    where
+        So: Into<Si>,
+    {
+        match event {
+            Either::Left(old) => D::evolve_in_place(state, &U::upcast(old)),
+            Either::Right(event) => D::evolve_in_place(state, event),
+        }
+    }
+
+    fn initial_state() -> So {
+        D::initial_state()
+    }
+
+    fn is_terminal(state: &Si) -> bool {
+        D::is_terminal(state)
+    }
+}
+
+/// A trait for reading and replacing a part of type `Inner` of a larger value of type `Outer`, such
+/// as one field of a struct, for a [`ZoomedDecider`].
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait Lens<Outer, Inner> {
+    /// Returns the part of `outer` this lens focuses on.
+    fn get(outer: &Outer) -> Inner;
+
+    /// Returns a copy of `outer` with the part this lens focuses on replaced by `inner`.
+    fn set(outer: &Outer, inner: Inner) -> Outer;
+
+    /// Updates the part of `outer` this lens focuses on in place with `update`.
+    ///
+    /// By default this reads the part with [`Lens::get`] and writes it back with [`Lens::set`].
+    /// Lenses onto a field can override it to hand out the field itself, so that a
+    /// [`ZoomedDecider`] evolves it without copying the rest of the state.
+    fn update(outer: &mut Outer, update: impl FnOnce(&mut Inner)) {
+        let mut inner = Self::get(outer);
+        update(&mut inner);
+        *outer = Self::set(outer, inner);
+    }
+}
+
+/// A type for running the decider `D`, with the state `S`, over a part of a larger state `Outer`,
+/// given by the [`Lens`] `L`.
+///
+/// Commands are decided against the part of the state read through the lens, and events evolve
+/// that part and write it back through the lens, leaving the rest of the state untouched. The
+/// initial state is the [`Default`] of `Outer`, with the initial state of `D` written into it.
+///
+/// To run several deciders over different parts of the same state, compose them first, such as
+/// with [`ComposedDeciders`], then zoom the composed decider with a lens to a tuple of the parts.
+pub struct ZoomedDecider<D, L, S> {
+    decider: PhantomData<D>,
+    lens: PhantomData<L>,
+    state: PhantomData<S>,
+}
+
+impl<D, L, S, C, E, Outer> Decider<C, E, Outer, Outer> for ZoomedDecider<D, L, S>
+where
+    D: Decider<C, E, S, S>,
+    L: Lens<Outer, S>,
+    Outer: Default,
+{
+    fn decide(command: &C, state: &Outer) -> Vec<E> {
+        D::decide(command, &L::get(state))
+    }
+
+    fn evolve(state: &Outer, event: &E) -> Outer {
+        L::set(state, D::evolve_owned(L::get(state), event))
+    }
+
+    fn initial_state() -> Outer {
+        L::set(&Outer::default(), D::initial_state())
+    }
+
+    fn is_terminal(state: &Outer) -> bool {
+        D::is_terminal(&L::get(state))
+    }
+
+    fn evolve_owned(mut state: Outer, event: &E) -> Outer {
+        Self::evolve_in_place(&mut state, event);
+        state
+    }
+
+    fn evolve_in_place(state: &mut Outer, event: &E) {
+        L::update(state, |inner| D::evolve_in_place(inner, event));
+    }
+}
+
+/// A type to modify the command type of a decider.
+///
+/// This type takes a decider that implements `Decider<Cd, E, So, Si>` and a command converter
+/// `CC`, which implements [`FallibleConverter`] to convert the *new* command type `Cn` into the
+/// decider's *native* command type `Cd`, and implements `Decider<Cn, E, So, Si>`. Commands that
+/// cannot be converted produce no events. Events and states are left untouched.
+///
+/// This is the command converting part of [`AdaptedDecider`]. Stacking it with
+/// [`MappedEventDecider`] and [`MappedDecider`] behaves as [`AdaptedDecider`] does.
+pub struct MappedCommandDecider<D, CC, Cd> {
+    decider: PhantomData<D>,
+    command_converter: PhantomData<CC>,
+    command_decider: PhantomData<Cd>,
+}
+
+impl<D, CC, Cn, Cd, E, So, Si> Decider<Cn, E, So, Si> for MappedCommandDecider<D, CC, Cd>
+where
+    D: Decider<Cd, E, So, Si>,
+    CC: FallibleConverter<Cn, Cd>,
+{
+    fn decide(command: &Cn, state: &Si) -> Vec<E> {
+        match CC::convert(command) {
+            Some(c) => D::decide(&c, state),
+            None => vec![],
+        }
+    }
+
+    fn evolve(state: &Si, event: &E) -> So {
+        D::evolve(state, event)
+    }
+
+    fn initial_state() -> So {
+        D::initial_state()
+    }
+
+    fn is_terminal(state: &Si) -> bool {
+        D::is_terminal(state)
+    }
+
+    fn evolve_owned(state: Si, event: &E) -> So {
+        D::evolve_owned(state, event)
+    }
+
+    fn evolve_in_place(state: &mut Si, event: &E)
+    where
+        So: Into<Si>,
+    {
+        D::evolve_in_place(state, event)
+    }
+}
+
+/// A type to modify the event type of a decider.
+///
+/// This type takes a decider that implements `Decider<C, Ed, So, Si>` and 2 event converters,
+/// and implements `Decider<C, En, So, Si>`:
+///
+/// - `ENC` - The event converter that implements [`FallibleConverter`], which converts the *new*
+///   event type into the decider's *native* event type, with the option of failure. Events that
+///   cannot be converted leave the state unchanged.
+/// - `EDC` - The event converter that implements [`InfallibleConverter`], which converts the
+///   decider's *native* event type into the *new* event type.
+///
+/// This is the event converting part of [`AdaptedDecider`].
+pub struct MappedEventDecider<D, ENC, EDC, Ed> {
+    decider: PhantomData<D>,
+    event_new_converter: PhantomData<ENC>,
+    event_decider_converter: PhantomData<EDC>,
+    event_decider: PhantomData<Ed>,
+}
+
+impl<D, ENC, EDC, C, En, Ed, So, Si> Decider<C, En, So, Si> for MappedEventDecider<D, ENC, EDC, Ed>
+where
+    D: Decider<C, Ed, So, Si>,
+    ENC: FallibleConverter<En, Ed>,
+    EDC: InfallibleConverter<Ed, En>,
+    Si: Clone + Into<So>,
+{
+    fn decide(command: &C, state: &Si) -> Vec<En> {
+        Self::decide_iter(command, state).collect()
+    }
+
+    fn decide_iter(
+        command: &C,
+        state: &Si,
+    ) -> impl Iterator<Item = En> + use<D, ENC, EDC, C, En, Ed, So, Si> {
+        D::decide_iter(command, state).map(|e| EDC::convert(&e))
+    }
+
+    fn evolve(state: &Si, event: &En) -> So {
+        match ENC::convert(event) {
+            Some(e) => D::evolve(state, &e),
+            None => state.clone().into(),
+        }
+    }
+
+    fn initial_state() -> So {
+        D::initial_state()
+    }
+
+    fn is_terminal(state: &Si) -> bool {
+        D::is_terminal(state)
+    }
+
+    fn evolve_owned(state: Si, event: &En) -> So {
+        match ENC::convert(event) {
+            Some(e) => D::evolve_owned(state, &e),
+            None => state.into(),
+        }
+    }
+
+    fn evolve_in_place(state: &mut Si, event: &En)
+    where
+        So: Into<Si>,
+    {
+        let Some(e) = ENC::convert(event) else {
+            // The state is left unchanged, without the round trip through `So` that `evolve` makes.
+            return;
+        };
+        D::evolve_in_place(state, &e);
+    }
+}
+
+/// A type to modify the output state type of a decider
+///
+/// This type takes a Decider and an additional `SC` type, which implements the
+/// [`InfallibleConverter`] trait to convert the decider's native output state type into a new
+/// output state type.
+pub struct MappedDecider<D, SC, C, E, Sn, Sdo, Sdi>
+where
+    D: Decider<C, E, Sdo, Sdi>,
+    SC: InfallibleConverter<Sdo, Sn>,
+{
+    decider: PhantomData<D>,
+    state_converter: PhantomData<SC>,
+    command: PhantomData<C>,
+    event: PhantomData<E>,
+    state_new: PhantomData<Sn>,
+    state_decider_out: PhantomData<Sdo>,
+    state_decider_in: PhantomData<Sdi>,
+}
+
+impl<D, SC, C, E, Sn, Sdo, Sdi> Decider<C, E, Sn, Sdi> for MappedDecider<D, SC, C, E, Sn, Sdo, Sdi>
+where
+    D: Decider<C, E, Sdo, Sdi>,
+    SC: InfallibleConverter<Sdo, Sn>,
+{
+    fn decide(command: &C, state: &Sdi) -> Vec<E> {
+        D::decide(command, state)
+    }
+
+    fn decide_iter(
+        command: &C,
+        state: &Sdi,
+    ) -> impl Iterator<Item = E> + use<D, SC, C, E, Sn, Sdo, Sdi> {
+        D::decide_iter(command, state)
+    }
+
+    fn evolve(state: &Sdi, event: &E) -> Sn {
+        SC::convert(&D::evolve(state, event))
+    }
+
+    fn initial_state() -> Sn {
+        SC::convert(&D::initial_state())
+    }
+
+    fn is_terminal(state: &Sdi) -> bool {
+        D::is_terminal(state)
+    }
+
+    fn evolve_owned(state: Sdi, event: &E) -> Sn {
+        SC::convert(&D::evolve_owned(state, event))
+    }
+}
+
+/// A type to combine two deciders that take the same input types, and transform their output types
+/// into a common output type.
+///
+/// This type combines two decider types, simply concatenating the outputs of their decide method,
+/// with the events of `D1` first, followed by the events of `D2`.
+///
+/// It also takes an `SC` type, which must implement [`InfallibleConverter`], which takes as input
+/// the tuple of both decider's output state types, and return a new state type, which is what the
+/// output state type of this decider will be.
+///
+/// As with [`ComposedDeciders`], the [`TerminalPolicy`] `T` decides whether this decider is
+/// terminal, which by default is only when both deciders are terminal.
+pub struct Map2Deciders<D1, D2, SC, C, E, Si, S1, S2, So, T = AllTerminal>
+where
+    D1: Decider<C, E, S1, Si>,
+    D2: Decider<C, E, S2, Si>,
+    SC: InfallibleConverter<(S1, S2), So>,
+{
+    decider1: PhantomData<D1>,
+    decider2: PhantomData<D2>,
+    state_combiner: PhantomData<SC>,
+    command: PhantomData<C>,
+    event: PhantomData<E>,
+    state_initial: PhantomData<Si>,
+    state_decider_1: PhantomData<S1>,
+    state_decider_2: PhantomData<S2>,
+    state_output: PhantomData<So>,
+    terminal_policy: PhantomData<T>,
+}
+
+impl<D1, D2, SC, C, E, Si, S1, S2, So, T> Decider<C, E, So, Si>
+    for Map2Deciders<D1, D2, SC, C, E, Si, S1, S2, So, T>
+where
+    D1: Decider<C, E, S1, Si>,
+    D2: Decider<C, E, S2, Si>,
+    SC: InfallibleConverter<(S1, S2), So>,
+    T: TerminalPolicy,
+{
+    fn decide(command: &C, state: &Si) -> Vec<E> {
+        Self::decide_iter(command, state).collect()
+    }
+
+    fn decide_iter(
+        command: &C,
+        state: &Si,
+    ) -> impl Iterator<Item = E> + use<D1, D2, SC, C, E, Si, S1, S2, So, T> {
+        D1::decide_iter(command, state).chain(D2::decide_iter(command, state))
+    }
+
+    fn evolve(state: &Si, event: &E) -> So {
+        let s1 = D1::evolve(state, event);
+        let s2 = D2::evolve(state, event);
+        SC::convert(&(s1, s2))
+    }
+
+    fn evolve_owned(state: Si, event: &E) -> So {
+        let s1 = D1::evolve(&state, event);
+        let s2 = D2::evolve_owned(state, event);
+        SC::convert(&(s1, s2))
+    }
+
+    fn initial_state() -> So {
+        SC::convert(&(D1::initial_state(), D2::initial_state()))
+    }
+
+    fn is_terminal(state: &Si) -> bool {
+        T::is_terminal(D1::is_terminal(state), D2::is_terminal(state))
+    }
+}
+
+/// A type to combine two deciders that take different command types, but the same event and input
+/// state types, and transform their output types into a common output type.
+///
+/// This is the same as [`Map2Deciders`], except that commands are instances of [`Either`], and
+/// each command is only decided by its own decider: `Left` commands by `D1`, and `Right` commands
+/// by `D2`. The events decided are those of that decider alone, in the order it decided them.
+/// Events are shared, so every event is evolved by both deciders. As in [`Map2Deciders`], `SC`
+/// combines the tuple of both output states, and the [`TerminalPolicy`] `T` decides whether this
+/// decider is terminal.
+pub struct Map2EitherDeciders<D1, C1, D2, C2, SC, E, Si, S1, S2, So, T = AllTerminal>
+where
+    D1: Decider<C1, E, S1, Si>,
+    D2: Decider<C2, E, S2, Si>,
+    SC: InfallibleConverter<(S1, S2), So>,
+{
+    decider1: PhantomData<D1>,
+    command1: PhantomData<C1>,
+    decider2: PhantomData<D2>,
+    command2: PhantomData<C2>,
+    state_combiner: PhantomData<SC>,
+    event: PhantomData<E>,
+    state_initial: PhantomData<Si>,
+    state_decider_1: PhantomData<S1>,
+    state_decider_2: PhantomData<S2>,
+    state_output: PhantomData<So>,
+    terminal_policy: PhantomData<T>,
+}
+
+impl<D1, C1, D2, C2, SC, E, Si, S1, S2, So, T> Decider<Either<C1, C2>, E, So, Si>
+    for Map2EitherDeciders<D1, C1, D2, C2, SC, E, Si, S1, S2, So, T>
+where
+    D1: Decider<C1, E, S1, Si>,
+    D2: Decider<C2, E, S2, Si>,
+    SC: InfallibleConverter<(S1, S2), So>,
+    T: TerminalPolicy,
+{
+    fn decide(command: &Either<C1, C2>, state: &Si) -> Vec<E> {
+        match command {
+            Either::Left(c) => D1::decide(c, state),
+            Either::Right(c) => D2::decide(c, state),
+        }
+    }
+
+    fn evolve(state: &Si, event: &E) -> So {
+        let s1 = D1::evolve(state, event);
+        let s2 = D2::evolve(state, event);
+        SC::convert(&(s1, s2))
+    }
+
+    fn evolve_owned(state: Si, event: &E) -> So {
+        let s1 = D1::evolve(&state, event);
+        let s2 = D2::evolve_owned(state, event);
+        SC::convert(&(s1, s2))
+    }
+
+    fn initial_state() -> So {
+        SC::convert(&(D1::initial_state(), D2::initial_state()))
+    }
+
+    fn is_terminal(state: &Si) -> bool {
+        T::is_terminal(D1::is_terminal(state), D2::is_terminal(state))
+    }
+}
+
+/// A type to combine three deciders that take the same input types, and transform their output
+/// types into a common output type.
+///
+/// This is the same as [`Map2Deciders`], but for three deciders, without needing to nest
+/// [`Map2Deciders`]. The outputs of the decide methods are concatenated in order, and the
+/// [`TerminalPolicy`] `T` decides whether the decider is terminal, which by default is only when
+/// all three deciders are terminal.
+///
+/// `SC` must implement [`InfallibleConverter`], taking as input the tuple of all three decider's
+/// output state types.
+pub struct Map3Deciders<D1, D2, D3, SC, C, E, Si, S1, S2, S3, So, T = AllTerminal>
+where
+    D1: Decider<C, E, S1, Si>,
+    D2: Decider<C, E, S2, Si>,
+    D3: Decider<C, E, S3, Si>,
+    SC: InfallibleConverter<(S1, S2, S3), So>,
+{
+    decider1: PhantomData<D1>,
+    decider2: PhantomData<D2>,
+    decider3: PhantomData<D3>,
+    state_combiner: PhantomData<SC>,
+    command: PhantomData<C>,
+    event: PhantomData<E>,
+    state_initial: PhantomData<Si>,
+    state_decider_1: PhantomData<S1>,
+    state_decider_2: PhantomData<S2>,
+    state_decider_3: PhantomData<S3>,
+    state_output: PhantomData<So>,
+    terminal_policy: PhantomData<T>,
+}
+
+impl<D1, D2, D3, SC, C, E, Si, S1, S2, S3, So, T> Decider<C, E, So, Si>
+    for Map3Deciders<D1, D2, D3, SC, C, E, Si, S1, S2, S3, So, T>
+where
+    D1: Decider<C, E, S1, Si>,
+    D2: Decider<C, E, S2, Si>,
+    D3: Decider<C, E, S3, Si>,
+    SC: InfallibleConverter<(S1, S2, S3), So>,
+    T: TerminalPolicy,
+{
+    fn decide(command: &C, state: &Si) -> Vec<E> {
+        Self::decide_iter(command, state).collect()
+    }
+
+    fn decide_iter(
+        command: &C,
+        state: &Si,
+    ) -> impl Iterator<Item = E> + use<D1, D2, D3, SC, C, E, Si, S1, S2, S3, So, T> {
+        D1::decide_iter(command, state)
+            .chain(D2::decide_iter(command, state))
+            .chain(D3::decide_iter(command, state))
+    }
+
+    fn evolve(state: &Si, event: &E) -> So {
+        let s1 = D1::evolve(state, event);
+        let s2 = D2::evolve(state, event);
+        let s3 = D3::evolve(state, event);
+        SC::convert(&(s1, s2, s3))
+    }
+
+    fn evolve_owned(state: Si, event: &E) -> So {
+        let s1 = D1::evolve(&state, event);
+        let s2 = D2::evolve(&state, event);
+        let s3 = D3::evolve_owned(state, event);
+        SC::convert(&(s1, s2, s3))
+    }
+
+    fn initial_state() -> So {
+        SC::convert(&(
+            D1::initial_state(),
+            D2::initial_state(),
+            D3::initial_state(),
+        ))
+    }
+
+    fn is_terminal(state: &Si) -> bool {
+        let first_two = T::is_terminal(D1::is_terminal(state), D2::is_terminal(state));
+        T::is_terminal(first_two, D3::is_terminal(state))
+    }
+}
+
+/// A type to combine four deciders that take the same input types, and transform their output
+/// types into a common output type.
+///
+/// This is the same as [`Map3Deciders`], but for four deciders. `SC` takes as input the tuple of
+/// all four decider's output state types.
+pub struct Map4Deciders<D1, D2, D3, D4, SC, C, E, Si, S1, S2, S3, S4, So, T = AllTerminal>
+where
+    D1: Decider<C, E, S1, Si>,
+    D2: Decider<C, E, S2, Si>,
+    D3: Decider<C, E, S3, Si>,
+    D4: Decider<C, E, S4, Si>,
+    SC: InfallibleConverter<(S1, S2, S3, S4), So>,
+{
+    decider1: PhantomData<D1>,
+    decider2: PhantomData<D2>,
+    decider3: PhantomData<D3>,
+    decider4: PhantomData<D4>,
+    state_combiner: PhantomData<SC>,
+    command: PhantomData<C>,
+    event: PhantomData<E>,
+    state_initial: PhantomData<Si>,
+    state_decider_1: PhantomData<S1>,
+    state_decider_2: PhantomData<S2>,
+    state_decider_3: PhantomData<S3>,
+    state_decider_4: PhantomData<S4>,
+    state_output: PhantomData<So>,
+    terminal_policy: PhantomData<T>,
+}
+
+impl<D1, D2, D3, D4, SC, C, E, Si, S1, S2, S3, S4, So, T> Decider<C, E, So, Si>
+    for Map4Deciders<D1, D2, D3, D4, SC, C, E, Si, S1, S2, S3, S4, So, T>
+where
+    D1: Decider<C, E, S1, Si>,
+    D2: Decider<C, E, S2, Si>,
+    D3: Decider<C, E, S3, Si>,
+    D4: Decider<C, E, S4, Si>,
+    SC: InfallibleConverter<(S1, S2, S3, S4), So>,
+    T: TerminalPolicy,
+{
+    fn decide(command: &C, state: &Si) -> Vec<E> {
+        Self::decide_iter(command, state).collect()
+    }
+
+    fn decide_iter(
+        command: &C,
+        state: &Si,
+    ) -> impl Iterator<Item = E> + use<D1, D2, D3, D4, SC, C, E, Si, S1, S2, S3, S4, So, T> {
+        D1::decide_iter(command, state)
+            .chain(D2::decide_iter(command, state))
+            .chain(D3::decide_iter(command, state))
+            .chain(D4::decide_iter(command, state))
+    }
+
+    fn evolve(state: &Si, event: &E) -> So {
+        let s1 = D1::evolve(state, event);
+        let s2 = D2::evolve(state, event);
+        let s3 = D3::evolve(state, event);
+        let s4 = D4::evolve(state, event);
+        SC::convert(&(s1, s2, s3, s4))
+    }
+
+    fn evolve_owned(state: Si, event: &E) -> So {
+        let s1 = D1::evolve(&state, event);
+        let s2 = D2::evolve(&state, event);
+        let s3 = D3::evolve(&state, event);
+        let s4 = D4::evolve_owned(state, event);
+        SC::convert(&(s1, s2, s3, s4))
+    }
+
+    fn initial_state() -> So {
+        SC::convert(&(
+            D1::initial_state(),
+            D2::initial_state(),
+            D3::initial_state(),
+            D4::initial_state(),
+        ))
+    }
+
+    fn is_terminal(state: &Si) -> bool {
+        let first_two = T::is_terminal(D1::is_terminal(state), D2::is_terminal(state));
+        let first_three = T::is_terminal(first_two, D3::is_terminal(state));
+        T::is_terminal(first_three, D4::is_terminal(state))
+    }
+}
+
+/// A type to modify both the input and the output state types of a decider, so that they are the
+/// same new state type.
+///
+/// This type takes a decider that implements `Decider<C, E, Sdo, Sdi>`, a converter `SInC` that
+/// implements [`InfallibleConverter`] from the new state type `Sn` into the decider's input state
+/// type, and a converter `SOutC` that implements [`InfallibleConverter`] from the decider's output
+/// state type into `Sn`, and implements `Decider<C, E, Sn, Sn>`.
+///
+/// This replaces stacking [`MappedDecider`] on top of [`AdaptedDecider`] when only the state types
+/// need converting:
+///
+/// ```
+/// use deciders_rs::{decider, deciders::{Decider, DimappedDecider}};
+/// use deciders_rs::utilities::InfallibleConverter;
+///
+/// pub enum Command { Toggle }
+/// pub enum Event { Toggled }
+/// #[derive(Clone, Copy, Debug, PartialEq)]
+/// pub enum State { Off, On }
+///
+/// pub struct Switch;
+///
+/// decider! {
+///     Switch[Command, Event, State];
+///     decide(command, state) {
+///         (Command::Toggle, _) => vec![Event::Toggled],
+///     }
+///     evolve(state, event) {
+///         (State::Off, Event::Toggled) => State::On,
+///         (State::On, Event::Toggled) => State::Off,
+///     }
+///     initial_state() { State::Off }
+///     is_terminal(state) { false }
+/// }
+///
+/// #[derive(Debug, PartialEq)]
+/// pub struct Lamp { lit: bool }
+///
+/// pub struct LampToState;
+/// impl InfallibleConverter<Lamp, State> for LampToState {
+///     fn convert(lamp: &Lamp) -> State {
+///         if lamp.lit { State::On } else { State::Off }
+///     }
+/// }
+///
+/// pub struct StateToLamp;
+/// impl InfallibleConverter<State, Lamp> for StateToLamp {
+///     fn convert(state: &State) -> Lamp {
+///         Lamp { lit: *state == State::On }
+///     }
+/// }
+///
+/// // Instead of
+/// // MappedDecider<AdaptedDecider<Switch, .., LampToState, .., Lamp, State>, StateToLamp, ..>
+/// type LampSwitch = DimappedDecider<Switch, LampToState, StateToLamp, State, State>;
+///
+/// assert_eq!(LampSwitch::initial_state(), Lamp { lit: false });
+/// assert_eq!(LampSwitch::evolve(&Lamp { lit: false }, &Event::Toggled), Lamp { lit: true });
+/// ```
+pub struct DimappedDecider<D, SInC, SOutC, Sdo, Sdi> {
+    decider: PhantomData<D>,
+    state_input_converter: PhantomData<SInC>,
+    state_output_converter: PhantomData<SOutC>,
+    state_decider_output: PhantomData<Sdo>,
+    state_decider_input: PhantomData<Sdi>,
+}
+
+impl<D, SInC, SOutC, C, E, Sn, Sdo, Sdi> Decider<C, E, Sn, Sn>
+    for DimappedDecider<D, SInC, SOutC, Sdo, Sdi>
+where
+    D: Decider<C, E, Sdo, Sdi>,
+    SInC: InfallibleConverter<Sn, Sdi>,
+    SOutC: InfallibleConverter<Sdo, Sn>,
+{
+    fn decide(command: &C, state: &Sn) -> Vec<E> {
+        D::decide(command, &SInC::convert(state))
+    }
+
+    fn evolve(state: &Sn, event: &E) -> Sn {
+        SOutC::convert(&D::evolve_owned(SInC::convert(state), event))
+    }
+
+    fn initial_state() -> Sn {
+        SOutC::convert(&D::initial_state())
+    }
+
+    fn is_terminal(state: &Sn) -> bool {
+        D::is_terminal(&SInC::convert(state))
+    }
+
+    fn evolve_owned(state: Sn, event: &E) -> Sn {
+        SOutC::convert(&D::evolve_owned(SInC::convert(&state), event))
+    }
+
+    fn evolve_in_place(state: &mut Sn, event: &E) {
+        *state = SOutC::convert(&D::evolve_owned(SInC::convert(state), event));
+    }
+}
+
+/// The [`DeciderAt`] counterpart of [`MappedDecider`], converting the output state of the decider
+/// `D` into the state type `Sn` with the converter `SC`.
+///
+/// Every method is forwarded to the equivalent [`MappedDecider`].
+pub struct Mapped<D, SC, Sn> {
+    decider: PhantomData<D>,
+    state_converter: PhantomData<SC>,
+    state_new: PhantomData<Sn>,
+}
+
+type MappedAt<D, SC, Sn> = MappedDecider<
+    D,
+    SC,
+    <D as DeciderAt>::Command,
+    <D as DeciderAt>::Event,
+    Sn,
+    <D as DeciderAt>::StateOut,
+    <D as DeciderAt>::StateIn,
+>;
+
+impl<D, SC, Sn> DeciderAt for Mapped<D, SC, Sn>
+where
+    D: DeciderAt,
+    SC: InfallibleConverter<D::StateOut, Sn>,
+{
+    type Command = D::Command;
+    type Event = D::Event;
+    type StateOut = Sn;
+    type StateIn = D::StateIn;
+
+    fn decide(command: &D::Command, state: &D::StateIn) -> Vec<D::Event> {
+        MappedAt::<D, SC, Sn>::decide(command, state)
+    }
+
+    fn decide_iter(
+        command: &D::Command,
+        state: &D::StateIn,
+    ) -> impl Iterator<Item = D::Event> + use<D, SC, Sn> {
+        MappedAt::<D, SC, Sn>::decide_iter(command, state)
+    }
+
+    fn evolve(state: &D::StateIn, event: &D::Event) -> Sn {
+        MappedAt::<D, SC, Sn>::evolve(state, event)
+    }
+
+    fn evolve_owned(state: D::StateIn, event: &D::Event) -> Sn {
+        MappedAt::<D, SC, Sn>::evolve_owned(state, event)
+    }
+
+    fn initial_state() -> Sn {
+        MappedAt::<D, SC, Sn>::initial_state()
+    }
+
+    fn is_terminal(state: &D::StateIn) -> bool {
+        MappedAt::<D, SC, Sn>::is_terminal(state)
+    }
+}
+
+/// The [`DeciderAt`] counterpart of [`Map2Deciders`], combining the deciders `D1` and `D2`, which
+/// take the same input types, and converting their output states into the state type `So` with the
+/// converter `SC`.
+///
+/// Every method is forwarded to the equivalent [`Map2Deciders`], including its [`TerminalPolicy`]
+/// `T`.
+pub struct Map2<D1, D2, SC, So, T = AllTerminal> {
+    decider1: PhantomData<D1>,
+    decider2: PhantomData<D2>,
+    state_combiner: PhantomData<SC>,
+    state_output: PhantomData<So>,
+    terminal_policy: PhantomData<T>,
+}
+
+type Map2At<D1, D2, SC, So, T> = Map2Deciders<
+    D1,
+    D2,
+    SC,
+    <D1 as DeciderAt>::Command,
+    <D1 as DeciderAt>::Event,
+    <D1 as DeciderAt>::StateIn,
+    <D1 as DeciderAt>::StateOut,
+    <D2 as DeciderAt>::StateOut,
+    So,
+    T,
+>;
+
+impl<D1, D2, SC, So, T> DeciderAt for Map2<D1, D2, SC, So, T>
+where
+    D1: DeciderAt,
+    D2: DeciderAt<Command = D1::Command, Event = D1::Event, StateIn = D1::StateIn>,
+    SC: InfallibleConverter<(D1::StateOut, D2::StateOut), So>,
+    T: TerminalPolicy,
+{
+    type Command = D1::Command;
+    type Event = D1::Event;
+    type StateOut = So;
+    type StateIn = D1::StateIn;
+
+    fn decide(command: &D1::Command, state: &D1::StateIn) -> Vec<D1::Event> {
+        Map2At::<D1, D2, SC, So, T>::decide(command, state)
+    }
+
+    fn decide_iter(
+        command: &D1::Command,
+        state: &D1::StateIn,
+    ) -> impl Iterator<Item = D1::Event> + use<D1, D2, SC, So, T> {
+        Map2At::<D1, D2, SC, So, T>::decide_iter(command, state)
+    }
+
+    fn evolve(state: &D1::StateIn, event: &D1::Event) -> So {
+        Map2At::<D1, D2, SC, So, T>::evolve(state, event)
+    }
+
+    fn evolve_owned(state: D1::StateIn, event: &D1::Event) -> So {
+        Map2At::<D1, D2, SC, So, T>::evolve_owned(state, event)
+    }
+
+    fn initial_state() -> So {
+        Map2At::<D1, D2, SC, So, T>::initial_state()
+    }
+
+    fn is_terminal(state: &D1::StateIn) -> bool {
+        Map2At::<D1, D2, SC, So, T>::is_terminal(state)
+    }
+}
+
+/// A trait for values that can be applied to an input of type `A`, giving an output of type `B`,
+/// such as the output state of the function decider of an [`AppliedDecider`].
+///
+/// This is implemented for all functions and closures taking an `A` and returning a `B`, and can
+/// be implemented for other types, such as structs holding the values a function would capture.
+pub trait Apply<A, B> {
+    /// Applies this value to `input`.
+    fn apply(self, input: A) -> B;
+}
+
+impl<F, A, B> Apply<A, B> for F
+where
+    F: FnOnce(A) -> B,
+{
+    fn apply(self, input: A) -> B {
+        self(input)
+    }
+}
+
+/// This type takes a decider, whose output state is a function, and applies that function to the
+/// output state of a second decider.
+///
+/// The function is the type `F`, which can be anything that implements [`Apply`], such as a
+/// closure, or a boxed closure if it must be named. It defaults to a function pointer.
+pub struct AppliedDecider<FD, D, C, E, Si, Sd, So, F = fn(Sd) -> So>
+where
+    FD: Decider<C, E, F, Si>,
+    D: Decider<C, E, Sd, Si>,
+    F: Apply<Sd, So>,
+{
+    function_decider: PhantomData<FD>,
+    decider: PhantomData<D>,
     command: PhantomData<C>,
     event: PhantomData<E>,
     state_initial: PhantomData<Si>,
     state_decider: PhantomData<Sd>,
     state_output: PhantomData<So>,
+    function: PhantomData<F>,
 }
 
-impl<FD, D, C, E, Si, Sd, So> Decider<C, E, So, Si> for AppliedDecider<FD, D, C, E, Si, Sd, So>
+impl<FD, D, C, E, Si, Sd, So, F> Decider<C, E, So, Si>
+    for AppliedDecider<FD, D, C, E, Si, Sd, So, F>
 where
-    FD: Decider<C, E, fn(Sd) -> So, Si>,
+    FD: Decider<C, E, F, Si>,
     D: Decider<C, E, Sd, Si>,
+    F: Apply<Sd, So>,
 {
     fn decide(command: &C, state: &Si) -> Vec<E> {
-        FD::decide(command, state)
-            .into_iter()
-            .chain(D::decide(command, state))
-            .collect()
+        Self::decide_iter(command, state).collect()
+    }
+
+    fn decide_iter(
+        command: &C,
+        state: &Si,
+    ) -> impl Iterator<Item = E> + use<FD, D, C, E, Si, Sd, So, F> {
+        FD::decide_iter(command, state).chain(D::decide_iter(command, state))
     }
 
     fn evolve(state: &Si, event: &E) -> So {
         let s1 = FD::evolve(state, event);
         let s2 = D::evolve(state, event);
-        s1(s2)
+        s1.apply(s2)
     }
 
     fn initial_state() -> So {
-        FD::initial_state()(D::initial_state())
+        FD::initial_state().apply(D::initial_state())
     }
 
     fn is_terminal(state: &Si) -> bool {
         FD::is_terminal(state) && D::is_terminal(state)
     }
 }
+
+/// A value standing for the decider type `D`, returned by [`adapt`], so that the decider can be
+/// used without restating its command, event and state types, which are instead inferred from the
+/// arguments given to its methods.
+///
+/// [`compose`](fn@compose) and [`map`] return a [`ComposedToken`] and a [`MappedToken`], whose
+/// combinators take the types of the deciders as parameters, so they are inferred too.
+pub struct DeciderToken<D> {
+    decider: PhantomData<D>,
+}
+
+impl<D> DeciderToken<D> {
+    /// Returns the token for the decider type `D`.
+    pub fn new() -> Self {
+        DeciderToken {
+            decider: PhantomData,
+        }
+    }
+
+    /// Given the current state `state` and an event `event`, returns the new state of the decider,
+    /// as in [`Decider::evolve`].
+    pub fn evolve<C, E, So, Si>(&self, state: &Si, event: &E) -> So
+    where
+        D: Decider<C, E, So, Si>,
+    {
+        D::evolve(state, event)
+    }
+
+    /// Returns the state of the decider after evolving its initial state with every event in
+    /// `events`, in order, as in [`Decider::fold`].
+    pub fn fold<C, E, So, Si>(&self, events: &[E]) -> Si
+    where
+        D: Decider<C, E, So, Si>,
+        So: Into<Si>,
+    {
+        D::fold(events)
+    }
+
+    /// Decides the command `command` in the state reached by evolving the initial state with every
+    /// event in `events`, returning the events it generates.
+    pub fn run<C, E, So, Si>(&self, events: &[E], command: &C) -> Vec<E>
+    where
+        D: Decider<C, E, So, Si>,
+        So: Into<Si>,
+    {
+        D::decide(command, &D::fold(events))
+    }
+}
+
+impl<D> Default for DeciderToken<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Implemented by hand, since deriving them would require `D` to implement them too, which
+// deciders never need to.
+impl<D> Clone for DeciderToken<D> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<D> Copy for DeciderToken<D> {}
+
+impl<D> std::fmt::Debug for DeciderToken<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DeciderToken<{}>", std::any::type_name::<D>())
+    }
+}
+
+/// A value standing for the deciders `D1` and `D2` combined with [`ComposedDeciders`], returned by
+/// [`compose`](fn@compose).
+///
+/// The command, event and state types of both deciders are inferred from the arguments given to
+/// its methods, so they never need to be restated.
+pub struct ComposedToken<D1, D2> {
+    decider1: PhantomData<D1>,
+    decider2: PhantomData<D2>,
+}
+
+impl<D1, D2> ComposedToken<D1, D2> {
+    /// Returns the token for the deciders `D1` and `D2` combined with [`ComposedDeciders`].
+    pub fn new() -> Self {
+        ComposedToken {
+            decider1: PhantomData,
+            decider2: PhantomData,
+        }
+    }
+
+    /// Given the current state `state` and an event `event`, returns the new state of the
+    /// composed decider, as in [`Decider::evolve`].
+    pub fn evolve<C1, E1, S1, C2, E2, S2>(
+        &self,
+        state: &(S1, S2),
+        event: &Either<E1, E2>,
+    ) -> (S1, S2)
+    where
+        S1: Clone,
+        S2: Clone,
+        D1: Decider<C1, E1, S1, S1>,
+        D2: Decider<C2, E2, S2, S2>,
+    {
+        ComposedDeciders::<D1, C1, E1, S1, D2, C2, E2, S2>::evolve(state, event)
+    }
+
+    /// Returns the state of the composed decider after evolving its initial state with every
+    /// event in `events`, in order, as in [`Decider::fold`].
+    pub fn fold<C1, E1, S1, C2, E2, S2>(&self, events: &[Either<E1, E2>]) -> (S1, S2)
+    where
+        S1: Clone,
+        S2: Clone,
+        D1: Decider<C1, E1, S1, S1>,
+        D2: Decider<C2, E2, S2, S2>,
+    {
+        ComposedDeciders::<D1, C1, E1, S1, D2, C2, E2, S2>::fold(events)
+    }
+
+    /// Decides the command `command` in the state reached by evolving the initial state with every
+    /// event in `events`, returning the events it generates.
+    pub fn run<C1, E1, S1, C2, E2, S2>(
+        &self,
+        events: &[Either<E1, E2>],
+        command: &Either<C1, C2>,
+    ) -> Vec<Either<E1, E2>>
+    where
+        S1: Clone,
+        S2: Clone,
+        D1: Decider<C1, E1, S1, S1>,
+        D2: Decider<C2, E2, S2, S2>,
+    {
+        ComposedDeciders::<D1, C1, E1, S1, D2, C2, E2, S2>::decide(command, &self.fold(events))
+    }
+}
+
+impl<D1, D2> Default for ComposedToken<D1, D2> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D1, D2> Clone for ComposedToken<D1, D2> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<D1, D2> Copy for ComposedToken<D1, D2> {}
+
+impl<D1, D2> std::fmt::Debug for ComposedToken<D1, D2> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ComposedToken<{}, {}>",
+            std::any::type_name::<D1>(),
+            std::any::type_name::<D2>()
+        )
+    }
+}
+
+/// A value standing for the decider `D` with its output state converted by `SC`, as
+/// [`MappedDecider`], returned by [`map`].
+///
+/// The command, event and state types of `D` are inferred from the arguments given to its
+/// methods. A converter can convert into several state types, so the new state type is inferred
+/// from how the result is used when `SC` has more than one conversion from the state of `D`.
+pub struct MappedToken<D, SC> {
+    decider: PhantomData<D>,
+    state_converter: PhantomData<SC>,
+}
+
+impl<D, SC> MappedToken<D, SC> {
+    /// Returns the token for the decider `D` with its output state converted by `SC`.
+    pub fn new() -> Self {
+        MappedToken {
+            decider: PhantomData,
+            state_converter: PhantomData,
+        }
+    }
+
+    /// Given the current state `state` and an event `event`, returns the new, converted state of
+    /// the decider, as in [`Decider::evolve`].
+    pub fn evolve<C, E, Sn, Sdo, Sdi>(&self, state: &Sdi, event: &E) -> Sn
+    where
+        D: Decider<C, E, Sdo, Sdi>,
+        SC: InfallibleConverter<Sdo, Sn>,
+    {
+        MappedDecider::<D, SC, C, E, Sn, Sdo, Sdi>::evolve(state, event)
+    }
+
+    /// Returns the state of the decider after evolving its initial state with every event in
+    /// `events`, in order, as in [`Decider::fold`].
+    ///
+    /// The state is converted by `SC` after every event, so the new state type must convert back
+    /// into the input state of `D`.
+    pub fn fold<C, E, Sn, Sdo, Sdi>(&self, events: &[E]) -> Sdi
+    where
+        D: Decider<C, E, Sdo, Sdi>,
+        SC: InfallibleConverter<Sdo, Sn>,
+        Sn: Into<Sdi>,
+    {
+        MappedDecider::<D, SC, C, E, Sn, Sdo, Sdi>::fold(events)
+    }
+
+    /// Decides the command `command` in the state reached by evolving the initial state with every
+    /// event in `events`, returning the events it generates.
+    pub fn run<C, E, Sn, Sdo, Sdi>(&self, events: &[E], command: &C) -> Vec<E>
+    where
+        D: Decider<C, E, Sdo, Sdi>,
+        SC: InfallibleConverter<Sdo, Sn>,
+        Sn: Into<Sdi>,
+    {
+        MappedDecider::<D, SC, C, E, Sn, Sdo, Sdi>::decide(command, &self.fold(events))
+    }
+}
+
+impl<D, SC> Default for MappedToken<D, SC> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D, SC> Clone for MappedToken<D, SC> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<D, SC> Copy for MappedToken<D, SC> {}
+
+impl<D, SC> std::fmt::Debug for MappedToken<D, SC> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "MappedToken<{}, {}>",
+            std::any::type_name::<D>(),
+            std::any::type_name::<SC>()
+        )
+    }
+}
+
+/// Returns the token for the deciders `D1` and `D2` combined with [`ComposedDeciders`].
+pub fn compose<D1, D2>() -> ComposedToken<D1, D2> {
+    ComposedToken::new()
+}
+
+/// Returns the token for the decider `D` adapted with the [`Adapter`] `A`, as [`Adapted`].
+pub fn adapt<D, A>() -> DeciderToken<Adapted<D, A>> {
+    DeciderToken::new()
+}
+
+/// Returns the token for the decider `D` with its output state converted by `SC`, as
+/// [`MappedDecider`].
+pub fn map<D, SC>() -> MappedToken<D, SC> {
+    MappedToken::new()
+}
+
+/// An object safe version of [`Decider`], whose methods take `self`, for choosing a decider at
+/// runtime or storing deciders of different types behind the same shared command, event and
+/// state types, such as in a [`Registry`](crate::utilities::Registry).
+pub trait DynDecider<C, E, S> {
+    /// Given an incoming command `command` and the current state `state`, output a vector of
+    /// events that the command and state combination generates.
+    fn decide(&self, command: &C, state: &S) -> Vec<E>;
+
+    /// Returns whether the decider understands the command `command` in the state `state`.
+    ///
+    /// Deciders over shared types, such as [`ErasedDecider`], return `false` when the command or
+    /// the state cannot be converted to their own types, and decide no events for them. Defaults
+    /// to `true`.
+    fn accepts(&self, _command: &C, _state: &S) -> bool {
+        true
+    }
+
+    /// Given the current state `state` and an event `event`, return the new state. Events or
+    /// states that cannot be converted to the decider's own types leave the state unchanged.
+    fn evolve(&self, state: &S, event: &E) -> S;
+
+    /// Returns the initial state of the decider.
+    fn initial_state(&self) -> S;
+
+    /// Given the current state `state`, return whether or not the decider has reached an end
+    /// state.
+    fn is_terminal(&self, state: &S) -> bool;
+
+    /// Given the current state `state`, which it takes ownership of, and an event `event`, return
+    /// the new state, as in [`Decider::evolve_owned`].
+    fn evolve_owned(&self, state: S, event: &E) -> S {
+        self.evolve(&state, event)
+    }
+
+    /// Evolves the state `state` in place with the event `event`, as in
+    /// [`Decider::evolve_in_place`]. The runners of dynamic deciders evolve through this method.
+    fn evolve_in_place(&self, state: &mut S, event: &E) {
+        *state = self.evolve(state, event);
+    }
+}
+
+/// Adapts the decider `D` into a [`DynDecider`] over shared command, event and state types.
+///
+/// The converter `CV` converts between the shared types and the decider's native types `C`, `E`
+/// and `S`. It must implement [`FallibleConverter`] from each shared type to the native type,
+/// and [`InfallibleConverter`] from the native event and state types to the shared ones.
+pub struct ErasedDecider<D, CV, C, E, S>
+where
+    D: Decider<C, E, S, S>,
+{
+    decider: PhantomData<D>,
+    converter: PhantomData<CV>,
+    command: PhantomData<C>,
+    event: PhantomData<E>,
+    state: PhantomData<S>,
+}
+
+impl<D, CV, C, E, S> ErasedDecider<D, CV, C, E, S>
+where
+    D: Decider<C, E, S, S>,
+{
+    /// Constructs a new `ErasedDecider`.
+    pub fn new() -> Self {
+        Self {
+            decider: PhantomData,
+            converter: PhantomData,
+            command: PhantomData,
+            event: PhantomData,
+            state: PhantomData,
+        }
+    }
+}
+
+impl<D, CV, C, E, S> Default for ErasedDecider<D, CV, C, E, S>
+where
+    D: Decider<C, E, S, S>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D, CV, C, E, S, Cs, Es, Ss> DynDecider<Cs, Es, Ss> for ErasedDecider<D, CV, C, E, S>
+where
+    Ss: Clone,
+    D: Decider<C, E, S, S>,
+    CV: FallibleConverter<Cs, C>
+        + FallibleConverter<Es, E>
+        + InfallibleConverter<E, Es>
+        + FallibleConverter<Ss, S>
+        + InfallibleConverter<S, Ss>,
+{
+    fn decide(&self, command: &Cs, state: &Ss) -> Vec<Es> {
+        match (
+            <CV as FallibleConverter<Cs, C>>::convert(command),
+            <CV as FallibleConverter<Ss, S>>::convert(state),
+        ) {
+            (Some(c), Some(s)) => D::decide(&c, &s)
+                .iter()
+                .map(<CV as InfallibleConverter<E, Es>>::convert)
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    fn accepts(&self, command: &Cs, state: &Ss) -> bool {
+        <CV as FallibleConverter<Cs, C>>::convert(command).is_some()
+            && <CV as FallibleConverter<Ss, S>>::convert(state).is_some()
+    }
+
+    fn evolve(&self, state: &Ss, event: &Es) -> Ss {
+        match (
+            <CV as FallibleConverter<Ss, S>>::convert(state),
+            <CV as FallibleConverter<Es, E>>::convert(event),
+        ) {
+            (Some(s), Some(e)) => <CV as InfallibleConverter<S, Ss>>::convert(&D::evolve(&s, &e)),
+            _ => state.clone(),
+        }
+    }
+
+    fn initial_state(&self) -> Ss {
+        <CV as InfallibleConverter<S, Ss>>::convert(&D::initial_state())
+    }
+
+    fn is_terminal(&self, state: &Ss) -> bool {
+        <CV as FallibleConverter<Ss, S>>::convert(state).is_some_and(|s| D::is_terminal(&s))
+    }
+}
+
+/// Adapts the decider `D` into a [`DynDecider`] over its own command, event and state types, so
+/// it can be stored alongside hand-written [`DynDecider`]s.
+pub struct Statically<D> {
+    decider: PhantomData<D>,
+}
+
+impl<D> Statically<D> {
+    /// Constructs a new `Statically`.
+    pub fn new() -> Self {
+        Self {
+            decider: PhantomData,
+        }
+    }
+}
+
+impl<D> Default for Statically<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D, C, E, S> DynDecider<C, E, S> for Statically<D>
+where
+    D: Decider<C, E, S, S>,
+{
+    fn decide(&self, command: &C, state: &S) -> Vec<E> {
+        D::decide(command, state)
+    }
+
+    fn evolve(&self, state: &S, event: &E) -> S {
+        D::evolve(state, event)
+    }
+
+    fn initial_state(&self) -> S {
+        D::initial_state()
+    }
+
+    fn is_terminal(&self, state: &S) -> bool {
+        D::is_terminal(state)
+    }
+
+    fn evolve_owned(&self, state: S, event: &E) -> S {
+        D::evolve_owned(state, event)
+    }
+
+    fn evolve_in_place(&self, state: &mut S, event: &E) {
+        D::evolve_in_place(state, event)
+    }
+}
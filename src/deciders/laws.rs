@@ -0,0 +1,155 @@
+use std::{
+    fmt::{Debug, Display},
+    panic::{catch_unwind, AssertUnwindSafe},
+};
+
+use super::Decider;
+
+/// The most states [`check_decider`] explores, so that it finishes even for deciders with an
+/// unbounded number of states.
+pub const MAX_LAW_STATES: usize = 1000;
+
+/// A law that a well-formed decider should satisfy, checked by [`check_decider`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Law {
+    /// Folding the same events from the initial state always gives the same state as evolving
+    /// with them one at a time.
+    DeterministicFold,
+    /// Deciding a command never panics.
+    DecideDoesNotPanic,
+    /// Evolving with an event never panics, whether it was generated by deciding a command, or
+    /// given to a terminal state.
+    EvolveDoesNotPanic,
+    /// A terminal state stays terminal after evolving with any event.
+    TerminalStaysTerminal,
+    /// Folding no events gives the initial state.
+    InitialStateRoundTrips,
+}
+
+/// A violation of a [`Law`] found by [`check_decider`], along with the commands that were decided
+/// and the events that were evolved from the initial state to find it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LawViolation<C, E> {
+    /// The law that was violated.
+    pub law: Law,
+    /// The commands decided from the initial state, ending with the offending command if there is
+    /// one.
+    pub commands: Vec<C>,
+    /// The events evolved from the initial state, ending with the offending event if there is
+    /// one.
+    pub events: Vec<E>,
+}
+
+impl<C: Debug, E: Debug> Display for LawViolation<C, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "law {:?} violated after commands {:?} and events {:?}",
+            self.law, self.commands, self.events
+        )
+    }
+}
+
+impl<C: Debug, E: Debug> std::error::Error for LawViolation<C, E> {}
+
+/// A state reached while checking a decider, along with how it was reached.
+struct Reached<C, E, S> {
+    state: S,
+    commands: Vec<C>,
+    events: Vec<E>,
+}
+
+/// Checks that the decider `D` satisfies every [`Law`], exploring the states reachable from its
+/// initial state by deciding the commands in `commands`, and giving each terminal state every
+/// event in `events`.
+///
+/// Every command is decided in every reached state, and states are deduplicated by equality. At
+/// most [`MAX_LAW_STATES`] states are explored. Panics are caught and reported as violations, but
+/// are still printed by the panic hook. Returns the first violation found, if any.
+pub fn check_decider<D, C, E, S>(
+    commands: impl IntoIterator<Item = C>,
+    events: impl IntoIterator<Item = E>,
+) -> Result<(), LawViolation<C, E>>
+where
+    D: Decider<C, E, S, S>,
+    C: Clone,
+    E: Clone,
+    S: Clone + PartialEq,
+{
+    let commands: Vec<C> = commands.into_iter().collect();
+    let events: Vec<E> = events.into_iter().collect();
+    let violation = |law, commands: &[C], events: &[E]| LawViolation {
+        law,
+        commands: commands.to_vec(),
+        events: events.to_vec(),
+    };
+
+    if D::fold(&[]) != D::initial_state() {
+        return Err(violation(Law::InitialStateRoundTrips, &[], &[]));
+    }
+
+    let mut reached = vec![Reached {
+        state: D::initial_state(),
+        commands: vec![],
+        events: vec![],
+    }];
+    let mut next = 0;
+    while next < reached.len() && next < MAX_LAW_STATES {
+        let Reached {
+            state,
+            commands: path,
+            events: history,
+        } = &reached[next];
+        next += 1;
+
+        if D::fold(history) != *state || D::fold(history) != D::fold(history) {
+            return Err(violation(Law::DeterministicFold, path, history));
+        }
+
+        if D::is_terminal(state) {
+            for event in events.iter() {
+                let mut history = history.clone();
+                history.push(event.clone());
+                match catch_unwind(AssertUnwindSafe(|| D::evolve(state, event))) {
+                    Err(_) => return Err(violation(Law::EvolveDoesNotPanic, path, &history)),
+                    Ok(evolved) if !D::is_terminal(&evolved) => {
+                        return Err(violation(Law::TerminalStaysTerminal, path, &history))
+                    }
+                    Ok(_) => {}
+                }
+            }
+        }
+
+        let mut found = vec![];
+        for command in commands.iter() {
+            let mut path = path.clone();
+            path.push(command.clone());
+            let Ok(decided) = catch_unwind(AssertUnwindSafe(|| D::decide(command, state))) else {
+                return Err(violation(Law::DecideDoesNotPanic, &path, history));
+            };
+            if decided.is_empty() {
+                continue;
+            }
+            let mut history = history.clone();
+            let mut evolved = state.clone();
+            for event in decided {
+                history.push(event.clone());
+                match catch_unwind(AssertUnwindSafe(|| D::evolve(&evolved, &event))) {
+                    Ok(state) => evolved = state,
+                    Err(_) => return Err(violation(Law::EvolveDoesNotPanic, &path, &history)),
+                }
+            }
+            found.push(Reached {
+                state: evolved,
+                commands: path,
+                events: history,
+            });
+        }
+        for candidate in found {
+            if !reached.iter().any(|r| r.state == candidate.state) {
+                reached.push(candidate);
+            }
+        }
+    }
+    Ok(())
+}
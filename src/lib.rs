@@ -6,6 +6,13 @@
 //! [`evolve(state: &Si, event: &E) -> So`]: crate::deciders::Decider::evolve
 //! [`initial_state() -> So`]: crate::deciders::Decider::initial_state
 //! [`is_terminal(state: &Si) -> bool`]: crate::deciders::Decider::is_terminal
+//! [`fold`]: crate::deciders::Decider::fold
+//! [`fold_events`]: crate::deciders::fold_events
+//! [`decide_and_evolve`]: crate::deciders::Decider::decide_and_evolve
+//! [`decide_all`]: crate::deciders::Decider::decide_all
+//! [`evolve_owned`]: crate::deciders::Decider::evolve_owned
+//! [`decide_iter`]: crate::deciders::Decider::decide_iter
+//! [`evolve_in_place`]: crate::deciders::Decider::evolve_in_place
 //! [`Process<E, C, S>`]: crate::processes::Process
 //! [`evolve(state: &S, event: &E) -> S`]: crate::processes::Process::evolve
 //! [`resume(state: &S) -> Vec<C>`]: crate::processes::Process::resume
@@ -27,9 +34,201 @@
 //! [`collect_fold`]: crate::processes::collect_fold
 //! [`CombinedProcessDecider`]: crate::processes::CombinedProcessDecider
 //! [`InMemoryRunner`]: crate::utilities::InMemoryRunner
+//! [`EventSourcedRunner`]: crate::stores::EventSourcedRunner
+//! [`ComposedEventSourcedRunner`]: crate::stores::ComposedEventSourcedRunner
+//! [`EventStore`]: crate::stores::EventStore
+//! [`StoreError`]: crate::stores::StoreError
+//! [`InMemoryEventStore`]: crate::stores::InMemoryEventStore
+//! [`EventCodec`]: crate::stores::EventCodec
+//! [`EventSourcedRunner::apply_external`]: crate::stores::EventSourcedRunner::apply_external
+//! [`InboxStore`]: crate::stores::InboxStore
+//! [`ApplyResult::Duplicate`]: crate::stores::ApplyResult::Duplicate
+//! [`apply_retention`]: crate::stores::apply_retention
+//! [`RetentionPolicy`]: crate::stores::RetentionPolicy
+//! [`RetentionReport`]: crate::stores::RetentionReport
+//! [`EventSourcedRunnerBuilder`]: crate::stores::EventSourcedRunnerBuilder
+//! [`SnapshotPolicy`]: crate::stores::SnapshotPolicy
+//! [`DiagnosticSink`]: crate::stores::DiagnosticSink
+//! [`KeyedEventSourcedRunner`]: crate::stores::KeyedEventSourcedRunner
+//! [`KeyedSnapshotStore`]: crate::stores::KeyedSnapshotStore
+//! [`verify_snapshot`]: crate::stores::verify_snapshot
+//! [`migrate_stream`]: crate::stores::migrate_stream
+//! [`MigrationReport`]: crate::stores::MigrationReport
+//! [`ChaosStore`]: crate::stores::ChaosStore
+//! [`Projection<E>`]: crate::projections::Projection
+//! [`InMemoryProjection`]: crate::projections::InMemoryProjection
+//! [`CountByEventType`]: crate::projections::CountByEventType
+//! [`RoutedProjection`]: crate::projections::RoutedProjection
+//! [`KeyedProjection`]: crate::projections::KeyedProjection
+//! [`ProjectionRunner`]: crate::projections::ProjectionRunner
+//! [`EventLog`]: crate::projections::EventLog
+//! [`CheckpointStore`]: crate::projections::CheckpointStore
+//! [`QueryRegistry`]: crate::projections::QueryRegistry
+//! [`SqlProjection`]: crate::projections::SqlProjection
+//! [`ProjectionSpec`]: crate::testing::ProjectionSpec
+//! [`SagaBuilder`]: crate::processes::SagaBuilder
+//! [`SagaDefinition`]: crate::processes::SagaDefinition
+//! [`SagaProcess`]: crate::processes::SagaProcess
+//! [`WatchdogBuilder`]: crate::processes::WatchdogBuilder
+//! [`WatchdogDefinition`]: crate::processes::WatchdogDefinition
+//! [`WatchdogProcess`]: crate::processes::WatchdogProcess
+//! [`ProcessRunner`]: crate::processes::ProcessRunner
+//! [`ProcessStateStore`]: crate::processes::ProcessStateStore
+//! [`HumanTask`]: crate::processes::HumanTask
+//! [`to_dot`]: crate::viz::to_dot
+//! [`explore`]: crate::viz::explore
+//! [`trace_to_mermaid`]: crate::viz::trace_to_mermaid
+//! [`Trace`]: crate::viz::Trace
+//! [`simulate_trace`]: crate::viz::simulate_trace
+//! [`simulate_combined_trace`]: crate::viz::simulate_combined_trace
+//! [`WasmRunner`]: crate::wasm::WasmRunner
+//! [`spawn_thread_runner`]: crate::utilities::spawn_thread_runner
+//! [`Debugger`]: crate::debug::Debugger
+//! [`PublishingRunner`]: crate::utilities::PublishingRunner
+//! [`EventPublisher`]: crate::utilities::EventPublisher
+//! [`SimpleEventBus`]: crate::utilities::SimpleEventBus
+//! [`SharedRunner`]: crate::utilities::SharedRunner
+//! [`SharedEventBus`]: crate::utilities::SharedEventBus
+//! [`Locking`]: crate::utilities::Locking
+//! [`assert_transition_coverage`]: crate::testing::assert_transition_coverage
+//! [`TransitionExpectations`]: crate::testing::TransitionExpectations
+//! [`EnumerableState`]: crate::testing::EnumerableState
+//! [`EnumerableEvent`]: crate::testing::EnumerableEvent
+//! [`split_either`]: crate::utilities::split_either
+//! [`lefts`]: crate::utilities::lefts
+//! [`rights`]: crate::utilities::rights
+//! [`partition_keyed`]: crate::utilities::partition_keyed
+//! [`StateFingerprint`]: crate::utilities::StateFingerprint
+//! [`hash_fingerprint`]: crate::utilities::hash_fingerprint
+//! [`compare_replays`]: crate::testing::compare_replays
+//! [`Divergence`]: crate::testing::Divergence
+//! [`compare_replays_with_commands`]: crate::testing::compare_replays_with_commands
+//! [`AuthorizingRunner`]: crate::utilities::AuthorizingRunner
+//! [`Authorizer`]: crate::utilities::Authorizer
+//! [`Actor`]: crate::utilities::Actor
+//! [`HeterogeneousKeyedRunner`]: crate::utilities::HeterogeneousKeyedRunner
+//! [`Registry`]: crate::utilities::Registry
+//! [`DynDecider`]: crate::deciders::DynDecider
+//! [`ErasedDecider`]: crate::deciders::ErasedDecider
+//! [`Scheduler`]: crate::processes::Scheduler
+//! [`ScheduleStore`]: crate::processes::ScheduleStore
+//! [`DiffProjection`]: crate::projections::DiffProjection
+//! [`ViewChange`]: crate::projections::ViewChange
+//! [`diff_maps`]: crate::projections::diff_maps
+//! [`UpcastingCommandRunner`]: crate::utilities::UpcastingCommandRunner
+//! [`CommandUpcaster`]: crate::utilities::CommandUpcaster
+//! [`MigrationError`]: crate::utilities::MigrationError
+//! [`DynRunner`]: crate::utilities::DynRunner
+//! [`Statically`]: crate::deciders::Statically
+//! [`ConsistencyToken`]: crate::projections::ConsistencyToken
+//! [`Stale`]: crate::projections::Stale
+//! [`DeciderAt`]: crate::deciders::DeciderAt
+//! [`Bridged`]: crate::deciders::Bridged
+//! [`Composed`]: crate::deciders::Composed
+//! [`Mapped`]: crate::deciders::Mapped
+//! [`Map2`]: crate::deciders::Map2
+//! [`ProcessRouter`]: crate::processes::ProcessRouter
+//! [`RoutedProcess`]: crate::processes::RoutedProcess
+//! [`on_event`]: crate::processes::on_event
+//! [`on_event_type`]: crate::processes::on_event_type
+//! [`TimeBucketedProjection`]: crate::projections::TimeBucketedProjection
+//! [`TimeBucketDefinition`]: crate::projections::TimeBucketDefinition
+//! [`MaybeUnknown`]: crate::utilities::MaybeUnknown
+//! [`fold_known`]: crate::utilities::fold_known
+//! [`try_fold_known`]: crate::utilities::try_fold_known
+//! [`TryDecider`]: crate::deciders::TryDecider
+//! [`Lifted`]: crate::deciders::Lifted
+//! [`FallibleInMemoryRunner`]: crate::utilities::FallibleInMemoryRunner
+//! [`StateSpaceMonitor`]: crate::utilities::StateSpaceMonitor
+//! [`FingerprintSet`]: crate::utilities::FingerprintSet
+//! [`RejectingDecider`]: crate::deciders::RejectingDecider
+//! [`Validator`]: crate::deciders::Validator
+//! [`ContextDecider`]: crate::deciders::ContextDecider
+//! [`ProvidedContext`]: crate::deciders::ProvidedContext
+//! [`ContextProvider`]: crate::deciders::ContextProvider
+//! [`StreamVersion`]: crate::utilities::StreamVersion
+//! [`GlobalPosition`]: crate::utilities::GlobalPosition
+//! [`TerminalGuard`]: crate::deciders::TerminalGuard
+//! [`StrictTerminalGuard`]: crate::deciders::StrictTerminalGuard
+//! [`NeutralDecider`]: crate::deciders::NeutralDecider
+//! [`NeutralProcess`]: crate::processes::NeutralProcess
+//! [`NeutralFor`]: crate::deciders::NeutralFor
+//! [`check_decider`]: crate::deciders::laws::check_decider
+//! [`LawViolation`]: crate::deciders::laws::LawViolation
+//! [`Snapshotting`]: crate::deciders::Snapshotting
+//! [`UpcastingDecider`]: crate::deciders::UpcastingDecider
+//! [`EventUpcaster`]: crate::deciders::EventUpcaster
+//! [`ChainedUpcaster`]: crate::deciders::ChainedUpcaster
+//! [`decider!`]: crate::decider
+//! [`compose!`]: crate::compose
+//! [`InstrumentedDecider`]: crate::deciders::InstrumentedDecider
+//! [`CountingDecider`]: crate::deciders::CountingDecider
+//! [`DeciderStats`]: crate::deciders::DeciderStats
+//! [`Enumerable`]: crate::viz::Enumerable
+//! [`enumerate`]: crate::viz::enumerate
+//! [`BoundedDecider`]: crate::deciders::BoundedDecider
+//! [`StrictBoundedDecider`]: crate::deciders::StrictBoundedDecider
+//! [`DedupDecider`]: crate::deciders::DedupDecider
+//! [`DedupLastDecider`]: crate::deciders::DedupLastDecider
+//! [`FilteredDecider`]: crate::deciders::FilteredDecider
+//! [`Predicate`]: crate::deciders::Predicate
+//! [`SplitDecider`]: crate::deciders::SplitDecider
+//! [`BroadcastDecider`]: crate::deciders::BroadcastDecider
+//! [`SequencedDecider`]: crate::deciders::SequencedDecider
+//! [`Phase`]: crate::deciders::Phase
+//! [`RestartingDecider`]: crate::deciders::RestartingDecider
+//! [`MarkedRestartingDecider`]: crate::deciders::MarkedRestartingDecider
+//! [`ZoomedDecider`]: crate::deciders::ZoomedDecider
+//! [`Lens`]: crate::deciders::Lens
+//! [`GuardedDecider`]: crate::deciders::GuardedDecider
+//! [`Guard`]: crate::deciders::Guard
+//! [`TaggedDecider`]: crate::deciders::TaggedDecider
+//! [`StreamTag`]: crate::deciders::StreamTag
+//! [`MappedCommandDecider`]: crate::deciders::MappedCommandDecider
+//! [`MappedEventDecider`]: crate::deciders::MappedEventDecider
+//! [`Map3Deciders`]: crate::deciders::Map3Deciders
+//! [`Map4Deciders`]: crate::deciders::Map4Deciders
+//! [`Composed3Deciders`]: crate::deciders::Composed3Deciders
+//! [`Composed4Deciders`]: crate::deciders::Composed4Deciders
+//! [`OneOf3`]: crate::utilities::OneOf3
+//! [`OneOf4`]: crate::utilities::OneOf4
+//! [`Adapted`]: crate::deciders::Adapted
+//! [`Adapter`]: crate::deciders::Adapter
+//! [`TryAdaptedDecider`]: crate::deciders::TryAdaptedDecider
+//! [`Reporting`]: crate::deciders::Reporting
+//! [`DeadLetter`]: crate::deciders::DeadLetter
+//! [`Apply`]: crate::deciders::Apply
+//! [`Map2EitherDeciders`]: crate::deciders::Map2EitherDeciders
+//! [`TerminalPolicy`]: crate::deciders::TerminalPolicy
+//! [`AllTerminal`]: crate::deciders::AllTerminal
+//! [`AnyTerminal`]: crate::deciders::AnyTerminal
+//! [`DimappedDecider`]: crate::deciders::DimappedDecider
+//! [`compose`]: crate::deciders::compose
+//! [`adapt`]: crate::deciders::adapt
+//! [`map`]: crate::deciders::map
+//! [`ComposedToken`]: crate::deciders::ComposedToken
+//! [`MappedToken`]: crate::deciders::MappedToken
+//! [`DeciderToken`]: crate::deciders::DeciderToken
+//! [`Snapshot`]: crate::deciders::Snapshot
+//! [`SnapshotSchema`]: crate::deciders::SnapshotSchema
+//! [`TenantedRunner`]: crate::tenancy::TenantedRunner
+//! [`TenantHandle`]: crate::tenancy::TenantHandle
+//! [`SnapshotStore`]: crate::stores::SnapshotStore
 //! [examples]: https://github.com/AgentX1994/deciders.rs/blob/main/examples
 //! [integration tests]: https://github.com/AgentX1994/deciders.rs/blob/main/tests/integrations.rs
 #![doc = include_str!("../README.md")]
+pub mod debug;
 pub mod deciders;
+#[cfg(feature = "async")]
+pub mod pipeline;
 pub mod processes;
+pub mod projections;
+pub mod stores;
+pub mod tenancy;
+pub mod testing;
 pub mod utilities;
+pub mod viz;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "axum")]
+pub mod web;
@@ -0,0 +1,298 @@
+//! Adapters that connect runners to pipelines built from `futures` streams and sinks.
+//!
+//! Requires the `async` feature.
+
+use std::{collections::VecDeque, fmt::Display, future::poll_fn};
+
+use futures::{channel::oneshot, FutureExt, Sink, SinkExt, Stream, StreamExt};
+
+use crate::{
+    deciders::Decider,
+    utilities::{GlobalPosition, InMemoryRunner},
+};
+
+/// What an [`EventSinkForwarder`] does when its sink is not ready to accept more events.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SinkPolicy {
+    /// Each command is only acknowledged once all of its events have been accepted and flushed
+    /// by the sink, so a slow sink slows down the commands.
+    Wait,
+    /// Events the sink is not ready for are kept in a buffer of at most this many events, and
+    /// commands are acknowledged right away until the buffer is full. Once it is full, commands
+    /// wait for the sink as with [`SinkPolicy::Wait`].
+    Buffer(usize),
+}
+
+/// An error returned by an [`EventSinkForwarder`] that could not forward events to its sink.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ForwardError<SE> {
+    /// The sink returned the error `error`. The forwarder has stopped, and had forwarded the
+    /// events before `position`.
+    Sink {
+        /// The error returned by the sink.
+        error: SE,
+        /// The position of the first event that was not forwarded.
+        position: GlobalPosition,
+    },
+    /// The forwarder had already stopped because of an earlier sink error, after forwarding the
+    /// events before `position`. Nothing was decided or forwarded.
+    Stopped {
+        /// The position of the first event that was not forwarded.
+        position: GlobalPosition,
+    },
+}
+
+impl<SE: Display> Display for ForwardError<SE> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ForwardError::Sink { error, position } => {
+                write!(f, "the sink failed at position {position}: {error}")
+            }
+            ForwardError::Stopped { position } => {
+                write!(f, "the forwarder stopped at position {position}")
+            }
+        }
+    }
+}
+
+impl<SE: std::fmt::Debug + Display> std::error::Error for ForwardError<SE> {}
+
+/// Runs a decider like [`InMemoryRunner`], and forwards every event it generates, in order, into
+/// the sink `Si`, such as the sending half of a channel or a sink built from `futures`
+/// combinators.
+///
+/// When the sink is not ready, commands wait for it or buffer its events as configured by a
+/// [`SinkPolicy`]. Commands read from a stream are fed by a [`CommandStreamDriver`].
+///
+/// If the sink returns an error, the forwarder stops: the failing call returns the error with
+/// the position of the first event that was not forwarded, and every later call returns
+/// [`ForwardError::Stopped`] without deciding anything. Events are counted from 0 for the first
+/// event forwarded.
+pub struct EventSinkForwarder<C, E, S, D, Si>
+where
+    D: Decider<C, E, S, S>,
+{
+    runner: InMemoryRunner<C, E, S, D>,
+    sink: Si,
+    policy: SinkPolicy,
+    buffer: VecDeque<E>,
+    position: GlobalPosition,
+    stopped: bool,
+}
+
+impl<C, E, S, D, Si> EventSinkForwarder<C, E, S, D, Si>
+where
+    D: Decider<C, E, S, S>,
+    E: Clone,
+    Si: Sink<E> + Unpin,
+{
+    /// Constructs a new `EventSinkForwarder` running the runner `runner`, which forwards its
+    /// events into `sink` as configured by `policy`.
+    pub fn new(runner: InMemoryRunner<C, E, S, D>, sink: Si, policy: SinkPolicy) -> Self {
+        Self {
+            runner,
+            sink,
+            policy,
+            buffer: VecDeque::new(),
+            position: GlobalPosition::default(),
+            stopped: false,
+        }
+    }
+
+    /// Feeds the given command `command` through the runner, forwards the generated events into
+    /// the sink, and returns them.
+    ///
+    /// The runner's state is evolved before the events are forwarded, so if the sink fails, the
+    /// runner has still evolved with every event of `command`, and the error's position tells
+    /// which of them were forwarded.
+    pub async fn command(&mut self, command: &C) -> Result<Vec<E>, ForwardError<Si::Error>> {
+        self.check_running()?;
+        let events = self.runner.command(command);
+        self.forward(events.iter().cloned()).await?;
+        Ok(events)
+    }
+
+    /// Forwards `events` into the sink after any buffered events, as configured by the
+    /// forwarder's [`SinkPolicy`].
+    async fn forward(
+        &mut self,
+        events: impl IntoIterator<Item = E>,
+    ) -> Result<(), ForwardError<Si::Error>> {
+        self.check_running()?;
+        self.buffer.extend(events);
+        let bound = match self.policy {
+            SinkPolicy::Wait => 0,
+            SinkPolicy::Buffer(bound) => bound,
+        };
+        while !self.buffer.is_empty() {
+            let ready = poll_fn(|cx| self.sink.poll_ready_unpin(cx));
+            let ready = if self.buffer.len() > bound {
+                Some(ready.await)
+            } else {
+                ready.now_or_never()
+            };
+            match ready {
+                // The sink is busy, and the buffer can hold the rest of the events.
+                None => break,
+                Some(ready) => self.send_next(ready)?,
+            }
+        }
+        let flushed = poll_fn(|cx| self.sink.poll_flush_unpin(cx));
+        let flushed = match self.policy {
+            SinkPolicy::Wait => Some(flushed.await),
+            SinkPolicy::Buffer(_) => flushed.now_or_never(),
+        };
+        match flushed {
+            Some(Err(error)) => Err(self.stop(error)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Waits until every buffered event has been accepted and flushed by the sink.
+    pub async fn flush(&mut self) -> Result<(), ForwardError<Si::Error>> {
+        self.check_running()?;
+        while !self.buffer.is_empty() {
+            let ready = poll_fn(|cx| self.sink.poll_ready_unpin(cx)).await;
+            self.send_next(ready)?;
+        }
+        let flushed = self.sink.flush().await;
+        flushed.map_err(|error| self.stop(error))
+    }
+
+    /// Returns the position of the next event to forward, which is the number of events
+    /// forwarded so far.
+    pub fn position(&self) -> GlobalPosition {
+        self.position
+    }
+
+    /// Returns the number of events waiting for the sink to be ready.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Returns the runner whose events are forwarded.
+    pub fn runner(&self) -> &InMemoryRunner<C, E, S, D> {
+        &self.runner
+    }
+
+    /// Returns an error if the forwarder has stopped.
+    fn check_running(&self) -> Result<(), ForwardError<Si::Error>> {
+        if self.stopped {
+            Err(ForwardError::Stopped {
+                position: self.position,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sends the first buffered event into the sink, given whether the sink was `ready` for it.
+    ///
+    /// Events only leave the buffer once the sink is ready, so that no event is lost if the
+    /// future waiting for the sink is dropped.
+    fn send_next(&mut self, ready: Result<(), Si::Error>) -> Result<(), ForwardError<Si::Error>> {
+        let event = self.buffer.pop_front().expect("the buffer is not empty");
+        ready
+            .and_then(|()| self.sink.start_send_unpin(event))
+            .map_err(|error| self.stop(error))?;
+        self.position = self.position + 1;
+        Ok(())
+    }
+
+    /// Stops the forwarder because the sink returned `error`.
+    fn stop(&mut self, error: Si::Error) -> ForwardError<Si::Error> {
+        self.stopped = true;
+        ForwardError::Sink {
+            error,
+            position: self.position,
+        }
+    }
+}
+
+/// A command read from a stream by a [`CommandStreamDriver`], along with where to acknowledge it.
+#[derive(Debug)]
+pub struct CommandEnvelope<C> {
+    /// The command to feed through the runner.
+    pub command: C,
+    /// The channel the position reached after the command is sent on, once its events have been
+    /// forwarded as configured by the forwarder's [`SinkPolicy`].
+    pub ack: Option<oneshot::Sender<GlobalPosition>>,
+}
+
+impl<C> CommandEnvelope<C> {
+    /// Constructs a new `CommandEnvelope` for `command`, which is not acknowledged.
+    pub fn new(command: C) -> Self {
+        Self { command, ack: None }
+    }
+
+    /// Constructs a new `CommandEnvelope` for `command`, returning it along with the receiving
+    /// end of its acknowledgment.
+    ///
+    /// If the forwarder fails before the command's events are forwarded, the acknowledgment is
+    /// dropped, so the receiver reports that it was canceled.
+    pub fn with_ack(command: C) -> (Self, oneshot::Receiver<GlobalPosition>) {
+        let (ack, acked) = oneshot::channel();
+        (
+            Self {
+                command,
+                ack: Some(ack),
+            },
+            acked,
+        )
+    }
+}
+
+/// Reads [`CommandEnvelope`]s from a stream and feeds their commands through an
+/// [`EventSinkForwarder`], acknowledging each one once its events have been forwarded.
+///
+/// Since a command is only acknowledged once the forwarder has handled its events, a sink that
+/// applies backpressure also holds back the acknowledgments.
+pub struct CommandStreamDriver<C, E, S, D, Si>
+where
+    D: Decider<C, E, S, S>,
+{
+    forwarder: EventSinkForwarder<C, E, S, D, Si>,
+}
+
+impl<C, E, S, D, Si> CommandStreamDriver<C, E, S, D, Si>
+where
+    D: Decider<C, E, S, S>,
+    E: Clone,
+    Si: Sink<E> + Unpin,
+{
+    /// Constructs a new `CommandStreamDriver` feeding commands through `forwarder`.
+    pub fn new(forwarder: EventSinkForwarder<C, E, S, D, Si>) -> Self {
+        Self { forwarder }
+    }
+
+    /// Feeds the command of every envelope read from `commands` through the forwarder, in order,
+    /// until the stream ends or the sink fails, and returns the number of commands fed.
+    ///
+    /// Envelopes whose acknowledgment receiver was dropped are still fed.
+    pub async fn run(
+        &mut self,
+        commands: impl Stream<Item = CommandEnvelope<C>>,
+    ) -> Result<usize, ForwardError<Si::Error>> {
+        let mut commands = std::pin::pin!(commands);
+        let mut fed = 0;
+        while let Some(envelope) = commands.next().await {
+            self.forwarder.command(&envelope.command).await?;
+            if let Some(ack) = envelope.ack {
+                // The receiver may have stopped waiting, which does not undo the command.
+                let _ = ack.send(self.forwarder.position());
+            }
+            fed += 1;
+        }
+        Ok(fed)
+    }
+
+    /// Returns the forwarder commands are fed through.
+    pub fn forwarder(&self) -> &EventSinkForwarder<C, E, S, D, Si> {
+        &self.forwarder
+    }
+
+    /// Consumes this driver, returning its forwarder.
+    pub fn into_forwarder(self) -> EventSinkForwarder<C, E, S, D, Si> {
+        self.forwarder
+    }
+}
@@ -1,7 +1,20 @@
-use std::marker::PhantomData;
+use std::{
+    any::{Any, TypeId},
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{Debug, Display},
+    marker::PhantomData,
+    rc::Rc,
+};
 
-use crate::deciders::Decider;
-use crate::utilities::{FallibleConverter, InfallibleConverter};
+use crate::deciders::{BoundedDecider, Decider, NeutralFor, TerminalGuard};
+use crate::projections::event_type_name;
+use crate::utilities::{FallibleConverter, InfallibleConverter, StreamVersion};
+
+#[cfg(all(feature = "sqlite", feature = "serde"))]
+mod sqlite;
+#[cfg(all(feature = "sqlite", feature = "serde"))]
+pub use sqlite::{create_schedule_table, SqliteScheduleStore};
 
 /// A trait representing a Process
 ///
@@ -115,9 +128,9 @@ where
 pub fn collect_fold<P, E, C, S>(state: &S, events: &[E]) -> Vec<C>
 where
     P: Process<E, C, S>,
-    S: Copy + Clone,
+    S: Clone,
 {
-    let mut s = *state;
+    let mut s = state.clone();
     let mut all_commands = vec![];
     for event in events {
         s = P::evolve(&s, event);
@@ -126,6 +139,141 @@ where
     all_commands
 }
 
+/// The state of a process wrapped in a [`TerminalGuard`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GuardedState<S> {
+    /// The state of the wrapped process.
+    pub state: S,
+    /// Whether the wrapped process was already terminal before the last event, meaning it must
+    /// not react to it.
+    pub finished: bool,
+}
+
+/// A process wrapped in a [`TerminalGuard`] issues no commands in reaction to events that arrive
+/// once it has reached a terminal state.
+///
+/// The guard checks the state from before each event, which it records in a [`GuardedState`], so
+/// the process still reacts to the event that made it terminal, such as a saga issuing its
+/// compensations. Resuming issues the wrapped process's commands unless the last event arrived
+/// after it was terminal.
+impl<P, E, C, S> Process<E, C, GuardedState<S>> for TerminalGuard<P>
+where
+    P: Process<E, C, S>,
+{
+    fn evolve(state: &GuardedState<S>, event: &E) -> GuardedState<S> {
+        GuardedState {
+            state: P::evolve(&state.state, event),
+            finished: P::is_terminal(&state.state),
+        }
+    }
+
+    fn resume(state: &GuardedState<S>) -> Vec<C> {
+        if state.finished {
+            return vec![];
+        }
+        P::resume(&state.state)
+    }
+
+    fn react(state: &GuardedState<S>, event: &E) -> Vec<C> {
+        if state.finished {
+            return vec![];
+        }
+        P::react(&state.state, event)
+    }
+
+    fn initial_state() -> GuardedState<S> {
+        GuardedState {
+            state: P::initial_state(),
+            finished: false,
+        }
+    }
+
+    fn is_terminal(state: &GuardedState<S>) -> bool {
+        P::is_terminal(&state.state)
+    }
+}
+
+/// A process wrapped in a [`BoundedDecider`] issues at most `MAX` commands in each call to
+/// `react` or `resume`, keeping the first `MAX` commands in order.
+impl<P, E, C, S, const MAX: usize> Process<E, C, S> for BoundedDecider<P, MAX>
+where
+    P: Process<E, C, S>,
+{
+    fn evolve(state: &S, event: &E) -> S {
+        P::evolve(state, event)
+    }
+
+    fn resume(state: &S) -> Vec<C> {
+        let mut commands = P::resume(state);
+        commands.truncate(MAX);
+        commands
+    }
+
+    fn react(state: &S, event: &E) -> Vec<C> {
+        let mut commands = P::react(state, event);
+        commands.truncate(MAX);
+        commands
+    }
+
+    fn initial_state() -> S {
+        P::initial_state()
+    }
+
+    fn is_terminal(state: &S) -> bool {
+        P::is_terminal(state)
+    }
+}
+
+/// The neutral process, with any event and command types and a unit state, which never issues
+/// commands and is always terminal.
+///
+/// It is the identity for [`CombinedProcessDecider`]: combining it with a decider `D` behaves like
+/// `D`. Use [`NeutralFor`] for a neutral process with another state type.
+pub struct NeutralProcess;
+
+impl<E, C> Process<E, C, ()> for NeutralProcess {
+    fn evolve(_state: &(), _event: &E) {}
+
+    fn resume(_state: &()) -> Vec<C> {
+        vec![]
+    }
+
+    fn react(_state: &(), _event: &E) -> Vec<C> {
+        vec![]
+    }
+
+    fn initial_state() {}
+
+    fn is_terminal(_state: &()) -> bool {
+        true
+    }
+}
+
+impl<E, C, S> Process<E, C, S> for NeutralFor<S>
+where
+    S: Clone + Default,
+{
+    fn evolve(state: &S, _event: &E) -> S {
+        state.clone()
+    }
+
+    fn resume(_state: &S) -> Vec<C> {
+        vec![]
+    }
+
+    fn react(_state: &S, _event: &E) -> Vec<C> {
+        vec![]
+    }
+
+    fn initial_state() -> S {
+        S::default()
+    }
+
+    fn is_terminal(_state: &S) -> bool {
+        true
+    }
+}
+
 /// This type combines a Decider and a Process, feeding the events generated by the decider into
 /// the process, and feeding the commands generated from the process back into the decider. This
 /// results in a new type that implements the [`crate::deciders::Decider`] trait.
@@ -183,3 +331,1556 @@ where
         P::is_terminal(state_process) && D::is_terminal(state_decider)
     }
 }
+
+/// A predicate over events, used to describe which events a saga is waiting for.
+pub struct EventMatcher<E> {
+    matcher: Box<dyn Fn(&E) -> bool>,
+}
+
+impl<E> EventMatcher<E> {
+    /// Returns whether the event `event` matches.
+    pub fn matches(&self, event: &E) -> bool {
+        (self.matcher)(event)
+    }
+}
+
+impl<E> Debug for EventMatcher<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EventMatcher")
+    }
+}
+
+/// Constructs an [`EventMatcher`] from the predicate `matcher`.
+pub fn on_event<E>(matcher: impl Fn(&E) -> bool + 'static) -> EventMatcher<E> {
+    EventMatcher {
+        matcher: Box::new(matcher),
+    }
+}
+
+/// Constructs an [`EventMatcher`] that matches events whose variant is named `name`, using their
+/// [`Debug`] representation, so `Fitted { max_uses: 5 }` matches `"Fitted"`.
+pub fn on_event_type<E: Debug>(name: &str) -> EventMatcher<E> {
+    let name = name.to_string();
+    on_event(move |event| event_type_name(event) == name)
+}
+
+/// The command a saga step issues when it starts.
+#[derive(Clone, Debug)]
+pub struct Emit<C>(pub C);
+
+/// Constructs an [`Emit`] for the command `command`.
+pub fn emit<C>(command: C) -> Emit<C> {
+    Emit(command)
+}
+
+/// The commands a saga issues, in order, when one of its steps fails.
+#[derive(Clone, Debug)]
+pub struct Compensation<C>(pub Vec<C>);
+
+/// Constructs a [`Compensation`] from the commands `commands`.
+pub fn compensate_with<C>(commands: Vec<C>) -> Compensation<C> {
+    Compensation(commands)
+}
+
+/// One of the branches of a parallel saga step, built with [`branch`].
+#[derive(Debug)]
+pub struct SagaBranch<E, C> {
+    command: C,
+    completed_by: EventMatcher<E>,
+    compensation: Option<Compensation<C>>,
+}
+
+/// Constructs a [`SagaBranch`] that issues the command in `command` when its step starts, and is
+/// complete once an event matching `completed_by` arrives.
+pub fn branch<E, C>(completed_by: EventMatcher<E>, command: Emit<C>) -> SagaBranch<E, C> {
+    SagaBranch {
+        command: command.0,
+        completed_by,
+        compensation: None,
+    }
+}
+
+impl<E, C> SagaBranch<E, C> {
+    /// Sets the commands that undo this branch, which are issued if it loses an [`Join::AnyOf`].
+    pub fn with_compensation(mut self, compensation: Compensation<C>) -> Self {
+        self.compensation = Some(compensation);
+        self
+    }
+}
+
+/// How the branches of a parallel saga step are joined.
+#[derive(Debug)]
+pub enum Join<E, C> {
+    /// The step is complete once every branch is complete.
+    AllOf(Vec<SagaBranch<E, C>>),
+    /// The step is complete once any branch is complete. The compensations of the other
+    /// branches are then issued.
+    AnyOf(Vec<SagaBranch<E, C>>),
+}
+
+/// A single step of a [`Saga`]. A sequential step is a step with a single branch.
+#[derive(Debug)]
+struct SagaStep<E, C> {
+    name: String,
+    branches: Vec<SagaBranch<E, C>>,
+    any: bool,
+    failure: Option<(EventMatcher<E>, Compensation<C>)>,
+}
+
+/// A sequence of steps that issue commands and wait for the events confirming them, built with
+/// [`SagaBuilder`].
+///
+/// A saga is turned into a [`Process`] by implementing [`SagaDefinition`] and using
+/// [`SagaProcess`].
+#[derive(Debug)]
+pub struct Saga<E, C> {
+    started_by: EventMatcher<E>,
+    steps: Vec<SagaStep<E, C>>,
+}
+
+impl<E, C> Saga<E, C> {
+    /// Returns the number of steps in this saga.
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Returns whether this saga has no steps.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Returns the name of the step at index `index`, if there is one.
+    pub fn step_name(&self, index: usize) -> Option<&str> {
+        self.steps.get(index).map(|s| &s.name[..])
+    }
+}
+
+/// A builder for declaring a [`Saga`] step by step.
+///
+/// ```
+/// use deciders_rs::processes::{compensate_with, emit, on_event, Saga, SagaBuilder};
+///
+/// #[derive(Clone)]
+/// enum Command { Reserve, Charge, Release }
+/// enum Event { OrderPlaced, Reserved, Charged, PaymentFailed }
+///
+/// let saga: Saga<Event, Command> = SagaBuilder::new()
+///     .started_by(on_event(|e| matches!(e, Event::OrderPlaced)))
+///     .step("reserve", on_event(|e| matches!(e, Event::Reserved)), emit(Command::Reserve))
+///     .step("charge", on_event(|e| matches!(e, Event::Charged)), emit(Command::Charge))
+///     .on_failure(
+///         on_event(|e| matches!(e, Event::PaymentFailed)),
+///         compensate_with(vec![Command::Release]),
+///     )
+///     .build()
+///     .unwrap();
+/// assert_eq!(saga.len(), 2);
+/// ```
+#[derive(Debug)]
+pub struct SagaBuilder<E, C> {
+    started_by: Option<EventMatcher<E>>,
+    steps: Vec<SagaStep<E, C>>,
+    error: Option<SagaDefinitionError>,
+}
+
+/// An error returned by [`SagaBuilder::build`] when the saga declared is not valid.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SagaDefinitionError {
+    /// No start event was set with [`SagaBuilder::started_by`].
+    NotStarted,
+    /// No step was added.
+    NoSteps,
+    /// The parallel step with the contained name has no branches, so it could never complete.
+    NoBranches(String),
+    /// [`SagaBuilder::on_failure`] was called before any step was added.
+    FailureWithoutStep,
+}
+
+impl Display for SagaDefinitionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SagaDefinitionError::NotStarted => write!(f, "a saga must be started by an event"),
+            SagaDefinitionError::NoSteps => write!(f, "a saga must have at least one step"),
+            SagaDefinitionError::NoBranches(name) => {
+                write!(f, "parallel step \"{name}\" has no branches")
+            }
+            SagaDefinitionError::FailureWithoutStep => write!(f, "on_failure must follow a step"),
+        }
+    }
+}
+
+impl std::error::Error for SagaDefinitionError {}
+
+impl<E, C> SagaBuilder<E, C> {
+    /// Constructs a new `SagaBuilder` with no steps.
+    pub fn new() -> Self {
+        Self {
+            started_by: None,
+            steps: vec![],
+            error: None,
+        }
+    }
+
+    /// Sets the event that starts the saga. Until an event matching `matcher` arrives, the saga
+    /// does nothing.
+    pub fn started_by(mut self, matcher: EventMatcher<E>) -> Self {
+        self.started_by = Some(matcher);
+        self
+    }
+
+    /// Adds a step named `name`, which issues the command in `command` when it starts and is
+    /// complete once an event matching `completed_by` arrives. The next step then starts.
+    pub fn step(mut self, name: &str, completed_by: EventMatcher<E>, command: Emit<C>) -> Self {
+        self.steps.push(SagaStep {
+            name: name.to_string(),
+            branches: vec![branch(completed_by, command)],
+            any: false,
+            failure: None,
+        });
+        self
+    }
+
+    /// Adds a step named `name`, which issues the commands of all of its branches at once when it
+    /// starts, and is complete once the branches are joined as described by `join`.
+    ///
+    /// If `join` has no branches, [`SagaBuilder::build`] returns
+    /// [`SagaDefinitionError::NoBranches`].
+    pub fn parallel(mut self, name: &str, join: Join<E, C>) -> Self {
+        let (branches, any) = match join {
+            Join::AllOf(branches) => (branches, false),
+            Join::AnyOf(branches) => (branches, true),
+        };
+        if branches.is_empty() {
+            self.error
+                .get_or_insert(SagaDefinitionError::NoBranches(name.to_string()));
+        }
+        self.steps.push(SagaStep {
+            name: name.to_string(),
+            branches,
+            any,
+            failure: None,
+        });
+        self
+    }
+
+    /// Declares how the most recently added step fails: if an event matching `matcher` arrives
+    /// while that step is in progress, the saga issues the commands in `compensation`, in order,
+    /// and stops. For a parallel step, the compensations of its branches are not issued; the
+    /// step's compensation should undo whatever its branches did.
+    ///
+    /// If no step has been added yet, [`SagaBuilder::build`] returns
+    /// [`SagaDefinitionError::FailureWithoutStep`].
+    pub fn on_failure(mut self, matcher: EventMatcher<E>, compensation: Compensation<C>) -> Self {
+        match self.steps.last_mut() {
+            Some(step) => step.failure = Some((matcher, compensation)),
+            None => {
+                self.error
+                    .get_or_insert(SagaDefinitionError::FailureWithoutStep);
+            }
+        }
+        self
+    }
+
+    /// Builds the saga, or returns the first mistake in its declaration.
+    pub fn build(self) -> Result<Saga<E, C>, SagaDefinitionError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let started_by = self.started_by.ok_or(SagaDefinitionError::NotStarted)?;
+        if self.steps.is_empty() {
+            return Err(SagaDefinitionError::NoSteps);
+        }
+        Ok(Saga {
+            started_by,
+            steps: self.steps,
+        })
+    }
+}
+
+impl<E, C> Default for SagaBuilder<E, C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A trait for types that define a [`Saga`], so that it can be run as a [`Process`] by
+/// [`SagaProcess`].
+pub trait SagaDefinition<E, C> {
+    /// Returns the saga's definition.
+    ///
+    /// [`SagaProcess`] only calls this once per thread, and keeps the saga for later events.
+    fn saga() -> Saga<E, C>;
+}
+
+thread_local! {
+    static DEFINITIONS: RefCell<HashMap<TypeId, Rc<dyn Any>>> = RefCell::new(HashMap::new());
+}
+
+/// Returns the definition cached on this thread for the type `K`, building it with `build` the
+/// first time it is needed.
+///
+/// Definitions hold boxed closures, which cannot be shared between threads, so each thread keeps
+/// its own copy, as [`CountingDecider`](crate::deciders::CountingDecider) does with its counts.
+fn cached_definition<K, T>(build: impl FnOnce() -> T) -> Rc<T>
+where
+    K: 'static,
+    T: 'static,
+{
+    let key = TypeId::of::<K>();
+    let cached = DEFINITIONS.with(|definitions| definitions.borrow().get(&key).cloned());
+    if let Some(definition) = cached {
+        return definition
+            .downcast()
+            .expect("definitions are cached by the type that builds them");
+    }
+    // Built without holding the cache, in case building needs other cached definitions.
+    let definition = Rc::new(build());
+    DEFINITIONS.with(|definitions| {
+        definitions
+            .borrow_mut()
+            .insert(key, definition.clone() as Rc<dyn Any>)
+    });
+    definition
+}
+
+/// Where a saga is in its sequence of steps.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SagaPhase {
+    /// The saga has not been started yet.
+    NotStarted,
+    /// The step with the contained index is in progress, and every step before it is complete.
+    Running(usize),
+    /// Every step is complete.
+    Completed,
+    /// The step with the contained index failed and its compensation was issued.
+    Compensated(usize),
+}
+
+/// The state of a [`SagaProcess`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SagaState {
+    /// Where the saga is in its sequence of steps.
+    pub phase: SagaPhase,
+    /// Which branches of the step in progress are complete, indexed by branch. This is empty
+    /// once no step is in progress.
+    pub completed_branches: Vec<bool>,
+    /// Whether the phase changed with the last event, meaning its commands still need to be
+    /// issued.
+    pub changed: bool,
+}
+
+/// A [`Process`] that runs the [`Saga`] defined by `D`.
+///
+/// When the saga is started, and whenever a step completes, the commands of the next step's
+/// branches are issued, after the compensations of the branches that lost if the completed step
+/// was a [`Join::AnyOf`]. When a step fails, its compensation is issued. Resuming re-issues the
+/// commands of the incomplete branches of the step in progress, or the compensation of the failed
+/// step. The process is terminal once every step is complete or a step failed.
+pub struct SagaProcess<D, E, C> {
+    definition: PhantomData<D>,
+    event: PhantomData<E>,
+    command: PhantomData<C>,
+}
+
+impl<D, E, C> SagaProcess<D, E, C>
+where
+    D: SagaDefinition<E, C> + 'static,
+    E: 'static,
+    C: Clone + 'static,
+{
+    fn saga() -> Rc<Saga<E, C>> {
+        cached_definition::<Self, _>(D::saga)
+    }
+
+    fn failure_compensation(saga: &Saga<E, C>, step: usize) -> Vec<C> {
+        match &saga.steps[step].failure {
+            Some((_, compensation)) => compensation.0.clone(),
+            None => vec![],
+        }
+    }
+
+    fn incomplete_branch_commands(saga: &Saga<E, C>, step: usize, completed: &[bool]) -> Vec<C> {
+        saga.steps[step]
+            .branches
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !completed.get(*i).copied().unwrap_or(false))
+            .map(|(_, b)| b.command.clone())
+            .collect()
+    }
+
+    /// Returns the compensations of the branches of the step `step` that lost its join to the
+    /// event `event`.
+    fn losing_branch_compensations(saga: &Saga<E, C>, step: usize, event: &E) -> Vec<C> {
+        let step = &saga.steps[step];
+        if !step.any {
+            return vec![];
+        }
+        step.branches
+            .iter()
+            .filter(|b| !b.completed_by.matches(event))
+            .filter_map(|b| b.compensation.as_ref())
+            .flat_map(|c| c.0.clone())
+            .collect()
+    }
+}
+
+impl<D, E, C> Process<E, C, SagaState> for SagaProcess<D, E, C>
+where
+    D: SagaDefinition<E, C> + 'static,
+    E: 'static,
+    C: Clone + 'static,
+{
+    fn evolve(state: &SagaState, event: &E) -> SagaState {
+        let saga = Self::saga();
+        let mut completed_branches = state.completed_branches.clone();
+        let phase = match state.phase {
+            SagaPhase::NotStarted if saga.started_by.matches(event) => {
+                completed_branches = vec![false; saga.steps[0].branches.len()];
+                SagaPhase::Running(0)
+            }
+            SagaPhase::Running(step) => {
+                let current = &saga.steps[step];
+                completed_branches.resize(current.branches.len(), false);
+                for (completed, b) in completed_branches.iter_mut().zip(&current.branches) {
+                    if b.completed_by.matches(event) {
+                        *completed = true;
+                    }
+                }
+                let joined = if current.any {
+                    completed_branches.iter().any(|completed| *completed)
+                } else {
+                    completed_branches.iter().all(|completed| *completed)
+                };
+                if joined {
+                    if step + 1 == saga.steps.len() {
+                        completed_branches = vec![];
+                        SagaPhase::Completed
+                    } else {
+                        completed_branches = vec![false; saga.steps[step + 1].branches.len()];
+                        SagaPhase::Running(step + 1)
+                    }
+                } else {
+                    match &current.failure {
+                        Some((matcher, _)) if matcher.matches(event) => {
+                            SagaPhase::Compensated(step)
+                        }
+                        _ => state.phase,
+                    }
+                }
+            }
+            phase => phase,
+        };
+        SagaState {
+            phase,
+            completed_branches,
+            changed: phase != state.phase,
+        }
+    }
+
+    fn resume(state: &SagaState) -> Vec<C> {
+        let saga = Self::saga();
+        match state.phase {
+            SagaPhase::Running(step) => {
+                Self::incomplete_branch_commands(&saga, step, &state.completed_branches)
+            }
+            SagaPhase::Compensated(step) => Self::failure_compensation(&saga, step),
+            SagaPhase::NotStarted | SagaPhase::Completed => vec![],
+        }
+    }
+
+    fn react(state: &SagaState, event: &E) -> Vec<C> {
+        if !state.changed {
+            return vec![];
+        }
+        let saga = Self::saga();
+        match state.phase {
+            SagaPhase::Running(step) => {
+                let mut commands = match step {
+                    0 => vec![],
+                    _ => Self::losing_branch_compensations(&saga, step - 1, event),
+                };
+                commands.extend(Self::incomplete_branch_commands(&saga, step, &[]));
+                commands
+            }
+            SagaPhase::Completed => {
+                Self::losing_branch_compensations(&saga, saga.steps.len() - 1, event)
+            }
+            SagaPhase::Compensated(step) => Self::failure_compensation(&saga, step),
+            SagaPhase::NotStarted => vec![],
+        }
+    }
+
+    fn initial_state() -> SagaState {
+        SagaState {
+            phase: SagaPhase::NotStarted,
+            completed_branches: vec![],
+            changed: false,
+        }
+    }
+
+    fn is_terminal(state: &SagaState) -> bool {
+        matches!(
+            state.phase,
+            SagaPhase::Completed | SagaPhase::Compensated(_)
+        )
+    }
+}
+
+/// An expectation that an event arrives within a deadline, built with [`expect`].
+#[derive(Debug)]
+pub struct Expectation<E, C> {
+    armed_by: EventMatcher<E>,
+    expected: EventMatcher<E>,
+    within: u64,
+    otherwise: Vec<C>,
+}
+
+/// A partially built [`Expectation`].
+///
+/// `A` is `()` until the arming event is set with [`ExpectationBuilder::after`], and only then
+/// can the expectation be finished with [`ExpectationBuilder::otherwise`], so an expectation
+/// that is never armed does not compile:
+///
+/// ```compile_fail
+/// use deciders_rs::processes::{expect, on_event, Expectation};
+///
+/// let never_armed: Expectation<u32, ()> = expect(on_event(|e: &u32| *e == 1))
+///     .within(10)
+///     .otherwise(vec![()]);
+/// ```
+#[derive(Debug)]
+pub struct ExpectationBuilder<E, A = ()> {
+    armed_by: A,
+    expected: EventMatcher<E>,
+    within: u64,
+}
+
+/// Starts building an [`Expectation`] that an event matching `expected` arrives.
+pub fn expect<E>(expected: EventMatcher<E>) -> ExpectationBuilder<E> {
+    ExpectationBuilder {
+        armed_by: (),
+        expected,
+        within: 0,
+    }
+}
+
+impl<E, A> ExpectationBuilder<E, A> {
+    /// Sets the event that starts the deadline, usually the event recording that a command was
+    /// issued.
+    pub fn after(self, armed_by: EventMatcher<E>) -> ExpectationBuilder<E, EventMatcher<E>> {
+        ExpectationBuilder {
+            armed_by,
+            expected: self.expected,
+            within: self.within,
+        }
+    }
+
+    /// Sets how many ticks the expected event may take to arrive after the expectation is armed.
+    pub fn within(mut self, ticks: u64) -> Self {
+        self.within = ticks;
+        self
+    }
+}
+
+impl<E> ExpectationBuilder<E, EventMatcher<E>> {
+    /// Sets the commands issued if the deadline passes without the expected event arriving,
+    /// finishing the expectation.
+    pub fn otherwise<C>(self, commands: Vec<C>) -> Expectation<E, C> {
+        Expectation {
+            armed_by: self.armed_by,
+            expected: self.expected,
+            within: self.within,
+            otherwise: commands,
+        }
+    }
+}
+
+/// A function reading the current time from tick events.
+type Clock<E> = Box<dyn Fn(&E) -> Option<u64>>;
+
+/// A set of [`Expectation`]s and the clock they are measured against, built with
+/// [`WatchdogBuilder`].
+pub struct Watchdog<E, C> {
+    clock: Clock<E>,
+    expectations: Vec<Expectation<E, C>>,
+    ended_by: Option<EventMatcher<E>>,
+}
+
+impl<E, C> Debug for Watchdog<E, C>
+where
+    E: Debug,
+    C: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Watchdog")
+            .field("expectations", &self.expectations)
+            .field("ended_by", &self.ended_by)
+            .finish()
+    }
+}
+
+/// A builder for declaring a [`Watchdog`].
+///
+/// Time is measured in ticks, read from the events themselves by the `clock` function given to
+/// [`WatchdogBuilder::new`], which returns the current time for tick events and `None` for every
+/// other event.
+///
+/// ```
+/// use deciders_rs::processes::{expect, on_event, Watchdog, WatchdogBuilder};
+///
+/// enum Event { Tick(u64), ShipmentRequested, Shipped }
+/// enum Command { Escalate }
+///
+/// let watchdog: Watchdog<Event, Command> = WatchdogBuilder::new(|e| match e {
+///     Event::Tick(now) => Some(*now),
+///     _ => None,
+/// })
+/// .watch(
+///     expect(on_event(|e| matches!(e, Event::Shipped)))
+///         .after(on_event(|e| matches!(e, Event::ShipmentRequested)))
+///         .within(10)
+///         .otherwise(vec![Command::Escalate]),
+/// )
+/// .build();
+/// assert_eq!(watchdog.len(), 1);
+/// ```
+#[derive(Debug)]
+pub struct WatchdogBuilder<E, C> {
+    watchdog: Watchdog<E, C>,
+}
+
+impl<E, C> WatchdogBuilder<E, C> {
+    /// Constructs a new `WatchdogBuilder` that reads the current time from events with `clock`.
+    pub fn new(clock: impl Fn(&E) -> Option<u64> + 'static) -> Self {
+        Self {
+            watchdog: Watchdog {
+                clock: Box::new(clock),
+                expectations: vec![],
+                ended_by: None,
+            },
+        }
+    }
+
+    /// Adds the expectation `expectation`.
+    pub fn watch(mut self, expectation: Expectation<E, C>) -> Self {
+        self.watchdog.expectations.push(expectation);
+        self
+    }
+
+    /// Sets the event that ends the watchdog. Once it arrives, every armed expectation is
+    /// cleared and the watchdog is terminal. Without one, the watchdog never ends.
+    pub fn until(mut self, ended_by: EventMatcher<E>) -> Self {
+        self.watchdog.ended_by = Some(ended_by);
+        self
+    }
+
+    /// Builds the watchdog.
+    pub fn build(self) -> Watchdog<E, C> {
+        self.watchdog
+    }
+}
+
+impl<E, C> Watchdog<E, C> {
+    /// Returns the number of expectations in this watchdog.
+    pub fn len(&self) -> usize {
+        self.expectations.len()
+    }
+
+    /// Returns whether this watchdog has no expectations.
+    pub fn is_empty(&self) -> bool {
+        self.expectations.is_empty()
+    }
+}
+
+/// A trait for types that define a [`Watchdog`], so that it can be run as a [`Process`] by
+/// [`WatchdogProcess`].
+pub trait WatchdogDefinition<E, C> {
+    /// Returns the watchdog's definition.
+    fn watchdog() -> Watchdog<E, C>;
+}
+
+/// The state of a [`WatchdogProcess`], with an entry for each expectation of its watchdog.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchdogState {
+    /// The time of the most recent tick.
+    pub now: u64,
+    /// The deadline of each armed expectation, indexed in the order they were added.
+    pub deadlines: Vec<Option<u64>>,
+    /// Which expectations expired with the last event, and so have commands to issue.
+    pub expired: Vec<bool>,
+    /// Whether the event ending the watchdog has arrived.
+    pub ended: bool,
+}
+
+/// A [`Process`] that runs the [`Watchdog`] defined by `D`.
+///
+/// Its state has an entry for each expectation of the watchdog. A state stored before
+/// expectations were added to the definition is extended with unarmed ones when it is next
+/// evolved.
+///
+/// An expectation is armed when its arming event arrives, with a deadline of the current time
+/// plus its allowance (saturating at `u64::MAX`), and cleared when the expected event arrives. If
+/// a tick at or after the deadline arrives first, its otherwise-commands are issued. Resuming
+/// issues the otherwise-commands of the expectations that expired with the last event, so that
+/// they are not lost if the process stopped between saving its state and reacting; they may
+/// therefore be issued more than once. The process is terminal once the event set with
+/// [`WatchdogBuilder::until`] arrives.
+pub struct WatchdogProcess<D, E, C> {
+    definition: PhantomData<D>,
+    event: PhantomData<E>,
+    command: PhantomData<C>,
+}
+
+impl<D, E, C> WatchdogProcess<D, E, C>
+where
+    D: WatchdogDefinition<E, C>,
+{
+    fn expired_commands(state: &WatchdogState) -> Vec<C> {
+        D::watchdog()
+            .expectations
+            .into_iter()
+            .zip(&state.expired)
+            .filter(|(_, expired)| **expired)
+            .flat_map(|(expectation, _)| expectation.otherwise)
+            .collect()
+    }
+}
+
+impl<D, E, C> Process<E, C, WatchdogState> for WatchdogProcess<D, E, C>
+where
+    D: WatchdogDefinition<E, C>,
+    C: Clone,
+{
+    fn evolve(state: &WatchdogState, event: &E) -> WatchdogState {
+        let watchdog = D::watchdog();
+        let len = watchdog.len();
+        let mut new_state = state.clone();
+        new_state.deadlines.resize(len, None);
+        new_state.expired = vec![false; len];
+        if state.ended {
+            return new_state;
+        }
+        if watchdog
+            .ended_by
+            .as_ref()
+            .is_some_and(|ended_by| ended_by.matches(event))
+        {
+            new_state.deadlines = vec![None; len];
+            new_state.ended = true;
+            return new_state;
+        }
+        if let Some(now) = (watchdog.clock)(event) {
+            new_state.now = now;
+        }
+        for (i, expectation) in watchdog.expectations.iter().enumerate() {
+            match new_state.deadlines[i] {
+                Some(_) if expectation.expected.matches(event) => new_state.deadlines[i] = None,
+                Some(deadline) if new_state.now >= deadline => {
+                    new_state.deadlines[i] = None;
+                    new_state.expired[i] = true;
+                }
+                None if expectation.armed_by.matches(event) => {
+                    new_state.deadlines[i] = Some(new_state.now.saturating_add(expectation.within));
+                }
+                _ => {}
+            }
+        }
+        new_state
+    }
+
+    fn resume(state: &WatchdogState) -> Vec<C> {
+        Self::expired_commands(state)
+    }
+
+    fn react(state: &WatchdogState, _event: &E) -> Vec<C> {
+        Self::expired_commands(state)
+    }
+
+    fn initial_state() -> WatchdogState {
+        let len = D::watchdog().len();
+        WatchdogState {
+            now: 0,
+            deadlines: vec![None; len],
+            expired: vec![false; len],
+            ended: false,
+        }
+    }
+
+    fn is_terminal(state: &WatchdogState) -> bool {
+        state.ended
+    }
+}
+
+/// What a [`RoutedProcess`] does with an event that no route of its [`ProcessRouter`] matches.
+pub enum Unrouted<E> {
+    /// Leave the state unchanged and issue no commands.
+    Ignore,
+    /// Like [`Unrouted::Ignore`], but also call the function with the event when reacting to it,
+    /// such as to log it.
+    Report(fn(&E)),
+}
+
+impl<E> Debug for Unrouted<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Unrouted::Ignore => f.write_str("Ignore"),
+            Unrouted::Report(_) => f.write_str("Report"),
+        }
+    }
+}
+
+type EvolveRoute<E, S> = (EventMatcher<E>, Box<dyn Fn(&S, &E) -> S>);
+type ReactRoute<E, C, S> = (EventMatcher<E>, Box<dyn Fn(&S, &E) -> Vec<C>>);
+type ResumeHandler<C, S> = Box<dyn Fn(&S) -> Vec<C>>;
+type TerminalHandler<S> = Box<dyn Fn(&S) -> bool>;
+
+/// A table of handlers for a process, registered per type of event, and run as a [`Process`] by
+/// [`RoutedProcess`].
+///
+/// Each event is dispatched to the first handler registered with a matching [`EventMatcher`],
+/// such as one built with [`on_event`] for an enum variant, or with [`on_event_type`] for a
+/// variant name. State updates and reactions are routed separately, so an event can update the
+/// state without issuing commands, or the other way around.
+///
+/// ```
+/// use deciders_rs::processes::{on_event, on_event_type, ProcessRouter, Unrouted};
+///
+/// #[derive(Debug)]
+/// enum Event { SwitchedOn, SwitchedOff, Fitted }
+/// enum Command { StartTimer, StopTimer }
+///
+/// let router: ProcessRouter<Event, Command, bool> = ProcessRouter::new(false)
+///     .evolve_on(on_event(|e| matches!(e, Event::SwitchedOn)), |_, _| true)
+///     .evolve_on(on_event_type("SwitchedOff"), |_, _| false)
+///     .on(on_event(|e| matches!(e, Event::SwitchedOn)), |_, _| vec![Command::StartTimer])
+///     .on(on_event_type("SwitchedOff"), |_, _| vec![Command::StopTimer])
+///     .unrouted(Unrouted::Report(|e| eprintln!("unrouted event {e:?}")));
+/// assert_eq!(router.len(), 4);
+/// ```
+pub struct ProcessRouter<E, C, S> {
+    initial_state: S,
+    evolve_routes: Vec<EvolveRoute<E, S>>,
+    react_routes: Vec<ReactRoute<E, C, S>>,
+    resume: Option<ResumeHandler<C, S>>,
+    terminal: Option<TerminalHandler<S>>,
+    unrouted: Unrouted<E>,
+}
+
+impl<E, C, S> ProcessRouter<E, C, S> {
+    /// Constructs a new `ProcessRouter` with no routes, for a process that starts in the state
+    /// `initial_state`.
+    pub fn new(initial_state: S) -> Self {
+        Self {
+            initial_state,
+            evolve_routes: vec![],
+            react_routes: vec![],
+            resume: None,
+            terminal: None,
+            unrouted: Unrouted::Ignore,
+        }
+    }
+
+    /// Routes events matching `matcher` to `handler` when reacting to them. `handler` is given
+    /// the process' state and the event, and returns the commands to issue.
+    pub fn on(
+        mut self,
+        matcher: EventMatcher<E>,
+        handler: impl Fn(&S, &E) -> Vec<C> + 'static,
+    ) -> Self {
+        self.react_routes.push((matcher, Box::new(handler)));
+        self
+    }
+
+    /// Routes events matching `matcher` to `handler` when evolving the state. `handler` is given
+    /// the current state and the event, and returns the new state.
+    pub fn evolve_on(
+        mut self,
+        matcher: EventMatcher<E>,
+        handler: impl Fn(&S, &E) -> S + 'static,
+    ) -> Self {
+        self.evolve_routes.push((matcher, Box::new(handler)));
+        self
+    }
+
+    /// Sets the commands issued when resuming from a state. By default, no commands are issued.
+    pub fn resume_with(mut self, resume: impl Fn(&S) -> Vec<C> + 'static) -> Self {
+        self.resume = Some(Box::new(resume));
+        self
+    }
+
+    /// Sets which states are terminal. By default, no state is terminal.
+    pub fn terminal_when(mut self, terminal: impl Fn(&S) -> bool + 'static) -> Self {
+        self.terminal = Some(Box::new(terminal));
+        self
+    }
+
+    /// Sets what happens to events that match no route. The default is [`Unrouted::Ignore`].
+    pub fn unrouted(mut self, unrouted: Unrouted<E>) -> Self {
+        self.unrouted = unrouted;
+        self
+    }
+
+    /// Returns the number of routes in this router.
+    pub fn len(&self) -> usize {
+        self.evolve_routes.len() + self.react_routes.len()
+    }
+
+    /// Returns whether this router has no routes.
+    pub fn is_empty(&self) -> bool {
+        self.evolve_routes.is_empty() && self.react_routes.is_empty()
+    }
+}
+
+impl<E, C, S> Debug for ProcessRouter<E, C, S>
+where
+    S: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProcessRouter")
+            .field("initial_state", &self.initial_state)
+            .field("evolve_routes", &self.evolve_routes.len())
+            .field("react_routes", &self.react_routes.len())
+            .field("unrouted", &self.unrouted)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A trait for types that define a [`ProcessRouter`], so that it can be run as a [`Process`] by
+/// [`RoutedProcess`].
+pub trait RouterDefinition<E, C, S> {
+    /// Returns the router's definition.
+    ///
+    /// [`RoutedProcess`] only calls this once per thread, and keeps the router for later events.
+    fn router() -> ProcessRouter<E, C, S>;
+}
+
+/// A [`Process`] that dispatches events through the [`ProcessRouter`] defined by `D`.
+///
+/// Evolving an event runs the first matching state update, and reacting to it runs the first
+/// matching handler. Events that match neither kind of route are handled as the router's
+/// [`Unrouted`] policy says.
+pub struct RoutedProcess<D, E, C, S> {
+    definition: PhantomData<D>,
+    event: PhantomData<E>,
+    command: PhantomData<C>,
+    state: PhantomData<S>,
+}
+
+impl<D, E, C, S> RoutedProcess<D, E, C, S>
+where
+    D: RouterDefinition<E, C, S> + 'static,
+    E: 'static,
+    C: 'static,
+    S: 'static,
+{
+    fn router() -> Rc<ProcessRouter<E, C, S>> {
+        cached_definition::<Self, _>(D::router)
+    }
+}
+
+impl<D, E, C, S> Process<E, C, S> for RoutedProcess<D, E, C, S>
+where
+    D: RouterDefinition<E, C, S> + 'static,
+    E: 'static,
+    C: 'static,
+    S: Clone + 'static,
+{
+    fn evolve(state: &S, event: &E) -> S {
+        match Self::router()
+            .evolve_routes
+            .iter()
+            .find(|(matcher, _)| matcher.matches(event))
+        {
+            Some((_, handler)) => handler(state, event),
+            None => state.clone(),
+        }
+    }
+
+    fn resume(state: &S) -> Vec<C> {
+        match &Self::router().resume {
+            Some(resume) => resume(state),
+            None => vec![],
+        }
+    }
+
+    fn react(state: &S, event: &E) -> Vec<C> {
+        let router = Self::router();
+        if let Some((_, handler)) = router
+            .react_routes
+            .iter()
+            .find(|(matcher, _)| matcher.matches(event))
+        {
+            return handler(state, event);
+        }
+        if let Unrouted::Report(report) = &router.unrouted {
+            if !router.evolve_routes.iter().any(|(m, _)| m.matches(event)) {
+                report(event);
+            }
+        }
+        vec![]
+    }
+
+    fn initial_state() -> S {
+        Self::router().initial_state.clone()
+    }
+
+    fn is_terminal(state: &S) -> bool {
+        Self::router()
+            .terminal
+            .as_ref()
+            .is_some_and(|terminal| terminal(state))
+    }
+}
+
+/// An error returned when saving a process' state with a version that does not directly follow
+/// the stored version, meaning another runner has saved the same process in the meantime.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VersionConflict {
+    /// The version that was being saved.
+    pub version: StreamVersion,
+    /// The version that was already stored.
+    pub stored: StreamVersion,
+}
+
+impl Display for VersionConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "cannot save process state version {} over stored version {}",
+            self.version, self.stored
+        )
+    }
+}
+
+impl std::error::Error for VersionConflict {}
+
+/// A trait for storing the states of process instances, so that in-flight processes survive a
+/// restart.
+///
+/// Each saved state has a version, starting at 1 and increasing by one with every save. A store
+/// rejects a save whose version does not directly follow the stored version.
+pub trait ProcessStateStore<S> {
+    /// Returns the last state saved for the process `process_id` and its version, if there is
+    /// one.
+    fn load(&self, process_id: &str) -> Option<(StreamVersion, S)>;
+
+    /// Saves the state `state` as version `version` of the process `process_id`.
+    fn save(
+        &mut self,
+        process_id: &str,
+        version: StreamVersion,
+        state: &S,
+    ) -> Result<(), VersionConflict>;
+}
+
+/// A simple [`ProcessStateStore`] that keeps process states in memory.
+#[derive(Clone, Debug)]
+pub struct InMemoryProcessStateStore<S> {
+    states: HashMap<String, (StreamVersion, S)>,
+}
+
+impl<S> InMemoryProcessStateStore<S> {
+    /// Constructs a new, empty `InMemoryProcessStateStore`.
+    pub fn new() -> Self {
+        Self {
+            states: HashMap::new(),
+        }
+    }
+}
+
+impl<S> Default for InMemoryProcessStateStore<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> ProcessStateStore<S> for InMemoryProcessStateStore<S>
+where
+    S: Clone,
+{
+    fn load(&self, process_id: &str) -> Option<(StreamVersion, S)> {
+        self.states.get(process_id).cloned()
+    }
+
+    fn save(
+        &mut self,
+        process_id: &str,
+        version: StreamVersion,
+        state: &S,
+    ) -> Result<(), VersionConflict> {
+        let stored = self
+            .states
+            .get(process_id)
+            .map_or(StreamVersion::INITIAL, |(v, _)| *v);
+        if version != stored.next() {
+            return Err(VersionConflict { version, stored });
+        }
+        self.states
+            .insert(process_id.to_string(), (version, state.clone()));
+        Ok(())
+    }
+}
+
+/// A type that runs a single instance of a process, persisting its state in a
+/// [`ProcessStateStore`] after every event.
+///
+/// A runner constructed with a store that already holds a state for its process id continues
+/// from that state, and [`ProcessRunner::resume`] returns the commands that were in flight when
+/// it stopped.
+///
+/// The new state is saved before the commands reacting to an event are returned, so a crash can
+/// never lose a step of the process. A crash after saving but before the commands are
+/// dispatched is recovered by resuming, which means commands are delivered at least once and
+/// their handlers should be idempotent.
+pub struct ProcessRunner<P, E, C, S, PS>
+where
+    P: Process<E, C, S>,
+    PS: ProcessStateStore<S>,
+{
+    process_id: String,
+    version: StreamVersion,
+    state: S,
+    store: PS,
+    process: PhantomData<P>,
+    event: PhantomData<E>,
+    command: PhantomData<C>,
+}
+
+impl<P, E, C, S, PS> ProcessRunner<P, E, C, S, PS>
+where
+    P: Process<E, C, S>,
+    PS: ProcessStateStore<S>,
+{
+    /// Constructs a new `ProcessRunner` for the process `process_id`, loading its state from
+    /// `store`, or starting from the initial state if none is stored.
+    pub fn new(process_id: &str, store: PS) -> Self {
+        let (version, state) = store
+            .load(process_id)
+            .unwrap_or_else(|| (StreamVersion::INITIAL, P::initial_state()));
+        Self {
+            process_id: process_id.to_string(),
+            version,
+            state,
+            store,
+            process: PhantomData,
+            event: PhantomData,
+            command: PhantomData,
+        }
+    }
+
+    /// Returns the commands needed to continue the process from its current state, as given by
+    /// [`Process::resume`]. This should be called after constructing a runner that may have
+    /// loaded a stored state.
+    pub fn resume(&self) -> Vec<C> {
+        P::resume(&self.state)
+    }
+
+    /// Evolves the process with the event `event`, saves the new state, and returns the commands
+    /// the process issued in reaction.
+    ///
+    /// If the store rejects the new state, the runner's state is left unchanged and the error is
+    /// returned.
+    pub fn handle(&mut self, event: &E) -> Result<Vec<C>, VersionConflict> {
+        let state = P::evolve(&self.state, event);
+        let version = self.version.next();
+        self.store.save(&self.process_id, version, &state)?;
+        self.version = version;
+        self.state = state;
+        Ok(P::react(&self.state, event))
+    }
+
+    /// Returns a reference to the current state of the process.
+    pub fn get_state(&self) -> &S {
+        &self.state
+    }
+
+    /// Returns the version of the current state, which is 0 if it has never been saved.
+    pub fn version(&self) -> StreamVersion {
+        self.version
+    }
+
+    /// Returns whether the process has reached a terminal state.
+    pub fn is_terminal(&self) -> bool {
+        P::is_terminal(&self.state)
+    }
+
+    /// Consumes this runner, returning its process state store.
+    pub fn into_store(self) -> PS {
+        self.store
+    }
+}
+
+impl<P, E, C, S, PS> Debug for ProcessRunner<P, E, C, S, PS>
+where
+    P: Process<E, C, S>,
+    PS: ProcessStateStore<S>,
+    S: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProcessRunner")
+            .field("process_id", &self.process_id)
+            .field("version", &self.version)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+/// A trait for identifying which of a process' commands are tasks waiting on a human, such as an
+/// approval, rather than commands dispatched to another system.
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait HumanTask<C> {
+    /// Returns the name of the step the command `command` belongs to if it is a human task, or
+    /// `None` otherwise.
+    fn task_name(command: &C) -> Option<String>;
+}
+
+/// A human task a process is waiting on, as listed by [`ProcessRunner::pending_actions`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingAction<C> {
+    /// The task's id, made of the process id and the task's name, which stays the same for as
+    /// long as the task is pending.
+    pub id: String,
+    /// The command representing the task.
+    pub command: C,
+}
+
+/// An error returned by [`ProcessRunner::complete_action`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum CompleteActionError {
+    /// There is no pending action with the contained id, for example because it was already
+    /// completed.
+    NotPending(String),
+    /// Saving the process' new state failed.
+    VersionConflict(VersionConflict),
+}
+
+impl Display for CompleteActionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompleteActionError::NotPending(id) => write!(f, "action {id} is not pending"),
+            CompleteActionError::VersionConflict(conflict) => write!(f, "{conflict}"),
+        }
+    }
+}
+
+impl std::error::Error for CompleteActionError {}
+
+impl From<VersionConflict> for CompleteActionError {
+    fn from(value: VersionConflict) -> Self {
+        CompleteActionError::VersionConflict(value)
+    }
+}
+
+impl<P, E, C, S, PS> ProcessRunner<P, E, C, S, PS>
+where
+    P: Process<E, C, S>,
+    PS: ProcessStateStore<S>,
+{
+    /// Returns the human tasks the process is currently waiting on: the commands returned by
+    /// [`Process::resume`] that `H` identifies as human tasks.
+    pub fn pending_actions<H>(&self) -> Vec<PendingAction<C>>
+    where
+        H: HumanTask<C>,
+    {
+        self.resume()
+            .into_iter()
+            .filter_map(|command| {
+                H::task_name(&command).map(|name| PendingAction {
+                    id: format!("{}/{}", self.process_id, name),
+                    command,
+                })
+            })
+            .collect()
+    }
+
+    /// Completes the pending action with the id `task_id` by handling `event`, the event
+    /// recording the human's decision, and returns the commands the process issued in reaction.
+    pub fn complete_action<H>(
+        &mut self,
+        task_id: &str,
+        event: &E,
+    ) -> Result<Vec<C>, CompleteActionError>
+    where
+        H: HumanTask<C>,
+    {
+        if !self
+            .pending_actions::<H>()
+            .iter()
+            .any(|action| action.id == task_id)
+        {
+            return Err(CompleteActionError::NotPending(task_id.to_string()));
+        }
+        Ok(self.handle(event)?)
+    }
+}
+
+/// The id of a command scheduled with a [`Scheduler`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ScheduleId(pub u64);
+
+/// A command scheduled with a [`Scheduler`] to be dispatched at a given time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScheduledCommand<C> {
+    /// The id of the scheduled command.
+    pub id: ScheduleId,
+    /// The time at which the command is due, in the same units as the times given to
+    /// [`Scheduler::due`].
+    pub at: u64,
+    /// The command.
+    pub command: C,
+}
+
+/// A trait for storing the commands scheduled with a [`Scheduler`], so that they survive a
+/// restart.
+pub trait ScheduleStore<C> {
+    /// The error returned when the store cannot be read or written.
+    type Error;
+
+    /// Returns a new schedule id, different from every id returned before.
+    fn next_id(&mut self) -> Result<ScheduleId, Self::Error>;
+
+    /// Returns every scheduled command that has not been removed.
+    fn load(&self) -> Result<Vec<ScheduledCommand<C>>, Self::Error>;
+
+    /// Saves the scheduled command `entry`.
+    fn insert(&mut self, entry: &ScheduledCommand<C>) -> Result<(), Self::Error>;
+
+    /// Removes the scheduled command with the id `id`, returning whether it was stored.
+    fn remove(&mut self, id: ScheduleId) -> Result<bool, Self::Error>;
+}
+
+/// A simple [`ScheduleStore`] that keeps scheduled commands in memory.
+#[derive(Clone, Debug)]
+pub struct InMemoryScheduleStore<C> {
+    entries: HashMap<ScheduleId, ScheduledCommand<C>>,
+    next_id: u64,
+}
+
+impl<C> InMemoryScheduleStore<C> {
+    /// Constructs a new, empty `InMemoryScheduleStore`.
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<C> Default for InMemoryScheduleStore<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> ScheduleStore<C> for InMemoryScheduleStore<C>
+where
+    C: Clone,
+{
+    type Error = std::convert::Infallible;
+
+    fn next_id(&mut self) -> Result<ScheduleId, Self::Error> {
+        self.next_id += 1;
+        Ok(ScheduleId(self.next_id))
+    }
+
+    fn load(&self) -> Result<Vec<ScheduledCommand<C>>, Self::Error> {
+        Ok(self.entries.values().cloned().collect())
+    }
+
+    fn insert(&mut self, entry: &ScheduledCommand<C>) -> Result<(), Self::Error> {
+        self.entries.insert(entry.id, entry.clone());
+        Ok(())
+    }
+
+    fn remove(&mut self, id: ScheduleId) -> Result<bool, Self::Error> {
+        Ok(self.entries.remove(&id).is_some())
+    }
+}
+
+/// A command issued by a process, either to be dispatched right away or after a delay through a
+/// [`Scheduler`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Delayable<C> {
+    /// The command should be dispatched right away.
+    Now(C),
+    /// The command should be dispatched once the given delay has passed.
+    Delayed(C, u64),
+}
+
+type DispatchOutcomes<R, Er> = Vec<(ScheduleId, Result<R, Er>)>;
+
+/// A type that holds commands until the time they are due, persisting them in a
+/// [`ScheduleStore`].
+///
+/// Times are plain numbers, such as seconds since an epoch or the ticks of a test clock, and the
+/// scheduler is driven by calling [`Scheduler::due`] or [`Scheduler::dispatch_due`] with the
+/// current time, or by one of the driver loops [`Scheduler::run`] and `Scheduler::run_every`
+/// (which requires the `tokio` feature). A scheduler constructed with a store that already holds
+/// commands dispatches them when they are due, so they survive a restart. Every method that
+/// reads or writes the store returns the store's error if it fails.
+///
+/// A due command is only removed from the store once it has been dispatched, so a command whose
+/// dispatch failed, or that was due when the scheduler stopped, is dispatched again later. The
+/// same command may therefore be dispatched more than once across a restart, always with the same
+/// [`ScheduleId`], which the receiver can use to ignore duplicates.
+pub struct Scheduler<C, SS>
+where
+    SS: ScheduleStore<C>,
+{
+    store: SS,
+    command: PhantomData<C>,
+}
+
+impl<C, SS> Scheduler<C, SS>
+where
+    SS: ScheduleStore<C>,
+{
+    /// Constructs a new `Scheduler` that persists its commands in `store`.
+    pub fn new(store: SS) -> Self {
+        Self {
+            store,
+            command: PhantomData,
+        }
+    }
+
+    /// Schedules the command `command` to be dispatched at the time `at`, and returns its id.
+    pub fn schedule(&mut self, at: u64, command: C) -> Result<ScheduleId, SS::Error> {
+        let id = self.store.next_id()?;
+        self.store.insert(&ScheduledCommand { id, at, command })?;
+        Ok(id)
+    }
+
+    /// Cancels the scheduled command with the id `id`, returning whether it was still waiting to
+    /// be dispatched.
+    pub fn cancel(&mut self, id: ScheduleId) -> Result<bool, SS::Error> {
+        self.store.remove(id)
+    }
+
+    /// Schedules the command `command` issued at the time `now` if it is delayed, or returns it
+    /// to be dispatched right away otherwise.
+    pub fn submit(&mut self, now: u64, command: Delayable<C>) -> Result<Option<C>, SS::Error> {
+        match command {
+            Delayable::Now(command) => Ok(Some(command)),
+            Delayable::Delayed(command, delay) => {
+                self.schedule(now.saturating_add(delay), command)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Returns every scheduled command due at or before the time `now`, ordered by the time they
+    /// are due, then by the order they were scheduled.
+    ///
+    /// The commands stay scheduled until they are acknowledged with [`Scheduler::acknowledge`]
+    /// once dispatched.
+    pub fn due(&self, now: u64) -> Result<Vec<ScheduledCommand<C>>, SS::Error> {
+        let mut due: Vec<_> = self
+            .store
+            .load()?
+            .into_iter()
+            .filter(|entry| entry.at <= now)
+            .collect();
+        due.sort_by_key(|entry| (entry.at, entry.id));
+        Ok(due)
+    }
+
+    /// Removes the scheduled command with the id `id` after it has been dispatched, returning
+    /// whether it was still scheduled.
+    pub fn acknowledge(&mut self, id: ScheduleId) -> Result<bool, SS::Error> {
+        self.store.remove(id)
+    }
+
+    /// Passes every command due at or before the time `now` to `dispatch` along with its id, such
+    /// as a closure sending it to a runner, and returns the outcome of each along with its id.
+    ///
+    /// Each command is removed from the store only if `dispatch` succeeds; commands whose
+    /// dispatch failed stay scheduled and are passed again on the next call. If the store fails,
+    /// the commands not yet dispatched stay scheduled and the store's error is returned.
+    pub fn dispatch_due<R, Er>(
+        &mut self,
+        now: u64,
+        mut dispatch: impl FnMut(ScheduleId, &C) -> Result<R, Er>,
+    ) -> Result<DispatchOutcomes<R, Er>, SS::Error> {
+        let mut outcomes = vec![];
+        for entry in self.due(now)? {
+            let outcome = dispatch(entry.id, &entry.command);
+            if outcome.is_ok() {
+                self.acknowledge(entry.id)?;
+            }
+            outcomes.push((entry.id, outcome));
+        }
+        Ok(outcomes)
+    }
+
+    /// Drives this scheduler from the clock `clock`, dispatching the commands due at each time
+    /// it returns like [`Scheduler::dispatch_due`] and passing each outcome to `on_outcome`, until
+    /// `clock` returns `None` or the store fails.
+    ///
+    /// Each call to `clock` is one tick of the loop, so a clock that returns the ticks of a test
+    /// clock runs the loop without waiting, and a clock that sleeps before reading the time runs
+    /// it in real time.
+    pub fn run<R, Er>(
+        &mut self,
+        mut clock: impl FnMut() -> Option<u64>,
+        mut dispatch: impl FnMut(ScheduleId, &C) -> Result<R, Er>,
+        mut on_outcome: impl FnMut(ScheduleId, Result<R, Er>),
+    ) -> Result<(), SS::Error> {
+        while let Some(now) = clock() {
+            for (id, outcome) in self.dispatch_due(now, &mut dispatch)? {
+                on_outcome(id, outcome);
+            }
+        }
+        Ok(())
+    }
+
+    /// Drives this scheduler like [`Scheduler::run`], but waits for a tokio timer firing every
+    /// `period` before each call to `clock`.
+    ///
+    /// The first call to `clock` is made right away. The loop must run inside a tokio runtime
+    /// with its timer enabled.
+    #[cfg(feature = "tokio")]
+    pub async fn run_every<R, Er>(
+        &mut self,
+        period: std::time::Duration,
+        mut clock: impl FnMut() -> Option<u64>,
+        mut dispatch: impl FnMut(ScheduleId, &C) -> Result<R, Er>,
+        mut on_outcome: impl FnMut(ScheduleId, Result<R, Er>),
+    ) -> Result<(), SS::Error> {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            let Some(now) = clock() else {
+                return Ok(());
+            };
+            for (id, outcome) in self.dispatch_due(now, &mut dispatch)? {
+                on_outcome(id, outcome);
+            }
+        }
+    }
+
+    /// Returns every command that is still scheduled, ordered like [`Scheduler::due`].
+    pub fn pending(&self) -> Result<Vec<ScheduledCommand<C>>, SS::Error> {
+        let mut pending = self.store.load()?;
+        pending.sort_by_key(|entry| (entry.at, entry.id));
+        Ok(pending)
+    }
+
+    /// Consumes this scheduler, returning its schedule store.
+    pub fn into_store(self) -> SS {
+        self.store
+    }
+}
+
+impl<C, SS> Debug for Scheduler<C, SS>
+where
+    SS: ScheduleStore<C> + Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("store", &self.store)
+            .finish()
+    }
+}
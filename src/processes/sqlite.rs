@@ -0,0 +1,137 @@
+use std::{fmt::Debug, marker::PhantomData};
+
+use rusqlite::{params, Connection};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{ScheduleId, ScheduleStore, ScheduledCommand};
+
+/// Creates the tables used by [`SqliteScheduleStore`] if they do not already exist.
+pub fn create_schedule_table(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS scheduled_commands (
+            schedule TEXT NOT NULL,
+            id INTEGER NOT NULL,
+            at INTEGER NOT NULL,
+            command TEXT NOT NULL,
+            PRIMARY KEY (schedule, id)
+        );
+        CREATE TABLE IF NOT EXISTS schedule_ids (
+            schedule TEXT PRIMARY KEY,
+            last_id INTEGER NOT NULL
+        );",
+    )
+}
+
+/// A [`ScheduleStore`] that keeps scheduled commands in an SQLite database, serialized as JSON.
+///
+/// Several named schedules can share a database. Times are stored bit for bit as signed
+/// integers, so a time above [`i64::MAX`] reads back unchanged but appears negative in SQL. A
+/// command that cannot be serialized or deserialized is reported as a
+/// [`rusqlite::Error::ToSqlConversionFailure`] or [`rusqlite::Error::FromSqlConversionFailure`].
+pub struct SqliteScheduleStore<C> {
+    name: String,
+    connection: Connection,
+    command: PhantomData<C>,
+}
+
+impl<C> SqliteScheduleStore<C> {
+    /// Constructs a new `SqliteScheduleStore` for the schedule named `name` in `connection`,
+    /// which holds every command previously scheduled under that name.
+    ///
+    /// The tables are created if they do not already exist.
+    pub fn new(name: &str, connection: Connection) -> rusqlite::Result<Self> {
+        create_schedule_table(&connection)?;
+        Ok(Self {
+            name: name.to_string(),
+            connection,
+            command: PhantomData,
+        })
+    }
+
+    /// Returns a reference to the database connection.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Consumes this store, returning its database connection.
+    pub fn into_connection(self) -> Connection {
+        self.connection
+    }
+}
+
+impl<C> Debug for SqliteScheduleStore<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SqliteScheduleStore")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+fn stored_id(id: ScheduleId) -> rusqlite::Result<i64> {
+    i64::try_from(id.0).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+impl<C> ScheduleStore<C> for SqliteScheduleStore<C>
+where
+    C: Serialize + DeserializeOwned,
+{
+    type Error = rusqlite::Error;
+
+    fn next_id(&mut self) -> rusqlite::Result<ScheduleId> {
+        let last_id: i64 = self.connection.query_row(
+            "INSERT INTO schedule_ids (schedule, last_id) VALUES (?1, 1)
+             ON CONFLICT(schedule) DO UPDATE SET last_id = last_id + 1
+             RETURNING last_id",
+            params![self.name],
+            |row| row.get(0),
+        )?;
+        let last_id = u64::try_from(last_id)
+            .map_err(|_| rusqlite::Error::IntegralValueOutOfRange(0, last_id))?;
+        Ok(ScheduleId(last_id))
+    }
+
+    fn load(&self) -> rusqlite::Result<Vec<ScheduledCommand<C>>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT id, at, command FROM scheduled_commands WHERE schedule = ?1")?;
+        let rows = statement.query_map(params![self.name], |row| {
+            let id: i64 = row.get(0)?;
+            let at: i64 = row.get(1)?;
+            let command: String = row.get(2)?;
+            let id =
+                u64::try_from(id).map_err(|_| rusqlite::Error::IntegralValueOutOfRange(0, id))?;
+            let command = serde_json::from_str(&command).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    2,
+                    rusqlite::types::Type::Text,
+                    Box::new(e),
+                )
+            })?;
+            Ok(ScheduledCommand {
+                id: ScheduleId(id),
+                at: at as u64,
+                command,
+            })
+        })?;
+        rows.collect()
+    }
+
+    fn insert(&mut self, entry: &ScheduledCommand<C>) -> rusqlite::Result<()> {
+        let command = serde_json::to_string(&entry.command)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.connection.execute(
+            "INSERT OR REPLACE INTO scheduled_commands (schedule, id, at, command)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![self.name, stored_id(entry.id)?, entry.at as i64, command],
+        )?;
+        Ok(())
+    }
+
+    fn remove(&mut self, id: ScheduleId) -> rusqlite::Result<bool> {
+        let removed = self.connection.execute(
+            "DELETE FROM scheduled_commands WHERE schedule = ?1 AND id = ?2",
+            params![self.name, stored_id(id)?],
+        )?;
+        Ok(removed > 0)
+    }
+}
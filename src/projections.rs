@@ -0,0 +1,1476 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    fmt::Display,
+    hash::Hash,
+    marker::PhantomData,
+    ops::{Deref, Range},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "serde")]
+use crate::utilities::MaybeUnknown;
+use crate::utilities::{lefts, rights, Either, GlobalPosition};
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "sqlite")]
+pub use sqlite::{
+    create_checkpoint_table, create_event_bucket_table, RowMapper, SqlProjection,
+    SqlProjectionError, SqlStatement, SqlValue,
+};
+
+/// A trait representing a Projection
+///
+/// A projection folds events of type `E` into a read model, or "view", that is optimized for
+/// querying. Unlike a decider's state, a view is never used to make decisions, so it can be shaped
+/// however is most convenient for the readers of the data.
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait Projection<E> {
+    /// The type of the view this projection produces.
+    type View;
+
+    /// Returns the view before any events have been applied.
+    fn initial() -> Self::View;
+
+    /// Given the current view `view` and an event `event`, returns the new view.
+    fn apply(view: &Self::View, event: &E) -> Self::View;
+
+    /// Applies the event `event` to the view `view` in place.
+    ///
+    /// By default this calls [`Projection::apply`] and replaces the view, but projections with
+    /// large views can override this to avoid rebuilding the whole view for every event.
+    fn apply_mut(view: &mut Self::View, event: &E) {
+        *view = Self::apply(view, event);
+    }
+
+    /// Applies each event in `events` to the view `view` in place, in order.
+    ///
+    /// By default this calls [`Projection::apply_mut`] for each event, but projections that can
+    /// apply a batch of events more efficiently can override this.
+    fn apply_all_mut(view: &mut Self::View, events: &[E]) {
+        for e in events {
+            Self::apply_mut(view, e);
+        }
+    }
+}
+
+/// A type that wraps a projection type and stores that projection's view internally.
+///
+/// Events can be fed to this type as they are produced, for example by passing it the events
+/// returned from [`crate::utilities::InMemoryRunner::command`], and the current view can be read
+/// at any time.
+///
+/// Callbacks registered with [`InMemoryProjection::on_change`] are notified whenever applying
+/// events changes the view.
+pub struct InMemoryProjection<P, E>
+where
+    P: Projection<E>,
+{
+    view: P::View,
+    notifier: ChangeNotifier<P::View>,
+    projection: PhantomData<P>,
+    event: PhantomData<E>,
+}
+
+impl<P, E> InMemoryProjection<P, E>
+where
+    P: Projection<E>,
+{
+    /// Constructs a new `InMemoryProjection`, initializing the view to the initial view of the
+    /// projection.
+    pub fn new() -> Self {
+        Self::with_view(P::initial())
+    }
+
+    /// Constructs a new `InMemoryProjection`, initializing the view to the given view.
+    pub fn with_view(view: P::View) -> Self {
+        Self {
+            view,
+            notifier: ChangeNotifier::new(),
+            projection: PhantomData,
+            event: PhantomData,
+        }
+    }
+
+    /// Applies a single event `event` to the view.
+    pub fn apply(&mut self, event: &E) {
+        P::apply_mut(&mut self.view, event);
+        self.notifier.notify(&self.view);
+    }
+
+    /// Applies each event in `events` to the view, in order.
+    ///
+    /// If coalescing is enabled, change callbacks are notified once after all of the events have
+    /// been applied, otherwise they are notified after each event.
+    pub fn apply_all(&mut self, events: &[E]) {
+        if self.notifier.coalesce {
+            P::apply_all_mut(&mut self.view, events);
+            self.notifier.notify(&self.view);
+            return;
+        }
+        for e in events {
+            P::apply_mut(&mut self.view, e);
+            self.notifier.notify(&self.view);
+        }
+    }
+
+    /// Sets whether the changes made by a single call to [`InMemoryProjection::apply_all`] are
+    /// coalesced into one notification. Coalescing is disabled by default.
+    pub fn set_coalescing(&mut self, coalesce: bool) {
+        self.notifier.coalesce = coalesce;
+    }
+
+    /// Returns a reference to the current view.
+    pub fn view(&self) -> &P::View {
+        &self.view
+    }
+
+    /// Executes the query named `name` from `registry` with the parameters `params` against the
+    /// current view.
+    pub fn query(
+        &self,
+        registry: &QueryRegistry<P::View>,
+        name: &str,
+        params: &str,
+    ) -> Result<QueryResult, QueryError> {
+        registry.execute(&self.view, name, params)
+    }
+}
+
+impl<P, E> InMemoryProjection<P, E>
+where
+    P: Projection<E>,
+    P::View: Clone + PartialEq + 'static,
+{
+    /// Registers a callback that is called with the new view whenever the view changes.
+    ///
+    /// Notifications are delivered in event order, and only when the view is different from the
+    /// view the callback was last notified with (or the view at registration time).
+    pub fn on_change(&mut self, callback: impl FnMut(&P::View) + 'static) {
+        self.notifier.subscribe(&self.view, callback);
+    }
+
+    /// Returns a receiver that always holds the latest view, and is marked as changed whenever
+    /// the view changes.
+    #[cfg(feature = "tokio")]
+    pub fn watch_view(&mut self) -> tokio::sync::watch::Receiver<P::View> {
+        self.notifier.watch(&self.view)
+    }
+}
+
+impl<P, E> InMemoryProjection<P, E>
+where
+    P: DiffProjection<E>,
+{
+    /// Applies a single event `event` to the view like [`InMemoryProjection::apply`], and
+    /// returns the changes it made to the view.
+    pub fn apply_diff(&mut self, event: &E) -> Vec<P::Change> {
+        let changes = P::apply_diff_mut(&mut self.view, event);
+        self.notifier.notify(&self.view);
+        changes
+    }
+
+    /// Applies each event in `events` to the view like [`InMemoryProjection::apply_all`], and
+    /// returns the changes they made to the view, merged with [`DiffProjection::merge_changes`].
+    pub fn apply_all_diff(&mut self, events: &[E]) -> Vec<P::Change> {
+        let mut changes = vec![];
+        for e in events {
+            changes.extend(P::apply_diff_mut(&mut self.view, e));
+            if !self.notifier.coalesce {
+                self.notifier.notify(&self.view);
+            }
+        }
+        if self.notifier.coalesce {
+            self.notifier.notify(&self.view);
+        }
+        P::merge_changes(changes)
+    }
+}
+
+impl<P, E> Default for InMemoryProjection<P, E>
+where
+    P: Projection<E>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P, E> Debug for InMemoryProjection<P, E>
+where
+    P: Projection<E>,
+    P::View: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InMemoryProjection")
+            .field("view", &self.view)
+            .finish()
+    }
+}
+
+type ChangeCallback<V> = Box<dyn FnMut(&V)>;
+
+/// The callbacks to notify when a view changes.
+struct ChangeNotifier<V> {
+    callbacks: Vec<ChangeCallback<V>>,
+    coalesce: bool,
+}
+
+impl<V> ChangeNotifier<V> {
+    fn new() -> Self {
+        Self {
+            callbacks: vec![],
+            coalesce: false,
+        }
+    }
+
+    fn notify(&mut self, view: &V) {
+        for callback in self.callbacks.iter_mut() {
+            callback(view);
+        }
+    }
+}
+
+impl<V> ChangeNotifier<V>
+where
+    V: Clone + PartialEq + 'static,
+{
+    /// Adds a callback that is only called when the view differs from the last view it saw,
+    /// starting with `current`.
+    fn subscribe(&mut self, current: &V, mut callback: impl FnMut(&V) + 'static) {
+        let mut last = current.clone();
+        self.callbacks.push(Box::new(move |view| {
+            if *view != last {
+                last = view.clone();
+                callback(view);
+            }
+        }));
+    }
+
+    #[cfg(feature = "tokio")]
+    fn watch(&mut self, current: &V) -> tokio::sync::watch::Receiver<V> {
+        let (sender, receiver) = tokio::sync::watch::channel(current.clone());
+        self.subscribe(current, move |view| {
+            sender.send_replace(view.clone());
+        });
+        receiver
+    }
+}
+
+/// A sample projection that counts how many events of each type have been seen.
+///
+/// The type of an event is the name of its variant, taken from the event's [`Debug`]
+/// representation, so `Fitted { max_uses: 5 }` is counted as `"Fitted"`. The view maps each type
+/// name to the number of times it has been seen.
+pub struct CountByEventType;
+
+/// Returns the name of the variant of `event`, using its [`Debug`] representation.
+pub fn event_type_name<E: Debug>(event: &E) -> String {
+    let debug = format!("{event:?}");
+    match debug.find([' ', '{', '(']) {
+        Some(i) => debug[..i].to_string(),
+        None => debug,
+    }
+}
+
+impl<E> Projection<E> for CountByEventType
+where
+    E: Debug,
+{
+    type View = HashMap<String, u64>;
+
+    fn initial() -> HashMap<String, u64> {
+        HashMap::new()
+    }
+
+    fn apply(view: &HashMap<String, u64>, event: &E) -> HashMap<String, u64> {
+        let mut view = view.clone();
+        Self::apply_mut(&mut view, event);
+        view
+    }
+
+    fn apply_mut(view: &mut HashMap<String, u64>, event: &E) {
+        *view.entry(event_type_name(event)).or_insert(0) += 1;
+    }
+}
+
+/// A trait describing how a [`TimeBucketedProjection`] buckets events of type `E`.
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait TimeBucketDefinition<E> {
+    /// The width of each bucket, in the same unit as the timestamps, such as 3600 for hourly
+    /// buckets of timestamps in seconds. It must be at least 1, which is checked at compile time.
+    const BUCKET_WIDTH: u64;
+
+    /// How many of the most recent buckets are kept. Older buckets are dropped. A retention of 0
+    /// keeps every bucket.
+    const RETENTION: u64;
+
+    /// Returns the timestamp of the event `event`, or `None` if it should not be counted.
+    fn timestamp(event: &E) -> Option<u64>;
+
+    /// Returns the type of the event `event`. By default, this is the name of its variant, as
+    /// returned by [`event_type_name`].
+    fn event_type(event: &E) -> String
+    where
+        E: Debug,
+    {
+        event_type_name(event)
+    }
+}
+
+/// The view of a [`TimeBucketedProjection`]: the number of events of each type seen in each
+/// bucket, with buckets identified by the timestamp they start at.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BucketCounts {
+    buckets: BTreeMap<u64, BTreeMap<String, u64>>,
+}
+
+impl BucketCounts {
+    /// Returns the number of events of the type `event_type` in the bucket starting at `bucket`.
+    pub fn count(&self, bucket: u64, event_type: &str) -> u64 {
+        self.buckets
+            .get(&bucket)
+            .and_then(|counts| counts.get(event_type))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of events of every type in the bucket starting at `bucket`.
+    pub fn total(&self, bucket: u64) -> u64 {
+        self.buckets
+            .get(&bucket)
+            .map_or(0, |counts| counts.values().sum())
+    }
+
+    /// Returns the start of every bucket with events, in order.
+    pub fn buckets(&self) -> impl Iterator<Item = u64> + '_ {
+        self.buckets.keys().copied()
+    }
+
+    fn retain_recent(&mut self, width: u64, retention: u64) {
+        if retention == 0 {
+            return;
+        }
+        if let Some(&latest) = self.buckets.keys().next_back() {
+            let oldest = latest.saturating_sub(retention.saturating_sub(1).saturating_mul(width));
+            self.buckets = self.buckets.split_off(&oldest);
+        }
+    }
+}
+
+/// A projection that counts events per type in fixed-width buckets of time, such as events per
+/// hour for a dashboard, as described by `D`.
+///
+/// Only the most recent [`TimeBucketDefinition::RETENTION`] buckets are kept, or every bucket if
+/// the retention is 0. Views built from
+/// different parts of a stream, such as shards, can be combined with
+/// [`TimeBucketedProjection::merge`].
+pub struct TimeBucketedProjection<D> {
+    definition: PhantomData<D>,
+}
+
+impl<D> TimeBucketedProjection<D> {
+    /// Combines the views `left` and `right` by adding their counts, then drops the buckets that
+    /// are too old to be kept. Merging is associative and commutative.
+    pub fn merge<E>(left: &BucketCounts, right: &BucketCounts) -> BucketCounts
+    where
+        D: TimeBucketDefinition<E>,
+    {
+        let mut merged = left.clone();
+        for (bucket, counts) in right.buckets.iter() {
+            let merged_counts = merged.buckets.entry(*bucket).or_default();
+            for (event_type, count) in counts.iter() {
+                *merged_counts.entry(event_type.clone()).or_insert(0) += count;
+            }
+        }
+        merged.retain_recent(D::BUCKET_WIDTH, D::RETENTION);
+        merged
+    }
+
+    /// Returns the start of the bucket containing the timestamp `timestamp`.
+    pub fn bucket_of<E>(timestamp: u64) -> u64
+    where
+        D: TimeBucketDefinition<E>,
+    {
+        const { assert!(D::BUCKET_WIDTH >= 1, "the bucket width must be at least 1") };
+        timestamp - timestamp % D::BUCKET_WIDTH
+    }
+}
+
+impl<D, E> Projection<E> for TimeBucketedProjection<D>
+where
+    D: TimeBucketDefinition<E>,
+    E: Debug,
+{
+    type View = BucketCounts;
+
+    fn initial() -> BucketCounts {
+        BucketCounts::default()
+    }
+
+    fn apply(view: &BucketCounts, event: &E) -> BucketCounts {
+        let mut view = view.clone();
+        Self::apply_mut(&mut view, event);
+        view
+    }
+
+    fn apply_mut(view: &mut BucketCounts, event: &E) {
+        let Some(timestamp) = D::timestamp(event) else {
+            return;
+        };
+        let bucket = Self::bucket_of::<E>(timestamp);
+        *view
+            .buckets
+            .entry(bucket)
+            .or_default()
+            .entry(D::event_type(event))
+            .or_insert(0) += 1;
+        view.retain_recent(D::BUCKET_WIDTH, D::RETENTION);
+    }
+}
+
+/// A type that routes the events of a composed stream to two projections.
+///
+/// The events are instances of the [`Either`] type, such as those produced by
+/// [`crate::deciders::ComposedDeciders`]. `Left` events are applied to the projection `P1` and
+/// `Right` events are applied to the projection `P2`. The view is a tuple of both projections'
+/// views.
+pub struct RoutedProjection<P1, P2> {
+    projection1: PhantomData<P1>,
+    projection2: PhantomData<P2>,
+}
+
+impl<P1, P2, E1, E2> Projection<Either<E1, E2>> for RoutedProjection<P1, P2>
+where
+    P1: Projection<E1>,
+    P2: Projection<E2>,
+    P1::View: Clone,
+    P2::View: Clone,
+{
+    type View = (P1::View, P2::View);
+
+    fn initial() -> (P1::View, P2::View) {
+        (P1::initial(), P2::initial())
+    }
+
+    fn apply(view: &(P1::View, P2::View), event: &Either<E1, E2>) -> (P1::View, P2::View) {
+        match event {
+            Either::Left(e) => (P1::apply(&view.0, e), view.1.clone()),
+            Either::Right(e) => (view.0.clone(), P2::apply(&view.1, e)),
+        }
+    }
+
+    fn apply_mut(view: &mut (P1::View, P2::View), event: &Either<E1, E2>) {
+        match event {
+            Either::Left(e) => P1::apply_mut(&mut view.0, e),
+            Either::Right(e) => P2::apply_mut(&mut view.1, e),
+        }
+    }
+
+    // Both views are independent, so each side's events can be applied separately.
+    fn apply_all_mut(view: &mut (P1::View, P2::View), events: &[Either<E1, E2>]) {
+        for e in lefts(events.iter().map(Either::as_ref)) {
+            P1::apply_mut(&mut view.0, e);
+        }
+        for e in rights(events.iter().map(Either::as_ref)) {
+            P2::apply_mut(&mut view.1, e);
+        }
+    }
+}
+
+/// A type that applies a projection separately for each key of a keyed stream.
+///
+/// The events are tuples of a key and an event, such as those produced by
+/// [`crate::deciders::ManyDecider`]. The view is a HashMap mapping each key to the view of the
+/// projection `P` over only the events with that key. A key's view is initialized the first time
+/// an event with that key is applied.
+pub struct KeyedProjection<P> {
+    projection: PhantomData<P>,
+}
+
+impl<P, K, E> Projection<(K, E)> for KeyedProjection<P>
+where
+    K: Eq + Hash + Clone,
+    P: Projection<E>,
+    P::View: Clone,
+{
+    type View = HashMap<K, P::View>;
+
+    fn initial() -> HashMap<K, P::View> {
+        HashMap::new()
+    }
+
+    fn apply(views: &HashMap<K, P::View>, event: &(K, E)) -> HashMap<K, P::View> {
+        let mut views = views.clone();
+        Self::apply_mut(&mut views, event);
+        views
+    }
+
+    fn apply_mut(views: &mut HashMap<K, P::View>, (key, event): &(K, E)) {
+        let view = views.entry(key.clone()).or_insert_with(P::initial);
+        P::apply_mut(view, event);
+    }
+
+    // Looks up each key's view once per run of consecutive events with that key, without
+    // allocating, and only clones a key when its view is created.
+    fn apply_all_mut(views: &mut HashMap<K, P::View>, events: &[(K, E)]) {
+        for run in events.chunk_by(|(a, _), (b, _)| a == b) {
+            let key = &run[0].0;
+            if !views.contains_key(key) {
+                views.insert(key.clone(), P::initial());
+            }
+            let view = views.get_mut(key).expect("the view was just inserted");
+            for (_, e) in run {
+                P::apply_mut(view, e);
+            }
+        }
+    }
+}
+
+impl<P, K, E> DiffProjection<(K, E)> for KeyedProjection<P>
+where
+    K: Eq + Hash + Clone,
+    P: Projection<E>,
+    P::View: Clone + PartialEq,
+{
+    type Change = ViewChange<K, P::View>;
+
+    fn apply_diff(
+        views: &HashMap<K, P::View>,
+        event: &(K, E),
+    ) -> (HashMap<K, P::View>, Vec<ViewChange<K, P::View>>) {
+        let mut views = views.clone();
+        let changes = Self::apply_diff_mut(&mut views, event);
+        (views, changes)
+    }
+
+    fn apply_diff_mut(
+        views: &mut HashMap<K, P::View>,
+        (key, event): &(K, E),
+    ) -> Vec<ViewChange<K, P::View>> {
+        match views.get_mut(key) {
+            Some(view) => {
+                let old = view.clone();
+                P::apply_mut(view, event);
+                if *view == old {
+                    vec![]
+                } else {
+                    vec![ViewChange::Updated(key.clone(), view.clone())]
+                }
+            }
+            None => {
+                let mut view = P::initial();
+                P::apply_mut(&mut view, event);
+                views.insert(key.clone(), view.clone());
+                vec![ViewChange::Added(key.clone(), view)]
+            }
+        }
+    }
+
+    fn merge_changes(changes: Vec<ViewChange<K, P::View>>) -> Vec<ViewChange<K, P::View>> {
+        merge_view_changes(changes)
+    }
+}
+
+/// A type that applies the known events of a stream that may contain unknown events, as read
+/// with [`MaybeUnknown`], to the projection `P`, skipping the unknown ones.
+///
+/// Requires the `serde` feature.
+#[cfg(feature = "serde")]
+pub struct SkipUnknown<P> {
+    projection: PhantomData<P>,
+}
+
+#[cfg(feature = "serde")]
+impl<P, E> Projection<MaybeUnknown<E>> for SkipUnknown<P>
+where
+    P: Projection<E>,
+    P::View: Clone,
+{
+    type View = P::View;
+
+    fn initial() -> P::View {
+        P::initial()
+    }
+
+    fn apply(view: &P::View, event: &MaybeUnknown<E>) -> P::View {
+        match event {
+            MaybeUnknown::Known(event) => P::apply(view, event),
+            MaybeUnknown::Unknown { .. } => view.clone(),
+        }
+    }
+
+    fn apply_mut(view: &mut P::View, event: &MaybeUnknown<E>) {
+        if let MaybeUnknown::Known(event) = event {
+            P::apply_mut(view, event);
+        }
+    }
+}
+
+/// A change to one entry of a HashMap-shaped view, as reported by a [`DiffProjection`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum ViewChange<K, V> {
+    /// An entry was added with the given value.
+    Added(K, V),
+    /// An existing entry was changed to the given value.
+    Updated(K, V),
+    /// An entry was removed.
+    Removed(K),
+}
+
+impl<K, V> ViewChange<K, V> {
+    /// Returns the key of the changed entry.
+    pub fn key(&self) -> &K {
+        match self {
+            ViewChange::Added(k, _) | ViewChange::Updated(k, _) | ViewChange::Removed(k) => k,
+        }
+    }
+}
+
+/// A projection that can report what applying an event changed in its view, so that readers
+/// can be sent the changes instead of the whole view.
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait DiffProjection<E>: Projection<E> {
+    /// The type of a change to the view, such as a [`ViewChange`].
+    type Change;
+
+    /// Given the current view `view` and an event `event`, returns the new view and the changes
+    /// made to it.
+    fn apply_diff(view: &Self::View, event: &E) -> (Self::View, Vec<Self::Change>);
+
+    /// Applies the event `event` to the view `view` in place, and returns the changes made to
+    /// it.
+    ///
+    /// By default this calls [`DiffProjection::apply_diff`] and replaces the view.
+    fn apply_diff_mut(view: &mut Self::View, event: &E) -> Vec<Self::Change> {
+        let (new_view, changes) = Self::apply_diff(view, event);
+        *view = new_view;
+        changes
+    }
+
+    /// Merges the changes `changes`, made by several events in order, into the fewest changes
+    /// with the same result.
+    ///
+    /// By default the changes are returned as they are.
+    fn merge_changes(changes: Vec<Self::Change>) -> Vec<Self::Change> {
+        changes
+    }
+}
+
+/// Returns the changes that turn the view `old` into the view `new`, ordered by key.
+pub fn diff_maps<K, V>(old: &HashMap<K, V>, new: &HashMap<K, V>) -> Vec<ViewChange<K, V>>
+where
+    K: Eq + Hash + Ord + Clone,
+    V: PartialEq + Clone,
+{
+    let mut changes: Vec<_> = new
+        .iter()
+        .filter_map(|(k, v)| match old.get(k) {
+            None => Some(ViewChange::Added(k.clone(), v.clone())),
+            Some(old) if old != v => Some(ViewChange::Updated(k.clone(), v.clone())),
+            Some(_) => None,
+        })
+        .chain(
+            old.keys()
+                .filter(|k| !new.contains_key(*k))
+                .map(|k| ViewChange::Removed(k.clone())),
+        )
+        .collect();
+    changes.sort_by(|a, b| a.key().cmp(b.key()));
+    changes
+}
+
+/// Merges the changes `changes`, made in order, into at most one change per key, ordered by the
+/// first change to each key.
+///
+/// For example, an entry that was added and then updated is reported as added with its latest
+/// value, and an entry that was added and then removed is not reported at all.
+pub fn merge_view_changes<K, V>(changes: Vec<ViewChange<K, V>>) -> Vec<ViewChange<K, V>>
+where
+    K: Eq + Hash + Clone,
+{
+    let mut indices = HashMap::new();
+    let mut merged: Vec<Option<ViewChange<K, V>>> = vec![];
+    for change in changes {
+        let Some(&i) = indices.get(change.key()) else {
+            indices.insert(change.key().clone(), merged.len());
+            merged.push(Some(change));
+            continue;
+        };
+        merged[i] = match (merged[i].take(), change) {
+            (Some(ViewChange::Added(..)), ViewChange::Removed(_)) => None,
+            (Some(ViewChange::Added(..)), ViewChange::Updated(k, v)) => {
+                Some(ViewChange::Added(k, v))
+            }
+            (Some(ViewChange::Removed(_)), ViewChange::Added(k, v)) => {
+                Some(ViewChange::Updated(k, v))
+            }
+            (_, change) => Some(change),
+        };
+    }
+    merged.into_iter().flatten().collect()
+}
+
+/// A source of events ordered by their global position.
+///
+/// Positions start at 0 and increase with every event. Slices and vectors of events implement
+/// this trait, using each event's index as its position.
+pub trait EventLog<E> {
+    /// Returns an iterator over every event with a position greater than or equal to `position`,
+    /// along with that position, in increasing order of position.
+    fn events_from<'a>(
+        &'a self,
+        position: GlobalPosition,
+    ) -> impl Iterator<Item = (GlobalPosition, &'a E)>
+    where
+        E: 'a;
+}
+
+impl<E> EventLog<E> for [E] {
+    fn events_from<'a>(
+        &'a self,
+        position: GlobalPosition,
+    ) -> impl Iterator<Item = (GlobalPosition, &'a E)>
+    where
+        E: 'a,
+    {
+        self.iter()
+            .enumerate()
+            .skip(u64::from(position) as usize)
+            .map(|(i, e)| (GlobalPosition::from(i as u64), e))
+    }
+}
+
+impl<E> EventLog<E> for Vec<E> {
+    fn events_from<'a>(
+        &'a self,
+        position: GlobalPosition,
+    ) -> impl Iterator<Item = (GlobalPosition, &'a E)>
+    where
+        E: 'a,
+    {
+        self[..].events_from(position)
+    }
+}
+
+/// Returns the events in `log` from `next_position` on, skipping any the log redelivers from
+/// before it.
+///
+/// If the log delivers an event at a position that is not after the previous one, the iterator
+/// returns a [`PositionError`] and ends, since positions only move forward.
+pub(crate) fn unapplied_events<'a, E, L>(
+    log: &'a L,
+    next_position: GlobalPosition,
+) -> impl Iterator<Item = Result<(GlobalPosition, &'a E), PositionError>>
+where
+    E: 'a,
+    L: EventLog<E> + ?Sized,
+{
+    let mut previous: Option<GlobalPosition> = None;
+    let mut failed = false;
+    log.events_from(next_position)
+        .map_while(move |(position, event)| {
+            if failed {
+                return None;
+            }
+            if let Some(previous) = previous.filter(|&previous| position <= previous) {
+                failed = true;
+                return Some(Err(PositionError {
+                    minimum: previous + 1,
+                    found: position,
+                }));
+            }
+            previous = Some(position);
+            Some(Ok((position, event)))
+        })
+        .filter(move |item| !matches!(item, Ok((position, _)) if *position < next_position))
+}
+
+/// A token identifying a point in an [`EventLog`], returned after writing events so that readers
+/// can wait until a projection has caught up with them.
+///
+/// The token holds the position after the last event written, so a [`ProjectionRunner`] has
+/// reached it once its next position is at least the token's. It is formatted and parsed as a
+/// plain number, so it can be passed around as a string, such as in an HTTP header.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ConsistencyToken(pub GlobalPosition);
+
+impl ConsistencyToken {
+    /// Returns the token for the end of `log`, after every event currently in it.
+    pub fn after<E>(log: &[E]) -> Self {
+        Self(GlobalPosition::from(log.len() as u64))
+    }
+}
+
+impl From<u64> for ConsistencyToken {
+    fn from(position: u64) -> Self {
+        Self(position.into())
+    }
+}
+
+impl Display for ConsistencyToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for ConsistencyToken {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(|position: u64| Self(position.into()))
+    }
+}
+
+/// An error returned when a projection has not caught up with a [`ConsistencyToken`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stale {
+    /// The token the projection needed to reach.
+    pub required: ConsistencyToken,
+    /// The position of the next event the projection had not applied.
+    pub reached: GlobalPosition,
+}
+
+impl Display for Stale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the projection has reached position {}, but position {} is required",
+            self.reached, self.required
+        )
+    }
+}
+
+impl std::error::Error for Stale {}
+
+/// The progress of a projection through an [`EventLog`], along with the view at that point.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint<V> {
+    /// The position of the next event the projection has not yet applied.
+    pub next_position: GlobalPosition,
+    /// The view after applying every event before `next_position`.
+    pub view: V,
+}
+
+impl<V> Checkpoint<V> {
+    /// Records that the event at `position` has been applied, moving the next position past it.
+    ///
+    /// Returns an error and leaves the checkpoint unchanged if `position` is before the next
+    /// position, since positions only move forward.
+    pub fn advance_past(&mut self, position: GlobalPosition) -> Result<(), PositionError> {
+        if position < self.next_position {
+            return Err(PositionError {
+                minimum: self.next_position,
+                found: position,
+            });
+        }
+        self.next_position = position + 1;
+        Ok(())
+    }
+}
+
+/// A trait for storing the checkpoints of named projections.
+pub trait CheckpointStore<V> {
+    /// Returns the last checkpoint saved for the projection named `name`, if there is one.
+    fn load(&self, name: &str) -> Option<Checkpoint<V>>;
+
+    /// Saves the checkpoint `checkpoint` for the projection named `name`, replacing any previous
+    /// checkpoint.
+    fn save(&mut self, name: &str, checkpoint: &Checkpoint<V>);
+}
+
+/// A simple [`CheckpointStore`] that keeps checkpoints in memory.
+#[derive(Clone, Debug)]
+pub struct InMemoryCheckpointStore<V> {
+    checkpoints: HashMap<String, Checkpoint<V>>,
+}
+
+impl<V> InMemoryCheckpointStore<V> {
+    /// Constructs a new, empty `InMemoryCheckpointStore`.
+    pub fn new() -> Self {
+        Self {
+            checkpoints: HashMap::new(),
+        }
+    }
+}
+
+impl<V> Default for InMemoryCheckpointStore<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> CheckpointStore<V> for InMemoryCheckpointStore<V>
+where
+    V: Clone,
+{
+    fn load(&self, name: &str) -> Option<Checkpoint<V>> {
+        self.checkpoints.get(name).cloned()
+    }
+
+    fn save(&mut self, name: &str, checkpoint: &Checkpoint<V>) {
+        self.checkpoints
+            .insert(name.to_string(), checkpoint.clone());
+    }
+}
+
+/// What a [`ProjectionRunner`] does when the next position in an [`EventLog`] is not the position
+/// it expected.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GapPolicy {
+    /// Stop consuming events and return a [`GapError`].
+    Fail,
+    /// Replace the view with the snapshot barrier set by
+    /// [`ProjectionRunner::set_snapshot_barrier`], which holds the view of every event the log
+    /// trimmed, and continue from the barrier's position. If there is no barrier, or it does not
+    /// reach the first event after the gap, stop and return a [`GapError`] as in
+    /// [`GapPolicy::Fail`].
+    RebuildFromSnapshotBarrier,
+    /// Continue from the first event after the gap, keeping the view, and record the skipped
+    /// range of positions.
+    SkipAndRecord,
+}
+
+/// An error returned when an [`EventLog`] skipped over positions a [`ProjectionRunner`] had not
+/// applied yet.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GapError {
+    /// The position the runner expected to be delivered next.
+    pub expected: GlobalPosition,
+    /// The position that was actually delivered.
+    pub found: GlobalPosition,
+}
+
+impl Display for GapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "expected event at position {}, but the next event was at position {}",
+            self.expected, self.found
+        )
+    }
+}
+
+impl std::error::Error for GapError {}
+
+/// An error returned when an [`EventLog`] delivered an event at a position that is not after the
+/// previous one, even though positions only move forward.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PositionError {
+    /// The earliest position that could have been delivered.
+    pub minimum: GlobalPosition,
+    /// The position that was actually delivered.
+    pub found: GlobalPosition,
+}
+
+impl Display for PositionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the event log delivered position {}, but positions had to be at least {}",
+            self.found, self.minimum
+        )
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+/// An error returned when a [`ProjectionRunner`] stopped consuming an [`EventLog`] before its
+/// end.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CatchUpError {
+    /// The log skipped over positions the runner had not applied yet, and the runner's
+    /// [`GapPolicy`] did not allow it to continue.
+    Gap(GapError),
+    /// The log delivered positions out of order.
+    Position(PositionError),
+}
+
+impl From<GapError> for CatchUpError {
+    fn from(error: GapError) -> Self {
+        CatchUpError::Gap(error)
+    }
+}
+
+impl From<PositionError> for CatchUpError {
+    fn from(error: PositionError) -> Self {
+        CatchUpError::Position(error)
+    }
+}
+
+impl Display for CatchUpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CatchUpError::Gap(error) => write!(f, "{error}"),
+            CatchUpError::Position(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for CatchUpError {}
+
+/// An error returned when [`ProjectionRunner::wait_for`] did not reach a [`ConsistencyToken`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WaitError {
+    /// The runner had not reached the token when the timeout passed.
+    Stale(Stale),
+    /// The runner stopped catching up with the log, so it could not reach the token.
+    CatchUp(CatchUpError),
+}
+
+impl From<Stale> for WaitError {
+    fn from(error: Stale) -> Self {
+        WaitError::Stale(error)
+    }
+}
+
+impl From<CatchUpError> for WaitError {
+    fn from(error: CatchUpError) -> Self {
+        WaitError::CatchUp(error)
+    }
+}
+
+impl Display for WaitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WaitError::Stale(error) => write!(f, "{error}"),
+            WaitError::CatchUp(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for WaitError {}
+
+/// How long [`ProjectionRunner::wait_for`] waits between attempts to catch up.
+const CONSISTENCY_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// A type that keeps a projection up to date with an [`EventLog`], persisting its progress in a
+/// [`CheckpointStore`].
+///
+/// Each event in the log is applied exactly once: the runner remembers the position of the next
+/// event it needs, skips everything before it, and saves a checkpoint after consuming new events.
+/// A runner constructed with a store that already holds a checkpoint for its name resumes from
+/// that checkpoint instead of starting over.
+///
+/// Callbacks registered with [`ProjectionRunner::on_change`] are notified whenever consuming
+/// events changes the view.
+///
+/// If the log skips over positions the runner has not applied yet, for example because old
+/// events were trimmed while the runner was lagging behind, the runner's [`GapPolicy`] decides
+/// what happens.
+pub struct ProjectionRunner<P, E, CS>
+where
+    P: Projection<E>,
+    CS: CheckpointStore<P::View>,
+{
+    name: String,
+    checkpoint: Checkpoint<P::View>,
+    store: CS,
+    notifier: ChangeNotifier<P::View>,
+    gap_policy: GapPolicy,
+    barrier: Option<Checkpoint<P::View>>,
+    skipped: Vec<Range<GlobalPosition>>,
+    projection: PhantomData<P>,
+    event: PhantomData<E>,
+}
+
+impl<P, E, CS> ProjectionRunner<P, E, CS>
+where
+    P: Projection<E>,
+    CS: CheckpointStore<P::View>,
+{
+    /// Constructs a new `ProjectionRunner` for the projection named `name`, resuming from the
+    /// checkpoint in `store` if there is one, or starting from the initial view otherwise.
+    pub fn new(name: &str, store: CS) -> Self {
+        let checkpoint = store.load(name).unwrap_or_else(|| Checkpoint {
+            next_position: GlobalPosition::default(),
+            view: P::initial(),
+        });
+        Self {
+            name: name.to_string(),
+            checkpoint,
+            store,
+            notifier: ChangeNotifier::new(),
+            gap_policy: GapPolicy::Fail,
+            barrier: None,
+            skipped: vec![],
+            projection: PhantomData,
+            event: PhantomData,
+        }
+    }
+
+    /// Applies every event in `log` that has not been applied yet, then saves a checkpoint.
+    ///
+    /// Returns the number of events that were applied, or an error if a gap was found in the
+    /// log's positions that the gap policy does not allow skipping, or if the log delivered
+    /// positions out of order. In that case, the checkpoint is saved after the last event that
+    /// was applied.
+    pub fn catch_up<L>(&mut self, log: &L) -> Result<u64, CatchUpError>
+    where
+        L: EventLog<E> + ?Sized,
+    {
+        self.consume(log, |_| ())
+    }
+
+    /// Resets the view to the initial view and reapplies every event in `log` from position 0,
+    /// then saves a checkpoint.
+    ///
+    /// `progress` is called with the position of each event after it has been applied. Returns
+    /// the number of events that were applied, or an error as in [`ProjectionRunner::catch_up`].
+    pub fn rebuild<L>(
+        &mut self,
+        log: &L,
+        progress: impl FnMut(GlobalPosition),
+    ) -> Result<u64, CatchUpError>
+    where
+        L: EventLog<E> + ?Sized,
+    {
+        self.checkpoint = Checkpoint {
+            next_position: GlobalPosition::default(),
+            view: P::initial(),
+        };
+        self.skipped.clear();
+        self.consume(log, progress)
+    }
+
+    fn consume<L>(
+        &mut self,
+        log: &L,
+        mut progress: impl FnMut(GlobalPosition),
+    ) -> Result<u64, CatchUpError>
+    where
+        L: EventLog<E> + ?Sized,
+    {
+        let mut applied = 0;
+        let mut result = Ok(());
+        for item in unapplied_events(log, self.checkpoint.next_position) {
+            let (position, event) = match item {
+                Ok(item) => item,
+                Err(error) => {
+                    result = Err(error.into());
+                    break;
+                }
+            };
+            if position < self.checkpoint.next_position {
+                // Already reflected in a snapshot barrier loaded after a gap.
+                continue;
+            }
+            if position > self.checkpoint.next_position {
+                let gap = GapError {
+                    expected: self.checkpoint.next_position,
+                    found: position,
+                };
+                match self.gap_policy {
+                    GapPolicy::Fail => {
+                        result = Err(gap.into());
+                        break;
+                    }
+                    GapPolicy::RebuildFromSnapshotBarrier => {
+                        let covers_gap = self.barrier.as_ref().is_some_and(|barrier| {
+                            barrier.next_position > gap.expected
+                                && barrier.next_position >= position
+                        });
+                        match self.barrier.take() {
+                            Some(barrier) if covers_gap => self.checkpoint = barrier,
+                            barrier => {
+                                self.barrier = barrier;
+                                result = Err(gap.into());
+                                break;
+                            }
+                        }
+                        if position < self.checkpoint.next_position {
+                            continue;
+                        }
+                    }
+                    GapPolicy::SkipAndRecord => {
+                        self.skipped.push(gap.expected..gap.found);
+                    }
+                }
+            }
+            if let Err(error) = self.checkpoint.advance_past(position) {
+                result = Err(error.into());
+                break;
+            }
+            P::apply_mut(&mut self.checkpoint.view, event);
+            applied += 1;
+            progress(position);
+            if !self.notifier.coalesce {
+                self.notifier.notify(&self.checkpoint.view);
+            }
+        }
+        self.store.save(&self.name, &self.checkpoint);
+        if self.notifier.coalesce {
+            self.notifier.notify(&self.checkpoint.view);
+        }
+        result.map(|_| applied)
+    }
+
+    /// Sets how gaps in the positions delivered by an [`EventLog`] are handled. The default policy
+    /// is [`GapPolicy::Fail`].
+    pub fn set_gap_policy(&mut self, policy: GapPolicy) {
+        self.gap_policy = policy;
+    }
+
+    /// Sets the snapshot barrier loaded under [`GapPolicy::RebuildFromSnapshotBarrier`]: the view
+    /// after applying every event before `barrier.next_position`, saved before the log trimmed
+    /// those events.
+    ///
+    /// The barrier is only loaded for a gap that starts before its position and ends at or before
+    /// it, and is discarded once loaded.
+    pub fn set_snapshot_barrier(&mut self, barrier: Checkpoint<P::View>) {
+        self.barrier = Some(barrier);
+    }
+
+    /// Returns the ranges of positions that were skipped under [`GapPolicy::SkipAndRecord`].
+    pub fn skipped_ranges(&self) -> &[Range<GlobalPosition>] {
+        &self.skipped
+    }
+
+    /// Sets whether the changes made by a single call to [`ProjectionRunner::catch_up`] or
+    /// [`ProjectionRunner::rebuild`] are coalesced into one notification. Coalescing is disabled
+    /// by default.
+    pub fn set_coalescing(&mut self, coalesce: bool) {
+        self.notifier.coalesce = coalesce;
+    }
+
+    /// Returns a reference to the current view.
+    pub fn view(&self) -> &P::View {
+        &self.checkpoint.view
+    }
+
+    /// Executes the query named `name` from `registry` with the parameters `params` against the
+    /// current view.
+    pub fn query(
+        &self,
+        registry: &QueryRegistry<P::View>,
+        name: &str,
+        params: &str,
+    ) -> Result<QueryResult, QueryError> {
+        registry.execute(&self.checkpoint.view, name, params)
+    }
+
+    /// Returns the position of the next event this runner has not yet applied.
+    pub fn next_position(&self) -> GlobalPosition {
+        self.checkpoint.next_position
+    }
+
+    /// Returns an error if this runner has not applied every event before the token `token`.
+    pub fn check_consistency(&self, token: ConsistencyToken) -> Result<(), Stale> {
+        let reached = self.checkpoint.next_position;
+        if reached >= token.0 {
+            Ok(())
+        } else {
+            Err(Stale {
+                required: token,
+                reached,
+            })
+        }
+    }
+
+    /// Executes the query named `name` like [`ProjectionRunner::query`], but if `token` is given,
+    /// fails with [`QueryError::Stale`] unless the view reflects every event before it.
+    pub fn query_consistent(
+        &self,
+        registry: &QueryRegistry<P::View>,
+        name: &str,
+        params: &str,
+        token: Option<ConsistencyToken>,
+    ) -> Result<QueryResult, QueryError> {
+        if let Some(token) = token {
+            self.check_consistency(token).map_err(QueryError::Stale)?;
+        }
+        self.query(registry, name, params)
+    }
+
+    /// Repeatedly catches up with the log returned by `log` until this runner has reached the
+    /// token `token`, or returns [`WaitError::Stale`] once `timeout` has passed.
+    ///
+    /// `log` is called before each attempt, so it can lock a log that is being written to by
+    /// another thread, such as by returning a [`MutexGuard`](std::sync::MutexGuard). Gaps in
+    /// the log are handled by the runner's [`GapPolicy`]. If catching up fails, such as on a gap
+    /// under [`GapPolicy::Fail`], the runner cannot make progress, so the error is returned
+    /// straight away as [`WaitError::CatchUp`].
+    pub fn wait_for<G, L>(
+        &mut self,
+        token: ConsistencyToken,
+        timeout: Duration,
+        mut log: impl FnMut() -> G,
+    ) -> Result<(), WaitError>
+    where
+        G: Deref<Target = L>,
+        L: EventLog<E> + ?Sized,
+    {
+        let deadline = Instant::now() + timeout;
+        loop {
+            self.catch_up(&*log())?;
+            let result = self.check_consistency(token);
+            if result.is_ok() || Instant::now() >= deadline {
+                return Ok(result?);
+            }
+            thread::sleep(CONSISTENCY_POLL_INTERVAL.min(deadline - Instant::now()));
+        }
+    }
+
+    /// Consumes this runner, returning its checkpoint store.
+    pub fn into_checkpoint_store(self) -> CS {
+        self.store
+    }
+}
+
+impl<P, E, CS> ProjectionRunner<P, E, CS>
+where
+    P: Projection<E>,
+    P::View: Clone + PartialEq + 'static,
+    CS: CheckpointStore<P::View>,
+{
+    /// Registers a callback that is called with the new view whenever the view changes.
+    ///
+    /// Notifications are delivered in event order, and only when the view is different from the
+    /// view the callback was last notified with (or the view at registration time).
+    pub fn on_change(&mut self, callback: impl FnMut(&P::View) + 'static) {
+        self.notifier.subscribe(&self.checkpoint.view, callback);
+    }
+
+    /// Returns a receiver that always holds the latest view, and is marked as changed whenever
+    /// the view changes.
+    #[cfg(feature = "tokio")]
+    pub fn watch_view(&mut self) -> tokio::sync::watch::Receiver<P::View> {
+        self.notifier.watch(&self.checkpoint.view)
+    }
+}
+
+impl<P, E, CS> Debug for ProjectionRunner<P, E, CS>
+where
+    P: Projection<E>,
+    P::View: Debug,
+    CS: CheckpointStore<P::View>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProjectionRunner")
+            .field("name", &self.name)
+            .field("checkpoint", &self.checkpoint)
+            .finish()
+    }
+}
+
+/// The result of executing a query against a view.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum QueryResult {
+    /// A numeric result.
+    Number(f64),
+    /// A textual result.
+    String(String),
+    /// A list of results.
+    List(Vec<QueryResult>),
+    /// An arbitrary JSON result, for views that are already serializable.
+    #[cfg(feature = "serde")]
+    Json(serde_json::Value),
+}
+
+/// An error returned when a query could not be executed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum QueryError {
+    /// No query with the given name has been registered.
+    UnknownQuery(String),
+    /// The parameters passed to a query could not be parsed.
+    InvalidParameters {
+        /// The name of the query.
+        query: String,
+        /// A description of what was wrong with the parameters.
+        message: String,
+    },
+    /// The view had not caught up with the [`ConsistencyToken`] given with the query.
+    Stale(Stale),
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::UnknownQuery(name) => write!(f, "unknown query \"{name}\""),
+            QueryError::InvalidParameters { query, message } => {
+                write!(f, "invalid parameters for query \"{query}\": {message}")
+            }
+            QueryError::Stale(stale) => write!(f, "{stale}"),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+type QueryFn<V> = Box<dyn Fn(&V, &str) -> Result<QueryResult, String>>;
+
+/// A collection of named queries over views of type `V`.
+///
+/// This allows application code to ask for a result by name, such as `"working_bulb_count"`,
+/// without needing to know how the view is laid out.
+pub struct QueryRegistry<V> {
+    queries: HashMap<String, QueryFn<V>>,
+}
+
+impl<V> QueryRegistry<V> {
+    /// Constructs a new, empty `QueryRegistry`.
+    pub fn new() -> Self {
+        Self {
+            queries: HashMap::new(),
+        }
+    }
+
+    /// Registers a query named `name` that takes no parameters, replacing any query previously
+    /// registered with that name.
+    pub fn register(&mut self, name: &str, query: impl Fn(&V) -> QueryResult + 'static) {
+        self.queries
+            .insert(name.to_string(), Box::new(move |view, _| Ok(query(view))));
+    }
+
+    /// Registers a query named `name` that takes parameters, replacing any query previously
+    /// registered with that name.
+    ///
+    /// The query is given the view and the parameter string passed to
+    /// [`QueryRegistry::execute`], and returns a description of the problem if the parameters
+    /// could not be parsed.
+    pub fn register_with_params(
+        &mut self,
+        name: &str,
+        query: impl Fn(&V, &str) -> Result<QueryResult, String> + 'static,
+    ) {
+        self.queries.insert(name.to_string(), Box::new(query));
+    }
+
+    /// Executes the query named `name` with the parameters `params` against the view `view`.
+    pub fn execute(&self, view: &V, name: &str, params: &str) -> Result<QueryResult, QueryError> {
+        let query = self
+            .queries
+            .get(name)
+            .ok_or_else(|| QueryError::UnknownQuery(name.to_string()))?;
+        query(view, params).map_err(|message| QueryError::InvalidParameters {
+            query: name.to_string(),
+            message,
+        })
+    }
+}
+
+impl<V> Default for QueryRegistry<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> Debug for QueryRegistry<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QueryRegistry")
+            .field("queries", &self.queries.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
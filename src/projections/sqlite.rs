@@ -0,0 +1,293 @@
+use std::{
+    fmt::{Debug, Display},
+    marker::PhantomData,
+};
+
+use rusqlite::{params, types::ToSqlOutput, Connection, OptionalExtension, ToSql};
+
+use super::{
+    unapplied_events, EventLog, PositionError, TimeBucketDefinition, TimeBucketedProjection,
+};
+use crate::utilities::GlobalPosition;
+
+/// A value that can be bound to a parameter of a [`SqlStatement`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum SqlValue {
+    /// The SQL `NULL` value.
+    Null,
+    /// A signed integer.
+    Integer(i64),
+    /// A floating point number.
+    Real(f64),
+    /// A string.
+    Text(String),
+}
+
+impl ToSql for SqlValue {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match self {
+            SqlValue::Null => rusqlite::types::Null.to_sql(),
+            SqlValue::Integer(i) => i.to_sql(),
+            SqlValue::Real(r) => r.to_sql(),
+            SqlValue::Text(t) => t.to_sql(),
+        }
+    }
+}
+
+/// A parameterized SQL statement, such as an insert, update, or delete.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SqlStatement {
+    /// The SQL text of the statement, using `?` placeholders for its parameters.
+    pub sql: String,
+    /// The values bound to the statement's parameters, in order.
+    pub params: Vec<SqlValue>,
+}
+
+impl SqlStatement {
+    /// Constructs a new `SqlStatement` from its SQL text and parameters.
+    pub fn new(sql: &str, params: Vec<SqlValue>) -> Self {
+        Self {
+            sql: sql.to_string(),
+            params,
+        }
+    }
+}
+
+/// A trait for translating events of type `E` into the SQL statements that update a set of
+/// tables.
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait RowMapper<E> {
+    /// Returns the statements that apply the event `event` to the tables, or an error if the
+    /// event cannot be stored.
+    fn statements(event: &E) -> rusqlite::Result<Vec<SqlStatement>>;
+
+    /// Returns the statements that empty the tables before a rebuild.
+    fn reset_statements() -> Vec<SqlStatement>;
+}
+
+/// Creates the table used to store the checkpoints of [`SqlProjection`]s, if it does not
+/// already exist.
+pub fn create_checkpoint_table(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS projection_checkpoints (
+            name TEXT PRIMARY KEY NOT NULL,
+            next_position INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Creates the `event_buckets` table written to by the [`RowMapper`] of a
+/// [`TimeBucketedProjection`], if it does not already exist.
+pub fn create_event_bucket_table(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS event_buckets (
+            bucket INTEGER NOT NULL,
+            event_type TEXT NOT NULL,
+            count INTEGER NOT NULL,
+            PRIMARY KEY (bucket, event_type)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Counts events in the `event_buckets` table created by [`create_event_bucket_table`], so that
+/// a [`TimeBucketedProjection`] can be run as a [`SqlProjection`]. Buckets older than the
+/// retention are deleted as newer events arrive, unless the retention is 0. An event whose bucket
+/// starts above [`i64::MAX`], which SQLite cannot store as an integer, is reported as a
+/// [`rusqlite::Error::ToSqlConversionFailure`].
+impl<D, E> RowMapper<E> for TimeBucketedProjection<D>
+where
+    D: TimeBucketDefinition<E>,
+    E: Debug,
+{
+    fn statements(event: &E) -> rusqlite::Result<Vec<SqlStatement>> {
+        let Some(timestamp) = D::timestamp(event) else {
+            return Ok(vec![]);
+        };
+        let bucket = i64::try_from(Self::bucket_of::<E>(timestamp))
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let mut statements = vec![SqlStatement::new(
+            "INSERT INTO event_buckets (bucket, event_type, count) VALUES (?1, ?2, 1)
+             ON CONFLICT(bucket, event_type) DO UPDATE SET count = count + 1",
+            vec![
+                SqlValue::Integer(bucket),
+                SqlValue::Text(D::event_type(event)),
+            ],
+        )];
+        if D::RETENTION > 0 {
+            let window = (D::RETENTION - 1).saturating_mul(D::BUCKET_WIDTH);
+            // A window wider than any stored bucket keeps every bucket.
+            let window = i64::try_from(window).unwrap_or(i64::MAX);
+            statements.push(SqlStatement::new(
+                "DELETE FROM event_buckets WHERE bucket < (SELECT MAX(bucket) FROM event_buckets) - ?1",
+                vec![SqlValue::Integer(window)],
+            ));
+        }
+        Ok(statements)
+    }
+
+    fn reset_statements() -> Vec<SqlStatement> {
+        vec![SqlStatement::new("DELETE FROM event_buckets", vec![])]
+    }
+}
+
+/// An error returned when a [`SqlProjection`] could not consume an [`EventLog`]. Nothing from
+/// the failed batch of events is written to the database.
+#[derive(Debug, PartialEq)]
+pub enum SqlProjectionError {
+    /// The database returned an error.
+    Sql(rusqlite::Error),
+    /// The log delivered positions out of order.
+    Position(PositionError),
+}
+
+impl From<rusqlite::Error> for SqlProjectionError {
+    fn from(error: rusqlite::Error) -> Self {
+        SqlProjectionError::Sql(error)
+    }
+}
+
+impl From<PositionError> for SqlProjectionError {
+    fn from(error: PositionError) -> Self {
+        SqlProjectionError::Position(error)
+    }
+}
+
+impl Display for SqlProjectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SqlProjectionError::Sql(error) => write!(f, "{error}"),
+            SqlProjectionError::Position(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for SqlProjectionError {}
+
+/// A projection that writes its view into the tables of a SQLite database.
+///
+/// The statements produced by the [`RowMapper`] `M` for each event are executed in the same
+/// transaction that advances the projection's checkpoint row, so each batch of events is applied
+/// to the database exactly once, even if the process stops part way through.
+pub struct SqlProjection<M, E>
+where
+    M: RowMapper<E>,
+{
+    name: String,
+    connection: Connection,
+    next_position: GlobalPosition,
+    mapper: PhantomData<M>,
+    event: PhantomData<E>,
+}
+
+impl<M, E> SqlProjection<M, E>
+where
+    M: RowMapper<E>,
+{
+    /// Constructs a new `SqlProjection` named `name` that writes to `connection`, resuming from
+    /// the checkpoint stored in the database if there is one.
+    ///
+    /// The checkpoint table is created if it does not already exist.
+    pub fn new(name: &str, connection: Connection) -> rusqlite::Result<Self> {
+        create_checkpoint_table(&connection)?;
+        let next_position: i64 = connection
+            .query_row(
+                "SELECT next_position FROM projection_checkpoints WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()?
+            .unwrap_or(0);
+        let next_position = u64::try_from(next_position)
+            .map_err(|_| rusqlite::Error::IntegralValueOutOfRange(0, next_position))?;
+        Ok(Self {
+            name: name.to_string(),
+            connection,
+            next_position: next_position.into(),
+            mapper: PhantomData,
+            event: PhantomData,
+        })
+    }
+
+    /// Applies every event in `log` that has not been applied yet in a single transaction.
+    ///
+    /// Returns the number of events that were applied, or an error if the database fails or the
+    /// log delivers positions out of order, in which case the transaction is rolled back.
+    pub fn catch_up<L>(&mut self, log: &L) -> Result<u64, SqlProjectionError>
+    where
+        L: EventLog<E> + ?Sized,
+    {
+        self.consume(log, false)
+    }
+
+    /// Empties the tables using [`RowMapper::reset_statements`] and reapplies every event in
+    /// `log` from position 0, all in a single transaction.
+    ///
+    /// Returns the number of events that were applied, or an error as in
+    /// [`SqlProjection::catch_up`].
+    pub fn rebuild<L>(&mut self, log: &L) -> Result<u64, SqlProjectionError>
+    where
+        L: EventLog<E> + ?Sized,
+    {
+        self.consume(log, true)
+    }
+
+    fn consume<L>(&mut self, log: &L, reset: bool) -> Result<u64, SqlProjectionError>
+    where
+        L: EventLog<E> + ?Sized,
+    {
+        let transaction = self.connection.transaction()?;
+        let mut next_position = self.next_position;
+        if reset {
+            for statement in M::reset_statements() {
+                transaction.execute(
+                    &statement.sql,
+                    rusqlite::params_from_iter(&statement.params),
+                )?;
+            }
+            next_position = GlobalPosition::default();
+        }
+        let mut applied = 0;
+        for item in unapplied_events(log, next_position) {
+            let (position, event) = item?;
+            for statement in M::statements(event)? {
+                transaction.execute(
+                    &statement.sql,
+                    rusqlite::params_from_iter(&statement.params),
+                )?;
+            }
+            next_position = position + 1;
+            applied += 1;
+        }
+        let stored_position = i64::try_from(u64::from(next_position))
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        transaction.execute(
+            "INSERT INTO projection_checkpoints (name, next_position) VALUES (?1, ?2)
+             ON CONFLICT(name) DO UPDATE SET next_position = excluded.next_position",
+            params![self.name, stored_position],
+        )?;
+        transaction.commit()?;
+        self.next_position = next_position;
+        Ok(applied)
+    }
+
+    /// Returns the position of the next event this projection has not yet applied.
+    pub fn next_position(&self) -> GlobalPosition {
+        self.next_position
+    }
+
+    /// Returns a reference to the database connection, for querying the projected tables.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Consumes this projection, returning its database connection.
+    pub fn into_connection(self) -> Connection {
+        self.connection
+    }
+}
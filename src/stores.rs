@@ -0,0 +1,2320 @@
+//! Stores that persist the events and snapshots of streams, and runners that keep the state of
+//! a decider in step with its stream.
+
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::{Debug, Display},
+    hash::Hash,
+    marker::PhantomData,
+    thread,
+    time::Duration,
+};
+
+use crate::deciders::{
+    evolve_all_in_place, Decider, EventUpcaster, Snapshot, SnapshotSchema, Snapshotting,
+};
+use crate::utilities::{Either, StateFingerprint, StreamVersion};
+
+#[cfg(all(feature = "sqlite", feature = "serde"))]
+mod sqlite;
+#[cfg(all(feature = "sqlite", feature = "serde"))]
+pub use sqlite::{
+    create_event_tables, create_snapshot_table, SqliteEventStore, SqliteSnapshotStore,
+};
+
+/// An error returned by an [`EventStore`], a [`SnapshotStore`] or an [`EventCodec`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum StoreError {
+    /// Events were appended against a version that is not the stream's current version,
+    /// meaning something else appended to the stream in the meantime.
+    Conflict {
+        /// The id of the stream whose version did not match.
+        stream_id: String,
+        /// The version the events were appended against.
+        expected: StreamVersion,
+        /// The current version of the stream.
+        stored: StreamVersion,
+    },
+    /// Events were loaded from before the first version a stream still holds, because the
+    /// events before it were removed with [`EventStore::truncate_before`].
+    Truncated {
+        /// The id of the truncated stream.
+        stream_id: String,
+        /// The version of the first event the stream still holds.
+        first: StreamVersion,
+    },
+    /// The stream was moved out of the store by `archive_stream`, leaving the given receipt.
+    Archived(ArchiveReceipt),
+    /// An event or snapshot could not be encoded or decoded.
+    Codec(String),
+    /// The storage behind the store failed, such as a database error.
+    Io(String),
+}
+
+impl Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreError::Conflict {
+                stream_id,
+                expected,
+                stored,
+            } => write!(
+                f,
+                "cannot append to stream {stream_id} at version {expected}, it is at version {stored}"
+            ),
+            StoreError::Truncated { stream_id, first } => write!(
+                f,
+                "stream {stream_id} was truncated, its first event is at version {first}"
+            ),
+            StoreError::Archived(receipt) => write!(
+                f,
+                "stream {} was archived at version {}",
+                receipt.stream_id, receipt.version
+            ),
+            StoreError::Codec(reason) => write!(f, "cannot encode or decode a payload: {reason}"),
+            StoreError::Io(reason) => write!(f, "store failed: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+/// The events to append to a single stream, as given to [`EventStore::append_multi`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamAppend<'a, E> {
+    /// The id of the stream to append to.
+    pub stream_id: &'a str,
+    /// The version the stream must currently be at.
+    pub expected: StreamVersion,
+    /// The events to append, in order.
+    pub events: &'a [E],
+}
+
+/// A trait for storing the events of streams, which is the durable data a decider's state is
+/// rebuilt from.
+///
+/// The version of a stream is the number of events appended to it, so it is 0 before anything
+/// has been appended. A store rejects an append made against a version that is not the stream's
+/// current version with a [`StoreError::Conflict`].
+///
+/// The oldest events of a stream can be removed with [`EventStore::truncate_before`], such as by
+/// [`apply_retention`], which leaves the versions of the remaining events unchanged. Loading
+/// from before the first remaining event returns a [`StoreError::Truncated`], so a truncated
+/// stream can only be rebuilt from a snapshot and the events after it.
+///
+/// A whole stream can be moved out of the store with [`EventStore::archive`], such as by
+/// `archive_stream`. Every later load, append or version of the stream then returns a
+/// [`StoreError::Archived`] with the receipt the store keeps in its place, until the stream is
+/// unarchived.
+pub trait EventStore<E> {
+    /// Returns the events of the stream `stream_id` from the version `from` onwards, in order,
+    /// which is empty if nothing has been appended past it.
+    fn load_from(&self, stream_id: &str, from: StreamVersion) -> Result<Vec<E>, StoreError>;
+
+    /// Returns every event of the stream `stream_id` in order, which is empty if nothing has
+    /// been appended to it.
+    fn load(&self, stream_id: &str) -> Result<Vec<E>, StoreError> {
+        self.load_from(stream_id, StreamVersion::INITIAL)
+    }
+
+    /// Returns the current version of the stream `stream_id`.
+    fn version(&self, stream_id: &str) -> Result<StreamVersion, StoreError>;
+
+    /// Returns the version of the first event the stream `stream_id` still holds, which is 0
+    /// unless it has been truncated.
+    fn first_version(&self, stream_id: &str) -> Result<StreamVersion, StoreError>;
+
+    /// Removes the events of the stream `stream_id` before the version `before`, which is
+    /// capped at the stream's current version, and returns how many were removed.
+    fn truncate_before(
+        &mut self,
+        stream_id: &str,
+        before: StreamVersion,
+    ) -> Result<u64, StoreError>;
+
+    /// Removes every event of the stream named by `receipt`, keeping `receipt` in its place.
+    ///
+    /// Returns [`StoreError::Conflict`] if the stream is not at the version of the receipt, as
+    /// when events were appended after it was written.
+    fn archive(&mut self, receipt: &ArchiveReceipt) -> Result<(), StoreError>;
+
+    /// Removes the receipt kept in place of the archived stream `stream_id`, returning it, or
+    /// `None` if the stream is not archived. The stream is then empty, at version 0.
+    fn unarchive(&mut self, stream_id: &str) -> Result<Option<ArchiveReceipt>, StoreError>;
+
+    /// Returns the ids of every stream events have been appended to and that is not archived,
+    /// in order.
+    fn stream_ids(&self) -> Result<Vec<String>, StoreError>;
+
+    /// Appends the events of every append in `appends` to its stream in a single transaction,
+    /// and returns the new version of each stream, in order.
+    ///
+    /// Either every append succeeds or none of them does: if any stream is not at the version
+    /// its append expects, nothing is appended and the conflict of the first such stream is
+    /// returned. A stream may appear in several appends, each expecting the version left by the
+    /// previous one.
+    fn append_multi(
+        &mut self,
+        appends: &[StreamAppend<'_, E>],
+    ) -> Result<Vec<StreamVersion>, StoreError>;
+
+    /// Appends `events` to the stream `stream_id`, whose current version must be `expected`,
+    /// and returns the new version of the stream.
+    fn append(
+        &mut self,
+        stream_id: &str,
+        expected: StreamVersion,
+        events: &[E],
+    ) -> Result<StreamVersion, StoreError> {
+        let versions = self.append_multi(&[StreamAppend {
+            stream_id,
+            expected,
+            events,
+        }])?;
+        Ok(versions[0])
+    }
+}
+
+/// Checks each append of `appends` against the current versions given by `version`, returning
+/// the version each stream is left at after every append, in order.
+///
+/// This is the conflict check of [`EventStore::append_multi`], shared by the stores so that a
+/// stream appearing in several appends is checked the same way everywhere.
+pub(crate) fn check_appends<E>(
+    appends: &[StreamAppend<'_, E>],
+    mut version: impl FnMut(&str) -> Result<StreamVersion, StoreError>,
+) -> Result<Vec<StreamVersion>, StoreError> {
+    let mut current: HashMap<&str, StreamVersion> = HashMap::new();
+    let mut versions = Vec::with_capacity(appends.len());
+    for append in appends {
+        let stored = match current.get(append.stream_id) {
+            Some(stored) => *stored,
+            None => version(append.stream_id)?,
+        };
+        if append.expected != stored {
+            return Err(StoreError::Conflict {
+                stream_id: append.stream_id.to_string(),
+                expected: append.expected,
+                stored,
+            });
+        }
+        let appended = stored + append.events.len() as u64;
+        current.insert(append.stream_id, appended);
+        versions.push(appended);
+    }
+    Ok(versions)
+}
+
+/// Checks that a stream at the version `stored` can be archived with `receipt`, as
+/// [`EventStore::archive`] does.
+pub(crate) fn check_archive(
+    receipt: &ArchiveReceipt,
+    stored: StreamVersion,
+) -> Result<(), StoreError> {
+    if receipt.version != stored {
+        return Err(StoreError::Conflict {
+            stream_id: receipt.stream_id.clone(),
+            expected: receipt.version,
+            stored,
+        });
+    }
+    Ok(())
+}
+
+/// The result of applying an event delivered by another system with
+/// [`EventSourcedRunner::apply_external`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ApplyResult {
+    /// The event was appended and evolved, leaving the stream at the given version.
+    Applied(StreamVersion),
+    /// An event with the same source and id was already applied, so nothing was done.
+    Duplicate,
+}
+
+/// A trait for event stores that also record which externally delivered events each stream has
+/// received, so that an event redelivered by its source is applied only once.
+///
+/// A received event is identified by its source, such as the name of a message broker, and the
+/// id the source gave it. The record is kept alongside the stream and updated in the same
+/// transaction as the append, so an event is never appended without being recorded.
+pub trait InboxStore<E>: EventStore<E> {
+    /// Returns whether the stream `stream_id` has received the event `external_id` of `source`.
+    fn has_received(
+        &self,
+        stream_id: &str,
+        source: &str,
+        external_id: &str,
+    ) -> Result<bool, StoreError>;
+
+    /// Appends `events` to the stream `stream_id`, whose current version must be `expected`, and
+    /// records that it received the event `external_id` of `source`, unless it already has, in
+    /// which case nothing is appended and [`ApplyResult::Duplicate`] is returned.
+    fn append_received(
+        &mut self,
+        stream_id: &str,
+        expected: StreamVersion,
+        events: &[E],
+        source: &str,
+        external_id: &str,
+    ) -> Result<ApplyResult, StoreError>;
+}
+
+/// A simple [`EventStore`] that keeps streams in memory.
+///
+/// As an [`InboxStore`], it remembers every received event unless its capacity is limited with
+/// [`InMemoryEventStore::with_inbox_capacity`].
+#[derive(Clone, Debug)]
+pub struct InMemoryEventStore<E> {
+    streams: HashMap<String, Stream<E>>,
+    archived: HashMap<String, ArchiveReceipt>,
+    inbox: HashMap<String, VecDeque<(String, String)>>,
+    inbox_capacity: Option<usize>,
+}
+
+impl<E> InMemoryEventStore<E> {
+    /// Constructs a new, empty `InMemoryEventStore`.
+    pub fn new() -> Self {
+        Self {
+            streams: HashMap::new(),
+            archived: HashMap::new(),
+            inbox: HashMap::new(),
+            inbox_capacity: None,
+        }
+    }
+
+    /// Returns this store, remembering at most `capacity` received events per stream. Once a
+    /// stream's inbox is full, receiving an event forgets the oldest one, so a redelivery of it
+    /// would be applied again.
+    pub fn with_inbox_capacity(self, capacity: usize) -> Self {
+        Self {
+            inbox_capacity: Some(capacity),
+            ..self
+        }
+    }
+
+    fn stored_version(&self, stream_id: &str) -> Result<StreamVersion, StoreError> {
+        self.check_archived(stream_id)?;
+        Ok(StreamVersion::from(
+            self.streams
+                .get(stream_id)
+                .map_or(0, |stream| stream.first + stream.events.len() as u64),
+        ))
+    }
+
+    fn check_archived(&self, stream_id: &str) -> Result<(), StoreError> {
+        match self.archived.get(stream_id) {
+            Some(receipt) => Err(StoreError::Archived(receipt.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The events an [`InMemoryEventStore`] holds for a stream.
+#[derive(Clone, Debug)]
+struct Stream<E> {
+    /// The version of the first event held, which is past 0 once the stream is truncated.
+    first: u64,
+    events: Vec<E>,
+}
+
+impl<E> Default for InMemoryEventStore<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E> EventStore<E> for InMemoryEventStore<E>
+where
+    E: Clone,
+{
+    fn load_from(&self, stream_id: &str, from: StreamVersion) -> Result<Vec<E>, StoreError> {
+        self.check_archived(stream_id)?;
+        let Some(stream) = self.streams.get(stream_id) else {
+            return Ok(vec![]);
+        };
+        let Some(from) = u64::from(from).checked_sub(stream.first) else {
+            return Err(StoreError::Truncated {
+                stream_id: stream_id.to_string(),
+                first: StreamVersion::from(stream.first),
+            });
+        };
+        let from = usize::try_from(from).unwrap_or(usize::MAX);
+        Ok(stream
+            .events
+            .get(from..)
+            .map(<[E]>::to_vec)
+            .unwrap_or_default())
+    }
+
+    fn version(&self, stream_id: &str) -> Result<StreamVersion, StoreError> {
+        self.stored_version(stream_id)
+    }
+
+    fn first_version(&self, stream_id: &str) -> Result<StreamVersion, StoreError> {
+        self.check_archived(stream_id)?;
+        Ok(StreamVersion::from(
+            self.streams.get(stream_id).map_or(0, |stream| stream.first),
+        ))
+    }
+
+    fn truncate_before(
+        &mut self,
+        stream_id: &str,
+        before: StreamVersion,
+    ) -> Result<u64, StoreError> {
+        self.check_archived(stream_id)?;
+        let Some(stream) = self.streams.get_mut(stream_id) else {
+            return Ok(0);
+        };
+        let removed = u64::from(before)
+            .saturating_sub(stream.first)
+            .min(stream.events.len() as u64);
+        stream.events.drain(..removed as usize);
+        stream.first += removed;
+        Ok(removed)
+    }
+
+    fn archive(&mut self, receipt: &ArchiveReceipt) -> Result<(), StoreError> {
+        check_archive(receipt, self.stored_version(&receipt.stream_id)?)?;
+        self.streams.remove(&receipt.stream_id);
+        self.archived
+            .insert(receipt.stream_id.clone(), receipt.clone());
+        Ok(())
+    }
+
+    fn unarchive(&mut self, stream_id: &str) -> Result<Option<ArchiveReceipt>, StoreError> {
+        Ok(self.archived.remove(stream_id))
+    }
+
+    fn stream_ids(&self) -> Result<Vec<String>, StoreError> {
+        let mut ids: Vec<String> = self.streams.keys().cloned().collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn append_multi(
+        &mut self,
+        appends: &[StreamAppend<'_, E>],
+    ) -> Result<Vec<StreamVersion>, StoreError> {
+        let versions = check_appends(appends, |stream_id| self.stored_version(stream_id))?;
+        for append in appends {
+            self.streams
+                .entry(append.stream_id.to_string())
+                .or_insert_with(|| Stream {
+                    first: 0,
+                    events: vec![],
+                })
+                .events
+                .extend(append.events.iter().cloned());
+        }
+        Ok(versions)
+    }
+}
+
+impl<E> InboxStore<E> for InMemoryEventStore<E>
+where
+    E: Clone,
+{
+    fn has_received(
+        &self,
+        stream_id: &str,
+        source: &str,
+        external_id: &str,
+    ) -> Result<bool, StoreError> {
+        Ok(self.inbox.get(stream_id).is_some_and(|received| {
+            received
+                .iter()
+                .any(|(s, id)| s == source && id == external_id)
+        }))
+    }
+
+    fn append_received(
+        &mut self,
+        stream_id: &str,
+        expected: StreamVersion,
+        events: &[E],
+        source: &str,
+        external_id: &str,
+    ) -> Result<ApplyResult, StoreError> {
+        if self.has_received(stream_id, source, external_id)? {
+            return Ok(ApplyResult::Duplicate);
+        }
+        let version = self.append(stream_id, expected, events)?;
+        let received = self.inbox.entry(stream_id.to_string()).or_default();
+        received.push_back((source.to_string(), external_id.to_string()));
+        if let Some(capacity) = self.inbox_capacity {
+            while received.len() > capacity {
+                received.pop_front();
+            }
+        }
+        Ok(ApplyResult::Applied(version))
+    }
+}
+
+/// A trait for storing the latest [`Snapshot`] of each stream, so that a state can be rebuilt
+/// without replaying its whole stream.
+pub trait SnapshotStore<S> {
+    /// Returns the last snapshot saved under `key`, if there is one.
+    fn load(&self, key: &str) -> Result<Option<Snapshot<S>>, StoreError>;
+
+    /// Saves the snapshot `snapshot` under `key`, replacing any previous snapshot.
+    fn save(&mut self, key: &str, snapshot: &Snapshot<S>) -> Result<(), StoreError>;
+}
+
+/// A simple [`SnapshotStore`] that keeps snapshots in memory.
+#[derive(Clone, Debug)]
+pub struct InMemorySnapshotStore<S> {
+    snapshots: HashMap<String, Snapshot<S>>,
+}
+
+impl<S> InMemorySnapshotStore<S> {
+    /// Constructs a new, empty `InMemorySnapshotStore`.
+    pub fn new() -> Self {
+        Self {
+            snapshots: HashMap::new(),
+        }
+    }
+}
+
+impl<S> Default for InMemorySnapshotStore<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> SnapshotStore<S> for InMemorySnapshotStore<S>
+where
+    S: Clone,
+{
+    fn load(&self, key: &str) -> Result<Option<Snapshot<S>>, StoreError> {
+        Ok(self.snapshots.get(key).cloned())
+    }
+
+    fn save(&mut self, key: &str, snapshot: &Snapshot<S>) -> Result<(), StoreError> {
+        self.snapshots.insert(key.to_string(), snapshot.clone());
+        Ok(())
+    }
+}
+
+/// The snapshot of a single key of a keyed collection, as saved to a [`KeyedSnapshotStore`].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeySnapshot<S> {
+    /// The version of the schema the snapshot was written with.
+    pub schema_version: u32,
+    /// The version of the collection's stream the snapshot was taken at.
+    pub taken_at_version: StreamVersion,
+    /// The snapshot of the key's state, or `None` for a tombstone, left when the key was pruned
+    /// so that older snapshots and events of the key are not loaded again.
+    pub state: Option<S>,
+}
+
+/// A trait for storing a snapshot of each key of a keyed collection separately, so that saving
+/// the collection only writes the keys that changed.
+///
+/// The snapshots of a collection are grouped under the prefix `stream_prefix`, such as the id of
+/// the collection's stream.
+pub trait KeyedSnapshotStore<K, S> {
+    /// Saves the snapshot `snapshot` of the key `key`, replacing any previous one.
+    fn save_key(
+        &mut self,
+        stream_prefix: &str,
+        key: &K,
+        snapshot: &KeySnapshot<S>,
+    ) -> Result<(), StoreError>;
+
+    /// Returns the latest snapshot of every key saved under `stream_prefix`, including
+    /// tombstones.
+    fn load_all(&self, stream_prefix: &str) -> Result<HashMap<K, KeySnapshot<S>>, StoreError>;
+}
+
+/// A simple [`KeyedSnapshotStore`] that keeps snapshots in memory, counting how many it has
+/// written.
+#[derive(Clone, Debug)]
+pub struct InMemoryKeyedSnapshotStore<K, S> {
+    snapshots: HashMap<String, HashMap<K, KeySnapshot<S>>>,
+    writes: u64,
+}
+
+impl<K, S> InMemoryKeyedSnapshotStore<K, S> {
+    /// Constructs a new, empty `InMemoryKeyedSnapshotStore`.
+    pub fn new() -> Self {
+        Self {
+            snapshots: HashMap::new(),
+            writes: 0,
+        }
+    }
+
+    /// Returns the number of snapshots written to this store, including tombstones.
+    pub fn writes(&self) -> u64 {
+        self.writes
+    }
+}
+
+impl<K, S> Default for InMemoryKeyedSnapshotStore<K, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, S> KeyedSnapshotStore<K, S> for InMemoryKeyedSnapshotStore<K, S>
+where
+    K: Clone + Eq + Hash,
+    S: Clone,
+{
+    fn save_key(
+        &mut self,
+        stream_prefix: &str,
+        key: &K,
+        snapshot: &KeySnapshot<S>,
+    ) -> Result<(), StoreError> {
+        self.snapshots
+            .entry(stream_prefix.to_string())
+            .or_default()
+            .insert(key.clone(), snapshot.clone());
+        self.writes += 1;
+        Ok(())
+    }
+
+    fn load_all(&self, stream_prefix: &str) -> Result<HashMap<K, KeySnapshot<S>>, StoreError> {
+        Ok(self
+            .snapshots
+            .get(stream_prefix)
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+/// A limit on the age of the events kept by a [`RetentionPolicy`].
+///
+/// Events carry no time of their own, so their timestamps are read with `timestamp`, such as
+/// from a field of the event.
+#[derive(Debug)]
+pub struct MaxAge<E> {
+    /// The greatest age an event is kept at, in the same unit as its timestamps.
+    pub max_age: u64,
+    /// The current time, which the age of every event is measured from.
+    pub now: u64,
+    /// Returns the time the event was recorded at, or `None` if it has no timestamp, in which
+    /// case it is never too old.
+    pub timestamp: fn(&E) -> Option<u64>,
+}
+
+impl<E> MaxAge<E> {
+    fn is_expired(&self, event: &E) -> bool {
+        (self.timestamp)(event).is_some_and(|at| self.now.saturating_sub(at) > self.max_age)
+    }
+}
+
+/// Which events [`apply_retention`] removes from the streams of an [`EventStore`].
+///
+/// An event is kept only if it is within both limits, so with both set, a stream keeps at most
+/// `max_events` events, none of them older than `max_age`. Since a stream can only be truncated
+/// from its start, an event too old to keep is still kept if an event before it is not, as when
+/// events are not in timestamp order.
+#[derive(Debug)]
+pub struct RetentionPolicy<E> {
+    /// The greatest number of events each stream keeps, or `None` for no limit.
+    pub max_events: Option<u64>,
+    /// The greatest age of the events each stream keeps, or `None` for no limit.
+    pub max_age: Option<MaxAge<E>>,
+    /// Whether to keep every event after the latest snapshot of each stream, whatever the
+    /// limits are, so that the stream can still be rebuilt from the snapshot. A stream without
+    /// a snapshot is then never truncated.
+    pub respect_snapshot_barrier: bool,
+    /// Whether to only report what would be removed, leaving the store untouched.
+    pub dry_run: bool,
+}
+
+/// What [`apply_retention`] removed from a stream, or would remove in a dry run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamRetention {
+    /// The id of the stream.
+    pub stream_id: String,
+    /// The version of the first event the stream held before retention was applied.
+    pub first: StreamVersion,
+    /// The version of the first event the stream keeps.
+    pub kept_from: StreamVersion,
+    /// Whether the stream's snapshot kept events the limits of the policy would have removed.
+    pub held_by_snapshot: bool,
+}
+
+impl StreamRetention {
+    /// Returns the number of events removed from the stream.
+    pub fn removed(&self) -> u64 {
+        self.kept_from - self.first
+    }
+}
+
+/// The result of [`apply_retention`], with an entry for every stream of the store, in order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetentionReport {
+    /// What was removed from each stream.
+    pub streams: Vec<StreamRetention>,
+    /// Whether this was a dry run, so nothing was actually removed.
+    pub dry_run: bool,
+}
+
+impl RetentionReport {
+    /// Returns the number of events removed from every stream.
+    pub fn removed(&self) -> u64 {
+        self.streams.iter().map(StreamRetention::removed).sum()
+    }
+}
+
+/// Removes the events of every stream of `store` that `policy` does not keep, with
+/// [`EventStore::truncate_before`], and reports what was removed.
+///
+/// The snapshot of each stream is looked up in `snapshots` under the stream's id. With
+/// [`RetentionPolicy::respect_snapshot_barrier`] set, no event after a stream's latest snapshot
+/// is removed, so its state can still be rebuilt from the snapshot and the events after it. A
+/// stream is truncated at most once, so if it fails, the streams before it stay truncated.
+pub fn apply_retention<E, S, ES, SS>(
+    store: &mut ES,
+    snapshots: &SS,
+    policy: &RetentionPolicy<E>,
+) -> Result<RetentionReport, StoreError>
+where
+    ES: EventStore<E>,
+    SS: SnapshotStore<S>,
+{
+    let mut streams = vec![];
+    for stream_id in store.stream_ids()? {
+        let first = store.first_version(&stream_id)?;
+        let version = store.version(&stream_id)?;
+        let mut kept_from = first;
+        if let Some(max_events) = policy.max_events {
+            let oldest = StreamVersion::from(u64::from(version).saturating_sub(max_events));
+            kept_from = kept_from.max(oldest);
+        }
+        if let Some(max_age) = &policy.max_age {
+            let expired = store
+                .load_from(&stream_id, first)?
+                .iter()
+                .take_while(|event| max_age.is_expired(event))
+                .count();
+            kept_from = kept_from.max(first + expired as u64);
+        }
+        let mut held_by_snapshot = false;
+        if policy.respect_snapshot_barrier {
+            let barrier = snapshots.load(&stream_id)?.map_or(first, |snapshot| {
+                snapshot.taken_at_version.clamp(first, version)
+            });
+            if barrier < kept_from {
+                kept_from = barrier;
+                held_by_snapshot = true;
+            }
+        }
+        if !policy.dry_run && kept_from > first {
+            store.truncate_before(&stream_id, kept_from)?;
+        }
+        streams.push(StreamRetention {
+            stream_id,
+            first,
+            kept_from,
+            held_by_snapshot,
+        });
+    }
+    Ok(RetentionReport {
+        streams,
+        dry_run: policy.dry_run,
+    })
+}
+
+/// A trait for turning events or snapshots of type `T` into bytes and back, so that stores
+/// persisting bytes, such as `SqliteEventStore`, work with any encoding.
+///
+/// Decoding bytes that were not produced by `encode` must return a [`StoreError::Codec`] rather
+/// than panic, since stored bytes may be corrupt.
+pub trait EventCodec<T> {
+    /// Encodes `value` into bytes.
+    fn encode(&self, value: &T) -> Result<Vec<u8>, StoreError>;
+
+    /// Decodes a value from `bytes`.
+    fn decode(&self, bytes: &[u8]) -> Result<T, StoreError>;
+}
+
+/// An [`EventCodec`] encoding values as JSON.
+///
+/// Requires the `serde` feature.
+#[cfg(feature = "serde")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct JsonCodec;
+
+#[cfg(feature = "serde")]
+impl<T> EventCodec<T> for JsonCodec
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    fn encode(&self, value: &T) -> Result<Vec<u8>, StoreError> {
+        serde_json::to_vec(value).map_err(|e| StoreError::Codec(e.to_string()))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<T, StoreError> {
+        serde_json::from_slice(bytes).map_err(|e| StoreError::Codec(e.to_string()))
+    }
+}
+
+/// The header of a payload compressed by a [`CompressingCodec`], followed by the id of the
+/// compression. It starts with a zero byte, which no JSON payload does.
+#[cfg(feature = "zstd")]
+const COMPRESSED_HEADER: [u8; 3] = [0, b'D', b'C'];
+
+/// The compression id of zstd in the header of a compressed payload.
+#[cfg(feature = "zstd")]
+const ZSTD: u8 = 1;
+
+/// An [`EventCodec`] compressing the payloads of another codec with zstd, such as to store
+/// large snapshots or verbose JSON events in less space.
+///
+/// Each compressed payload starts with a short header recording the compression used. Payloads
+/// without it are decoded by the inner codec as they are, so a store can switch to compression
+/// while still reading what it wrote before, as long as none of the inner codec's payloads
+/// starts with the header, as no JSON payload does.
+///
+/// Requires the `zstd` feature.
+#[cfg(feature = "zstd")]
+#[derive(Copy, Clone, Debug)]
+pub struct CompressingCodec<Inner> {
+    inner: Inner,
+    level: i32,
+}
+
+#[cfg(feature = "zstd")]
+impl<Inner> CompressingCodec<Inner> {
+    /// Constructs a new `CompressingCodec` compressing the payloads of `inner` with zstd's
+    /// default level.
+    pub fn new(inner: Inner) -> Self {
+        Self::with_level(inner, zstd::DEFAULT_COMPRESSION_LEVEL)
+    }
+
+    /// Constructs a new `CompressingCodec` compressing the payloads of `inner` with the zstd
+    /// level `level`, from 1 for the fastest to 22 for the smallest.
+    pub fn with_level(inner: Inner, level: i32) -> Self {
+        Self { inner, level }
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<Inner> Default for CompressingCodec<Inner>
+where
+    Inner: Default,
+{
+    fn default() -> Self {
+        Self::new(Inner::default())
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<T, Inner> EventCodec<T> for CompressingCodec<Inner>
+where
+    Inner: EventCodec<T>,
+{
+    fn encode(&self, value: &T) -> Result<Vec<u8>, StoreError> {
+        let payload = self.inner.encode(value)?;
+        let compressed = zstd::encode_all(payload.as_slice(), self.level)
+            .map_err(|e| StoreError::Codec(e.to_string()))?;
+        let mut bytes = Vec::with_capacity(COMPRESSED_HEADER.len() + 1 + compressed.len());
+        bytes.extend_from_slice(&COMPRESSED_HEADER);
+        bytes.push(ZSTD);
+        bytes.extend_from_slice(&compressed);
+        Ok(bytes)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<T, StoreError> {
+        let Some(rest) = bytes.strip_prefix(&COMPRESSED_HEADER) else {
+            return self.inner.decode(bytes);
+        };
+        match rest.split_first() {
+            Some((&ZSTD, compressed)) => {
+                let payload =
+                    zstd::decode_all(compressed).map_err(|e| StoreError::Codec(e.to_string()))?;
+                self.inner.decode(&payload)
+            }
+            Some((id, _)) => Err(StoreError::Codec(format!("unknown compression {id}"))),
+            None => Err(StoreError::Codec(
+                "compressed payload has no compression id".to_string(),
+            )),
+        }
+    }
+}
+
+/// The result of checking the stored [`Snapshot`] of a stream against a full replay of the
+/// stream, returned by [`verify_snapshot`] and the functions like it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VerificationReport {
+    /// Restoring the snapshot and evolving the rest of the stream gives the same state as
+    /// replaying the stream from the start, at the snapshot's version and after every later
+    /// event.
+    Consistent,
+    /// The restored and replayed states differ after `applied` events of the stream. If
+    /// `applied` is the version the snapshot was taken at, the snapshot itself is wrong.
+    Diverged {
+        /// The number of events of the stream applied to both states.
+        applied: usize,
+    },
+    /// The snapshot was taken at a version past the end of the stream.
+    AheadOfStream {
+        /// The number of events in the stream.
+        len: usize,
+    },
+    /// The stream has no snapshot to check.
+    NoSnapshot,
+}
+
+impl VerificationReport {
+    /// Returns whether the snapshot agrees with a full replay, which holds when there is no
+    /// snapshot.
+    pub fn is_consistent(&self) -> bool {
+        matches!(
+            self,
+            VerificationReport::Consistent | VerificationReport::NoSnapshot
+        )
+    }
+}
+
+/// Checks that restoring the snapshot of the stream `stream_id` in `snapshots` and evolving the
+/// events of the stream in `events` that follow it gives the same states as replaying the whole
+/// stream from the start.
+///
+/// Both are folded in a single pass, and the first number of events after which they differ is
+/// reported, so that a bug in `evolve` or in a snapshot migration is found before the snapshot
+/// is used.
+pub fn verify_snapshot<C, E, S, D, ES, SS>(
+    events: &ES,
+    snapshots: &SS,
+    stream_id: &str,
+) -> Result<VerificationReport, StoreError>
+where
+    S: PartialEq,
+    D: Decider<C, E, S, S> + Snapshotting<S>,
+    ES: EventStore<E>,
+    SS: SnapshotStore<D::Snapshot>,
+{
+    verify::<C, E, S, D, ES, SS>(events, snapshots, stream_id, |replayed, restored| {
+        replayed == restored
+    })
+}
+
+/// Checks the snapshot of a stream like [`verify_snapshot`], comparing the states by their
+/// [`StateFingerprint`], for states that cannot be compared for equality.
+pub fn verify_snapshot_fingerprints<C, E, S, D, ES, SS>(
+    events: &ES,
+    snapshots: &SS,
+    stream_id: &str,
+) -> Result<VerificationReport, StoreError>
+where
+    S: StateFingerprint,
+    D: Decider<C, E, S, S> + Snapshotting<S>,
+    ES: EventStore<E>,
+    SS: SnapshotStore<D::Snapshot>,
+{
+    verify::<C, E, S, D, ES, SS>(events, snapshots, stream_id, |replayed, restored| {
+        replayed.fingerprint() == restored.fingerprint()
+    })
+}
+
+/// Checks the snapshot of every stream of `events` like [`verify_snapshot`], returning the
+/// report of each stream along with its id, in order.
+pub fn verify_snapshots<C, E, S, D, ES, SS>(
+    events: &ES,
+    snapshots: &SS,
+) -> Result<Vec<(String, VerificationReport)>, StoreError>
+where
+    S: PartialEq,
+    D: Decider<C, E, S, S> + Snapshotting<S>,
+    ES: EventStore<E>,
+    SS: SnapshotStore<D::Snapshot>,
+{
+    events
+        .stream_ids()?
+        .into_iter()
+        .map(|stream_id| {
+            let report = verify_snapshot::<C, E, S, D, ES, SS>(events, snapshots, &stream_id)?;
+            Ok((stream_id, report))
+        })
+        .collect()
+}
+
+/// Replays the stream `stream_id` and restores its snapshot with the rest of the stream,
+/// checking the states with `equiv` at the snapshot's version and after every later event.
+fn verify<C, E, S, D, ES, SS>(
+    events: &ES,
+    snapshots: &SS,
+    stream_id: &str,
+    equiv: impl Fn(&S, &S) -> bool,
+) -> Result<VerificationReport, StoreError>
+where
+    D: Decider<C, E, S, S> + Snapshotting<S>,
+    ES: EventStore<E>,
+    SS: SnapshotStore<D::Snapshot>,
+{
+    let Some(snapshot) = snapshots.load(stream_id)? else {
+        return Ok(VerificationReport::NoSnapshot);
+    };
+    let events = events.load(stream_id)?;
+    let Some(taken_at) = usize::try_from(u64::from(snapshot.taken_at_version))
+        .ok()
+        .filter(|taken_at| *taken_at <= events.len())
+    else {
+        return Ok(VerificationReport::AheadOfStream { len: events.len() });
+    };
+    let (head, tail) = events.split_at(taken_at);
+    let mut replayed = D::fold(head);
+    let mut restored = D::from_snapshot(&snapshot.state);
+    if !equiv(&replayed, &restored) {
+        return Ok(VerificationReport::Diverged { applied: taken_at });
+    }
+    for (applied, event) in (taken_at + 1..).zip(tail) {
+        D::evolve_in_place(&mut replayed, event);
+        D::evolve_in_place(&mut restored, event);
+        if !equiv(&replayed, &restored) {
+            return Ok(VerificationReport::Diverged { applied });
+        }
+    }
+    Ok(VerificationReport::Consistent)
+}
+
+/// How [`migrate_stream`] and [`migrate_streams`] migrate a stream whose states are of type `SO`
+/// in the old model and `SN` in the new one.
+#[derive(Debug)]
+pub struct MigrationOptions<SO, SN> {
+    /// Returns whether a state of the old model and a state of the new one are the same, which
+    /// the migration is checked with after every event of the stream.
+    pub equivalent: fn(&SO, &SN) -> bool,
+    /// Whether to only report how each stream would be migrated, leaving the target store
+    /// untouched.
+    pub dry_run: bool,
+}
+
+/// How a stream was migrated by [`migrate_stream`], or would be in a dry run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MigrationReport {
+    /// The id of the stream.
+    pub stream_id: String,
+    /// The number of events of the old model read from the source store.
+    pub read: u64,
+    /// The number of events of the new model the stream was upcast to.
+    pub upcast: u64,
+    /// The versions of the events of the old model the upcaster could not map to the new one.
+    pub unmappable: Vec<StreamVersion>,
+    /// The number of events of the old model after which the states of both models first
+    /// stopped being equivalent, or `None` if they always were.
+    pub divergence: Option<u64>,
+    /// Whether the upcast events were written to the target store, which they only are if the
+    /// migration succeeded and is not a dry run.
+    pub written: bool,
+}
+
+impl MigrationReport {
+    /// Returns whether every event could be upcast and the states of both models stayed
+    /// equivalent throughout the stream.
+    pub fn is_successful(&self) -> bool {
+        self.unmappable.is_empty() && self.divergence.is_none()
+    }
+}
+
+/// The progress of [`migrate_streams`], given to its callback after each stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MigrationProgress<'a> {
+    /// How the latest stream was migrated.
+    pub report: &'a MigrationReport,
+    /// The number of streams migrated so far, including the latest one.
+    pub migrated: usize,
+    /// The number of streams to migrate.
+    pub total: usize,
+}
+
+/// Migrates the stream `stream_id` of `source`, whose events are of the old model decided by
+/// `DO`, to the same stream of `target`, whose events are of the new model decided by `DN`.
+///
+/// Every event is upcast with `U`, which returns the events of the new model it was renamed,
+/// split or folded into, in order, or `None` if it has no counterpart in the new model. Both
+/// streams are folded with their deciders as they are read, and the states are checked with
+/// [`MigrationOptions::equivalent`] before the first event and after every event of the old
+/// model. The upcast events are written to `target` only if every event could be upcast and the
+/// states stayed equivalent, so a failed migration writes nothing.
+///
+/// Returns [`StoreError::Conflict`] if the stream of `target` is not empty.
+pub fn migrate_stream<CO, EO, SO, DO, CN, EN, SN, DN, U, ESO, ESN>(
+    source: &ESO,
+    target: &mut ESN,
+    stream_id: &str,
+    options: &MigrationOptions<SO, SN>,
+) -> Result<MigrationReport, StoreError>
+where
+    DO: Decider<CO, EO, SO, SO>,
+    DN: Decider<CN, EN, SN, SN>,
+    U: EventUpcaster<EO, Option<Vec<EN>>>,
+    ESO: EventStore<EO>,
+    ESN: EventStore<EN>,
+{
+    let old_events = source.load(stream_id)?;
+    let mut new_events = vec![];
+    let mut unmappable = vec![];
+    let mut old_state = DO::initial_state();
+    let mut new_state = DN::initial_state();
+    let mut divergence = (!(options.equivalent)(&old_state, &new_state)).then_some(0);
+    for (read, event) in (1..).zip(&old_events) {
+        DO::evolve_in_place(&mut old_state, event);
+        match U::upcast(event) {
+            Some(upcast) => {
+                evolve_all_in_place(&mut new_state, &upcast, DN::evolve_in_place);
+                new_events.extend(upcast);
+            }
+            None => unmappable.push(StreamVersion::from(read)),
+        }
+        if divergence.is_none() && !(options.equivalent)(&old_state, &new_state) {
+            divergence = Some(read);
+        }
+    }
+    let mut report = MigrationReport {
+        stream_id: stream_id.to_string(),
+        read: old_events.len() as u64,
+        upcast: new_events.len() as u64,
+        unmappable,
+        divergence,
+        written: false,
+    };
+    if report.is_successful() && !options.dry_run {
+        target.append(stream_id, StreamVersion::INITIAL, &new_events)?;
+        report.written = true;
+    }
+    Ok(report)
+}
+
+/// Migrates every stream of `source` like [`migrate_stream`], calling `progress` after each one,
+/// and returns the report of each stream, in order.
+///
+/// A stream that fails to migrate does not stop the others, but a store error does, leaving the
+/// streams before it migrated.
+pub fn migrate_streams<CO, EO, SO, DO, CN, EN, SN, DN, U, ESO, ESN>(
+    source: &ESO,
+    target: &mut ESN,
+    options: &MigrationOptions<SO, SN>,
+    mut progress: impl FnMut(&MigrationProgress),
+) -> Result<Vec<MigrationReport>, StoreError>
+where
+    DO: Decider<CO, EO, SO, SO>,
+    DN: Decider<CN, EN, SN, SN>,
+    U: EventUpcaster<EO, Option<Vec<EN>>>,
+    ESO: EventStore<EO>,
+    ESN: EventStore<EN>,
+{
+    let stream_ids = source.stream_ids()?;
+    let mut reports = Vec::with_capacity(stream_ids.len());
+    for stream_id in &stream_ids {
+        let report = migrate_stream::<CO, EO, SO, DO, CN, EN, SN, DN, U, ESO, ESN>(
+            source, target, stream_id, options,
+        )?;
+        progress(&MigrationProgress {
+            report: &report,
+            migrated: reports.len() + 1,
+            total: stream_ids.len(),
+        });
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+/// The record an archived stream leaves in its [`EventStore`], returned by `archive_stream`
+/// and given back by every later load of the stream as a [`StoreError::Archived`].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArchiveReceipt {
+    /// The id of the archived stream.
+    pub stream_id: String,
+    /// The version the stream was archived at, which is the number of events in its archive.
+    pub version: StreamVersion,
+    /// The version the archived snapshot of the stream was taken at, if it had one.
+    pub snapshot_version: Option<StreamVersion>,
+}
+
+/// A stream exported by [`archive_stream`], along with its latest snapshot, as it is written to
+/// an archive and read back by [`restore_from_archive`].
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct StreamArchive<E, S> {
+    /// The record of the archive, which the store keeps in place of the stream.
+    pub receipt: ArchiveReceipt,
+    /// Every event of the stream, in order.
+    pub events: Vec<E>,
+    /// The latest snapshot of the stream, if it had one.
+    pub snapshot: Option<Snapshot<S>>,
+}
+
+/// Writes `archive` to `sink` as JSON.
+#[cfg(feature = "serde")]
+fn write_archive<E, S>(
+    mut sink: impl std::io::Write,
+    archive: &StreamArchive<E, S>,
+) -> Result<(), StoreError>
+where
+    E: serde::Serialize,
+    S: serde::Serialize,
+{
+    serde_json::to_writer(&mut sink, archive).map_err(|e| StoreError::Codec(e.to_string()))?;
+    sink.flush().map_err(|e| StoreError::Io(e.to_string()))
+}
+
+/// Moves the stream `stream_id` of `store` out of it, writing its events and its latest
+/// snapshot in `snapshots` to `sink` as a [`StreamArchive`], such as to a file in cold storage.
+///
+/// Once the archive is written, the events are removed from `store` with
+/// [`EventStore::archive`], which keeps the returned receipt in their place, so the stream can
+/// still be audited, and loading it returns a [`StoreError::Archived`] until it is restored with
+/// [`restore_from_archive`]. The snapshot is left in `snapshots`. A stream that was truncated
+/// cannot be archived, since its first events are already gone.
+///
+/// Requires the `serde` feature.
+#[cfg(feature = "serde")]
+pub fn archive_stream<E, S, ES, SS>(
+    store: &mut ES,
+    snapshots: &SS,
+    sink: impl std::io::Write,
+    stream_id: &str,
+) -> Result<ArchiveReceipt, StoreError>
+where
+    E: serde::Serialize,
+    S: serde::Serialize,
+    ES: EventStore<E>,
+    SS: SnapshotStore<S>,
+{
+    let version = store.version(stream_id)?;
+    let events = store.load(stream_id)?;
+    let snapshot = snapshots.load(stream_id)?;
+    let archive = StreamArchive {
+        receipt: ArchiveReceipt {
+            stream_id: stream_id.to_string(),
+            version,
+            snapshot_version: snapshot.as_ref().map(|snapshot| snapshot.taken_at_version),
+        },
+        events,
+        snapshot,
+    };
+    write_archive(sink, &archive)?;
+    store.archive(&archive.receipt)?;
+    Ok(archive.receipt)
+}
+
+/// Brings back a stream archived by [`archive_stream`], reading its [`StreamArchive`] from
+/// `reader`, appending its events to `store` and saving its snapshot to `snapshots`.
+///
+/// The stream is unarchived with [`EventStore::unarchive`] first, so it can be restored into
+/// the store it was archived from as well as into any other. Returns [`StoreError::Conflict`]
+/// if the stream already holds events, and [`StoreError::Codec`] if the archive is not a valid
+/// one.
+///
+/// Requires the `serde` feature.
+#[cfg(feature = "serde")]
+pub fn restore_from_archive<E, S, ES, SS>(
+    store: &mut ES,
+    snapshots: &mut SS,
+    reader: impl std::io::Read,
+) -> Result<ArchiveReceipt, StoreError>
+where
+    E: serde::de::DeserializeOwned,
+    S: serde::de::DeserializeOwned,
+    ES: EventStore<E>,
+    SS: SnapshotStore<S>,
+{
+    let archive: StreamArchive<E, S> =
+        serde_json::from_reader(reader).map_err(|e| StoreError::Codec(e.to_string()))?;
+    let receipt = archive.receipt;
+    if archive.events.len() as u64 != u64::from(receipt.version) {
+        return Err(StoreError::Codec(format!(
+            "the archive of stream {} holds {} events, its receipt says {}",
+            receipt.stream_id,
+            archive.events.len(),
+            receipt.version
+        )));
+    }
+    store.unarchive(&receipt.stream_id)?;
+    store.append(&receipt.stream_id, StreamVersion::INITIAL, &archive.events)?;
+    if let Some(snapshot) = &archive.snapshot {
+        snapshots.save(&receipt.stream_id, snapshot)?;
+    }
+    Ok(receipt)
+}
+
+/// When an [`EventSourcedRunner`] saves a snapshot of its state, evaluated after every command
+/// that appends events.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SnapshotPolicy {
+    /// Never save a snapshot.
+    Never,
+    /// Save a snapshot whenever the stream's version passes a multiple of the given number of
+    /// events. A number of 0 never saves one.
+    EveryNEvents(u64),
+    /// Save a snapshot when the state becomes terminal.
+    OnTerminal,
+    /// Save a snapshot when any of the given policies would.
+    Composite(Vec<SnapshotPolicy>),
+}
+
+impl SnapshotPolicy {
+    /// Returns whether a snapshot is due after the stream moved from the version `previous` to
+    /// `version`, leaving the state terminal or not.
+    fn is_due(&self, previous: StreamVersion, version: StreamVersion, terminal: bool) -> bool {
+        match self {
+            SnapshotPolicy::Never => false,
+            SnapshotPolicy::EveryNEvents(0) => false,
+            SnapshotPolicy::EveryNEvents(n) => u64::from(previous) / n != u64::from(version) / n,
+            SnapshotPolicy::OnTerminal => terminal,
+            SnapshotPolicy::Composite(policies) => policies
+                .iter()
+                .any(|policy| policy.is_due(previous, version, terminal)),
+        }
+    }
+}
+
+/// Something that went wrong in an [`EventSourcedRunner`] without failing the command it
+/// happened in.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Diagnostic {
+    /// A snapshot of the stream `stream_id` at the version `version` could not be saved.
+    SnapshotFailed {
+        /// The id of the stream.
+        stream_id: String,
+        /// The version the snapshot was taken at.
+        version: StreamVersion,
+        /// The error of the snapshot store.
+        error: StoreError,
+    },
+}
+
+/// A trait for receiving the [`Diagnostic`]s of an [`EventSourcedRunner`], such as to log them.
+///
+/// A sink is sent along with its runner, such as to the threads of a web server, so it must be
+/// `Send`. It is implemented for every `FnMut(&Diagnostic)` closure that is.
+pub trait DiagnosticSink: Send {
+    /// Reports the diagnostic `diagnostic`.
+    fn report(&mut self, diagnostic: &Diagnostic);
+}
+
+impl<F> DiagnosticSink for F
+where
+    F: FnMut(&Diagnostic) + Send,
+{
+    fn report(&mut self, diagnostic: &Diagnostic) {
+        self(diagnostic)
+    }
+}
+
+/// The snapshot store of an [`EventSourcedRunner`] that does not save snapshots.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoSnapshots;
+
+/// Loads the state of a stream from its latest snapshot, returning it with the version it was
+/// taken at.
+type LoadSnapshot<S, SS> = fn(&SS, &str) -> Result<Option<(S, StreamVersion)>, StoreError>;
+
+/// Saves a snapshot of a state taken at a version of a stream.
+type SaveSnapshot<S, SS> = fn(&mut SS, &str, &S, StreamVersion) -> Result<(), StoreError>;
+
+/// How an [`EventSourcedRunner`] reads and writes the snapshots of its decider, captured when
+/// its snapshot store is set so that the runner itself needs no bounds on the decider's
+/// snapshots.
+struct SnapshotHooks<S, SS> {
+    load: LoadSnapshot<S, SS>,
+    save: SaveSnapshot<S, SS>,
+}
+
+/// A builder for an [`EventSourcedRunner`], returned by [`EventSourcedRunner::builder`].
+///
+/// ```
+/// use deciders_rs::deciders::{Decider, SnapshotSchema, Snapshotting};
+/// use deciders_rs::stores::{
+///     EventSourcedRunner, InMemoryEventStore, InMemorySnapshotStore, SnapshotPolicy,
+///     SnapshotStore,
+/// };
+///
+/// struct Counter;
+///
+/// impl Decider<(), (), u64, u64> for Counter {
+///     fn decide(_: &(), _: &u64) -> Vec<()> {
+///         vec![()]
+///     }
+///
+///     fn evolve(state: &u64, _: &()) -> u64 {
+///         state + 1
+///     }
+///
+///     fn initial_state() -> u64 {
+///         0
+///     }
+///
+///     fn is_terminal(_: &u64) -> bool {
+///         false
+///     }
+/// }
+///
+/// impl Snapshotting<u64> for Counter {
+///     type Snapshot = u64;
+///
+///     fn to_snapshot(state: &u64) -> u64 {
+///         *state
+///     }
+///
+///     fn from_snapshot(snapshot: &u64) -> u64 {
+///         *snapshot
+///     }
+/// }
+///
+/// impl SnapshotSchema<u64> for Counter {
+///     const SCHEMA_VERSION: u32 = 1;
+/// }
+///
+/// let mut runner = EventSourcedRunner::<(), (), u64, Counter, _>::builder(
+///     "counter",
+///     InMemoryEventStore::new(),
+/// )
+/// .snapshots(InMemorySnapshotStore::new(), SnapshotPolicy::EveryNEvents(2))
+/// .load()
+/// .unwrap();
+/// runner.command(&()).unwrap();
+/// runner.command(&()).unwrap();
+/// let snapshot = runner.snapshot_store().load("counter").unwrap();
+/// assert_eq!(snapshot.map(|snapshot| snapshot.state), Some(2));
+/// ```
+pub struct EventSourcedRunnerBuilder<C, E, S, D, ES, SS = NoSnapshots>
+where
+    D: Decider<C, E, S, S>,
+{
+    stream_id: String,
+    store: ES,
+    snapshots: SS,
+    policy: SnapshotPolicy,
+    hooks: Option<SnapshotHooks<S, SS>>,
+    diagnostics: Option<Box<dyn DiagnosticSink>>,
+    command: PhantomData<C>,
+    event: PhantomData<E>,
+    decider: PhantomData<D>,
+}
+
+impl<C, E, S, D, ES, SS> EventSourcedRunnerBuilder<C, E, S, D, ES, SS>
+where
+    D: Decider<C, E, S, S>,
+    ES: EventStore<E>,
+{
+    /// Sets the store the runner saves snapshots to, whenever `policy` says one is due, and
+    /// loads the state from.
+    ///
+    /// When loaded, the runner starts from the stream's latest snapshot and the events after
+    /// it. A snapshot written with another schema version than the decider's current one, or
+    /// taken past the stream's version, is ignored, and every event of the stream is replayed.
+    pub fn snapshots<SS2>(
+        self,
+        snapshots: SS2,
+        policy: SnapshotPolicy,
+    ) -> EventSourcedRunnerBuilder<C, E, S, D, ES, SS2>
+    where
+        D: SnapshotSchema<S>,
+        SS2: SnapshotStore<D::Snapshot>,
+    {
+        EventSourcedRunnerBuilder {
+            stream_id: self.stream_id,
+            store: self.store,
+            snapshots,
+            policy,
+            hooks: Some(SnapshotHooks {
+                load: |snapshots, stream_id| {
+                    Ok(snapshots
+                        .load(stream_id)?
+                        .filter(|snapshot| snapshot.schema_version == D::SCHEMA_VERSION)
+                        .map(|snapshot| {
+                            (D::from_snapshot(&snapshot.state), snapshot.taken_at_version)
+                        }))
+                },
+                save: |snapshots, stream_id, state, version| {
+                    snapshots.save(stream_id, &Snapshot::of::<D, S>(state, version))
+                },
+            }),
+            diagnostics: self.diagnostics,
+            command: PhantomData,
+            event: PhantomData,
+            decider: PhantomData,
+        }
+    }
+
+    /// Sets the sink the runner reports its [`Diagnostic`]s to. Without one, they are dropped.
+    pub fn diagnostics(mut self, sink: impl DiagnosticSink + 'static) -> Self {
+        self.diagnostics = Some(Box::new(sink));
+        self
+    }
+
+    /// Loads the runner's state from its stores.
+    pub fn load(self) -> Result<EventSourcedRunner<C, E, S, D, ES, SS>, StoreError> {
+        let version = self.store.version(&self.stream_id)?;
+        let snapshot = match &self.hooks {
+            Some(hooks) => (hooks.load)(&self.snapshots, &self.stream_id)?
+                .filter(|(_, taken_at_version)| *taken_at_version <= version),
+            None => None,
+        };
+        let state = match snapshot {
+            Some((mut state, taken_at_version)) => {
+                let tail = self.store.load_from(&self.stream_id, taken_at_version)?;
+                evolve_all_in_place(&mut state, &tail, D::evolve_in_place);
+                state
+            }
+            None => D::fold(&self.store.load(&self.stream_id)?),
+        };
+        Ok(EventSourcedRunner {
+            stream_id: self.stream_id,
+            store: self.store,
+            state,
+            version,
+            snapshots: self.snapshots,
+            policy: self.policy,
+            hooks: self.hooks,
+            diagnostics: self.diagnostics,
+            command: PhantomData,
+            event: PhantomData,
+            decider: PhantomData,
+        })
+    }
+}
+
+impl<C, E, S, D, ES, SS> Debug for EventSourcedRunnerBuilder<C, E, S, D, ES, SS>
+where
+    D: Decider<C, E, S, S>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventSourcedRunnerBuilder")
+            .field("stream_id", &self.stream_id)
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A type that runs a decider over a stream of an [`EventStore`], appending the events of every
+/// command to the stream.
+///
+/// The state is rebuilt from the stream when the runner is loaded. The events of a command are
+/// only evolved into the state once the store has accepted them, so a failed append leaves the
+/// runner as it was. A [`StoreError::Conflict`] means something else appended to the stream, and
+/// the runner should be loaded again before retrying the command.
+///
+/// A runner built with a snapshot store, with [`EventSourcedRunner::builder`], also saves a
+/// snapshot of its state whenever its [`SnapshotPolicy`] says one is due. Saving a snapshot is
+/// best-effort: the events are already appended by then, so a failure is reported to the
+/// runner's [`DiagnosticSink`] and the command still succeeds.
+pub struct EventSourcedRunner<C, E, S, D, ES, SS = NoSnapshots>
+where
+    D: Decider<C, E, S, S>,
+{
+    stream_id: String,
+    store: ES,
+    state: S,
+    version: StreamVersion,
+    snapshots: SS,
+    policy: SnapshotPolicy,
+    hooks: Option<SnapshotHooks<S, SS>>,
+    diagnostics: Option<Box<dyn DiagnosticSink>>,
+    command: PhantomData<C>,
+    event: PhantomData<E>,
+    decider: PhantomData<D>,
+}
+
+impl<C, E, S, D, ES> EventSourcedRunner<C, E, S, D, ES>
+where
+    D: Decider<C, E, S, S>,
+    ES: EventStore<E>,
+{
+    /// Constructs a new `EventSourcedRunner` for the stream `stream_id` of `store`, folding
+    /// every event of the stream into the decider's state.
+    pub fn load(stream_id: &str, store: ES) -> Result<Self, StoreError> {
+        Self::builder(stream_id, store).load()
+    }
+
+    /// Returns a builder for an `EventSourcedRunner` for the stream `stream_id` of `store`,
+    /// which saves no snapshots until a snapshot store is set.
+    pub fn builder(stream_id: &str, store: ES) -> EventSourcedRunnerBuilder<C, E, S, D, ES> {
+        EventSourcedRunnerBuilder {
+            stream_id: stream_id.to_string(),
+            store,
+            snapshots: NoSnapshots,
+            policy: SnapshotPolicy::Never,
+            hooks: None,
+            diagnostics: None,
+            command: PhantomData,
+            event: PhantomData,
+            decider: PhantomData,
+        }
+    }
+}
+
+impl<C, E, S, D, ES, SS> EventSourcedRunner<C, E, S, D, ES, SS>
+where
+    D: Decider<C, E, S, S>,
+    ES: EventStore<E>,
+{
+    /// Feeds the given command `command` through the decider, appends the generated events to
+    /// the stream, and returns them once they are appended.
+    ///
+    /// If the append fails, the error is returned and the state is left untouched.
+    pub fn command(&mut self, command: &C) -> Result<Vec<E>, StoreError> {
+        let events = D::decide(command, &self.state);
+        let previous = self.version;
+        self.version = self.store.append(&self.stream_id, self.version, &events)?;
+        evolve_all_in_place(&mut self.state, &events, D::evolve_in_place);
+        self.snapshot_if_due(previous);
+        Ok(events)
+    }
+
+    /// Appends the event `event`, delivered by another system as the event `external_id` of
+    /// `source`, to the stream and evolves the state with it, unless the stream has already
+    /// received it.
+    ///
+    /// An upstream system retrying a delivery can send the same event several times. Each one
+    /// after the first returns [`ApplyResult::Duplicate`] and leaves the stream and state
+    /// untouched.
+    pub fn apply_external(
+        &mut self,
+        source: &str,
+        external_id: &str,
+        event: &E,
+    ) -> Result<ApplyResult, StoreError>
+    where
+        ES: InboxStore<E>,
+    {
+        let applied = self.store.append_received(
+            &self.stream_id,
+            self.version,
+            std::slice::from_ref(event),
+            source,
+            external_id,
+        )?;
+        if let ApplyResult::Applied(version) = applied {
+            let previous = self.version;
+            self.version = version;
+            D::evolve_in_place(&mut self.state, event);
+            self.snapshot_if_due(previous);
+        }
+        Ok(applied)
+    }
+
+    /// Saves a snapshot of the state if the stream moved on from the version `previous` and the
+    /// policy says one is due, reporting a failure to the diagnostic sink.
+    fn snapshot_if_due(&mut self, previous: StreamVersion) {
+        let Some(hooks) = &self.hooks else {
+            return;
+        };
+        if previous == self.version
+            || !self
+                .policy
+                .is_due(previous, self.version, D::is_terminal(&self.state))
+        {
+            return;
+        }
+        if let Err(error) = (hooks.save)(
+            &mut self.snapshots,
+            &self.stream_id,
+            &self.state,
+            self.version,
+        ) {
+            if let Some(diagnostics) = &mut self.diagnostics {
+                diagnostics.report(&Diagnostic::SnapshotFailed {
+                    stream_id: self.stream_id.clone(),
+                    version: self.version,
+                    error,
+                });
+            }
+        }
+    }
+
+    /// Returns a reference to the current state of the decider.
+    pub fn get_state(&self) -> &S {
+        &self.state
+    }
+
+    /// Returns the version of the stream the state is at.
+    pub fn version(&self) -> StreamVersion {
+        self.version
+    }
+
+    /// Returns the id of the stream this runner appends to.
+    pub fn stream_id(&self) -> &str {
+        &self.stream_id
+    }
+
+    /// Returns a reference to the event store.
+    pub fn store(&self) -> &ES {
+        &self.store
+    }
+
+    /// Returns a reference to the snapshot store.
+    pub fn snapshot_store(&self) -> &SS {
+        &self.snapshots
+    }
+
+    /// Consumes this runner, returning its event store.
+    pub fn into_store(self) -> ES {
+        self.store
+    }
+
+    /// Consumes this runner, returning its event store and snapshot store.
+    pub fn into_stores(self) -> (ES, SS) {
+        (self.store, self.snapshots)
+    }
+}
+
+impl<C, E, S, D, ES, SS> Debug for EventSourcedRunner<C, E, S, D, ES, SS>
+where
+    S: Debug,
+    D: Decider<C, E, S, S>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventSourcedRunner")
+            .field("stream_id", &self.stream_id)
+            .field("state", &self.state)
+            .field("version", &self.version)
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+/// An event stored by a [`ComposedEventSourcedRunner`], along with its place among the events of
+/// both of the runner's streams.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Interleaved<E> {
+    /// The number of events appended to both streams before this one.
+    pub index: u64,
+    /// The event.
+    pub event: E,
+}
+
+/// A type that runs a decider with `Either` events, such as [`ComposedDeciders`], over two
+/// streams of one [`EventStore`], appending its `Left` events to one stream and its `Right`
+/// events to the other.
+///
+/// The events of a command are appended to both streams with a single
+/// [`EventStore::append_multi`], so a crash can never leave one stream with its events and the
+/// other without. Each stream's version is checked separately, and a conflict on either of them
+/// fails the whole command, naming the stream in the [`StoreError::Conflict`]. Both streams are
+/// checked even when a command only generates events for one of them, since it was decided
+/// against both sides of the state.
+///
+/// Every stored event is [`Interleaved`] with the number of events written to both streams
+/// before it, so that loading the runner merges the streams back into the order the events were
+/// decided in.
+///
+/// [`ComposedDeciders`]: crate::deciders::ComposedDeciders
+pub struct ComposedEventSourcedRunner<C, E1, E2, S, D, ES>
+where
+    D: Decider<C, Either<E1, E2>, S, S>,
+{
+    left_stream_id: String,
+    right_stream_id: String,
+    store: ES,
+    state: S,
+    left_version: StreamVersion,
+    right_version: StreamVersion,
+    command: PhantomData<C>,
+    event: PhantomData<Either<E1, E2>>,
+    decider: PhantomData<D>,
+}
+
+impl<C, E1, E2, S, D, ES> ComposedEventSourcedRunner<C, E1, E2, S, D, ES>
+where
+    E1: Clone,
+    E2: Clone,
+    D: Decider<C, Either<E1, E2>, S, S>,
+    ES: EventStore<Interleaved<Either<E1, E2>>>,
+{
+    /// Constructs a new `ComposedEventSourcedRunner` appending `Left` events to the stream
+    /// `left_stream_id` of `store` and `Right` events to the stream `right_stream_id`, folding
+    /// the events of both streams into the decider's state in the order they were decided in.
+    pub fn load(
+        left_stream_id: &str,
+        right_stream_id: &str,
+        store: ES,
+    ) -> Result<Self, StoreError> {
+        let mut events = store.load(left_stream_id)?;
+        events.extend(store.load(right_stream_id)?);
+        events.sort_by_key(|stored| stored.index);
+        let events: Vec<_> = events.into_iter().map(|stored| stored.event).collect();
+        Ok(Self {
+            left_version: store.version(left_stream_id)?,
+            right_version: store.version(right_stream_id)?,
+            left_stream_id: left_stream_id.to_string(),
+            right_stream_id: right_stream_id.to_string(),
+            store,
+            state: D::fold(&events),
+            command: PhantomData,
+            event: PhantomData,
+            decider: PhantomData,
+        })
+    }
+
+    /// Feeds the given command `command` through the decider, appends the generated events to
+    /// their streams in one transaction, and returns them once they are appended.
+    ///
+    /// If the append fails, the error is returned and the state is left untouched.
+    pub fn command(&mut self, command: &C) -> Result<Vec<Either<E1, E2>>, StoreError> {
+        let events = D::decide(command, &self.state);
+        let first = u64::from(self.left_version) + u64::from(self.right_version);
+        let (mut left, mut right) = (vec![], vec![]);
+        for (index, event) in (first..).zip(&events) {
+            let stored = Interleaved {
+                index,
+                event: event.clone(),
+            };
+            match event {
+                Either::Left(_) => left.push(stored),
+                Either::Right(_) => right.push(stored),
+            }
+        }
+        let versions = self.store.append_multi(&[
+            StreamAppend {
+                stream_id: &self.left_stream_id,
+                expected: self.left_version,
+                events: &left,
+            },
+            StreamAppend {
+                stream_id: &self.right_stream_id,
+                expected: self.right_version,
+                events: &right,
+            },
+        ])?;
+        self.left_version = versions[0];
+        self.right_version = versions[1];
+        evolve_all_in_place(&mut self.state, &events, D::evolve_in_place);
+        Ok(events)
+    }
+
+    /// Returns a reference to the current state of the decider.
+    pub fn get_state(&self) -> &S {
+        &self.state
+    }
+
+    /// Returns the versions of the `Left` and `Right` streams the state is at.
+    pub fn versions(&self) -> (StreamVersion, StreamVersion) {
+        (self.left_version, self.right_version)
+    }
+
+    /// Returns a reference to the event store.
+    pub fn store(&self) -> &ES {
+        &self.store
+    }
+
+    /// Consumes this runner, returning its event store.
+    pub fn into_store(self) -> ES {
+        self.store
+    }
+}
+
+impl<C, E1, E2, S, D, ES> Debug for ComposedEventSourcedRunner<C, E1, E2, S, D, ES>
+where
+    S: Debug,
+    D: Decider<C, Either<E1, E2>, S, S>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ComposedEventSourcedRunner")
+            .field("left_stream_id", &self.left_stream_id)
+            .field("right_stream_id", &self.right_stream_id)
+            .field("state", &self.state)
+            .field("versions", &(self.left_version, self.right_version))
+            .finish()
+    }
+}
+
+/// A type that runs a collection of deciders of the same type over a single stream of an
+/// [`EventStore`], like a [`ManyDecider`](crate::deciders::ManyDecider), saving a snapshot of
+/// each key separately to a [`KeyedSnapshotStore`].
+///
+/// The runner tracks which keys changed since their last snapshot, and
+/// [`KeyedEventSourcedRunner::snapshot`] only writes those, so a command to one key of a large
+/// collection leads to a single write. When the runner is loaded, each key starts from its own
+/// snapshot and only folds the events of the stream after it. A key pruned from the collection
+/// is saved as a tombstone, so its older snapshot and events are not loaded again.
+pub struct KeyedEventSourcedRunner<C, E, S, D, ES, KS>
+where
+    D: Decider<C, E, S, S>,
+{
+    stream_id: String,
+    store: ES,
+    snapshots: KS,
+    states: HashMap<String, S>,
+    version: StreamVersion,
+    dirty: HashSet<String>,
+    command: PhantomData<C>,
+    event: PhantomData<E>,
+    decider: PhantomData<D>,
+}
+
+impl<C, E, S, D, ES, KS> KeyedEventSourcedRunner<C, E, S, D, ES, KS>
+where
+    D: SnapshotSchema<S> + Decider<C, E, S, S>,
+    ES: EventStore<(String, E)>,
+    KS: KeyedSnapshotStore<String, D::Snapshot>,
+{
+    /// Constructs a new `KeyedEventSourcedRunner` for the stream `stream_id` of `store`, loading
+    /// each key from its snapshot in `snapshots` and the events of the key after it.
+    ///
+    /// A snapshot written with another schema version than the decider's current one is
+    /// ignored, and the key is folded from all of its events.
+    pub fn load(stream_id: &str, store: ES, snapshots: KS) -> Result<Self, StoreError> {
+        let mut states = HashMap::new();
+        let mut loaded_at = HashMap::new();
+        for (key, snapshot) in snapshots.load_all(stream_id)? {
+            match snapshot.state {
+                None => {}
+                Some(_) if snapshot.schema_version != D::SCHEMA_VERSION => continue,
+                Some(state) => {
+                    states.insert(key.clone(), D::from_snapshot(&state));
+                }
+            }
+            loaded_at.insert(key, snapshot.taken_at_version);
+        }
+        let mut dirty = HashSet::new();
+        for (position, (key, event)) in (0..).zip(store.load(stream_id)?) {
+            if StreamVersion::from(position) < loaded_at.get(&key).copied().unwrap_or_default() {
+                continue;
+            }
+            D::evolve_in_place(
+                states.entry(key.clone()).or_insert_with(D::initial_state),
+                &event,
+            );
+            dirty.insert(key);
+        }
+        Ok(Self {
+            stream_id: stream_id.to_string(),
+            version: store.version(stream_id)?,
+            store,
+            snapshots,
+            states,
+            dirty,
+            command: PhantomData,
+            event: PhantomData,
+            decider: PhantomData,
+        })
+    }
+
+    /// Feeds the command `command` to the decider of its key, which starts from the decider's
+    /// initial state if the key is not in the collection, appends the generated events to the
+    /// stream, and returns them once they are appended.
+    ///
+    /// If the append fails, the error is returned and the state is left untouched.
+    pub fn command(
+        &mut self,
+        (key, command): &(String, C),
+    ) -> Result<Vec<(String, E)>, StoreError> {
+        let events = match self.states.get(key) {
+            Some(state) => D::decide(command, state),
+            None => D::decide(command, &D::initial_state()),
+        };
+        if events.is_empty() {
+            return Ok(vec![]);
+        }
+        let events: Vec<(String, E)> = events.into_iter().map(|e| (key.clone(), e)).collect();
+        self.version = self.store.append(&self.stream_id, self.version, &events)?;
+        let state = self
+            .states
+            .entry(key.clone())
+            .or_insert_with(D::initial_state);
+        for (_, event) in &events {
+            D::evolve_in_place(state, event);
+        }
+        self.dirty.insert(key.clone());
+        Ok(events)
+    }
+
+    /// Removes the key `key` from the collection, returning whether it was there. A tombstone
+    /// is saved for it by the next snapshot.
+    pub fn prune(&mut self, key: &str) -> bool {
+        let pruned = self.states.remove(key).is_some();
+        if pruned {
+            self.dirty.insert(key.to_string());
+        }
+        pruned
+    }
+
+    /// Removes every key whose state is terminal from the collection, as with
+    /// [`KeyedEventSourcedRunner::prune`], and returns them in order.
+    pub fn prune_terminal(&mut self) -> Vec<String> {
+        let mut terminal: Vec<String> = self
+            .states
+            .iter()
+            .filter(|(_, state)| D::is_terminal(state))
+            .map(|(key, _)| key.clone())
+            .collect();
+        terminal.sort();
+        for key in &terminal {
+            self.prune(key);
+        }
+        terminal
+    }
+
+    /// Moves every key whose state is terminal out of the collection, writing each one to the
+    /// sink `sink` returns for it as a [`StreamArchive`], and returns their receipts in order.
+    ///
+    /// The archive of a key holds its events, as the stream `{stream_id}/{key}`, and a snapshot
+    /// of its final state, so [`restore_from_archive`] brings it back as a stream of its own for
+    /// auditing. Once its archive is written, each key is pruned as with
+    /// [`KeyedEventSourcedRunner::prune`], so a tombstone is saved for it by the next snapshot.
+    /// The events of the keys stay in the shared stream, which is only trimmed by retention.
+    ///
+    /// Requires the `serde` feature.
+    #[cfg(feature = "serde")]
+    pub fn archive_terminal_keys<W>(
+        &mut self,
+        mut sink: impl FnMut(&str) -> W,
+    ) -> Result<Vec<ArchiveReceipt>, StoreError>
+    where
+        E: serde::Serialize,
+        D::Snapshot: serde::Serialize,
+        W: std::io::Write,
+    {
+        let mut terminal: Vec<String> = self
+            .states
+            .iter()
+            .filter(|(_, state)| D::is_terminal(state))
+            .map(|(key, _)| key.clone())
+            .collect();
+        if terminal.is_empty() {
+            return Ok(vec![]);
+        }
+        terminal.sort();
+        let mut events: HashMap<String, Vec<E>> = HashMap::new();
+        for (key, event) in self.store.load(&self.stream_id)? {
+            if self.states.get(&key).is_some_and(D::is_terminal) {
+                events.entry(key).or_default().push(event);
+            }
+        }
+        let mut receipts = Vec::with_capacity(terminal.len());
+        for key in terminal {
+            let events = events.remove(&key).unwrap_or_default();
+            let version = StreamVersion::from(events.len() as u64);
+            let archive = StreamArchive {
+                receipt: ArchiveReceipt {
+                    stream_id: format!("{}/{key}", self.stream_id),
+                    version,
+                    snapshot_version: Some(version),
+                },
+                events,
+                snapshot: Some(Snapshot {
+                    schema_version: D::SCHEMA_VERSION,
+                    taken_at_version: version,
+                    state: D::to_snapshot(&self.states[&key]),
+                }),
+            };
+            write_archive(sink(&key), &archive)?;
+            self.prune(&key);
+            receipts.push(archive.receipt);
+        }
+        Ok(receipts)
+    }
+
+    /// Saves a snapshot of every key that changed since its last snapshot, or a tombstone for
+    /// every key pruned since, and returns how many were saved.
+    ///
+    /// If a snapshot cannot be saved, the error is returned, and the keys not saved yet are
+    /// saved by the next call.
+    pub fn snapshot(&mut self) -> Result<usize, StoreError> {
+        let mut dirty: Vec<String> = self.dirty.iter().cloned().collect();
+        dirty.sort();
+        for key in &dirty {
+            let snapshot = KeySnapshot {
+                schema_version: D::SCHEMA_VERSION,
+                taken_at_version: self.version,
+                state: self.states.get(key).map(D::to_snapshot),
+            };
+            self.snapshots.save_key(&self.stream_id, key, &snapshot)?;
+            self.dirty.remove(key);
+        }
+        Ok(dirty.len())
+    }
+
+    /// Returns a reference to the state of every key of the collection.
+    pub fn get_state(&self) -> &HashMap<String, S> {
+        &self.states
+    }
+
+    /// Returns whether the key `key` changed since its last snapshot.
+    pub fn is_dirty(&self, key: &str) -> bool {
+        self.dirty.contains(key)
+    }
+
+    /// Returns the version of the stream the collection is at.
+    pub fn version(&self) -> StreamVersion {
+        self.version
+    }
+
+    /// Returns a reference to the event store.
+    pub fn store(&self) -> &ES {
+        &self.store
+    }
+
+    /// Returns a reference to the snapshot store.
+    pub fn snapshot_store(&self) -> &KS {
+        &self.snapshots
+    }
+
+    /// Consumes this runner, returning its event store and snapshot store.
+    pub fn into_stores(self) -> (ES, KS) {
+        (self.store, self.snapshots)
+    }
+}
+
+impl<C, E, S, D, ES, KS> Debug for KeyedEventSourcedRunner<C, E, S, D, ES, KS>
+where
+    S: Debug,
+    D: Decider<C, E, S, S>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyedEventSourcedRunner")
+            .field("stream_id", &self.stream_id)
+            .field("states", &self.states)
+            .field("version", &self.version)
+            .field("dirty", &self.dirty)
+            .finish()
+    }
+}
+
+/// An operation of an [`EventStore`] that a [`ChaosStore`] can inject faults into.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ChaosOperation {
+    /// An append, by [`EventStore::append`] or [`EventStore::append_multi`].
+    Append,
+    /// A read, by [`EventStore::load_from`], [`EventStore::load`], [`EventStore::version`],
+    /// [`EventStore::first_version`] or [`EventStore::stream_ids`].
+    Read,
+}
+
+impl Display for ChaosOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChaosOperation::Append => write!(f, "append"),
+            ChaosOperation::Read => write!(f, "read"),
+        }
+    }
+}
+
+/// The operations a [`ChaosStore`] has seen, and the faults it injected into them.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChaosCounters {
+    /// The number of appends attempted, including the failed ones.
+    pub appends: u64,
+    /// The number of reads attempted, including the failed ones.
+    pub reads: u64,
+    /// The number of appends that failed with an injected fault.
+    pub append_faults: u64,
+    /// The number of reads that failed with an injected fault.
+    pub read_faults: u64,
+    /// The number of failed appends that still appended to some of their streams.
+    pub torn_appends: u64,
+}
+
+/// An [`EventStore`] wrapping another one and injecting faults into its operations, to test how
+/// runners and processes behave when their store misbehaves.
+///
+/// Each [`ChaosOperation`] can be made to fail on chosen attempts, such as the third append,
+/// with [`ChaosStore::fail_nth`], or at random with [`ChaosStore::with_failure_rate`]. An
+/// injected fault is returned as a [`StoreError::Io`] without calling the wrapped store, so the
+/// streams are left untouched. The random faults are drawn from a generator seeded with
+/// [`ChaosStore::with_seed`], so a test run fails the same operations every time.
+///
+/// Truncating, archiving and unarchiving streams are forwarded to the wrapped store as they
+/// are. There is no async variant, as every store of this crate is synchronous.
+#[derive(Clone, Debug)]
+pub struct ChaosStore<S> {
+    inner: S,
+    script: HashSet<(ChaosOperation, u64)>,
+    append_failure_rate: f64,
+    read_failure_rate: f64,
+    latency: Duration,
+    torn_appends: bool,
+    rng: Cell<u64>,
+    counters: Cell<ChaosCounters>,
+}
+
+impl<S> ChaosStore<S> {
+    /// Constructs a new `ChaosStore` wrapping `inner`, which injects no faults until told to.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            script: HashSet::new(),
+            append_failure_rate: 0.0,
+            read_failure_rate: 0.0,
+            latency: Duration::ZERO,
+            torn_appends: false,
+            rng: Cell::new(0),
+            counters: Cell::new(ChaosCounters::default()),
+        }
+    }
+
+    /// Returns this store, failing the `n`th attempt of `operation`, counting from 1.
+    pub fn fail_nth(mut self, operation: ChaosOperation, n: u64) -> Self {
+        self.script.insert((operation, n));
+        self
+    }
+
+    /// Returns this store, failing each attempt of `operation` with the probability `rate`,
+    /// between 0 and 1.
+    pub fn with_failure_rate(self, operation: ChaosOperation, rate: f64) -> Self {
+        match operation {
+            ChaosOperation::Append => Self {
+                append_failure_rate: rate,
+                ..self
+            },
+            ChaosOperation::Read => Self {
+                read_failure_rate: rate,
+                ..self
+            },
+        }
+    }
+
+    /// Returns this store, drawing its random faults from a generator seeded with `seed`.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self {
+            rng: Cell::new(seed),
+            ..self
+        }
+    }
+
+    /// Returns this store, sleeping for `latency` before every append and read.
+    pub fn with_latency(self, latency: Duration) -> Self {
+        Self { latency, ..self }
+    }
+
+    /// Returns this store, tearing the appends to several streams it fails: every append but
+    /// the last is made before the fault is returned, as a store without transactions across
+    /// streams could do. This checks that a caller does not rely on [`EventStore::append_multi`]
+    /// being atomic.
+    pub fn with_torn_appends(self) -> Self {
+        Self {
+            torn_appends: true,
+            ..self
+        }
+    }
+
+    /// Returns the operations seen and the faults injected so far.
+    pub fn counters(&self) -> ChaosCounters {
+        self.counters.get()
+    }
+
+    /// Returns the wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns the wrapped store, to change it without injecting faults.
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes this store, returning the wrapped store.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Counts an attempt of `operation`, returning the fault to inject into it, if any.
+    fn inject(&self, operation: ChaosOperation) -> Result<(), StoreError> {
+        if !self.latency.is_zero() {
+            thread::sleep(self.latency);
+        }
+        let mut counters = self.counters.get();
+        let (attempt, rate) = match operation {
+            ChaosOperation::Append => {
+                counters.appends += 1;
+                (counters.appends, self.append_failure_rate)
+            }
+            ChaosOperation::Read => {
+                counters.reads += 1;
+                (counters.reads, self.read_failure_rate)
+            }
+        };
+        let fails =
+            self.script.contains(&(operation, attempt)) || (rate > 0.0 && self.draw() < rate);
+        if fails {
+            match operation {
+                ChaosOperation::Append => counters.append_faults += 1,
+                ChaosOperation::Read => counters.read_faults += 1,
+            }
+        }
+        self.counters.set(counters);
+        if fails {
+            Err(StoreError::Io(format!(
+                "injected fault in {operation} {attempt}"
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the next number of the generator, between 0 and 1, using SplitMix64.
+    fn draw(&self) -> f64 {
+        let state = self.rng.get().wrapping_add(0x9E37_79B9_7F4A_7C15);
+        self.rng.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl<E, S> EventStore<E> for ChaosStore<S>
+where
+    S: EventStore<E>,
+{
+    fn load_from(&self, stream_id: &str, from: StreamVersion) -> Result<Vec<E>, StoreError> {
+        self.inject(ChaosOperation::Read)?;
+        self.inner.load_from(stream_id, from)
+    }
+
+    fn version(&self, stream_id: &str) -> Result<StreamVersion, StoreError> {
+        self.inject(ChaosOperation::Read)?;
+        self.inner.version(stream_id)
+    }
+
+    fn first_version(&self, stream_id: &str) -> Result<StreamVersion, StoreError> {
+        self.inject(ChaosOperation::Read)?;
+        self.inner.first_version(stream_id)
+    }
+
+    fn truncate_before(
+        &mut self,
+        stream_id: &str,
+        before: StreamVersion,
+    ) -> Result<u64, StoreError> {
+        self.inner.truncate_before(stream_id, before)
+    }
+
+    fn archive(&mut self, receipt: &ArchiveReceipt) -> Result<(), StoreError> {
+        self.inner.archive(receipt)
+    }
+
+    fn unarchive(&mut self, stream_id: &str) -> Result<Option<ArchiveReceipt>, StoreError> {
+        self.inner.unarchive(stream_id)
+    }
+
+    fn stream_ids(&self) -> Result<Vec<String>, StoreError> {
+        self.inject(ChaosOperation::Read)?;
+        self.inner.stream_ids()
+    }
+
+    fn append_multi(
+        &mut self,
+        appends: &[StreamAppend<'_, E>],
+    ) -> Result<Vec<StreamVersion>, StoreError> {
+        let Err(fault) = self.inject(ChaosOperation::Append) else {
+            return self.inner.append_multi(appends);
+        };
+        if self.torn_appends
+            && appends.len() > 1
+            && self
+                .inner
+                .append_multi(&appends[..appends.len() - 1])
+                .is_ok()
+        {
+            let mut counters = self.counters.get();
+            counters.torn_appends += 1;
+            self.counters.set(counters);
+        }
+        Err(fault)
+    }
+}
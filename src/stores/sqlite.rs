@@ -0,0 +1,492 @@
+use std::{fmt::Debug, marker::PhantomData};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::{
+    check_appends, check_archive, ApplyResult, ArchiveReceipt, EventCodec, EventStore, InboxStore,
+    JsonCodec, SnapshotStore, StoreError, StreamAppend,
+};
+use crate::deciders::Snapshot;
+use crate::utilities::StreamVersion;
+
+/// Creates the tables used by [`SqliteEventStore`] if they do not already exist.
+pub fn create_event_tables(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS event_streams (
+            stream TEXT PRIMARY KEY,
+            version INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS events (
+            stream TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            payload BLOB NOT NULL,
+            PRIMARY KEY (stream, position)
+        );
+        CREATE TABLE IF NOT EXISTS inbox (
+            stream TEXT NOT NULL,
+            source TEXT NOT NULL,
+            external_id TEXT NOT NULL,
+            received INTEGER NOT NULL,
+            PRIMARY KEY (stream, source, external_id)
+        );
+        CREATE TABLE IF NOT EXISTS archived_streams (
+            stream TEXT PRIMARY KEY,
+            version INTEGER NOT NULL,
+            snapshot_version INTEGER
+        );",
+    )
+}
+
+fn io_error(error: rusqlite::Error) -> StoreError {
+    StoreError::Io(error.to_string())
+}
+
+fn stored_version(version: StreamVersion) -> Result<i64, StoreError> {
+    i64::try_from(u64::from(version)).map_err(|e| StoreError::Io(e.to_string()))
+}
+
+fn read_stored_version(value: i64) -> Result<StreamVersion, StoreError> {
+    let version = u64::try_from(value).map_err(|e| StoreError::Io(e.to_string()))?;
+    Ok(StreamVersion::from(version))
+}
+
+fn read_receipt(
+    connection: &Connection,
+    stream_id: &str,
+) -> Result<Option<ArchiveReceipt>, StoreError> {
+    let archived: Option<(i64, Option<i64>)> = connection
+        .query_row(
+            "SELECT version, snapshot_version FROM archived_streams WHERE stream = ?1",
+            params![stream_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(io_error)?;
+    let Some((version, snapshot_version)) = archived else {
+        return Ok(None);
+    };
+    Ok(Some(ArchiveReceipt {
+        stream_id: stream_id.to_string(),
+        version: read_stored_version(version)?,
+        snapshot_version: snapshot_version.map(read_stored_version).transpose()?,
+    }))
+}
+
+/// Reads the version of the stream `stream_id`, or returns [`StoreError::Archived`] if it is
+/// archived.
+fn read_version(connection: &Connection, stream_id: &str) -> Result<StreamVersion, StoreError> {
+    if let Some(receipt) = read_receipt(connection, stream_id)? {
+        return Err(StoreError::Archived(receipt));
+    }
+    let version: Option<i64> = connection
+        .query_row(
+            "SELECT version FROM event_streams WHERE stream = ?1",
+            params![stream_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(io_error)?;
+    read_stored_version(version.unwrap_or(0))
+}
+
+/// An [`EventStore`] that keeps streams in an SQLite database, encoding each event with the
+/// codec `Co`, which defaults to [`JsonCodec`].
+///
+/// Every call to [`EventStore::append_multi`] runs in a single transaction, so the events of
+/// several streams are committed together or not at all. As an [`InboxStore`], it records
+/// received events in the same transaction as their append, and remembers every one of them
+/// unless its capacity is limited with [`SqliteEventStore::with_inbox_capacity`].
+pub struct SqliteEventStore<E, Co = JsonCodec> {
+    connection: Connection,
+    codec: Co,
+    inbox_capacity: Option<usize>,
+    event: PhantomData<E>,
+}
+
+impl<E, Co> SqliteEventStore<E, Co>
+where
+    Co: Default,
+{
+    /// Constructs a new `SqliteEventStore` over `connection`, which holds every stream
+    /// previously appended to it.
+    ///
+    /// The tables are created if they do not already exist.
+    pub fn new(connection: Connection) -> rusqlite::Result<Self> {
+        Self::with_codec(connection, Co::default())
+    }
+}
+
+impl<E, Co> SqliteEventStore<E, Co> {
+    /// Constructs a new `SqliteEventStore` over `connection` encoding events with `codec`.
+    ///
+    /// The tables are created if they do not already exist.
+    pub fn with_codec(connection: Connection, codec: Co) -> rusqlite::Result<Self> {
+        create_event_tables(&connection)?;
+        Ok(Self {
+            connection,
+            codec,
+            inbox_capacity: None,
+            event: PhantomData,
+        })
+    }
+
+    /// Returns this store, remembering at most `capacity` received events per stream. Once a
+    /// stream's inbox is full, receiving an event forgets the oldest one, so a redelivery of it
+    /// would be applied again.
+    pub fn with_inbox_capacity(self, capacity: usize) -> Self {
+        Self {
+            inbox_capacity: Some(capacity),
+            ..self
+        }
+    }
+
+    /// Returns a reference to the database connection.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Consumes this store, returning its database connection.
+    pub fn into_connection(self) -> Connection {
+        self.connection
+    }
+}
+
+impl<E, Co> Debug for SqliteEventStore<E, Co> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SqliteEventStore").finish_non_exhaustive()
+    }
+}
+
+/// Appends the events of every append in `appends` within `transaction`, returning the new
+/// version of each stream.
+fn append_in<E, Co>(
+    transaction: &Connection,
+    codec: &Co,
+    appends: &[StreamAppend<'_, E>],
+) -> Result<Vec<StreamVersion>, StoreError>
+where
+    Co: EventCodec<E>,
+{
+    let versions = check_appends(appends, |stream_id| read_version(transaction, stream_id))?;
+    for append in appends {
+        let first = u64::from(append.expected);
+        for (position, event) in (first..).zip(append.events) {
+            transaction
+                .execute(
+                    "INSERT INTO events (stream, position, payload) VALUES (?1, ?2, ?3)",
+                    params![
+                        append.stream_id,
+                        stored_version(StreamVersion::from(position))?,
+                        codec.encode(event)?
+                    ],
+                )
+                .map_err(io_error)?;
+        }
+        transaction
+            .execute(
+                "INSERT INTO event_streams (stream, version) VALUES (?1, ?2)
+                 ON CONFLICT(stream) DO UPDATE SET version = excluded.version",
+                params![
+                    append.stream_id,
+                    stored_version(append.expected + append.events.len() as u64)?
+                ],
+            )
+            .map_err(io_error)?;
+    }
+    Ok(versions)
+}
+
+fn has_received_in(
+    connection: &Connection,
+    stream_id: &str,
+    source: &str,
+    external_id: &str,
+) -> Result<bool, StoreError> {
+    connection
+        .query_row(
+            "SELECT 1 FROM inbox WHERE stream = ?1 AND source = ?2 AND external_id = ?3",
+            params![stream_id, source, external_id],
+            |_| Ok(()),
+        )
+        .optional()
+        .map(|found| found.is_some())
+        .map_err(io_error)
+}
+
+impl<E, Co> EventStore<E> for SqliteEventStore<E, Co>
+where
+    Co: EventCodec<E>,
+{
+    fn load_from(&self, stream_id: &str, from: StreamVersion) -> Result<Vec<E>, StoreError> {
+        let first = self.first_version(stream_id)?;
+        if from < first {
+            return Err(StoreError::Truncated {
+                stream_id: stream_id.to_string(),
+                first,
+            });
+        }
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT payload FROM events WHERE stream = ?1 AND position >= ?2
+                 ORDER BY position",
+            )
+            .map_err(io_error)?;
+        let payloads = statement
+            .query_map(params![stream_id, stored_version(from)?], |row| {
+                row.get::<_, Vec<u8>>(0)
+            })
+            .map_err(io_error)?;
+        payloads
+            .map(|payload| self.codec.decode(&payload.map_err(io_error)?))
+            .collect()
+    }
+
+    fn version(&self, stream_id: &str) -> Result<StreamVersion, StoreError> {
+        read_version(&self.connection, stream_id)
+    }
+
+    fn first_version(&self, stream_id: &str) -> Result<StreamVersion, StoreError> {
+        // Once every event of a truncated stream is removed, its first event is the next one.
+        let version = read_version(&self.connection, stream_id)?;
+        let first: i64 = self
+            .connection
+            .query_row(
+                "SELECT COALESCE(MIN(position), ?2) FROM events WHERE stream = ?1",
+                params![stream_id, stored_version(version)?],
+                |row| row.get(0),
+            )
+            .map_err(io_error)?;
+        let first = u64::try_from(first).map_err(|e| StoreError::Io(e.to_string()))?;
+        Ok(StreamVersion::from(first))
+    }
+
+    fn truncate_before(
+        &mut self,
+        stream_id: &str,
+        before: StreamVersion,
+    ) -> Result<u64, StoreError> {
+        read_version(&self.connection, stream_id)?;
+        let removed = self
+            .connection
+            .execute(
+                "DELETE FROM events WHERE stream = ?1 AND position < ?2",
+                params![stream_id, stored_version(before)?],
+            )
+            .map_err(io_error)?;
+        Ok(removed as u64)
+    }
+
+    fn archive(&mut self, receipt: &ArchiveReceipt) -> Result<(), StoreError> {
+        let transaction = self.connection.transaction().map_err(io_error)?;
+        check_archive(receipt, read_version(&transaction, &receipt.stream_id)?)?;
+        transaction
+            .execute(
+                "DELETE FROM events WHERE stream = ?1",
+                params![receipt.stream_id],
+            )
+            .map_err(io_error)?;
+        transaction
+            .execute(
+                "DELETE FROM event_streams WHERE stream = ?1",
+                params![receipt.stream_id],
+            )
+            .map_err(io_error)?;
+        transaction
+            .execute(
+                "INSERT INTO archived_streams (stream, version, snapshot_version)
+                 VALUES (?1, ?2, ?3)",
+                params![
+                    receipt.stream_id,
+                    stored_version(receipt.version)?,
+                    receipt.snapshot_version.map(stored_version).transpose()?
+                ],
+            )
+            .map_err(io_error)?;
+        transaction.commit().map_err(io_error)
+    }
+
+    fn unarchive(&mut self, stream_id: &str) -> Result<Option<ArchiveReceipt>, StoreError> {
+        let transaction = self.connection.transaction().map_err(io_error)?;
+        let receipt = read_receipt(&transaction, stream_id)?;
+        transaction
+            .execute(
+                "DELETE FROM archived_streams WHERE stream = ?1",
+                params![stream_id],
+            )
+            .map_err(io_error)?;
+        transaction.commit().map_err(io_error)?;
+        Ok(receipt)
+    }
+
+    fn stream_ids(&self) -> Result<Vec<String>, StoreError> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT stream FROM event_streams ORDER BY stream")
+            .map_err(io_error)?;
+        let ids = statement
+            .query_map([], |row| row.get(0))
+            .map_err(io_error)?;
+        ids.collect::<rusqlite::Result<_>>().map_err(io_error)
+    }
+
+    fn append_multi(
+        &mut self,
+        appends: &[StreamAppend<'_, E>],
+    ) -> Result<Vec<StreamVersion>, StoreError> {
+        let transaction = self.connection.transaction().map_err(io_error)?;
+        let versions = append_in(&transaction, &self.codec, appends)?;
+        transaction.commit().map_err(io_error)?;
+        Ok(versions)
+    }
+}
+
+impl<E, Co> InboxStore<E> for SqliteEventStore<E, Co>
+where
+    Co: EventCodec<E>,
+{
+    fn has_received(
+        &self,
+        stream_id: &str,
+        source: &str,
+        external_id: &str,
+    ) -> Result<bool, StoreError> {
+        has_received_in(&self.connection, stream_id, source, external_id)
+    }
+
+    fn append_received(
+        &mut self,
+        stream_id: &str,
+        expected: StreamVersion,
+        events: &[E],
+        source: &str,
+        external_id: &str,
+    ) -> Result<ApplyResult, StoreError> {
+        let transaction = self.connection.transaction().map_err(io_error)?;
+        if has_received_in(&transaction, stream_id, source, external_id)? {
+            return Ok(ApplyResult::Duplicate);
+        }
+        let versions = append_in(
+            &transaction,
+            &self.codec,
+            &[StreamAppend {
+                stream_id,
+                expected,
+                events,
+            }],
+        )?;
+        transaction
+            .execute(
+                "INSERT INTO inbox (stream, source, external_id, received)
+                 SELECT ?1, ?2, ?3, COALESCE(MAX(received), 0) + 1 FROM inbox WHERE stream = ?1",
+                params![stream_id, source, external_id],
+            )
+            .map_err(io_error)?;
+        if let Some(capacity) = self.inbox_capacity {
+            let capacity = i64::try_from(capacity).unwrap_or(i64::MAX);
+            transaction
+                .execute(
+                    "DELETE FROM inbox WHERE stream = ?1 AND received <=
+                     (SELECT MAX(received) FROM inbox WHERE stream = ?1) - ?2",
+                    params![stream_id, capacity],
+                )
+                .map_err(io_error)?;
+        }
+        transaction.commit().map_err(io_error)?;
+        Ok(ApplyResult::Applied(versions[0]))
+    }
+}
+
+/// Creates the table used by [`SqliteSnapshotStore`] if it does not already exist.
+pub fn create_snapshot_table(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS snapshots (
+            key TEXT PRIMARY KEY,
+            payload BLOB NOT NULL
+        );",
+    )
+}
+
+/// A [`SnapshotStore`] that keeps the latest snapshot under each key in an SQLite database,
+/// encoding each [`Snapshot`] with the codec `Co`, which defaults to [`JsonCodec`].
+pub struct SqliteSnapshotStore<S, Co = JsonCodec> {
+    connection: Connection,
+    codec: Co,
+    snapshot: PhantomData<S>,
+}
+
+impl<S, Co> SqliteSnapshotStore<S, Co>
+where
+    Co: Default,
+{
+    /// Constructs a new `SqliteSnapshotStore` over `connection`, which holds every snapshot
+    /// previously saved to it.
+    ///
+    /// The table is created if it does not already exist.
+    pub fn new(connection: Connection) -> rusqlite::Result<Self> {
+        Self::with_codec(connection, Co::default())
+    }
+}
+
+impl<S, Co> SqliteSnapshotStore<S, Co> {
+    /// Constructs a new `SqliteSnapshotStore` over `connection` encoding snapshots with
+    /// `codec`.
+    ///
+    /// The table is created if it does not already exist.
+    pub fn with_codec(connection: Connection, codec: Co) -> rusqlite::Result<Self> {
+        create_snapshot_table(&connection)?;
+        Ok(Self {
+            connection,
+            codec,
+            snapshot: PhantomData,
+        })
+    }
+
+    /// Returns a reference to the database connection.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Consumes this store, returning its database connection.
+    pub fn into_connection(self) -> Connection {
+        self.connection
+    }
+}
+
+impl<S, Co> Debug for SqliteSnapshotStore<S, Co> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SqliteSnapshotStore")
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, Co> SnapshotStore<S> for SqliteSnapshotStore<S, Co>
+where
+    Co: EventCodec<Snapshot<S>>,
+{
+    fn load(&self, key: &str) -> Result<Option<Snapshot<S>>, StoreError> {
+        let payload: Option<Vec<u8>> = self
+            .connection
+            .query_row(
+                "SELECT payload FROM snapshots WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(io_error)?;
+        payload
+            .map(|payload| self.codec.decode(&payload))
+            .transpose()
+    }
+
+    fn save(&mut self, key: &str, snapshot: &Snapshot<S>) -> Result<(), StoreError> {
+        self.connection
+            .execute(
+                "INSERT INTO snapshots (key, payload) VALUES (?1, ?2)
+                 ON CONFLICT(key) DO UPDATE SET payload = excluded.payload",
+                params![key, self.codec.encode(snapshot)?],
+            )
+            .map_err(io_error)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,347 @@
+//! Runners and stores that keep the streams, snapshots and projection checkpoints of many
+//! tenants apart while they share the same stores.
+
+use std::{
+    cell::RefCell,
+    collections::{hash_map::Entry, HashMap},
+    fmt::{Debug, Display},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    deciders::{Decider, SnapshotSchema},
+    projections::{Checkpoint, CheckpointStore},
+    stores::{EventStore, SnapshotStore, StoreError},
+    utilities::{InMemoryRunner, StreamVersion},
+};
+
+/// The id of a tenant, such as a customer, whose data must be kept apart from every other
+/// tenant's.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TenantId(String);
+
+impl TenantId {
+    /// Constructs a new `TenantId` from the string `id`.
+    pub fn new(id: &str) -> Self {
+        Self(id.to_string())
+    }
+
+    /// Returns the id as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the key `key` namespaced to this tenant.
+    ///
+    /// The namespace starts with the length of the tenant id, so two tenants can never share a
+    /// key, even if their ids contain the separator.
+    fn namespace(&self, key: &str) -> String {
+        format!("{}:{}/{}", self.0.len(), self.0, key)
+    }
+}
+
+impl From<&str> for TenantId {
+    fn from(id: &str) -> Self {
+        Self::new(id)
+    }
+}
+
+impl Display for TenantId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The in-memory state of a tenant loaded by a [`TenantedRunner`].
+struct LoadedTenant<C, E, S, D>
+where
+    D: Decider<C, E, S, S>,
+{
+    runner: InMemoryRunner<C, E, S, D>,
+    version: StreamVersion,
+    last_used: Instant,
+}
+
+/// A type that runs the same decider for many tenants, keeping each tenant's stream in an
+/// [`EventStore`] and its snapshots in a [`SnapshotStore`] shared by every tenant.
+///
+/// Every stream id and snapshot key is namespaced with the tenant's [`TenantId`], and the
+/// commands and state of a tenant are only reachable through the [`TenantHandle`] returned by
+/// [`TenantedRunner::for_tenant`], which has no way to name another tenant. A tenant's state is
+/// loaded from the stores the first time its handle is used, from its latest snapshot and the
+/// events after it, and kept in memory until it is evicted with [`TenantHandle::evict`] or
+/// [`TenantedRunner::evict_idle`]. Evicting a tenant saves a snapshot of its state and drops it
+/// from memory, while its events stay in the store. Errors of either store are returned as a
+/// [`StoreError`].
+pub struct TenantedRunner<C, E, S, D, ES, SS>
+where
+    D: Decider<C, E, S, S>,
+{
+    stream_id: String,
+    events: RefCell<ES>,
+    snapshots: RefCell<SS>,
+    tenants: RefCell<HashMap<TenantId, LoadedTenant<C, E, S, D>>>,
+}
+
+impl<C, E, S, D, ES, SS> TenantedRunner<C, E, S, D, ES, SS>
+where
+    D: SnapshotSchema<S> + Decider<C, E, S, S>,
+    ES: EventStore<E>,
+    SS: SnapshotStore<D::Snapshot>,
+{
+    /// Constructs a new `TenantedRunner` that keeps the stream `stream_id` of every tenant in
+    /// `events` and its snapshots in `snapshots`.
+    pub fn new(stream_id: &str, events: ES, snapshots: SS) -> Self {
+        Self {
+            stream_id: stream_id.to_string(),
+            events: RefCell::new(events),
+            snapshots: RefCell::new(snapshots),
+            tenants: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Returns a handle to run commands for the tenant `tenant`. Its state is not loaded until
+    /// the handle is used.
+    pub fn for_tenant(&self, tenant: TenantId) -> TenantHandle<'_, C, E, S, D, ES, SS> {
+        TenantHandle {
+            runner: self,
+            tenant,
+        }
+    }
+
+    /// Returns whether the state of the tenant `tenant` is currently in memory.
+    pub fn is_loaded(&self, tenant: &TenantId) -> bool {
+        self.tenants.borrow().contains_key(tenant)
+    }
+
+    /// Evicts every tenant whose handle has not been used for at least `max_idle`, as with
+    /// [`TenantHandle::evict`], and returns their ids.
+    ///
+    /// If a tenant's snapshot cannot be saved, it stays in memory and the error is returned,
+    /// while the tenants evicted before it stay evicted.
+    pub fn evict_idle(&self, max_idle: Duration) -> Result<Vec<TenantId>, StoreError> {
+        let idle: Vec<TenantId> = self
+            .tenants
+            .borrow()
+            .iter()
+            .filter(|(_, loaded)| loaded.last_used.elapsed() >= max_idle)
+            .map(|(tenant, _)| tenant.clone())
+            .collect();
+        for tenant in &idle {
+            self.evict(tenant)?;
+        }
+        Ok(idle)
+    }
+
+    /// Consumes this runner, returning its event store and snapshot store.
+    pub fn into_stores(self) -> (ES, SS) {
+        (self.events.into_inner(), self.snapshots.into_inner())
+    }
+
+    /// Calls `f` with the in-memory state of the tenant `tenant`, loading it first if needed.
+    fn with_tenant<R>(
+        &self,
+        tenant: &TenantId,
+        f: impl FnOnce(&mut LoadedTenant<C, E, S, D>) -> R,
+    ) -> Result<R, StoreError> {
+        let mut tenants = self.tenants.borrow_mut();
+        let loaded = match tenants.entry(tenant.clone()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(self.load(tenant)?),
+        };
+        loaded.last_used = Instant::now();
+        Ok(f(loaded))
+    }
+
+    /// Loads the state of the tenant `tenant` from its latest snapshot and the events after it,
+    /// or from all of its events if it has no usable snapshot.
+    fn load(&self, tenant: &TenantId) -> Result<LoadedTenant<C, E, S, D>, StoreError> {
+        let key = tenant.namespace(&self.stream_id);
+        let events = self.events.borrow();
+        let version = events.version(&key)?;
+        let snapshot = self
+            .snapshots
+            .borrow()
+            .load(&key)?
+            .filter(|snapshot| snapshot.schema_version == D::SCHEMA_VERSION)
+            .filter(|snapshot| snapshot.taken_at_version <= version);
+        let runner = match snapshot {
+            Some(snapshot) => InMemoryRunner::hydrate(
+                &snapshot.state,
+                &events.load_from(&key, snapshot.taken_at_version)?,
+            ),
+            None => InMemoryRunner::from_events(&events.load(&key)?),
+        };
+        Ok(LoadedTenant {
+            runner,
+            version,
+            last_used: Instant::now(),
+        })
+    }
+
+    /// Saves a snapshot of the tenant `tenant` if it is loaded, returning whether it was.
+    fn save_snapshot(&self, tenant: &TenantId) -> Result<bool, StoreError> {
+        let tenants = self.tenants.borrow();
+        let Some(loaded) = tenants.get(tenant) else {
+            return Ok(false);
+        };
+        self.snapshots.borrow_mut().save(
+            &tenant.namespace(&self.stream_id),
+            &loaded.runner.versioned_snapshot(loaded.version),
+        )?;
+        Ok(true)
+    }
+
+    /// Saves a snapshot of the tenant `tenant` and drops it from memory, returning whether it
+    /// was loaded. If the snapshot cannot be saved, the tenant stays in memory.
+    fn evict(&self, tenant: &TenantId) -> Result<bool, StoreError> {
+        let saved = self.save_snapshot(tenant)?;
+        self.tenants.borrow_mut().remove(tenant);
+        Ok(saved)
+    }
+}
+
+impl<C, E, S, D, ES, SS> Debug for TenantedRunner<C, E, S, D, ES, SS>
+where
+    D: Decider<C, E, S, S>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TenantedRunner")
+            .field("stream_id", &self.stream_id)
+            .field(
+                "loaded_tenants",
+                &self.tenants.borrow().keys().collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+/// A handle to the commands and state of a single tenant of a [`TenantedRunner`].
+pub struct TenantHandle<'a, C, E, S, D, ES, SS>
+where
+    D: Decider<C, E, S, S>,
+{
+    runner: &'a TenantedRunner<C, E, S, D, ES, SS>,
+    tenant: TenantId,
+}
+
+impl<C, E, S, D, ES, SS> TenantHandle<'_, C, E, S, D, ES, SS>
+where
+    D: SnapshotSchema<S> + Decider<C, E, S, S>,
+    ES: EventStore<E>,
+    SS: SnapshotStore<D::Snapshot>,
+{
+    /// Returns the id of the tenant this handle belongs to.
+    pub fn tenant(&self) -> &TenantId {
+        &self.tenant
+    }
+
+    /// Feeds the given command `command` through the decider, appends the generated events to
+    /// the tenant's stream, and returns them.
+    ///
+    /// If the event store rejects the events, such as because something else appended to the
+    /// tenant's stream in the meantime, the tenant's in-memory state is dropped, so that the next
+    /// call loads it from the store again, and the error is returned.
+    pub fn command(&self, command: &C) -> Result<Vec<E>, StoreError> {
+        let runner = self.runner;
+        let appended = runner.with_tenant(&self.tenant, |loaded| {
+            let events = loaded.runner.command(command);
+            let version = runner.events.borrow_mut().append(
+                &self.tenant.namespace(&runner.stream_id),
+                loaded.version,
+                &events,
+            )?;
+            loaded.version = version;
+            Ok(events)
+        })?;
+        if appended.is_err() {
+            runner.tenants.borrow_mut().remove(&self.tenant);
+        }
+        appended
+    }
+
+    /// Returns a copy of the tenant's current state.
+    pub fn state(&self) -> Result<S, StoreError>
+    where
+        S: Clone,
+    {
+        self.runner
+            .with_tenant(&self.tenant, |loaded| loaded.runner.get_state().clone())
+    }
+
+    /// Returns the version of the tenant's stream, which is the number of events in it.
+    pub fn version(&self) -> Result<StreamVersion, StoreError> {
+        self.runner
+            .with_tenant(&self.tenant, |loaded| loaded.version)
+    }
+
+    /// Returns every event of the tenant's stream, in order, as read from the event store.
+    pub fn events(&self) -> Result<Vec<E>, StoreError> {
+        self.runner
+            .events
+            .borrow()
+            .load(&self.tenant.namespace(&self.runner.stream_id))
+    }
+
+    /// Saves a snapshot of the tenant's current state to the snapshot store.
+    pub fn snapshot(&self) -> Result<(), StoreError> {
+        self.runner.with_tenant(&self.tenant, |_| ())?;
+        self.runner.save_snapshot(&self.tenant)?;
+        Ok(())
+    }
+
+    /// Saves a snapshot of the tenant's state and drops it from memory, returning whether it was
+    /// loaded. The next use of a handle for the tenant loads it again from the stores.
+    pub fn evict(&self) -> Result<bool, StoreError> {
+        self.runner.evict(&self.tenant)
+    }
+
+    /// Wraps the checkpoint store `store` so that the checkpoints of projections, such as those
+    /// run by a [`ProjectionRunner`](crate::projections::ProjectionRunner) over
+    /// [`TenantHandle::events`], are namespaced to the tenant.
+    pub fn checkpoints<CS>(&self, store: CS) -> TenantCheckpoints<CS> {
+        TenantCheckpoints {
+            tenant: self.tenant.clone(),
+            store,
+        }
+    }
+}
+
+impl<C, E, S, D, ES, SS> Debug for TenantHandle<'_, C, E, S, D, ES, SS>
+where
+    D: Decider<C, E, S, S>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TenantHandle")
+            .field("tenant", &self.tenant)
+            .finish()
+    }
+}
+
+/// A [`CheckpointStore`] that namespaces the names of projections with a tenant's id before
+/// passing them to the wrapped store, as returned by [`TenantHandle::checkpoints`].
+#[derive(Clone, Debug)]
+pub struct TenantCheckpoints<CS> {
+    tenant: TenantId,
+    store: CS,
+}
+
+impl<CS> TenantCheckpoints<CS> {
+    /// Consumes this wrapper, returning the wrapped checkpoint store.
+    pub fn into_inner(self) -> CS {
+        self.store
+    }
+}
+
+impl<V, CS> CheckpointStore<V> for TenantCheckpoints<CS>
+where
+    CS: CheckpointStore<V>,
+{
+    fn load(&self, name: &str) -> Option<Checkpoint<V>> {
+        self.store.load(&self.tenant.namespace(name))
+    }
+
+    fn save(&mut self, name: &str, checkpoint: &Checkpoint<V>) {
+        self.store.save(&self.tenant.namespace(name), checkpoint)
+    }
+}
@@ -0,0 +1,400 @@
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+    marker::PhantomData,
+    mem::{discriminant, Discriminant},
+};
+
+use crate::deciders::Decider;
+use crate::projections::Projection;
+
+/// A helper for testing projections in a "given events, expect view" style.
+///
+/// Each expectation method folds the given events through the projection `P` and panics with a
+/// descriptive message if the expectation is not met, so these can be used directly inside
+/// `#[test]` functions.
+pub struct ProjectionSpec<'a, P, E>
+where
+    P: Projection<E>,
+{
+    events: &'a [E],
+    projection: PhantomData<P>,
+}
+
+impl<'a, P, E> ProjectionSpec<'a, P, E>
+where
+    P: Projection<E>,
+    P::View: Debug,
+    E: Debug,
+{
+    /// Constructs a new `ProjectionSpec` that will apply the events `events`, in order, starting
+    /// from the projection's initial view.
+    pub fn given(events: &'a [E]) -> Self {
+        Self {
+            events,
+            projection: PhantomData,
+        }
+    }
+
+    fn fold(&self) -> P::View {
+        let mut view = P::initial();
+        for e in self.events {
+            P::apply_mut(&mut view, e);
+        }
+        view
+    }
+
+    /// Asserts that the view after applying every event equals `expected`.
+    pub fn expect_view(self, expected: P::View)
+    where
+        P::View: PartialEq,
+    {
+        let view = self.fold();
+        assert!(
+            view == expected,
+            "unexpected view after {} events\n  expected: {expected:?}\n    actual: {view:?}",
+            self.events.len()
+        );
+    }
+
+    /// Asserts that the view after applying every event satisfies the predicate `predicate`.
+    pub fn expect(self, predicate: impl FnOnce(&P::View) -> bool) {
+        let view = self.fold();
+        assert!(
+            predicate(&view),
+            "view after {} events did not satisfy the expectation: {view:?}",
+            self.events.len()
+        );
+    }
+
+    /// Asserts that the view after applying each event equals the corresponding view in
+    /// `expected`, reporting the index of the first event after which they differ.
+    ///
+    /// `expected` must contain exactly one view per given event.
+    pub fn expect_views(self, expected: &[P::View])
+    where
+        P::View: PartialEq,
+    {
+        assert_eq!(
+            expected.len(),
+            self.events.len(),
+            "expected {} views, one per given event, but got {}",
+            self.events.len(),
+            expected.len()
+        );
+        let mut view = P::initial();
+        for (i, (event, expected)) in self.events.iter().zip(expected).enumerate() {
+            P::apply_mut(&mut view, event);
+            assert!(
+                view == *expected,
+                "unexpected view after event {i} ({event:?})\n  expected: {expected:?}\n    actual: {view:?}"
+            );
+        }
+    }
+}
+
+/// How evolving a state of one variant with an event of one variant is expected to behave,
+/// checked by [`assert_transition_coverage`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TransitionKind {
+    /// Evolving results in a state of a different variant.
+    Changes,
+    /// Evolving results in a state of the same variant, although its fields may change.
+    NoOp,
+    /// The event can never be applied to the state, so evolving is not checked.
+    Unreachable,
+}
+
+/// The expected [`TransitionKind`] of every pair of a state variant and an event variant, for
+/// [`assert_transition_coverage`].
+///
+/// Variants are identified by an example value, so a state or event with fields can be given with
+/// any values for them.
+pub struct TransitionExpectations<S, E> {
+    kinds: HashMap<(Discriminant<S>, Discriminant<E>), TransitionKind>,
+}
+
+impl<S, E> TransitionExpectations<S, E> {
+    /// Constructs a new `TransitionExpectations` with no pair classified.
+    pub fn new() -> Self {
+        Self {
+            kinds: HashMap::new(),
+        }
+    }
+
+    /// Classifies evolving states of the variant of `state` with events of the variant of `event`
+    /// as `kind`, replacing any previous classification of the pair.
+    pub fn expect(mut self, state: &S, event: &E, kind: TransitionKind) -> Self {
+        self.kinds
+            .insert((discriminant(state), discriminant(event)), kind);
+        self
+    }
+
+    /// Classifies the pair of the variants of `state` and `event` as [`TransitionKind::Changes`].
+    pub fn changes(self, state: &S, event: &E) -> Self {
+        self.expect(state, event, TransitionKind::Changes)
+    }
+
+    /// Classifies the pair of the variants of `state` and `event` as [`TransitionKind::NoOp`].
+    pub fn no_op(self, state: &S, event: &E) -> Self {
+        self.expect(state, event, TransitionKind::NoOp)
+    }
+
+    /// Classifies the pair of the variants of `state` and `event` as
+    /// [`TransitionKind::Unreachable`].
+    pub fn unreachable(self, state: &S, event: &E) -> Self {
+        self.expect(state, event, TransitionKind::Unreachable)
+    }
+
+    fn kind(&self, state: &S, event: &E) -> Option<TransitionKind> {
+        self.kinds
+            .get(&(discriminant(state), discriminant(event)))
+            .copied()
+    }
+}
+
+impl<S, E> Default for TransitionExpectations<S, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S, E> Debug for TransitionExpectations<S, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransitionExpectations")
+            .field("pairs", &self.kinds.len())
+            .finish()
+    }
+}
+
+/// A trait for state types that can list an example of each of their variants, so the lists
+/// given to [`assert_transition_coverage`] are kept next to the type instead of in each test.
+pub trait EnumerableState: Sized {
+    /// Returns one example value of each variant.
+    fn exemplars() -> Vec<Self>;
+}
+
+/// A trait for event types that can list an example of each of their variants, so the lists
+/// given to [`assert_transition_coverage`] are kept next to the type instead of in each test.
+pub trait EnumerableEvent: Sized {
+    /// Returns one example value of each variant.
+    fn exemplars() -> Vec<Self>;
+}
+
+/// Asserts that evolving each state in `states` with each event in `events` through the decider
+/// `D` behaves as classified in `expectations`, so that a catch-all arm in `evolve` cannot hide a
+/// missing transition.
+///
+/// Whether a transition changes the state is decided by comparing the variants of the state
+/// before and after evolving. Every pair must be classified; unclassified pairs are reported
+/// along with the pairs that behave differently than expected, in a single table.
+pub fn assert_transition_coverage<C, E, S, D>(
+    states: &[S],
+    events: &[E],
+    expectations: &TransitionExpectations<S, E>,
+) where
+    E: Debug,
+    S: Debug,
+    D: Decider<C, E, S, S>,
+{
+    let mut mismatches = vec![];
+    for state in states {
+        for event in events {
+            let expected = expectations.kind(state, event);
+            if expected == Some(TransitionKind::Unreachable) {
+                continue;
+            }
+            let actual = if discriminant(&D::evolve(state, event)) == discriminant(state) {
+                TransitionKind::NoOp
+            } else {
+                TransitionKind::Changes
+            };
+            if expected != Some(actual) {
+                let expected = match expected {
+                    Some(kind) => format!("{kind:?}"),
+                    None => "unclassified".to_string(),
+                };
+                mismatches.push(format!(
+                    "  {state:?} + {event:?}: expected {expected}, actual {actual:?}"
+                ));
+            }
+        }
+    }
+    assert!(
+        mismatches.is_empty(),
+        "{} transition(s) did not match their expectations:\n{}",
+        mismatches.len(),
+        mismatches.join("\n")
+    );
+}
+
+/// Asserts that the decider `D` behaves as classified in `expectations` for every pair of the
+/// [`exemplars`](EnumerableState::exemplars) of its states and events, like
+/// [`assert_transition_coverage`].
+pub fn assert_enumerated_transition_coverage<C, E, S, D>(
+    expectations: &TransitionExpectations<S, E>,
+) where
+    E: Debug + EnumerableEvent,
+    S: Debug + EnumerableState,
+    D: Decider<C, E, S, S>,
+{
+    assert_transition_coverage::<C, E, S, D>(&S::exemplars(), &E::exemplars(), expectations);
+}
+
+/// The first point at which two deciders replaying the same events stopped agreeing, found by
+/// [`compare_replays`] or [`compare_replays_with_commands`].
+///
+/// States, events and commands are given by their [`Debug`] representations, since the two
+/// deciders may have different state types.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Divergence {
+    /// The initial states of the deciders are not equivalent.
+    InitialState {
+        /// The initial state of the first decider.
+        left: String,
+        /// The initial state of the second decider.
+        right: String,
+    },
+    /// The states of the deciders are not equivalent after evolving them with an event.
+    State {
+        /// The index of the event.
+        index: usize,
+        /// The event.
+        event: String,
+        /// The state of the first decider after the event.
+        left: String,
+        /// The state of the second decider after the event.
+        right: String,
+    },
+    /// The deciders decided different events for a command.
+    Decision {
+        /// The number of events applied to the states before deciding.
+        applied: usize,
+        /// The command.
+        command: String,
+        /// The events decided by the first decider.
+        left: String,
+        /// The events decided by the second decider.
+        right: String,
+    },
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Divergence::InitialState { left, right } => {
+                write!(f, "initial states diverge\n  left: {left}\n right: {right}")
+            }
+            Divergence::State {
+                index,
+                event,
+                left,
+                right,
+            } => write!(
+                f,
+                "states diverge after event {index} ({event})\n  left: {left}\n right: {right}"
+            ),
+            Divergence::Decision {
+                applied,
+                command,
+                left,
+                right,
+            } => write!(
+                f,
+                "decisions for {command} diverge after {applied} events\n  left: {left}\n right: {right}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+/// Folds the events `events` through the deciders `D1` and `D2`, such as two versions of the
+/// same decider, and checks that their states are equivalent according to `equiv` initially and
+/// after every event.
+///
+/// Returns the first [`Divergence`] found, if any.
+pub fn compare_replays<C, E, S1, S2, D1, D2>(
+    events: &[E],
+    equiv: impl Fn(&S1, &S2) -> bool,
+) -> Result<(), Divergence>
+where
+    E: Debug,
+    S1: Debug,
+    S2: Debug,
+    D1: Decider<C, E, S1, S1>,
+    D2: Decider<C, E, S2, S2>,
+{
+    replay::<C, E, S1, S2, D1, D2>(events, equiv, |_, _, _| Ok(()))
+}
+
+/// Compares the deciders `D1` and `D2` like [`compare_replays`], and additionally checks that
+/// they decide the same events for each command in `commands` initially and after every event.
+pub fn compare_replays_with_commands<C, E, S1, S2, D1, D2>(
+    events: &[E],
+    commands: &[C],
+    equiv: impl Fn(&S1, &S2) -> bool,
+) -> Result<(), Divergence>
+where
+    C: Debug,
+    E: Debug + PartialEq,
+    S1: Debug,
+    S2: Debug,
+    D1: Decider<C, E, S1, S1>,
+    D2: Decider<C, E, S2, S2>,
+{
+    replay::<C, E, S1, S2, D1, D2>(events, equiv, |applied, left, right| {
+        for command in commands {
+            let left_events = D1::decide(command, left);
+            let right_events = D2::decide(command, right);
+            if left_events != right_events {
+                return Err(Divergence::Decision {
+                    applied,
+                    command: format!("{command:?}"),
+                    left: format!("{left_events:?}"),
+                    right: format!("{right_events:?}"),
+                });
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Folds `events` through both deciders, checking the states with `equiv` and calling
+/// `check_decisions` with the number of events applied and both states before each event and
+/// after the last one.
+fn replay<C, E, S1, S2, D1, D2>(
+    events: &[E],
+    equiv: impl Fn(&S1, &S2) -> bool,
+    mut check_decisions: impl FnMut(usize, &S1, &S2) -> Result<(), Divergence>,
+) -> Result<(), Divergence>
+where
+    E: Debug,
+    S1: Debug,
+    S2: Debug,
+    D1: Decider<C, E, S1, S1>,
+    D2: Decider<C, E, S2, S2>,
+{
+    let mut left = D1::initial_state();
+    let mut right = D2::initial_state();
+    if !equiv(&left, &right) {
+        return Err(Divergence::InitialState {
+            left: format!("{left:?}"),
+            right: format!("{right:?}"),
+        });
+    }
+    for (index, event) in events.iter().enumerate() {
+        check_decisions(index, &left, &right)?;
+        left = D1::evolve(&left, event);
+        right = D2::evolve(&right, event);
+        if !equiv(&left, &right) {
+            return Err(Divergence::State {
+                index,
+                event: format!("{event:?}"),
+                left: format!("{left:?}"),
+                right: format!("{right:?}"),
+            });
+        }
+    }
+    check_decisions(events.len(), &left, &right)
+}
@@ -1,10 +1,36 @@
-use std::{fmt::Debug, marker::PhantomData};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    fmt::{Debug, Display},
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    ops::{Add, DerefMut, Sub},
+    rc::Rc,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
 
-use crate::deciders::Decider;
+use crate::{
+    deciders::{
+        evolve_all_in_place, fold_events, ContextDecider, ContextProvider, Decider, DynDecider,
+        ProvidedContext, RejectingDecider, Snapshot, SnapshotSchema, Snapshotting, TryDecider,
+        Validator,
+    },
+    projections::ConsistencyToken,
+};
+
+#[cfg(feature = "serde")]
+use crate::deciders::SnapshotMigrator;
 
 /// A simple enum representing one of two types.
 ///
 /// This is a reimplementation of the `Either` type as seen in Haskell or F#.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Either<L, R> {
     /// A value of the left type, `L`.
@@ -13,6 +39,91 @@ pub enum Either<L, R> {
     Right(R),
 }
 
+impl<L, R> Either<L, R> {
+    /// Converts from `&Either<L, R>` to `Either<&L, &R>`.
+    pub fn as_ref(&self) -> Either<&L, &R> {
+        match self {
+            Either::Left(l) => Either::Left(l),
+            Either::Right(r) => Either::Right(r),
+        }
+    }
+}
+
+/// A simple enum representing one of three types, like [`Either`] for three types.
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OneOf3<A, B, C> {
+    /// A value of the first type, `A`.
+    First(A),
+    /// A value of the second type, `B`.
+    Second(B),
+    /// A value of the third type, `C`.
+    Third(C),
+}
+
+/// A simple enum representing one of four types, like [`Either`] for four types.
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OneOf4<A, B, C, D> {
+    /// A value of the first type, `A`.
+    First(A),
+    /// A value of the second type, `B`.
+    Second(B),
+    /// A value of the third type, `C`.
+    Third(C),
+    /// A value of the fourth type, `D`.
+    Fourth(D),
+}
+
+/// Splits a composed stream of events, such as those produced by
+/// [`crate::deciders::ComposedDeciders`], into the `Left` and `Right` events, keeping the order
+/// of each side.
+///
+/// Both vectors are allocated up front for the least number of events the iterator reports.
+pub fn split_either<L, R>(events: impl IntoIterator<Item = Either<L, R>>) -> (Vec<L>, Vec<R>) {
+    let events = events.into_iter();
+    let (len, _) = events.size_hint();
+    let mut lefts = Vec::with_capacity(len);
+    let mut rights = Vec::with_capacity(len);
+    for event in events {
+        match event {
+            Either::Left(l) => lefts.push(l),
+            Either::Right(r) => rights.push(r),
+        }
+    }
+    (lefts, rights)
+}
+
+/// Returns an iterator over only the `Left` values of `events`, in order.
+pub fn lefts<L, R>(events: impl IntoIterator<Item = Either<L, R>>) -> impl Iterator<Item = L> {
+    events.into_iter().filter_map(|e| match e {
+        Either::Left(l) => Some(l),
+        Either::Right(_) => None,
+    })
+}
+
+/// Returns an iterator over only the `Right` values of `events`, in order.
+pub fn rights<L, R>(events: impl IntoIterator<Item = Either<L, R>>) -> impl Iterator<Item = R> {
+    events.into_iter().filter_map(|e| match e {
+        Either::Left(_) => None,
+        Either::Right(r) => Some(r),
+    })
+}
+
+/// Splits a keyed stream of events, such as those produced by
+/// [`crate::deciders::ManyDecider`], into a HashMap mapping each key to its events, keeping the
+/// order of each key's events.
+pub fn partition_keyed<K, E>(events: impl IntoIterator<Item = (K, E)>) -> HashMap<K, Vec<E>>
+where
+    K: Eq + Hash,
+{
+    let mut partitions: HashMap<K, Vec<E>> = HashMap::new();
+    for (key, event) in events {
+        partitions.entry(key).or_default().push(event);
+    }
+    partitions
+}
+
 /// A simple trait used for converting one type to another, with the option for failure by
 /// returning `None`
 ///
@@ -32,6 +143,320 @@ pub trait InfallibleConverter<I, O> {
     fn convert(input: &I) -> O;
 }
 
+/// A trait for states that can be reduced to a cheap fingerprint, for quickly telling whether
+/// two states may be equal.
+///
+/// Equal states must have equal fingerprints, and fingerprints of the same state are the same
+/// across runs, but two different states may share a fingerprint, so an exact comparison is still
+/// needed when they match.
+///
+/// States that implement [`Hash`] can implement this with [`hash_fingerprint`]. It is implemented
+/// for the primitive types and the composite states built by this crate's deciders: tuples,
+/// options, vectors and HashMaps, whose fingerprint does not depend on the order of their entries.
+pub trait StateFingerprint {
+    /// Returns the fingerprint of this state.
+    fn fingerprint(&self) -> u64;
+}
+
+/// Returns the fingerprint of `value` computed from its [`Hash`] implementation.
+///
+/// The bytes written by the `Hash` implementation are hashed with 64-bit FNV-1a, with integers
+/// written in little-endian order and `usize`s widened to 64 bits. Unlike the standard library's
+/// default hasher, whose algorithm may change between releases, the fingerprint of a value is
+/// therefore the same across toolchains and platforms for as long as its `Hash` implementation
+/// does not change, so fingerprints can be stored.
+pub fn hash_fingerprint<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = FnvHasher::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A 64-bit FNV-1a hasher, used by [`hash_fingerprint`].
+struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+}
+
+/// Returns the fingerprint of `value` computed from its serialized form, for states that
+/// implement [`serde::Serialize`] but not [`Hash`].
+///
+/// Serialized maps list their entries in iteration order, so unlike the fingerprint of a
+/// HashMap, this depends on the order of entries in any maps inside the state.
+///
+/// Requires the `serde` feature.
+#[cfg(feature = "serde")]
+pub fn serialized_fingerprint<T: serde::Serialize + ?Sized>(value: &T) -> u64 {
+    hash_fingerprint(&serde_json::to_vec(value).expect("state should serialize to JSON"))
+}
+
+macro_rules! impl_hash_fingerprint {
+    ($($ty:ty),*) => {
+        $(
+            impl StateFingerprint for $ty {
+                fn fingerprint(&self) -> u64 {
+                    hash_fingerprint(self)
+                }
+            }
+        )*
+    };
+}
+
+impl_hash_fingerprint!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    str,
+    String
+);
+
+impl<T: StateFingerprint> StateFingerprint for Option<T> {
+    fn fingerprint(&self) -> u64 {
+        hash_fingerprint(&self.as_ref().map(T::fingerprint))
+    }
+}
+
+impl<T: StateFingerprint> StateFingerprint for [T] {
+    fn fingerprint(&self) -> u64 {
+        let fingerprints: Vec<_> = self.iter().map(T::fingerprint).collect();
+        hash_fingerprint(&fingerprints)
+    }
+}
+
+impl<T: StateFingerprint> StateFingerprint for Vec<T> {
+    fn fingerprint(&self) -> u64 {
+        self.as_slice().fingerprint()
+    }
+}
+
+impl<T1: StateFingerprint, T2: StateFingerprint> StateFingerprint for (T1, T2) {
+    fn fingerprint(&self) -> u64 {
+        hash_fingerprint(&(self.0.fingerprint(), self.1.fingerprint()))
+    }
+}
+
+impl<T1: StateFingerprint, T2: StateFingerprint, T3: StateFingerprint> StateFingerprint
+    for (T1, T2, T3)
+{
+    fn fingerprint(&self) -> u64 {
+        hash_fingerprint(&(
+            self.0.fingerprint(),
+            self.1.fingerprint(),
+            self.2.fingerprint(),
+        ))
+    }
+}
+
+impl<K, V, H> StateFingerprint for HashMap<K, V, H>
+where
+    K: Hash,
+    V: StateFingerprint,
+{
+    fn fingerprint(&self) -> u64 {
+        // Summing the entries' fingerprints makes the result independent of the iteration order.
+        let entries = self
+            .iter()
+            .map(|(k, v)| hash_fingerprint(&(k, v.fingerprint())))
+            .fold(0u64, u64::wrapping_add);
+        hash_fingerprint(&(self.len(), entries))
+    }
+}
+
+/// The version of a single stream, such as the number of times a process' state has been saved
+/// to a [`ProcessStateStore`](crate::processes::ProcessStateStore).
+///
+/// Versions start at 0, before anything has been written, and only move forward, so the only
+/// arithmetic supported is advancing by a number of steps and taking the distance between two
+/// versions. Unlike a plain `u64`, a version cannot be mixed up with a [`GlobalPosition`]:
+///
+/// ```compile_fail
+/// use deciders_rs::utilities::{GlobalPosition, StreamVersion};
+///
+/// let version: StreamVersion = GlobalPosition::from(3);
+/// ```
+///
+/// Code written against the previous `u64` versions can be migrated with the `From<u64>`
+/// implementation, e.g. `StreamVersion::from(2)`, and `u64::from(version)` gets the number back.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamVersion(u64);
+
+impl StreamVersion {
+    /// The version of a stream nothing has been written to.
+    pub const INITIAL: Self = Self(0);
+
+    /// Returns the version directly following this one.
+    pub fn next(self) -> Self {
+        self + 1
+    }
+}
+
+/// A position in a log shared by many streams, such as an
+/// [`EventLog`](crate::projections::EventLog), counted from the start of the log.
+///
+/// Positions only move forward, so the only arithmetic supported is advancing by a number of
+/// events and taking the distance between two positions. Projections check this as they consume
+/// an event log, and a [`Checkpoint`](crate::projections::Checkpoint) refuses to move back.
+/// Unlike a plain `u64`, a position cannot be mixed up with a [`StreamVersion`]:
+///
+/// ```compile_fail
+/// use deciders_rs::utilities::{GlobalPosition, StreamVersion};
+///
+/// let position: GlobalPosition = StreamVersion::from(3);
+/// ```
+///
+/// Code written against the previous `u64` positions can be migrated with the `From<u64>`
+/// implementation, e.g. `GlobalPosition::from(2)`, and `u64::from(position)` gets the number
+/// back.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GlobalPosition(u64);
+
+macro_rules! impl_counter {
+    ($($ty:ty),*) => {
+        $(
+            impl From<u64> for $ty {
+                fn from(value: u64) -> Self {
+                    Self(value)
+                }
+            }
+
+            impl From<$ty> for u64 {
+                fn from(value: $ty) -> Self {
+                    value.0
+                }
+            }
+
+            impl Display for $ty {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    write!(f, "{}", self.0)
+                }
+            }
+
+            impl Add<u64> for $ty {
+                type Output = Self;
+
+                fn add(self, steps: u64) -> Self {
+                    Self(self.0 + steps)
+                }
+            }
+
+            impl Sub for $ty {
+                type Output = u64;
+
+                /// Returns the number of steps from `earlier` to `self`. Panics in debug builds
+                /// if `earlier` is actually later, since that means something moved backwards.
+                fn sub(self, earlier: Self) -> u64 {
+                    debug_assert!(
+                        earlier <= self,
+                        "{} is later than {}, but should be earlier",
+                        earlier,
+                        self
+                    );
+                    self.0 - earlier.0
+                }
+            }
+        )*
+    };
+}
+
+impl_counter!(StreamVersion, GlobalPosition);
+
+/// A set of state fingerprints, such as those of the states reached while exploring a decider
+/// with [`explore`](crate::viz::explore).
+///
+/// With the `serde` feature, the set can be serialized, so that it can be computed once, for
+/// example at build time, and loaded by a [`StateSpaceMonitor`].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FingerprintSet {
+    fingerprints: BTreeSet<u64>,
+}
+
+impl FingerprintSet {
+    /// Constructs a new, empty `FingerprintSet`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the fingerprint `fingerprint` to the set, returning whether it was not already in it.
+    pub fn insert(&mut self, fingerprint: u64) -> bool {
+        self.fingerprints.insert(fingerprint)
+    }
+
+    /// Returns whether the fingerprint `fingerprint` is in the set.
+    pub fn contains(&self, fingerprint: u64) -> bool {
+        self.fingerprints.contains(&fingerprint)
+    }
+
+    /// Returns the number of fingerprints in the set.
+    pub fn len(&self) -> usize {
+        self.fingerprints.len()
+    }
+
+    /// Returns whether the set has no fingerprints.
+    pub fn is_empty(&self) -> bool {
+        self.fingerprints.is_empty()
+    }
+}
+
+impl FromIterator<u64> for FingerprintSet {
+    fn from_iter<I: IntoIterator<Item = u64>>(iter: I) -> Self {
+        Self {
+            fingerprints: iter.into_iter().collect(),
+        }
+    }
+}
+
 /// A type that wraps a decider type and stores that decider's state type internally and exposing a
 /// simpler interface.
 ///
@@ -73,22 +498,155 @@ where
         }
     }
 
+    /// Constructs a new `InMemoryRunner`, initializing the state to the state of the decider after
+    /// every event in `events`.
+    pub fn from_events(events: &[E]) -> Self {
+        Self::with_state(D::fold(events))
+    }
+
+    /// Constructs a new `InMemoryRunner` from the snapshot `snapshot`, bringing its state up to
+    /// date with `tail`, the events written after the snapshot was taken.
+    pub fn hydrate(snapshot: &D::Snapshot, tail: &[E]) -> Self
+    where
+        D: Snapshotting<S>,
+    {
+        Self::with_state(fold_events::<C, E, S, S, D>(
+            D::from_snapshot(snapshot),
+            tail,
+        ))
+    }
+
+    /// Returns a snapshot of the current state of the decider.
+    pub fn snapshot(&self) -> D::Snapshot
+    where
+        D: Snapshotting<S>,
+    {
+        D::to_snapshot(&self.state)
+    }
+
+    /// Returns a snapshot of the current state of the decider, ready to be persisted, recording
+    /// the decider's schema version and the stream version `taken_at_version` the state is at.
+    pub fn versioned_snapshot(&self, taken_at_version: StreamVersion) -> Snapshot<D::Snapshot>
+    where
+        D: SnapshotSchema<S>,
+    {
+        Snapshot::of::<D, S>(&self.state, taken_at_version)
+    }
+
+    /// Constructs a new `InMemoryRunner` from the persisted [`Snapshot`] `stored` and `events`,
+    /// the whole stream it was taken from.
+    ///
+    /// A snapshot written with an older schema version is converted with
+    /// [`SnapshotMigrator::migrate`], and the events after the version it was taken at are then
+    /// evolved. If the snapshot cannot be read, cannot be migrated, was written with a newer
+    /// schema, or is ahead of `events`, it is ignored and every event is replayed instead.
+    ///
+    /// Requires the `serde` feature.
+    #[cfg(feature = "serde")]
+    pub fn restore(stored: &serde_json::Value, events: &[E]) -> Self
+    where
+        D: SnapshotMigrator<S>,
+        D::Snapshot: serde::de::DeserializeOwned,
+    {
+        let restored = <Snapshot<serde_json::Value> as serde::Deserialize>::deserialize(stored)
+            .ok()
+            .and_then(|stored| {
+                let tail = usize::try_from(u64::from(stored.taken_at_version))
+                    .ok()
+                    .and_then(|taken_at| events.get(taken_at..))?;
+                let snapshot = match stored.schema_version.cmp(&D::SCHEMA_VERSION) {
+                    std::cmp::Ordering::Less => {
+                        D::migrate(stored.schema_version, stored.state).ok()
+                    }
+                    std::cmp::Ordering::Equal => serde_json::from_value(stored.state).ok(),
+                    std::cmp::Ordering::Greater => None,
+                }?;
+                Some(Self::hydrate(&snapshot, tail))
+            });
+        restored.unwrap_or_else(|| Self::from_events(events))
+    }
+
     /// Feeds the given command `command` through the decider and returns the generated list of
     /// events.
     ///
     /// Also evolves the internal state of the decider according to the generated events.
     pub fn command(&mut self, command: &C) -> Vec<E> {
         let events = D::decide(command, &self.state);
-        for e in events.iter() {
-            self.state = D::evolve(&self.state, e);
-        }
+        evolve_all_in_place(&mut self.state, &events, D::evolve_in_place);
         events
     }
 
+    /// Feeds each command in `commands` through the decider in order, like
+    /// [`InMemoryRunner::command`], and returns the events generated by each command.
+    ///
+    /// Commands after the decider has reached a terminal state are still decided against that
+    /// state.
+    pub fn commands(&mut self, commands: &[C]) -> Vec<Vec<E>> {
+        commands.iter().map(|c| self.command(c)).collect()
+    }
+
+    /// Feeds the given command `command` through the decider like [`InMemoryRunner::command`],
+    /// appends the generated events to `log`, and returns them along with a
+    /// [`ConsistencyToken`] for the end of `log`.
+    ///
+    /// Readers can pass the token to
+    /// [`ProjectionRunner::wait_for`](crate::projections::ProjectionRunner::wait_for) to wait
+    /// until a projection of `log` reflects the command.
+    pub fn command_into_log(&mut self, command: &C, log: &mut Vec<E>) -> (Vec<E>, ConsistencyToken)
+    where
+        E: Clone,
+    {
+        let events = self.command(command);
+        log.extend(events.iter().cloned());
+        (events, ConsistencyToken::after(log))
+    }
+
     /// Returns a reference to the current state of the decider.
     pub fn get_state(&self) -> &S {
         &self.state
     }
+
+    /// Returns the fingerprint of the current state of the decider.
+    pub fn state_fingerprint(&self) -> u64
+    where
+        S: StateFingerprint,
+    {
+        self.state.fingerprint()
+    }
+}
+
+impl<C, E, R, S, D> InMemoryRunner<C, Either<E, R>, S, RejectingDecider<D, R>>
+where
+    D: Decider<C, E, S, S> + Validator<C, R, S>,
+    S: Clone,
+{
+    /// Feeds the given command `command` through the decider and returns the generated events
+    /// and rejections separately.
+    ///
+    /// Also evolves the internal state of the decider according to the generated events.
+    pub fn command_with_rejections(&mut self, command: &C) -> (Vec<E>, Vec<R>) {
+        split_either(self.command(command))
+    }
+}
+
+impl<C, E, S, D, P, Ctx> InMemoryRunner<C, E, S, ProvidedContext<D, P, Ctx>>
+where
+    D: ContextDecider<Ctx, C, E, S, S>,
+    P: ContextProvider<Ctx>,
+{
+    /// Feeds the given command `command` through the decider with the context `context` instead of
+    /// the one from the provider, and returns the generated list of events.
+    ///
+    /// Also evolves the internal state of the decider according to the generated events.
+    pub fn command_with_context(&mut self, command: &C, context: &Ctx) -> Vec<E> {
+        let events = D::decide(command, &self.state, context);
+        evolve_all_in_place(
+            &mut self.state,
+            &events,
+            ProvidedContext::<D, P, Ctx>::evolve_in_place,
+        );
+        events
+    }
 }
 
 impl<C, E, S, D> Default for InMemoryRunner<C, E, S, D>
@@ -111,3 +669,1886 @@ where
             .finish()
     }
 }
+
+/// A runner like [`InMemoryRunner`], but for a [`TryDecider`], which can reject commands.
+pub struct FallibleInMemoryRunner<C, E, S, D>
+where
+    D: TryDecider<C, E, S, S>,
+{
+    state: S,
+    command: PhantomData<C>,
+    event: PhantomData<E>,
+    decider: PhantomData<D>,
+}
+
+impl<C, E, S, D> FallibleInMemoryRunner<C, E, S, D>
+where
+    D: TryDecider<C, E, S, S>,
+{
+    /// Constructs a new `FallibleInMemoryRunner`, initializing the state to the default initial
+    /// state of the decider.
+    pub fn new() -> Self {
+        Self::with_state(D::initial_state())
+    }
+
+    /// Constructs a new `FallibleInMemoryRunner`, initializing the state to the given state.
+    pub fn with_state(state: S) -> Self {
+        Self {
+            state,
+            command: PhantomData,
+            event: PhantomData,
+            decider: PhantomData,
+        }
+    }
+
+    /// Feeds the given command `command` through the decider and returns the generated list of
+    /// events, or the decider's error if it rejected the command.
+    ///
+    /// Also evolves the internal state of the decider according to the generated events. The
+    /// state is left unchanged if the command is rejected.
+    pub fn command(&mut self, command: &C) -> Result<Vec<E>, D::Error> {
+        let events = D::decide(command, &self.state)?;
+        evolve_all_in_place(&mut self.state, &events, D::evolve_in_place);
+        Ok(events)
+    }
+
+    /// Returns a reference to the current state of the decider.
+    pub fn get_state(&self) -> &S {
+        &self.state
+    }
+}
+
+impl<C, E, S, D> Default for FallibleInMemoryRunner<C, E, S, D>
+where
+    D: TryDecider<C, E, S, S>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C, E, S, D> Debug for FallibleInMemoryRunner<C, E, S, D>
+where
+    D: TryDecider<C, E, S, S>,
+    S: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FallibleInMemoryRunner")
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+/// A runner like [`InMemoryRunner`], but for a decider chosen at runtime, such as from
+/// configuration, and stored as a boxed [`DynDecider`].
+///
+/// Use [`Statically`](crate::deciders::Statically) to run a [`Decider`] with this runner.
+pub struct DynRunner<C, E, S> {
+    decider: Box<dyn DynDecider<C, E, S>>,
+    state: S,
+}
+
+impl<C, E, S> DynRunner<C, E, S> {
+    /// Constructs a new `DynRunner` for the decider `decider`, initializing the state to its
+    /// initial state.
+    pub fn new(decider: Box<dyn DynDecider<C, E, S>>) -> Self {
+        let state = decider.initial_state();
+        Self { decider, state }
+    }
+
+    /// Constructs a new `DynRunner` for the decider `decider`, initializing the state to the
+    /// given state.
+    pub fn with_state(decider: Box<dyn DynDecider<C, E, S>>, state: S) -> Self {
+        Self { decider, state }
+    }
+
+    /// Feeds the given command `command` through the decider and returns the generated list of
+    /// events.
+    ///
+    /// Also evolves the internal state of the decider according to the generated events.
+    pub fn command(&mut self, command: &C) -> Vec<E> {
+        let events = self.decider.decide(command, &self.state);
+        evolve_all_in_place(&mut self.state, &events, |state, e| {
+            self.decider.evolve_in_place(state, e)
+        });
+        events
+    }
+
+    /// Returns a reference to the current state of the decider.
+    pub fn get_state(&self) -> &S {
+        &self.state
+    }
+
+    /// Returns whether or not the decider has reached an end state.
+    pub fn is_terminal(&self) -> bool {
+        self.decider.is_terminal(&self.state)
+    }
+}
+
+impl<C, E, S> Debug for DynRunner<C, E, S>
+where
+    S: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DynRunner")
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An error returned when sending a command to a runner thread spawned with
+/// [`spawn_thread_runner`], or shutting it down.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ThreadRunnerError {
+    /// The runner thread is no longer running, because it was shut down or because the decider
+    /// panicked.
+    Stopped,
+    /// No reply arrived within the timeout given to [`SyncCommandSender::send_timeout`].
+    Timeout,
+    /// The runner thread panicked, so its final state is lost.
+    Panicked,
+    /// The queue of pending commands was full, so the command was rejected without being
+    /// queued, as configured with [`OverflowMode::Reject`].
+    Overloaded,
+    /// The command was dropped from the queue of pending commands to make room for a newer one,
+    /// as configured with [`OverflowMode::ShedOldest`].
+    Shed,
+}
+
+impl Display for ThreadRunnerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ThreadRunnerError::Stopped => write!(f, "the runner thread has stopped"),
+            ThreadRunnerError::Timeout => write!(f, "the runner thread did not reply in time"),
+            ThreadRunnerError::Panicked => write!(f, "the runner thread panicked"),
+            ThreadRunnerError::Overloaded => write!(f, "the runner's command queue is full"),
+            ThreadRunnerError::Shed => {
+                write!(
+                    f,
+                    "the command was shed from the runner's full command queue"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ThreadRunnerError {}
+
+/// What a runner thread does with a new command when its queue of pending commands is full.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OverflowMode {
+    /// The new command is rejected with [`ThreadRunnerError::Overloaded`].
+    Reject,
+    /// The oldest pending command is dropped, failing with [`ThreadRunnerError::Shed`], and the
+    /// new command is queued.
+    ShedOldest,
+}
+
+/// A notification that the queue of pending commands of a runner thread crossed one of the
+/// watermarks set with [`AdmissionPolicy::with_watermarks`], carrying the depth of the queue.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Watermark {
+    /// The queue grew to the high watermark. Producers should pause.
+    High(usize),
+    /// The queue shrank to the low watermark after reaching the high watermark. Producers can
+    /// resume.
+    Low(usize),
+}
+
+/// A callback notified when the queue of a runner thread crosses a watermark.
+type WatermarkCallback = Arc<dyn Fn(Watermark) + Send + Sync>;
+
+/// Limits on the commands waiting to be handled by a runner thread spawned with
+/// [`spawn_thread_runner_with_policy`].
+#[derive(Clone)]
+pub struct AdmissionPolicy {
+    capacity: Option<usize>,
+    mode: OverflowMode,
+    watermarks: Option<(usize, usize, WatermarkCallback)>,
+}
+
+impl AdmissionPolicy {
+    /// Constructs a new `AdmissionPolicy` that queues any number of commands.
+    pub fn unbounded() -> Self {
+        Self {
+            capacity: None,
+            mode: OverflowMode::Reject,
+            watermarks: None,
+        }
+    }
+
+    /// Constructs a new `AdmissionPolicy` that queues at most `capacity` commands besides the
+    /// one being handled, and handles any further commands as configured by `mode`.
+    pub fn bounded(capacity: usize, mode: OverflowMode) -> Self {
+        Self {
+            capacity: Some(capacity),
+            mode,
+            watermarks: None,
+        }
+    }
+
+    /// Notifies `callback` with [`Watermark::High`] when the number of queued commands grows to
+    /// `high`, and then with [`Watermark::Low`] when it shrinks back to `low`.
+    ///
+    /// The callback is called on the thread that changed the queue, after releasing the queue.
+    pub fn with_watermarks(
+        mut self,
+        high: usize,
+        low: usize,
+        callback: impl Fn(Watermark) + Send + Sync + 'static,
+    ) -> Self {
+        self.watermarks = Some((high, low, Arc::new(callback)));
+        self
+    }
+}
+
+impl Default for AdmissionPolicy {
+    fn default() -> Self {
+        Self::unbounded()
+    }
+}
+
+impl Debug for AdmissionPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AdmissionPolicy")
+            .field("capacity", &self.capacity)
+            .field("mode", &self.mode)
+            .field(
+                "watermarks",
+                &self.watermarks.as_ref().map(|(high, low, _)| (high, low)),
+            )
+            .finish()
+    }
+}
+
+/// Statistics about the commands sent to a runner thread.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RunnerStats {
+    /// The number of commands waiting to be handled, not counting the one being handled.
+    pub queue_depth: usize,
+    /// The number of commands that have been handled.
+    pub handled: u64,
+    /// The number of commands rejected with [`ThreadRunnerError::Overloaded`].
+    pub rejected: u64,
+    /// The number of commands dropped with [`ThreadRunnerError::Shed`].
+    pub shed: u64,
+}
+
+type Reply<E> = SyncSender<Result<Vec<E>, ThreadRunnerError>>;
+
+struct QueueState<C, E> {
+    pending: VecDeque<(C, Reply<E>)>,
+    open: bool,
+    above_high: bool,
+    stats: RunnerStats,
+}
+
+/// The commands waiting to be handled by a runner thread, shared by its senders and handle.
+struct CommandQueue<C, E> {
+    state: Mutex<QueueState<C, E>>,
+    ready: Condvar,
+    policy: AdmissionPolicy,
+}
+
+impl<C, E> CommandQueue<C, E> {
+    fn lock(&self) -> MutexGuard<'_, QueueState<C, E>> {
+        // The lock is never held while deciding, so a panicking decider cannot poison it.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn push(&self, command: C, reply: Reply<E>) -> Result<(), ThreadRunnerError> {
+        let mut state = self.lock();
+        if !state.open {
+            return Err(ThreadRunnerError::Stopped);
+        }
+        if self
+            .policy
+            .capacity
+            .is_some_and(|capacity| state.pending.len() >= capacity)
+        {
+            match self.policy.mode {
+                OverflowMode::Reject => {
+                    state.stats.rejected += 1;
+                    return Err(ThreadRunnerError::Overloaded);
+                }
+                OverflowMode::ShedOldest => {
+                    state.stats.shed += 1;
+                    match state.pending.pop_front() {
+                        Some((_, shed)) => {
+                            let _ = shed.send(Err(ThreadRunnerError::Shed));
+                        }
+                        // With no room at all, the new command is the oldest one.
+                        None => return Err(ThreadRunnerError::Shed),
+                    }
+                }
+            }
+        }
+        state.pending.push_back((command, reply));
+        let watermark = self.update_depth(&mut state);
+        drop(state);
+        self.ready.notify_one();
+        self.notify(watermark);
+        Ok(())
+    }
+
+    /// Waits for the next command, returning `None` once the queue is closed and empty.
+    fn pop(&self) -> Option<(C, Reply<E>)> {
+        let mut state = self.lock();
+        while state.pending.is_empty() && state.open {
+            state = self.ready.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        let next = state.pending.pop_front();
+        let watermark = self.update_depth(&mut state);
+        drop(state);
+        self.notify(watermark);
+        next
+    }
+
+    fn close(&self) {
+        self.lock().open = false;
+        self.ready.notify_all();
+    }
+
+    fn update_depth(&self, state: &mut QueueState<C, E>) -> Option<Watermark> {
+        let depth = state.pending.len();
+        state.stats.queue_depth = depth;
+        let (high, low, _) = self.policy.watermarks.as_ref()?;
+        if !state.above_high && depth >= *high {
+            state.above_high = true;
+            Some(Watermark::High(depth))
+        } else if state.above_high && depth <= *low {
+            state.above_high = false;
+            Some(Watermark::Low(depth))
+        } else {
+            None
+        }
+    }
+
+    fn notify(&self, watermark: Option<Watermark>) {
+        if let (Some(watermark), Some((_, _, callback))) = (watermark, &self.policy.watermarks) {
+            callback(watermark);
+        }
+    }
+}
+
+/// Closes the queue when the runner thread stops, including when the decider panics, failing
+/// every pending command instead of leaving its sender waiting.
+struct CloseOnDrop<C, E>(Arc<CommandQueue<C, E>>);
+
+impl<C, E> Drop for CloseOnDrop<C, E> {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.open = false;
+        state.pending.clear();
+        state.stats.queue_depth = 0;
+    }
+}
+
+/// A handle for sending commands to a runner thread spawned with [`spawn_thread_runner`].
+///
+/// Senders can be cloned to send commands from several threads. Commands are handled one at a
+/// time, in the order they arrive.
+pub struct SyncCommandSender<C, E> {
+    queue: Arc<CommandQueue<C, E>>,
+}
+
+impl<C, E> Clone for SyncCommandSender<C, E> {
+    fn clone(&self) -> Self {
+        Self {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl<C, E> Debug for SyncCommandSender<C, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SyncCommandSender").finish_non_exhaustive()
+    }
+}
+
+impl<C, E> SyncCommandSender<C, E> {
+    /// Sends the command `command` to the runner thread and waits for the generated list of
+    /// events.
+    ///
+    /// Returns [`ThreadRunnerError::Stopped`] if the runner thread has stopped, including when
+    /// the decider panicked while handling this command, or an error from the
+    /// [`AdmissionPolicy`] if the command was not admitted.
+    pub fn send(&self, command: C) -> Result<Vec<E>, ThreadRunnerError> {
+        let receiver = self.submit(command)?;
+        receiver.recv().map_err(|_| ThreadRunnerError::Stopped)?
+    }
+
+    /// Sends the command `command` to the runner thread like [`SyncCommandSender::send`], but
+    /// waits at most `timeout` for the reply.
+    pub fn send_timeout(&self, command: C, timeout: Duration) -> Result<Vec<E>, ThreadRunnerError> {
+        let receiver = self.submit(command)?;
+        receiver.recv_timeout(timeout).map_err(|e| match e {
+            RecvTimeoutError::Timeout => ThreadRunnerError::Timeout,
+            RecvTimeoutError::Disconnected => ThreadRunnerError::Stopped,
+        })?
+    }
+
+    /// Returns statistics about the commands sent to the runner thread.
+    pub fn stats(&self) -> RunnerStats {
+        self.queue.lock().stats
+    }
+
+    fn submit(
+        &self,
+        command: C,
+    ) -> Result<Receiver<Result<Vec<E>, ThreadRunnerError>>, ThreadRunnerError> {
+        let (reply, receiver) = mpsc::sync_channel(1);
+        self.queue.push(command, reply)?;
+        Ok(receiver)
+    }
+}
+
+/// A handle for stopping a runner thread spawned with [`spawn_thread_runner`].
+///
+/// Dropping the handle without calling [`ThreadRunnerHandle::shutdown`] stops the thread once it
+/// has handled the pending commands, without waiting for it.
+pub struct ThreadRunnerHandle<C, E, S> {
+    queue: Arc<CommandQueue<C, E>>,
+    thread: Option<JoinHandle<S>>,
+}
+
+impl<C, E, S> Debug for ThreadRunnerHandle<C, E, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ThreadRunnerHandle")
+            .field("thread", &self.thread)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C, E, S> ThreadRunnerHandle<C, E, S> {
+    /// Stops the runner thread once it has handled the commands sent before this call, waits for
+    /// it to finish, and returns the final state of the decider.
+    ///
+    /// Returns [`ThreadRunnerError::Panicked`] if the decider panicked.
+    pub fn shutdown(mut self) -> Result<S, ThreadRunnerError> {
+        self.queue.close();
+        let thread = self
+            .thread
+            .take()
+            .expect("the thread is only taken by shutdown");
+        thread.join().map_err(|_| ThreadRunnerError::Panicked)
+    }
+}
+
+impl<C, E, S> Drop for ThreadRunnerHandle<C, E, S> {
+    fn drop(&mut self) {
+        self.queue.close();
+    }
+}
+
+/// Spawns a thread that owns an [`InMemoryRunner`] for the decider `D` and handles the commands
+/// sent to it, without needing an async runtime.
+///
+/// Returns a sender for submitting commands, which can be cloned and shared between threads, and
+/// a handle for shutting the thread down. If the decider panics, the thread stops and every
+/// pending or later command fails with [`ThreadRunnerError::Stopped`] instead of blocking.
+///
+/// Any number of commands can wait to be handled; use [`spawn_thread_runner_with_policy`] to
+/// limit them.
+pub fn spawn_thread_runner<C, E, S, D>() -> (SyncCommandSender<C, E>, ThreadRunnerHandle<C, E, S>)
+where
+    C: Send + 'static,
+    E: Send + 'static,
+    S: Send + 'static,
+    D: Decider<C, E, S, S> + 'static,
+{
+    spawn_thread_runner_with_policy::<C, E, S, D>(AdmissionPolicy::unbounded())
+}
+
+/// Spawns a runner thread like [`spawn_thread_runner`], admitting commands according to
+/// `policy`, so that a flood of commands is rejected or shed instead of queueing without bound.
+pub fn spawn_thread_runner_with_policy<C, E, S, D>(
+    policy: AdmissionPolicy,
+) -> (SyncCommandSender<C, E>, ThreadRunnerHandle<C, E, S>)
+where
+    C: Send + 'static,
+    E: Send + 'static,
+    S: Send + 'static,
+    D: Decider<C, E, S, S> + 'static,
+{
+    let queue = Arc::new(CommandQueue {
+        state: Mutex::new(QueueState {
+            pending: VecDeque::new(),
+            open: true,
+            above_high: false,
+            stats: RunnerStats::default(),
+        }),
+        ready: Condvar::new(),
+        policy,
+    });
+    let thread_queue = CloseOnDrop(queue.clone());
+    let thread = thread::spawn(move || {
+        let mut runner = InMemoryRunner::<C, E, S, D>::new();
+        while let Some((command, reply)) = thread_queue.0.pop() {
+            let events = runner.command(&command);
+            thread_queue.0.lock().stats.handled += 1;
+            // The sender may have timed out and gone away, in which case the events are dropped.
+            let _ = reply.send(Ok(events));
+        }
+        runner.state
+    });
+    (
+        SyncCommandSender {
+            queue: queue.clone(),
+        },
+        ThreadRunnerHandle {
+            queue,
+            thread: Some(thread),
+        },
+    )
+}
+
+/// An error returned when an [`EventPublisher`] fails to deliver an event.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PublishError {
+    /// The topic the event was published to.
+    pub topic: String,
+    /// A description of what went wrong.
+    pub message: String,
+}
+
+impl Display for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to publish to {}: {}", self.topic, self.message)
+    }
+}
+
+impl std::error::Error for PublishError {}
+
+/// A trait for types that deliver events to whoever is interested in a topic.
+pub trait EventPublisher<E> {
+    /// Publishes the event `event` to the topic `topic`.
+    fn publish(&self, topic: &str, event: &E) -> Result<(), PublishError>;
+}
+
+impl<E, P> EventPublisher<E> for &P
+where
+    P: EventPublisher<E>,
+{
+    fn publish(&self, topic: &str, event: &E) -> Result<(), PublishError> {
+        (*self).publish(topic, event)
+    }
+}
+
+enum Subscriber<E> {
+    Callback(Rc<dyn Fn(&E)>),
+    Channel(Sender<E>),
+}
+
+impl<E> Clone for Subscriber<E> {
+    fn clone(&self) -> Self {
+        match self {
+            Subscriber::Callback(callback) => Subscriber::Callback(callback.clone()),
+            Subscriber::Channel(sender) => Subscriber::Channel(sender.clone()),
+        }
+    }
+}
+
+/// An [`EventPublisher`] that delivers events to subscribers in the same thread.
+///
+/// Subscribers are either callbacks, which are called as soon as an event is published, or
+/// channels, which receive a clone of every event. The subscribers of a topic receive its events
+/// in the order they were published, and are given each event in the order they subscribed.
+pub struct SimpleEventBus<E> {
+    subscribers: RefCell<HashMap<String, Vec<Subscriber<E>>>>,
+    /// Events published by callbacks while another event is being delivered, in order.
+    pending: RefCell<VecDeque<(String, E)>>,
+    delivering: Cell<bool>,
+}
+
+impl<E> SimpleEventBus<E> {
+    /// Constructs a new `SimpleEventBus` with no subscribers.
+    pub fn new() -> Self {
+        Self {
+            subscribers: RefCell::new(HashMap::new()),
+            pending: RefCell::new(VecDeque::new()),
+            delivering: Cell::new(false),
+        }
+    }
+
+    /// Subscribes to the topic `topic`, calling `callback` with each event published to it.
+    pub fn subscribe(&mut self, topic: &str, callback: impl Fn(&E) + 'static) {
+        self.subscribers
+            .get_mut()
+            .entry(topic.to_string())
+            .or_default()
+            .push(Subscriber::Callback(Rc::new(callback)));
+    }
+
+    /// Subscribes to the topic `topic`, returning a channel that receives each event published to
+    /// it.
+    ///
+    /// Once the receiver is dropped, the next event published to the topic fails with a
+    /// [`PublishError`], and the subscription is removed.
+    pub fn subscribe_channel(&mut self, topic: &str) -> Receiver<E> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers
+            .get_mut()
+            .entry(topic.to_string())
+            .or_default()
+            .push(Subscriber::Channel(sender));
+        receiver
+    }
+}
+
+impl<E: Clone> SimpleEventBus<E> {
+    /// Delivers the event `event` to every subscriber of the topic `topic`, removing the channels
+    /// that have disconnected.
+    fn deliver(&self, topic: &str, event: &E) -> Result<(), PublishError> {
+        // The subscribers are cloned so that no borrow is held while the callbacks run.
+        let subscribers = match self.subscribers.borrow().get(topic) {
+            Some(subscribers) => subscribers.clone(),
+            None => return Ok(()),
+        };
+        let mut disconnected = vec![];
+        for (i, subscriber) in subscribers.iter().enumerate() {
+            match subscriber {
+                Subscriber::Callback(callback) => callback(event),
+                Subscriber::Channel(sender) => {
+                    if sender.send(event.clone()).is_err() {
+                        disconnected.push(i);
+                    }
+                }
+            }
+        }
+        if disconnected.is_empty() {
+            return Ok(());
+        }
+        if let Some(subscribers) = self.subscribers.borrow_mut().get_mut(topic) {
+            // Subscribing needs a mutable reference, and events published by the callbacks are
+            // only delivered after this one, so the indices are still valid.
+            for i in disconnected.iter().rev() {
+                subscribers.remove(*i);
+            }
+        }
+        Err(PublishError {
+            topic: topic.to_string(),
+            message: format!("{} subscribed channel(s) disconnected", disconnected.len()),
+        })
+    }
+}
+
+/// Marks a [`SimpleEventBus`] as no longer delivering when dropped, and discards the events still
+/// queued, which are only left over if a callback panicked.
+struct Delivering<'a, E> {
+    delivering: &'a Cell<bool>,
+    pending: &'a RefCell<VecDeque<(String, E)>>,
+}
+
+impl<E> Drop for Delivering<'_, E> {
+    fn drop(&mut self) {
+        self.pending.borrow_mut().clear();
+        self.delivering.set(false);
+    }
+}
+
+impl<E> Default for SimpleEventBus<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E> Debug for SimpleEventBus<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let subscribers = self.subscribers.borrow();
+        let mut counts: Vec<_> = subscribers.iter().map(|(t, s)| (t, s.len())).collect();
+        counts.sort();
+        f.debug_struct("SimpleEventBus")
+            .field("subscribers", &counts)
+            .finish()
+    }
+}
+
+impl<E> EventPublisher<E> for SimpleEventBus<E>
+where
+    E: Clone,
+{
+    /// Delivers the event `event` to every subscriber of the topic `topic`.
+    ///
+    /// Every subscriber is given the event even if delivering it to another one failed. A
+    /// callback may publish further events to the bus. These are queued, and delivered in the
+    /// order they were published once every subscriber has been given `event`, before the
+    /// outermost call to `publish` returns. Publishing from a callback always succeeds; the
+    /// outermost call returns the first error of the events it delivered instead.
+    fn publish(&self, topic: &str, event: &E) -> Result<(), PublishError> {
+        if self.delivering.get() {
+            self.pending
+                .borrow_mut()
+                .push_back((topic.to_string(), event.clone()));
+            return Ok(());
+        }
+        self.delivering.set(true);
+        let _delivering = Delivering {
+            delivering: &self.delivering,
+            pending: &self.pending,
+        };
+        let mut result = self.deliver(topic, event);
+        loop {
+            let Some((topic, event)) = self.pending.borrow_mut().pop_front() else {
+                break;
+            };
+            let delivered = self.deliver(&topic, &event);
+            if result.is_ok() {
+                result = delivered;
+            }
+        }
+        result
+    }
+}
+
+/// A callback given the errors a [`PublishingRunner`] runs into while publishing events.
+type PublishErrorHandler = Box<dyn FnMut(&PublishError)>;
+
+/// A runner that wraps the runner `R` and publishes every event it generates to a topic of the
+/// publisher `P`, so that projections, processes and other components can all be given the
+/// same events without each being wired to the runner.
+///
+/// Events are published after the state has been evolved with them. Failing to publish an event
+/// never fails the command; the error is instead given to the callback set with
+/// [`PublishingRunner::on_publish_error`], and the remaining events are still published.
+pub struct PublishingRunner<R, P> {
+    runner: R,
+    publisher: P,
+    topic: String,
+    on_error: Option<PublishErrorHandler>,
+}
+
+impl<R, P> PublishingRunner<R, P> {
+    /// Constructs a new `PublishingRunner` that publishes the events generated by `runner` to
+    /// the topic `topic` of `publisher`.
+    pub fn new(runner: R, publisher: P, topic: &str) -> Self {
+        Self {
+            runner,
+            publisher,
+            topic: topic.to_string(),
+            on_error: None,
+        }
+    }
+
+    /// Sets a callback that is given every error returned when publishing an event, replacing
+    /// any previously set callback. Without one, errors are ignored.
+    pub fn on_publish_error(&mut self, callback: impl FnMut(&PublishError) + 'static) {
+        self.on_error = Some(Box::new(callback));
+    }
+
+    /// Returns a reference to the wrapped runner.
+    pub fn runner(&self) -> &R {
+        &self.runner
+    }
+
+    /// Returns a reference to the publisher.
+    pub fn publisher(&self) -> &P {
+        &self.publisher
+    }
+
+    /// Consumes this runner and returns the wrapped runner.
+    pub fn into_inner(self) -> R {
+        self.runner
+    }
+}
+
+impl<C, E, S, D, P> PublishingRunner<InMemoryRunner<C, E, S, D>, P>
+where
+    D: Decider<C, E, S, S>,
+    P: EventPublisher<E>,
+{
+    /// Feeds the given command `command` through the wrapped runner, publishes the generated
+    /// events, and returns them.
+    pub fn command(&mut self, command: &C) -> Vec<E> {
+        let events = self.runner.command(command);
+        for e in events.iter() {
+            if let Err(error) = self.publisher.publish(&self.topic, e) {
+                if let Some(on_error) = &mut self.on_error {
+                    on_error(&error);
+                }
+            }
+        }
+        events
+    }
+
+    /// Returns a reference to the current state of the decider.
+    pub fn get_state(&self) -> &S {
+        self.runner.get_state()
+    }
+}
+
+impl<R, P> Debug for PublishingRunner<R, P>
+where
+    R: Debug,
+    P: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PublishingRunner")
+            .field("runner", &self.runner)
+            .field("publisher", &self.publisher)
+            .field("topic", &self.topic)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A trait for the locks guarding the state shared between threads by a [`SharedRunner`] or a
+/// [`SharedEventBus`].
+///
+/// Both only lock through this trait, so that their command and publishing paths can also be run
+/// with a model of a mutex, such as `LoomLocking` with the `loom-tests` feature, to check every
+/// way they can interleave.
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait Locking {
+    /// A lock guarding a value of type `T`.
+    type Mutex<T>;
+    /// The guard returned by [`Locking::lock`], giving access to the value until it is dropped.
+    type Guard<'a, T: 'a>: DerefMut<Target = T>;
+
+    /// Constructs a new lock guarding `value`.
+    fn new<T>(value: T) -> Self::Mutex<T>;
+
+    /// Waits for the lock `mutex` and returns a guard for its value.
+    fn lock<T>(mutex: &Self::Mutex<T>) -> Self::Guard<'_, T>;
+
+    /// Consumes the lock `mutex` and returns its value.
+    fn into_inner<T>(mutex: Self::Mutex<T>) -> T;
+}
+
+/// The [`Locking`] of the standard library's [`Mutex`].
+///
+/// A lock poisoned by a panicking decider or callback is still used, since neither is called
+/// before the state is left as it was or fully evolved.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct StdLocking;
+
+impl Locking for StdLocking {
+    type Mutex<T> = Mutex<T>;
+    type Guard<'a, T: 'a> = MutexGuard<'a, T>;
+
+    fn new<T>(value: T) -> Mutex<T> {
+        Mutex::new(value)
+    }
+
+    fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+        mutex.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn into_inner<T>(mutex: Mutex<T>) -> T {
+        mutex.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The [`Locking`] of `loom`'s model of a mutex, whose locks can only be used inside
+/// `loom::model`.
+///
+/// Requires the `loom-tests` feature.
+#[cfg(feature = "loom-tests")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LoomLocking;
+
+#[cfg(feature = "loom-tests")]
+impl Locking for LoomLocking {
+    type Mutex<T> = loom::sync::Mutex<T>;
+    type Guard<'a, T: 'a> = loom::sync::MutexGuard<'a, T>;
+
+    fn new<T>(value: T) -> Self::Mutex<T> {
+        loom::sync::Mutex::new(value)
+    }
+
+    fn lock<T>(mutex: &Self::Mutex<T>) -> Self::Guard<'_, T> {
+        mutex.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn into_inner<T>(mutex: Self::Mutex<T>) -> T {
+        mutex.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A callback given the errors a [`SharedRunner`] runs into while publishing events.
+type SharedPublishErrorHandler = Box<dyn Fn(&PublishError) + Send + Sync>;
+
+/// A runner that can be shared between threads, publishing every event it generates to a topic
+/// of the publisher `P` like a [`PublishingRunner`].
+///
+/// Each command locks the state, then decides, evolves the state and publishes the events before
+/// unlocking it, so commands from different threads are handled one at a time and their events
+/// are published in the order the state was evolved with them. Folding the published events
+/// therefore always gives the state of the runner. The publisher must not send commands back to
+/// the runner while an event is published, since the state is still locked.
+pub struct SharedRunner<C, E, S, D, P, K = StdLocking>
+where
+    K: Locking,
+{
+    state: K::Mutex<S>,
+    publisher: P,
+    topic: String,
+    on_error: Option<SharedPublishErrorHandler>,
+    command: PhantomData<C>,
+    event: PhantomData<E>,
+    decider: PhantomData<D>,
+}
+
+impl<C, E, S, D, P, K> SharedRunner<C, E, S, D, P, K>
+where
+    D: Decider<C, E, S, S>,
+    P: EventPublisher<E>,
+    K: Locking,
+{
+    /// Constructs a new `SharedRunner` that publishes the events generated by the decider to the
+    /// topic `topic` of `publisher`, initializing the state to the initial state of the decider.
+    pub fn new(publisher: P, topic: &str) -> Self {
+        Self::with_state(D::initial_state(), publisher, topic)
+    }
+
+    /// Constructs a new `SharedRunner` like [`SharedRunner::new`], initializing the state to the
+    /// given state.
+    pub fn with_state(state: S, publisher: P, topic: &str) -> Self {
+        Self {
+            state: K::new(state),
+            publisher,
+            topic: topic.to_string(),
+            on_error: None,
+            command: PhantomData,
+            event: PhantomData,
+            decider: PhantomData,
+        }
+    }
+
+    /// Sets a callback that is given every error returned when publishing an event, replacing
+    /// any previously set callback. Without one, errors are ignored.
+    pub fn on_publish_error(&mut self, callback: impl Fn(&PublishError) + Send + Sync + 'static) {
+        self.on_error = Some(Box::new(callback));
+    }
+
+    /// Feeds the given command `command` through the decider, publishes the generated events,
+    /// and returns them.
+    ///
+    /// Failing to publish an event never fails the command, as with a [`PublishingRunner`].
+    pub fn command(&self, command: &C) -> Vec<E> {
+        let mut state = K::lock(&self.state);
+        let events = D::decide(command, &state);
+        evolve_all_in_place(&mut *state, &events, D::evolve_in_place);
+        for e in events.iter() {
+            if let Err(error) = self.publisher.publish(&self.topic, e) {
+                if let Some(on_error) = &self.on_error {
+                    on_error(&error);
+                }
+            }
+        }
+        events
+    }
+
+    /// Returns a copy of the current state of the decider.
+    pub fn get_state(&self) -> S
+    where
+        S: Clone,
+    {
+        K::lock(&self.state).clone()
+    }
+
+    /// Returns a reference to the publisher.
+    pub fn publisher(&self) -> &P {
+        &self.publisher
+    }
+
+    /// Consumes this runner and returns the state of the decider.
+    pub fn into_state(self) -> S {
+        K::into_inner(self.state)
+    }
+}
+
+impl<C, E, S, D, P, K> Debug for SharedRunner<C, E, S, D, P, K>
+where
+    P: Debug,
+    K: Locking,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedRunner")
+            .field("publisher", &self.publisher)
+            .field("topic", &self.topic)
+            .finish_non_exhaustive()
+    }
+}
+
+type SharedSubscribers<E> = HashMap<String, Vec<Box<dyn Fn(&E) + Send + Sync>>>;
+
+/// An [`EventPublisher`] that delivers events to callbacks, and that can be shared between
+/// threads unlike a [`SimpleEventBus`].
+///
+/// Events are delivered while the bus is locked, so every subscriber of a topic is given its
+/// events in the same order, and a subscriber is given every event published after
+/// [`SharedEventBus::subscribe`] returns. Callbacks must therefore not subscribe or publish to
+/// the bus they are subscribed to.
+pub struct SharedEventBus<E, K = StdLocking>
+where
+    K: Locking,
+{
+    subscribers: K::Mutex<SharedSubscribers<E>>,
+}
+
+impl<E, K> SharedEventBus<E, K>
+where
+    K: Locking,
+{
+    /// Constructs a new `SharedEventBus` with no subscribers.
+    pub fn new() -> Self {
+        Self {
+            subscribers: K::new(HashMap::new()),
+        }
+    }
+
+    /// Subscribes to the topic `topic`, calling `callback` with each event published to it.
+    pub fn subscribe(&self, topic: &str, callback: impl Fn(&E) + Send + Sync + 'static) {
+        K::lock(&self.subscribers)
+            .entry(topic.to_string())
+            .or_default()
+            .push(Box::new(callback));
+    }
+}
+
+impl<E, K> Default for SharedEventBus<E, K>
+where
+    K: Locking,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E, K> Debug for SharedEventBus<E, K>
+where
+    K: Locking,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let subscribers = K::lock(&self.subscribers);
+        let mut counts: Vec<_> = subscribers.iter().map(|(t, s)| (t, s.len())).collect();
+        counts.sort();
+        f.debug_struct("SharedEventBus")
+            .field("subscribers", &counts)
+            .finish()
+    }
+}
+
+impl<E, K> EventPublisher<E> for SharedEventBus<E, K>
+where
+    K: Locking,
+{
+    /// Delivers the event `event` to every subscriber of the topic `topic`, in the order they
+    /// subscribed.
+    fn publish(&self, topic: &str, event: &E) -> Result<(), PublishError> {
+        if let Some(subscribers) = K::lock(&self.subscribers).get(topic) {
+            for callback in subscribers {
+                callback(event);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Who issued a command, as checked by an [`Authorizer`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Actor {
+    /// The id of the actor, such as a user name.
+    pub id: String,
+    /// The claims of the actor, such as its roles.
+    pub claims: HashSet<String>,
+}
+
+impl Actor {
+    /// Constructs a new `Actor` with the id `id` and the claims `claims`.
+    pub fn new<'a>(id: &str, claims: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            id: id.to_string(),
+            claims: claims.into_iter().map(str::to_string).collect(),
+        }
+    }
+
+    /// Returns whether the actor has the claim `claim`.
+    pub fn has_claim(&self, claim: &str) -> bool {
+        self.claims.contains(claim)
+    }
+}
+
+/// An error returned when an actor is not allowed to issue a command.
+///
+/// Unlike a command the decider rejects by returning no events, a denied command never reaches
+/// the decider.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Denied {
+    /// Why the command was denied.
+    pub reason: String,
+}
+
+impl Display for Denied {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "command denied: {}", self.reason)
+    }
+}
+
+impl std::error::Error for Denied {}
+
+/// A trait for deciding whether an actor may issue a command of type `C` to a decider in the
+/// state `S`.
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait Authorizer<C, S> {
+    /// Returns an error if the actor `actor` may not issue the command `command` in the state
+    /// `state`.
+    fn authorize(actor: &Actor, command: &C, state: &S) -> Result<(), Denied>;
+}
+
+/// A callback given the actor and the error whenever an [`AuthorizingRunner`] denies a command.
+type DeniedHandler = Box<dyn FnMut(&Actor, &Denied)>;
+
+/// A runner that wraps the runner `R` and only lets commands through if the authorizer `A`
+/// allows the actor issuing them to.
+///
+/// Every denied command can be recorded, for example in an audit log, through the callback set
+/// with [`AuthorizingRunner::on_denied`].
+pub struct AuthorizingRunner<R, A> {
+    runner: R,
+    on_denied: Option<DeniedHandler>,
+    authorizer: PhantomData<A>,
+}
+
+impl<R, A> AuthorizingRunner<R, A> {
+    /// Constructs a new `AuthorizingRunner` that authorizes the commands sent to `runner`.
+    pub fn new(runner: R) -> Self {
+        Self {
+            runner,
+            on_denied: None,
+            authorizer: PhantomData,
+        }
+    }
+
+    /// Sets a callback that is given the actor and the error of every denied command, replacing
+    /// any previously set callback.
+    pub fn on_denied(&mut self, callback: impl FnMut(&Actor, &Denied) + 'static) {
+        self.on_denied = Some(Box::new(callback));
+    }
+
+    /// Returns a reference to the wrapped runner.
+    pub fn runner(&self) -> &R {
+        &self.runner
+    }
+
+    /// Consumes this runner and returns the wrapped runner.
+    pub fn into_inner(self) -> R {
+        self.runner
+    }
+}
+
+impl<C, E, S, D, A> AuthorizingRunner<InMemoryRunner<C, E, S, D>, A>
+where
+    D: Decider<C, E, S, S>,
+    A: Authorizer<C, S>,
+{
+    /// Checks that the actor `actor` may issue the command `command` in the current state, then
+    /// feeds it through the wrapped runner and returns the generated events.
+    ///
+    /// Returns [`Denied`] without deciding the command if the actor may not issue it.
+    pub fn command(&mut self, actor: &Actor, command: &C) -> Result<Vec<E>, Denied> {
+        if let Err(denied) = A::authorize(actor, command, self.runner.get_state()) {
+            if let Some(on_denied) = &mut self.on_denied {
+                on_denied(actor, &denied);
+            }
+            return Err(denied);
+        }
+        Ok(self.runner.command(command))
+    }
+
+    /// Returns a reference to the current state of the decider.
+    pub fn get_state(&self) -> &S {
+        self.runner.get_state()
+    }
+}
+
+impl<R, A> Debug for AuthorizingRunner<R, A>
+where
+    R: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthorizingRunner")
+            .field("runner", &self.runner)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A state a [`StateSpaceMonitor`] found outside of its set of known states, along with the
+/// command and event that led to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Escape<C, E> {
+    /// The command that was decided.
+    pub command: C,
+    /// The event that evolved the decider into the unknown state.
+    pub event: E,
+    /// The fingerprint of the unknown state.
+    pub fingerprint: u64,
+}
+
+impl<C: Debug, E: Debug> Display for Escape<C, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "state {:#018x} reached by {:?} / {:?} is outside of the known state space",
+            self.fingerprint, self.command, self.event
+        )
+    }
+}
+
+impl<C: Debug, E: Debug> std::error::Error for Escape<C, E> {}
+
+/// A callback given every [`Escape`] found by a [`StateSpaceMonitor`].
+type EscapeHandler<C, E> = Box<dyn FnMut(&Escape<C, E>)>;
+
+/// A runner that wraps an [`InMemoryRunner`] for the decider `D` and checks that every state it
+/// evolves into is in a set of known states, such as those reached by exploring the decider in
+/// tests.
+///
+/// Every state outside of the set is reported through the callback set with
+/// [`StateSpaceMonitor::on_escape`]. In strict mode, set with [`StateSpaceMonitor::set_strict`],
+/// the command leading to it is also rejected, leaving the state unchanged.
+pub struct StateSpaceMonitor<C, E, S, D>
+where
+    D: Decider<C, E, S, S>,
+{
+    runner: InMemoryRunner<C, E, S, D>,
+    known: FingerprintSet,
+    strict: bool,
+    on_escape: Option<EscapeHandler<C, E>>,
+}
+
+impl<C, E, S, D> StateSpaceMonitor<C, E, S, D>
+where
+    D: Decider<C, E, S, S>,
+    S: StateFingerprint,
+{
+    /// Constructs a new `StateSpaceMonitor` that checks the states of `runner` against the known
+    /// states `known`.
+    pub fn new(runner: InMemoryRunner<C, E, S, D>, known: FingerprintSet) -> Self {
+        Self {
+            runner,
+            known,
+            strict: false,
+            on_escape: None,
+        }
+    }
+
+    /// Sets a callback that is given every state found outside of the known states, replacing
+    /// any previously set callback.
+    pub fn on_escape(&mut self, callback: impl FnMut(&Escape<C, E>) + 'static) {
+        self.on_escape = Some(Box::new(callback));
+    }
+
+    /// Sets whether commands leading outside of the known states are rejected. Defaults to
+    /// `false`, where they are only reported.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Feeds the given command `command` through the wrapped runner and returns the generated list
+    /// of events.
+    ///
+    /// Every state the decider evolves through is checked against the known states, and the
+    /// first one outside of them is reported. In strict mode, the command is then rejected with
+    /// the [`Escape`] and the state is left unchanged.
+    pub fn command(&mut self, command: &C) -> Result<Vec<E>, Escape<C, E>>
+    where
+        C: Clone,
+        E: Clone,
+    {
+        let events = D::decide(command, &self.runner.state);
+        let mut state = None;
+        let mut escape = None;
+        for event in events.iter() {
+            let next = D::evolve(state.as_ref().unwrap_or(&self.runner.state), event);
+            let fingerprint = next.fingerprint();
+            if escape.is_none() && !self.known.contains(fingerprint) {
+                escape = Some(Escape {
+                    command: command.clone(),
+                    event: event.clone(),
+                    fingerprint,
+                });
+            }
+            state = Some(next);
+        }
+        if let Some(escape) = escape {
+            if let Some(on_escape) = &mut self.on_escape {
+                on_escape(&escape);
+            }
+            if self.strict {
+                return Err(escape);
+            }
+        }
+        if let Some(state) = state {
+            self.runner.state = state;
+        }
+        Ok(events)
+    }
+
+    /// Returns a reference to the current state of the decider.
+    pub fn get_state(&self) -> &S {
+        self.runner.get_state()
+    }
+
+    /// Returns a reference to the known states.
+    pub fn known(&self) -> &FingerprintSet {
+        &self.known
+    }
+
+    /// Returns a reference to the wrapped runner.
+    pub fn runner(&self) -> &InMemoryRunner<C, E, S, D> {
+        &self.runner
+    }
+
+    /// Consumes this monitor and returns the wrapped runner.
+    pub fn into_inner(self) -> InMemoryRunner<C, E, S, D> {
+        self.runner
+    }
+}
+
+impl<C, E, S, D> Debug for StateSpaceMonitor<C, E, S, D>
+where
+    D: Decider<C, E, S, S>,
+    S: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StateSpaceMonitor")
+            .field("runner", &self.runner)
+            .field("known", &self.known.len())
+            .field("strict", &self.strict)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A set of deciders of different types behind the same command, event and state types, each
+/// registered under a type tag, for a [`HeterogeneousKeyedRunner`].
+pub struct Registry<C, E, S> {
+    deciders: HashMap<String, Box<dyn DynDecider<C, E, S>>>,
+}
+
+impl<C, E, S> Registry<C, E, S> {
+    /// Constructs a new, empty `Registry`.
+    pub fn new() -> Self {
+        Self {
+            deciders: HashMap::new(),
+        }
+    }
+
+    /// Registers the decider `decider` under the type tag `tag`, replacing any decider already
+    /// registered under it.
+    pub fn register(&mut self, tag: &str, decider: impl DynDecider<C, E, S> + 'static) {
+        self.deciders.insert(tag.to_string(), Box::new(decider));
+    }
+
+    /// Returns the decider registered under the type tag `tag`, if any.
+    pub fn get(&self, tag: &str) -> Option<&dyn DynDecider<C, E, S>> {
+        self.deciders.get(tag).map(|d| d.as_ref())
+    }
+}
+
+impl<C, E, S> Default for Registry<C, E, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C, E, S> Debug for Registry<C, E, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut tags: Vec<_> = self.deciders.keys().collect();
+        tags.sort();
+        f.debug_struct("Registry").field("tags", &tags).finish()
+    }
+}
+
+/// An error returned by a [`HeterogeneousKeyedRunner`] when a command cannot be decided.
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeyedCommandError {
+    /// No decider is registered under the type tag.
+    UnknownTag(String),
+    /// No decider has been created with the key.
+    UnknownKey(String),
+    /// A decider has already been created with the key.
+    KeyExists(String),
+    /// The decider of the key cannot convert the command to its own command type.
+    Unconvertible {
+        /// The key the command was sent to.
+        key: String,
+        /// The type tag of the key's decider.
+        tag: String,
+    },
+}
+
+impl Display for KeyedCommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyedCommandError::UnknownTag(tag) => write!(f, "no decider is registered as {tag}"),
+            KeyedCommandError::UnknownKey(key) => write!(f, "{key} has not been created"),
+            KeyedCommandError::KeyExists(key) => write!(f, "{key} has already been created"),
+            KeyedCommandError::Unconvertible { key, tag } => {
+                write!(
+                    f,
+                    "the command cannot be handled by {key}, which is a {tag}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for KeyedCommandError {}
+
+/// A runner for deciders of different types, each identified by a key, behind the same command,
+/// event and state types.
+///
+/// This is like running a [`ManyDecider`](crate::deciders::ManyDecider), except that each key
+/// can be a different kind of decider from a [`Registry`]. Each key is created with the type tag
+/// of its decider, and later commands are routed to that decider.
+pub struct HeterogeneousKeyedRunner<C, E, S> {
+    registry: Registry<C, E, S>,
+    states: HashMap<String, (String, S)>,
+}
+
+impl<C, E, S> HeterogeneousKeyedRunner<C, E, S> {
+    /// Constructs a new `HeterogeneousKeyedRunner` with no keys, using the deciders in
+    /// `registry`.
+    pub fn new(registry: Registry<C, E, S>) -> Self {
+        Self {
+            registry,
+            states: HashMap::new(),
+        }
+    }
+
+    /// Creates the key `key` with the decider registered under the type tag `tag`, starting
+    /// from its initial state, and feeds the command `command` through it.
+    ///
+    /// Returns an error without creating the key if it already exists, the tag is unknown, or
+    /// the decider cannot convert the command.
+    pub fn create(
+        &mut self,
+        key: &str,
+        tag: &str,
+        command: &C,
+    ) -> Result<Vec<(String, E)>, KeyedCommandError> {
+        if self.states.contains_key(key) {
+            return Err(KeyedCommandError::KeyExists(key.to_string()));
+        }
+        let decider = self
+            .registry
+            .get(tag)
+            .ok_or_else(|| KeyedCommandError::UnknownTag(tag.to_string()))?;
+        let state = decider.initial_state();
+        self.states
+            .insert(key.to_string(), (tag.to_string(), state));
+        let result = self.command(key, command);
+        if result.is_err() {
+            self.states.remove(key);
+        }
+        result
+    }
+
+    /// Feeds the command `command` through the decider of the key `key`, evolves its state, and
+    /// returns the generated events, each paired with the key.
+    ///
+    /// Returns an error if the key has not been created or its decider cannot convert the
+    /// command.
+    pub fn command(
+        &mut self,
+        key: &str,
+        command: &C,
+    ) -> Result<Vec<(String, E)>, KeyedCommandError> {
+        let (tag, state) = self
+            .states
+            .get_mut(key)
+            .ok_or_else(|| KeyedCommandError::UnknownKey(key.to_string()))?;
+        let decider = self
+            .registry
+            .get(tag)
+            .ok_or_else(|| KeyedCommandError::UnknownTag(tag.clone()))?;
+        if !decider.accepts(command, state) {
+            return Err(KeyedCommandError::Unconvertible {
+                key: key.to_string(),
+                tag: tag.clone(),
+            });
+        }
+        let events = decider.decide(command, state);
+        evolve_all_in_place(state, &events, |state, e| decider.evolve_in_place(state, e));
+        Ok(events.into_iter().map(|e| (key.to_string(), e)).collect())
+    }
+
+    /// Returns the type tag and current state of the key `key`, if it has been created.
+    pub fn get_state(&self, key: &str) -> Option<(&str, &S)> {
+        self.states
+            .get(key)
+            .map(|(tag, state)| (tag.as_str(), state))
+    }
+
+    /// Returns whether the decider of the key `key` has reached an end state.
+    pub fn is_terminal(&self, key: &str) -> bool {
+        self.states.get(key).is_some_and(|(tag, state)| {
+            self.registry
+                .get(tag)
+                .is_some_and(|decider| decider.is_terminal(state))
+        })
+    }
+}
+
+impl<C, E, S> Debug for HeterogeneousKeyedRunner<C, E, S>
+where
+    S: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HeterogeneousKeyedRunner")
+            .field("registry", &self.registry)
+            .field("states", &self.states)
+            .finish()
+    }
+}
+
+/// An error returned when a command of an old version cannot be upcast to the current version,
+/// such as when its variant was removed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MigrationError {
+    /// Why the command cannot be upcast.
+    pub reason: String,
+}
+
+impl MigrationError {
+    /// The code identifying this error, so clients can tell they sent a deprecated command.
+    pub const CODE: &'static str = "DEPRECATED_COMMAND";
+}
+
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}", Self::CODE, self.reason)
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+/// A trait for converting commands of an old version, of type `O`, into commands of the current
+/// version, of type `C`, so that clients sending old commands keep working.
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait CommandUpcaster<O, C> {
+    /// Converts the old command `command` into the current command it stands for, or returns an
+    /// error if it no longer has an equivalent.
+    fn upcast(command: &O) -> Result<C, MigrationError>;
+}
+
+/// A runner that wraps the runner `R` and also accepts commands of old versions, converting
+/// them with the upcaster `U`.
+pub struct UpcastingCommandRunner<R, U> {
+    runner: R,
+    upcaster: PhantomData<U>,
+}
+
+impl<R, U> UpcastingCommandRunner<R, U> {
+    /// Constructs a new `UpcastingCommandRunner` that sends upcast commands to `runner`.
+    pub fn new(runner: R) -> Self {
+        Self {
+            runner,
+            upcaster: PhantomData,
+        }
+    }
+
+    /// Returns a reference to the wrapped runner.
+    pub fn runner(&self) -> &R {
+        &self.runner
+    }
+
+    /// Consumes this runner and returns the wrapped runner.
+    pub fn into_inner(self) -> R {
+        self.runner
+    }
+}
+
+impl<C, E, S, D, U> UpcastingCommandRunner<InMemoryRunner<C, E, S, D>, U>
+where
+    D: Decider<C, E, S, S>,
+{
+    /// Feeds the current command `command` through the wrapped runner and returns the generated
+    /// events.
+    pub fn command(&mut self, command: &C) -> Vec<E> {
+        self.runner.command(command)
+    }
+
+    /// Upcasts the old command `command`, then feeds it through the wrapped runner and returns
+    /// the generated events.
+    ///
+    /// Returns a [`MigrationError`] without deciding anything if the command cannot be upcast.
+    pub fn command_old<O>(&mut self, command: &O) -> Result<Vec<E>, MigrationError>
+    where
+        U: CommandUpcaster<O, C>,
+    {
+        let command = U::upcast(command)?;
+        Ok(self.runner.command(&command))
+    }
+
+    /// Returns a reference to the current state of the decider.
+    pub fn get_state(&self) -> &S {
+        self.runner.get_state()
+    }
+}
+
+impl<R, U> Debug for UpcastingCommandRunner<R, U>
+where
+    R: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UpcastingCommandRunner")
+            .field("runner", &self.runner)
+            .finish()
+    }
+}
+
+/// An event read from a stream that may contain events written by a newer version of the
+/// application, whose types this version does not know about.
+///
+/// Deserializing a `MaybeUnknown<E>` never fails because of an unknown event: if the value is
+/// valid JSON whose type is not a variant of `E`, it is kept as an [`MaybeUnknown::Unknown`]
+/// event, so the rest of the stream can still be read. Serializing an unknown event writes its
+/// original JSON back unchanged.
+///
+/// An event whose type is a variant of `E` but whose payload is not valid is corrupt rather than
+/// unknown, so deserializing it returns an error instead of an unknown event that would then be
+/// skipped. The variants are read from `E`'s `Deserialize` implementation, which works for the
+/// externally tagged enums serde derives by default and for enums tagged with a `type` field; for
+/// other enums every event that fails to deserialize is kept as unknown.
+///
+/// Requires the `serde` feature.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq)]
+pub enum MaybeUnknown<E> {
+    /// An event this version knows about.
+    Known(E),
+    /// An event that could not be deserialized as a known event.
+    Unknown {
+        /// The type of the event, taken from the name of its variant or its `type` field, or an
+        /// empty string if it has neither.
+        event_type: String,
+        /// The event's original JSON.
+        raw: serde_json::Value,
+    },
+}
+
+#[cfg(feature = "serde")]
+impl<E> MaybeUnknown<E> {
+    /// Returns the event if it is known.
+    pub fn known(&self) -> Option<&E> {
+        match self {
+            MaybeUnknown::Known(event) => Some(event),
+            MaybeUnknown::Unknown { .. } => None,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, E> serde::Deserialize<'de> for MaybeUnknown<E>
+where
+    E: serde::de::DeserializeOwned,
+{
+    fn deserialize<Des>(deserializer: Des) -> Result<Self, Des::Error>
+    where
+        Des: serde::Deserializer<'de>,
+    {
+        let raw = serde_json::Value::deserialize(deserializer)?;
+        let error = match E::deserialize(&raw) {
+            Ok(event) => return Ok(MaybeUnknown::Known(event)),
+            Err(error) => error,
+        };
+        let event_type = match &raw {
+            // Unit variants are written as their name, and other variants as an object whose
+            // only key is their name, unless the enum is internally tagged.
+            serde_json::Value::String(name) => name.clone(),
+            serde_json::Value::Object(fields) => match fields.get("type") {
+                Some(serde_json::Value::String(name)) => name.clone(),
+                _ if fields.len() == 1 => fields.keys().next().cloned().unwrap_or_default(),
+                _ => String::new(),
+            },
+            _ => String::new(),
+        };
+        if variant_names::<E>().contains(&event_type.as_str()) {
+            return Err(serde::de::Error::custom(format_args!(
+                "malformed \"{event_type}\" event: {error}"
+            )));
+        }
+        Ok(MaybeUnknown::Unknown { event_type, raw })
+    }
+}
+
+/// Returns the names of the variants of the enum `E`, or an empty slice if they cannot be found.
+///
+/// `E` is deserialized from a probe that asks for a variant that does not exist: serde's derived
+/// implementations report it as an unknown variant, along with the names they expected.
+#[cfg(feature = "serde")]
+fn variant_names<E>() -> &'static [&'static str]
+where
+    E: serde::de::DeserializeOwned,
+{
+    match E::deserialize(VariantProbe) {
+        Err(VariantProbeError(variants)) => variants,
+        Ok(_) => &[],
+    }
+}
+
+/// The tag [`VariantProbe`] asks for, which no enum is expected to have as a variant.
+#[cfg(feature = "serde")]
+const PROBED_VARIANT: &str = "\0probe";
+
+/// A deserializer that only holds an enum tag, used by [`variant_names`].
+#[cfg(feature = "serde")]
+struct VariantProbe;
+
+/// The error returned by [`VariantProbe`], holding the variant names it found, if any.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+struct VariantProbeError(&'static [&'static str]);
+
+#[cfg(feature = "serde")]
+impl Display for VariantProbeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "variants {:?}", self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for VariantProbeError {}
+
+#[cfg(feature = "serde")]
+impl serde::de::Error for VariantProbeError {
+    fn custom<T: Display>(_msg: T) -> Self {
+        VariantProbeError(&[])
+    }
+
+    fn unknown_variant(_variant: &str, expected: &'static [&'static str]) -> Self {
+        VariantProbeError(expected)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserializer<'de> for VariantProbe {
+    type Error = VariantProbeError;
+
+    /// Enums tagged with a field are read from a map, so the probe is a map whose `type` field is
+    /// the probed variant.
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        visitor.visit_map(serde::de::value::MapDeserializer::new(std::iter::once((
+            "type",
+            PROBED_VARIANT,
+        ))))
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        Err(VariantProbeError(variants))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map struct identifier
+        ignored_any
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<E> serde::Serialize for MaybeUnknown<E>
+where
+    E: serde::Serialize,
+{
+    fn serialize<Ser>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+    where
+        Ser: serde::Serializer,
+    {
+        match self {
+            MaybeUnknown::Known(event) => event.serialize(serializer),
+            MaybeUnknown::Unknown { raw, .. } => raw.serialize(serializer),
+        }
+    }
+}
+
+/// An error returned when folding a stream in strict mode finds an event of an unknown type.
+///
+/// Requires the `serde` feature.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnknownEventError {
+    /// The position of the unknown event in the stream.
+    pub position: usize,
+    /// The type of the unknown event.
+    pub event_type: String,
+}
+
+#[cfg(feature = "serde")]
+impl Display for UnknownEventError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "unknown event type \"{}\" at position {}",
+            self.event_type, self.position
+        )
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for UnknownEventError {}
+
+/// Returns the state of the decider `D` after evolving its initial state with every known event
+/// in `events`, skipping unknown events.
+///
+/// `on_unknown` is called with the position and type of each unknown event that is skipped.
+///
+/// Requires the `serde` feature.
+#[cfg(feature = "serde")]
+pub fn fold_known<C, E, S, D>(
+    events: &[MaybeUnknown<E>],
+    mut on_unknown: impl FnMut(usize, &str),
+) -> S
+where
+    D: Decider<C, E, S, S>,
+{
+    events
+        .iter()
+        .enumerate()
+        .fold(D::initial_state(), |state, (position, event)| match event {
+            MaybeUnknown::Known(event) => D::evolve(&state, event),
+            MaybeUnknown::Unknown { event_type, .. } => {
+                on_unknown(position, event_type);
+                state
+            }
+        })
+}
+
+/// Returns the state of the decider `D` after evolving its initial state with every event in
+/// `events`, or an error for the first unknown event.
+///
+/// Requires the `serde` feature.
+#[cfg(feature = "serde")]
+pub fn try_fold_known<C, E, S, D>(events: &[MaybeUnknown<E>]) -> Result<S, UnknownEventError>
+where
+    D: Decider<C, E, S, S>,
+{
+    events
+        .iter()
+        .enumerate()
+        .try_fold(D::initial_state(), |state, (position, event)| match event {
+            MaybeUnknown::Known(event) => Ok(D::evolve(&state, event)),
+            MaybeUnknown::Unknown { event_type, .. } => Err(UnknownEventError {
+                position,
+                event_type: event_type.clone(),
+            }),
+        })
+}
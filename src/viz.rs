@@ -0,0 +1,497 @@
+use std::{collections::HashMap, fmt::Debug};
+
+use crate::deciders::Decider;
+use crate::processes::Process;
+use crate::utilities::{FingerprintSet, StateFingerprint};
+
+/// A state reached while exploring a decider with [`explore`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateNode<S> {
+    /// The state.
+    pub state: S,
+    /// Whether the decider considers the state terminal.
+    pub terminal: bool,
+}
+
+/// A transition between two states found while exploring a decider with [`explore`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transition {
+    /// The index of the state the transition starts from.
+    pub from: usize,
+    /// The index of the state the transition leads to.
+    pub to: usize,
+    /// The variant of the command that was decided, followed by the variants of the events it
+    /// produced, such as `SwitchOn / SwitchedOn`.
+    pub label: String,
+}
+
+/// The states of a decider reachable from a starting state, and the transitions between them.
+///
+/// The starting state is always the state at index 0.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateGraph<S> {
+    /// The reachable states, in the order they were first reached.
+    pub nodes: Vec<StateNode<S>>,
+    /// The transitions between the states, in the order they were found.
+    pub transitions: Vec<Transition>,
+}
+
+/// Explores the states of the decider `D` reachable from `initial` by deciding the commands in
+/// `commands`, taking at most `max_depth` steps from `initial`.
+///
+/// The exploration is breadth first, so the result is the same on every run. Every command is
+/// tried in every reached state, and commands producing no events are ignored. States are
+/// deduplicated by their [fingerprint](StateFingerprint) and equality, so each state appears only
+/// once, even if it can be reached in several ways.
+pub fn explore<C, E, S, D>(initial: &S, commands: &[C], max_depth: usize) -> StateGraph<S>
+where
+    C: Debug,
+    E: Debug,
+    S: Clone + PartialEq + StateFingerprint,
+    D: Decider<C, E, S, S>,
+{
+    let mut graph = StateGraph {
+        nodes: vec![StateNode {
+            state: initial.clone(),
+            terminal: D::is_terminal(initial),
+        }],
+        transitions: vec![],
+    };
+    // Maps each fingerprint to the indices of the states with it.
+    let mut indices: HashMap<u64, Vec<usize>> = HashMap::from([(initial.fingerprint(), vec![0])]);
+    let mut frontier = vec![0];
+    for _ in 0..max_depth {
+        let mut next_frontier = vec![];
+        for from in frontier {
+            for command in commands {
+                let state = &graph.nodes[from].state;
+                let events = D::decide(command, state);
+                if events.is_empty() {
+                    continue;
+                }
+                let next = events.iter().fold(state.clone(), |s, e| D::evolve(&s, e));
+                let event_labels: Vec<_> = events.iter().map(type_label).collect();
+                let label = format!("{} / {}", type_label(command), event_labels.join(", "));
+                let same_fingerprint = indices.entry(next.fingerprint()).or_default();
+                let to = match same_fingerprint
+                    .iter()
+                    .find(|i| graph.nodes[**i].state == next)
+                {
+                    Some(to) => *to,
+                    None => {
+                        let to = graph.nodes.len();
+                        graph.nodes.push(StateNode {
+                            terminal: D::is_terminal(&next),
+                            state: next,
+                        });
+                        same_fingerprint.push(to);
+                        next_frontier.push(to);
+                        to
+                    }
+                };
+                let transition = Transition { from, to, label };
+                if !graph.transitions.contains(&transition) {
+                    graph.transitions.push(transition);
+                }
+            }
+        }
+        frontier = next_frontier;
+    }
+    graph
+}
+
+impl<S> StateGraph<S> {
+    /// Returns the fingerprints of every state in this graph, such as to check at runtime that a
+    /// decider stays within the explored states with a
+    /// [`StateSpaceMonitor`](crate::utilities::StateSpaceMonitor).
+    pub fn fingerprints(&self) -> FingerprintSet
+    where
+        S: StateFingerprint,
+    {
+        self.nodes.iter().map(|n| n.state.fingerprint()).collect()
+    }
+
+    /// Renders this graph in the DOT language used by Graphviz, labelling each state with
+    /// `state_label`.
+    ///
+    /// The starting state is marked with an incoming arrow from a point, and terminal states are
+    /// drawn as double circles.
+    pub fn to_dot(&self, state_label: impl Fn(&S) -> String) -> String {
+        let mut dot = String::from("digraph {\n    start [shape=point];\n    start -> n0;\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let shape = if node.terminal {
+                "doublecircle"
+            } else {
+                "circle"
+            };
+            dot.push_str(&format!(
+                "    n{i} [label=\"{}\", shape={shape}];\n",
+                escape(&state_label(&node.state))
+            ));
+        }
+        for transition in &self.transitions {
+            dot.push_str(&format!(
+                "    n{} -> n{} [label=\"{}\"];\n",
+                transition.from,
+                transition.to,
+                escape(&transition.label)
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Renders the states of the decider `D` reachable from its initial state in the DOT language
+/// used by Graphviz, labelling each state with its [`Debug`] representation.
+///
+/// See [`explore`] for how the states are found, and [`StateGraph::to_dot`] for how they are
+/// rendered.
+pub fn to_dot<C, E, S, D>(commands: &[C], max_depth: usize) -> String
+where
+    C: Debug,
+    E: Debug,
+    S: Clone + PartialEq + StateFingerprint + Debug,
+    D: Decider<C, E, S, S>,
+{
+    explore::<C, E, S, D>(&D::initial_state(), commands, max_depth).to_dot(|s| format!("{s:?}"))
+}
+
+/// A trait for deciders that can list all of their states and all of their commands, so that
+/// their whole state machine can be drawn with [`enumerate`], instead of only the states reachable
+/// from the initial state.
+///
+/// Note that all methods defined on this trait do not take self, so it is useless to make a
+/// concrete instance of an object with this trait.
+pub trait Enumerable<C, S> {
+    /// Returns every state of this decider, or one example of each kind of state for states
+    /// holding data.
+    fn all_states() -> Vec<S>;
+
+    /// Returns every command of this decider, or one example of each kind of command for commands
+    /// holding data.
+    fn all_commands() -> Vec<C>;
+}
+
+/// Builds the state machine of the decider `D` by deciding each of its commands in each of its
+/// states, as listed by [`Enumerable`], and evolving each state with the events produced.
+///
+/// Each state is mapped to a node with `abstraction`, so that states holding data, such as a
+/// count, can be drawn as one node by mapping them to the same value. The initial state is always
+/// the node at index 0, followed by the states listed by [`Enumerable::all_states`] and any other
+/// states reached, and a node is terminal if the first state mapped to it is. Commands producing
+/// no events are ignored, and transitions are labelled as by [`explore`].
+pub fn enumerate<C, E, S, D, A>(abstraction: impl Fn(&S) -> A) -> StateGraph<A>
+where
+    C: Debug,
+    E: Debug,
+    A: PartialEq,
+    D: Decider<C, E, S, S> + Enumerable<C, S>,
+{
+    let mut graph = StateGraph {
+        nodes: vec![],
+        transitions: vec![],
+    };
+    let node = |graph: &mut StateGraph<A>, state: &S| {
+        let abstracted = abstraction(state);
+        match graph.nodes.iter().position(|n| n.state == abstracted) {
+            Some(i) => i,
+            None => {
+                graph.nodes.push(StateNode {
+                    state: abstracted,
+                    terminal: D::is_terminal(state),
+                });
+                graph.nodes.len() - 1
+            }
+        }
+    };
+    node(&mut graph, &D::initial_state());
+    let states = D::all_states();
+    for state in &states {
+        node(&mut graph, state);
+    }
+    let commands = D::all_commands();
+    for state in &states {
+        let from = node(&mut graph, state);
+        for command in &commands {
+            let events = D::decide(command, state);
+            if events.is_empty() {
+                continue;
+            }
+            let next = events
+                .iter()
+                .skip(1)
+                .fold(D::evolve(state, &events[0]), |s, e| D::evolve(&s, e));
+            let event_labels: Vec<_> = events.iter().map(type_label).collect();
+            let label = format!("{} / {}", type_label(command), event_labels.join(", "));
+            let to = node(&mut graph, &next);
+            let transition = Transition { from, to, label };
+            if !graph.transitions.contains(&transition) {
+                graph.transitions.push(transition);
+            }
+        }
+    }
+    graph
+}
+
+/// Renders the state machine of the decider `D`, as built by [`enumerate`], in the DOT language
+/// used by Graphviz, labelling each state with its [`Debug`] representation.
+///
+/// Use [`enumerate`] and [`StateGraph::to_dot`] directly to draw states holding data as one node.
+pub fn enumerated_to_dot<C, E, S, D>() -> String
+where
+    C: Debug,
+    E: Debug,
+    S: Debug,
+    D: Decider<C, E, S, S> + Enumerable<C, S>,
+{
+    enumerate::<C, E, S, D, String>(|s| format!("{s:?}")).to_dot(String::clone)
+}
+
+/// Who issued a command recorded in a [`Trace`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Issuer {
+    /// The command was given by the caller.
+    Caller,
+    /// The command was issued by a process reacting to an event.
+    Process,
+}
+
+/// An entry of a [`Trace`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceEntry<C, E, S> {
+    /// A command was decided.
+    Command {
+        /// Who issued the command.
+        issuer: Issuer,
+        /// The command.
+        command: C,
+    },
+    /// A decider emitted an event.
+    Event {
+        /// The event.
+        event: E,
+        /// The state after evolving with the event.
+        state: S,
+    },
+}
+
+/// A record of the commands decided and the events emitted while running a decider, and possibly
+/// a process reacting to its events, produced by [`simulate_trace`] or
+/// [`simulate_combined_trace`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trace<C, E, S> {
+    /// The state the simulation started from.
+    pub initial_state: S,
+    /// The commands and events, in the order they happened.
+    pub entries: Vec<TraceEntry<C, E, S>>,
+    /// Whether a process took part in the simulation.
+    pub has_process: bool,
+}
+
+/// Runs the decider `D` on each of the commands in `commands` in turn, starting from `state` and
+/// evolving the state with every event, and records what happened in a [`Trace`].
+pub fn simulate_trace<C, E, S, D>(state: &S, commands: &[C]) -> Trace<C, E, S>
+where
+    C: Clone,
+    S: Clone,
+    D: Decider<C, E, S, S>,
+{
+    let mut current = state.clone();
+    let mut entries = vec![];
+    for command in commands {
+        entries.push(TraceEntry::Command {
+            issuer: Issuer::Caller,
+            command: command.clone(),
+        });
+        for event in D::decide(command, &current) {
+            current = D::evolve(&current, &event);
+            entries.push(TraceEntry::Event {
+                event,
+                state: current.clone(),
+            });
+        }
+    }
+    Trace {
+        initial_state: state.clone(),
+        entries,
+        has_process: false,
+    }
+}
+
+/// Runs the decider `D` together with the process `P` on each of the commands in `commands` in
+/// turn, like [`CombinedProcessDecider`](crate::processes::CombinedProcessDecider), and records
+/// what happened in a [`Trace`].
+///
+/// Unlike `CombinedProcessDecider::decide`, both states are evolved with every event as it is
+/// emitted, so the commands the process issues are decided against the up to date state, as
+/// they would be by a runner handling one command at a time.
+pub fn simulate_combined_trace<P, D, E, C, Sp, Sd>(
+    state: &(Sp, Sd),
+    commands: &[C],
+) -> Trace<C, E, (Sp, Sd)>
+where
+    C: Clone,
+    Sp: Clone,
+    Sd: Clone,
+    P: Process<E, C, Sp>,
+    D: Decider<C, E, Sd, Sd>,
+{
+    let (mut state_process, mut state_decider) = state.clone();
+    let mut entries = vec![];
+    for command in commands {
+        let mut pending = vec![(Issuer::Caller, command.clone())];
+        while !pending.is_empty() {
+            let (issuer, command) = pending.remove(0);
+            let events = D::decide(&command, &state_decider);
+            entries.push(TraceEntry::Command { issuer, command });
+            for event in events {
+                state_decider = D::evolve(&state_decider, &event);
+                state_process = P::evolve(&state_process, &event);
+                pending.extend(
+                    P::react(&state_process, &event)
+                        .into_iter()
+                        .map(|c| (Issuer::Process, c)),
+                );
+                entries.push(TraceEntry::Event {
+                    event,
+                    state: (state_process.clone(), state_decider.clone()),
+                });
+            }
+        }
+    }
+    Trace {
+        initial_state: state.clone(),
+        entries,
+        has_process: true,
+    }
+}
+
+/// Renders the trace `trace` as a Mermaid sequence diagram, with one arrow per command and per
+/// event, labelled with their variants.
+///
+/// The participants are the caller, the decider, and the process if one took part. Events are
+/// sent to the process if there is one, and back to the caller otherwise.
+pub fn trace_to_mermaid<C, E, S>(trace: &Trace<C, E, S>) -> String
+where
+    C: Debug,
+    E: Debug,
+{
+    render_mermaid(trace, None::<fn(&S) -> String>)
+}
+
+/// Renders the trace `trace` like [`trace_to_mermaid`], adding a note over the decider with the
+/// label given by `state_label` whenever the label of the state changes.
+pub fn trace_to_mermaid_with_states<C, E, S>(
+    trace: &Trace<C, E, S>,
+    state_label: impl Fn(&S) -> String,
+) -> String
+where
+    C: Debug,
+    E: Debug,
+{
+    render_mermaid(trace, Some(state_label))
+}
+
+fn render_mermaid<C, E, S>(
+    trace: &Trace<C, E, S>,
+    state_label: Option<impl Fn(&S) -> String>,
+) -> String
+where
+    C: Debug,
+    E: Debug,
+{
+    let mut diagram =
+        String::from("sequenceDiagram\n    participant Caller\n    participant Decider\n");
+    let event_target = if trace.has_process {
+        diagram.push_str("    participant Process\n");
+        "Process"
+    } else {
+        "Caller"
+    };
+    let mut last_label = state_label.as_ref().map(|l| l(&trace.initial_state));
+    for entry in &trace.entries {
+        match entry {
+            TraceEntry::Command { issuer, command } => {
+                let issuer = match issuer {
+                    Issuer::Caller => "Caller",
+                    Issuer::Process => "Process",
+                };
+                diagram.push_str(&format!(
+                    "    {issuer}->>Decider: {}\n",
+                    escape_mermaid(&type_label(command))
+                ));
+            }
+            TraceEntry::Event { event, state } => {
+                diagram.push_str(&format!(
+                    "    Decider-->>{event_target}: {}\n",
+                    escape_mermaid(&type_label(event))
+                ));
+                if let Some(state_label) = &state_label {
+                    let label = state_label(state);
+                    if last_label.as_ref() != Some(&label) {
+                        diagram.push_str(&format!(
+                            "    Note over Decider: {}\n",
+                            escape_mermaid(&label)
+                        ));
+                        last_label = Some(label);
+                    }
+                }
+            }
+        }
+    }
+    diagram
+}
+
+/// Returns the name of the variant of `value` using its [`Debug`] representation, including the
+/// variants of any wrapping enums, such as `Left::SwitchedOn` for `Left(SwitchedOn)`, and the key
+/// of keyed values, such as `kitchen::SwitchedOn` for `("kitchen", SwitchedOn)`.
+fn type_label<T: Debug>(value: &T) -> String {
+    debug_label(&format!("{value:?}"))
+}
+
+fn debug_label(debug: &str) -> String {
+    if let Some((key, rest)) = debug
+        .strip_prefix('(')
+        .and_then(|d| d.strip_suffix(')'))
+        .and_then(|d| d.split_once(", "))
+    {
+        return format!("{}::{}", key.trim_matches('"'), debug_label(rest));
+    }
+    let mut label = String::new();
+    let mut rest = debug;
+    loop {
+        let end = rest.find([' ', '{', '(', ')', ',']).unwrap_or(rest.len());
+        label.push_str(&rest[..end]);
+        match rest[end..].strip_prefix('(') {
+            Some(inner) if end > 0 && inner.starts_with(|c: char| c.is_ascii_uppercase()) => {
+                label.push_str("::");
+                rest = inner;
+            }
+            _ if label.is_empty() => return debug.to_string(),
+            _ => return label,
+        }
+    }
+}
+
+/// Escapes `label` for use inside a quoted DOT string.
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Escapes `text` for use in a Mermaid message or note.
+fn escape_mermaid(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '#' => escaped.push_str("#35;"),
+            ';' => escaped.push_str("#59;"),
+            '\n' => escaped.push_str("<br/>"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
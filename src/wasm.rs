@@ -0,0 +1,182 @@
+use std::{fmt::Display, marker::PhantomData};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::deciders::{evolve_all_in_place, Decider};
+
+#[doc(hidden)]
+pub use wasm_bindgen;
+
+/// The kind of error returned by a [`WasmRunner`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WasmErrorCode {
+    /// The command was not valid JSON for the decider's command type, or the events or state
+    /// could not be serialized to JSON.
+    BadJson,
+    /// The decider is in a terminal state and accepts no more commands.
+    Terminal,
+}
+
+impl WasmErrorCode {
+    /// Returns the code as a string, as used in the messages of the errors thrown to JavaScript.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WasmErrorCode::BadJson => "BAD_JSON",
+            WasmErrorCode::Terminal => "TERMINAL",
+        }
+    }
+}
+
+/// An error returned by a [`WasmRunner`].
+///
+/// When thrown to JavaScript by a runner exported with [`export_decider`](crate::export_decider),
+/// the error's message is its code in brackets followed by a description, such as
+/// `[BAD_JSON] expected value at line 1 column 1`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WasmError {
+    /// The kind of error.
+    pub code: WasmErrorCode,
+    /// A description of the error.
+    pub message: String,
+}
+
+impl Display for WasmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}", self.code.as_str(), self.message)
+    }
+}
+
+impl std::error::Error for WasmError {}
+
+/// A runner for a decider whose commands, events and state are exchanged as JSON, for exposing
+/// deciders to JavaScript.
+///
+/// `wasm_bindgen` cannot export generic types, so this type is exported for a specific decider
+/// with the [`export_decider`](crate::export_decider) macro.
+pub struct WasmRunner<C, E, S, D>
+where
+    D: Decider<C, E, S, S>,
+{
+    state: S,
+    command: PhantomData<C>,
+    event: PhantomData<E>,
+    decider: PhantomData<D>,
+}
+
+impl<C, E, S, D> WasmRunner<C, E, S, D>
+where
+    C: DeserializeOwned,
+    E: Serialize,
+    S: Serialize,
+    D: Decider<C, E, S, S>,
+{
+    /// Constructs a new `WasmRunner`, initializing the state to the initial state of the decider.
+    pub fn new() -> Self {
+        Self {
+            state: D::initial_state(),
+            command: PhantomData,
+            event: PhantomData,
+            decider: PhantomData,
+        }
+    }
+
+    /// Parses the command in the JSON string `json`, feeds it through the decider, evolves the
+    /// state, and returns the generated events as a JSON array.
+    pub fn command(&mut self, json: &str) -> Result<String, WasmError> {
+        let command: C = serde_json::from_str(json).map_err(|e| WasmError {
+            code: WasmErrorCode::BadJson,
+            message: e.to_string(),
+        })?;
+        if D::is_terminal(&self.state) {
+            return Err(WasmError {
+                code: WasmErrorCode::Terminal,
+                message: "the decider is in a terminal state".to_string(),
+            });
+        }
+        let events = D::decide(&command, &self.state);
+        // The events are serialized first, so the state is left unchanged if they cannot be.
+        let json = to_json(&events)?;
+        evolve_all_in_place(&mut self.state, &events, D::evolve_in_place);
+        Ok(json)
+    }
+
+    /// Returns the current state of the decider as JSON.
+    pub fn state(&self) -> Result<String, WasmError> {
+        to_json(&self.state)
+    }
+}
+
+/// Serializes `value` to JSON, with a [`WasmErrorCode::BadJson`] error if it cannot be.
+fn to_json<T: Serialize>(value: &T) -> Result<String, WasmError> {
+    serde_json::to_string(value).map_err(|e| WasmError {
+        code: WasmErrorCode::BadJson,
+        message: e.to_string(),
+    })
+}
+
+impl<C, E, S, D> Default for WasmRunner<C, E, S, D>
+where
+    C: DeserializeOwned,
+    E: Serialize,
+    S: Serialize,
+    D: Decider<C, E, S, S>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Exports a decider to JavaScript as a class wrapping a [`WasmRunner`].
+///
+/// `export_decider!(BulbRunner: Bulb[Command, Event, State] as "bulb")` defines a Rust type
+/// `BulbRunner`, exported to JavaScript as the class `bulb`, with a constructor and the methods
+/// `command(json)` and `state()`. Errors are thrown as JavaScript `Error`s whose messages start
+/// with their [`WasmErrorCode`](crate::wasm::WasmErrorCode) in brackets.
+///
+/// Requires the `wasm` feature.
+#[macro_export]
+macro_rules! export_decider {
+    ($name:ident : $decider:ty [$command:ty, $event:ty, $state:ty] as $js_name:literal) => {
+        // wasm_bindgen only recognizes its attributes inside an impl by their bare name, so it is
+        // imported in a scope of its own to avoid clashing with the caller's imports.
+        const _: () = {
+            use $crate::wasm::wasm_bindgen::prelude::wasm_bindgen;
+
+            #[wasm_bindgen(js_class = $js_name, wasm_bindgen = $crate::wasm::wasm_bindgen)]
+            impl $name {
+                #[wasm_bindgen(constructor)]
+                pub fn new() -> Self {
+                    Self {
+                        runner: $crate::wasm::WasmRunner::new(),
+                    }
+                }
+
+                pub fn command(
+                    &mut self,
+                    json: &str,
+                ) -> Result<String, $crate::wasm::wasm_bindgen::JsError> {
+                    self.runner
+                        .command(json)
+                        .map_err(|e| $crate::wasm::wasm_bindgen::JsError::new(&e.to_string()))
+                }
+
+                pub fn state(&self) -> Result<String, $crate::wasm::wasm_bindgen::JsError> {
+                    self.runner
+                        .state()
+                        .map_err(|e| $crate::wasm::wasm_bindgen::JsError::new(&e.to_string()))
+                }
+            }
+        };
+
+        #[$crate::wasm::wasm_bindgen::prelude::wasm_bindgen(js_name = $js_name, wasm_bindgen = $crate::wasm::wasm_bindgen)]
+        pub struct $name {
+            runner: $crate::wasm::WasmRunner<$command, $event, $state, $decider>,
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+    };
+}
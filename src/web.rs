@@ -0,0 +1,325 @@
+//! HTTP endpoints for event-sourced runners, built with `axum`.
+//!
+//! [`command_router`] turns a shared [`EventSourcedRunner`] into a [`Router`] accepting commands
+//! as JSON, and [`keyed_command_router`] does the same for every key of a
+//! [`KeyedEventSourcedRunner`]. Failures are returned as [`Problem`] bodies with a status code
+//! telling them apart, so that clients can react to each kind.
+//!
+//! Requires the `axum` feature.
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use axum::{
+    extract::{rejection::JsonRejection, Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::deciders::{Decider, SnapshotSchema};
+use crate::stores::{
+    EventSourcedRunner, EventStore, KeyedEventSourcedRunner, KeyedSnapshotStore, StoreError,
+};
+use crate::utilities::StreamVersion;
+
+/// A command sent to an endpoint as the JSON body of a request.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CommandEnvelope<C> {
+    /// The command to feed through the runner.
+    pub command: C,
+    /// The version the stream must be at for the command to be accepted, as read by the client,
+    /// or `None` to accept the command at any version.
+    #[serde(default)]
+    pub expected_version: Option<StreamVersion>,
+}
+
+/// An event returned by an endpoint, along with the version of the stream it left.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EventEnvelope<E> {
+    /// The version of the stream once the event was appended.
+    pub version: StreamVersion,
+    /// The event.
+    pub event: E,
+}
+
+/// A failure returned by an endpoint, with a problem details body as described by RFC 9457.
+///
+/// | Status | Type | Cause |
+/// |---|---|---|
+/// | 400 | `malformed-json` | The body is not valid JSON. |
+/// | 404 | `not-found` | The key of a keyed runner has no state. |
+/// | 406 | `not-acceptable` | The request does not accept a JSON response. |
+/// | 409 | `version-conflict` | The stream is not at the expected version, or was appended to by another writer. |
+/// | 409 | `terminal` | The decider is in a terminal state and accepts no more commands. |
+/// | 410 | `archived` | The stream was archived. |
+/// | 415 | `unsupported-media-type` | The body is not sent as `application/json`. |
+/// | 422 | `invalid-command` | The body is JSON but not a command envelope of the decider. |
+/// | 500 | `store-failure` | The event store failed. |
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Problem {
+    /// The kind of problem, from the table above.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The reason phrase of the status code.
+    pub title: String,
+    /// The status code of the response.
+    pub status: u16,
+    /// A description of this occurrence of the problem.
+    pub detail: String,
+}
+
+impl Problem {
+    /// Constructs a new `Problem` of the kind `kind` with the status code `status`.
+    pub fn new(status: StatusCode, kind: &str, detail: impl Into<String>) -> Self {
+        Self {
+            kind: kind.to_string(),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail: detail.into(),
+        }
+    }
+
+    fn terminal() -> Self {
+        Self::new(
+            StatusCode::CONFLICT,
+            "terminal",
+            "the decider is in a terminal state",
+        )
+    }
+}
+
+impl From<JsonRejection> for Problem {
+    fn from(rejection: JsonRejection) -> Self {
+        let kind = match rejection {
+            JsonRejection::JsonDataError(_) => "invalid-command",
+            JsonRejection::MissingJsonContentType(_) => "unsupported-media-type",
+            _ => "malformed-json",
+        };
+        Self::new(rejection.status(), kind, rejection.body_text())
+    }
+}
+
+impl From<StoreError> for Problem {
+    fn from(error: StoreError) -> Self {
+        match error {
+            StoreError::Conflict { .. } => {
+                Self::new(StatusCode::CONFLICT, "version-conflict", error.to_string())
+            }
+            StoreError::Archived(_) => Self::new(StatusCode::GONE, "archived", error.to_string()),
+            _ => Self::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "store-failure",
+                error.to_string(),
+            ),
+        }
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut response = (status, Json(self)).into_response();
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/problem+json"),
+        );
+        response
+    }
+}
+
+/// An [`EventSourcedRunner`] shared by the handlers of a [`command_router`].
+pub type SharedEventSourcedRunner<C, E, S, D, ES, SS> =
+    Arc<Mutex<EventSourcedRunner<C, E, S, D, ES, SS>>>;
+
+/// A [`KeyedEventSourcedRunner`] shared by the handlers of a [`keyed_command_router`].
+pub type SharedKeyedRunner<C, E, S, D, ES, KS> =
+    Arc<Mutex<KeyedEventSourcedRunner<C, E, S, D, ES, KS>>>;
+
+/// Returns a router with the endpoints of `runner`:
+///
+/// - `POST /commands` feeds the command of the [`CommandEnvelope`] in the body through the
+///   runner, returning the events it appended as a JSON array of [`EventEnvelope`]s.
+/// - `GET /state` returns the state of the decider as JSON.
+///
+/// Failures are returned as [`Problem`]s.
+pub fn command_router<C, E, S, D, ES, SS>(
+    runner: SharedEventSourcedRunner<C, E, S, D, ES, SS>,
+) -> Router
+where
+    C: DeserializeOwned + Send + 'static,
+    E: Serialize + Send + 'static,
+    S: Serialize + Send + 'static,
+    D: Decider<C, E, S, S> + Send + 'static,
+    ES: EventStore<E> + Send + 'static,
+    SS: Send + 'static,
+{
+    Router::new()
+        .route("/commands", post(post_command::<C, E, S, D, ES, SS>))
+        .route("/state", get(get_state::<C, E, S, D, ES, SS>))
+        .with_state(runner)
+}
+
+/// Returns a router with the endpoints of every key of `runner`:
+///
+/// - `POST /aggregates/{id}/commands` feeds the command of the [`CommandEnvelope`] in the body
+///   to the key `id`, returning the events it appended as a JSON array of [`EventEnvelope`]s.
+///   The expected version is the version of the stream shared by every key.
+/// - `GET /aggregates/{id}/state` returns the state of the key `id` as JSON.
+///
+/// Failures are returned as [`Problem`]s.
+pub fn keyed_command_router<C, E, S, D, ES, KS>(
+    runner: SharedKeyedRunner<C, E, S, D, ES, KS>,
+) -> Router
+where
+    C: DeserializeOwned + Send + 'static,
+    E: Serialize + Send + 'static,
+    S: Serialize + Send + 'static,
+    D: SnapshotSchema<S> + Decider<C, E, S, S> + Send + 'static,
+    ES: EventStore<(String, E)> + Send + 'static,
+    KS: KeyedSnapshotStore<String, D::Snapshot> + Send + 'static,
+{
+    Router::new()
+        .route(
+            "/aggregates/{id}/commands",
+            post(post_keyed_command::<C, E, S, D, ES, KS>),
+        )
+        .route(
+            "/aggregates/{id}/state",
+            get(get_keyed_state::<C, E, S, D, ES, KS>),
+        )
+        .with_state(runner)
+}
+
+/// Locks `runner`. A decider that panicked did so before anything was appended, leaving the
+/// runner as it was, so a poisoned lock is still used.
+fn lock<T>(runner: &Mutex<T>) -> MutexGuard<'_, T> {
+    runner.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Checks that the request accepts a JSON response, which it does if it says nothing.
+fn accepts_json(headers: &HeaderMap) -> Result<(), Problem> {
+    let Some(accept) = headers.get(header::ACCEPT) else {
+        return Ok(());
+    };
+    let accepted = accept
+        .to_str()
+        .unwrap_or_default()
+        .split(',')
+        .map(|media| media.split(';').next().unwrap_or_default().trim())
+        .any(|media| matches!(media, "application/json" | "application/*" | "*/*"));
+    if accepted {
+        Ok(())
+    } else {
+        Err(Problem::new(
+            StatusCode::NOT_ACCEPTABLE,
+            "not-acceptable",
+            "responses are only available as application/json",
+        ))
+    }
+}
+
+/// Checks that the stream is at the version the envelope expects, if it expects one.
+fn check_version(expected: Option<StreamVersion>, version: StreamVersion) -> Result<(), Problem> {
+    match expected {
+        Some(expected) if expected != version => Err(Problem::new(
+            StatusCode::CONFLICT,
+            "version-conflict",
+            format!("expected version {expected}, the stream is at version {version}"),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Wraps `events`, appended to a stream at the version `previous`, in envelopes.
+fn envelopes<E>(previous: StreamVersion, events: Vec<E>) -> Vec<EventEnvelope<E>> {
+    (1..)
+        .zip(events)
+        .map(|(appended, event)| EventEnvelope {
+            version: previous + appended,
+            event,
+        })
+        .collect()
+}
+
+async fn post_command<C, E, S, D, ES, SS>(
+    State(runner): State<SharedEventSourcedRunner<C, E, S, D, ES, SS>>,
+    headers: HeaderMap,
+    body: Result<Json<CommandEnvelope<C>>, JsonRejection>,
+) -> Result<Json<Vec<EventEnvelope<E>>>, Problem>
+where
+    D: Decider<C, E, S, S>,
+    ES: EventStore<E>,
+{
+    accepts_json(&headers)?;
+    let Json(envelope) = body?;
+    let mut runner = lock(&runner);
+    let previous = runner.version();
+    check_version(envelope.expected_version, previous)?;
+    if D::is_terminal(runner.get_state()) {
+        return Err(Problem::terminal());
+    }
+    let events = runner.command(&envelope.command)?;
+    Ok(Json(envelopes(previous, events)))
+}
+
+async fn get_state<C, E, S, D, ES, SS>(
+    State(runner): State<SharedEventSourcedRunner<C, E, S, D, ES, SS>>,
+    headers: HeaderMap,
+) -> Result<Response, Problem>
+where
+    S: Serialize,
+    D: Decider<C, E, S, S>,
+    ES: EventStore<E>,
+{
+    accepts_json(&headers)?;
+    Ok(Json(lock(&runner).get_state()).into_response())
+}
+
+async fn post_keyed_command<C, E, S, D, ES, KS>(
+    State(runner): State<SharedKeyedRunner<C, E, S, D, ES, KS>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    body: Result<Json<CommandEnvelope<C>>, JsonRejection>,
+) -> Result<Json<Vec<EventEnvelope<E>>>, Problem>
+where
+    D: SnapshotSchema<S> + Decider<C, E, S, S>,
+    ES: EventStore<(String, E)>,
+    KS: KeyedSnapshotStore<String, D::Snapshot>,
+{
+    accepts_json(&headers)?;
+    let Json(envelope) = body?;
+    let mut runner = lock(&runner);
+    let previous = runner.version();
+    check_version(envelope.expected_version, previous)?;
+    if runner.get_state().get(&id).is_some_and(D::is_terminal) {
+        return Err(Problem::terminal());
+    }
+    let events = runner.command(&(id, envelope.command))?;
+    let events = events.into_iter().map(|(_, event)| event).collect();
+    Ok(Json(envelopes(previous, events)))
+}
+
+async fn get_keyed_state<C, E, S, D, ES, KS>(
+    State(runner): State<SharedKeyedRunner<C, E, S, D, ES, KS>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, Problem>
+where
+    S: Serialize,
+    D: SnapshotSchema<S> + Decider<C, E, S, S>,
+    ES: EventStore<(String, E)>,
+    KS: KeyedSnapshotStore<String, D::Snapshot>,
+{
+    accepts_json(&headers)?;
+    let runner = lock(&runner);
+    match runner.get_state().get(&id) {
+        Some(state) => Ok(Json(state).into_response()),
+        None => Err(Problem::new(
+            StatusCode::NOT_FOUND,
+            "not-found",
+            format!("{id} has no state"),
+        )),
+    }
+}
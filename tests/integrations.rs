@@ -1,36 +1,80 @@
+use deciders_rs::debug::Debugger;
+use deciders_rs::deciders::laws::{check_decider, Law, LawViolation};
 use deciders_rs::deciders::{
-    AdaptedDecider, ComposedDeciders, Decider, ManyDecider, MappedDecider,
+    adapt, compose, fold_events, map, Adapted, AdaptedDecider, Adapter, AllTerminal, AndGuard,
+    AnyTerminal, AppliedDecider, Apply, BoundedDecider, Bridged, BroadcastDecider, ChainedUpcaster,
+    Composed, Composed3Deciders, Composed4Deciders, ComposedDeciders, ContextDecider,
+    ContextProvider, CountingDecider, DeadLetter, Decider, DeciderStats, DedupDecider,
+    DedupLastDecider, DimappedDecider, DynDecider, ErasedDecider, EventUpcaster, FilteredDecider,
+    Guard, GuardFailure, GuardedDecider, InitialStateOf, Lens, Lifted, ManyDecider, Map2,
+    Map2Deciders, Map2EitherDeciders, Map3Deciders, Mapped, MappedCommandDecider, MappedDecider,
+    MappedEventDecider, MarkedRestartingDecider, NeutralDecider, NeutralFor, Phase, Predicate,
+    ProvidedContext, RejectingDecider, Reporting, Restarted, RestartingDecider, SequencedDecider,
+    Snapshot, Snapshotting, SplitDecider, Statically, StreamTag, Streamed, StrictBoundedDecider,
+    StrictTerminalGuard, Tagged, TaggedDecider, TerminalError, TerminalGuard, TooManyEvents,
+    TryAdaptedDecider, TryDecider, UpcastingDecider, Validator, ZoomedDecider, MAX_DEAD_LETTERS,
 };
-use deciders_rs::processes::{AdaptedProcess, CombinedProcessDecider, Process};
-use deciders_rs::utilities::{Either, FallibleConverter, InMemoryRunner, InfallibleConverter};
+use deciders_rs::processes::{
+    branch, collect_fold, compensate_with, emit, expect, on_event, on_event_type, AdaptedProcess,
+    CombinedProcessDecider, CompleteActionError, Delayable, GuardedState, HumanTask,
+    InMemoryProcessStateStore, InMemoryScheduleStore, Join, NeutralProcess, PendingAction, Process,
+    ProcessRouter, ProcessRunner, ProcessStateStore, RoutedProcess, RouterDefinition, Saga,
+    SagaBuilder, SagaDefinition, SagaDefinitionError, SagaPhase, SagaProcess, SagaState, Scheduler,
+    Unrouted, VersionConflict, Watchdog, WatchdogBuilder, WatchdogDefinition, WatchdogProcess,
+    WatchdogState,
+};
+use deciders_rs::projections::{
+    diff_maps, event_type_name, merge_view_changes, CatchUpError, Checkpoint, ConsistencyToken,
+    CountByEventType, EventLog, GapError, GapPolicy, InMemoryCheckpointStore, InMemoryProjection,
+    KeyedProjection, PositionError, Projection, ProjectionRunner, QueryError, QueryRegistry,
+    QueryResult, RoutedProjection, Stale, TimeBucketDefinition, TimeBucketedProjection, ViewChange,
+    WaitError,
+};
+use deciders_rs::stores::{
+    apply_retention, migrate_stream, migrate_streams, verify_snapshot,
+    verify_snapshot_fingerprints, verify_snapshots, ApplyResult, ChaosCounters, ChaosOperation,
+    ChaosStore, ComposedEventSourcedRunner, Diagnostic, EventSourcedRunner, EventStore,
+    InMemoryEventStore, InMemoryKeyedSnapshotStore, InMemorySnapshotStore, InboxStore, Interleaved,
+    KeyedEventSourcedRunner, MaxAge, MigrationOptions, MigrationReport, RetentionPolicy,
+    RetentionReport, SnapshotPolicy, SnapshotStore, StoreError, StreamAppend, StreamRetention,
+    VerificationReport,
+};
+use deciders_rs::tenancy::{TenantId, TenantedRunner};
+use deciders_rs::testing::{
+    assert_enumerated_transition_coverage, assert_transition_coverage, compare_replays,
+    compare_replays_with_commands, Divergence, ProjectionSpec, TransitionExpectations,
+};
+use deciders_rs::utilities::{
+    lefts, partition_keyed, rights, spawn_thread_runner, spawn_thread_runner_with_policy,
+    split_either, Actor, AdmissionPolicy, Authorizer, AuthorizingRunner, CommandUpcaster, Denied,
+    DynRunner, Either, EventPublisher, FallibleConverter, FallibleInMemoryRunner, FingerprintSet,
+    GlobalPosition, HeterogeneousKeyedRunner, InMemoryRunner, InfallibleConverter,
+    KeyedCommandError, MigrationError, OneOf3, OverflowMode, PublishingRunner, Registry,
+    RunnerStats, SharedEventBus, SharedRunner, SimpleEventBus, StateFingerprint, StateSpaceMonitor,
+    StreamVersion, ThreadRunnerError, UpcastingCommandRunner, Watermark,
+};
+use deciders_rs::viz;
+use std::cell::RefCell;
 use std::collections::HashMap;
-
-struct NeutralDecider;
-
-impl Decider<(), (), (), ()> for NeutralDecider {
-    fn decide(_command: &(), _state: &()) -> Vec<()> {
-        vec![]
-    }
-
-    fn evolve(_state: &(), _event: &()) {}
-
-    fn initial_state() {}
-
-    fn is_terminal(_state: &()) -> bool {
-        true
-    }
-}
+use std::rc::{Rc, Weak};
+use std::time::Duration;
 
 mod bulb {
     use super::Decider;
+    #[cfg(feature = "serde")]
+    use deciders_rs::deciders::{MigrateError, SnapshotMigrator};
+    use deciders_rs::deciders::{SnapshotSchema, Snapshotting};
+    use deciders_rs::utilities::{hash_fingerprint, StateFingerprint};
 
-    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Copy, Clone, Debug, PartialEq)]
     pub enum Command {
         Fit { max_uses: u64 },
         SwitchOn,
         SwitchOff,
     }
 
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     #[derive(Copy, Clone, Debug, PartialEq)]
     pub enum Event {
         Fitted { max_uses: u64 },
@@ -39,13 +83,15 @@ mod bulb {
         Blew,
     }
 
-    #[derive(Copy, Clone, Debug, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
     pub enum Status {
         On,
         Off,
     }
 
-    #[derive(Copy, Clone, Debug, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
     pub enum State {
         NotFitted,
         Working { status: Status, remaining_uses: u64 },
@@ -115,24 +161,138 @@ mod bulb {
             matches!(*state, State::Blown)
         }
     }
+
+    impl StateFingerprint for State {
+        fn fingerprint(&self) -> u64 {
+            hash_fingerprint(self)
+        }
+    }
+
+    /// Snapshots only keep what the bulb still needs: whether it is on and how many uses it has
+    /// left, if it is working at all.
+    impl Snapshotting<State> for Bulb {
+        type Snapshot = Result<(bool, u64), bool>;
+
+        fn to_snapshot(state: &State) -> Self::Snapshot {
+            match *state {
+                State::NotFitted => Err(false),
+                State::Working {
+                    status,
+                    remaining_uses,
+                } => Ok((status == Status::On, remaining_uses)),
+                State::Blown => Err(true),
+            }
+        }
+
+        fn from_snapshot(snapshot: &Self::Snapshot) -> State {
+            match *snapshot {
+                Err(false) => State::NotFitted,
+                Ok((on, remaining_uses)) => State::Working {
+                    status: if on { Status::On } else { Status::Off },
+                    remaining_uses,
+                },
+                Err(true) => State::Blown,
+            }
+        }
+    }
+
+    /// The first schema stored whether the bulb was fitted, lit and how many uses it had left.
+    impl SnapshotSchema<State> for Bulb {
+        const SCHEMA_VERSION: u32 = 2;
+    }
+
+    #[cfg(feature = "serde")]
+    impl SnapshotMigrator<State> for Bulb {
+        fn migrate(version: u32, raw: serde_json::Value) -> Result<Self::Snapshot, MigrateError> {
+            #[derive(serde::Deserialize)]
+            struct SnapshotV1 {
+                fitted: bool,
+                lit: bool,
+                uses_left: u64,
+            }
+
+            let error = |reason: String| MigrateError {
+                schema_version: version,
+                reason,
+            };
+            if version != 1 {
+                return Err(error("unknown schema version".to_string()));
+            }
+            let v1: SnapshotV1 = serde_json::from_value(raw).map_err(|e| error(e.to_string()))?;
+            Ok(match v1 {
+                SnapshotV1 { fitted: false, .. } => Err(false),
+                SnapshotV1 { lit, uses_left, .. } => Ok((lit, uses_left)),
+            })
+        }
+    }
+}
+
+mod switch {
+    use deciders_rs::decider;
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum Command {
+        Press,
+        Break,
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum Event {
+        Toggled,
+        Broke,
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum State {
+        Off,
+        On,
+        Broken,
+    }
+
+    pub struct Switch;
+
+    decider! {
+        Switch[Command, Event, State];
+        decide(command, state) {
+            (_, State::Broken) => vec![],
+            (Command::Press, _) => vec![Event::Toggled],
+            (Command::Break, _) => vec![Event::Broke],
+        }
+        evolve(state, event) {
+            (_, Event::Broke) => State::Broken,
+            (State::Off, Event::Toggled) => State::On,
+            (State::On, Event::Toggled) => State::Off,
+            (State::Broken, Event::Toggled) => State::Broken,
+        }
+        initial_state() {
+            State::Off
+        }
+        is_terminal(state) {
+            *state == State::Broken
+        }
+    }
 }
 
 mod cat {
     use super::Decider;
+    use deciders_rs::testing::{EnumerableEvent, EnumerableState};
+    use deciders_rs::utilities::{hash_fingerprint, StateFingerprint};
+    use deciders_rs::viz::Enumerable;
 
-    #[derive(Copy, Clone, Debug)]
+    #[derive(Copy, Clone, Debug, PartialEq)]
     pub enum Command {
         WakeUp,
         GetToSleep,
     }
 
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     #[derive(Copy, Clone, Debug, PartialEq)]
     pub enum Event {
         WokeUp,
         GotToSleep,
     }
 
-    #[derive(Copy, Clone, Debug, PartialEq)]
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
     pub enum State {
         Awake,
         Asleep,
@@ -166,6 +326,154 @@ mod cat {
             false
         }
     }
+
+    impl StateFingerprint for State {
+        fn fingerprint(&self) -> u64 {
+            hash_fingerprint(self)
+        }
+    }
+
+    impl EnumerableState for State {
+        fn exemplars() -> Vec<Self> {
+            vec![State::Awake, State::Asleep]
+        }
+    }
+
+    impl EnumerableEvent for Event {
+        fn exemplars() -> Vec<Self> {
+            vec![Event::WokeUp, Event::GotToSleep]
+        }
+    }
+
+    impl Enumerable<Command, State> for Cat {
+        fn all_states() -> Vec<State> {
+            State::exemplars()
+        }
+
+        fn all_commands() -> Vec<Command> {
+            vec![Command::WakeUp, Command::GetToSleep]
+        }
+    }
+}
+
+/// A smaller copy of the decider in the `update_server` example, which cannot be imported here.
+mod update_server {
+    use deciders_rs::deciders::Decider;
+    use deciders_rs::viz::Enumerable;
+
+    pub const VERSIONS: [&str; 3] = ["1.0.0", "1.1.0", "2.0.0"];
+
+    #[derive(Clone, Debug)]
+    pub enum Command {
+        QueryForUpdate { current_version: String },
+        DownloadUpdate { desired_version: String },
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum State {
+        NewConnection,
+        UpdateAvailable { new_versions: Vec<&'static str> },
+        UnknownVersion,
+        NoNewUpdate,
+        DownloadReady { version: &'static str },
+        DownloadUnavailable,
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum Event {
+        UpdateAvailable { new_versions: Vec<&'static str> },
+        UnknownVersionQueried,
+        AlreadyUpToDate,
+        GotUpdateData { version: &'static str },
+        InvalidVersion,
+    }
+
+    pub struct UpdateServer;
+
+    impl Decider<Command, Event, State, State> for UpdateServer {
+        fn decide(command: &Command, state: &State) -> Vec<Event> {
+            match (state, command) {
+                (State::NewConnection, Command::QueryForUpdate { current_version }) => {
+                    match VERSIONS.iter().position(|v| v == current_version) {
+                        None => vec![Event::UnknownVersionQueried],
+                        Some(i) if i + 1 == VERSIONS.len() => vec![Event::AlreadyUpToDate],
+                        Some(i) => vec![Event::UpdateAvailable {
+                            new_versions: VERSIONS[i + 1..].to_vec(),
+                        }],
+                    }
+                }
+                (
+                    State::UpdateAvailable { new_versions },
+                    Command::DownloadUpdate { desired_version },
+                ) => match new_versions.iter().find(|v| *v == desired_version) {
+                    Some(version) => vec![Event::GotUpdateData { version }],
+                    None => vec![Event::InvalidVersion],
+                },
+                _ => vec![],
+            }
+        }
+
+        fn evolve(state: &State, event: &Event) -> State {
+            match (state, event) {
+                (State::NewConnection, Event::UpdateAvailable { new_versions }) => {
+                    State::UpdateAvailable {
+                        new_versions: new_versions.clone(),
+                    }
+                }
+                (State::NewConnection, Event::UnknownVersionQueried) => State::UnknownVersion,
+                (State::NewConnection, Event::AlreadyUpToDate) => State::NoNewUpdate,
+                (State::UpdateAvailable { .. }, Event::GotUpdateData { version }) => {
+                    State::DownloadReady { version }
+                }
+                (State::UpdateAvailable { .. }, Event::InvalidVersion) => {
+                    State::DownloadUnavailable
+                }
+                _ => state.clone(),
+            }
+        }
+
+        fn initial_state() -> State {
+            State::NewConnection
+        }
+
+        fn is_terminal(state: &State) -> bool {
+            matches!(
+                state,
+                State::NoNewUpdate | State::DownloadReady { .. } | State::DownloadUnavailable
+            )
+        }
+    }
+
+    impl Enumerable<Command, State> for UpdateServer {
+        fn all_states() -> Vec<State> {
+            vec![
+                State::NewConnection,
+                State::UpdateAvailable {
+                    new_versions: VERSIONS[1..].to_vec(),
+                },
+                State::UnknownVersion,
+                State::NoNewUpdate,
+                State::DownloadReady { version: "2.0.0" },
+                State::DownloadUnavailable,
+            ]
+        }
+
+        fn all_commands() -> Vec<Command> {
+            let query = |version: &str| Command::QueryForUpdate {
+                current_version: version.to_string(),
+            };
+            let download = |version: &str| Command::DownloadUpdate {
+                desired_version: version.to_string(),
+            };
+            vec![
+                query("1.0.0"),
+                query("2.0.0"),
+                query("0.1.0"),
+                download("2.0.0"),
+                download("0.1.0"),
+            ]
+        }
+    }
 }
 
 mod cat_light {
@@ -228,10 +536,7 @@ fn run_decider<C, E, S, D>(events: &[E], command: &C) -> Vec<E>
 where
     D: Decider<C, E, S, S>,
 {
-    let new_state = events
-        .iter()
-        .fold(D::initial_state(), |s, e| D::evolve(&s, e));
-    D::decide(command, &new_state)
+    D::decide(command, &D::fold(events))
 }
 
 #[test]
@@ -655,3 +960,9251 @@ fn compose_process() {
         )
     );
 }
+
+#[test]
+fn event_sourced_runner_test() {
+    use bulb::{Bulb, Command, Event, State, Status};
+
+    let mut runner = EventSourcedRunner::<Command, Event, State, Bulb, _>::load(
+        "hall",
+        InMemoryEventStore::new(),
+    )
+    .unwrap();
+    assert_eq!(
+        runner.command(&Command::Fit { max_uses: 2 }),
+        Ok(vec![Event::Fitted { max_uses: 2 }])
+    );
+    assert_eq!(
+        runner.command(&Command::SwitchOn),
+        Ok(vec![Event::SwitchedOn])
+    );
+    assert_eq!(runner.version(), StreamVersion::from(2));
+
+    // Loading the stream again folds the stored events into the same state.
+    let store = runner.into_store();
+    assert_eq!(
+        store.load("hall"),
+        Ok(vec![Event::Fitted { max_uses: 2 }, Event::SwitchedOn])
+    );
+    let runner = EventSourcedRunner::<Command, Event, State, Bulb, _>::load("hall", store).unwrap();
+    assert_eq!(
+        *runner.get_state(),
+        State::Working {
+            status: Status::On,
+            remaining_uses: 1
+        }
+    );
+}
+
+type CatAndBulbEvent = Either<cat::Event, bulb::Event>;
+
+fn interleaved(index: u64, event: CatAndBulbEvent) -> Interleaved<CatAndBulbEvent> {
+    Interleaved { index, event }
+}
+
+#[test]
+fn composed_event_sourced_runner_test() {
+    use Either::*;
+    type CatAndBulb = ComposedDeciders<
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        bulb::Bulb,
+        bulb::Command,
+        bulb::Event,
+        bulb::State,
+    >;
+    type Runner<ES> = ComposedEventSourcedRunner<
+        Either<cat::Command, bulb::Command>,
+        cat::Event,
+        bulb::Event,
+        (cat::State, bulb::State),
+        CatAndBulb,
+        ES,
+    >;
+
+    let mut runner = Runner::load("cat", "bulb", InMemoryEventStore::new()).unwrap();
+    runner
+        .command(&Right(bulb::Command::Fit { max_uses: 5 }))
+        .unwrap();
+    runner.command(&Left(cat::Command::GetToSleep)).unwrap();
+    runner.command(&Right(bulb::Command::SwitchOn)).unwrap();
+    assert_eq!(
+        runner.versions(),
+        (StreamVersion::from(1), StreamVersion::from(2))
+    );
+
+    // Each side's events are in its own stream, indexed in the order they were decided in.
+    let mut store = runner.into_store();
+    assert_eq!(
+        store.load("cat"),
+        Ok(vec![interleaved(1, Left(cat::Event::GotToSleep))])
+    );
+    assert_eq!(
+        store.load("bulb"),
+        Ok(vec![
+            interleaved(0, Right(bulb::Event::Fitted { max_uses: 5 })),
+            interleaved(2, Right(bulb::Event::SwitchedOn)),
+        ])
+    );
+    let expected = (
+        cat::State::Asleep,
+        bulb::State::Working {
+            status: bulb::Status::On,
+            remaining_uses: 4,
+        },
+    );
+    let runner = Runner::load("cat", "bulb", store.clone()).unwrap();
+    assert_eq!(*runner.get_state(), expected);
+
+    // A conflict on the bulb stream alone rejects the whole append and names the bulb stream.
+    let cat_events = [interleaved(3, Left(cat::Event::WokeUp))];
+    assert_eq!(
+        store.append_multi(&[
+            StreamAppend {
+                stream_id: "cat",
+                expected: StreamVersion::from(1),
+                events: &cat_events,
+            },
+            StreamAppend {
+                stream_id: "bulb",
+                expected: StreamVersion::from(1),
+                events: &[],
+            },
+        ]),
+        Err(StoreError::Conflict {
+            stream_id: "bulb".to_string(),
+            expected: StreamVersion::from(1),
+            stored: StreamVersion::from(2),
+        })
+    );
+    assert_eq!(store.version("cat"), Ok(StreamVersion::from(1)));
+}
+
+#[test]
+fn inbox_test() {
+    use bulb::{Bulb, Command, Event, State, Status};
+
+    let mut runner = EventSourcedRunner::<Command, Event, State, Bulb, _>::load(
+        "hall",
+        InMemoryEventStore::new().with_inbox_capacity(2),
+    )
+    .unwrap();
+    runner.command(&Command::Fit { max_uses: 3 }).unwrap();
+
+    // The broker redelivers the first switch-on, which only uses the bulb once.
+    assert_eq!(
+        runner.apply_external("mqtt", "1", &Event::SwitchedOn),
+        Ok(ApplyResult::Applied(StreamVersion::from(2)))
+    );
+    assert_eq!(
+        runner.apply_external("mqtt", "1", &Event::SwitchedOn),
+        Ok(ApplyResult::Duplicate)
+    );
+    assert_eq!(
+        *runner.get_state(),
+        State::Working {
+            status: Status::On,
+            remaining_uses: 2
+        }
+    );
+
+    // The same id from another source is a different event.
+    assert_eq!(
+        runner.apply_external("zigbee", "1", &Event::SwitchedOff),
+        Ok(ApplyResult::Applied(StreamVersion::from(3)))
+    );
+    assert_eq!(runner.store().has_received("hall", "zigbee", "1"), Ok(true));
+
+    // Once the inbox is full, the oldest event is forgotten.
+    runner
+        .apply_external("mqtt", "2", &Event::SwitchedOn)
+        .unwrap();
+    assert_eq!(runner.store().has_received("hall", "mqtt", "1"), Ok(false));
+    assert_eq!(runner.store().has_received("hall", "mqtt", "2"), Ok(true));
+    assert_eq!(
+        runner.store().load("hall"),
+        Ok(vec![
+            Event::Fitted { max_uses: 3 },
+            Event::SwitchedOn,
+            Event::SwitchedOff,
+            Event::SwitchedOn,
+        ])
+    );
+}
+
+#[test]
+fn retention_test() {
+    use bulb::{Bulb, Command, Event, State};
+
+    let mut snapshots = InMemorySnapshotStore::new();
+    let mut runner = EventSourcedRunner::<Command, Event, State, Bulb, _>::load(
+        "hall",
+        InMemoryEventStore::new(),
+    )
+    .unwrap();
+    for command in [
+        Command::Fit { max_uses: 5 },
+        Command::SwitchOn,
+        Command::SwitchOff,
+    ] {
+        runner.command(&command).unwrap();
+    }
+    snapshots
+        .save(
+            "hall",
+            &Snapshot::of::<Bulb, _>(runner.get_state(), runner.version()),
+        )
+        .unwrap();
+    runner.command(&Command::SwitchOn).unwrap();
+    runner.command(&Command::SwitchOff).unwrap();
+    let state = *runner.get_state();
+    let mut store = runner.into_store();
+    store
+        .append(
+            "porch",
+            StreamVersion::INITIAL,
+            &[Event::Fitted { max_uses: 1 }, Event::SwitchedOn],
+        )
+        .unwrap();
+
+    // The snapshot of the hall keeps the events after it, and the porch has no snapshot at all.
+    let mut policy = RetentionPolicy {
+        max_events: Some(1),
+        max_age: None,
+        respect_snapshot_barrier: true,
+        dry_run: true,
+    };
+    let report = apply_retention(&mut store, &snapshots, &policy).unwrap();
+    assert_eq!(
+        report.streams,
+        vec![
+            StreamRetention {
+                stream_id: "hall".to_string(),
+                first: StreamVersion::INITIAL,
+                kept_from: StreamVersion::from(3),
+                held_by_snapshot: true,
+            },
+            StreamRetention {
+                stream_id: "porch".to_string(),
+                first: StreamVersion::INITIAL,
+                kept_from: StreamVersion::INITIAL,
+                held_by_snapshot: true,
+            },
+        ]
+    );
+    assert_eq!(report.removed(), 3);
+    assert_eq!(store.load("hall").unwrap().len(), 5);
+
+    policy.dry_run = false;
+    assert_eq!(
+        apply_retention(&mut store, &snapshots, &policy),
+        Ok(RetentionReport {
+            dry_run: false,
+            ..report
+        })
+    );
+    assert_eq!(
+        store.load("hall"),
+        Err(StoreError::Truncated {
+            stream_id: "hall".to_string(),
+            first: StreamVersion::from(3),
+        })
+    );
+    assert_eq!(store.version("hall"), Ok(StreamVersion::from(5)));
+
+    // The snapshot and the events after it still rebuild the state from before the trim.
+    let snapshot = snapshots.load("hall").unwrap().unwrap();
+    let tail = store.load_from("hall", snapshot.taken_at_version).unwrap();
+    assert_eq!(tail, vec![Event::SwitchedOn, Event::SwitchedOff]);
+    assert_eq!(
+        InMemoryRunner::<Command, Event, State, Bulb>::hydrate(&snapshot.state, &tail).get_state(),
+        &state
+    );
+
+    // Without the barrier, only the limits decide.
+    policy.respect_snapshot_barrier = false;
+    let report = apply_retention(&mut store, &snapshots, &policy).unwrap();
+    assert_eq!(report.removed(), 2);
+    assert_eq!(store.first_version("hall"), Ok(StreamVersion::from(4)));
+    assert_eq!(
+        store.load("porch"),
+        Err(StoreError::Truncated {
+            stream_id: "porch".to_string(),
+            first: StreamVersion::from(1),
+        })
+    );
+    assert_eq!(
+        store.append("porch", StreamVersion::from(2), &[Event::SwitchedOff]),
+        Ok(StreamVersion::from(3))
+    );
+}
+
+#[test]
+fn age_retention_test() {
+    // Each event is the time it was recorded at.
+    let mut store = InMemoryEventStore::new();
+    store
+        .append("readings", StreamVersion::INITIAL, &[10, 20, 30, 25, 40])
+        .unwrap();
+    let policy = RetentionPolicy {
+        max_events: None,
+        max_age: Some(MaxAge {
+            max_age: 15,
+            now: 45,
+            timestamp: |at: &u64| Some(*at),
+        }),
+        respect_snapshot_barrier: false,
+        dry_run: false,
+    };
+
+    // The reading at 25 is too old, but stays behind the one at 30, which is not.
+    let report = apply_retention(&mut store, &InMemorySnapshotStore::<()>::new(), &policy);
+    assert_eq!(report.map(|report| report.removed()), Ok(2));
+    assert_eq!(
+        store.load_from("readings", StreamVersion::from(2)),
+        Ok(vec![30, 25, 40])
+    );
+}
+
+/// The version of the latest snapshot of the stream `stream_id` in `snapshots`.
+fn snapshot_version<S>(snapshots: &impl SnapshotStore<S>, stream_id: &str) -> Option<u64> {
+    let snapshot = snapshots.load(stream_id).unwrap();
+    snapshot.map(|snapshot| u64::from(snapshot.taken_at_version))
+}
+
+#[test]
+fn snapshot_policy_test() {
+    use bulb::{Bulb, Command, Event, State};
+
+    let commands = [
+        Command::Fit { max_uses: 1 },
+        Command::SwitchOn,
+        Command::SwitchOff,
+        Command::SwitchOn,
+    ];
+    let versions_with = |policy: SnapshotPolicy| {
+        let mut runner = EventSourcedRunner::<Command, Event, State, Bulb, _>::builder(
+            "hall",
+            InMemoryEventStore::new(),
+        )
+        .snapshots(InMemorySnapshotStore::new(), policy)
+        .load()
+        .unwrap();
+        commands
+            .iter()
+            .map(|command| {
+                runner.command(command).unwrap();
+                snapshot_version(runner.snapshot_store(), "hall")
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        versions_with(SnapshotPolicy::Never),
+        vec![None, None, None, None]
+    );
+    assert_eq!(
+        versions_with(SnapshotPolicy::EveryNEvents(2)),
+        vec![None, Some(2), Some(2), Some(4)]
+    );
+    // The last switch-on blows the bulb.
+    assert_eq!(
+        versions_with(SnapshotPolicy::OnTerminal),
+        vec![None, None, None, Some(4)]
+    );
+    assert_eq!(
+        versions_with(SnapshotPolicy::Composite(vec![
+            SnapshotPolicy::EveryNEvents(3),
+            SnapshotPolicy::OnTerminal,
+        ])),
+        vec![None, None, Some(3), Some(4)]
+    );
+}
+
+#[test]
+fn snapshot_loading_test() {
+    use bulb::{Bulb, Command, Event, State, Status};
+
+    let mut runner = EventSourcedRunner::<Command, Event, State, Bulb, _>::builder(
+        "hall",
+        InMemoryEventStore::new(),
+    )
+    .snapshots(
+        InMemorySnapshotStore::new(),
+        SnapshotPolicy::EveryNEvents(2),
+    )
+    .load()
+    .unwrap();
+    for command in [
+        Command::Fit { max_uses: 3 },
+        Command::SwitchOn,
+        Command::SwitchOff,
+    ] {
+        runner.command(&command).unwrap();
+    }
+
+    // The events before the snapshot are gone, so only the snapshot can rebuild the state.
+    let (mut store, mut snapshots) = runner.into_stores();
+    store
+        .truncate_before("hall", StreamVersion::from(2))
+        .unwrap();
+    let state = State::Working {
+        status: Status::Off,
+        remaining_uses: 2,
+    };
+    let runner = EventSourcedRunner::<Command, Event, State, Bulb, _>::builder("hall", store)
+        .snapshots(snapshots.clone(), SnapshotPolicy::Never)
+        .load()
+        .unwrap();
+    assert_eq!(*runner.get_state(), state);
+    assert_eq!(runner.version(), StreamVersion::from(3));
+
+    // A snapshot of another schema is ignored, which needs the whole stream.
+    snapshots
+        .save(
+            "hall",
+            &Snapshot {
+                schema_version: 1,
+                taken_at_version: StreamVersion::from(2),
+                state: Ok((true, 2)),
+            },
+        )
+        .unwrap();
+    let load =
+        EventSourcedRunner::<Command, Event, State, Bulb, _>::builder("hall", runner.into_store())
+            .snapshots(snapshots, SnapshotPolicy::Never)
+            .load();
+    assert_eq!(
+        load.map(|runner| *runner.get_state()),
+        Err(StoreError::Truncated {
+            stream_id: "hall".to_string(),
+            first: StreamVersion::from(2),
+        })
+    );
+}
+
+/// A snapshot store whose writes always fail.
+struct BrokenSnapshotStore;
+
+impl<S> SnapshotStore<S> for BrokenSnapshotStore {
+    fn load(&self, _key: &str) -> Result<Option<Snapshot<S>>, StoreError> {
+        Ok(None)
+    }
+
+    fn save(&mut self, _key: &str, _snapshot: &Snapshot<S>) -> Result<(), StoreError> {
+        Err(StoreError::Io("disk full".to_string()))
+    }
+}
+
+#[test]
+fn failed_snapshot_test() {
+    use bulb::{Bulb, Command, Event, State};
+    use std::sync::{Arc, Mutex};
+
+    let diagnostics = Arc::new(Mutex::new(vec![]));
+    let reported = diagnostics.clone();
+    let mut runner = EventSourcedRunner::<Command, Event, State, Bulb, _>::builder(
+        "hall",
+        InMemoryEventStore::new(),
+    )
+    .snapshots(BrokenSnapshotStore, SnapshotPolicy::EveryNEvents(1))
+    .diagnostics(move |diagnostic: &Diagnostic| reported.lock().unwrap().push(diagnostic.clone()))
+    .load()
+    .unwrap();
+
+    // The command succeeds and keeps its events, even though its snapshot was not saved.
+    assert_eq!(
+        runner.command(&Command::Fit { max_uses: 1 }),
+        Ok(vec![Event::Fitted { max_uses: 1 }])
+    );
+    assert_eq!(
+        runner.store().load("hall"),
+        Ok(vec![Event::Fitted { max_uses: 1 }])
+    );
+    assert_eq!(
+        *diagnostics.lock().unwrap(),
+        vec![Diagnostic::SnapshotFailed {
+            stream_id: "hall".to_string(),
+            version: StreamVersion::from(1),
+            error: StoreError::Io("disk full".to_string()),
+        }]
+    );
+}
+
+#[test]
+fn keyed_snapshot_test() {
+    use bulb::{Bulb, Command, Event, State, Status};
+
+    type Runner = KeyedEventSourcedRunner<
+        Command,
+        Event,
+        State,
+        Bulb,
+        InMemoryEventStore<(String, Event)>,
+        InMemoryKeyedSnapshotStore<String, Result<(bool, u64), bool>>,
+    >;
+
+    let mut runner = Runner::load(
+        "bulbs",
+        InMemoryEventStore::new(),
+        InMemoryKeyedSnapshotStore::new(),
+    )
+    .unwrap();
+    for i in 0..1000 {
+        runner
+            .command(&(format!("bulb-{i}"), Command::Fit { max_uses: i }))
+            .unwrap();
+    }
+    assert_eq!(runner.snapshot(), Ok(1000));
+
+    // Only the bulb that changed is written again.
+    runner
+        .command(&("bulb-7".to_string(), Command::SwitchOn))
+        .unwrap();
+    assert!(runner.is_dirty("bulb-7"));
+    assert_eq!(runner.snapshot(), Ok(1));
+    assert_eq!(runner.snapshot_store().writes(), 1001);
+
+    let states = runner.get_state().clone();
+    let (store, snapshots) = runner.into_stores();
+    let mut runner = Runner::load("bulbs", store, snapshots).unwrap();
+    assert_eq!(*runner.get_state(), states);
+    assert!(!runner.is_dirty("bulb-7"));
+    assert_eq!(
+        runner.get_state()["bulb-7"],
+        State::Working {
+            status: Status::On,
+            remaining_uses: 6
+        }
+    );
+
+    // A blown bulb is pruned, and its tombstone keeps it from coming back when loaded.
+    runner
+        .command(&("bulb-0".to_string(), Command::SwitchOn))
+        .unwrap();
+    assert_eq!(runner.get_state()["bulb-0"], State::Blown);
+    assert_eq!(runner.prune_terminal(), vec!["bulb-0".to_string()]);
+    assert_eq!(runner.snapshot(), Ok(1));
+    let (store, snapshots) = runner.into_stores();
+    let mut runner = Runner::load("bulbs", store, snapshots).unwrap();
+    assert_eq!(runner.get_state().len(), 999);
+    assert!(!runner.get_state().contains_key("bulb-0"));
+
+    // The key can still be used again, starting over.
+    runner
+        .command(&("bulb-0".to_string(), Command::Fit { max_uses: 2 }))
+        .unwrap();
+    assert_eq!(runner.snapshot(), Ok(1));
+    let (store, snapshots) = runner.into_stores();
+    let runner = Runner::load("bulbs", store, snapshots).unwrap();
+    assert_eq!(
+        runner.get_state()["bulb-0"],
+        State::Working {
+            status: Status::Off,
+            remaining_uses: 2
+        }
+    );
+}
+
+#[test]
+fn verify_snapshot_test() {
+    use bulb::{Bulb, Command, Event, State};
+
+    let events = [
+        Event::Fitted { max_uses: 2 },
+        Event::SwitchedOn,
+        Event::SwitchedOff,
+        Event::SwitchedOn,
+        Event::SwitchedOff,
+        Event::Blew,
+    ];
+    let snapshot_at = |taken_at: u64, state| Snapshot {
+        schema_version: 2,
+        taken_at_version: StreamVersion::from(taken_at),
+        state,
+    };
+    let mut store = InMemoryEventStore::new();
+    let mut snapshots = InMemorySnapshotStore::new();
+    for (stream_id, snapshot) in [
+        ("hall", Some(snapshot_at(2, Ok((true, 1))))),
+        // A snapshot with too many uses agrees with the replay again once the bulb has blown,
+        // but the report points at the snapshot itself.
+        ("porch", Some(snapshot_at(2, Ok((true, 4))))),
+        ("attic", Some(snapshot_at(6, Err(true)))),
+        ("garage", Some(snapshot_at(7, Err(true)))),
+        ("cellar", None),
+    ] {
+        store
+            .append(stream_id, StreamVersion::INITIAL, &events)
+            .unwrap();
+        if let Some(snapshot) = snapshot {
+            snapshots.save(stream_id, &snapshot).unwrap();
+        }
+    }
+
+    assert_eq!(
+        verify_snapshot::<Command, Event, State, Bulb, _, _>(&store, &snapshots, "hall"),
+        Ok(VerificationReport::Consistent)
+    );
+    assert_eq!(
+        verify_snapshot_fingerprints::<Command, Event, State, Bulb, _, _>(
+            &store, &snapshots, "hall"
+        )
+        .map(|report| report.is_consistent()),
+        Ok(true)
+    );
+    assert_eq!(
+        verify_snapshot_fingerprints::<Command, Event, State, Bulb, _, _>(
+            &store, &snapshots, "porch"
+        ),
+        Ok(VerificationReport::Diverged { applied: 2 })
+    );
+
+    // Every stream of the store is checked in one go.
+    let report = |stream_id: &str, report| (stream_id.to_string(), report);
+    assert_eq!(
+        verify_snapshots::<Command, Event, State, Bulb, _, _>(&store, &snapshots),
+        Ok(vec![
+            report("attic", VerificationReport::Consistent),
+            report("cellar", VerificationReport::NoSnapshot),
+            report("garage", VerificationReport::AheadOfStream { len: 6 }),
+            report("hall", VerificationReport::Consistent),
+            report("porch", VerificationReport::Diverged { applied: 2 }),
+        ])
+    );
+}
+
+mod bulb_v1 {
+    use super::{Decider, EventUpcaster};
+
+    /// The events of the first model of a bulb, before it was refactored into the current one.
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum Event {
+        Installed {
+            lifetime: u64,
+        },
+        TurnedOn,
+        TurnedOff,
+        /// Turned on and straight back off.
+        Flickered,
+        Inspected,
+        Stolen,
+        Burnt,
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum State {
+        Empty,
+        Installed { lit: bool, lifetime: u64 },
+        Burnt,
+    }
+
+    pub struct BulbV1;
+
+    impl Decider<(), Event, State, State> for BulbV1 {
+        fn decide(_: &(), _: &State) -> Vec<Event> {
+            vec![]
+        }
+
+        fn evolve(state: &State, event: &Event) -> State {
+            match (*state, *event) {
+                // Unlike the current bulb, an installed bulb could be replaced.
+                (State::Empty | State::Installed { .. }, Event::Installed { lifetime }) => {
+                    State::Installed {
+                        lit: false,
+                        lifetime,
+                    }
+                }
+                (State::Installed { lifetime, .. }, Event::TurnedOn) => State::Installed {
+                    lit: true,
+                    lifetime: lifetime - 1,
+                },
+                (State::Installed { lifetime, .. }, Event::TurnedOff) => State::Installed {
+                    lit: false,
+                    lifetime,
+                },
+                (State::Installed { lifetime, .. }, Event::Flickered) => State::Installed {
+                    lit: false,
+                    lifetime: lifetime - 1,
+                },
+                (State::Installed { .. }, Event::Stolen) => State::Empty,
+                (State::Installed { .. }, Event::Burnt) => State::Burnt,
+                (state, _) => state,
+            }
+        }
+
+        fn initial_state() -> State {
+            State::Empty
+        }
+
+        fn is_terminal(state: &State) -> bool {
+            *state == State::Burnt
+        }
+    }
+
+    pub struct V1ToV2;
+
+    impl EventUpcaster<Event, Option<Vec<super::bulb::Event>>> for V1ToV2 {
+        fn upcast(event: &Event) -> Option<Vec<super::bulb::Event>> {
+            use super::bulb::Event as V2;
+            Some(match *event {
+                Event::Installed { lifetime } => vec![V2::Fitted { max_uses: lifetime }],
+                Event::TurnedOn => vec![V2::SwitchedOn],
+                Event::TurnedOff => vec![V2::SwitchedOff],
+                Event::Flickered => vec![V2::SwitchedOn, V2::SwitchedOff],
+                Event::Inspected => vec![],
+                Event::Burnt => vec![V2::Blew],
+                // A stolen bulb cannot be unfitted in the current model.
+                Event::Stolen => return None,
+            })
+        }
+    }
+
+    pub fn equivalent(old: &State, new: &super::bulb::State) -> bool {
+        use super::bulb::{State as V2, Status};
+        match (*old, *new) {
+            (State::Empty, V2::NotFitted) | (State::Burnt, V2::Blown) => true,
+            (
+                State::Installed { lit, lifetime },
+                V2::Working {
+                    status,
+                    remaining_uses,
+                },
+            ) => lit == (status == Status::On) && lifetime == remaining_uses,
+            _ => false,
+        }
+    }
+}
+
+#[test]
+fn migrate_streams_test() {
+    use bulb::{Bulb, Command, Event, State};
+    use bulb_v1::{BulbV1, V1ToV2};
+
+    let mut source = InMemoryEventStore::new();
+    for (stream_id, events) in [
+        (
+            "hall",
+            vec![
+                bulb_v1::Event::Installed { lifetime: 2 },
+                bulb_v1::Event::TurnedOn,
+                bulb_v1::Event::Inspected,
+                bulb_v1::Event::TurnedOff,
+                bulb_v1::Event::Flickered,
+                bulb_v1::Event::Burnt,
+            ],
+        ),
+        (
+            "porch",
+            vec![
+                bulb_v1::Event::Installed { lifetime: 3 },
+                bulb_v1::Event::Stolen,
+            ],
+        ),
+        (
+            "attic",
+            vec![
+                bulb_v1::Event::Installed { lifetime: 3 },
+                bulb_v1::Event::TurnedOn,
+                bulb_v1::Event::Installed { lifetime: 5 },
+            ],
+        ),
+    ] {
+        source
+            .append(stream_id, StreamVersion::INITIAL, &events)
+            .unwrap();
+    }
+
+    let mut options = MigrationOptions {
+        equivalent: bulb_v1::equivalent,
+        dry_run: true,
+    };
+    let mut target = InMemoryEventStore::new();
+    let migrate = |target: &mut InMemoryEventStore<Event>, options: &MigrationOptions<_, _>| {
+        let mut progress = vec![];
+        let reports = migrate_streams::<(), _, _, BulbV1, Command, _, _, Bulb, V1ToV2, _, _>(
+            &source,
+            target,
+            options,
+            |p| progress.push((p.report.stream_id.clone(), p.migrated, p.total)),
+        )
+        .unwrap();
+        (reports, progress)
+    };
+
+    let (reports, progress) = migrate(&mut target, &options);
+    assert_eq!(
+        progress,
+        vec![
+            ("attic".to_string(), 1, 3),
+            ("hall".to_string(), 2, 3),
+            ("porch".to_string(), 3, 3),
+        ]
+    );
+    let report = |stream_id: &str, read, upcast, unmappable: &[u64], divergence| MigrationReport {
+        stream_id: stream_id.to_string(),
+        read,
+        upcast,
+        unmappable: unmappable
+            .iter()
+            .copied()
+            .map(StreamVersion::from)
+            .collect(),
+        divergence,
+        written: false,
+    };
+    let expected = vec![
+        // The first model let an installed bulb be replaced, which the current one ignores.
+        report("attic", 3, 3, &[], Some(3)),
+        report("hall", 6, 6, &[], None),
+        report("porch", 2, 1, &[2], Some(2)),
+    ];
+    assert_eq!(reports, expected);
+    assert!(reports[1].is_successful());
+    assert_eq!(target.stream_ids(), Ok(vec![]));
+
+    // Only the stream that migrated successfully is written.
+    options.dry_run = false;
+    let (reports, _) = migrate(&mut target, &options);
+    assert!(reports[1].written && !reports[0].written && !reports[2].written);
+    assert_eq!(target.stream_ids(), Ok(vec!["hall".to_string()]));
+    let migrated = target.load("hall").unwrap();
+    assert_eq!(migrated.len(), 6);
+    assert_eq!(Bulb::fold(&migrated), State::Blown);
+
+    // A stream already in the target store is never overwritten.
+    assert!(matches!(
+        migrate_stream::<(), _, _, BulbV1, Command, _, _, Bulb, V1ToV2, _, _>(
+            &source,
+            &mut target,
+            "hall",
+            &options
+        ),
+        Err(StoreError::Conflict { .. })
+    ));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn archive_stream_test() {
+    use bulb::{Bulb, Command, Event, State};
+    use deciders_rs::stores::{archive_stream, restore_from_archive, ArchiveReceipt};
+
+    let events = [
+        Event::Fitted { max_uses: 1 },
+        Event::SwitchedOn,
+        Event::SwitchedOff,
+        Event::Blew,
+    ];
+    let mut store = InMemoryEventStore::new();
+    store
+        .append("hall", StreamVersion::INITIAL, &events)
+        .unwrap();
+    store
+        .append("porch", StreamVersion::INITIAL, &events[..1])
+        .unwrap();
+    let mut snapshots = InMemorySnapshotStore::new();
+    let snapshot = Snapshot {
+        schema_version: 2,
+        taken_at_version: StreamVersion::from(4),
+        state: Err(true),
+    };
+    snapshots.save("hall", &snapshot).unwrap();
+
+    let mut archive = vec![];
+    let receipt = archive_stream(&mut store, &snapshots, &mut archive, "hall").unwrap();
+    assert_eq!(
+        receipt,
+        ArchiveReceipt {
+            stream_id: "hall".to_string(),
+            version: StreamVersion::from(4),
+            snapshot_version: Some(StreamVersion::from(4)),
+        }
+    );
+
+    // The archived stream is gone from the live store until it is restored.
+    let archived = StoreError::Archived(receipt.clone());
+    assert_eq!(store.load("hall"), Err(archived.clone()));
+    assert_eq!(store.version("hall"), Err(archived.clone()));
+    assert_eq!(
+        store.append("hall", StreamVersion::from(4), &[Event::SwitchedOn]),
+        Err(archived.clone())
+    );
+    assert_eq!(
+        EventSourcedRunner::<Command, Event, State, Bulb, _>::load("hall", store.clone())
+            .map(|_| ())
+            .unwrap_err(),
+        archived
+    );
+    assert_eq!(store.stream_ids(), Ok(vec!["porch".to_string()]));
+
+    assert!(matches!(
+        restore_from_archive::<Event, Result<(bool, u64), bool>, _, _>(
+            &mut store,
+            &mut snapshots,
+            &archive[..archive.len() / 2]
+        ),
+        Err(StoreError::Codec(_))
+    ));
+    assert_eq!(
+        restore_from_archive(&mut store, &mut snapshots, &archive[..]),
+        Ok(receipt.clone())
+    );
+    assert_eq!(store.load("hall"), Ok(events.to_vec()));
+    let runner = EventSourcedRunner::<Command, Event, State, Bulb, _>::load("hall", store).unwrap();
+    assert_eq!(*runner.get_state(), State::Blown);
+
+    // An archive can be restored into any other store too.
+    let mut cold = InMemoryEventStore::new();
+    let mut cold_snapshots = InMemorySnapshotStore::new();
+    restore_from_archive(&mut cold, &mut cold_snapshots, &archive[..]).unwrap();
+    assert_eq!(cold.load("hall"), Ok(events.to_vec()));
+    assert_eq!(cold_snapshots.load("hall"), Ok(Some(snapshot)));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn archive_terminal_keys_test() {
+    use bulb::{Bulb, Command, Event, State};
+    use deciders_rs::stores::restore_from_archive;
+
+    let mut runner = KeyedEventSourcedRunner::<Command, Event, State, Bulb, _, _>::load(
+        "bulbs",
+        InMemoryEventStore::new(),
+        InMemoryKeyedSnapshotStore::new(),
+    )
+    .unwrap();
+    for (key, command) in [
+        ("kitchen", Command::Fit { max_uses: 0 }),
+        ("porch", Command::Fit { max_uses: 2 }),
+        ("kitchen", Command::SwitchOn),
+        ("porch", Command::SwitchOn),
+    ] {
+        runner.command(&(key.to_string(), command)).unwrap();
+    }
+
+    let cold_storage = std::env::temp_dir().join("deciders_archive_terminal_keys_test");
+    std::fs::create_dir_all(&cold_storage).unwrap();
+    let receipts = runner
+        .archive_terminal_keys(|key| std::fs::File::create(cold_storage.join(key)).unwrap())
+        .unwrap();
+    assert_eq!(receipts.len(), 1);
+    assert_eq!(receipts[0].stream_id, "bulbs/kitchen");
+    assert_eq!(receipts[0].version, StreamVersion::from(2));
+    assert!(!runner.get_state().contains_key("kitchen"));
+    assert!(runner.get_state().contains_key("porch"));
+    assert_eq!(runner.snapshot(), Ok(2));
+
+    // The archived key comes back as a stream of its own.
+    let mut audit = InMemoryEventStore::new();
+    let mut audit_snapshots = InMemorySnapshotStore::<Result<(bool, u64), bool>>::new();
+    restore_from_archive(
+        &mut audit,
+        &mut audit_snapshots,
+        std::fs::File::open(cold_storage.join("kitchen")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        audit.load("bulbs/kitchen"),
+        Ok(vec![Event::Fitted { max_uses: 0 }, Event::Blew])
+    );
+    assert_eq!(
+        audit_snapshots
+            .load("bulbs/kitchen")
+            .map(|snapshot| snapshot.map(|snapshot| snapshot.state)),
+        Ok(Some(Err(true)))
+    );
+
+    // Once the tombstone is saved, the archived key is not loaded back.
+    let (store, snapshots) = runner.into_stores();
+    let runner = KeyedEventSourcedRunner::<Command, Event, State, Bulb, _, _>::load(
+        "bulbs", store, snapshots,
+    )
+    .unwrap();
+    assert!(!runner.get_state().contains_key("kitchen"));
+}
+
+#[test]
+fn chaos_store_scripted_append_test() {
+    use bulb::{Bulb, Command, Event, State, Status};
+
+    let store = ChaosStore::new(InMemoryEventStore::new()).fail_nth(ChaosOperation::Append, 3);
+    let mut runner =
+        EventSourcedRunner::<Command, Event, State, Bulb, _>::load("hall", store).unwrap();
+    runner.command(&Command::Fit { max_uses: 3 }).unwrap();
+    runner.command(&Command::SwitchOn).unwrap();
+    let before = *runner.get_state();
+
+    // The failed append surfaces as an error and leaves the state and version as they were.
+    assert!(matches!(
+        runner.command(&Command::SwitchOff),
+        Err(StoreError::Io(_))
+    ));
+    assert_eq!(*runner.get_state(), before);
+    assert_eq!(runner.version(), StreamVersion::from(2));
+
+    // Retrying the command appends it against the same version.
+    assert_eq!(
+        runner.command(&Command::SwitchOff),
+        Ok(vec![Event::SwitchedOff])
+    );
+    let store = runner.into_store();
+    let ChaosCounters {
+        appends,
+        append_faults,
+        read_faults,
+        ..
+    } = store.counters();
+    assert_eq!((appends, append_faults, read_faults), (4, 1, 0));
+    let runner =
+        EventSourcedRunner::<Command, Event, State, Bulb, _>::load("hall", store.into_inner())
+            .unwrap();
+    assert_eq!(
+        *runner.get_state(),
+        State::Working {
+            status: Status::Off,
+            remaining_uses: 2
+        }
+    );
+}
+
+#[test]
+fn chaos_store_seeded_test() {
+    let faults = |seed| {
+        let store = ChaosStore::new(InMemoryEventStore::<u8>::new())
+            .with_seed(seed)
+            .with_failure_rate(ChaosOperation::Read, 0.5);
+        let faults: Vec<bool> = (0..64).map(|_| store.load("hall").is_err()).collect();
+        assert_eq!(
+            store.counters().read_faults,
+            faults.iter().filter(|fault| **fault).count() as u64
+        );
+        faults
+    };
+
+    // The same seed fails the same reads, and another seed fails others.
+    let first = faults(7);
+    assert_eq!(faults(7), first);
+    assert_ne!(faults(8), first);
+    assert!(first.contains(&true) && first.contains(&false));
+
+    let mut store = ChaosStore::new(InMemoryEventStore::new())
+        .with_failure_rate(ChaosOperation::Append, 1.0)
+        .with_latency(Duration::from_millis(2));
+    let start = std::time::Instant::now();
+    assert!(store.append("hall", StreamVersion::INITIAL, &[1]).is_err());
+    assert!(start.elapsed() >= Duration::from_millis(2));
+    assert_eq!(store.inner().version("hall"), Ok(StreamVersion::INITIAL));
+}
+
+#[test]
+fn chaos_store_torn_append_test() {
+    let appends = [
+        StreamAppend {
+            stream_id: "hall",
+            expected: StreamVersion::INITIAL,
+            events: &[1, 2][..],
+        },
+        StreamAppend {
+            stream_id: "porch",
+            expected: StreamVersion::INITIAL,
+            events: &[3][..],
+        },
+    ];
+
+    let mut store = ChaosStore::new(InMemoryEventStore::new()).fail_nth(ChaosOperation::Append, 1);
+    assert!(matches!(
+        store.append_multi(&appends),
+        Err(StoreError::Io(_))
+    ));
+    assert_eq!(store.inner().stream_ids(), Ok(vec![]));
+
+    // A torn append lands every stream but the last before failing.
+    let mut store = ChaosStore::new(InMemoryEventStore::new())
+        .fail_nth(ChaosOperation::Append, 1)
+        .with_torn_appends();
+    assert!(matches!(
+        store.append_multi(&appends),
+        Err(StoreError::Io(_))
+    ));
+    assert_eq!(store.load("hall"), Ok(vec![1, 2]));
+    assert_eq!(store.load("porch"), Ok(vec![]));
+    assert_eq!(store.counters().torn_appends, 1);
+    assert_eq!(
+        store.append_multi(&appends),
+        Err(StoreError::Conflict {
+            stream_id: "hall".to_string(),
+            expected: StreamVersion::INITIAL,
+            stored: StreamVersion::from(2),
+        })
+    );
+}
+
+#[cfg(all(feature = "sqlite", feature = "serde"))]
+mod sqlite_event_store {
+    use super::{bulb, cat, interleaved, CatAndBulbEvent};
+    use deciders_rs::deciders::ComposedDeciders;
+    use deciders_rs::stores::{
+        ApplyResult, ComposedEventSourcedRunner, EventSourcedRunner, EventStore, InboxStore,
+        Interleaved, SqliteEventStore, StoreError,
+    };
+    use deciders_rs::utilities::{Either, StreamVersion};
+    use rusqlite::Connection;
+
+    type CatAndBulb = ComposedDeciders<
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        bulb::Bulb,
+        bulb::Command,
+        bulb::Event,
+        bulb::State,
+    >;
+    type Runner = ComposedEventSourcedRunner<
+        Either<cat::Command, bulb::Command>,
+        cat::Event,
+        bulb::Event,
+        (cat::State, bulb::State),
+        CatAndBulb,
+        SqliteEventStore<Interleaved<CatAndBulbEvent>>,
+    >;
+
+    #[test]
+    fn sqlite_composed_event_sourced_runner_test() {
+        use Either::*;
+
+        // Both stores are connections to the same in-memory database.
+        let open = || {
+            let connection =
+                Connection::open("file:composed_events?mode=memory&cache=shared").unwrap();
+            SqliteEventStore::new(connection).unwrap()
+        };
+        let mut first = Runner::load("cat", "bulb", open()).unwrap();
+        let mut second = Runner::load("cat", "bulb", open()).unwrap();
+        assert_eq!(
+            first.command(&Right(bulb::Command::Fit { max_uses: 5 })),
+            Ok(vec![Right(bulb::Event::Fitted { max_uses: 5 })])
+        );
+
+        // The second runner decided against an empty bulb stream, so its append conflicts on
+        // the bulb stream, and nothing is written to the cat stream either.
+        assert_eq!(
+            second.command(&Left(cat::Command::GetToSleep)),
+            Err(StoreError::Conflict {
+                stream_id: "bulb".to_string(),
+                expected: StreamVersion::from(0),
+                stored: StreamVersion::from(1),
+            })
+        );
+        assert_eq!(second.get_state().0, cat::State::Awake);
+        assert_eq!(second.store().load("cat"), Ok(vec![]));
+
+        let mut second = Runner::load("cat", "bulb", second.into_store()).unwrap();
+        second.command(&Left(cat::Command::GetToSleep)).unwrap();
+        second.command(&Right(bulb::Command::SwitchOn)).unwrap();
+        assert_eq!(
+            second.store().load("bulb"),
+            Ok(vec![
+                interleaved(0, Right(bulb::Event::Fitted { max_uses: 5 })),
+                interleaved(2, Right(bulb::Event::SwitchedOn)),
+            ])
+        );
+        let reloaded = Runner::load("cat", "bulb", open()).unwrap();
+        assert_eq!(reloaded.get_state(), second.get_state());
+        assert_eq!(
+            reloaded.versions(),
+            (StreamVersion::from(1), StreamVersion::from(2))
+        );
+    }
+
+    #[test]
+    fn sqlite_inbox_test() {
+        use bulb::{Bulb, Command, Event, State, Status};
+
+        let store = SqliteEventStore::<Event>::new(Connection::open_in_memory().unwrap())
+            .unwrap()
+            .with_inbox_capacity(1);
+        let mut runner =
+            EventSourcedRunner::<Command, Event, State, Bulb, _>::load("hall", store).unwrap();
+        runner.command(&Command::Fit { max_uses: 3 }).unwrap();
+        assert_eq!(
+            runner.apply_external("mqtt", "1", &Event::SwitchedOn),
+            Ok(ApplyResult::Applied(StreamVersion::from(2)))
+        );
+        assert_eq!(
+            runner.apply_external("mqtt", "1", &Event::SwitchedOn),
+            Ok(ApplyResult::Duplicate)
+        );
+        assert_eq!(
+            *runner.get_state(),
+            State::Working {
+                status: Status::On,
+                remaining_uses: 2
+            }
+        );
+
+        // With room for a single event, receiving another forgets the first.
+        runner
+            .apply_external("mqtt", "2", &Event::SwitchedOff)
+            .unwrap();
+        assert_eq!(runner.store().has_received("hall", "mqtt", "1"), Ok(false));
+        assert_eq!(runner.store().has_received("hall", "mqtt", "2"), Ok(true));
+        assert_eq!(runner.version(), StreamVersion::from(3));
+    }
+
+    #[test]
+    fn sqlite_truncate_test() {
+        use bulb::Event;
+
+        let mut store =
+            SqliteEventStore::<Event>::new(Connection::open_in_memory().unwrap()).unwrap();
+        let events = [
+            Event::Fitted { max_uses: 2 },
+            Event::SwitchedOn,
+            Event::SwitchedOff,
+        ];
+        store
+            .append("hall", StreamVersion::INITIAL, &events)
+            .unwrap();
+        assert_eq!(store.truncate_before("hall", StreamVersion::from(2)), Ok(2));
+        assert_eq!(store.first_version("hall"), Ok(StreamVersion::from(2)));
+        assert_eq!(
+            store.load("hall"),
+            Err(StoreError::Truncated {
+                stream_id: "hall".to_string(),
+                first: StreamVersion::from(2),
+            })
+        );
+
+        // Truncating every event leaves the stream at its version, ready for the next event.
+        assert_eq!(store.truncate_before("hall", StreamVersion::from(9)), Ok(1));
+        assert_eq!(store.first_version("hall"), Ok(StreamVersion::from(3)));
+        assert_eq!(
+            store.append("hall", StreamVersion::from(3), &[Event::SwitchedOn]),
+            Ok(StreamVersion::from(4))
+        );
+        assert_eq!(
+            store.load_from("hall", StreamVersion::from(3)),
+            Ok(vec![Event::SwitchedOn])
+        );
+    }
+
+    #[test]
+    fn sqlite_migrate_stream_test() {
+        use super::bulb_v1::{self, BulbV1, V1ToV2};
+        use bulb::{Bulb, Command, Event};
+        use deciders_rs::stores::{migrate_stream, MigrationOptions};
+
+        // The old events are read with the codec they were written with.
+        let mut source =
+            SqliteEventStore::<bulb_v1::Event>::new(Connection::open_in_memory().unwrap()).unwrap();
+        source
+            .append(
+                "hall",
+                StreamVersion::INITIAL,
+                &[
+                    bulb_v1::Event::Installed { lifetime: 2 },
+                    bulb_v1::Event::Flickered,
+                ],
+            )
+            .unwrap();
+        let mut target =
+            SqliteEventStore::<Event>::new(Connection::open_in_memory().unwrap()).unwrap();
+        let report = migrate_stream::<(), _, _, BulbV1, Command, _, _, Bulb, V1ToV2, _, _>(
+            &source,
+            &mut target,
+            "hall",
+            &MigrationOptions {
+                equivalent: bulb_v1::equivalent,
+                dry_run: false,
+            },
+        )
+        .unwrap();
+        assert!(report.is_successful() && report.written);
+        assert_eq!(
+            target.load("hall"),
+            Ok(vec![
+                Event::Fitted { max_uses: 2 },
+                Event::SwitchedOn,
+                Event::SwitchedOff
+            ])
+        );
+    }
+
+    #[test]
+    fn sqlite_archive_test() {
+        use bulb::Event;
+        use deciders_rs::stores::{archive_stream, restore_from_archive, InMemorySnapshotStore};
+
+        let path = "file:sqlite_archive?mode=memory&cache=shared";
+        let mut store = SqliteEventStore::<Event>::new(Connection::open(path).unwrap()).unwrap();
+        let events = [Event::Fitted { max_uses: 0 }, Event::Blew];
+        store
+            .append("hall", StreamVersion::INITIAL, &events)
+            .unwrap();
+        let mut snapshots = InMemorySnapshotStore::<Result<(bool, u64), bool>>::new();
+
+        let mut archive = vec![];
+        let receipt = archive_stream(&mut store, &snapshots, &mut archive, "hall").unwrap();
+        assert_eq!(receipt.snapshot_version, None);
+
+        // The receipt is kept in the database, so every connection sees the stream as archived.
+        let other = SqliteEventStore::<Event>::new(Connection::open(path).unwrap()).unwrap();
+        assert_eq!(
+            other.load("hall"),
+            Err(StoreError::Archived(receipt.clone()))
+        );
+        assert_eq!(other.stream_ids(), Ok(vec![]));
+
+        assert_eq!(
+            restore_from_archive(&mut store, &mut snapshots, &archive[..]),
+            Ok(receipt)
+        );
+        assert_eq!(other.load("hall"), Ok(events.to_vec()));
+        assert_eq!(other.version("hall"), Ok(StreamVersion::from(2)));
+    }
+}
+
+#[cfg(all(feature = "zstd", feature = "serde"))]
+mod compression {
+    use super::bulb;
+    use deciders_rs::deciders::Snapshot;
+    use deciders_rs::stores::{CompressingCodec, EventCodec, JsonCodec, StoreError};
+    use deciders_rs::utilities::StreamVersion;
+    use std::collections::HashMap;
+
+    #[test]
+    fn compressing_codec_test() {
+        let codec = CompressingCodec::with_level(JsonCodec, 19);
+        let event = bulb::Event::Fitted { max_uses: 3 };
+        let bytes = codec.encode(&event).unwrap();
+        assert_eq!(codec.decode(&bytes), Ok(event));
+
+        // Payloads written before compression was turned on are still read.
+        let legacy = JsonCodec.encode(&event).unwrap();
+        assert_eq!(codec.decode(&legacy), Ok(event));
+
+        let mut unknown = bytes.clone();
+        unknown[3] = 9;
+        assert_eq!(
+            EventCodec::<bulb::Event>::decode(&codec, &unknown),
+            Err(StoreError::Codec("unknown compression 9".to_string()))
+        );
+    }
+
+    #[test]
+    fn compressed_snapshot_size_test() {
+        // The snapshot of a collection of 10,000 bulbs, as a `ManyDecider` of bulbs takes it.
+        let bulbs: HashMap<String, Result<(bool, u64), bool>> = (0..10_000)
+            .map(|i| (format!("bulb-{i}"), Ok((i % 3 == 0, i % 50))))
+            .collect();
+        let snapshot = Snapshot {
+            schema_version: 2,
+            taken_at_version: StreamVersion::from(10_000),
+            state: bulbs,
+        };
+        let json = JsonCodec.encode(&snapshot).unwrap();
+        let codec = CompressingCodec::new(JsonCodec);
+        let compressed = codec.encode(&snapshot).unwrap();
+        assert!(
+            compressed.len() * 4 < json.len(),
+            "{} bytes compressed to {}",
+            json.len(),
+            compressed.len()
+        );
+        assert_eq!(codec.decode(&compressed), Ok(snapshot));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_compressed_stores_test() {
+        use bulb::Event;
+        use deciders_rs::stores::{
+            EventStore, SnapshotStore, SqliteEventStore, SqliteSnapshotStore,
+        };
+        use rusqlite::Connection;
+
+        type Compressed = CompressingCodec<JsonCodec>;
+
+        let path = "file:compressed_stores?mode=memory&cache=shared";
+        let mut legacy = SqliteEventStore::<Event>::new(Connection::open(path).unwrap()).unwrap();
+        legacy
+            .append(
+                "hall",
+                StreamVersion::INITIAL,
+                &[Event::Fitted { max_uses: 2 }],
+            )
+            .unwrap();
+
+        // The stores take the compressing codec like any other.
+        let mut events =
+            SqliteEventStore::<Event, Compressed>::new(Connection::open(path).unwrap()).unwrap();
+        events
+            .append("hall", StreamVersion::from(1), &[Event::SwitchedOn])
+            .unwrap();
+        assert_eq!(
+            events.load("hall"),
+            Ok(vec![Event::Fitted { max_uses: 2 }, Event::SwitchedOn])
+        );
+
+        let mut snapshots = SqliteSnapshotStore::<Result<(bool, u64), bool>, Compressed>::new(
+            Connection::open(path).unwrap(),
+        )
+        .unwrap();
+        let snapshot = Snapshot {
+            schema_version: 2,
+            taken_at_version: StreamVersion::from(2),
+            state: Ok((true, 1)),
+        };
+        snapshots.save("hall", &snapshot).unwrap();
+        assert_eq!(snapshots.load("hall"), Ok(Some(snapshot)));
+        assert_eq!(snapshots.load("porch"), Ok(None));
+    }
+}
+
+#[cfg(feature = "axum")]
+mod web {
+    use super::bulb::{Bulb, Command, Event, State, Status};
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request, StatusCode};
+    use axum::Router;
+    use deciders_rs::stores::{
+        EventSourcedRunner, InMemoryEventStore, InMemoryKeyedSnapshotStore, KeyedEventSourcedRunner,
+    };
+    use deciders_rs::web::{command_router, keyed_command_router, Problem};
+    use futures::executor::block_on;
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    /// Sends a request to `router`, returning the status, the content type and the JSON body of
+    /// the response.
+    fn send(router: &Router, request: Request<Body>) -> (StatusCode, String, Value) {
+        block_on(async {
+            let response = router.clone().oneshot(request).await.unwrap();
+            let status = response.status();
+            let content_type = response.headers()[header::CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .to_string();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, content_type, serde_json::from_slice(&body).unwrap())
+        })
+    }
+
+    fn post(uri: &str, body: impl Into<Body>) -> Request<Body> {
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.into())
+            .unwrap()
+    }
+
+    fn problem(body: Value) -> Problem {
+        serde_json::from_value(body).unwrap()
+    }
+
+    fn router() -> Router {
+        let runner = EventSourcedRunner::<Command, Event, State, Bulb, _>::load(
+            "hall",
+            InMemoryEventStore::new(),
+        )
+        .unwrap();
+        command_router(Arc::new(Mutex::new(runner)))
+    }
+
+    #[test]
+    fn command_router_test() {
+        let router = router();
+        let fit = json!({ "command": { "Fit": { "max_uses": 1 } }, "expected_version": 0 });
+        let (status, content_type, body) = send(&router, post("/commands", fit.to_string()));
+        assert_eq!(
+            (status, content_type.as_str()),
+            (StatusCode::OK, "application/json")
+        );
+        assert_eq!(
+            body,
+            json!([{ "version": 1, "event": { "Fitted": { "max_uses": 1 } } }])
+        );
+
+        // The stream has moved on since the client read it.
+        let (status, content_type, body) = send(&router, post("/commands", fit.to_string()));
+        assert_eq!(
+            (status, content_type.as_str()),
+            (StatusCode::CONFLICT, "application/problem+json")
+        );
+        assert_eq!(problem(body).kind, "version-conflict");
+
+        let (status, _, body) = send(
+            &router,
+            post("/commands", json!({ "command": "SwitchOn" }).to_string()),
+        );
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!([{ "version": 2, "event": "SwitchedOn" }]));
+
+        let (status, _, body) = send(&router, Request::get("/state").body(Body::empty()).unwrap());
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_value::<State>(body).unwrap(),
+            State::Working {
+                status: Status::On,
+                remaining_uses: 0
+            }
+        );
+
+        // The bulb blows once it has no uses left, after which it takes no more commands.
+        for command in ["SwitchOff", "SwitchOn"] {
+            let body = json!({ "command": command }).to_string();
+            assert_eq!(send(&router, post("/commands", body)).0, StatusCode::OK);
+        }
+        let (status, _, body) = send(
+            &router,
+            post("/commands", json!({ "command": "SwitchOn" }).to_string()),
+        );
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(problem(body).kind, "terminal");
+    }
+
+    #[test]
+    fn command_router_rejection_test() {
+        let router = router();
+        let kind = |request| {
+            let (status, content_type, body) = send(&router, request);
+            assert_eq!(content_type, "application/problem+json");
+            let problem = problem(body);
+            assert_eq!(problem.status, status.as_u16());
+            (status, problem.kind)
+        };
+        let rejected = |status: StatusCode, kind: &str| (status, kind.to_string());
+
+        assert_eq!(
+            kind(post("/commands", "{ \"command\": ")),
+            rejected(StatusCode::BAD_REQUEST, "malformed-json")
+        );
+        assert_eq!(
+            kind(post(
+                "/commands",
+                json!({ "command": "Unscrew" }).to_string()
+            )),
+            rejected(StatusCode::UNPROCESSABLE_ENTITY, "invalid-command")
+        );
+        assert_eq!(
+            kind(
+                Request::post("/commands")
+                    .header(header::CONTENT_TYPE, "text/plain")
+                    .body(Body::from(json!({ "command": "SwitchOn" }).to_string()))
+                    .unwrap()
+            ),
+            rejected(StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported-media-type")
+        );
+        assert_eq!(
+            kind(
+                Request::get("/state")
+                    .header(header::ACCEPT, "text/html")
+                    .body(Body::empty())
+                    .unwrap()
+            ),
+            rejected(StatusCode::NOT_ACCEPTABLE, "not-acceptable")
+        );
+
+        let accepted = Request::get("/state")
+            .header(header::ACCEPT, "text/html, application/*;q=0.8")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(send(&router, accepted).0, StatusCode::OK);
+    }
+
+    #[test]
+    fn keyed_command_router_test() {
+        let runner = KeyedEventSourcedRunner::<Command, Event, State, Bulb, _, _>::load(
+            "bulbs",
+            InMemoryEventStore::new(),
+            InMemoryKeyedSnapshotStore::new(),
+        )
+        .unwrap();
+        let router = keyed_command_router(Arc::new(Mutex::new(runner)));
+
+        let fit = json!({ "command": { "Fit": { "max_uses": 3 } } }).to_string();
+        let (status, _, body) = send(&router, post("/aggregates/kitchen/commands", fit.clone()));
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            json!([{ "version": 1, "event": { "Fitted": { "max_uses": 3 } } }])
+        );
+        let (status, _, body) = send(&router, post("/aggregates/porch/commands", fit));
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["version"], 2);
+
+        let get = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+        let (status, _, body) = send(&router, get("/aggregates/kitchen/state"));
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_value::<State>(body).unwrap(),
+            State::Working {
+                status: Status::Off,
+                remaining_uses: 3
+            }
+        );
+        let (status, _, body) = send(&router, get("/aggregates/attic/state"));
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(problem(body).kind, "not-found");
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn store_conflict_test() {
+        use deciders_rs::stores::SqliteEventStore;
+        use rusqlite::Connection;
+
+        // Two servers share a database, each with its own runner.
+        let path = "file:web_conflict?mode=memory&cache=shared";
+        let routers: Vec<Router> = (0..2)
+            .map(|_| {
+                let store =
+                    SqliteEventStore::<Event>::new(Connection::open(path).unwrap()).unwrap();
+                let runner =
+                    EventSourcedRunner::<Command, Event, State, Bulb, _>::load("hall", store)
+                        .unwrap();
+                command_router(Arc::new(Mutex::new(runner)))
+            })
+            .collect();
+
+        let fit = json!({ "command": { "Fit": { "max_uses": 3 } } }).to_string();
+        assert_eq!(
+            send(&routers[0], post("/commands", fit.clone())).0,
+            StatusCode::OK
+        );
+        let (status, _, body) = send(&routers[1], post("/commands", fit));
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(problem(body).kind, "version-conflict");
+    }
+}
+
+struct TotalSwitchOns;
+
+impl Projection<bulb::Event> for TotalSwitchOns {
+    type View = u64;
+
+    fn initial() -> u64 {
+        0
+    }
+
+    fn apply(view: &u64, event: &bulb::Event) -> u64 {
+        match event {
+            bulb::Event::SwitchedOn => view + 1,
+            _ => *view,
+        }
+    }
+}
+
+#[test]
+fn projection_test() {
+    let mut runner = InMemoryRunner::<_, _, _, bulb::Bulb>::new();
+    let mut switch_ons = InMemoryProjection::<TotalSwitchOns, _>::new();
+    let mut counts = InMemoryProjection::<CountByEventType, _>::new();
+    for command in [
+        bulb::Command::Fit { max_uses: 5 },
+        bulb::Command::SwitchOn,
+        bulb::Command::SwitchOn,
+        bulb::Command::SwitchOff,
+        bulb::Command::SwitchOn,
+    ] {
+        let events = runner.command(&command);
+        switch_ons.apply_all(&events);
+        counts.apply_all(&events);
+    }
+    assert_eq!(*switch_ons.view(), 2);
+    assert_eq!(counts.view()["Fitted"], 1);
+    assert_eq!(counts.view()["SwitchedOn"], 2);
+    assert_eq!(counts.view()["SwitchedOff"], 1);
+    assert!(!counts.view().contains_key("Blew"));
+
+    switch_ons.apply_all(&[
+        bulb::Event::SwitchedOff,
+        bulb::Event::SwitchedOn,
+        bulb::Event::SwitchedOff,
+        bulb::Event::SwitchedOn,
+    ]);
+    assert_eq!(*switch_ons.view(), 4);
+}
+
+struct CatSleepCount;
+
+impl Projection<cat::Event> for CatSleepCount {
+    type View = u64;
+
+    fn initial() -> u64 {
+        0
+    }
+
+    fn apply(view: &u64, event: &cat::Event) -> u64 {
+        match event {
+            cat::Event::GotToSleep => view + 1,
+            _ => *view,
+        }
+    }
+}
+
+#[test]
+fn routed_keyed_projection_test() {
+    type HouseProjection = RoutedProjection<KeyedProjection<TotalSwitchOns>, CatSleepCount>;
+    use Either::*;
+    let mut projection = InMemoryProjection::<HouseProjection, _>::new();
+    assert_eq!(*projection.view(), (HashMap::new(), 0));
+
+    projection.apply_all(&[
+        Left(("kitchen".to_string(), bulb::Event::Fitted { max_uses: 5 })),
+        Left(("kitchen".to_string(), bulb::Event::SwitchedOn)),
+        Right(cat::Event::GotToSleep),
+        Left(("hall".to_string(), bulb::Event::Fitted { max_uses: 5 })),
+        Left(("kitchen".to_string(), bulb::Event::SwitchedOff)),
+        Left(("kitchen".to_string(), bulb::Event::SwitchedOn)),
+        Right(cat::Event::WokeUp),
+        Right(cat::Event::GotToSleep),
+    ]);
+
+    let (switch_ons, sleeps) = projection.view();
+    assert_eq!(switch_ons.len(), 2);
+    assert_eq!(switch_ons["kitchen"], 2);
+    assert_eq!(switch_ons["hall"], 0);
+    assert_eq!(*sleeps, 2);
+
+    assert_eq!(
+        HouseProjection::apply(
+            projection.view(),
+            &Left(("hall".to_string(), bulb::Event::SwitchedOn))
+        )
+        .0["hall"],
+        1
+    );
+
+    // Applying a batch with coalescing goes through the routers' batch paths.
+    let mut batched = InMemoryProjection::<HouseProjection, _>::new();
+    batched.set_coalescing(true);
+    let batch = [
+        Left(("kitchen".to_string(), bulb::Event::Fitted { max_uses: 5 })),
+        Right(cat::Event::GotToSleep),
+        Left(("hall".to_string(), bulb::Event::Fitted { max_uses: 5 })),
+        Left(("kitchen".to_string(), bulb::Event::SwitchedOn)),
+        Right(cat::Event::WokeUp),
+        Left(("kitchen".to_string(), bulb::Event::SwitchedOff)),
+        Left(("kitchen".to_string(), bulb::Event::SwitchedOn)),
+        Right(cat::Event::GotToSleep),
+    ];
+    batched.apply_all(&batch);
+    assert_eq!(batched.view(), projection.view());
+
+    // A keyed batch with interleaved keys gives the same views as applying each event.
+    type Keyed = KeyedProjection<TotalSwitchOns>;
+    let (keyed, _) = split_either(batch);
+    let mut views = Keyed::initial();
+    Keyed::apply_all_mut(&mut views, &keyed);
+    let expected = keyed
+        .iter()
+        .fold(Keyed::initial(), |views, e| Keyed::apply(&views, e));
+    assert_eq!(views, expected);
+    assert_eq!(views["kitchen"], 2);
+}
+
+#[test]
+fn projection_runner_test() {
+    type Runner = ProjectionRunner<
+        CountByEventType,
+        bulb::Event,
+        InMemoryCheckpointStore<HashMap<String, u64>>,
+    >;
+    let log = vec![
+        bulb::Event::Fitted { max_uses: 5 },
+        bulb::Event::SwitchedOn,
+        bulb::Event::SwitchedOff,
+        bulb::Event::SwitchedOn,
+        bulb::Event::SwitchedOff,
+        bulb::Event::Blew,
+    ];
+
+    let mut runner = Runner::new("counts", InMemoryCheckpointStore::new());
+    assert_eq!(runner.catch_up(&log[..3]), Ok(3));
+    assert_eq!(runner.catch_up(&log[..3]), Ok(0));
+    assert_eq!(runner.next_position(), GlobalPosition::from(3));
+
+    // Simulate the runner being killed and restarted with the same checkpoint store.
+    let store = runner.into_checkpoint_store();
+    let mut runner = Runner::new("counts", store);
+    assert_eq!(runner.next_position(), GlobalPosition::from(3));
+    assert_eq!(runner.catch_up(&log), Ok(3));
+
+    let mut rebuilt = Runner::new("rebuilt", InMemoryCheckpointStore::new());
+    let mut positions = vec![];
+    assert_eq!(rebuilt.rebuild(&log, |p| positions.push(p)), Ok(6));
+    assert_eq!(
+        positions,
+        (0..6).map(GlobalPosition::from).collect::<Vec<_>>()
+    );
+    assert_eq!(runner.view(), rebuilt.view());
+    assert_eq!(runner.view()["SwitchedOn"], 2);
+
+    let mut progress_calls = 0;
+    assert_eq!(runner.rebuild(&log, |_| progress_calls += 1), Ok(6));
+    assert_eq!(progress_calls, 6);
+    assert_eq!(runner.view(), rebuilt.view());
+}
+
+struct BulbStatuses;
+
+impl Projection<(String, bulb::Event)> for BulbStatuses {
+    type View = HashMap<String, bulb::State>;
+
+    fn initial() -> HashMap<String, bulb::State> {
+        HashMap::new()
+    }
+
+    fn apply(
+        view: &HashMap<String, bulb::State>,
+        (id, event): &(String, bulb::Event),
+    ) -> HashMap<String, bulb::State> {
+        let mut view = view.clone();
+        let state = view.get(id).copied().unwrap_or(bulb::Bulb::initial_state());
+        view.insert(id.clone(), bulb::Bulb::evolve(&state, event));
+        view
+    }
+}
+
+#[test]
+fn query_registry_test() {
+    let mut registry = QueryRegistry::<HashMap<String, bulb::State>>::new();
+    registry.register("working_bulb_count", |view| {
+        QueryResult::Number(
+            view.values()
+                .filter(|s| matches!(s, bulb::State::Working { .. }))
+                .count() as f64,
+        )
+    });
+    registry.register_with_params("bulbs_with_uses_left", |view, params| {
+        let minimum: u64 = params.trim().parse().map_err(|e| format!("{e}"))?;
+        let mut ids: Vec<String> = view
+            .iter()
+            .filter(|(_, s)| {
+                matches!(s, bulb::State::Working { remaining_uses, .. } if *remaining_uses >= minimum)
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        ids.sort();
+        Ok(QueryResult::List(
+            ids.into_iter().map(QueryResult::String).collect(),
+        ))
+    });
+
+    let mut projection = InMemoryProjection::<BulbStatuses, _>::new();
+    projection.apply_all(&[
+        ("kitchen".to_string(), bulb::Event::Fitted { max_uses: 5 }),
+        ("hall".to_string(), bulb::Event::Fitted { max_uses: 1 }),
+        ("porch".to_string(), bulb::Event::Fitted { max_uses: 3 }),
+        ("porch".to_string(), bulb::Event::Blew),
+    ]);
+
+    assert_eq!(
+        projection.query(&registry, "working_bulb_count", ""),
+        Ok(QueryResult::Number(2.0))
+    );
+    assert_eq!(
+        projection.query(&registry, "bulbs_with_uses_left", "1"),
+        Ok(QueryResult::List(vec![
+            QueryResult::String("hall".to_string()),
+            QueryResult::String("kitchen".to_string())
+        ]))
+    );
+    assert_eq!(
+        projection.query(&registry, "bulbs_with_uses_left", "2"),
+        Ok(QueryResult::List(vec![QueryResult::String(
+            "kitchen".to_string()
+        )]))
+    );
+    assert!(matches!(
+        projection.query(&registry, "bulbs_with_uses_left", "many"),
+        Err(QueryError::InvalidParameters { query, .. }) if query == "bulbs_with_uses_left"
+    ));
+    assert_eq!(
+        projection.query(&registry, "blown_bulb_count", ""),
+        Err(QueryError::UnknownQuery("blown_bulb_count".to_string()))
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn query_result_serde_test() {
+    let mut registry = QueryRegistry::<HashMap<String, bulb::State>>::new();
+    registry.register("statuses", |view| {
+        let statuses: std::collections::BTreeMap<_, _> = view
+            .iter()
+            .map(|(id, state)| {
+                let status = match state {
+                    bulb::State::Working { .. } => "working",
+                    bulb::State::NotFitted | bulb::State::Blown => "not working",
+                };
+                (id.clone(), status)
+            })
+            .collect();
+        QueryResult::Json(serde_json::to_value(statuses).unwrap())
+    });
+
+    let mut projection = InMemoryProjection::<BulbStatuses, _>::new();
+    projection.apply_all(&[
+        ("kitchen".to_string(), bulb::Event::Fitted { max_uses: 5 }),
+        ("porch".to_string(), bulb::Event::Fitted { max_uses: 3 }),
+        ("porch".to_string(), bulb::Event::Blew),
+    ]);
+    let result = projection.query(&registry, "statuses", "").unwrap();
+    assert_eq!(
+        result,
+        QueryResult::Json(serde_json::json!({"kitchen": "working", "porch": "not working"}))
+    );
+
+    let results = QueryResult::List(vec![
+        QueryResult::Number(2.0),
+        QueryResult::String("hall".to_string()),
+        result,
+    ]);
+    let json = serde_json::to_string(&results).unwrap();
+    assert_eq!(
+        json,
+        r#"{"List":[{"Number":2.0},{"String":"hall"},{"Json":{"kitchen":"working","porch":"not working"}}]}"#
+    );
+    assert_eq!(serde_json::from_str::<QueryResult>(&json).unwrap(), results);
+}
+
+#[test]
+fn projection_change_notification_test() {
+    let lifecycle = [
+        bulb::Event::Fitted { max_uses: 5 },
+        bulb::Event::SwitchedOn,
+        bulb::Event::SwitchedOff,
+        bulb::Event::SwitchedOn,
+    ];
+
+    let notifications = Rc::new(RefCell::new(vec![]));
+    let mut projection = InMemoryProjection::<TotalSwitchOns, _>::new();
+    let n = notifications.clone();
+    projection.on_change(move |view| n.borrow_mut().push(*view));
+    projection.apply_all(&lifecycle);
+    assert_eq!(*notifications.borrow(), [1, 2]);
+    // Events that don't change the view produce no notification.
+    projection.apply(&bulb::Event::SwitchedOff);
+    assert_eq!(*notifications.borrow(), [1, 2]);
+
+    notifications.borrow_mut().clear();
+    let mut projection = InMemoryProjection::<TotalSwitchOns, _>::new();
+    projection.set_coalescing(true);
+    let n = notifications.clone();
+    projection.on_change(move |view| n.borrow_mut().push(*view));
+    projection.apply_all(&lifecycle);
+    assert_eq!(*notifications.borrow(), [2]);
+
+    notifications.borrow_mut().clear();
+    let mut runner =
+        ProjectionRunner::<TotalSwitchOns, _, _>::new("switch_ons", InMemoryCheckpointStore::new());
+    let n = notifications.clone();
+    runner.on_change(move |view| n.borrow_mut().push(*view));
+    runner.catch_up(&lifecycle[..]).unwrap();
+    assert_eq!(*notifications.borrow(), [1, 2]);
+    runner.set_coalescing(true);
+    runner.rebuild(&lifecycle[..], |_| ()).unwrap();
+    assert_eq!(*notifications.borrow(), [1, 2]);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn projection_watch_view_test() {
+    let mut projection = InMemoryProjection::<TotalSwitchOns, _>::new();
+    let mut receiver = projection.watch_view();
+    assert_eq!(*receiver.borrow_and_update(), 0);
+    projection.apply(&bulb::Event::Fitted { max_uses: 5 });
+    assert!(!receiver.has_changed().unwrap());
+    projection.apply(&bulb::Event::SwitchedOn);
+    assert!(receiver.has_changed().unwrap());
+    assert_eq!(*receiver.borrow_and_update(), 1);
+}
+
+/// An event log whose events before `first_position` have been trimmed.
+struct TrimmedLog {
+    first_position: u64,
+    events: Vec<bulb::Event>,
+}
+
+impl EventLog<bulb::Event> for TrimmedLog {
+    fn events_from<'a>(
+        &'a self,
+        position: GlobalPosition,
+    ) -> impl Iterator<Item = (GlobalPosition, &'a bulb::Event)>
+    where
+        bulb::Event: 'a,
+    {
+        let first_position = GlobalPosition::from(self.first_position);
+        self.events
+            .iter()
+            .enumerate()
+            .map(move |(i, e)| (first_position + i as u64, e))
+            .filter(move |(p, _)| *p >= position)
+    }
+}
+
+#[test]
+fn projection_gap_policy_test() {
+    type Runner = ProjectionRunner<TotalSwitchOns, bulb::Event, InMemoryCheckpointStore<u64>>;
+    let mut log = TrimmedLog {
+        first_position: 0,
+        events: vec![
+            bulb::Event::Fitted { max_uses: 5 },
+            bulb::Event::SwitchedOn,
+            bulb::Event::SwitchedOff,
+            bulb::Event::SwitchedOn,
+            bulb::Event::SwitchedOff,
+            bulb::Event::SwitchedOn,
+        ],
+    };
+
+    // Each runner lags behind at position 2 while retention trims everything before position 4.
+    let lagging = |policy| {
+        let mut runner = Runner::new("switch_ons", InMemoryCheckpointStore::new());
+        runner.set_gap_policy(policy);
+        runner.catch_up(&log.events[..2]).unwrap();
+        runner
+    };
+    let mut failing = lagging(GapPolicy::Fail);
+    let mut rebuilding = lagging(GapPolicy::RebuildFromSnapshotBarrier);
+    let mut skipping = lagging(GapPolicy::SkipAndRecord);
+    let mut ahead = lagging(GapPolicy::RebuildFromSnapshotBarrier);
+    log.events.drain(..4);
+    log.first_position = 4;
+
+    assert_eq!(
+        failing.catch_up(&log),
+        Err(CatchUpError::Gap(GapError {
+            expected: GlobalPosition::from(2),
+            found: GlobalPosition::from(4)
+        }))
+    );
+    assert_eq!(failing.next_position(), GlobalPosition::from(2));
+    assert_eq!(*failing.view(), 1);
+
+    // Waiting for a token past the gap fails straight away instead of timing out.
+    assert_eq!(
+        failing.wait_for(ConsistencyToken::from(6), Duration::from_secs(60), || &log),
+        Err(WaitError::CatchUp(CatchUpError::Gap(GapError {
+            expected: GlobalPosition::from(2),
+            found: GlobalPosition::from(4)
+        })))
+    );
+
+    // Without a snapshot barrier, rebuilding would drop the trimmed events, so it refuses.
+    assert_eq!(
+        rebuilding.catch_up(&log),
+        Err(CatchUpError::Gap(GapError {
+            expected: GlobalPosition::from(2),
+            found: GlobalPosition::from(4)
+        }))
+    );
+    assert_eq!(*rebuilding.view(), 1);
+
+    // A barrier short of the first event after the gap does not cover it either.
+    rebuilding.set_snapshot_barrier(Checkpoint {
+        next_position: GlobalPosition::from(3),
+        view: 1,
+    });
+    assert!(rebuilding.catch_up(&log).is_err());
+
+    // The barrier holds the view of the two switch-ons before position 4.
+    rebuilding.set_snapshot_barrier(Checkpoint {
+        next_position: GlobalPosition::from(4),
+        view: 2,
+    });
+    assert_eq!(rebuilding.catch_up(&log), Ok(2));
+    assert_eq!(rebuilding.next_position(), GlobalPosition::from(6));
+    assert_eq!(*rebuilding.view(), 3);
+
+    // A barrier past the first event after the gap skips the events it already reflects.
+    ahead.set_snapshot_barrier(Checkpoint {
+        next_position: GlobalPosition::from(5),
+        view: 2,
+    });
+    assert_eq!(ahead.catch_up(&log), Ok(1));
+    assert_eq!(ahead.next_position(), GlobalPosition::from(6));
+    assert_eq!(*ahead.view(), 3);
+
+    let skipped = GlobalPosition::from(2)..GlobalPosition::from(4);
+    assert_eq!(skipping.catch_up(&log), Ok(2));
+    assert_eq!(skipping.next_position(), GlobalPosition::from(6));
+    assert_eq!(*skipping.view(), 2);
+    assert_eq!(skipping.skipped_ranges().len(), 1);
+    assert_eq!(skipping.skipped_ranges()[0], skipped);
+
+    // Catching up again neither reapplies nor skips anything.
+    assert_eq!(skipping.catch_up(&log), Ok(0));
+    assert_eq!(*skipping.view(), 2);
+    assert_eq!(skipping.skipped_ranges().len(), 1);
+    assert_eq!(skipping.skipped_ranges()[0], skipped);
+}
+
+#[cfg(feature = "sqlite")]
+mod sql_projection {
+    use super::{bulb, GlobalPosition};
+    use deciders_rs::projections::{RowMapper, SqlProjection, SqlStatement, SqlValue};
+    use rusqlite::Connection;
+
+    struct BulbRows;
+
+    impl RowMapper<(String, bulb::Event)> for BulbRows {
+        fn statements((id, event): &(String, bulb::Event)) -> rusqlite::Result<Vec<SqlStatement>> {
+            let id = SqlValue::Text(id.clone());
+            Ok(match event {
+                bulb::Event::Fitted { max_uses } => vec![SqlStatement::new(
+                    "INSERT INTO bulbs (id, status, remaining_uses) VALUES (?1, 'off', ?2)",
+                    vec![id, SqlValue::Integer(*max_uses as i64)],
+                )],
+                bulb::Event::SwitchedOn => vec![SqlStatement::new(
+                    "UPDATE bulbs SET status = 'on', remaining_uses = remaining_uses - 1
+                     WHERE id = ?1",
+                    vec![id],
+                )],
+                bulb::Event::SwitchedOff => vec![SqlStatement::new(
+                    "UPDATE bulbs SET status = 'off' WHERE id = ?1",
+                    vec![id],
+                )],
+                bulb::Event::Blew => vec![SqlStatement::new(
+                    "UPDATE bulbs SET status = 'blown' WHERE id = ?1",
+                    vec![id],
+                )],
+            })
+        }
+
+        fn reset_statements() -> Vec<SqlStatement> {
+            vec![SqlStatement::new("DELETE FROM bulbs", vec![])]
+        }
+    }
+
+    fn rows(connection: &Connection) -> Vec<(String, String, i64)> {
+        let mut statement = connection
+            .prepare("SELECT id, status, remaining_uses FROM bulbs ORDER BY id")
+            .unwrap();
+        statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn sql_projection_test() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute(
+                "CREATE TABLE bulbs (id TEXT PRIMARY KEY, status TEXT, remaining_uses INTEGER)",
+                [],
+            )
+            .unwrap();
+        let log = vec![
+            ("kitchen".to_string(), bulb::Event::Fitted { max_uses: 5 }),
+            ("kitchen".to_string(), bulb::Event::SwitchedOn),
+            ("hall".to_string(), bulb::Event::Fitted { max_uses: 1 }),
+            ("hall".to_string(), bulb::Event::SwitchedOn),
+            ("hall".to_string(), bulb::Event::SwitchedOff),
+            ("hall".to_string(), bulb::Event::Blew),
+        ];
+
+        let mut projection = SqlProjection::<BulbRows, _>::new("bulbs", connection).unwrap();
+        assert_eq!(projection.catch_up(&log[..4]), Ok(4));
+        assert_eq!(projection.catch_up(&log[..4]), Ok(0));
+
+        // Restarting from the same database resumes from the stored checkpoint.
+        let connection = projection.into_connection();
+        let mut projection = SqlProjection::<BulbRows, _>::new("bulbs", connection).unwrap();
+        assert_eq!(projection.next_position(), GlobalPosition::from(4));
+        assert_eq!(projection.catch_up(&log), Ok(2));
+        let expected = vec![
+            ("hall".to_string(), "blown".to_string(), 0),
+            ("kitchen".to_string(), "on".to_string(), 4),
+        ];
+        assert_eq!(rows(projection.connection()), expected);
+
+        assert_eq!(projection.rebuild(&log), Ok(6));
+        assert_eq!(rows(projection.connection()), expected);
+        assert_eq!(projection.next_position(), GlobalPosition::from(6));
+    }
+
+    #[test]
+    fn sql_time_bucketed_projection_test() {
+        use super::{stamped_bulb_events, Hourly};
+        use deciders_rs::projections::{create_event_bucket_table, TimeBucketedProjection};
+
+        let connection = Connection::open_in_memory().unwrap();
+        create_event_bucket_table(&connection).unwrap();
+        let log = stamped_bulb_events();
+        let mut projection =
+            SqlProjection::<TimeBucketedProjection<Hourly>, _>::new("buckets", connection).unwrap();
+        let buckets = |connection: &Connection| -> Vec<(i64, String, i64)> {
+            connection
+                .prepare("SELECT bucket, event_type, count FROM event_buckets ORDER BY bucket, event_type")
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .unwrap()
+                .map(Result::unwrap)
+                .collect()
+        };
+
+        projection.catch_up(&log[..5]).unwrap();
+        assert_eq!(
+            buckets(projection.connection())[..3],
+            [
+                (0, "Fitted".to_string(), 1),
+                (0, "SwitchedOff".to_string(), 1),
+                (0, "SwitchedOn".to_string(), 1),
+            ]
+        );
+        projection.catch_up(&log).unwrap();
+        assert_eq!(
+            buckets(projection.connection()),
+            [
+                (3600, "SwitchedOff".to_string(), 1),
+                (3600, "SwitchedOn".to_string(), 1),
+                (7200, "SwitchedOn".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn sql_time_bucket_overflow_test() {
+        use super::{Hourly, Stamped};
+        use deciders_rs::projections::{
+            create_event_bucket_table, SqlProjectionError, TimeBucketedProjection,
+        };
+
+        let connection = Connection::open_in_memory().unwrap();
+        create_event_bucket_table(&connection).unwrap();
+        let mut projection =
+            SqlProjection::<TimeBucketedProjection<Hourly>, _>::new("buckets", connection).unwrap();
+        let log = vec![
+            Stamped(3600, bulb::Event::SwitchedOn),
+            Stamped(u64::MAX, bulb::Event::SwitchedOn),
+        ];
+        assert!(matches!(
+            projection.catch_up(&log),
+            Err(SqlProjectionError::Sql(
+                rusqlite::Error::ToSqlConversionFailure(_)
+            ))
+        ));
+        // The whole batch is rolled back, so nothing is counted and the checkpoint stays put.
+        assert_eq!(projection.next_position(), GlobalPosition::from(0));
+        let count: i64 = projection
+            .connection()
+            .query_row("SELECT COUNT(*) FROM event_buckets", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+}
+
+#[test]
+fn projection_spec_test() {
+    let events = [
+        bulb::Event::Fitted { max_uses: 5 },
+        bulb::Event::SwitchedOn,
+        bulb::Event::SwitchedOff,
+        bulb::Event::SwitchedOn,
+    ];
+    let counts = |pairs: &[(&str, u64)]| -> HashMap<String, u64> {
+        pairs.iter().map(|(k, v)| (k.to_string(), *v)).collect()
+    };
+
+    ProjectionSpec::<CountByEventType, bulb::Event>::given(&[]).expect_view(HashMap::new());
+    ProjectionSpec::<CountByEventType, _>::given(&events).expect_view(counts(&[
+        ("Fitted", 1),
+        ("SwitchedOn", 2),
+        ("SwitchedOff", 1),
+    ]));
+    ProjectionSpec::<CountByEventType, _>::given(&events)
+        .expect(|view| view.values().sum::<u64>() == 4);
+    ProjectionSpec::<CountByEventType, _>::given(&events).expect_views(&[
+        counts(&[("Fitted", 1)]),
+        counts(&[("Fitted", 1), ("SwitchedOn", 1)]),
+        counts(&[("Fitted", 1), ("SwitchedOn", 1), ("SwitchedOff", 1)]),
+        counts(&[("Fitted", 1), ("SwitchedOn", 2), ("SwitchedOff", 1)]),
+    ]);
+
+    use Either::*;
+    ProjectionSpec::<RoutedProjection<KeyedProjection<TotalSwitchOns>, CatSleepCount>, _>::given(
+        &[
+            Left(("kitchen".to_string(), bulb::Event::SwitchedOn)),
+            Right(cat::Event::GotToSleep),
+        ],
+    )
+    .expect_views(&[
+        (HashMap::from([("kitchen".to_string(), 1)]), 0),
+        (HashMap::from([("kitchen".to_string(), 1)]), 1),
+    ]);
+}
+
+#[test]
+#[should_panic(expected = "unexpected view after event 1 (SwitchedOn)")]
+fn projection_spec_divergence_test() {
+    ProjectionSpec::<TotalSwitchOns, _>::given(&[
+        bulb::Event::Fitted { max_uses: 5 },
+        bulb::Event::SwitchedOn,
+        bulb::Event::SwitchedOff,
+    ])
+    .expect_views(&[0, 0, 1]);
+}
+
+mod order {
+    use super::Decider;
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum Command {
+        Place { shippable: bool },
+        Reserve,
+        Charge,
+        Ship,
+        Release,
+        Refund,
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum Event {
+        Placed { shippable: bool },
+        Reserved,
+        Charged,
+        Shipped,
+        ShippingFailed,
+        Released,
+        Refunded,
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub struct State {
+        pub shippable: bool,
+    }
+
+    pub struct Order;
+
+    impl Decider<Command, Event, State, State> for Order {
+        fn decide(command: &Command, state: &State) -> Vec<Event> {
+            vec![match command {
+                Command::Place { shippable } => Event::Placed {
+                    shippable: *shippable,
+                },
+                Command::Reserve => Event::Reserved,
+                Command::Charge => Event::Charged,
+                Command::Ship if state.shippable => Event::Shipped,
+                Command::Ship => Event::ShippingFailed,
+                Command::Release => Event::Released,
+                Command::Refund => Event::Refunded,
+            }]
+        }
+
+        fn evolve(state: &State, event: &Event) -> State {
+            match event {
+                Event::Placed { shippable } => State {
+                    shippable: *shippable,
+                },
+                _ => *state,
+            }
+        }
+
+        fn initial_state() -> State {
+            State { shippable: true }
+        }
+
+        fn is_terminal(_state: &State) -> bool {
+            true
+        }
+    }
+}
+
+struct Fulfillment;
+
+impl SagaDefinition<order::Event, order::Command> for Fulfillment {
+    fn saga() -> Saga<order::Event, order::Command> {
+        use order::{Command, Event};
+        SagaBuilder::new()
+            .started_by(on_event(|e| matches!(e, Event::Placed { .. })))
+            .step(
+                "reserve",
+                on_event(|e| matches!(e, Event::Reserved)),
+                emit(Command::Reserve),
+            )
+            .step(
+                "charge",
+                on_event(|e| matches!(e, Event::Charged)),
+                emit(Command::Charge),
+            )
+            .step(
+                "ship",
+                on_event(|e| matches!(e, Event::Shipped)),
+                emit(Command::Ship),
+            )
+            .on_failure(
+                on_event(|e| matches!(e, Event::ShippingFailed)),
+                compensate_with(vec![Command::Refund, Command::Release]),
+            )
+            .build()
+            .unwrap()
+    }
+}
+
+type FulfillmentProcess = SagaProcess<Fulfillment, order::Event, order::Command>;
+
+/// Feeds `commands` through the order decider and the fulfillment saga until no commands remain,
+/// returning every generated event and the final saga state.
+fn run_saga(state: SagaState, commands: Vec<order::Command>) -> (Vec<order::Event>, SagaState) {
+    let mut saga_state = state;
+    let mut order_state = order::Order::initial_state();
+    let mut commands = commands;
+    let mut all_events = vec![];
+    while !commands.is_empty() {
+        let command = commands.remove(0);
+        for event in order::Order::decide(&command, &order_state) {
+            order_state = order::Order::evolve(&order_state, &event);
+            saga_state = FulfillmentProcess::evolve(&saga_state, &event);
+            commands.extend(FulfillmentProcess::react(&saga_state, &event));
+            all_events.push(event);
+        }
+    }
+    (all_events, saga_state)
+}
+
+#[test]
+fn saga_test() {
+    use order::{Command, Event};
+
+    assert_eq!(Fulfillment::saga().len(), 3);
+    assert_eq!(Fulfillment::saga().step_name(1), Some("charge"));
+
+    let (events, state) = run_saga(
+        FulfillmentProcess::initial_state(),
+        vec![Command::Place { shippable: true }],
+    );
+    assert_eq!(
+        events,
+        vec![
+            Event::Placed { shippable: true },
+            Event::Reserved,
+            Event::Charged,
+            Event::Shipped
+        ]
+    );
+    assert_eq!(state.phase, SagaPhase::Completed);
+    assert!(FulfillmentProcess::is_terminal(&state));
+
+    // Duplicate confirmations do not re-issue commands.
+    let charging = SagaState {
+        phase: SagaPhase::Running(1),
+        completed_branches: vec![false],
+        changed: false,
+    };
+    let after_duplicate = FulfillmentProcess::evolve(&charging, &Event::Reserved);
+    assert_eq!(after_duplicate, charging);
+    assert_eq!(
+        FulfillmentProcess::react(&after_duplicate, &Event::Reserved),
+        vec![]
+    );
+}
+
+#[test]
+fn saga_resume_test() {
+    use order::{Command, Event};
+
+    let mut state = FulfillmentProcess::initial_state();
+    for event in [Event::Placed { shippable: true }, Event::Reserved] {
+        state = FulfillmentProcess::evolve(&state, &event);
+    }
+    assert_eq!(state.phase, SagaPhase::Running(1));
+    assert!(!FulfillmentProcess::is_terminal(&state));
+
+    let resumed = FulfillmentProcess::resume(&state);
+    assert_eq!(resumed, vec![Command::Charge]);
+    let (events, state) = run_saga(state, resumed);
+    assert_eq!(events, vec![Event::Charged, Event::Shipped]);
+    assert_eq!(state.phase, SagaPhase::Completed);
+}
+
+#[test]
+fn saga_compensation_test() {
+    use order::{Command, Event};
+
+    let (events, state) = run_saga(
+        FulfillmentProcess::initial_state(),
+        vec![Command::Place { shippable: false }],
+    );
+    assert_eq!(
+        events,
+        vec![
+            Event::Placed { shippable: false },
+            Event::Reserved,
+            Event::Charged,
+            Event::ShippingFailed,
+            Event::Refunded,
+            Event::Released,
+        ]
+    );
+    assert_eq!(state.phase, SagaPhase::Compensated(2));
+    assert!(FulfillmentProcess::is_terminal(&state));
+    assert_eq!(
+        FulfillmentProcess::resume(&state),
+        vec![Command::Refund, Command::Release]
+    );
+}
+
+mod delivery {
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum Event {
+        Tick(u64),
+        ShipmentRequested,
+        Shipped,
+        PaymentRequested,
+        Paid,
+        Closed,
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum Command {
+        EscalateShipment,
+        EscalatePayment,
+    }
+}
+
+struct DeliveryWatchdog;
+
+impl WatchdogDefinition<delivery::Event, delivery::Command> for DeliveryWatchdog {
+    fn watchdog() -> Watchdog<delivery::Event, delivery::Command> {
+        use delivery::{Command, Event};
+        WatchdogBuilder::new(|e| match e {
+            Event::Tick(now) => Some(*now),
+            _ => None,
+        })
+        .watch(
+            expect(on_event(|e| matches!(e, Event::Shipped)))
+                .after(on_event(|e| matches!(e, Event::ShipmentRequested)))
+                .within(10)
+                .otherwise(vec![Command::EscalateShipment]),
+        )
+        .watch(
+            expect(on_event(|e| matches!(e, Event::Paid)))
+                .after(on_event(|e| matches!(e, Event::PaymentRequested)))
+                .within(3)
+                .otherwise(vec![Command::EscalatePayment]),
+        )
+        .until(on_event(|e| matches!(e, Event::Closed)))
+        .build()
+    }
+}
+
+type DeliveryWatchdogProcess =
+    WatchdogProcess<DeliveryWatchdog, delivery::Event, delivery::Command>;
+
+#[test]
+fn watchdog_test() {
+    use delivery::{Command, Event};
+
+    let mut state = DeliveryWatchdogProcess::initial_state();
+    assert!(!DeliveryWatchdogProcess::is_terminal(&state));
+    let mut fire = |events: &[Event]| -> Vec<Command> {
+        let commands = collect_fold::<DeliveryWatchdogProcess, _, _, _>(&state, events);
+        for event in events {
+            state = DeliveryWatchdogProcess::evolve(&state, event);
+        }
+        commands
+    };
+
+    // Both expectations are armed concurrently; the shipment arrives in time, the payment does
+    // not.
+    assert_eq!(
+        fire(&[
+            Event::Tick(1),
+            Event::ShipmentRequested,
+            Event::PaymentRequested,
+            Event::Tick(3),
+        ]),
+        vec![]
+    );
+    assert_eq!(
+        fire(&[Event::Shipped, Event::Tick(4)]),
+        vec![Command::EscalatePayment]
+    );
+    // The expired expectation fires only once, and late confirmations change nothing.
+    assert_eq!(
+        fire(&[Event::Tick(5), Event::Paid, Event::Tick(20)]),
+        vec![]
+    );
+    // Only the closing event ends the watchdog, disarming anything still pending.
+    assert_eq!(
+        fire(&[Event::ShipmentRequested, Event::Closed, Event::Tick(40)]),
+        vec![]
+    );
+    assert!(DeliveryWatchdogProcess::is_terminal(&state));
+    assert_eq!(state.deadlines, [None, None]);
+}
+
+#[test]
+fn watchdog_restart_test() {
+    use delivery::{Command, Event};
+
+    let mut state = DeliveryWatchdogProcess::initial_state();
+    for event in [Event::Tick(100), Event::ShipmentRequested, Event::Tick(105)] {
+        state = DeliveryWatchdogProcess::evolve(&state, &event);
+    }
+    assert_eq!(state.deadlines, [Some(110), None]);
+    assert!(!DeliveryWatchdogProcess::is_terminal(&state));
+
+    // A restarted watchdog resumes from its stored state without issuing anything, and still
+    // fires once the deadline passes.
+    let restarted = state;
+    assert_eq!(DeliveryWatchdogProcess::resume(&restarted), vec![]);
+    assert_eq!(
+        collect_fold::<DeliveryWatchdogProcess, _, _, _>(
+            &restarted,
+            &[Event::Tick(109), Event::Tick(110), Event::Tick(111)]
+        ),
+        vec![Command::EscalateShipment]
+    );
+
+    // A watchdog stopped after saving the expiring tick but before reacting to it issues the
+    // overdue commands when resumed.
+    let expired = DeliveryWatchdogProcess::evolve(&restarted, &Event::Tick(110));
+    assert_eq!(
+        DeliveryWatchdogProcess::resume(&expired),
+        vec![Command::EscalateShipment]
+    );
+    let later = DeliveryWatchdogProcess::evolve(&expired, &Event::Tick(111));
+    assert_eq!(DeliveryWatchdogProcess::resume(&later), vec![]);
+    assert!(!DeliveryWatchdogProcess::is_terminal(&later));
+
+    // Deadlines saturate rather than overflow.
+    let mut late = DeliveryWatchdogProcess::initial_state();
+    for event in [Event::Tick(u64::MAX - 1), Event::ShipmentRequested] {
+        late = DeliveryWatchdogProcess::evolve(&late, &event);
+    }
+    assert_eq!(late.deadlines, [Some(u64::MAX), None]);
+
+    // A state stored when the watchdog had fewer expectations is extended with unarmed ones.
+    let stored = WatchdogState {
+        now: 100,
+        deadlines: vec![Some(110)],
+        expired: vec![false],
+        ended: false,
+    };
+    let expired = DeliveryWatchdogProcess::evolve(&stored, &Event::Tick(110));
+    assert_eq!(expired.deadlines, [None, None]);
+    assert_eq!(
+        DeliveryWatchdogProcess::resume(&expired),
+        vec![Command::EscalateShipment]
+    );
+}
+
+#[test]
+fn process_runner_restart_test() {
+    use order::{Command, Event};
+
+    type FulfillmentRunner = ProcessRunner<
+        FulfillmentProcess,
+        Event,
+        Command,
+        SagaState,
+        InMemoryProcessStateStore<SagaState>,
+    >;
+
+    let mut runner = FulfillmentRunner::new("order-1", InMemoryProcessStateStore::new());
+    assert_eq!(runner.resume(), vec![]);
+    assert_eq!(
+        runner.handle(&Event::Placed { shippable: true }),
+        Ok(vec![Command::Reserve])
+    );
+    assert_eq!(runner.handle(&Event::Reserved), Ok(vec![Command::Charge]));
+    assert_eq!(runner.version(), StreamVersion::from(2));
+
+    // The runner stops before the charge is confirmed; a new one picks up from the store.
+    let store = runner.into_store();
+    let mut runner = FulfillmentRunner::new("order-1", store.clone());
+    assert_eq!(runner.version(), StreamVersion::from(2));
+    assert_eq!(runner.get_state().phase, SagaPhase::Running(1));
+    assert_eq!(runner.resume(), vec![Command::Charge]);
+    assert_eq!(runner.handle(&Event::Charged), Ok(vec![Command::Ship]));
+    assert_eq!(runner.handle(&Event::Shipped), Ok(vec![]));
+    assert!(runner.is_terminal());
+
+    // Another process id starts fresh.
+    let other = FulfillmentRunner::new("order-2", runner.into_store());
+    assert_eq!(other.version(), StreamVersion::INITIAL);
+    assert_eq!(other.get_state().phase, SagaPhase::NotStarted);
+
+    // Saving over a state another runner already advanced is rejected.
+    let mut store = other.into_store();
+    let state = FulfillmentProcess::initial_state();
+    assert_eq!(
+        store.save("order-1", StreamVersion::from(4), &state),
+        Err(VersionConflict {
+            version: StreamVersion::from(4),
+            stored: StreamVersion::from(4)
+        })
+    );
+    assert_eq!(
+        store.save("order-2", StreamVersion::INITIAL.next(), &state),
+        Ok(())
+    );
+}
+
+mod refund {
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum Command {
+        Approve,
+        Refund,
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum Event {
+        Requested,
+        Approved,
+        Refunded,
+    }
+}
+
+struct RefundSaga;
+
+impl SagaDefinition<refund::Event, refund::Command> for RefundSaga {
+    fn saga() -> Saga<refund::Event, refund::Command> {
+        use refund::{Command, Event};
+        SagaBuilder::new()
+            .started_by(on_event(|e| matches!(e, Event::Requested)))
+            .step(
+                "approve",
+                on_event(|e| matches!(e, Event::Approved)),
+                emit(Command::Approve),
+            )
+            .step(
+                "refund",
+                on_event(|e| matches!(e, Event::Refunded)),
+                emit(Command::Refund),
+            )
+            .build()
+            .unwrap()
+    }
+}
+
+struct Approvals;
+
+impl HumanTask<refund::Command> for Approvals {
+    fn task_name(command: &refund::Command) -> Option<String> {
+        match command {
+            refund::Command::Approve => Some("approve".to_string()),
+            refund::Command::Refund => None,
+        }
+    }
+}
+
+#[test]
+fn pending_actions_test() {
+    use refund::{Command, Event};
+
+    let mut runner = ProcessRunner::<
+        SagaProcess<RefundSaga, Event, Command>,
+        Event,
+        Command,
+        SagaState,
+        InMemoryProcessStateStore<SagaState>,
+    >::new("refund-7", InMemoryProcessStateStore::new());
+    assert_eq!(runner.pending_actions::<Approvals>(), vec![]);
+
+    assert_eq!(runner.handle(&Event::Requested), Ok(vec![Command::Approve]));
+    assert_eq!(
+        runner.pending_actions::<Approvals>(),
+        vec![PendingAction {
+            id: "refund-7/approve".to_string(),
+            command: Command::Approve
+        }]
+    );
+
+    assert_eq!(
+        runner.complete_action::<Approvals>("refund-7/approve", &Event::Approved),
+        Ok(vec![Command::Refund])
+    );
+    assert_eq!(runner.pending_actions::<Approvals>(), vec![]);
+    assert_eq!(
+        runner.complete_action::<Approvals>("refund-7/approve", &Event::Approved),
+        Err(CompleteActionError::NotPending(
+            "refund-7/approve".to_string()
+        ))
+    );
+    assert_eq!(runner.handle(&Event::Refunded), Ok(vec![]));
+    assert!(runner.is_terminal());
+    assert_eq!(runner.pending_actions::<Approvals>(), vec![]);
+}
+
+mod dispatch {
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum Command {
+        Pack,
+        Invoice,
+        BookCourier,
+        BookPost,
+        CancelCourier,
+        CancelPost,
+        Refund,
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum Event {
+        Placed,
+        Packed,
+        Invoiced,
+        InvoiceRejected,
+        CourierBooked,
+        PostBooked,
+    }
+}
+
+struct Dispatch;
+
+impl SagaDefinition<dispatch::Event, dispatch::Command> for Dispatch {
+    fn saga() -> Saga<dispatch::Event, dispatch::Command> {
+        use dispatch::{Command, Event};
+        use Join::*;
+        SagaBuilder::new()
+            .started_by(on_event(|e| matches!(e, Event::Placed)))
+            .parallel(
+                "prepare",
+                AllOf(vec![
+                    branch(
+                        on_event(|e| matches!(e, Event::Packed)),
+                        emit(Command::Pack),
+                    ),
+                    branch(
+                        on_event(|e| matches!(e, Event::Invoiced)),
+                        emit(Command::Invoice),
+                    ),
+                ]),
+            )
+            .on_failure(
+                on_event(|e| matches!(e, Event::InvoiceRejected)),
+                compensate_with(vec![Command::Refund]),
+            )
+            .parallel(
+                "deliver",
+                AnyOf(vec![
+                    branch(
+                        on_event(|e| matches!(e, Event::CourierBooked)),
+                        emit(Command::BookCourier),
+                    )
+                    .with_compensation(compensate_with(vec![Command::CancelCourier])),
+                    branch(
+                        on_event(|e| matches!(e, Event::PostBooked)),
+                        emit(Command::BookPost),
+                    )
+                    .with_compensation(compensate_with(vec![Command::CancelPost])),
+                ]),
+            )
+            .build()
+            .unwrap()
+    }
+}
+
+type DispatchProcess = SagaProcess<Dispatch, dispatch::Event, dispatch::Command>;
+
+#[test]
+fn parallel_saga_test() {
+    use dispatch::{Command, Event};
+
+    fn feed(state: &mut SagaState, event: Event) -> Vec<Command> {
+        *state = DispatchProcess::evolve(state, &event);
+        DispatchProcess::react(state, &event)
+    }
+
+    let mut state = DispatchProcess::initial_state();
+
+    assert_eq!(
+        feed(&mut state, Event::Placed),
+        vec![Command::Pack, Command::Invoice]
+    );
+    // The AllOf join waits for both branches.
+    assert_eq!(feed(&mut state, Event::Invoiced), vec![]);
+    assert_eq!(state.phase, SagaPhase::Running(0));
+    assert_eq!(DispatchProcess::resume(&state), vec![Command::Pack]);
+    assert_eq!(
+        feed(&mut state, Event::Packed),
+        vec![Command::BookCourier, Command::BookPost]
+    );
+    // The AnyOf join completes with the first branch, and the late branch is compensated.
+    assert_eq!(
+        feed(&mut state, Event::PostBooked),
+        vec![Command::CancelCourier]
+    );
+    assert_eq!(state.phase, SagaPhase::Completed);
+    assert!(DispatchProcess::is_terminal(&state));
+    assert_eq!(feed(&mut state, Event::CourierBooked), vec![]);
+}
+
+#[test]
+fn parallel_saga_failure_test() {
+    use dispatch::{Command, Event};
+
+    let mut state = DispatchProcess::initial_state();
+    for event in [Event::Placed, Event::Packed, Event::InvoiceRejected] {
+        state = DispatchProcess::evolve(&state, &event);
+    }
+    assert_eq!(state.phase, SagaPhase::Compensated(0));
+    assert_eq!(
+        DispatchProcess::react(&state, &Event::InvoiceRejected),
+        vec![Command::Refund]
+    );
+    assert_eq!(DispatchProcess::resume(&state), vec![Command::Refund]);
+}
+
+#[test]
+fn saga_definition_error_test() {
+    use dispatch::{Command, Event};
+
+    fn placed() -> SagaBuilder<Event, Command> {
+        SagaBuilder::new().started_by(on_event(|e| matches!(e, Event::Placed)))
+    }
+    let packed = || on_event(|e| matches!(e, Event::Packed));
+
+    assert_eq!(
+        SagaBuilder::<Event, Command>::new()
+            .step("pack", packed(), emit(Command::Pack))
+            .build()
+            .unwrap_err(),
+        SagaDefinitionError::NotStarted
+    );
+    assert_eq!(placed().build().unwrap_err(), SagaDefinitionError::NoSteps);
+    assert_eq!(
+        placed()
+            .parallel("prepare", Join::AllOf(vec![]))
+            .build()
+            .unwrap_err(),
+        SagaDefinitionError::NoBranches("prepare".to_string())
+    );
+    assert_eq!(
+        placed()
+            .on_failure(packed(), compensate_with(vec![Command::Refund]))
+            .step("pack", packed(), emit(Command::Pack))
+            .build()
+            .unwrap_err(),
+        SagaDefinitionError::FailureWithoutStep
+    );
+
+    // A parallel step is not limited in how many branches it joins.
+    struct Wide;
+    impl SagaDefinition<Event, Command> for Wide {
+        fn saga() -> Saga<Event, Command> {
+            placed()
+                .parallel(
+                    "pack",
+                    Join::AllOf(
+                        (0..100)
+                            .map(|_| {
+                                branch(
+                                    on_event(|e| matches!(e, Event::Packed)),
+                                    emit(Command::Pack),
+                                )
+                            })
+                            .collect(),
+                    ),
+                )
+                .build()
+                .unwrap()
+        }
+    }
+    type WideProcess = SagaProcess<Wide, Event, Command>;
+    let started = WideProcess::evolve(&WideProcess::initial_state(), &Event::Placed);
+    assert_eq!(WideProcess::react(&started, &Event::Placed).len(), 100);
+    assert_eq!(started.completed_branches.len(), 100);
+    let packed = WideProcess::evolve(&started, &Event::Packed);
+    assert_eq!(packed.phase, SagaPhase::Completed);
+    assert!(WideProcess::is_terminal(&packed));
+}
+
+mod cat_bulb {
+    use super::{bulb, cat, cat_light};
+    use super::{AdaptedProcess, CombinedProcessDecider, ComposedDeciders, Either};
+    use super::{FallibleConverter, InfallibleConverter};
+
+    pub type CatAndBulb = ComposedDeciders<
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        bulb::Bulb,
+        bulb::Command,
+        bulb::Event,
+        bulb::State,
+    >;
+
+    pub struct CatLightEventConverter;
+    pub struct CatLightCommandConverter;
+
+    impl FallibleConverter<Either<cat::Event, bulb::Event>, cat_light::Event>
+        for CatLightEventConverter
+    {
+        fn convert(event: &Either<cat::Event, bulb::Event>) -> Option<cat_light::Event> {
+            match event {
+                Either::Left(cat::Event::WokeUp) => Some(cat_light::Event::WokeUp),
+                Either::Right(bulb::Event::SwitchedOn) => Some(cat_light::Event::SwitchedOn),
+                _ => None,
+            }
+        }
+    }
+
+    impl InfallibleConverter<cat_light::Command, Either<cat::Command, bulb::Command>>
+        for CatLightCommandConverter
+    {
+        fn convert(command: &cat_light::Command) -> Either<cat::Command, bulb::Command> {
+            match command {
+                cat_light::Command::WakeUp => Either::Left(cat::Command::WakeUp),
+            }
+        }
+    }
+
+    pub type CatLightProcess = AdaptedProcess<
+        cat_light::CatLight,
+        Either<cat::Event, bulb::Event>,
+        cat_light::Event,
+        cat_light::Command,
+        Either<cat::Command, bulb::Command>,
+        cat_light::State,
+        CatLightEventConverter,
+        CatLightCommandConverter,
+    >;
+
+    pub type CatBulb = CombinedProcessDecider<
+        CatLightProcess,
+        CatAndBulb,
+        Either<cat::Event, bulb::Event>,
+        Either<cat::Command, bulb::Command>,
+        cat_light::State,
+        (cat::State, bulb::State),
+    >;
+}
+
+#[test]
+fn viz_test() {
+    use cat_bulb::CatAndBulb;
+    assert_eq!(
+        viz::to_dot::<_, _, _, cat::Cat>(&[cat::Command::WakeUp, cat::Command::GetToSleep], 4),
+        r#"digraph {
+    start [shape=point];
+    start -> n0;
+    n0 [label="Awake", shape=circle];
+    n1 [label="Asleep", shape=circle];
+    n0 -> n1 [label="GetToSleep / GotToSleep"];
+    n1 -> n0 [label="WakeUp / WokeUp"];
+}
+"#
+    );
+
+    let fitted = bulb::State::Working {
+        status: bulb::Status::Off,
+        remaining_uses: 2,
+    };
+    let graph = viz::explore::<_, _, _, bulb::Bulb>(
+        &fitted,
+        &[bulb::Command::SwitchOn, bulb::Command::SwitchOff],
+        6,
+    );
+    assert_eq!(graph.nodes.len(), 6);
+    assert_eq!(graph.transitions.len(), 5);
+    assert_eq!(graph.nodes[5].state, bulb::State::Blown);
+    assert!(graph.nodes[5].terminal);
+    let dot = graph.to_dot(|s| match s {
+        bulb::State::Working {
+            status,
+            remaining_uses,
+        } => format!("{status:?}\n\"{remaining_uses} left\""),
+        s => format!("{s:?}"),
+    });
+    assert!(dot.contains(r#"n0 [label="Off\n\"2 left\"", shape=circle];"#));
+    assert!(dot.contains(r#"n5 [label="Blown", shape=doublecircle];"#));
+
+    use Either::*;
+    let graph = viz::explore::<_, _, _, CatAndBulb>(
+        &(cat::State::Awake, fitted),
+        &[
+            Left(cat::Command::GetToSleep),
+            Right(bulb::Command::SwitchOn),
+        ],
+        1,
+    );
+    let labels: Vec<_> = graph.transitions.iter().map(|t| &t.label[..]).collect();
+    assert_eq!(
+        labels,
+        [
+            "Left::GetToSleep / Left::GotToSleep",
+            "Right::SwitchOn / Right::SwitchedOn"
+        ]
+    );
+}
+
+#[test]
+fn trace_to_mermaid_test() {
+    use cat_bulb::{CatAndBulb, CatBulb, CatLightProcess};
+    use Either::*;
+
+    let trace = viz::simulate_combined_trace::<
+        CatLightProcess,
+        CatAndBulb,
+        Either<cat::Event, bulb::Event>,
+        Either<cat::Command, bulb::Command>,
+        cat_light::State,
+        (cat::State, bulb::State),
+    >(
+        &CatBulb::initial_state(),
+        &[
+            Right(bulb::Command::Fit { max_uses: 5 }),
+            Left(cat::Command::GetToSleep),
+            Right(bulb::Command::SwitchOn),
+        ],
+    );
+    assert_eq!(
+        viz::trace_to_mermaid(&trace),
+        "sequenceDiagram
+    participant Caller
+    participant Decider
+    participant Process
+    Caller->>Decider: Right::Fit
+    Decider-->>Process: Right::Fitted
+    Caller->>Decider: Left::GetToSleep
+    Decider-->>Process: Left::GotToSleep
+    Caller->>Decider: Right::SwitchOn
+    Decider-->>Process: Right::SwitchedOn
+    Process->>Decider: Left::WakeUp
+    Decider-->>Process: Left::WokeUp
+"
+    );
+    assert_eq!(
+        viz::trace_to_mermaid_with_states(&trace, |(_, (cat, _))| format!("cat {cat:?}")),
+        "sequenceDiagram
+    participant Caller
+    participant Decider
+    participant Process
+    Caller->>Decider: Right::Fit
+    Decider-->>Process: Right::Fitted
+    Caller->>Decider: Left::GetToSleep
+    Decider-->>Process: Left::GotToSleep
+    Note over Decider: cat Asleep
+    Caller->>Decider: Right::SwitchOn
+    Decider-->>Process: Right::SwitchedOn
+    Process->>Decider: Left::WakeUp
+    Decider-->>Process: Left::WokeUp
+    Note over Decider: cat Awake
+"
+    );
+    // Labels are escaped in one pass, so the entity for `#` is not escaped again.
+    assert!(
+        viz::trace_to_mermaid_with_states(&trace, |(_, (cat, _))| format!("#{cat:?};\nnext"))
+            .contains("    Note over Decider: #35;Asleep#59;<br/>next\n")
+    );
+
+    let keyed = viz::simulate_trace::<_, _, _, ManyDecider<cat::Cat, _, _, _>>(
+        &HashMap::new(),
+        &[("tom".to_string(), cat::Command::GetToSleep)],
+    );
+    assert_eq!(
+        viz::trace_to_mermaid(&keyed),
+        "sequenceDiagram
+    participant Caller
+    participant Decider
+    Caller->>Decider: tom::GetToSleep
+    Decider-->>Caller: tom::GotToSleep
+"
+    );
+}
+
+#[cfg(feature = "wasm")]
+mod wasm_runner {
+    use super::bulb;
+    use deciders_rs::wasm::{WasmError, WasmErrorCode, WasmRunner};
+
+    deciders_rs::export_decider!(
+        BulbRunner: bulb::Bulb[bulb::Command, bulb::Event, bulb::State] as "bulb"
+    );
+
+    #[test]
+    fn wasm_runner_test() {
+        let mut runner = WasmRunner::<bulb::Command, bulb::Event, bulb::State, bulb::Bulb>::new();
+        assert_eq!(runner.state(), Ok(r#""NotFitted""#.to_string()));
+        assert_eq!(
+            runner.command(r#"{"Fit":{"max_uses":0}}"#),
+            Ok(r#"[{"Fitted":{"max_uses":0}}]"#.to_string())
+        );
+        assert_eq!(
+            runner.state(),
+            Ok(r#"{"Working":{"status":"Off","remaining_uses":0}}"#.to_string())
+        );
+
+        let error = runner.command(r#"{"Explode":{}}"#).unwrap_err();
+        assert_eq!(error.code, WasmErrorCode::BadJson);
+        assert!(error.to_string().starts_with("[BAD_JSON] "));
+
+        assert_eq!(
+            runner.command(r#""SwitchOn""#),
+            Ok(r#"["Blew"]"#.to_string())
+        );
+        assert_eq!(
+            runner.command(r#""SwitchOn""#),
+            Err(WasmError {
+                code: WasmErrorCode::Terminal,
+                message: "the decider is in a terminal state".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn wasm_runner_serialize_error_test() {
+        use deciders_rs::deciders::Decider;
+        use serde::ser::Error;
+
+        /// A value that always fails to serialize.
+        struct Opaque;
+
+        impl serde::Serialize for Opaque {
+            fn serialize<S: serde::Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+                Err(S::Error::custom("opaque values cannot be serialized"))
+            }
+        }
+
+        struct Sealed;
+
+        impl Decider<(), Opaque, u32, u32> for Sealed {
+            fn decide(_command: &(), _state: &u32) -> Vec<Opaque> {
+                vec![Opaque]
+            }
+
+            fn evolve(state: &u32, _event: &Opaque) -> u32 {
+                state + 1
+            }
+
+            fn initial_state() -> u32 {
+                0
+            }
+
+            fn is_terminal(_state: &u32) -> bool {
+                false
+            }
+        }
+
+        // The error is returned instead of aborting, and the state is left unchanged.
+        let mut runner = WasmRunner::<(), Opaque, u32, Sealed>::new();
+        let error = runner.command("null").unwrap_err();
+        assert_eq!(error.code, WasmErrorCode::BadJson);
+        assert!(error.message.contains("opaque values cannot be serialized"));
+        assert_eq!(runner.state(), Ok("0".to_string()));
+    }
+
+    #[test]
+    fn exported_decider_test() {
+        let mut runner = BulbRunner::new();
+        assert_eq!(
+            runner.command(r#"{"Fit":{"max_uses":3}}"#).ok(),
+            Some(r#"[{"Fitted":{"max_uses":3}}]"#.to_string())
+        );
+        assert_eq!(
+            runner.state().ok(),
+            Some(r#"{"Working":{"status":"Off","remaining_uses":3}}"#.to_string())
+        );
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[wasm_bindgen_test::wasm_bindgen_test]
+    fn exported_decider_errors_test() {
+        let mut runner = BulbRunner::new();
+        assert!(runner.command("not json").is_err());
+        assert!(runner.command(r#"{"Fit":{"max_uses":0}}"#).is_ok());
+        assert!(runner.command(r#""SwitchOn""#).is_ok());
+        assert!(runner.command(r#""SwitchOn""#).is_err());
+        assert_eq!(runner.state().ok(), Some(r#""Blown""#.to_string()));
+    }
+}
+
+#[test]
+fn thread_runner_test() {
+    let (sender, handle) = spawn_thread_runner::<cat::Command, cat::Event, cat::State, cat::Cat>();
+
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let sender = sender.clone();
+            std::thread::spawn(move || sender.send(cat::Command::GetToSleep).unwrap())
+        })
+        .collect();
+    let events: Vec<_> = threads
+        .into_iter()
+        .flat_map(|t| t.join().unwrap())
+        .collect();
+    // Only the first command to arrive finds the cat awake.
+    assert_eq!(events, vec![cat::Event::GotToSleep]);
+
+    assert_eq!(
+        sender.send_timeout(cat::Command::WakeUp, Duration::from_secs(5)),
+        Ok(vec![cat::Event::WokeUp])
+    );
+    assert_eq!(handle.shutdown(), Ok(cat::State::Awake));
+    assert_eq!(
+        sender.send(cat::Command::GetToSleep),
+        Err(ThreadRunnerError::Stopped)
+    );
+}
+
+#[test]
+fn thread_runner_panic_test() {
+    let (sender, handle) =
+        spawn_thread_runner::<bulb::Command, bulb::Event, bulb::State, bulb::Bulb>();
+
+    assert_eq!(
+        sender.send(bulb::Command::Fit { max_uses: 5 }),
+        Ok(vec![bulb::Event::Fitted { max_uses: 5 }])
+    );
+    // Fitting twice panics in the decider, which must not leave senders waiting forever.
+    assert_eq!(
+        sender.send(bulb::Command::Fit { max_uses: 5 }),
+        Err(ThreadRunnerError::Stopped)
+    );
+    assert_eq!(
+        sender.send_timeout(bulb::Command::SwitchOn, Duration::from_secs(5)),
+        Err(ThreadRunnerError::Stopped)
+    );
+    assert_eq!(handle.shutdown(), Err(ThreadRunnerError::Panicked));
+}
+
+#[test]
+fn debugger_test() {
+    use bulb::{Event, State, Status};
+
+    let mut events = vec![Event::Fitted { max_uses: 40 }];
+    for _ in 0..40 {
+        events.extend([Event::SwitchedOn, Event::SwitchedOff]);
+    }
+    events.push(Event::Blew);
+    let fold = |n: usize| bulb::Bulb::fold(&events[..n]);
+
+    type BulbDebugger<'a> = Debugger<'a, bulb::Command, Event, State, bulb::Bulb>;
+    let mut debugger = BulbDebugger::new(&events);
+    assert_eq!(
+        debugger.current_event(),
+        Some(&Event::Fitted { max_uses: 40 })
+    );
+    assert!(!debugger.step_back());
+
+    // Stepping in both directions always agrees with folding the events from the start.
+    while debugger.step_forward() {
+        assert_eq!(*debugger.current_state(), fold(debugger.position()));
+    }
+    assert_eq!(debugger.position(), events.len());
+    assert_eq!(debugger.current_event(), None);
+    assert_eq!(*debugger.current_state(), State::Blown);
+    while debugger.step_back() {
+        assert_eq!(*debugger.current_state(), fold(debugger.position()));
+    }
+    assert_eq!(debugger.position(), 0);
+    debugger.run_to(70);
+    assert_eq!(*debugger.current_state(), fold(70));
+    debugger.run_to(33);
+    assert_eq!(*debugger.current_state(), fold(33));
+
+    debugger.run_to(0);
+    debugger.break_when(|state, event| {
+        matches!(
+            (state, event),
+            (
+                State::Working {
+                    remaining_uses: 10,
+                    status: Status::Off
+                },
+                Event::SwitchedOn
+            )
+        )
+    });
+    debugger.break_when(|_, event| matches!(event, Event::Blew));
+    assert_eq!(debugger.run_until_break(), Some(61));
+    assert_eq!(debugger.current_event(), Some(&Event::SwitchedOn));
+    assert_eq!(debugger.run_until_break(), Some(81));
+    assert_eq!(debugger.run_until_break(), None);
+    assert_eq!(debugger.position(), events.len());
+
+    debugger.run_to(0);
+    debugger.clear_breakpoints();
+    debugger.break_when(|_, event| matches!(event, Event::Fitted { max_uses: 1 }));
+    assert_eq!(debugger.run_until_break(), None);
+}
+
+#[test]
+fn publishing_runner_test() {
+    use bulb::*;
+
+    let lights = Rc::new(RefCell::new(vec![]));
+    let lights_status = Rc::new(RefCell::new(vec![]));
+    let audit = Rc::new(RefCell::new(vec![]));
+    let mut bus = SimpleEventBus::new();
+    let events = lights.clone();
+    bus.subscribe("lights", move |e: &Event| events.borrow_mut().push(*e));
+    let status = lights_status.clone();
+    bus.subscribe("lights", move |e: &Event| {
+        status
+            .borrow_mut()
+            .push(matches!(e, Event::SwitchedOn).then_some(Status::On))
+    });
+    let events = audit.clone();
+    bus.subscribe("audit", move |e: &Event| events.borrow_mut().push(*e));
+    let dropped = bus.subscribe_channel("lights");
+    drop(dropped);
+
+    let errors = Rc::new(RefCell::new(vec![]));
+    let mut runner = PublishingRunner::new(InMemoryRunner::<_, _, _, Bulb>::new(), &bus, "lights");
+    let errors_seen = errors.clone();
+    runner.on_publish_error(move |e| errors_seen.borrow_mut().push(e.clone()));
+
+    assert_eq!(
+        runner.command(&Command::Fit { max_uses: 1 }),
+        vec![Event::Fitted { max_uses: 1 }]
+    );
+    assert_eq!(runner.command(&Command::SwitchOn), vec![Event::SwitchedOn]);
+    runner.command(&Command::SwitchOff);
+    assert_eq!(
+        *runner.get_state(),
+        State::Working {
+            status: Status::Off,
+            remaining_uses: 0
+        }
+    );
+
+    assert_eq!(
+        *lights.borrow(),
+        vec![
+            Event::Fitted { max_uses: 1 },
+            Event::SwitchedOn,
+            Event::SwitchedOff
+        ]
+    );
+    assert_eq!(*lights_status.borrow(), vec![None, Some(Status::On), None]);
+    assert!(audit.borrow().is_empty());
+    // The dropped channel fails only the first publish, and is unsubscribed afterwards.
+    assert_eq!(errors.borrow().len(), 1);
+    assert_eq!(errors.borrow()[0].topic, "lights");
+
+    let receiver = bus.subscribe_channel("audit");
+    let mut audit_runner =
+        PublishingRunner::new(InMemoryRunner::<_, _, _, Bulb>::new(), &bus, "audit");
+    audit_runner.command(&Command::Fit { max_uses: 0 });
+    audit_runner.command(&Command::SwitchOn);
+    assert_eq!(
+        *audit.borrow(),
+        vec![Event::Fitted { max_uses: 0 }, Event::Blew]
+    );
+    assert_eq!(
+        receiver.try_iter().collect::<Vec<_>>(),
+        vec![Event::Fitted { max_uses: 0 }, Event::Blew]
+    );
+    assert_eq!(lights.borrow().len(), 3);
+}
+
+#[test]
+fn shared_runner_test() {
+    use bulb::*;
+    use std::sync::{Arc, Mutex};
+
+    let runner = SharedRunner::<_, _, _, Bulb, _>::new(SharedEventBus::<Event>::new(), "lights");
+    let lights = Arc::new(Mutex::new(vec![]));
+    let events = lights.clone();
+    runner
+        .publisher()
+        .subscribe("lights", move |e: &Event| events.lock().unwrap().push(*e));
+    let audit = Arc::new(Mutex::new(vec![]));
+    let events = audit.clone();
+    runner
+        .publisher()
+        .subscribe("audit", move |e: &Event| events.lock().unwrap().push(*e));
+    runner.command(&Command::Fit { max_uses: 100 });
+
+    let runner = Arc::new(runner);
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let runner = runner.clone();
+            std::thread::spawn(move || {
+                (0..10)
+                    .map(|_| {
+                        runner.command(&Command::SwitchOn).len()
+                            + runner.command(&Command::SwitchOff).len()
+                    })
+                    .sum::<usize>()
+            })
+        })
+        .collect();
+    let decided: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+
+    let lights = lights.lock().unwrap();
+    assert_eq!(lights.len(), decided + 1);
+    assert_eq!(runner.get_state(), Bulb::fold(&lights));
+    assert!(audit.lock().unwrap().is_empty());
+}
+
+#[cfg(feature = "loom-tests")]
+mod loom_tests {
+    use super::bulb::{Bulb, Command, Event, State};
+    use deciders_rs::deciders::Decider;
+    use deciders_rs::utilities::{EventPublisher, LoomLocking, SharedEventBus, SharedRunner};
+    use loom::sync::{Arc, Mutex};
+    use loom::thread;
+
+    type Bus = SharedEventBus<Event, LoomLocking>;
+
+    /// Subscribes to the topic `topic` of `bus`, returning the events it is given.
+    fn collect(bus: &Bus, topic: &str) -> Arc<Mutex<Vec<Event>>> {
+        let collected = Arc::new(Mutex::new(vec![]));
+        let events = collected.clone();
+        bus.subscribe(topic, move |e: &Event| events.lock().unwrap().push(*e));
+        collected
+    }
+
+    #[test]
+    fn shared_runner_loom_test() {
+        loom::model(|| {
+            let runner = SharedRunner::<Command, Event, State, Bulb, Bus, LoomLocking>::new(
+                Bus::new(),
+                "lights",
+            );
+            let published = collect(runner.publisher(), "lights");
+            runner.command(&Command::Fit { max_uses: 1 });
+
+            let runner = Arc::new(runner);
+            let threads: Vec<_> = [Command::SwitchOn, Command::SwitchOff]
+                .into_iter()
+                .map(|command| {
+                    let runner = runner.clone();
+                    thread::spawn(move || runner.command(&command))
+                })
+                .collect();
+            let decided: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+
+            // Every decided event is published once, with the events of each command together.
+            let published = published.lock().unwrap().clone();
+            let (first, second) = (decided[0].clone(), decided[1].clone());
+            assert!(
+                published[1..] == [first.clone(), second.clone()].concat()
+                    || published[1..] == [second, first].concat(),
+                "{published:?} is not {decided:?} in some order"
+            );
+            assert_eq!(runner.get_state(), Bulb::fold(&published));
+        });
+    }
+
+    #[test]
+    fn shared_event_bus_loom_test() {
+        loom::model(|| {
+            let bus = Arc::new(Bus::new());
+            let early = collect(&bus, "lights");
+            let events = [Event::Fitted { max_uses: 1 }, Event::SwitchedOn];
+            let publisher = {
+                let bus = bus.clone();
+                thread::spawn(move || {
+                    for e in events {
+                        bus.publish("lights", &e).unwrap();
+                    }
+                })
+            };
+            let late = collect(&bus, "lights");
+            publisher.join().unwrap();
+
+            assert_eq!(*early.lock().unwrap(), events);
+            // A subscriber that joins while events are published is given every event published
+            // after it subscribed, in order.
+            let late = late.lock().unwrap();
+            assert!(
+                events.ends_with(&late),
+                "{late:?} is not the end of {events:?}"
+            );
+        });
+    }
+}
+
+#[test]
+fn transition_coverage_test() {
+    {
+        use cat::*;
+        assert_enumerated_transition_coverage::<Command, Event, State, Cat>(
+            &TransitionExpectations::new()
+                .changes(&State::Awake, &Event::GotToSleep)
+                .changes(&State::Asleep, &Event::WokeUp)
+                // Waking an awake cat, or putting a sleeping one to sleep, is never decided.
+                .unreachable(&State::Awake, &Event::WokeUp)
+                .unreachable(&State::Asleep, &Event::GotToSleep),
+        );
+    }
+
+    use bulb::*;
+    let working = State::Working {
+        status: Status::Off,
+        remaining_uses: 3,
+    };
+    let expectations = TransitionExpectations::new()
+        .changes(&State::NotFitted, &Event::Fitted { max_uses: 1 })
+        .changes(&working, &Event::Blew)
+        // Switching only changes the fields of the working state, so it is a no-op at the
+        // level of variants, even though it is the bulb's main logic.
+        .no_op(&working, &Event::SwitchedOn)
+        .no_op(&working, &Event::SwitchedOff)
+        // Deciding to fit a fitted bulb panics, and a bulb that is not working cannot be
+        // switched, but evolve ignores these events through its catch-all arm instead.
+        .unreachable(&working, &Event::Fitted { max_uses: 1 })
+        .no_op(&State::NotFitted, &Event::SwitchedOn)
+        .no_op(&State::NotFitted, &Event::SwitchedOff)
+        .no_op(&State::NotFitted, &Event::Blew)
+        .no_op(&State::Blown, &Event::Fitted { max_uses: 1 })
+        .no_op(&State::Blown, &Event::SwitchedOn)
+        .no_op(&State::Blown, &Event::SwitchedOff)
+        .no_op(&State::Blown, &Event::Blew);
+    assert_transition_coverage::<Command, Event, State, Bulb>(
+        &[State::NotFitted, working, State::Blown],
+        &[
+            Event::Fitted { max_uses: 1 },
+            Event::SwitchedOn,
+            Event::SwitchedOff,
+            Event::Blew,
+        ],
+        &expectations,
+    );
+}
+
+#[test]
+#[should_panic(expected = "2 transition(s) did not match their expectations:\n  \
+    Awake + GotToSleep: expected NoOp, actual Changes\n  \
+    Asleep + GotToSleep: expected unclassified, actual NoOp")]
+fn transition_coverage_mismatch_test() {
+    use cat::*;
+    assert_enumerated_transition_coverage::<Command, Event, State, Cat>(
+        &TransitionExpectations::new()
+            .no_op(&State::Awake, &Event::GotToSleep)
+            .changes(&State::Asleep, &Event::WokeUp)
+            .unreachable(&State::Awake, &Event::WokeUp),
+    );
+}
+
+#[test]
+fn event_stream_split_test() {
+    use Either::*;
+    let events: Vec<Either<bulb::Event, cat::Event>> = vec![
+        Left(bulb::Event::Fitted { max_uses: 5 }),
+        Right(cat::Event::GotToSleep),
+        Left(bulb::Event::SwitchedOn),
+        Right(cat::Event::WokeUp),
+    ];
+    assert_eq!(
+        split_either(events.clone()),
+        (
+            vec![bulb::Event::Fitted { max_uses: 5 }, bulb::Event::SwitchedOn],
+            vec![cat::Event::GotToSleep, cat::Event::WokeUp]
+        )
+    );
+    assert_eq!(
+        lefts(events.iter().map(Either::as_ref)).collect::<Vec<_>>(),
+        vec![
+            &bulb::Event::Fitted { max_uses: 5 },
+            &bulb::Event::SwitchedOn
+        ]
+    );
+    assert_eq!(
+        rights(events).collect::<Vec<_>>(),
+        vec![cat::Event::GotToSleep, cat::Event::WokeUp]
+    );
+
+    let (only_lefts, no_rights) = split_either(vec![
+        Left::<_, cat::Event>(bulb::Event::SwitchedOn),
+        Left(bulb::Event::SwitchedOff),
+    ]);
+    assert_eq!(
+        only_lefts,
+        vec![bulb::Event::SwitchedOn, bulb::Event::SwitchedOff]
+    );
+    assert!(no_rights.is_empty());
+    let (no_lefts, no_rights) = split_either(Vec::<Either<bulb::Event, cat::Event>>::new());
+    assert!(no_lefts.is_empty() && no_rights.is_empty());
+    assert_eq!(
+        rights::<cat::Event, bulb::Event>(vec![Left(cat::Event::WokeUp)]).count(),
+        0
+    );
+
+    let partitions = partition_keyed(vec![
+        ("kitchen", bulb::Event::Fitted { max_uses: 5 }),
+        ("hall", bulb::Event::Fitted { max_uses: 3 }),
+        ("kitchen", bulb::Event::SwitchedOn),
+        ("kitchen", bulb::Event::SwitchedOff),
+    ]);
+    assert_eq!(partitions.len(), 2);
+    assert_eq!(
+        partitions["kitchen"],
+        vec![
+            bulb::Event::Fitted { max_uses: 5 },
+            bulb::Event::SwitchedOn,
+            bulb::Event::SwitchedOff
+        ]
+    );
+    assert_eq!(
+        partitions["hall"],
+        vec![bulb::Event::Fitted { max_uses: 3 }]
+    );
+    assert!(partition_keyed(Vec::<(String, bulb::Event)>::new()).is_empty());
+}
+
+#[test]
+fn state_fingerprint_test() {
+    type Lights = ManyDecider<bulb::Bulb, bulb::Command, bulb::Event, bulb::State>;
+    let commands: Vec<_> = (0..20)
+        .flat_map(|i| {
+            let name = format!("bulb {i}");
+            [
+                (name.clone(), bulb::Command::Fit { max_uses: i }),
+                (name, bulb::Command::SwitchOn),
+            ]
+        })
+        .collect();
+
+    let mut forwards = InMemoryRunner::<_, _, _, Lights>::new();
+    let mut backwards = InMemoryRunner::<_, _, _, Lights>::new();
+    for command in &commands {
+        forwards.command(command);
+    }
+    for pair in commands.chunks(2).rev() {
+        backwards.command(&pair[0]);
+        backwards.command(&pair[1]);
+    }
+    // Each map was built in a different order and has its own iteration order.
+    assert_eq!(forwards.get_state(), backwards.get_state());
+    assert_eq!(forwards.state_fingerprint(), backwards.state_fingerprint());
+    assert_eq!(
+        forwards.state_fingerprint(),
+        forwards.get_state().clone().fingerprint()
+    );
+
+    backwards.command(&("bulb 3".to_string(), bulb::Command::SwitchOff));
+    assert_ne!(forwards.state_fingerprint(), backwards.state_fingerprint());
+    assert_ne!(
+        (bulb::State::NotFitted, bulb::State::Blown).fingerprint(),
+        (bulb::State::Blown, bulb::State::NotFitted).fingerprint()
+    );
+    // Fingerprints use a fixed algorithm, so they can be stored across toolchain upgrades.
+    assert_eq!(42u64.fingerprint(), 0xff3a_dd6b_3789_daef);
+    assert_eq!(42usize.fingerprint(), 42u64.fingerprint());
+
+    // Keyed states are not `Hash`, but can be explored through their fingerprints.
+    type Cats = ManyDecider<cat::Cat, cat::Command, cat::Event, cat::State>;
+    let graph = viz::explore::<_, _, _, Cats>(
+        &Cats::initial_state(),
+        &[
+            ("a".to_string(), cat::Command::GetToSleep),
+            ("b".to_string(), cat::Command::GetToSleep),
+        ],
+        3,
+    );
+    // No cat asleep, only a, only b, and both, reached in either order.
+    assert_eq!(graph.nodes.len(), 4);
+    assert_eq!(graph.transitions.len(), 4);
+
+    #[cfg(feature = "serde")]
+    {
+        use deciders_rs::utilities::serialized_fingerprint;
+        let working = bulb::State::Working {
+            status: bulb::Status::On,
+            remaining_uses: 2,
+        };
+        assert_eq!(
+            serialized_fingerprint(&working),
+            serialized_fingerprint(&working.clone())
+        );
+        assert_ne!(
+            serialized_fingerprint(&working),
+            serialized_fingerprint(&bulb::State::Blown)
+        );
+    }
+}
+
+/// A rewrite of the bulb with a different state type, which blows one use early.
+mod lamp {
+    use super::bulb::{Command, Event};
+    use super::Decider;
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub struct State {
+        pub fitted: bool,
+        pub on: bool,
+        pub remaining_uses: u64,
+        pub blown: bool,
+    }
+
+    pub struct Lamp;
+
+    impl Decider<Command, Event, State, State> for Lamp {
+        fn decide(command: &Command, state: &State) -> Vec<Event> {
+            match command {
+                Command::Fit { max_uses } if !state.fitted => vec![Event::Fitted {
+                    max_uses: *max_uses,
+                }],
+                Command::SwitchOn if state.fitted && !state.blown && !state.on => {
+                    if state.remaining_uses > 1 {
+                        vec![Event::SwitchedOn]
+                    } else {
+                        vec![Event::Blew]
+                    }
+                }
+                Command::SwitchOff if state.on => vec![Event::SwitchedOff],
+                _ => vec![],
+            }
+        }
+
+        fn evolve(state: &State, event: &Event) -> State {
+            let mut state = *state;
+            match event {
+                Event::Fitted { max_uses } => {
+                    state.fitted = true;
+                    state.remaining_uses = *max_uses;
+                }
+                Event::SwitchedOn => {
+                    state.on = true;
+                    state.remaining_uses -= 1;
+                    state.blown = state.remaining_uses == 0;
+                }
+                Event::SwitchedOff => state.on = false,
+                Event::Blew => state.blown = true,
+            }
+            state
+        }
+
+        fn initial_state() -> State {
+            State {
+                fitted: false,
+                on: false,
+                remaining_uses: 0,
+                blown: false,
+            }
+        }
+
+        fn is_terminal(state: &State) -> bool {
+            state.blown
+        }
+    }
+}
+
+#[test]
+fn compare_replays_test() {
+    use bulb::*;
+
+    fn equiv(bulb: &State, lamp: &lamp::State) -> bool {
+        match bulb {
+            State::NotFitted => !lamp.fitted,
+            State::Working {
+                status,
+                remaining_uses,
+            } => {
+                lamp.fitted
+                    && !lamp.blown
+                    && lamp.on == (*status == Status::On)
+                    && lamp.remaining_uses == *remaining_uses
+            }
+            State::Blown => lamp.blown,
+        }
+    }
+
+    // Both versions agree until a use runs out.
+    assert_eq!(
+        compare_replays::<Command, _, _, _, Bulb, lamp::Lamp>(
+            &[
+                Event::Fitted { max_uses: 2 },
+                Event::SwitchedOn,
+                Event::SwitchedOff
+            ],
+            equiv
+        ),
+        Ok(())
+    );
+
+    let events = [
+        Event::Fitted { max_uses: 2 },
+        Event::SwitchedOn,
+        Event::SwitchedOff,
+        Event::SwitchedOn,
+        Event::SwitchedOff,
+        Event::Blew,
+    ];
+    assert_eq!(
+        compare_replays::<Command, _, _, _, Bulb, lamp::Lamp>(&events, equiv),
+        Err(Divergence::State {
+            index: 3,
+            event: "SwitchedOn".to_string(),
+            left: "Working { status: On, remaining_uses: 0 }".to_string(),
+            right: "State { fitted: true, on: true, remaining_uses: 0, blown: true }".to_string(),
+        })
+    );
+
+    // Deciding shows the lamp blowing one use early, before its state diverges.
+    let divergence = compare_replays_with_commands::<_, _, _, _, Bulb, lamp::Lamp>(
+        &events,
+        &[Command::SwitchOn, Command::SwitchOff],
+        equiv,
+    )
+    .unwrap_err();
+    assert_eq!(
+        divergence,
+        Divergence::Decision {
+            applied: 3,
+            command: "SwitchOn".to_string(),
+            left: "[SwitchedOn]".to_string(),
+            right: "[Blew]".to_string(),
+        }
+    );
+    assert_eq!(
+        divergence.to_string(),
+        "decisions for SwitchOn diverge after 3 events\n  left: [SwitchedOn]\n right: [Blew]"
+    );
+}
+
+#[test]
+fn authorizing_runner_test() {
+    use bulb::*;
+
+    struct MaintenanceFits;
+
+    impl Authorizer<Command, State> for MaintenanceFits {
+        fn authorize(actor: &Actor, command: &Command, _state: &State) -> Result<(), Denied> {
+            match command {
+                Command::Fit { .. } if !actor.has_claim("maintenance") => Err(Denied {
+                    reason: format!("{} may not fit bulbs", actor.id),
+                }),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    let denials = Rc::new(RefCell::new(vec![]));
+    let mut runner =
+        AuthorizingRunner::<_, MaintenanceFits>::new(InMemoryRunner::<_, _, _, Bulb>::new());
+    let seen = denials.clone();
+    runner
+        .on_denied(move |actor, denied| seen.borrow_mut().push((actor.id.clone(), denied.clone())));
+
+    let resident = Actor::new("resident", ["tenant"]);
+    let technician = Actor::new("technician", ["tenant", "maintenance"]);
+    let denied = Denied {
+        reason: "resident may not fit bulbs".to_string(),
+    };
+    assert_eq!(
+        runner.command(&resident, &Command::Fit { max_uses: 5 }),
+        Err(denied.clone())
+    );
+    assert_eq!(*runner.get_state(), State::NotFitted);
+    assert_eq!(
+        runner.command(&technician, &Command::Fit { max_uses: 5 }),
+        Ok(vec![Event::Fitted { max_uses: 5 }])
+    );
+    assert_eq!(
+        runner.command(&resident, &Command::SwitchOn),
+        Ok(vec![Event::SwitchedOn])
+    );
+    // A command the bulb rejects is allowed through, and produces no events.
+    assert_eq!(runner.command(&resident, &Command::SwitchOn), Ok(vec![]));
+
+    assert_eq!(*denials.borrow(), vec![("resident".to_string(), denied)]);
+    assert_eq!(
+        denials.borrow()[0].1.to_string(),
+        "command denied: resident may not fit bulbs"
+    );
+}
+
+/// Shared command, event and state types for running bulbs and cats side by side.
+mod app {
+    use super::{bulb, cat, FallibleConverter, InfallibleConverter};
+
+    #[derive(Copy, Clone, Debug)]
+    pub enum AppCommand {
+        Bulb(bulb::Command),
+        Cat(cat::Command),
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum AppEvent {
+        Bulb(bulb::Event),
+        Cat(cat::Event),
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum AppState {
+        Bulb(bulb::State),
+        Cat(cat::State),
+    }
+
+    pub struct AppConverter;
+
+    macro_rules! convert_variant {
+        ($variant:ident, $module:ident) => {
+            impl FallibleConverter<AppCommand, $module::Command> for AppConverter {
+                fn convert(input: &AppCommand) -> Option<$module::Command> {
+                    match input {
+                        AppCommand::$variant(c) => Some(*c),
+                        _ => None,
+                    }
+                }
+            }
+
+            impl FallibleConverter<AppEvent, $module::Event> for AppConverter {
+                fn convert(input: &AppEvent) -> Option<$module::Event> {
+                    match input {
+                        AppEvent::$variant(e) => Some(*e),
+                        _ => None,
+                    }
+                }
+            }
+
+            impl InfallibleConverter<$module::Event, AppEvent> for AppConverter {
+                fn convert(input: &$module::Event) -> AppEvent {
+                    AppEvent::$variant(*input)
+                }
+            }
+
+            impl FallibleConverter<AppState, $module::State> for AppConverter {
+                fn convert(input: &AppState) -> Option<$module::State> {
+                    match input {
+                        AppState::$variant(s) => Some(*s),
+                        _ => None,
+                    }
+                }
+            }
+
+            impl InfallibleConverter<$module::State, AppState> for AppConverter {
+                fn convert(input: &$module::State) -> AppState {
+                    AppState::$variant(*input)
+                }
+            }
+        };
+    }
+
+    convert_variant!(Bulb, bulb);
+    convert_variant!(Cat, cat);
+}
+
+#[test]
+fn heterogeneous_keyed_runner_test() {
+    use app::*;
+
+    let mut registry = Registry::new();
+    registry.register(
+        "bulb",
+        ErasedDecider::<bulb::Bulb, AppConverter, _, _, _>::new(),
+    );
+    registry.register(
+        "cat",
+        ErasedDecider::<cat::Cat, AppConverter, _, _, _>::new(),
+    );
+    let mut runner = HeterogeneousKeyedRunner::new(registry);
+
+    assert_eq!(
+        runner.create(
+            "lamp-1",
+            "bulb",
+            &AppCommand::Bulb(bulb::Command::Fit { max_uses: 1 })
+        ),
+        Ok(vec![(
+            "lamp-1".to_string(),
+            AppEvent::Bulb(bulb::Event::Fitted { max_uses: 1 })
+        )])
+    );
+    assert_eq!(
+        runner.create("cat-1", "cat", &AppCommand::Cat(cat::Command::GetToSleep)),
+        Ok(vec![(
+            "cat-1".to_string(),
+            AppEvent::Cat(cat::Event::GotToSleep)
+        )])
+    );
+
+    assert_eq!(
+        runner.command("lamp-1", &AppCommand::Bulb(bulb::Command::SwitchOn)),
+        Ok(vec![(
+            "lamp-1".to_string(),
+            AppEvent::Bulb(bulb::Event::SwitchedOn)
+        )])
+    );
+    assert_eq!(
+        runner.command("cat-1", &AppCommand::Cat(cat::Command::WakeUp)),
+        Ok(vec![(
+            "cat-1".to_string(),
+            AppEvent::Cat(cat::Event::WokeUp)
+        )])
+    );
+    assert_eq!(
+        runner.get_state("lamp-1"),
+        Some((
+            "bulb",
+            &AppState::Bulb(bulb::State::Working {
+                status: bulb::Status::On,
+                remaining_uses: 0
+            })
+        ))
+    );
+    assert_eq!(
+        runner.get_state("cat-1"),
+        Some(("cat", &AppState::Cat(cat::State::Awake)))
+    );
+
+    // A cat cannot be switched on, nor a bulb woken up.
+    assert_eq!(
+        runner.command("cat-1", &AppCommand::Bulb(bulb::Command::SwitchOn)),
+        Err(KeyedCommandError::Unconvertible {
+            key: "cat-1".to_string(),
+            tag: "cat".to_string()
+        })
+    );
+    let woken = runner.create("lamp-2", "bulb", &AppCommand::Cat(cat::Command::WakeUp));
+    assert_eq!(
+        woken.unwrap_err().to_string(),
+        "the command cannot be handled by lamp-2, which is a bulb"
+    );
+    assert_eq!(runner.get_state("lamp-2"), None);
+
+    assert_eq!(
+        runner.create("cat-1", "cat", &AppCommand::Cat(cat::Command::WakeUp)),
+        Err(KeyedCommandError::KeyExists("cat-1".to_string()))
+    );
+    assert_eq!(
+        runner.create("dog-1", "dog", &AppCommand::Cat(cat::Command::WakeUp)),
+        Err(KeyedCommandError::UnknownTag("dog".to_string()))
+    );
+    assert_eq!(
+        runner.command("lamp-3", &AppCommand::Bulb(bulb::Command::SwitchOn)),
+        Err(KeyedCommandError::UnknownKey("lamp-3".to_string()))
+    );
+
+    runner
+        .command("lamp-1", &AppCommand::Bulb(bulb::Command::SwitchOff))
+        .unwrap();
+    assert!(!runner.is_terminal("lamp-1"));
+    runner
+        .command("lamp-1", &AppCommand::Bulb(bulb::Command::SwitchOn))
+        .unwrap();
+    assert!(runner.is_terminal("lamp-1"));
+    assert!(!runner.is_terminal("cat-1"));
+}
+
+#[test]
+fn scheduler_test() {
+    use bulb::*;
+    use std::collections::HashSet;
+    use std::convert::Infallible;
+
+    let mut runner = InMemoryRunner::<_, _, _, Bulb>::new();
+    runner.command(&Command::Fit { max_uses: 5 });
+    runner.command(&Command::SwitchOn);
+
+    let mut scheduler = Scheduler::new(InMemoryScheduleStore::new());
+    let switch_off = scheduler.schedule(30, Command::SwitchOff).unwrap();
+    let cancelled = scheduler.schedule(20, Command::SwitchOn).unwrap();
+    // Issued by a process at time 5, for 10 ticks later.
+    assert!(scheduler
+        .submit(5, Delayable::Delayed(Command::SwitchOn, 10))
+        .unwrap()
+        .is_none());
+    assert!(matches!(
+        scheduler.submit(5, Delayable::Now(Command::SwitchOff)),
+        Ok(Some(Command::SwitchOff))
+    ));
+    assert_eq!(scheduler.cancel(cancelled), Ok(true));
+    assert_eq!(scheduler.cancel(cancelled), Ok(false));
+    assert_eq!(
+        scheduler
+            .pending()
+            .unwrap()
+            .iter()
+            .map(|entry| entry.at)
+            .collect::<Vec<_>>(),
+        vec![15, 30]
+    );
+
+    assert!(scheduler.due(10).unwrap().is_empty());
+    let due = scheduler.due(25).unwrap();
+    assert_eq!(due.len(), 1);
+    assert_eq!(due[0].at, 15);
+    assert!(matches!(due[0].command, Command::SwitchOn));
+    // A due command stays scheduled until it is acknowledged.
+    assert_eq!(scheduler.due(25).unwrap(), due);
+    assert_eq!(scheduler.acknowledge(due[0].id), Ok(true));
+    assert!(scheduler.due(25).unwrap().is_empty());
+
+    // The remaining command survives a restart, and a failed dispatch keeps it scheduled.
+    let mut scheduler = Scheduler::new(scheduler.into_store());
+    assert_eq!(
+        scheduler.dispatch_due(40, |_, _| Err::<Vec<Event>, _>("unavailable")),
+        Ok(vec![(switch_off, Err("unavailable"))])
+    );
+    // A receiver that remembers the ids it has seen ignores redelivered commands.
+    let mut seen = HashSet::new();
+    let mut dispatch = |id, command: &Command| -> Result<_, Infallible> {
+        if seen.insert(id) {
+            Ok(runner.command(command))
+        } else {
+            Ok(vec![])
+        }
+    };
+    assert_eq!(
+        scheduler.dispatch_due(40, &mut dispatch),
+        Ok(vec![(switch_off, Ok(vec![Event::SwitchedOff]))])
+    );
+    assert_eq!(dispatch(switch_off, &Command::SwitchOff), Ok(vec![]));
+    assert!(scheduler
+        .dispatch_due(50, &mut dispatch)
+        .unwrap()
+        .is_empty());
+    assert!(scheduler.pending().unwrap().is_empty());
+    assert_eq!(
+        *runner.get_state(),
+        State::Working {
+            status: Status::Off,
+            remaining_uses: 4
+        }
+    );
+
+    // Delays past the end of time saturate instead of overflowing.
+    let mut scheduler = Scheduler::new(InMemoryScheduleStore::new());
+    scheduler
+        .submit(u64::MAX - 1, Delayable::Delayed(Command::SwitchOn, 10))
+        .unwrap();
+    assert_eq!(scheduler.pending().unwrap()[0].at, u64::MAX);
+}
+
+#[test]
+fn scheduler_run_test() {
+    use bulb::*;
+    use std::convert::Infallible;
+
+    let mut runner = InMemoryRunner::<_, _, _, Bulb>::new();
+    let mut scheduler = Scheduler::new(InMemoryScheduleStore::new());
+    let fit = scheduler
+        .schedule(10, Command::Fit { max_uses: 5 })
+        .unwrap();
+    let switch_on = scheduler.schedule(20, Command::SwitchOn).unwrap();
+    let switch_off = scheduler.schedule(40, Command::SwitchOff).unwrap();
+
+    // The loop runs one tick per time returned by the clock, and dispatches each due command
+    // exactly once.
+    let mut ticks = [5, 10, 15, 25, 30].into_iter();
+    let mut outcomes = vec![];
+    scheduler
+        .run(
+            || ticks.next(),
+            |_, command| Ok::<_, Infallible>(runner.command(command)),
+            |id, outcome| outcomes.push((id, outcome)),
+        )
+        .unwrap();
+    assert_eq!(
+        outcomes,
+        vec![
+            (fit, Ok(vec![Event::Fitted { max_uses: 5 }])),
+            (switch_on, Ok(vec![Event::SwitchedOn])),
+        ]
+    );
+    assert_eq!(
+        scheduler
+            .pending()
+            .unwrap()
+            .iter()
+            .map(|entry| entry.id)
+            .collect::<Vec<_>>(),
+        vec![switch_off]
+    );
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn scheduler_run_every_test() {
+    use bulb::*;
+    use std::convert::Infallible;
+    use std::time::Duration;
+
+    let mut scheduler = Scheduler::new(InMemoryScheduleStore::new());
+    let switch_on = scheduler.schedule(2, Command::SwitchOn).unwrap();
+    scheduler.schedule(9, Command::SwitchOff).unwrap();
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .start_paused(true)
+        .build()
+        .unwrap();
+    let mut clock_reads = 0;
+    let mut outcomes = vec![];
+    let elapsed = runtime.block_on(async {
+        let start = tokio::time::Instant::now();
+        scheduler
+            .run_every(
+                Duration::from_secs(1),
+                || {
+                    clock_reads += 1;
+                    // The clock counts the seconds of paused tokio time, and stops after four
+                    // reads.
+                    let now = start.elapsed().as_secs();
+                    (clock_reads <= 4).then_some(now)
+                },
+                |_, command| Ok::<_, Infallible>(*command),
+                |id, outcome| outcomes.push((id, outcome)),
+            )
+            .await
+            .unwrap();
+        start.elapsed()
+    });
+    // The timer fired at 0, 1, 2, 3 and 4 seconds, and the clock stopped the loop at 4.
+    assert_eq!(elapsed, Duration::from_secs(4));
+    assert_eq!(outcomes, vec![(switch_on, Ok(Command::SwitchOn))]);
+    assert_eq!(scheduler.pending().unwrap().len(), 1);
+}
+
+#[cfg(all(feature = "sqlite", feature = "serde"))]
+#[test]
+fn sqlite_schedule_store_test() {
+    use deciders_rs::processes::SqliteScheduleStore;
+    use rusqlite::Connection;
+
+    #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    enum Reminder {
+        Renew { subscription: String },
+        Expire { subscription: String },
+    }
+
+    let path = std::env::temp_dir().join(format!(
+        "deciders-rs-schedule-{}.sqlite",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let renew = Reminder::Renew {
+        subscription: "gold".to_string(),
+    };
+    let expire = Reminder::Expire {
+        subscription: "gold".to_string(),
+    };
+
+    let mut scheduler = Scheduler::new(
+        SqliteScheduleStore::new("reminders", Connection::open(&path).unwrap()).unwrap(),
+    );
+    let renewal = scheduler.schedule(100, renew.clone()).unwrap();
+    let expiry = scheduler.schedule(200, expire.clone()).unwrap();
+    let cancelled = scheduler.schedule(150, renew.clone()).unwrap();
+    let end_of_time = scheduler.schedule(u64::MAX, expire.clone()).unwrap();
+    assert_eq!(scheduler.cancel(cancelled), Ok(true));
+    // Another schedule in the same database is kept apart.
+    let mut other = Scheduler::new(
+        SqliteScheduleStore::new("other", Connection::open(&path).unwrap()).unwrap(),
+    );
+    other.schedule(50, renew.clone()).unwrap();
+    drop(other);
+    drop(scheduler);
+
+    // The commands survive a restart, and fire once they are due.
+    let mut scheduler = Scheduler::new(
+        SqliteScheduleStore::new("reminders", Connection::open(&path).unwrap()).unwrap(),
+    );
+    assert_eq!(
+        scheduler
+            .pending()
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.id, entry.at))
+            .collect::<Vec<_>>(),
+        vec![(renewal, 100), (expiry, 200), (end_of_time, u64::MAX)]
+    );
+    let mut dispatched = vec![];
+    let mut dispatch = |id, reminder: &Reminder| -> Result<(), ()> {
+        dispatched.push((id, reminder.clone()));
+        Ok(())
+    };
+    assert_eq!(scheduler.dispatch_due(150, &mut dispatch).unwrap().len(), 1);
+    assert_eq!(scheduler.dispatch_due(150, &mut dispatch).unwrap().len(), 0);
+    assert_eq!(scheduler.dispatch_due(250, &mut dispatch).unwrap().len(), 1);
+    assert_eq!(dispatched, vec![(renewal, renew), (expiry, expire)]);
+    // Ids are never reused, even after every command has been removed.
+    let next = scheduler.schedule(
+        300,
+        Reminder::Renew {
+            subscription: "silver".to_string(),
+        },
+    );
+    assert!(next.unwrap() > end_of_time);
+
+    drop(scheduler);
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// A decider that blocks while the gate is held, counting the commands it started deciding.
+mod slow {
+    use super::Decider;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    pub static GATE: Mutex<()> = Mutex::new(());
+    pub static STARTED: AtomicUsize = AtomicUsize::new(0);
+
+    pub struct Slow;
+
+    impl Decider<u32, u32, u32, u32> for Slow {
+        fn decide(command: &u32, _state: &u32) -> Vec<u32> {
+            STARTED.fetch_add(1, Ordering::SeqCst);
+            drop(GATE.lock());
+            vec![*command]
+        }
+
+        fn evolve(state: &u32, event: &u32) -> u32 {
+            state + event
+        }
+
+        fn initial_state() -> u32 {
+            0
+        }
+
+        fn is_terminal(_state: &u32) -> bool {
+            false
+        }
+    }
+
+    /// Waits until `condition` holds, failing the test if it takes too long.
+    pub fn wait_for(condition: impl Fn() -> bool) {
+        for _ in 0..1000 {
+            if condition() {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        panic!("timed out waiting for the runner");
+    }
+}
+
+#[test]
+fn thread_runner_admission_test() {
+    use std::sync::atomic::Ordering;
+    use std::sync::{Arc, Mutex};
+
+    let gate = slow::GATE.lock().unwrap();
+    let watermarks = Arc::new(Mutex::new(vec![]));
+    let seen = watermarks.clone();
+    let (sender, handle) = spawn_thread_runner_with_policy::<u32, u32, u32, slow::Slow>(
+        AdmissionPolicy::bounded(2, OverflowMode::Reject)
+            .with_watermarks(2, 0, move |w| seen.lock().unwrap().push(w)),
+    );
+    let send = |command: u32| {
+        let sender = sender.clone();
+        std::thread::spawn(move || sender.send(command))
+    };
+
+    // The first command blocks the runner, and the next two fill the queue.
+    let first = send(1);
+    slow::wait_for(|| slow::STARTED.load(Ordering::SeqCst) == 1);
+    let queued = [send(2), send(3)];
+    slow::wait_for(|| sender.stats().queue_depth == 2);
+    assert_eq!(*watermarks.lock().unwrap(), vec![Watermark::High(2)]);
+    assert_eq!(sender.send(4), Err(ThreadRunnerError::Overloaded));
+
+    drop(gate);
+    assert_eq!(first.join().unwrap(), Ok(vec![1]));
+    for q in queued {
+        assert!(q.join().unwrap().is_ok());
+    }
+    assert_eq!(
+        *watermarks.lock().unwrap(),
+        vec![Watermark::High(2), Watermark::Low(0)]
+    );
+    assert_eq!(
+        sender.stats(),
+        RunnerStats {
+            queue_depth: 0,
+            handled: 3,
+            rejected: 1,
+            shed: 0
+        }
+    );
+    assert_eq!(handle.shutdown(), Ok(6));
+
+    // Shedding drops the oldest queued command instead of the new one.
+    let gate = slow::GATE.lock().unwrap();
+    let (sender, handle) = spawn_thread_runner_with_policy::<u32, u32, u32, slow::Slow>(
+        AdmissionPolicy::bounded(1, OverflowMode::ShedOldest),
+    );
+    let send = |command: u32| {
+        let sender = sender.clone();
+        std::thread::spawn(move || sender.send(command))
+    };
+    let started = slow::STARTED.load(Ordering::SeqCst);
+    let first = send(10);
+    slow::wait_for(|| slow::STARTED.load(Ordering::SeqCst) == started + 1);
+    let oldest = send(20);
+    slow::wait_for(|| sender.stats().queue_depth == 1);
+    let newest = send(30);
+    assert_eq!(oldest.join().unwrap(), Err(ThreadRunnerError::Shed));
+
+    drop(gate);
+    assert_eq!(first.join().unwrap(), Ok(vec![10]));
+    assert_eq!(newest.join().unwrap(), Ok(vec![30]));
+    assert_eq!(sender.stats().shed, 1);
+    assert_eq!(handle.shutdown(), Ok(40));
+}
+
+#[test]
+fn diff_projection_test() {
+    use bulb::*;
+
+    struct BulbStates;
+
+    impl Projection<Event> for BulbStates {
+        type View = State;
+
+        fn initial() -> State {
+            Bulb::initial_state()
+        }
+
+        fn apply(view: &State, event: &Event) -> State {
+            Bulb::evolve(view, event)
+        }
+    }
+
+    let key = |k: &str, e: Event| (k.to_string(), e);
+    let working = |status, remaining_uses| State::Working {
+        status,
+        remaining_uses,
+    };
+    let mut projection = InMemoryProjection::<KeyedProjection<BulbStates>, _>::new();
+    let views = Rc::new(RefCell::new(0));
+    let notified = views.clone();
+    projection.on_change(move |_| *notified.borrow_mut() += 1);
+
+    assert_eq!(
+        projection.apply_all_diff(&[
+            key("kitchen", Event::Fitted { max_uses: 1 }),
+            key("hall", Event::Fitted { max_uses: 5 }),
+            key("hall", Event::SwitchedOn),
+        ]),
+        vec![
+            ViewChange::Added("kitchen".to_string(), working(Status::Off, 1)),
+            ViewChange::Added("hall".to_string(), working(Status::On, 4)),
+        ]
+    );
+    assert_eq!(*views.borrow(), 3);
+
+    // The kitchen bulb is used up and blows, while the hall bulb is left alone.
+    projection.set_coalescing(true);
+    assert_eq!(
+        projection.apply_all_diff(&[
+            key("kitchen", Event::SwitchedOn),
+            key("kitchen", Event::SwitchedOff),
+            key("kitchen", Event::Blew),
+        ]),
+        vec![ViewChange::Updated("kitchen".to_string(), State::Blown)]
+    );
+    assert_eq!(*views.borrow(), 4);
+    // Events that leave a view as it was change nothing.
+    assert!(projection
+        .apply_diff(&key("kitchen", Event::SwitchedOn))
+        .is_empty());
+
+    assert_eq!(
+        merge_view_changes(vec![
+            ViewChange::Added("a", 1),
+            ViewChange::Updated("b", 2),
+            ViewChange::Removed("a"),
+            ViewChange::Removed("b"),
+            ViewChange::Removed("c"),
+            ViewChange::Added("c", 3),
+        ]),
+        vec![ViewChange::Removed("b"), ViewChange::Updated("c", 3)]
+    );
+    let old = HashMap::from([("a", 1), ("b", 2), ("c", 3)]);
+    let new = HashMap::from([("b", 2), ("c", 4), ("d", 5)]);
+    assert_eq!(
+        diff_maps(&old, &new),
+        vec![
+            ViewChange::Removed("a"),
+            ViewChange::Updated("c", 4),
+            ViewChange::Added("d", 5),
+        ]
+    );
+}
+
+#[test]
+fn upcasting_command_runner_test() {
+    use bulb::*;
+
+    /// The bulb's commands before bulbs were given a number of uses.
+    enum CommandV1 {
+        Install { lifetime_hours: u64 },
+        TurnOn,
+        TurnOff,
+        Replace,
+    }
+
+    struct V1ToCurrent;
+
+    impl CommandUpcaster<CommandV1, Command> for V1ToCurrent {
+        fn upcast(command: &CommandV1) -> Result<Command, MigrationError> {
+            match command {
+                // A use was assumed to last an hour.
+                CommandV1::Install { lifetime_hours } => Ok(Command::Fit {
+                    max_uses: *lifetime_hours,
+                }),
+                CommandV1::TurnOn => Ok(Command::SwitchOn),
+                CommandV1::TurnOff => Ok(Command::SwitchOff),
+                CommandV1::Replace => Err(MigrationError {
+                    reason: "bulbs can no longer be replaced".to_string(),
+                }),
+            }
+        }
+    }
+
+    let mut runner =
+        UpcastingCommandRunner::<_, V1ToCurrent>::new(InMemoryRunner::<_, _, _, Bulb>::new());
+    assert_eq!(
+        runner.command_old(&CommandV1::Install { lifetime_hours: 3 }),
+        Ok(vec![Event::Fitted { max_uses: 3 }])
+    );
+    assert_eq!(
+        runner.command_old(&CommandV1::TurnOn),
+        Ok(vec![Event::SwitchedOn])
+    );
+    // Old and current clients can be served side by side.
+    assert_eq!(
+        runner.command(&Command::SwitchOff),
+        vec![Event::SwitchedOff]
+    );
+
+    let error = runner.command_old(&CommandV1::Replace).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "[DEPRECATED_COMMAND] bulbs can no longer be replaced"
+    );
+    assert_eq!(runner.command_old(&CommandV1::TurnOff), Ok(vec![]));
+    assert_eq!(
+        *runner.get_state(),
+        State::Working {
+            status: Status::Off,
+            remaining_uses: 2
+        }
+    );
+}
+
+#[test]
+fn dyn_runner_test() {
+    use bulb::*;
+
+    /// A bulb whose number of uses comes from configuration rather than from the command.
+    struct ConfiguredBulb {
+        max_uses: u64,
+    }
+
+    impl DynDecider<Command, Event, State> for ConfiguredBulb {
+        fn decide(&self, command: &Command, state: &State) -> Vec<Event> {
+            match (command, state) {
+                (Command::Fit { .. }, State::NotFitted) => vec![Event::Fitted {
+                    max_uses: self.max_uses,
+                }],
+                // Unlike `Bulb`, a configured bulb ignores being fitted again.
+                (Command::Fit { .. }, _) => vec![],
+                _ => Bulb::decide(command, state),
+            }
+        }
+
+        fn evolve(&self, state: &State, event: &Event) -> State {
+            Bulb::evolve(state, event)
+        }
+
+        fn initial_state(&self) -> State {
+            Bulb::initial_state()
+        }
+
+        fn is_terminal(&self, state: &State) -> bool {
+            Bulb::is_terminal(state)
+        }
+    }
+
+    let select = |configured: bool| -> Box<dyn DynDecider<Command, Event, State>> {
+        if configured {
+            Box::new(ConfiguredBulb { max_uses: 1 })
+        } else {
+            Box::new(Statically::<Bulb>::new())
+        }
+    };
+
+    let mut runner = DynRunner::new(select(false));
+    assert_eq!(
+        runner.command(&Command::Fit { max_uses: 5 }),
+        vec![Event::Fitted { max_uses: 5 }]
+    );
+    assert_eq!(runner.command(&Command::SwitchOn), vec![Event::SwitchedOn]);
+    assert_eq!(
+        *runner.get_state(),
+        State::Working {
+            status: Status::On,
+            remaining_uses: 4
+        }
+    );
+
+    let mut runner = DynRunner::new(select(true));
+    assert_eq!(
+        runner.command(&Command::Fit { max_uses: 5 }),
+        vec![Event::Fitted { max_uses: 1 }]
+    );
+    assert_eq!(runner.command(&Command::Fit { max_uses: 5 }), vec![]);
+    assert_eq!(runner.command(&Command::SwitchOn), vec![Event::SwitchedOn]);
+    assert_eq!(
+        runner.command(&Command::SwitchOff),
+        vec![Event::SwitchedOff]
+    );
+    assert_eq!(runner.command(&Command::SwitchOn), vec![Event::Blew]);
+    assert!(runner.is_terminal());
+}
+
+#[test]
+fn consistency_token_test() {
+    use bulb::*;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    let mut registry = QueryRegistry::<u64>::new();
+    registry.register("switch_ons", |view| QueryResult::Number(*view as f64));
+
+    let log = Arc::new(Mutex::new(vec![]));
+    let mut runner = InMemoryRunner::<_, _, _, Bulb>::new();
+    runner.command_into_log(&Command::Fit { max_uses: 5 }, &mut log.lock().unwrap());
+    let (events, token) = runner.command_into_log(&Command::SwitchOn, &mut log.lock().unwrap());
+    assert_eq!(events, vec![Event::SwitchedOn]);
+    assert_eq!(token, ConsistencyToken::from(2));
+    assert_eq!(token.to_string().parse(), Ok(token));
+
+    // The projection lags behind until it catches up with the log.
+    let mut projection =
+        ProjectionRunner::<TotalSwitchOns, _, _>::new("switch_ons", InMemoryCheckpointStore::new());
+    assert_eq!(
+        projection.query_consistent(&registry, "switch_ons", "", Some(token)),
+        Err(QueryError::Stale(Stale {
+            required: token,
+            reached: GlobalPosition::from(0)
+        }))
+    );
+    assert_eq!(
+        projection.query_consistent(&registry, "switch_ons", "", None),
+        Ok(QueryResult::Number(0.0))
+    );
+    projection
+        .wait_for(token, Duration::from_secs(5), || log.lock().unwrap())
+        .unwrap();
+    assert_eq!(
+        projection.query_consistent(&registry, "switch_ons", "", Some(token)),
+        Ok(QueryResult::Number(1.0))
+    );
+
+    // A token for events that are only written later resolves once they are.
+    let writer = {
+        let log = log.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            runner.command_into_log(&Command::SwitchOff, &mut log.lock().unwrap());
+            runner.command_into_log(&Command::SwitchOn, &mut log.lock().unwrap())
+        })
+    };
+    let pending = ConsistencyToken::from(4);
+    projection
+        .wait_for(pending, Duration::from_secs(5), || log.lock().unwrap())
+        .unwrap();
+    assert_eq!(writer.join().unwrap().1, pending);
+    assert_eq!(*projection.view(), 2);
+
+    // A token the log never reaches times out.
+    assert_eq!(
+        projection.wait_for(ConsistencyToken::from(5), Duration::from_millis(20), || log
+            .lock()
+            .unwrap()),
+        Err(WaitError::Stale(Stale {
+            required: ConsistencyToken::from(5),
+            reached: GlobalPosition::from(4)
+        }))
+    );
+}
+
+#[test]
+fn decider_at_test() {
+    use Either::*;
+
+    struct IsAsleep;
+
+    impl InfallibleConverter<cat::State, bool> for IsAsleep {
+        fn convert(state: &cat::State) -> bool {
+            *state == cat::State::Asleep
+        }
+    }
+
+    struct Describe;
+
+    impl InfallibleConverter<(cat::State, bool), String> for Describe {
+        fn convert((state, asleep): &(cat::State, bool)) -> String {
+            format!("{state:?}, asleep: {asleep}")
+        }
+    }
+
+    type CatAt = Bridged<cat::Cat, cat::Command, cat::Event, cat::State, cat::State>;
+    type BulbAt = Bridged<bulb::Bulb, bulb::Command, bulb::Event, bulb::State, bulb::State>;
+
+    type SleepyCat = Mapped<CatAt, IsAsleep, bool>;
+    assert!(!SleepyCat::initial_state());
+    assert!(SleepyCat::evolve(
+        &cat::State::Awake,
+        &cat::Event::GotToSleep
+    ));
+
+    type CatReport = Map2<CatAt, SleepyCat, Describe, String>;
+    assert_eq!(CatReport::initial_state(), "Awake, asleep: false");
+    assert_eq!(
+        CatReport::decide(&cat::Command::GetToSleep, &cat::State::Awake),
+        [cat::Event::GotToSleep, cat::Event::GotToSleep]
+    );
+    assert_eq!(
+        CatReport::evolve(&cat::State::Awake, &cat::Event::GotToSleep),
+        "Asleep, asleep: true"
+    );
+
+    // Deciders that only implement `Decider` can be composed by bridging them.
+    type BulbAndLamp =
+        Composed<BulbAt, Bridged<lamp::Lamp, bulb::Command, bulb::Event, lamp::State, lamp::State>>;
+    let mut runner = InMemoryRunner::<_, _, _, BulbAndLamp>::new();
+    runner.command(&Left(bulb::Command::Fit { max_uses: 1 }));
+    runner.command(&Right(bulb::Command::Fit { max_uses: 1 }));
+    assert_eq!(
+        runner.command(&Left(bulb::Command::SwitchOn)),
+        [Left(bulb::Event::SwitchedOn)]
+    );
+    assert_eq!(
+        runner.command(&Right(bulb::Command::SwitchOn)),
+        [Right(bulb::Event::Blew)]
+    );
+    assert!(runner.get_state().1.blown);
+
+    // Every method is forwarded to the primary combinators, including evolving in place.
+    let mut state = BulbAndLamp::initial_state();
+    BulbAndLamp::evolve_in_place(&mut state, &Left(bulb::Event::Fitted { max_uses: 1 }));
+    assert_eq!(
+        state.0,
+        BulbAndLamp::evolve(
+            &BulbAndLamp::initial_state(),
+            &Left(bulb::Event::Fitted { max_uses: 1 })
+        )
+        .0
+    );
+    assert_eq!(
+        BulbAndLamp::decide_iter(&Left(bulb::Command::SwitchOff), &state).count(),
+        0
+    );
+}
+
+#[test]
+fn decider_macro_test() {
+    use switch::*;
+
+    assert_eq!(Switch::initial_state(), State::Off);
+    assert_eq!(Switch::fold(&[Event::Toggled]), State::On);
+    assert_eq!(Switch::fold(&[Event::Toggled, Event::Toggled]), State::Off);
+    assert_eq!(
+        Switch::decide(&Command::Press, &State::On),
+        [Event::Toggled]
+    );
+    assert_eq!(Switch::decide(&Command::Break, &State::On), [Event::Broke]);
+    assert!(Switch::decide(&Command::Press, &State::Broken).is_empty());
+    assert!(!Switch::is_terminal(&State::On));
+    assert!(Switch::is_terminal(&Switch::fold(&[Event::Broke])));
+
+    // Deciders written with the macro implement `DeciderAt`, so they compose without bridging.
+    type SwitchAndSwitch = Composed<Switch, Switch>;
+    assert_eq!(
+        SwitchAndSwitch::fold(&[Either::Left(Event::Toggled), Either::Right(Event::Broke)]),
+        (State::On, State::Broken)
+    );
+}
+
+#[test]
+fn process_router_test() {
+    use cat_light::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct RoutedCatLight;
+
+    impl RouterDefinition<Event, Command, State> for RoutedCatLight {
+        fn router() -> ProcessRouter<Event, Command, State> {
+            ProcessRouter::new(State::Idle)
+                .evolve_on(on_event(|e| matches!(e, Event::SwitchedOn)), |_, _| {
+                    State::WakingUp
+                })
+                .evolve_on(on_event_type("WokeUp"), |_, _| State::Idle)
+                .on(
+                    on_event(|e| matches!(e, Event::SwitchedOn)),
+                    |state, _| match state {
+                        State::WakingUp => vec![Command::WakeUp],
+                        State::Idle => vec![],
+                    },
+                )
+                .resume_with(|state| match state {
+                    State::WakingUp => vec![Command::WakeUp],
+                    State::Idle => vec![],
+                })
+                .terminal_when(|state| matches!(state, State::Idle))
+        }
+    }
+
+    type Routed = RoutedProcess<RoutedCatLight, Event, Command, State>;
+    assert_eq!(Routed::initial_state(), CatLight::initial_state());
+    for state in [State::Idle, State::WakingUp] {
+        assert_eq!(Routed::resume(&state), CatLight::resume(&state));
+        assert_eq!(Routed::is_terminal(&state), CatLight::is_terminal(&state));
+        for event in [Event::SwitchedOn, Event::WokeUp] {
+            assert_eq!(
+                Routed::evolve(&state, &event),
+                CatLight::evolve(&state, &event)
+            );
+            assert_eq!(
+                Routed::react(&state, &event),
+                CatLight::react(&state, &event)
+            );
+        }
+    }
+
+    static UNROUTED: AtomicUsize = AtomicUsize::new(0);
+    static BUILDS: AtomicUsize = AtomicUsize::new(0);
+
+    struct SwitchOnly;
+
+    impl RouterDefinition<Event, Command, State> for SwitchOnly {
+        fn router() -> ProcessRouter<Event, Command, State> {
+            BUILDS.fetch_add(1, Ordering::SeqCst);
+            ProcessRouter::new(State::Idle)
+                .on(on_event_type("SwitchedOn"), |_, _| vec![Command::WakeUp])
+                .unrouted(Unrouted::Report(|_| {
+                    UNROUTED.fetch_add(1, Ordering::SeqCst);
+                }))
+        }
+    }
+
+    type Partial = RoutedProcess<SwitchOnly, Event, Command, State>;
+    assert_eq!(
+        Partial::react(&State::Idle, &Event::SwitchedOn),
+        [Command::WakeUp]
+    );
+    assert_eq!(UNROUTED.load(Ordering::SeqCst), 0);
+    assert_eq!(Partial::evolve(&State::Idle, &Event::WokeUp), State::Idle);
+    assert_eq!(Partial::react(&State::Idle, &Event::WokeUp), []);
+    assert_eq!(UNROUTED.load(Ordering::SeqCst), 1);
+    assert!(!Partial::is_terminal(&State::Idle));
+    // The router is built once, and kept for every later call.
+    assert_eq!(BUILDS.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn fold_test() {
+    use bulb::*;
+
+    let lifecycle = [
+        Event::Fitted { max_uses: 1 },
+        Event::SwitchedOn,
+        Event::SwitchedOff,
+        Event::Blew,
+    ];
+    assert_eq!(Bulb::fold(&[]), State::NotFitted);
+    assert_eq!(
+        Bulb::fold(&lifecycle[..2]),
+        State::Working {
+            status: Status::On,
+            remaining_uses: 0
+        }
+    );
+    assert_eq!(Bulb::fold(&lifecycle), State::Blown);
+
+    // Folding the rest of the events onto a snapshot gives the same state.
+    let snapshot = Bulb::fold(&lifecycle[..1]);
+    assert_eq!(
+        fold_events::<_, _, _, _, Bulb>(snapshot, &lifecycle[1..]),
+        State::Blown
+    );
+    assert_eq!(fold_events::<_, _, _, _, Bulb>(snapshot, &[]), snapshot);
+
+    let runner = InMemoryRunner::<_, _, _, Bulb>::from_events(&lifecycle[..3]);
+    assert_eq!(
+        *runner.get_state(),
+        State::Working {
+            status: Status::Off,
+            remaining_uses: 0
+        }
+    );
+}
+
+/// A bulb event along with the time, in seconds, it happened at.
+#[derive(Debug)]
+struct Stamped(u64, bulb::Event);
+
+/// Counts bulb events per hour, keeping the last two hours.
+struct Hourly;
+
+impl TimeBucketDefinition<Stamped> for Hourly {
+    const BUCKET_WIDTH: u64 = 3600;
+    const RETENTION: u64 = 2;
+
+    fn timestamp(event: &Stamped) -> Option<u64> {
+        Some(event.0)
+    }
+
+    fn event_type(event: &Stamped) -> String {
+        event_type_name(&event.1)
+    }
+}
+
+/// A bulb's events over three hours.
+fn stamped_bulb_events() -> Vec<Stamped> {
+    vec![
+        Stamped(100, bulb::Event::Fitted { max_uses: 5 }),
+        Stamped(200, bulb::Event::SwitchedOn),
+        Stamped(300, bulb::Event::SwitchedOff),
+        Stamped(3700, bulb::Event::SwitchedOn),
+        Stamped(4000, bulb::Event::SwitchedOff),
+        Stamped(7300, bulb::Event::SwitchedOn),
+    ]
+}
+
+#[test]
+fn time_bucketed_projection_test() {
+    type Buckets = TimeBucketedProjection<Hourly>;
+    let events = stamped_bulb_events();
+
+    let mut runner =
+        ProjectionRunner::<Buckets, _, _>::new("hourly", InMemoryCheckpointStore::new());
+    runner.catch_up(&events[..5]).unwrap();
+    let view = runner.view();
+    assert_eq!(view.buckets().collect::<Vec<_>>(), [0, 3600]);
+    assert_eq!(view.count(0, "SwitchedOn"), 1);
+    assert_eq!(view.count(0, "Blew"), 0);
+    assert_eq!(view.total(0), 3);
+    assert_eq!(view.count(3600, "SwitchedOff"), 1);
+
+    // The third hour pushes the first one out of the retained buckets.
+    runner.catch_up(&events).unwrap();
+    let whole = runner.view().clone();
+    assert_eq!(whole.buckets().collect::<Vec<_>>(), [3600, 7200]);
+    assert_eq!(whole.total(0), 0);
+    assert_eq!(whole.total(3600), 2);
+    assert_eq!(whole.count(7200, "SwitchedOn"), 1);
+
+    // Views of shards of the stream merge into the view of the whole stream, in any grouping.
+    let shard = |n: usize| {
+        events
+            .iter()
+            .skip(n)
+            .step_by(3)
+            .fold(Buckets::initial(), |view, e| Buckets::apply(&view, e))
+    };
+    let (a, b, c) = (shard(0), shard(1), shard(2));
+    let left = Buckets::merge(&Buckets::merge(&a, &b), &c);
+    let right = Buckets::merge(&a, &Buckets::merge(&b, &c));
+    assert_eq!(left, right);
+    assert_eq!(left, whole);
+
+    // A retention of 0 keeps every bucket.
+    struct Forever;
+
+    impl TimeBucketDefinition<Stamped> for Forever {
+        const BUCKET_WIDTH: u64 = 3600;
+        const RETENTION: u64 = 0;
+
+        fn timestamp(event: &Stamped) -> Option<u64> {
+            Some(event.0)
+        }
+    }
+
+    let view = events
+        .iter()
+        .fold(TimeBucketedProjection::<Forever>::initial(), |view, e| {
+            TimeBucketedProjection::<Forever>::apply(&view, e)
+        });
+    assert_eq!(view.buckets().collect::<Vec<_>>(), [0, 3600, 7200]);
+    assert_eq!(view.total(0), 3);
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_string(&whole).unwrap();
+        assert_eq!(
+            serde_json::from_str::<deciders_rs::projections::BucketCounts>(&json).unwrap(),
+            whole
+        );
+    }
+}
+
+#[test]
+fn decide_and_evolve_test() {
+    use bulb::*;
+
+    let working = State::Working {
+        status: Status::Off,
+        remaining_uses: 2,
+    };
+    assert_eq!(
+        Bulb::decide_and_evolve(&Command::SwitchOn, &working),
+        (
+            vec![Event::SwitchedOn],
+            State::Working {
+                status: Status::On,
+                remaining_uses: 1
+            }
+        )
+    );
+    // A command that generates no events leaves the state as it was.
+    assert_eq!(
+        Bulb::decide_and_evolve(&Command::SwitchOff, &working),
+        (vec![], working)
+    );
+
+    let mut runner = InMemoryRunner::<_, _, _, Bulb>::with_state(working);
+    let mut state = working;
+    for command in [Command::SwitchOn, Command::SwitchOff, Command::SwitchOff] {
+        let (events, new_state) = Bulb::decide_and_evolve(&command, &state);
+        assert_eq!(runner.command(&command), events);
+        assert_eq!(*runner.get_state(), new_state);
+        state = new_state;
+    }
+}
+
+#[test]
+fn try_decider_test() {
+    use bulb::*;
+
+    #[derive(Debug, PartialEq)]
+    enum BulbError {
+        Blown,
+    }
+
+    struct StrictBulb;
+
+    impl TryDecider<Command, Event, State, State> for StrictBulb {
+        type Error = BulbError;
+
+        fn decide(command: &Command, state: &State) -> Result<Vec<Event>, BulbError> {
+            match (command, state) {
+                (Command::SwitchOn, State::Blown) => Err(BulbError::Blown),
+                _ => Ok(Bulb::decide(command, state)),
+            }
+        }
+
+        fn evolve(state: &State, event: &Event) -> State {
+            Bulb::evolve(state, event)
+        }
+
+        fn initial_state() -> State {
+            Bulb::initial_state()
+        }
+
+        fn is_terminal(state: &State) -> bool {
+            Bulb::is_terminal(state)
+        }
+    }
+
+    let mut runner = FallibleInMemoryRunner::<_, _, _, StrictBulb>::new();
+    assert_eq!(
+        runner.command(&Command::Fit { max_uses: 0 }),
+        Ok(vec![Event::Fitted { max_uses: 0 }])
+    );
+    assert_eq!(runner.command(&Command::SwitchOn), Ok(vec![Event::Blew]));
+    assert_eq!(*runner.get_state(), State::Blown);
+    // A rejected command leaves the state as it was.
+    assert_eq!(runner.command(&Command::SwitchOn), Err(BulbError::Blown));
+    assert_eq!(*runner.get_state(), State::Blown);
+
+    // Infallible deciders can be lifted, and never reject a command.
+    let mut lifted = FallibleInMemoryRunner::<_, _, _, Lifted<Bulb>>::with_state(State::Blown);
+    assert_eq!(lifted.command(&Command::SwitchOn), Ok(vec![]));
+
+    // Errors from composed deciders say which side rejected the command.
+    type Strict = ComposedDeciders<
+        Lifted<cat::Cat>,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        StrictBulb,
+        Command,
+        Event,
+        State,
+    >;
+    let mut composed =
+        FallibleInMemoryRunner::<_, _, _, Strict>::with_state((cat::State::Asleep, State::Blown));
+    assert_eq!(
+        composed.command(&Either::Left(cat::Command::WakeUp)),
+        Ok(vec![Either::Left(cat::Event::WokeUp)])
+    );
+    match composed.command(&Either::Right(Command::SwitchOn)) {
+        Err(Either::Right(error)) => assert_eq!(error, BulbError::Blown),
+        other => panic!("expected the bulb to reject the command, got {:?}", other),
+    }
+    assert_eq!(*composed.get_state(), (cat::State::Awake, State::Blown));
+}
+
+#[test]
+fn state_space_monitor_test() {
+    use bulb::*;
+
+    let fitted = State::Working {
+        status: Status::Off,
+        remaining_uses: 4,
+    };
+    let graph = viz::explore::<_, _, _, Bulb>(&fitted, &[Command::SwitchOn, Command::SwitchOff], 6);
+    let known: FingerprintSet = graph.fingerprints();
+    assert_eq!(known.len(), graph.nodes.len());
+    // Six steps are not enough to use up all four switch-ons of the bulb.
+    let used_up = State::Working {
+        status: Status::On,
+        remaining_uses: 0,
+    };
+    assert!(!known.contains(used_up.fingerprint()));
+
+    let escapes = Rc::new(RefCell::new(vec![]));
+    let mut monitor = StateSpaceMonitor::new(InMemoryRunner::<_, _, _, Bulb>::new(), known);
+    let recorded = escapes.clone();
+    monitor.on_escape(move |escape| recorded.borrow_mut().push(escape.clone()));
+    for command in [
+        Command::Fit { max_uses: 4 },
+        Command::SwitchOn,
+        Command::SwitchOff,
+    ] {
+        assert!(monitor.command(&command).is_ok());
+    }
+    assert!(escapes.borrow().is_empty());
+
+    // A bulb fitted with a single use runs out after being switched on, which was never explored.
+    let runner = InMemoryRunner::<_, _, _, Bulb>::with_state(State::NotFitted);
+    let mut monitor = StateSpaceMonitor::new(runner, graph.fingerprints());
+    let recorded = escapes.clone();
+    monitor.on_escape(move |escape| recorded.borrow_mut().push(escape.clone()));
+    monitor.command(&Command::Fit { max_uses: 1 }).unwrap();
+    assert!(escapes.borrow().is_empty());
+    assert_eq!(
+        monitor.command(&Command::SwitchOn).unwrap(),
+        vec![Event::SwitchedOn]
+    );
+    assert_eq!(*monitor.get_state(), used_up);
+    assert_eq!(escapes.borrow().len(), 1);
+    let escape = escapes.borrow()[0].clone();
+    assert_eq!(escape.event, Event::SwitchedOn);
+    assert_eq!(escape.fingerprint, used_up.fingerprint());
+
+    // In strict mode, the command is rejected instead.
+    let runner = InMemoryRunner::<_, _, _, Bulb>::with_state(State::NotFitted);
+    let mut monitor = StateSpaceMonitor::new(runner, graph.fingerprints());
+    monitor.set_strict(true);
+    monitor.command(&Command::Fit { max_uses: 1 }).unwrap();
+    let error = monitor.command(&Command::SwitchOn).unwrap_err();
+    assert_eq!(error.fingerprint, used_up.fingerprint());
+    assert!(error.to_string().contains("SwitchOn / SwitchedOn"));
+    assert_eq!(
+        *monitor.get_state(),
+        State::Working {
+            status: Status::Off,
+            remaining_uses: 1
+        }
+    );
+
+    #[cfg(feature = "serde")]
+    {
+        let baked = serde_json::to_string(monitor.known()).unwrap();
+        let loaded: FingerprintSet = serde_json::from_str(&baked).unwrap();
+        assert_eq!(&loaded, monitor.known());
+    }
+}
+
+#[test]
+fn rejecting_decider_test() {
+    use bulb::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Rejection {
+        AlreadyFitted,
+    }
+
+    impl Validator<Command, Rejection, State> for Bulb {
+        fn validate(command: &Command, state: &State) -> Result<(), Rejection> {
+            match (command, state) {
+                (Command::Fit { .. }, State::NotFitted) => Ok(()),
+                (Command::Fit { .. }, _) => Err(Rejection::AlreadyFitted),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    let mut runner = InMemoryRunner::<_, _, _, RejectingDecider<Bulb, Rejection>>::new();
+    assert_eq!(
+        runner.command_with_rejections(&Command::Fit { max_uses: 2 }),
+        (vec![Event::Fitted { max_uses: 2 }], vec![])
+    );
+    let fitted = State::Working {
+        status: Status::Off,
+        remaining_uses: 2,
+    };
+    assert_eq!(*runner.get_state(), fitted);
+    // Fitting a bulb twice is rejected instead of panicking, and leaves the state unchanged.
+    assert_eq!(
+        runner.command_with_rejections(&Command::Fit { max_uses: 5 }),
+        (vec![], vec![Rejection::AlreadyFitted])
+    );
+    assert_eq!(*runner.get_state(), fitted);
+    // Commands with nothing to do are still told apart from rejected ones.
+    assert_eq!(
+        runner.command_with_rejections(&Command::SwitchOff),
+        (vec![], vec![])
+    );
+
+    // Rejections are output alongside events, but never evolved.
+    let events =
+        RejectingDecider::<Bulb, Rejection>::decide(&Command::Fit { max_uses: 5 }, &fitted);
+    assert_eq!(events, vec![Either::Right(Rejection::AlreadyFitted)]);
+    assert_eq!(
+        RejectingDecider::<Bulb, Rejection>::evolve(&fitted, &events[0]),
+        fitted
+    );
+}
+
+#[test]
+fn context_decider_test() {
+    use std::cell::Cell;
+
+    struct Clock {
+        now: u64,
+    }
+
+    enum Command {
+        Reserve { for_seconds: u64 },
+        CheckTimeout,
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Reserved { deadline: u64 },
+        TimedOut,
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum State {
+        Free,
+        Reserved { deadline: u64 },
+        Expired,
+    }
+
+    struct Reservation;
+
+    impl ContextDecider<Clock, Command, Event, State, State> for Reservation {
+        fn decide(command: &Command, state: &State, clock: &Clock) -> Vec<Event> {
+            match (command, state) {
+                (Command::Reserve { for_seconds }, State::Free) => vec![Event::Reserved {
+                    deadline: clock.now + for_seconds,
+                }],
+                (Command::CheckTimeout, State::Reserved { deadline }) if clock.now > *deadline => {
+                    vec![Event::TimedOut]
+                }
+                _ => vec![],
+            }
+        }
+
+        fn evolve(state: &State, event: &Event) -> State {
+            match (state, event) {
+                (State::Free, Event::Reserved { deadline }) => State::Reserved {
+                    deadline: *deadline,
+                },
+                (State::Reserved { .. }, Event::TimedOut) => State::Expired,
+                _ => *state,
+            }
+        }
+
+        fn initial_state() -> State {
+            State::Free
+        }
+
+        fn is_terminal(state: &State) -> bool {
+            *state == State::Expired
+        }
+    }
+
+    thread_local! {
+        static NOW: Cell<u64> = const { Cell::new(0) };
+    }
+
+    struct FakeClock;
+
+    impl ContextProvider<Clock> for FakeClock {
+        fn context() -> Clock {
+            Clock {
+                now: NOW.with(Cell::get),
+            }
+        }
+    }
+
+    type Reservations = ProvidedContext<Reservation, FakeClock, Clock>;
+
+    let mut runner = InMemoryRunner::<_, _, _, Reservations>::new();
+    NOW.with(|now| now.set(100));
+    assert_eq!(
+        runner.command(&Command::Reserve { for_seconds: 900 }),
+        vec![Event::Reserved { deadline: 1000 }]
+    );
+    NOW.with(|now| now.set(1000));
+    assert_eq!(runner.command(&Command::CheckTimeout), vec![]);
+    NOW.with(|now| now.set(1001));
+    assert_eq!(
+        runner.command(&Command::CheckTimeout),
+        vec![Event::TimedOut]
+    );
+    assert!(Reservations::is_terminal(runner.get_state()));
+
+    // The context can also be given explicitly, bypassing the provider.
+    let mut runner = InMemoryRunner::<_, _, _, Reservations>::new();
+    runner.command_with_context(&Command::Reserve { for_seconds: 60 }, &Clock { now: 0 });
+    assert_eq!(
+        runner.command_with_context(&Command::CheckTimeout, &Clock { now: 60 }),
+        vec![]
+    );
+    assert_eq!(*runner.get_state(), State::Reserved { deadline: 60 });
+    assert_eq!(
+        runner.command_with_context(&Command::CheckTimeout, &Clock { now: 61 }),
+        vec![Event::TimedOut]
+    );
+    assert_eq!(*runner.get_state(), State::Expired);
+}
+
+#[test]
+fn evolve_owned_test() {
+    use std::cell::Cell;
+
+    thread_local! {
+        static CLONES: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// A large state that counts how many times it has been copied.
+    #[derive(Debug, Default, PartialEq)]
+    struct Samples(Vec<u64>);
+
+    impl Clone for Samples {
+        fn clone(&self) -> Self {
+            CLONES.with(|clones| clones.set(clones.get() + 1));
+            Samples(self.0.clone())
+        }
+    }
+
+    struct Recorder;
+
+    impl Decider<u64, u64, Samples, Samples> for Recorder {
+        fn decide(sample: &u64, _state: &Samples) -> Vec<u64> {
+            vec![*sample]
+        }
+
+        fn evolve(state: &Samples, sample: &u64) -> Samples {
+            Self::evolve_owned(state.clone(), sample)
+        }
+
+        fn initial_state() -> Samples {
+            Samples(vec![])
+        }
+
+        fn is_terminal(_state: &Samples) -> bool {
+            false
+        }
+
+        fn evolve_owned(mut state: Samples, sample: &u64) -> Samples {
+            state.0.push(*sample);
+            state
+        }
+
+        fn evolve_in_place(state: &mut Samples, sample: &u64) {
+            state.0.push(*sample);
+        }
+    }
+
+    let events: Vec<u64> = (0..50_000).collect();
+    let replayed = Recorder::fold(&events);
+    assert_eq!(replayed.0.len(), 50_000);
+    assert_eq!(CLONES.with(Cell::get), 0);
+
+    let mut runner = InMemoryRunner::<_, _, _, Recorder>::from_events(&events);
+    for sample in 0..1000 {
+        runner.command(&sample);
+    }
+    assert_eq!(runner.get_state().0.len(), 51_000);
+    assert_eq!(CLONES.with(Cell::get), 0);
+
+    // Wrappers forward the in-place evolution, so they do not copy the state either.
+    let mut runner = InMemoryRunner::<_, _, _, CountingDecider<TerminalGuard<Recorder>>>::new();
+    runner.command(&1);
+    let mut runner =
+        InMemoryRunner::<_, _, _, ComposedDeciders<Recorder, _, _, _, Recorder, _, _, _>>::new();
+    runner.command(&Either::Left(1));
+    runner.command(&Either::Right(2));
+    assert_eq!(runner.get_state().1 .0, vec![2]);
+    let mut runner = FallibleInMemoryRunner::<_, _, _, Lifted<Recorder>>::new();
+    assert_eq!(runner.command(&3), Ok(vec![3]));
+    assert_eq!(CLONES.with(Cell::get), 0);
+
+    struct ContextRecorder;
+
+    impl ContextDecider<(), u64, u64, Samples, Samples> for ContextRecorder {
+        fn decide(sample: &u64, state: &Samples, _context: &()) -> Vec<u64> {
+            Recorder::decide(sample, state)
+        }
+
+        fn evolve(state: &Samples, sample: &u64) -> Samples {
+            Recorder::evolve(state, sample)
+        }
+
+        fn initial_state() -> Samples {
+            Recorder::initial_state()
+        }
+
+        fn is_terminal(state: &Samples) -> bool {
+            Recorder::is_terminal(state)
+        }
+
+        fn evolve_owned(state: Samples, sample: &u64) -> Samples {
+            Recorder::evolve_owned(state, sample)
+        }
+
+        fn evolve_in_place(state: &mut Samples, sample: &u64) {
+            Recorder::evolve_in_place(state, sample)
+        }
+    }
+
+    struct NoContext;
+
+    impl ContextProvider<()> for NoContext {
+        fn context() {}
+    }
+
+    #[derive(Default)]
+    struct Station {
+        samples: Samples,
+    }
+
+    struct SamplesLens;
+
+    impl Lens<Station, Samples> for SamplesLens {
+        fn get(station: &Station) -> Samples {
+            station.samples.clone()
+        }
+
+        fn set(_station: &Station, samples: Samples) -> Station {
+            Station { samples }
+        }
+
+        fn update(station: &mut Station, update: impl FnOnce(&mut Samples)) {
+            update(&mut station.samples)
+        }
+    }
+
+    let mut runner =
+        InMemoryRunner::<_, _, _, ProvidedContext<ContextRecorder, NoContext, ()>>::new();
+    runner.command(&4);
+    type RecorderThenRecorder = SequencedDecider<
+        Recorder,
+        u64,
+        u64,
+        Samples,
+        Recorder,
+        u64,
+        u64,
+        Samples,
+        InitialStateOf<Recorder, u64, u64>,
+    >;
+    let mut runner = InMemoryRunner::<_, _, _, RecorderThenRecorder>::new();
+    runner.command(&Either::Left(5));
+    assert!(matches!(runner.get_state(), Phase::Phase1(Samples(samples)) if samples == &[5]));
+    // Deciding reads the part through the lens, but evolving updates it where it is.
+    let mut station = Station::default();
+    ZoomedDecider::<Recorder, SamplesLens, Samples>::evolve_in_place(&mut station, &6);
+    assert_eq!(station.samples.0, vec![6]);
+    // Events that cannot be converted leave the state where it is.
+    struct EvenOnly;
+
+    impl FallibleConverter<u64, u64> for EvenOnly {
+        fn convert(sample: &u64) -> Option<u64> {
+            sample.is_multiple_of(2).then_some(*sample)
+        }
+    }
+
+    impl InfallibleConverter<u64, u64> for EvenOnly {
+        fn convert(sample: &u64) -> u64 {
+            *sample
+        }
+    }
+
+    type EvenRecorder = MappedEventDecider<Recorder, EvenOnly, EvenOnly, u64>;
+    let mut samples = Samples::default();
+    EvenRecorder::evolve_in_place(&mut samples, &8);
+    EvenRecorder::evolve_in_place(&mut samples, &9);
+    assert_eq!(samples.0, vec![8]);
+    let mut runner = DynRunner::new(Box::new(Statically::<Recorder>::new()));
+    runner.command(&7);
+    assert_eq!(runner.get_state().0, vec![7]);
+    assert_eq!(CLONES.with(Cell::get), 0);
+
+    // Wrappers that convert the state evolve the same way whether the state is borrowed, owned or
+    // updated in place.
+    struct Identity;
+
+    impl InfallibleConverter<Samples, Samples> for Identity {
+        fn convert(samples: &Samples) -> Samples {
+            samples.clone()
+        }
+    }
+
+    impl FallibleConverter<Samples, Samples> for Identity {
+        fn convert(samples: &Samples) -> Option<Samples> {
+            Some(samples.clone())
+        }
+    }
+
+    impl InfallibleConverter<(Samples, Samples), Samples> for Identity {
+        fn convert((first, second): &(Samples, Samples)) -> Samples {
+            Samples(first.0.iter().chain(&second.0).copied().collect())
+        }
+    }
+
+    impl FallibleConverter<u64, u64> for Identity {
+        fn convert(sample: &u64) -> Option<u64> {
+            Some(*sample)
+        }
+    }
+
+    impl InfallibleConverter<u64, u64> for Identity {
+        fn convert(sample: &u64) -> u64 {
+            *sample
+        }
+    }
+
+    fn assert_same_evolution<D>(state: Samples, sample: u64)
+    where
+        D: Decider<u64, u64, Samples, Samples>,
+    {
+        let expected = D::evolve(&state, &sample);
+        assert_eq!(D::evolve_owned(state.clone(), &sample), expected);
+        let mut in_place = state;
+        D::evolve_in_place(&mut in_place, &sample);
+        assert_eq!(in_place, expected);
+    }
+
+    let state = Samples(vec![1, 2]);
+    assert_same_evolution::<MappedDecider<Recorder, Identity, _, _, _, _, _>>(state.clone(), 3);
+    assert_same_evolution::<DimappedDecider<Recorder, Identity, Identity, Samples, Samples>>(
+        state.clone(),
+        3,
+    );
+    assert_same_evolution::<
+        TryAdaptedDecider<
+            Recorder,
+            Identity,
+            Identity,
+            Identity,
+            Identity,
+            Identity,
+            u64,
+            u64,
+            u64,
+            u64,
+            Samples,
+            Samples,
+        >,
+    >(state.clone(), 3);
+    assert_same_evolution::<Map2Deciders<Recorder, Recorder, Identity, _, _, _, _, _, _>>(
+        state.clone(),
+        3,
+    );
+
+    // `ManyDecider` only updates the state the event belongs to, with the same result.
+    let mut cats = HashMap::new();
+    cats.insert("a".to_string(), cat::State::Asleep);
+    let events = [("a".to_string(), cat::Event::WokeUp)];
+    let owned = ManyDecider::<cat::Cat, _, _, _>::evolve_owned(cats.clone(), &events[0]);
+    assert_eq!(
+        owned,
+        ManyDecider::<cat::Cat, _, _, _>::evolve(&cats, &events[0])
+    );
+    assert_eq!(owned["a"], cat::State::Awake);
+}
+
+#[test]
+fn evolve_in_place_test() {
+    use bulb::*;
+
+    let states = [
+        State::NotFitted,
+        State::Working {
+            status: Status::Off,
+            remaining_uses: 2,
+        },
+        State::Working {
+            status: Status::On,
+            remaining_uses: 1,
+        },
+        State::Blown,
+    ];
+    let events = [
+        Event::Fitted { max_uses: 3 },
+        Event::SwitchedOn,
+        Event::SwitchedOff,
+        Event::Blew,
+    ];
+    for state in states {
+        for event in events {
+            let mut in_place = state;
+            Bulb::evolve_in_place(&mut in_place, &event);
+            assert_eq!(in_place, Bulb::evolve(&state, &event));
+        }
+    }
+
+    // The runner, which evolves in place, ends up in the same state as folding the events.
+    let mut runner = InMemoryRunner::<_, _, _, Bulb>::new();
+    let mut log = vec![];
+    for command in [
+        Command::Fit { max_uses: 2 },
+        Command::SwitchOn,
+        Command::SwitchOff,
+        Command::SwitchOn,
+        Command::SwitchOff,
+        Command::SwitchOn,
+    ] {
+        log.extend(runner.command(&command));
+    }
+    assert_eq!(*runner.get_state(), State::Blown);
+    assert_eq!(*runner.get_state(), Bulb::fold(&log));
+
+    // `ManyDecider` only touches the entry of the decider the event belongs to.
+    type Bulbs = ManyDecider<Bulb, Command, Event, State>;
+    let mut bulbs = HashMap::from([("kitchen".to_string(), State::Blown)]);
+    let event = ("hall".to_string(), Event::Fitted { max_uses: 1 });
+    let expected = Bulbs::evolve(&bulbs, &event);
+    Bulbs::evolve_in_place(&mut bulbs, &event);
+    assert_eq!(bulbs, expected);
+    assert_eq!(bulbs["kitchen"], State::Blown);
+
+    // By default, evolving in place never needs the initial state, and leaves the state unchanged
+    // if evolving panics.
+    struct Fragile;
+
+    impl Decider<(), u8, u8, u8> for Fragile {
+        fn decide(_command: &(), _state: &u8) -> Vec<u8> {
+            vec![]
+        }
+
+        fn evolve(state: &u8, event: &u8) -> u8 {
+            assert_ne!(*event, 0, "zero is not a valid event");
+            state + event
+        }
+
+        fn initial_state() -> u8 {
+            panic!("the initial state should not be needed")
+        }
+
+        fn is_terminal(_state: &u8) -> bool {
+            false
+        }
+    }
+
+    let mut state = 1;
+    Fragile::evolve_in_place(&mut state, &2);
+    assert_eq!(state, 3);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        Fragile::evolve_in_place(&mut state, &0)
+    }));
+    assert!(result.is_err());
+    assert_eq!(state, 3);
+}
+
+#[test]
+fn decide_iter_test() {
+    use Either::*;
+
+    struct SwitchConverter;
+    impl FallibleConverter<bool, bulb::Command> for SwitchConverter {
+        fn convert(on: &bool) -> Option<bulb::Command> {
+            Some(if *on {
+                bulb::Command::SwitchOn
+            } else {
+                bulb::Command::SwitchOff
+            })
+        }
+    }
+
+    struct SameEvent;
+    impl FallibleConverter<bulb::Event, bulb::Event> for SameEvent {
+        fn convert(event: &bulb::Event) -> Option<bulb::Event> {
+            Some(*event)
+        }
+    }
+    impl InfallibleConverter<bulb::Event, bulb::Event> for SameEvent {
+        fn convert(event: &bulb::Event) -> bulb::Event {
+            *event
+        }
+    }
+
+    struct SameState;
+    impl InfallibleConverter<bulb::State, bulb::State> for SameState {
+        fn convert(state: &bulb::State) -> bulb::State {
+            *state
+        }
+    }
+
+    type Switch = AdaptedDecider<
+        bulb::Bulb,
+        SwitchConverter,
+        SameEvent,
+        SameEvent,
+        SameState,
+        bulb::Event,
+        bulb::Event,
+        bool,
+        bulb::Command,
+        bulb::State,
+        bulb::State,
+    >;
+    type MappedSwitch =
+        MappedDecider<Switch, SameState, bool, bulb::Event, bulb::State, bulb::State, bulb::State>;
+    type CatAndSwitch = ComposedDeciders<
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        MappedSwitch,
+        bool,
+        bulb::Event,
+        bulb::State,
+    >;
+
+    let state = (
+        cat::State::Asleep,
+        bulb::State::Working {
+            status: bulb::Status::Off,
+            remaining_uses: 1,
+        },
+    );
+    let cases = [
+        (Left(cat::Command::WakeUp), vec![Left(cat::Event::WokeUp)]),
+        (Left(cat::Command::GetToSleep), vec![]),
+        (Right(true), vec![Right(bulb::Event::SwitchedOn)]),
+        (Right(false), vec![]),
+    ];
+    for (command, expected) in cases {
+        assert_eq!(CatAndSwitch::decide(&command, &state), expected);
+        assert_eq!(
+            CatAndSwitch::decide_iter(&command, &state).collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    // Adapters convert the events as they are iterated over, without collecting them, so even an
+    // endless stream of events can be decided through them.
+    struct Endless;
+    impl Decider<bulb::Command, bulb::Event, bulb::State, bulb::State> for Endless {
+        fn decide(_command: &bulb::Command, _state: &bulb::State) -> Vec<bulb::Event> {
+            unreachable!("the events are only decided as an iterator")
+        }
+
+        fn decide_iter(
+            _command: &bulb::Command,
+            _state: &bulb::State,
+        ) -> impl Iterator<Item = bulb::Event> + use<> {
+            std::iter::repeat(bulb::Event::SwitchedOn)
+        }
+
+        fn evolve(state: &bulb::State, _event: &bulb::Event) -> bulb::State {
+            *state
+        }
+
+        fn initial_state() -> bulb::State {
+            bulb::State::NotFitted
+        }
+
+        fn is_terminal(_state: &bulb::State) -> bool {
+            false
+        }
+    }
+
+    type EndlessSwitch = AdaptedDecider<
+        Endless,
+        SwitchConverter,
+        SameEvent,
+        SameEvent,
+        SameState,
+        bulb::Event,
+        bulb::Event,
+        bool,
+        bulb::Command,
+        bulb::State,
+        bulb::State,
+    >;
+    let events: Vec<_> = EndlessSwitch::decide_iter(&true, &bulb::State::NotFitted)
+        .take(3)
+        .collect();
+    assert_eq!(events, [bulb::Event::SwitchedOn; 3]);
+}
+
+#[test]
+fn decide_all_test() {
+    use bulb::*;
+
+    let lifecycle = [
+        Command::Fit { max_uses: 2 },
+        Command::SwitchOn,
+        Command::SwitchOff,
+        Command::SwitchOn,
+        Command::SwitchOff,
+        Command::SwitchOn,
+        // The bulb has blown, so these are decided against the terminal state.
+        Command::SwitchOn,
+        Command::SwitchOff,
+    ];
+    let expected = vec![
+        vec![Event::Fitted { max_uses: 2 }],
+        vec![Event::SwitchedOn],
+        vec![Event::SwitchedOff],
+        vec![Event::SwitchedOn],
+        vec![Event::SwitchedOff],
+        vec![Event::Blew],
+        vec![],
+        vec![],
+    ];
+    assert_eq!(Bulb::decide_all(&lifecycle, &State::NotFitted), expected);
+
+    let mut runner = InMemoryRunner::<_, _, _, Bulb>::new();
+    assert_eq!(runner.commands(&lifecycle), expected);
+    assert_eq!(*runner.get_state(), State::Blown);
+    assert_eq!(runner.commands(&[]), Vec::<Vec<Event>>::new());
+}
+
+#[test]
+fn terminal_guard_test() {
+    use bulb::*;
+
+    /// A bulb that still lights up after it has blown.
+    struct LeakyBulb;
+
+    impl Decider<Command, Event, State, State> for LeakyBulb {
+        fn decide(command: &Command, state: &State) -> Vec<Event> {
+            match (command, state) {
+                (Command::SwitchOn, State::Blown) => vec![Event::SwitchedOn],
+                _ => Bulb::decide(command, state),
+            }
+        }
+
+        fn evolve(state: &State, event: &Event) -> State {
+            Bulb::evolve(state, event)
+        }
+
+        fn initial_state() -> State {
+            Bulb::initial_state()
+        }
+
+        fn is_terminal(state: &State) -> bool {
+            Bulb::is_terminal(state)
+        }
+    }
+
+    assert_eq!(
+        LeakyBulb::decide(&Command::SwitchOn, &State::Blown),
+        vec![Event::SwitchedOn]
+    );
+
+    let mut runner = InMemoryRunner::<_, _, _, TerminalGuard<LeakyBulb>>::new();
+    runner.command(&Command::Fit { max_uses: 0 });
+    assert_eq!(runner.command(&Command::SwitchOn), vec![Event::Blew]);
+    assert_eq!(runner.command(&Command::SwitchOn), vec![]);
+    assert_eq!(runner.command(&Command::SwitchOn), vec![]);
+    assert_eq!(*runner.get_state(), State::Blown);
+
+    let mut strict = FallibleInMemoryRunner::<_, _, _, StrictTerminalGuard<LeakyBulb>>::new();
+    strict.command(&Command::Fit { max_uses: 0 }).unwrap();
+    assert_eq!(strict.command(&Command::SwitchOn), Ok(vec![Event::Blew]));
+    assert_eq!(strict.command(&Command::SwitchOn), Err(TerminalError));
+    assert_eq!(*strict.get_state(), State::Blown);
+
+    // A process that pings after every pong until it has seen two of them.
+    struct Pinger;
+
+    impl Process<&'static str, &'static str, u8> for Pinger {
+        fn evolve(pongs: &u8, _event: &&'static str) -> u8 {
+            pongs + 1
+        }
+
+        fn resume(_pongs: &u8) -> Vec<&'static str> {
+            vec!["ping"]
+        }
+
+        fn react(_pongs: &u8, _event: &&'static str) -> Vec<&'static str> {
+            vec!["ping"]
+        }
+
+        fn initial_state() -> u8 {
+            0
+        }
+
+        fn is_terminal(pongs: &u8) -> bool {
+            *pongs >= 2
+        }
+    }
+
+    struct Ponger;
+
+    impl Decider<&'static str, &'static str, (), ()> for Ponger {
+        fn decide(_command: &&'static str, _state: &()) -> Vec<&'static str> {
+            vec!["pong"]
+        }
+
+        fn evolve(_state: &(), _event: &&'static str) {}
+
+        fn initial_state() {}
+
+        fn is_terminal(_state: &()) -> bool {
+            true
+        }
+    }
+
+    type GuardedPinger = TerminalGuard<Pinger>;
+
+    assert_eq!(Pinger::react(&3, &"pong"), vec!["ping"]);
+    // The guard still reacts to the event that made the process terminal, but not to later ones.
+    let mut state = GuardedPinger::initial_state();
+    let mut reactions = vec![];
+    for _ in 0..3 {
+        state = GuardedPinger::evolve(&state, &"pong");
+        reactions.push(GuardedPinger::react(&state, &"pong"));
+    }
+    assert_eq!(reactions, vec![vec!["ping"], vec!["ping"], vec![]]);
+    assert_eq!(
+        state,
+        GuardedState {
+            state: 3,
+            finished: true
+        }
+    );
+    assert!(GuardedPinger::is_terminal(&state));
+    assert_eq!(GuardedPinger::resume(&state), Vec::<&str>::new());
+
+    // Without the guard, the process would keep pinging forever.
+    type PingPong = CombinedProcessDecider<
+        GuardedPinger,
+        Ponger,
+        &'static str,
+        &'static str,
+        GuardedState<u8>,
+        (),
+    >;
+    let done = GuardedState {
+        state: 2,
+        finished: false,
+    };
+    assert_eq!(PingPong::decide(&"ping", &(done, ())), vec!["pong"]);
+}
+
+#[test]
+fn terminal_guard_process_test() {
+    type GuardedFulfillment = TerminalGuard<FulfillmentProcess>;
+    type GuardedWatchdog = TerminalGuard<DeliveryWatchdogProcess>;
+
+    // A saga issues its compensations from the failing event that makes it terminal.
+    {
+        use order::{Command, Event};
+
+        let events = [
+            Event::Placed { shippable: false },
+            Event::Reserved,
+            Event::Charged,
+            Event::ShippingFailed,
+        ];
+        let unguarded = collect_fold::<FulfillmentProcess, _, _, _>(
+            &FulfillmentProcess::initial_state(),
+            &events,
+        );
+        let guarded = collect_fold::<GuardedFulfillment, _, _, _>(
+            &GuardedFulfillment::initial_state(),
+            &events,
+        );
+        assert_eq!(guarded, unguarded);
+        assert!(guarded.ends_with(&[Command::Refund, Command::Release]));
+
+        let mut state = GuardedFulfillment::initial_state();
+        for event in &events {
+            state = GuardedFulfillment::evolve(&state, event);
+        }
+        assert!(GuardedFulfillment::is_terminal(&state));
+        assert_eq!(
+            GuardedFulfillment::resume(&state),
+            vec![Command::Refund, Command::Release]
+        );
+        let late = GuardedFulfillment::evolve(&state, &Event::ShippingFailed);
+        assert_eq!(GuardedFulfillment::resume(&late), vec![]);
+    }
+
+    // A fresh watchdog is not terminal, so the guard lets it fire.
+    {
+        use delivery::{Command, Event};
+
+        assert!(!GuardedWatchdog::is_terminal(
+            &GuardedWatchdog::initial_state()
+        ));
+        assert_eq!(
+            collect_fold::<GuardedWatchdog, _, _, _>(
+                &GuardedWatchdog::initial_state(),
+                &[Event::Tick(1), Event::PaymentRequested, Event::Tick(4)]
+            ),
+            vec![Command::EscalatePayment]
+        );
+        assert_eq!(
+            collect_fold::<GuardedWatchdog, _, _, _>(
+                &GuardedWatchdog::initial_state(),
+                &[Event::PaymentRequested, Event::Closed, Event::Tick(4)]
+            ),
+            vec![]
+        );
+    }
+}
+
+#[test]
+fn neutral_composition_test() {
+    use bulb::*;
+
+    type WithNeutral = ComposedDeciders<Bulb, Command, Event, State, NeutralDecider, (), (), ()>;
+    type WithNeutralFor =
+        ComposedDeciders<Bulb, Command, Event, State, NeutralFor<u8>, cat::Command, cat::Event, u8>;
+    type WithNeutralProcess =
+        CombinedProcessDecider<NeutralProcess, Bulb, Event, Command, (), State>;
+    type WithNeutralForProcess =
+        CombinedProcessDecider<NeutralFor<()>, Bulb, Event, Command, (), State>;
+
+    let lifecycle = [
+        Command::Fit { max_uses: 1 },
+        Command::SwitchOn,
+        Command::SwitchOff,
+        Command::SwitchOn,
+        Command::SwitchOn,
+    ];
+    let mut bulb = InMemoryRunner::<_, _, _, Bulb>::new();
+    let mut with_neutral = InMemoryRunner::<_, _, _, WithNeutral>::new();
+    let mut with_neutral_for = InMemoryRunner::<_, _, _, WithNeutralFor>::new();
+    let mut with_process = InMemoryRunner::<_, _, _, WithNeutralProcess>::new();
+    let mut with_neutral_for_process = InMemoryRunner::<_, _, _, WithNeutralForProcess>::new();
+    for command in lifecycle {
+        let events = bulb.command(&command);
+        let lefts: Vec<_> = events.iter().copied().map(Either::Left).collect();
+        assert_eq!(with_neutral.command(&Either::Left(command)), lefts);
+        let (lefts, rights) = split_either(with_neutral_for.command(&Either::Left(command)));
+        assert_eq!(lefts, events);
+        assert!(rights.is_empty());
+        assert_eq!(with_process.command(&command), events);
+        assert_eq!(with_neutral_for_process.command(&command), events);
+        assert_eq!(with_neutral.get_state().0, *bulb.get_state());
+        assert_eq!(with_neutral_for.get_state().0, *bulb.get_state());
+        assert_eq!(with_process.get_state().1, *bulb.get_state());
+        assert_eq!(
+            WithNeutral::is_terminal(with_neutral.get_state()),
+            Bulb::is_terminal(bulb.get_state())
+        );
+    }
+    assert_eq!(*bulb.get_state(), State::Blown);
+
+    // The neutral side never does anything.
+    assert_eq!(with_neutral.command(&Either::Right(())), vec![]);
+    assert_eq!(
+        with_neutral_for.command(&Either::Right(cat::Command::WakeUp)),
+        vec![]
+    );
+    assert_eq!(with_neutral_for.get_state().1, 0);
+    assert_eq!(
+        <NeutralProcess as Process<Event, Command, ()>>::react(&(), &Event::Blew),
+        vec![]
+    );
+    assert_eq!(
+        <NeutralProcess as Process<Event, Command, ()>>::resume(&()),
+        vec![]
+    );
+    assert!(<NeutralProcess as Process<Event, Command, ()>>::is_terminal(&()));
+}
+
+#[test]
+fn decider_laws_test() {
+    assert!(check_decider::<cat::Cat, _, _, _>(
+        [cat::Command::WakeUp, cat::Command::GetToSleep],
+        [cat::Event::WokeUp, cat::Event::GotToSleep],
+    )
+    .is_ok());
+
+    // Fitting a bulb twice panics, which is surfaced as a violation.
+    let violation = check_decider::<bulb::Bulb, _, _, _>(
+        [
+            bulb::Command::Fit { max_uses: 1 },
+            bulb::Command::SwitchOn,
+            bulb::Command::SwitchOff,
+        ],
+        [bulb::Event::Blew],
+    )
+    .unwrap_err();
+    assert_eq!(violation.law, Law::DecideDoesNotPanic);
+    assert!(matches!(
+        violation.commands[..],
+        [bulb::Command::Fit { .. }, bulb::Command::Fit { .. }]
+    ));
+    assert_eq!(violation.events, vec![bulb::Event::Fitted { max_uses: 1 }]);
+
+    // A decider that comes back from its terminal state.
+    struct Phoenix;
+
+    impl Decider<&'static str, &'static str, bool, bool> for Phoenix {
+        fn decide(command: &&'static str, alive: &bool) -> Vec<&'static str> {
+            match (*command, alive) {
+                ("burn", true) => vec!["burned"],
+                _ => vec![],
+            }
+        }
+
+        fn evolve(_alive: &bool, event: &&'static str) -> bool {
+            *event == "reborn"
+        }
+
+        fn initial_state() -> bool {
+            true
+        }
+
+        fn is_terminal(alive: &bool) -> bool {
+            !alive
+        }
+    }
+
+    assert_eq!(
+        check_decider::<Phoenix, _, _, _>(["burn"], ["burned"]),
+        Ok(())
+    );
+    let violation = check_decider::<Phoenix, _, _, _>(["burn"], ["burned", "reborn"]).unwrap_err();
+    assert_eq!(
+        violation,
+        LawViolation {
+            law: Law::TerminalStaysTerminal,
+            commands: vec!["burn"],
+            events: vec!["burned", "reborn"],
+        }
+    );
+    assert_eq!(
+        violation.to_string(),
+        r#"law TerminalStaysTerminal violated after commands ["burn"] and events ["burned", "reborn"]"#
+    );
+}
+
+#[test]
+fn snapshot_test() {
+    use bulb::{Bulb, Command, Event, State};
+
+    let events = [
+        Event::Fitted { max_uses: 2 },
+        Event::SwitchedOn,
+        Event::SwitchedOff,
+        Event::SwitchedOn,
+        Event::SwitchedOff,
+        Event::Blew,
+    ];
+    let replayed = Bulb::fold(&events);
+    assert_eq!(replayed, State::Blown);
+    for taken_at in 0..=events.len() {
+        let snapshot = Bulb::to_snapshot(&Bulb::fold(&events[..taken_at]));
+        let runner =
+            InMemoryRunner::<Command, Event, State, Bulb>::hydrate(&snapshot, &events[taken_at..]);
+        assert_eq!(*runner.get_state(), replayed);
+        assert_eq!(runner.snapshot(), Bulb::to_snapshot(&replayed));
+    }
+
+    type TwoBulbs = ComposedDeciders<Bulb, Command, Event, State, Bulb, Command, Event, State>;
+    let mut composed = InMemoryRunner::<_, _, _, TwoBulbs>::new();
+    composed.command(&Either::Left(Command::Fit { max_uses: 3 }));
+    composed.command(&Either::Left(Command::SwitchOn));
+    let snapshot = composed.snapshot();
+    assert_eq!(snapshot, (Ok((true, 2)), Err(false)));
+    let tail = [Either::Right(Event::Fitted { max_uses: 1 })];
+    let hydrated = InMemoryRunner::<_, _, _, TwoBulbs>::hydrate(&snapshot, &tail);
+    composed.command(&Either::Right(Command::Fit { max_uses: 1 }));
+    assert_eq!(hydrated.get_state(), composed.get_state());
+
+    type Lights = ManyDecider<Bulb, Command, Event, State>;
+    let mut lights = InMemoryRunner::<_, _, _, Lights>::new();
+    lights.command(&("hall".to_string(), Command::Fit { max_uses: 1 }));
+    lights.command(&("porch".to_string(), Command::Fit { max_uses: 0 }));
+    let snapshot = lights.snapshot();
+    assert_eq!(snapshot.len(), 2);
+    assert_eq!(snapshot["porch"], Ok((false, 0)));
+    lights.command(&("porch".to_string(), Command::SwitchOn));
+    let hydrated = InMemoryRunner::<_, _, _, Lights>::hydrate(
+        &snapshot,
+        &[("porch".to_string(), Event::Blew)],
+    );
+    assert_eq!(hydrated.get_state(), lights.get_state());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn versioned_snapshot_test() {
+    use bulb::{Bulb, Command, Event, State, Status};
+    use deciders_rs::deciders::Snapshot;
+
+    type Runner = InMemoryRunner<Command, Event, State, Bulb>;
+
+    let events = [
+        Event::Fitted { max_uses: 5 },
+        Event::SwitchedOn,
+        Event::SwitchedOff,
+        Event::SwitchedOn,
+    ];
+    let replayed = Bulb::fold(&events);
+
+    // A snapshot written with the first schema. It claims more uses than the events allow, to
+    // show that it was migrated and used instead of replaying the whole stream.
+    let v1 = serde_json::json!({
+        "schema_version": 1,
+        "taken_at_version": 2,
+        "state": { "fitted": true, "lit": true, "uses_left": 9 }
+    });
+    assert_eq!(
+        *Runner::restore(&v1, &events).get_state(),
+        State::Working {
+            status: Status::On,
+            remaining_uses: 8
+        }
+    );
+
+    let runner = Runner::from_events(&events);
+    let snapshot = runner.versioned_snapshot(StreamVersion::from(4));
+    assert_eq!(
+        snapshot,
+        Snapshot {
+            schema_version: 2,
+            taken_at_version: StreamVersion::from(4),
+            state: Ok((true, 3))
+        }
+    );
+    let stored = serde_json::to_value(&snapshot).unwrap();
+    assert_eq!(*Runner::restore(&stored, &events).get_state(), replayed);
+
+    // Snapshots that cannot be used fall back to replaying every event.
+    let unusable = [
+        serde_json::json!({ "schema_version": 0, "taken_at_version": 2, "state": null }),
+        serde_json::json!({ "schema_version": 1, "taken_at_version": 2, "state": { "lit": 1 } }),
+        serde_json::json!({ "schema_version": 3, "taken_at_version": 2, "state": { "Ok": [true, 9] } }),
+        serde_json::json!({ "schema_version": 2, "taken_at_version": 5, "state": { "Ok": [true, 9] } }),
+        serde_json::json!("not a snapshot"),
+    ];
+    for stored in unusable {
+        assert_eq!(*Runner::restore(&stored, &events).get_state(), replayed);
+    }
+}
+
+#[test]
+fn tenanted_runner_test() {
+    use bulb::{Bulb, Command, Event, State, Status};
+
+    let runner = TenantedRunner::<Command, Event, State, Bulb, _, _>::new(
+        "bulb",
+        InMemoryEventStore::new(),
+        InMemorySnapshotStore::new(),
+    );
+    let acme = runner.for_tenant(TenantId::new("acme"));
+    let globex = runner.for_tenant(TenantId::from("globex"));
+    assert!(!runner.is_loaded(acme.tenant()));
+
+    // Both tenants use the same stream id in the same stores, but their commands never see each
+    // other's events.
+    assert_eq!(
+        acme.command(&Command::Fit { max_uses: 3 }),
+        Ok(vec![Event::Fitted { max_uses: 3 }])
+    );
+    assert_eq!(
+        globex.command(&Command::Fit { max_uses: 1 }),
+        Ok(vec![Event::Fitted { max_uses: 1 }])
+    );
+    assert_eq!(
+        acme.command(&Command::SwitchOn),
+        Ok(vec![Event::SwitchedOn])
+    );
+    assert_eq!(
+        globex.command(&Command::SwitchOn),
+        Ok(vec![Event::SwitchedOn])
+    );
+    assert_eq!(
+        acme.command(&Command::SwitchOff),
+        Ok(vec![Event::SwitchedOff])
+    );
+    assert!(runner.is_loaded(acme.tenant()));
+    assert_eq!(
+        acme.events(),
+        Ok(vec![
+            Event::Fitted { max_uses: 3 },
+            Event::SwitchedOn,
+            Event::SwitchedOff
+        ])
+    );
+    assert_eq!(
+        globex.events(),
+        Ok(vec![Event::Fitted { max_uses: 1 }, Event::SwitchedOn])
+    );
+    assert_eq!(acme.version(), Ok(StreamVersion::from(3)));
+    assert_eq!(globex.version(), Ok(StreamVersion::from(2)));
+
+    // Evicting a tenant drops it from memory, and the next use reloads the same state.
+    let acme_state = State::Working {
+        status: Status::Off,
+        remaining_uses: 2,
+    };
+    assert_eq!(acme.evict(), Ok(true));
+    assert_eq!(acme.evict(), Ok(false));
+    assert!(!runner.is_loaded(acme.tenant()));
+    assert!(runner.is_loaded(globex.tenant()));
+    assert_eq!(acme.state(), Ok(acme_state));
+    assert_eq!(
+        acme.command(&Command::SwitchOn),
+        Ok(vec![Event::SwitchedOn])
+    );
+    assert_eq!(acme.version(), Ok(StreamVersion::from(4)));
+
+    let mut evicted = runner.evict_idle(Duration::ZERO).unwrap();
+    evicted.sort();
+    assert_eq!(evicted, [TenantId::new("acme"), TenantId::new("globex")]);
+    assert_eq!(
+        globex.state(),
+        Ok(State::Working {
+            status: Status::On,
+            remaining_uses: 0,
+        })
+    );
+
+    // Projections with the same name keep a checkpoint per tenant.
+    let mut counts = ProjectionRunner::<CountByEventType, Event, _>::new(
+        "counts",
+        acme.checkpoints(InMemoryCheckpointStore::new()),
+    );
+    assert_eq!(counts.catch_up(&acme.events().unwrap()), Ok(4));
+    let store = counts.into_checkpoint_store().into_inner();
+    let counts =
+        ProjectionRunner::<CountByEventType, Event, _>::new("counts", globex.checkpoints(store));
+    assert_eq!(counts.next_position(), GlobalPosition::from(0));
+
+    let (events, _) = runner.into_stores();
+    assert_eq!(events.stream_ids().map(|ids| ids.len()), Ok(2));
+}
+
+#[test]
+fn upcasting_test() {
+    use bulb::{Bulb, Command, Event, State, Status};
+
+    // The first schema called fitting a bulb installing it, and turning it on and off.
+    #[derive(Debug, PartialEq)]
+    enum BulbEventV1 {
+        Installed,
+        TurnedOn,
+        TurnedOff,
+    }
+
+    // The second schema renamed its variants, but still had no `max_uses`.
+    #[derive(Debug, PartialEq)]
+    enum BulbEventV2 {
+        Fitted,
+        SwitchedOn,
+        SwitchedOff,
+    }
+
+    struct V1ToV2;
+    impl EventUpcaster<BulbEventV1, BulbEventV2> for V1ToV2 {
+        fn upcast(event: &BulbEventV1) -> BulbEventV2 {
+            match event {
+                BulbEventV1::Installed => BulbEventV2::Fitted,
+                BulbEventV1::TurnedOn => BulbEventV2::SwitchedOn,
+                BulbEventV1::TurnedOff => BulbEventV2::SwitchedOff,
+            }
+        }
+    }
+
+    struct V2ToCurrent;
+    impl EventUpcaster<BulbEventV2, Event> for V2ToCurrent {
+        fn upcast(event: &BulbEventV2) -> Event {
+            match event {
+                BulbEventV2::Fitted => Event::Fitted { max_uses: 3 },
+                BulbEventV2::SwitchedOn => Event::SwitchedOn,
+                BulbEventV2::SwitchedOff => Event::SwitchedOff,
+            }
+        }
+    }
+
+    type BulbV2 = UpcastingDecider<Bulb, V2ToCurrent, Command, BulbEventV2, Event, State, State>;
+    let state = BulbV2::fold(&[
+        Either::Left(BulbEventV2::Fitted),
+        Either::Left(BulbEventV2::SwitchedOn),
+        Either::Left(BulbEventV2::SwitchedOff),
+        Either::Right(Event::SwitchedOn),
+    ]);
+    assert_eq!(
+        state,
+        State::Working {
+            status: Status::On,
+            remaining_uses: 1
+        }
+    );
+    assert_eq!(
+        BulbV2::decide(&Command::SwitchOff, &state),
+        [Either::Right(Event::SwitchedOff)]
+    );
+
+    type V1ToCurrent = ChainedUpcaster<V1ToV2, V2ToCurrent, BulbEventV2>;
+    type BulbV1 = UpcastingDecider<Bulb, V1ToCurrent, Command, BulbEventV1, Event, State, State>;
+    let mut runner = InMemoryRunner::<_, _, _, BulbV1>::with_state(BulbV1::fold(&[
+        Either::Left(BulbEventV1::Installed),
+        Either::Left(BulbEventV1::TurnedOn),
+        Either::Left(BulbEventV1::TurnedOff),
+    ]));
+    assert_eq!(
+        *runner.get_state(),
+        State::Working {
+            status: Status::Off,
+            remaining_uses: 2
+        }
+    );
+    runner.command(&Command::SwitchOn);
+    runner.command(&Command::SwitchOff);
+    runner.command(&Command::SwitchOn);
+    runner.command(&Command::SwitchOff);
+    assert_eq!(
+        runner.command(&Command::SwitchOn),
+        [Either::Right(Event::Blew)]
+    );
+    assert_eq!(*runner.get_state(), State::Blown);
+}
+
+#[test]
+fn compose_test() {
+    mod home {
+        use super::{bulb, cat, update_server};
+
+        deciders_rs::compose! {
+            #[derive(Clone, Debug)]
+            pub enum Command;
+            #[derive(Clone, Debug, PartialEq)]
+            pub enum Event;
+            #[derive(Clone, Debug, PartialEq)]
+            pub struct State;
+            pub struct Home {
+                Cat(cat): cat::Cat[cat::Command, cat::Event, cat::State],
+                Bulb(bulb): bulb::Bulb[bulb::Command, bulb::Event, bulb::State],
+                Server(server): update_server::UpdateServer[
+                    update_server::Command,
+                    update_server::Event,
+                    update_server::State
+                ],
+            }
+        }
+
+        deciders_rs::compose! {
+            pub enum FiniteCommand;
+            pub enum FiniteEvent;
+            #[derive(Clone, Debug, PartialEq)]
+            pub struct FiniteState;
+            pub struct Finite {
+                Bulb(bulb): bulb::Bulb[bulb::Command, bulb::Event, bulb::State],
+                Server(server): update_server::UpdateServer[
+                    update_server::Command,
+                    update_server::Event,
+                    update_server::State
+                ],
+            }
+        }
+    }
+    use home::{Command, Event, Finite, FiniteCommand, FiniteState, Home, State};
+
+    assert_eq!(
+        Home::initial_state(),
+        State {
+            cat: cat::State::Awake,
+            bulb: bulb::State::NotFitted,
+            server: update_server::State::NewConnection,
+        }
+    );
+
+    let mut runner = InMemoryRunner::<_, _, _, Home>::new();
+    assert_eq!(
+        runner.command(&Command::Cat(cat::Command::GetToSleep)),
+        [Event::Cat(cat::Event::GotToSleep)]
+    );
+    assert_eq!(
+        runner.command(&Command::Bulb(bulb::Command::Fit { max_uses: 1 })),
+        [Event::Bulb(bulb::Event::Fitted { max_uses: 1 })]
+    );
+    assert_eq!(
+        runner.command(&Command::Server(update_server::Command::QueryForUpdate {
+            current_version: "1.0.0".to_string(),
+        })),
+        [Event::Server(update_server::Event::UpdateAvailable {
+            new_versions: vec!["1.1.0", "2.0.0"],
+        })]
+    );
+    assert_eq!(
+        runner.command(&Command::Server(update_server::Command::DownloadUpdate {
+            desired_version: "2.0.0".to_string(),
+        })),
+        [Event::Server(update_server::Event::GotUpdateData {
+            version: "2.0.0"
+        })]
+    );
+    // The bulb is already off, and the cat's state does not change what the bulb decides.
+    assert!(runner
+        .command(&Command::Bulb(bulb::Command::SwitchOff))
+        .is_empty());
+    assert_eq!(
+        *runner.get_state(),
+        State {
+            cat: cat::State::Asleep,
+            bulb: bulb::State::Working {
+                status: bulb::Status::Off,
+                remaining_uses: 1,
+            },
+            server: update_server::State::DownloadReady { version: "2.0.0" },
+        }
+    );
+    // The cat never reaches an end state, so neither does the whole home.
+    assert!(!Home::is_terminal(runner.get_state()));
+
+    // The composition matches nesting ComposedDeciders.
+    type Nested = ComposedDeciders<
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        bulb::Bulb,
+        bulb::Command,
+        bulb::Event,
+        bulb::State,
+    >;
+    let nested = Nested::fold(&[
+        Either::Left(cat::Event::GotToSleep),
+        Either::Right(bulb::Event::Fitted { max_uses: 1 }),
+    ]);
+    assert_eq!(nested.0, runner.get_state().cat);
+    assert_eq!(nested.1, runner.get_state().bulb);
+
+    let mut finite = InMemoryRunner::<_, _, _, Finite>::new();
+    finite.command(&FiniteCommand::Bulb(bulb::Command::Fit { max_uses: 0 }));
+    finite.command(&FiniteCommand::Bulb(bulb::Command::SwitchOn));
+    assert!(!Finite::is_terminal(finite.get_state()));
+    finite.command(&FiniteCommand::Server(
+        update_server::Command::QueryForUpdate {
+            current_version: "2.0.0".to_string(),
+        },
+    ));
+    assert_eq!(
+        *finite.get_state(),
+        FiniteState {
+            bulb: bulb::State::Blown,
+            server: update_server::State::NoNewUpdate,
+        }
+    );
+    assert!(Finite::is_terminal(finite.get_state()));
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn instrumented_decider_test() {
+    use deciders_rs::deciders::InstrumentedDecider;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    // Records every span, field and event as a line of text.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Visit for Recorder {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("  {} = {value:?}", field.name()));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("span {}", span.metadata().name()));
+            span.record(&mut self.clone());
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _span: &Id, values: &Record<'_>) {
+            values.record(&mut self.clone());
+        }
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("event {}", event.metadata().name()));
+            event.record(&mut self.clone());
+        }
+
+        fn enter(&self, _span: &Id) {}
+
+        fn exit(&self, _span: &Id) {}
+    }
+
+    type Traced = InstrumentedDecider<bulb::Bulb>;
+    let recorder = Recorder::default();
+    let mut runner = InMemoryRunner::<_, _, _, Traced>::with_state(bulb::State::Working {
+        status: bulb::Status::Off,
+        remaining_uses: 1,
+    });
+    tracing::subscriber::with_default(recorder.clone(), || {
+        runner.command(&bulb::Command::SwitchOn);
+    });
+    let decider = std::any::type_name::<bulb::Bulb>();
+    assert_eq!(
+        *recorder.0.lock().unwrap(),
+        [
+            "span decide".to_string(),
+            format!("  decider = {decider:?}"),
+            "  command = SwitchOn".to_string(),
+            "  events = 1".to_string(),
+            "event evolve".to_string(),
+            format!("  decider = {decider:?}"),
+            "  event = SwitchedOn".to_string(),
+        ]
+    );
+
+    recorder.0.lock().unwrap().clear();
+    runner.command(&bulb::Command::SwitchOff);
+    tracing::subscriber::with_default(recorder.clone(), || {
+        runner.command(&bulb::Command::SwitchOn);
+    });
+    let recorded = recorder.0.lock().unwrap();
+    assert_eq!(
+        recorded[recorded.len() - 4..],
+        [
+            "event terminal".to_string(),
+            "  message = decider reached a terminal state".to_string(),
+            format!("  decider = {decider:?}"),
+            "  state = Blown".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn counting_decider_test() {
+    use bulb::{Bulb, Command, Event, State};
+    type Counted = CountingDecider<Bulb>;
+
+    Counted::reset();
+    let mut runner = InMemoryRunner::<_, _, _, Counted>::new();
+    runner.command(&Command::Fit { max_uses: 1 });
+    runner.command(&Command::SwitchOn);
+    runner.command(&Command::SwitchOff);
+    runner.command(&Command::SwitchOn);
+    runner.command(&Command::SwitchOff);
+    assert_eq!(*runner.get_state(), State::Blown);
+    assert_eq!(
+        Counted::stats(),
+        DeciderStats {
+            commands: 5,
+            events: 4,
+            empty_decisions: 1,
+            evolves: 4,
+        }
+    );
+
+    Counted::reset();
+    assert_eq!(Counted::stats(), DeciderStats::default());
+    type Lights = ManyDecider<Counted, Command, Event, State>;
+    let mut lights = InMemoryRunner::<_, _, _, Lights>::new();
+    lights.command(&("hall".to_string(), Command::Fit { max_uses: 2 }));
+    lights.command(&("porch".to_string(), Command::Fit { max_uses: 2 }));
+    lights.command(&("porch".to_string(), Command::SwitchOff));
+    type CatAndLight = ComposedDeciders<
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        Counted,
+        Command,
+        Event,
+        State,
+    >;
+    let mut composed = InMemoryRunner::<_, _, _, CatAndLight>::new();
+    composed.command(&Either::Left(cat::Command::GetToSleep));
+    composed.command(&Either::Right(Command::Fit { max_uses: 2 }));
+    assert_eq!(
+        Counted::stats(),
+        DeciderStats {
+            commands: 4,
+            events: 3,
+            empty_decisions: 1,
+            evolves: 3,
+        }
+    );
+}
+
+#[test]
+fn enumerated_dot_test() {
+    let dot = viz::enumerated_to_dot::<_, _, _, cat::Cat>();
+    assert!(dot.contains("    n0 [label=\"Awake\", shape=circle];\n"));
+    assert!(dot.contains("    n1 [label=\"Asleep\", shape=circle];\n"));
+    assert!(dot.contains("    n1 -> n0 [label=\"WakeUp / WokeUp\"];\n"));
+    assert!(dot.contains("    n0 -> n1 [label=\"GetToSleep / GotToSleep\"];\n"));
+
+    use update_server::{State, UpdateServer};
+    let graph = viz::enumerate::<_, _, _, UpdateServer, _>(|state| match state {
+        State::NewConnection => "new",
+        State::UpdateAvailable { .. } => "available",
+        State::UnknownVersion => "unknown",
+        State::NoNewUpdate => "up to date",
+        State::DownloadReady { .. } => "ready",
+        State::DownloadUnavailable => "unavailable",
+    });
+    let labels: Vec<_> = graph.nodes.iter().map(|n| n.state).collect();
+    assert_eq!(
+        labels,
+        [
+            "new",
+            "available",
+            "unknown",
+            "up to date",
+            "ready",
+            "unavailable"
+        ]
+    );
+    let terminal: Vec<_> = graph.nodes.iter().map(|n| n.terminal).collect();
+    assert_eq!(terminal, [false, false, false, true, true, true]);
+    let dot = graph.to_dot(|s| s.to_string());
+    assert!(dot.contains("    n4 [label=\"ready\", shape=doublecircle];\n"));
+    assert!(dot.contains("    n1 -> n4 [label=\"DownloadUpdate / GotUpdateData\"];\n"));
+    assert!(dot.contains("    n1 -> n5 [label=\"DownloadUpdate / InvalidVersion\"];\n"));
+    assert!(dot.contains("    n0 -> n1 [label=\"QueryForUpdate / UpdateAvailable\"];\n"));
+    assert_eq!(dot.matches(" -> ").count(), 1 + 5);
+}
+
+#[test]
+fn bounded_decider_test() {
+    // Decides the numbers below each command, and counts the events it has evolved.
+    struct Flood;
+
+    impl Decider<usize, usize, usize, usize> for Flood {
+        fn decide(command: &usize, _state: &usize) -> Vec<usize> {
+            (0..*command).collect()
+        }
+
+        fn evolve(state: &usize, _event: &usize) -> usize {
+            state + 1
+        }
+
+        fn initial_state() -> usize {
+            0
+        }
+
+        fn is_terminal(_state: &usize) -> bool {
+            false
+        }
+    }
+
+    // Issues the numbers below each event, or below its state when resumed.
+    impl Process<usize, usize, usize> for Flood {
+        fn evolve(state: &usize, _event: &usize) -> usize {
+            *state
+        }
+
+        fn resume(state: &usize) -> Vec<usize> {
+            (0..*state).collect()
+        }
+
+        fn react(_state: &usize, event: &usize) -> Vec<usize> {
+            (0..*event).collect()
+        }
+
+        fn initial_state() -> usize {
+            0
+        }
+
+        fn is_terminal(_state: &usize) -> bool {
+            false
+        }
+    }
+
+    type Lenient = BoundedDecider<Flood, 3>;
+    assert_eq!(<Lenient as Decider<_, _, _, _>>::decide(&2, &0), [0, 1]);
+    assert_eq!(
+        <Lenient as Decider<_, _, _, _>>::decide(&1000, &0),
+        [0, 1, 2]
+    );
+    let mut runner = InMemoryRunner::<_, _, _, Lenient>::new();
+    assert_eq!(runner.command(&10), [0, 1, 2]);
+    assert_eq!(*runner.get_state(), 3);
+
+    assert_eq!(<Lenient as Process<_, _, _>>::react(&0, &2), [0, 1]);
+    assert_eq!(<Lenient as Process<_, _, _>>::react(&0, &1000), [0, 1, 2]);
+    assert_eq!(<Lenient as Process<_, _, _>>::resume(&1000), [0, 1, 2]);
+
+    type Strict = StrictBoundedDecider<Flood, 3>;
+    assert_eq!(Strict::decide(&3, &0), Ok(vec![0, 1, 2]));
+    assert_eq!(
+        Strict::decide(&4, &0),
+        Err(TooManyEvents {
+            limit: 3,
+            decided: 4
+        })
+    );
+    let mut runner = FallibleInMemoryRunner::<_, _, _, Strict>::new();
+    assert_eq!(runner.command(&2), Ok(vec![0, 1]));
+    let error = runner.command(&1000).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "the decider decided 1000 events, more than its limit of 3"
+    );
+    assert_eq!(*runner.get_state(), 2);
+}
+
+#[test]
+fn dedup_decider_test() {
+    // Decides the events listed in each command, and counts the events it has evolved.
+    struct Echo;
+
+    impl Decider<Vec<char>, char, usize, usize> for Echo {
+        fn decide(command: &Vec<char>, _state: &usize) -> Vec<char> {
+            command.clone()
+        }
+
+        fn evolve(state: &usize, _event: &char) -> usize {
+            state + 1
+        }
+
+        fn initial_state() -> usize {
+            0
+        }
+
+        fn is_terminal(_state: &usize) -> bool {
+            false
+        }
+    }
+
+    type Dedup = DedupDecider<Echo>;
+    assert_eq!(Dedup::decide(&vec!['a', 'a'], &0), ['a']);
+    assert_eq!(Dedup::decide(&vec!['a', 'b', 'c'], &0), ['a', 'b', 'c']);
+    assert_eq!(
+        Dedup::decide(&vec!['a', 'a', 'b', 'b', 'b', 'a'], &0),
+        ['a', 'b', 'a']
+    );
+    let mut runner = InMemoryRunner::<_, _, _, Dedup>::new();
+    assert_eq!(runner.command(&vec!['a']), ['a']);
+    assert_eq!(runner.command(&vec!['a']), ['a']);
+
+    type DedupLast = DedupLastDecider<Echo>;
+    let mut runner = InMemoryRunner::<_, _, _, DedupLast>::new();
+    assert_eq!(*runner.get_state(), (0, None));
+    assert_eq!(runner.command(&vec!['a', 'a']), ['a']);
+    assert_eq!(runner.command(&vec!['a', 'b']), ['b']);
+    assert!(runner.command(&vec!['b', 'b']).is_empty());
+    assert_eq!(runner.command(&vec!['c', 'b']), ['c', 'b']);
+    assert_eq!(*runner.get_state(), (4, Some('b')));
+    assert_eq!(DedupLast::fold(&['a', 'b', 'c', 'b']), *runner.get_state());
+}
+
+#[test]
+fn filtered_decider_test() {
+    use bulb::{Bulb, Command, Event, State, Status};
+
+    // Bulbs cannot be switched on during maintenance.
+    struct Maintenance;
+
+    impl Predicate<Command> for Maintenance {
+        fn accept(command: &Command) -> bool {
+            !matches!(command, Command::SwitchOn)
+        }
+    }
+
+    type Maintained = FilteredDecider<Bulb, Maintenance>;
+    let working = State::Working {
+        status: Status::Off,
+        remaining_uses: 2,
+    };
+    assert!(Maintained::decide(&Command::SwitchOn, &working).is_empty());
+    assert_eq!(
+        Maintained::decide(&Command::Fit { max_uses: 2 }, &State::NotFitted),
+        [Event::Fitted { max_uses: 2 }]
+    );
+    // Events are still evolved, even those of filtered commands.
+    let on = Maintained::evolve(&working, &Event::SwitchedOn);
+    assert_eq!(
+        on,
+        State::Working {
+            status: Status::On,
+            remaining_uses: 1
+        }
+    );
+    assert_eq!(
+        Maintained::decide(&Command::SwitchOff, &on),
+        [Event::SwitchedOff]
+    );
+
+    type CatAndBulb = ComposedDeciders<
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        Maintained,
+        Command,
+        Event,
+        State,
+    >;
+    let mut runner = InMemoryRunner::<_, _, _, CatAndBulb>::with_state((cat::State::Awake, on));
+    assert!(runner.command(&Either::Right(Command::SwitchOn)).is_empty());
+    assert_eq!(
+        runner.command(&Either::Right(Command::SwitchOff)),
+        [Either::Right(Event::SwitchedOff)]
+    );
+    assert!(runner.command(&Either::Right(Command::SwitchOn)).is_empty());
+    assert_eq!(
+        runner.command(&Either::Left(cat::Command::GetToSleep)),
+        [Either::Left(cat::Event::GotToSleep)]
+    );
+    assert_eq!(
+        *runner.get_state(),
+        (
+            cat::State::Asleep,
+            State::Working {
+                status: Status::Off,
+                remaining_uses: 1
+            }
+        )
+    );
+}
+
+#[test]
+fn split_decider_test() {
+    #[derive(Copy, Clone, Debug)]
+    enum HouseCommand {
+        WakeCat,
+        PutCatToSleep,
+        FitBulb,
+        SwitchBulbOn,
+        OpenDoor,
+    }
+
+    struct HouseRouter;
+
+    impl FallibleConverter<HouseCommand, Either<cat::Command, bulb::Command>> for HouseRouter {
+        fn convert(command: &HouseCommand) -> Option<Either<cat::Command, bulb::Command>> {
+            match command {
+                HouseCommand::WakeCat => Some(Either::Left(cat::Command::WakeUp)),
+                HouseCommand::PutCatToSleep => Some(Either::Left(cat::Command::GetToSleep)),
+                HouseCommand::FitBulb => Some(Either::Right(bulb::Command::Fit { max_uses: 5 })),
+                HouseCommand::SwitchBulbOn => Some(Either::Right(bulb::Command::SwitchOn)),
+                HouseCommand::OpenDoor => None,
+            }
+        }
+    }
+
+    type House<T = AllTerminal> = SplitDecider<
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        bulb::Bulb,
+        bulb::Command,
+        bulb::Event,
+        bulb::State,
+        HouseRouter,
+        T,
+    >;
+    let mut runner = InMemoryRunner::<_, _, _, House>::new();
+    assert_eq!(
+        runner.command(&HouseCommand::PutCatToSleep),
+        [Either::Left(cat::Event::GotToSleep)]
+    );
+    assert_eq!(
+        *runner.get_state(),
+        (cat::State::Asleep, bulb::State::NotFitted)
+    );
+    assert_eq!(
+        runner.command(&HouseCommand::FitBulb),
+        [Either::Right(bulb::Event::Fitted { max_uses: 5 })]
+    );
+    assert!(runner.command(&HouseCommand::OpenDoor).is_empty());
+    assert_eq!(
+        runner.command(&HouseCommand::SwitchBulbOn),
+        [Either::Right(bulb::Event::SwitchedOn)]
+    );
+    assert_eq!(
+        *runner.get_state(),
+        (
+            cat::State::Asleep,
+            bulb::State::Working {
+                status: bulb::Status::On,
+                remaining_uses: 4
+            }
+        )
+    );
+    assert_eq!(
+        runner.command(&HouseCommand::WakeCat),
+        [Either::Left(cat::Event::WokeUp)]
+    );
+    assert_eq!(runner.get_state().0, cat::State::Awake);
+    assert!(!House::<AllTerminal>::is_terminal(runner.get_state()));
+    // The terminal policy applies as in ComposedDeciders.
+    let blown = (cat::State::Awake, bulb::State::Blown);
+    assert!(!House::<AllTerminal>::is_terminal(&blown));
+    assert!(House::<AnyTerminal>::is_terminal(&blown));
+}
+
+#[test]
+fn broadcast_decider_test() {
+    #[derive(Copy, Clone, Debug)]
+    enum HouseCommand {
+        PowerOut,
+        PowerOn,
+    }
+
+    struct ToCat;
+
+    impl FallibleConverter<HouseCommand, cat::Command> for ToCat {
+        fn convert(command: &HouseCommand) -> Option<cat::Command> {
+            match command {
+                HouseCommand::PowerOut => Some(cat::Command::WakeUp),
+                HouseCommand::PowerOn => None,
+            }
+        }
+    }
+
+    struct ToBulb;
+
+    impl FallibleConverter<HouseCommand, bulb::Command> for ToBulb {
+        fn convert(command: &HouseCommand) -> Option<bulb::Command> {
+            match command {
+                HouseCommand::PowerOut => Some(bulb::Command::SwitchOff),
+                HouseCommand::PowerOn => Some(bulb::Command::SwitchOn),
+            }
+        }
+    }
+
+    type House<T = AllTerminal> = BroadcastDecider<
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        bulb::Bulb,
+        bulb::Command,
+        bulb::Event,
+        bulb::State,
+        ToCat,
+        ToBulb,
+        T,
+    >;
+    let mut runner = InMemoryRunner::<_, _, _, House>::with_state((
+        cat::State::Asleep,
+        bulb::State::Working {
+            status: bulb::Status::Off,
+            remaining_uses: 3,
+        },
+    ));
+    assert_eq!(
+        runner.command(&HouseCommand::PowerOn),
+        [Either::Right(bulb::Event::SwitchedOn)]
+    );
+    assert_eq!(runner.get_state().0, cat::State::Asleep);
+    assert_eq!(
+        runner.command(&HouseCommand::PowerOut),
+        [
+            Either::Left(cat::Event::WokeUp),
+            Either::Right(bulb::Event::SwitchedOff)
+        ]
+    );
+    // Deciding lazily gives the same events as deciding into a vector.
+    let state = (cat::State::Asleep, runner.get_state().1);
+    assert_eq!(
+        House::<AllTerminal>::decide_iter(&HouseCommand::PowerOut, &state).collect::<Vec<_>>(),
+        House::<AllTerminal>::decide(&HouseCommand::PowerOut, &state)
+    );
+    assert_eq!(
+        *runner.get_state(),
+        (
+            cat::State::Awake,
+            bulb::State::Working {
+                status: bulb::Status::Off,
+                remaining_uses: 2
+            }
+        )
+    );
+    // Neither decider has anything to do after the power has already gone out.
+    assert!(runner.command(&HouseCommand::PowerOut).is_empty());
+    let blown = (cat::State::Awake, bulb::State::Blown);
+    assert!(!House::<AllTerminal>::is_terminal(&blown));
+    assert!(House::<AnyTerminal>::is_terminal(&blown));
+}
+
+#[test]
+fn sequenced_decider_test() {
+    type BulbThenCat = SequencedDecider<
+        bulb::Bulb,
+        bulb::Command,
+        bulb::Event,
+        bulb::State,
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        InitialStateOf<cat::Cat, cat::Command, cat::Event>,
+    >;
+    let mut runner = InMemoryRunner::<_, _, _, BulbThenCat>::new();
+    assert_eq!(*runner.get_state(), Phase::Phase1(bulb::State::NotFitted));
+    // The cat is not running yet.
+    assert!(runner
+        .command(&Either::Right(cat::Command::GetToSleep))
+        .is_empty());
+    runner.command(&Either::Left(bulb::Command::Fit { max_uses: 0 }));
+    assert_eq!(
+        runner.command(&Either::Left(bulb::Command::SwitchOn)),
+        [Either::Left(bulb::Event::Blew)]
+    );
+    assert_eq!(*runner.get_state(), Phase::Phase2(cat::State::Awake));
+    assert!(runner
+        .command(&Either::Left(bulb::Command::SwitchOn))
+        .is_empty());
+    assert_eq!(
+        runner.command(&Either::Right(cat::Command::GetToSleep)),
+        [Either::Right(cat::Event::GotToSleep)]
+    );
+    assert_eq!(*runner.get_state(), Phase::Phase2(cat::State::Asleep));
+    assert!(!BulbThenCat::is_terminal(runner.get_state()));
+
+    // The cat falls asleep in the dark once the bulb has blown.
+    struct AsleepInTheDark;
+    impl InfallibleConverter<bulb::State, cat::State> for AsleepInTheDark {
+        fn convert(_blown: &bulb::State) -> cat::State {
+            cat::State::Asleep
+        }
+    }
+    type Seeded = SequencedDecider<
+        bulb::Bulb,
+        bulb::Command,
+        bulb::Event,
+        bulb::State,
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        AsleepInTheDark,
+    >;
+    let state = Seeded::fold(&[
+        Either::Left(bulb::Event::Fitted { max_uses: 0 }),
+        Either::Left(bulb::Event::Blew),
+        Either::Right(cat::Event::WokeUp),
+    ]);
+    assert_eq!(state, Phase::Phase2(cat::State::Awake));
+}
+
+#[test]
+fn restarting_decider_test() {
+    use bulb::{Bulb, Command, Event, State};
+
+    let mut runner = InMemoryRunner::<_, _, _, RestartingDecider<Bulb>>::new();
+    runner.command(&Command::Fit { max_uses: 0 });
+    assert_eq!(runner.command(&Command::SwitchOn), [Event::Blew]);
+    assert_eq!(*runner.get_state(), State::NotFitted);
+    assert!(!RestartingDecider::<Bulb>::is_terminal(runner.get_state()));
+    assert_eq!(
+        runner.command(&Command::Fit { max_uses: 1 }),
+        [Event::Fitted { max_uses: 1 }]
+    );
+    // Evolving a state that was already terminal does not restart it.
+    assert_eq!(
+        RestartingDecider::<Bulb>::evolve(&State::Blown, &Event::Blew),
+        State::Blown
+    );
+
+    type Marked = MarkedRestartingDecider<Bulb>;
+    let mut runner = InMemoryRunner::<_, _, _, Marked>::new();
+    assert_eq!(
+        runner.command(&Command::Fit { max_uses: 0 }),
+        [Either::Left(Event::Fitted { max_uses: 0 })]
+    );
+    assert_eq!(
+        runner.command(&Command::SwitchOn),
+        [Either::Left(Event::Blew), Either::Right(Restarted)]
+    );
+    assert_eq!(*runner.get_state(), State::NotFitted);
+    assert_eq!(
+        runner.command(&Command::Fit { max_uses: 1 }),
+        [Either::Left(Event::Fitted { max_uses: 1 })]
+    );
+    assert_eq!(
+        Marked::fold(&[
+            Either::Left(Event::Fitted { max_uses: 0 }),
+            Either::Left(Event::Blew),
+        ]),
+        State::Blown
+    );
+}
+
+#[test]
+fn zoomed_decider_test() {
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    struct House {
+        bulb: bulb::State,
+        cat: cat::State,
+        doors: u8,
+    }
+
+    impl Default for House {
+        fn default() -> Self {
+            House {
+                bulb: bulb::State::Blown,
+                cat: cat::State::Asleep,
+                doors: 2,
+            }
+        }
+    }
+
+    struct BulbLens;
+    impl Lens<House, bulb::State> for BulbLens {
+        fn get(house: &House) -> bulb::State {
+            house.bulb
+        }
+
+        fn set(house: &House, bulb: bulb::State) -> House {
+            House { bulb, ..*house }
+        }
+    }
+
+    struct CatLens;
+    impl Lens<House, cat::State> for CatLens {
+        fn get(house: &House) -> cat::State {
+            house.cat
+        }
+
+        fn set(house: &House, cat: cat::State) -> House {
+            House { cat, ..*house }
+        }
+    }
+
+    type HouseBulb = ZoomedDecider<bulb::Bulb, BulbLens, bulb::State>;
+    type HouseCat = ZoomedDecider<cat::Cat, CatLens, cat::State>;
+    assert_eq!(
+        HouseBulb::initial_state(),
+        House {
+            bulb: bulb::State::NotFitted,
+            cat: cat::State::Asleep,
+            doors: 2,
+        }
+    );
+    let mut runner = InMemoryRunner::<_, _, _, HouseBulb>::new();
+    runner.command(&bulb::Command::Fit { max_uses: 1 });
+    runner.command(&bulb::Command::SwitchOn);
+    assert_eq!(
+        *runner.get_state(),
+        House {
+            bulb: bulb::State::Working {
+                status: bulb::Status::On,
+                remaining_uses: 0,
+            },
+            cat: cat::State::Asleep,
+            doors: 2,
+        }
+    );
+
+    // Composed zoomed deciders each keep their own copy of the house.
+    type SideBySide = ComposedDeciders<
+        HouseBulb,
+        bulb::Command,
+        bulb::Event,
+        House,
+        HouseCat,
+        cat::Command,
+        cat::Event,
+        House,
+    >;
+    let mut runner = InMemoryRunner::<_, _, _, SideBySide>::new();
+    runner.command(&Either::Left(bulb::Command::Fit { max_uses: 1 }));
+    runner.command(&Either::Right(cat::Command::WakeUp));
+    let (bulb_side, cat_side) = *runner.get_state();
+    assert_eq!(
+        bulb_side.bulb,
+        bulb::State::Working {
+            status: bulb::Status::Off,
+            remaining_uses: 1,
+        }
+    );
+    assert_eq!(bulb_side.cat, cat::State::Asleep);
+    assert_eq!(cat_side.cat, cat::State::Awake);
+
+    // Zooming a composed decider drives both parts of one house.
+    struct BothLens;
+    impl Lens<House, (cat::State, bulb::State)> for BothLens {
+        fn get(house: &House) -> (cat::State, bulb::State) {
+            (house.cat, house.bulb)
+        }
+
+        fn set(house: &House, (cat, bulb): (cat::State, bulb::State)) -> House {
+            House {
+                cat,
+                bulb,
+                ..*house
+            }
+        }
+    }
+    type CatAndBulb = ComposedDeciders<
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        bulb::Bulb,
+        bulb::Command,
+        bulb::Event,
+        bulb::State,
+    >;
+    type WholeHouse = ZoomedDecider<CatAndBulb, BothLens, (cat::State, bulb::State)>;
+    let mut runner = InMemoryRunner::<_, _, _, WholeHouse>::new();
+    runner.command(&Either::Left(cat::Command::GetToSleep));
+    runner.command(&Either::Right(bulb::Command::Fit { max_uses: 3 }));
+    runner.command(&Either::Right(bulb::Command::SwitchOn));
+    assert_eq!(
+        *runner.get_state(),
+        House {
+            bulb: bulb::State::Working {
+                status: bulb::Status::On,
+                remaining_uses: 2,
+            },
+            cat: cat::State::Asleep,
+            doors: 2,
+        }
+    );
+}
+
+#[test]
+fn guarded_decider_test() {
+    use bulb::{Bulb, Command, Event, State, Status};
+
+    struct FitOnce;
+    impl Guard<Command, State> for FitOnce {
+        fn check(command: &Command, state: &State) -> Result<(), GuardFailure> {
+            match (command, state) {
+                (Command::Fit { .. }, State::NotFitted)
+                | (Command::SwitchOn | Command::SwitchOff, _) => Ok(()),
+                (Command::Fit { .. }, _) => Err(GuardFailure::new("the bulb is already fitted")),
+            }
+        }
+    }
+
+    struct UsableBulbs;
+    impl Guard<Command, State> for UsableBulbs {
+        fn check(command: &Command, _state: &State) -> Result<(), GuardFailure> {
+            match command {
+                Command::Fit { max_uses: 0 } => Err(GuardFailure::new("the bulb cannot be used")),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    type Guarded = GuardedDecider<Bulb, AndGuard<FitOnce, UsableBulbs>>;
+    let working = State::Working {
+        status: Status::Off,
+        remaining_uses: 2,
+    };
+    assert!(
+        <Guarded as Decider<_, _, _, _>>::decide(&Command::Fit { max_uses: 2 }, &working)
+            .is_empty()
+    );
+    assert!(<Guarded as Decider<_, _, _, _>>::decide(
+        &Command::Fit { max_uses: 0 },
+        &State::NotFitted
+    )
+    .is_empty());
+    assert_eq!(
+        <Guarded as Decider<_, _, _, _>>::decide(&Command::SwitchOn, &working),
+        [Event::SwitchedOn]
+    );
+
+    let mut runner = InMemoryRunner::<_, _, _, Guarded>::new();
+    assert_eq!(
+        runner.command(&Command::Fit { max_uses: 2 }),
+        [Event::Fitted { max_uses: 2 }]
+    );
+    assert!(runner.command(&Command::Fit { max_uses: 2 }).is_empty());
+    assert_eq!(*runner.get_state(), working);
+
+    let mut runner = FallibleInMemoryRunner::<_, _, _, Guarded>::with_state(working);
+    assert_eq!(
+        runner.command(&Command::Fit { max_uses: 2 }).unwrap_err(),
+        GuardFailure::new("the bulb is already fitted")
+    );
+    let mut runner = FallibleInMemoryRunner::<_, _, _, Guarded>::new();
+    assert_eq!(
+        runner
+            .command(&Command::Fit { max_uses: 0 })
+            .unwrap_err()
+            .to_string(),
+        "guard failed: the bulb cannot be used"
+    );
+    assert_eq!(*runner.get_state(), State::NotFitted);
+}
+
+#[test]
+fn tagged_decider_test() {
+    struct CatStream;
+    impl StreamTag for CatStream {
+        const STREAM: &'static str = "cat-1";
+    }
+
+    struct BulbStream;
+    impl StreamTag for BulbStream {
+        const STREAM: &'static str = "bulb-1";
+    }
+
+    type TaggedCat = TaggedDecider<cat::Cat, CatStream>;
+    type TaggedBulb = TaggedDecider<bulb::Bulb, BulbStream>;
+    type House = ComposedDeciders<
+        TaggedCat,
+        cat::Command,
+        Tagged<cat::Event>,
+        cat::State,
+        TaggedBulb,
+        bulb::Command,
+        Tagged<bulb::Event>,
+        bulb::State,
+    >;
+    let mut runner = InMemoryRunner::<_, _, _, House>::new();
+    type HouseEvent = Either<Tagged<cat::Event>, Tagged<bulb::Event>>;
+    let mut streams: HashMap<&str, Vec<HouseEvent>> = HashMap::new();
+    for command in [
+        Either::Left(cat::Command::GetToSleep),
+        Either::Right(bulb::Command::Fit { max_uses: 1 }),
+        Either::Right(bulb::Command::SwitchOn),
+        Either::Left(cat::Command::WakeUp),
+    ] {
+        for event in runner.command(&command) {
+            streams.entry(event.stream()).or_default().push(event);
+        }
+    }
+    assert_eq!(
+        streams["cat-1"],
+        [
+            Either::Left(Tagged {
+                stream: "cat-1",
+                event: cat::Event::GotToSleep
+            }),
+            Either::Left(Tagged {
+                stream: "cat-1",
+                event: cat::Event::WokeUp
+            }),
+        ]
+    );
+    assert_eq!(streams["bulb-1"].len(), 2);
+    assert!(streams["bulb-1"].iter().all(|e| e.stream() == "bulb-1"));
+    assert_eq!(
+        *runner.get_state(),
+        (
+            cat::State::Awake,
+            bulb::State::Working {
+                status: bulb::Status::On,
+                remaining_uses: 0
+            }
+        )
+    );
+    assert_eq!(
+        House::fold(&streams["bulb-1"]),
+        (cat::State::Awake, runner.get_state().1)
+    );
+}
+
+#[test]
+fn mapped_command_decider_test() {
+    #[derive(Copy, Clone)]
+    enum Request {
+        Sleep,
+        Wake,
+        Feed,
+    }
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum Notification {
+        Slept,
+        Woke,
+        Purred,
+    }
+    #[derive(Debug, PartialEq)]
+    enum Mood {
+        Sleepy,
+        Playful,
+    }
+
+    struct FromRequest;
+    impl FallibleConverter<Request, cat::Command> for FromRequest {
+        fn convert(request: &Request) -> Option<cat::Command> {
+            match request {
+                Request::Sleep => Some(cat::Command::GetToSleep),
+                Request::Wake => Some(cat::Command::WakeUp),
+                Request::Feed => None,
+            }
+        }
+    }
+
+    struct FromNotification;
+    impl FallibleConverter<Notification, cat::Event> for FromNotification {
+        fn convert(notification: &Notification) -> Option<cat::Event> {
+            match notification {
+                Notification::Slept => Some(cat::Event::GotToSleep),
+                Notification::Woke => Some(cat::Event::WokeUp),
+                Notification::Purred => None,
+            }
+        }
+    }
+
+    struct ToNotification;
+    impl InfallibleConverter<cat::Event, Notification> for ToNotification {
+        fn convert(event: &cat::Event) -> Notification {
+            match event {
+                cat::Event::GotToSleep => Notification::Slept,
+                cat::Event::WokeUp => Notification::Woke,
+            }
+        }
+    }
+
+    struct SameState;
+    impl InfallibleConverter<cat::State, cat::State> for SameState {
+        fn convert(state: &cat::State) -> cat::State {
+            *state
+        }
+    }
+
+    struct ToMood;
+    impl InfallibleConverter<cat::State, Mood> for ToMood {
+        fn convert(state: &cat::State) -> Mood {
+            match state {
+                cat::State::Asleep => Mood::Sleepy,
+                cat::State::Awake => Mood::Playful,
+            }
+        }
+    }
+
+    type Adapted = MappedDecider<
+        AdaptedDecider<
+            cat::Cat,
+            FromRequest,
+            FromNotification,
+            ToNotification,
+            SameState,
+            Notification,
+            cat::Event,
+            Request,
+            cat::Command,
+            cat::State,
+            cat::State,
+        >,
+        ToMood,
+        Request,
+        Notification,
+        Mood,
+        cat::State,
+        cat::State,
+    >;
+    type Stacked = MappedDecider<
+        MappedEventDecider<
+            MappedCommandDecider<cat::Cat, FromRequest, cat::Command>,
+            FromNotification,
+            ToNotification,
+            cat::Event,
+        >,
+        ToMood,
+        Request,
+        Notification,
+        Mood,
+        cat::State,
+        cat::State,
+    >;
+
+    let requests = [Request::Sleep, Request::Wake, Request::Feed];
+    let notifications = [
+        Notification::Slept,
+        Notification::Woke,
+        Notification::Purred,
+    ];
+    assert_eq!(Stacked::initial_state(), Adapted::initial_state());
+    for state in [cat::State::Awake, cat::State::Asleep] {
+        for request in &requests {
+            assert_eq!(
+                Stacked::decide(request, &state),
+                Adapted::decide(request, &state)
+            );
+        }
+        for notification in &notifications {
+            assert_eq!(
+                Stacked::evolve(&state, notification),
+                Adapted::evolve(&state, notification)
+            );
+        }
+        assert_eq!(Stacked::is_terminal(&state), Adapted::is_terminal(&state));
+    }
+
+    type CommandsOnly = MappedCommandDecider<cat::Cat, FromRequest, cat::Command>;
+    let mut runner = InMemoryRunner::<_, _, _, CommandsOnly>::new();
+    assert_eq!(runner.command(&Request::Sleep), [cat::Event::GotToSleep]);
+    assert!(runner.command(&Request::Feed).is_empty());
+    assert_eq!(*runner.get_state(), cat::State::Asleep);
+}
+
+#[test]
+fn mapped_event_decider_test() {
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum PowerEvent {
+        Installed { max_uses: u64 },
+        PoweredOn,
+        PoweredOff,
+        Failed,
+        Dimmed,
+    }
+
+    struct FromPowerEvent;
+    impl FallibleConverter<PowerEvent, bulb::Event> for FromPowerEvent {
+        fn convert(event: &PowerEvent) -> Option<bulb::Event> {
+            match event {
+                PowerEvent::Installed { max_uses } => Some(bulb::Event::Fitted {
+                    max_uses: *max_uses,
+                }),
+                PowerEvent::PoweredOn => Some(bulb::Event::SwitchedOn),
+                PowerEvent::PoweredOff => Some(bulb::Event::SwitchedOff),
+                PowerEvent::Failed => Some(bulb::Event::Blew),
+                PowerEvent::Dimmed => None,
+            }
+        }
+    }
+
+    struct ToPowerEvent;
+    impl InfallibleConverter<bulb::Event, PowerEvent> for ToPowerEvent {
+        fn convert(event: &bulb::Event) -> PowerEvent {
+            match event {
+                bulb::Event::Fitted { max_uses } => PowerEvent::Installed {
+                    max_uses: *max_uses,
+                },
+                bulb::Event::SwitchedOn => PowerEvent::PoweredOn,
+                bulb::Event::SwitchedOff => PowerEvent::PoweredOff,
+                bulb::Event::Blew => PowerEvent::Failed,
+            }
+        }
+    }
+
+    type PowerBulb = MappedEventDecider<bulb::Bulb, FromPowerEvent, ToPowerEvent, bulb::Event>;
+
+    let mut runner = InMemoryRunner::<_, _, _, PowerBulb>::new();
+    assert_eq!(
+        runner.command(&bulb::Command::Fit { max_uses: 1 }),
+        [PowerEvent::Installed { max_uses: 1 }]
+    );
+    assert_eq!(
+        runner.command(&bulb::Command::SwitchOn),
+        [PowerEvent::PoweredOn]
+    );
+    assert_eq!(
+        *runner.get_state(),
+        bulb::State::Working {
+            status: bulb::Status::On,
+            remaining_uses: 0
+        }
+    );
+
+    let state = *runner.get_state();
+    assert_eq!(PowerBulb::evolve(&state, &PowerEvent::Dimmed), state);
+    assert_eq!(
+        PowerBulb::fold(&[PowerEvent::Installed { max_uses: 3 }, PowerEvent::Dimmed]),
+        bulb::State::Working {
+            status: bulb::Status::Off,
+            remaining_uses: 3
+        }
+    );
+    assert_eq!(
+        PowerBulb::fold(&[PowerEvent::Dimmed]),
+        bulb::State::NotFitted
+    );
+}
+
+#[test]
+fn map3_deciders_test() {
+    #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    struct Summary {
+        uses: u64,
+        on: bool,
+        blown: bool,
+    }
+
+    const MAX_USES: u64 = 2;
+
+    struct Usage;
+    impl Decider<bulb::Command, bulb::Event, u64, Summary> for Usage {
+        fn decide(_command: &bulb::Command, _state: &Summary) -> Vec<bulb::Event> {
+            vec![]
+        }
+
+        fn evolve(state: &Summary, event: &bulb::Event) -> u64 {
+            match event {
+                bulb::Event::SwitchedOn => state.uses + 1,
+                _ => state.uses,
+            }
+        }
+
+        fn initial_state() -> u64 {
+            0
+        }
+
+        fn is_terminal(_state: &Summary) -> bool {
+            true
+        }
+    }
+
+    struct Power;
+    impl Decider<bulb::Command, bulb::Event, bool, Summary> for Power {
+        fn decide(command: &bulb::Command, state: &Summary) -> Vec<bulb::Event> {
+            match command {
+                bulb::Command::SwitchOn if !state.on && !state.blown => {
+                    vec![bulb::Event::SwitchedOn]
+                }
+                bulb::Command::SwitchOff if state.on => vec![bulb::Event::SwitchedOff],
+                _ => vec![],
+            }
+        }
+
+        fn evolve(state: &Summary, event: &bulb::Event) -> bool {
+            match event {
+                bulb::Event::SwitchedOn => true,
+                bulb::Event::SwitchedOff | bulb::Event::Blew => false,
+                _ => state.on,
+            }
+        }
+
+        fn initial_state() -> bool {
+            false
+        }
+
+        fn is_terminal(state: &Summary) -> bool {
+            state.blown
+        }
+    }
+
+    struct Blown;
+    impl Decider<bulb::Command, bulb::Event, bool, Summary> for Blown {
+        fn decide(command: &bulb::Command, state: &Summary) -> Vec<bulb::Event> {
+            match command {
+                bulb::Command::SwitchOn if !state.on && !state.blown && state.uses == MAX_USES => {
+                    vec![bulb::Event::Blew]
+                }
+                _ => vec![],
+            }
+        }
+
+        fn evolve(state: &Summary, event: &bulb::Event) -> bool {
+            state.blown || *event == bulb::Event::Blew
+        }
+
+        fn initial_state() -> bool {
+            false
+        }
+
+        fn is_terminal(state: &Summary) -> bool {
+            state.blown
+        }
+    }
+
+    struct Summarize;
+    impl InfallibleConverter<(u64, bool, bool), Summary> for Summarize {
+        fn convert((uses, on, blown): &(u64, bool, bool)) -> Summary {
+            Summary {
+                uses: *uses,
+                on: *on,
+                blown: *blown,
+            }
+        }
+    }
+
+    type BulbSummary = Map3Deciders<
+        Usage,
+        Power,
+        Blown,
+        Summarize,
+        bulb::Command,
+        bulb::Event,
+        Summary,
+        u64,
+        bool,
+        bool,
+        Summary,
+    >;
+
+    assert_eq!(BulbSummary::initial_state(), Summary::default());
+
+    let mut runner = InMemoryRunner::<_, _, _, BulbSummary>::new();
+    for _ in 0..MAX_USES {
+        assert_eq!(
+            runner.command(&bulb::Command::SwitchOn),
+            [bulb::Event::SwitchedOn]
+        );
+        assert!(!BulbSummary::is_terminal(runner.get_state()));
+        assert_eq!(
+            runner.command(&bulb::Command::SwitchOff),
+            [bulb::Event::SwitchedOff]
+        );
+    }
+    assert_eq!(
+        runner.command(&bulb::Command::SwitchOn),
+        [bulb::Event::SwitchedOn, bulb::Event::Blew]
+    );
+    assert_eq!(
+        *runner.get_state(),
+        Summary {
+            uses: MAX_USES + 1,
+            on: false,
+            blown: true
+        }
+    );
+    assert!(BulbSummary::is_terminal(runner.get_state()));
+    assert!(runner.command(&bulb::Command::SwitchOn).is_empty());
+}
+
+#[test]
+fn compose3_test_1() {
+    type CatBulbNeutral = Composed3Deciders<
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        bulb::Bulb,
+        bulb::Command,
+        bulb::Event,
+        bulb::State,
+        NeutralDecider,
+        (),
+        (),
+        (),
+    >;
+    type Command = OneOf3<cat::Command, bulb::Command, ()>;
+    type Event = OneOf3<cat::Event, bulb::Event, ()>;
+    type State = (cat::State, bulb::State, ());
+    use OneOf3::*;
+    assert_eq!(
+        run_decider::<Command, Event, State, CatBulbNeutral>(&[], &First(cat::Command::GetToSleep)),
+        [First(cat::Event::GotToSleep)]
+    );
+    assert_eq!(
+        run_decider::<Command, Event, State, CatBulbNeutral>(
+            &[],
+            &Second(bulb::Command::Fit { max_uses: 5 })
+        ),
+        [Second(bulb::Event::Fitted { max_uses: 5 })]
+    );
+    assert_eq!(
+        run_decider::<Command, Event, State, CatBulbNeutral>(&[], &Third(())),
+        []
+    );
+    assert_eq!(
+        CatBulbNeutral::fold(&[
+            First(cat::Event::GotToSleep),
+            Second(bulb::Event::Fitted { max_uses: 5 }),
+            Third(()),
+        ]),
+        (
+            cat::State::Asleep,
+            bulb::State::Working {
+                status: bulb::Status::Off,
+                remaining_uses: 5
+            },
+            ()
+        )
+    );
+    assert!(!CatBulbNeutral::is_terminal(
+        &CatBulbNeutral::initial_state()
+    ));
+}
+
+#[test]
+fn many_non_copy_test() {
+    use update_server::{Command, Event, State, UpdateServer};
+    type ManyServers = ManyDecider<UpdateServer, Command, Event, State>;
+
+    let query = Command::QueryForUpdate {
+        current_version: "1.0.0".to_string(),
+    };
+    let download = Command::DownloadUpdate {
+        desired_version: "2.0.0".to_string(),
+    };
+    let mut runner = InMemoryRunner::<_, _, _, ManyServers>::new();
+    assert_eq!(
+        runner.command(&("alice".to_string(), query.clone())),
+        [(
+            "alice".to_string(),
+            Event::UpdateAvailable {
+                new_versions: vec!["1.1.0", "2.0.0"]
+            }
+        )]
+    );
+    assert_eq!(runner.command(&("bob".to_string(), download.clone())), []);
+    assert_eq!(
+        runner.command(&("alice".to_string(), download)),
+        [(
+            "alice".to_string(),
+            Event::GotUpdateData { version: "2.0.0" }
+        )]
+    );
+    assert_eq!(
+        runner.get_state().get("alice"),
+        Some(&State::DownloadReady { version: "2.0.0" })
+    );
+    assert_eq!(runner.get_state().get("bob"), None);
+
+    type ServerAndCat = ComposedDeciders<
+        UpdateServer,
+        Command,
+        Event,
+        State,
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+    >;
+    assert_eq!(
+        ServerAndCat::fold(&[
+            Either::Left(Event::AlreadyUpToDate),
+            Either::Right(cat::Event::GotToSleep),
+        ]),
+        (State::NoNewUpdate, cat::State::Asleep)
+    );
+    assert_eq!(
+        ServerAndCat::decide(&Either::Left(query), &ServerAndCat::initial_state()),
+        [Either::Left(Event::UpdateAvailable {
+            new_versions: vec!["1.1.0", "2.0.0"]
+        })]
+    );
+}
+
+#[test]
+fn adapter_test() {
+    enum AdaptedCommand {
+        Sleep,
+        Wake,
+        Feed,
+    }
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum AdaptedEvent {
+        Slept,
+        Woke,
+        Fed,
+    }
+    #[derive(Debug, PartialEq)]
+    enum AdaptedState {
+        Slep,
+        Wake,
+    }
+
+    struct CatAdapter;
+    impl Adapter for CatAdapter {
+        type Command = AdaptedCommand;
+        type Event = AdaptedEvent;
+        type State = AdaptedState;
+        type DeciderCommand = cat::Command;
+        type DeciderEvent = cat::Event;
+        type DeciderState = cat::State;
+
+        fn convert_command(command: &AdaptedCommand) -> Option<cat::Command> {
+            match command {
+                AdaptedCommand::Sleep => Some(cat::Command::GetToSleep),
+                AdaptedCommand::Wake => Some(cat::Command::WakeUp),
+                AdaptedCommand::Feed => None,
+            }
+        }
+
+        fn convert_event_in(event: &AdaptedEvent) -> Option<cat::Event> {
+            match event {
+                AdaptedEvent::Slept => Some(cat::Event::GotToSleep),
+                AdaptedEvent::Woke => Some(cat::Event::WokeUp),
+                AdaptedEvent::Fed => None,
+            }
+        }
+
+        fn convert_event_out(event: &cat::Event) -> AdaptedEvent {
+            match event {
+                cat::Event::GotToSleep => AdaptedEvent::Slept,
+                cat::Event::WokeUp => AdaptedEvent::Woke,
+            }
+        }
+
+        fn convert_state(state: &AdaptedState) -> cat::State {
+            match state {
+                AdaptedState::Slep => cat::State::Asleep,
+                AdaptedState::Wake => cat::State::Awake,
+            }
+        }
+    }
+
+    struct CatStateOutConverter;
+    impl InfallibleConverter<cat::State, AdaptedState> for CatStateOutConverter {
+        fn convert(input: &cat::State) -> AdaptedState {
+            match input {
+                cat::State::Asleep => AdaptedState::Slep,
+                cat::State::Awake => AdaptedState::Wake,
+            }
+        }
+    }
+
+    type AdaptedMappedCat = MappedDecider<
+        Adapted<cat::Cat, CatAdapter>,
+        CatStateOutConverter,
+        AdaptedCommand,
+        AdaptedEvent,
+        AdaptedState,
+        cat::State,
+        AdaptedState,
+    >;
+
+    assert_eq!(
+        run_decider::<AdaptedCommand, AdaptedEvent, AdaptedState, AdaptedMappedCat>(
+            &[],
+            &AdaptedCommand::Sleep
+        ),
+        &[AdaptedEvent::Slept]
+    );
+    assert_eq!(
+        run_decider::<AdaptedCommand, AdaptedEvent, AdaptedState, AdaptedMappedCat>(
+            &[AdaptedEvent::Slept, AdaptedEvent::Fed],
+            &AdaptedCommand::Wake
+        ),
+        &[AdaptedEvent::Woke]
+    );
+    assert_eq!(
+        run_decider::<AdaptedCommand, AdaptedEvent, AdaptedState, AdaptedMappedCat>(
+            &[],
+            &AdaptedCommand::Feed
+        ),
+        &[]
+    );
+    assert_eq!(
+        AdaptedMappedCat::fold(&[AdaptedEvent::Slept, AdaptedEvent::Woke]),
+        AdaptedState::Wake
+    );
+}
+
+#[test]
+fn try_adapted_test() {
+    #[derive(Clone, Debug, PartialEq)]
+    enum Pet {
+        Cat(cat::State),
+        Adopted { by: String },
+    }
+
+    struct SameCommand;
+    impl FallibleConverter<cat::Command, cat::Command> for SameCommand {
+        fn convert(command: &cat::Command) -> Option<cat::Command> {
+            Some(*command)
+        }
+    }
+
+    struct SameEvent;
+    impl FallibleConverter<cat::Event, cat::Event> for SameEvent {
+        fn convert(event: &cat::Event) -> Option<cat::Event> {
+            Some(*event)
+        }
+    }
+    impl InfallibleConverter<cat::Event, cat::Event> for SameEvent {
+        fn convert(event: &cat::Event) -> cat::Event {
+            *event
+        }
+    }
+
+    struct PetToCat;
+    impl FallibleConverter<Pet, cat::State> for PetToCat {
+        fn convert(pet: &Pet) -> Option<cat::State> {
+            match pet {
+                Pet::Cat(state) => Some(*state),
+                Pet::Adopted { .. } => None,
+            }
+        }
+    }
+
+    struct CatToPet;
+    impl InfallibleConverter<cat::State, Pet> for CatToPet {
+        fn convert(state: &cat::State) -> Pet {
+            Pet::Cat(*state)
+        }
+    }
+
+    type PetCat = TryAdaptedDecider<
+        cat::Cat,
+        SameCommand,
+        SameEvent,
+        SameEvent,
+        PetToCat,
+        CatToPet,
+        cat::Event,
+        cat::Event,
+        cat::Command,
+        cat::Command,
+        Pet,
+        cat::State,
+    >;
+
+    assert_eq!(PetCat::initial_state(), Pet::Cat(cat::State::Awake));
+    assert_eq!(
+        PetCat::decide(&cat::Command::GetToSleep, &PetCat::initial_state()),
+        [cat::Event::GotToSleep]
+    );
+    assert_eq!(
+        PetCat::fold(&[cat::Event::GotToSleep]),
+        Pet::Cat(cat::State::Asleep)
+    );
+    assert!(!PetCat::is_terminal(&Pet::Cat(cat::State::Asleep)));
+
+    let adopted = Pet::Adopted {
+        by: "Alice".to_string(),
+    };
+    for command in [cat::Command::GetToSleep, cat::Command::WakeUp] {
+        assert!(PetCat::decide(&command, &adopted).is_empty());
+    }
+    for event in [cat::Event::GotToSleep, cat::Event::WokeUp] {
+        assert_eq!(PetCat::evolve(&adopted, &event), adopted);
+    }
+    assert!(PetCat::is_terminal(&adopted));
+}
+
+#[test]
+fn dead_letter_test() {
+    use cat_bulb::CatBulb;
+    struct CatOfCatBulb;
+    impl Adapter for CatOfCatBulb {
+        type Command = Either<cat::Command, bulb::Command>;
+        type Event = Either<cat::Event, bulb::Event>;
+        type State = cat::State;
+        type DeciderCommand = cat::Command;
+        type DeciderEvent = cat::Event;
+        type DeciderState = cat::State;
+
+        fn convert_command(command: &Self::Command) -> Option<cat::Command> {
+            match command {
+                Either::Left(command) => Some(*command),
+                Either::Right(_) => None,
+            }
+        }
+
+        fn convert_event_in(event: &Self::Event) -> Option<cat::Event> {
+            match event {
+                Either::Left(event) => Some(*event),
+                Either::Right(_) => None,
+            }
+        }
+
+        fn convert_event_out(event: &cat::Event) -> Self::Event {
+            Either::Left(*event)
+        }
+
+        fn convert_state(state: &cat::State) -> cat::State {
+            *state
+        }
+    }
+    type ReportingCat = Adapted<cat::Cat, Reporting<CatOfCatBulb>>;
+    type Reports = Reporting<CatOfCatBulb>;
+    use Either::*;
+
+    // The commands of the `compose_process` scenario, given to the cat alone.
+    let commands = [
+        Right(bulb::Command::Fit { max_uses: 5 }),
+        Left(cat::Command::GetToSleep),
+        Left(cat::Command::WakeUp),
+        Right(bulb::Command::SwitchOn),
+        Right(bulb::Command::SwitchOff),
+    ];
+    let mut composed = InMemoryRunner::<_, _, _, CatBulb>::new();
+    let mut cat = InMemoryRunner::<_, _, _, ReportingCat>::new();
+    let mut events = vec![];
+    for command in &commands {
+        events.extend(composed.command(command));
+        cat.command(command);
+    }
+    assert_eq!(*cat.get_state(), cat::State::Awake);
+    assert_eq!(
+        Reports::drained_dead_letters(),
+        [
+            DeadLetter::Command(Right(bulb::Command::Fit { max_uses: 5 })),
+            DeadLetter::Command(Right(bulb::Command::SwitchOn)),
+            DeadLetter::Command(Right(bulb::Command::SwitchOff)),
+        ]
+    );
+
+    assert_eq!(ReportingCat::fold(&events), cat::State::Awake);
+    assert_eq!(
+        Reports::drained_dead_letters(),
+        [
+            DeadLetter::Event(Right(bulb::Event::Fitted { max_uses: 5 })),
+            DeadLetter::Event(Right(bulb::Event::SwitchedOn)),
+            DeadLetter::Event(Right(bulb::Event::SwitchedOff)),
+        ]
+    );
+    assert!(Reports::drained_dead_letters().is_empty());
+    assert_eq!(Reports::dropped(), 6);
+
+    Reports::reset();
+    assert_eq!(Reports::dropped(), 0);
+    for _ in 0..MAX_DEAD_LETTERS + 1 {
+        ReportingCat::evolve(&cat::State::Awake, &Right(bulb::Event::Blew));
+    }
+    assert_eq!(Reports::dropped(), MAX_DEAD_LETTERS as u64 + 1);
+    assert_eq!(Reports::drained_dead_letters().len(), MAX_DEAD_LETTERS);
+}
+
+#[test]
+fn applied_decider_test() {
+    struct Config {
+        scale: u64,
+    }
+    fn config() -> Config {
+        Config { scale: 3 }
+    }
+
+    struct Count;
+    impl Decider<u64, u64, u64, u64> for Count {
+        fn decide(command: &u64, _state: &u64) -> Vec<u64> {
+            vec![*command]
+        }
+
+        fn evolve(state: &u64, event: &u64) -> u64 {
+            state + event
+        }
+
+        fn initial_state() -> u64 {
+            0
+        }
+
+        fn is_terminal(_state: &u64) -> bool {
+            true
+        }
+    }
+
+    type Scaler = Box<dyn Fn(u64) -> u64>;
+    fn scaler() -> Scaler {
+        let scale = config().scale;
+        Box::new(move |count| count * scale)
+    }
+
+    struct Scale;
+    impl Decider<u64, u64, Scaler, u64> for Scale {
+        fn decide(_command: &u64, _state: &u64) -> Vec<u64> {
+            vec![]
+        }
+
+        fn evolve(_state: &u64, _event: &u64) -> Scaler {
+            scaler()
+        }
+
+        fn initial_state() -> Scaler {
+            scaler()
+        }
+
+        fn is_terminal(_state: &u64) -> bool {
+            true
+        }
+    }
+
+    type Scaled = AppliedDecider<Scale, Count, u64, u64, u64, u64, u64, Scaler>;
+    assert_eq!(Scaled::initial_state(), 0);
+    assert_eq!(Scaled::decide(&2, &0), [2]);
+    assert_eq!(Scaled::evolve(&4, &2), 18);
+    assert!(Scaled::is_terminal(&0));
+
+    struct Offset {
+        by: u64,
+    }
+    impl Apply<u64, u64> for Offset {
+        fn apply(self, count: u64) -> u64 {
+            count + self.by
+        }
+    }
+
+    struct OffsetByEvent;
+    impl Decider<u64, u64, Offset, u64> for OffsetByEvent {
+        fn decide(_command: &u64, _state: &u64) -> Vec<u64> {
+            vec![]
+        }
+
+        fn evolve(_state: &u64, event: &u64) -> Offset {
+            Offset { by: *event }
+        }
+
+        fn initial_state() -> Offset {
+            Offset { by: 0 }
+        }
+
+        fn is_terminal(_state: &u64) -> bool {
+            true
+        }
+    }
+
+    type OffsetCount = AppliedDecider<OffsetByEvent, Count, u64, u64, u64, u64, u64, Offset>;
+    assert_eq!(OffsetCount::evolve(&4, &2), 8);
+
+    struct Double;
+    impl Decider<u64, u64, fn(u64) -> u64, u64> for Double {
+        fn decide(_command: &u64, _state: &u64) -> Vec<u64> {
+            vec![]
+        }
+
+        fn evolve(_state: &u64, _event: &u64) -> fn(u64) -> u64 {
+            |count| count * 2
+        }
+
+        fn initial_state() -> fn(u64) -> u64 {
+            |count| count * 2
+        }
+
+        fn is_terminal(_state: &u64) -> bool {
+            true
+        }
+    }
+
+    type Doubled = AppliedDecider<Double, Count, u64, u64, u64, u64, u64>;
+    assert_eq!(Doubled::evolve(&4, &2), 12);
+}
+
+#[test]
+fn map2_either_deciders_test() {
+    #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    struct Lamp {
+        on: bool,
+        flickers: u64,
+    }
+
+    enum Telemetry {
+        Flicker,
+    }
+
+    struct Power;
+    impl Decider<bulb::Command, bulb::Event, bool, Lamp> for Power {
+        fn decide(command: &bulb::Command, state: &Lamp) -> Vec<bulb::Event> {
+            match command {
+                bulb::Command::SwitchOn if !state.on => vec![bulb::Event::SwitchedOn],
+                bulb::Command::SwitchOff if state.on => vec![bulb::Event::SwitchedOff],
+                _ => vec![],
+            }
+        }
+
+        fn evolve(state: &Lamp, event: &bulb::Event) -> bool {
+            match event {
+                bulb::Event::SwitchedOn => true,
+                bulb::Event::SwitchedOff => false,
+                _ => state.on,
+            }
+        }
+
+        fn initial_state() -> bool {
+            false
+        }
+
+        fn is_terminal(_state: &Lamp) -> bool {
+            false
+        }
+    }
+
+    struct Flickers;
+    impl Decider<Telemetry, bulb::Event, u64, Lamp> for Flickers {
+        fn decide(command: &Telemetry, state: &Lamp) -> Vec<bulb::Event> {
+            match (command, state.on) {
+                (Telemetry::Flicker, true) => {
+                    vec![bulb::Event::SwitchedOff, bulb::Event::SwitchedOn]
+                }
+                (Telemetry::Flicker, false) => {
+                    vec![bulb::Event::SwitchedOn, bulb::Event::SwitchedOff]
+                }
+            }
+        }
+
+        fn evolve(state: &Lamp, event: &bulb::Event) -> u64 {
+            match event {
+                bulb::Event::SwitchedOn | bulb::Event::SwitchedOff => state.flickers + 1,
+                _ => state.flickers,
+            }
+        }
+
+        fn initial_state() -> u64 {
+            0
+        }
+
+        fn is_terminal(_state: &Lamp) -> bool {
+            false
+        }
+    }
+
+    struct ToLamp;
+    impl InfallibleConverter<(bool, u64), Lamp> for ToLamp {
+        fn convert((on, flickers): &(bool, u64)) -> Lamp {
+            Lamp {
+                on: *on,
+                flickers: *flickers,
+            }
+        }
+    }
+
+    type Monitored = Map2EitherDeciders<
+        Power,
+        bulb::Command,
+        Flickers,
+        Telemetry,
+        ToLamp,
+        bulb::Event,
+        Lamp,
+        bool,
+        u64,
+        Lamp,
+    >;
+    use Either::*;
+
+    let mut runner = InMemoryRunner::<_, _, _, Monitored>::new();
+    assert_eq!(
+        runner.command(&Left(bulb::Command::SwitchOn)),
+        [bulb::Event::SwitchedOn]
+    );
+    assert_eq!(
+        *runner.get_state(),
+        Lamp {
+            on: true,
+            flickers: 1
+        }
+    );
+    assert!(runner.command(&Left(bulb::Command::SwitchOn)).is_empty());
+    assert_eq!(
+        runner.command(&Right(Telemetry::Flicker)),
+        [bulb::Event::SwitchedOff, bulb::Event::SwitchedOn]
+    );
+    assert_eq!(
+        *runner.get_state(),
+        Lamp {
+            on: true,
+            flickers: 3
+        }
+    );
+    assert_eq!(
+        runner.command(&Left(bulb::Command::SwitchOff)),
+        [bulb::Event::SwitchedOff]
+    );
+    assert_eq!(
+        *runner.get_state(),
+        Lamp {
+            on: false,
+            flickers: 4
+        }
+    );
+    assert!(!Monitored::is_terminal(runner.get_state()));
+}
+
+#[test]
+fn terminal_policy_test() {
+    type CatAndBulbAll = ComposedDeciders<
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        bulb::Bulb,
+        bulb::Command,
+        bulb::Event,
+        bulb::State,
+    >;
+    type CatAndBulbAny = ComposedDeciders<
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        bulb::Bulb,
+        bulb::Command,
+        bulb::Event,
+        bulb::State,
+        AnyTerminal,
+    >;
+    use Either::*;
+
+    let mut runner = InMemoryRunner::<_, _, _, CatAndBulbAny>::new();
+    runner.command(&Right(bulb::Command::Fit { max_uses: 0 }));
+    assert!(!CatAndBulbAny::is_terminal(runner.get_state()));
+    assert_eq!(
+        runner.command(&Right(bulb::Command::SwitchOn)),
+        [Right(bulb::Event::Blew)]
+    );
+    assert_eq!(runner.get_state().1, bulb::State::Blown);
+    assert!(CatAndBulbAny::is_terminal(runner.get_state()));
+    assert!(!CatAndBulbAll::is_terminal(runner.get_state()));
+
+    struct Both;
+    impl InfallibleConverter<(cat::State, bulb::State), (cat::State, bulb::State)> for Both {
+        fn convert(states: &(cat::State, bulb::State)) -> (cat::State, bulb::State) {
+            *states
+        }
+    }
+    struct CatPart;
+    impl Decider<(), (), cat::State, (cat::State, bulb::State)> for CatPart {
+        fn decide(_command: &(), _state: &(cat::State, bulb::State)) -> Vec<()> {
+            vec![]
+        }
+
+        fn evolve(state: &(cat::State, bulb::State), _event: &()) -> cat::State {
+            state.0
+        }
+
+        fn initial_state() -> cat::State {
+            cat::Cat::initial_state()
+        }
+
+        fn is_terminal(state: &(cat::State, bulb::State)) -> bool {
+            cat::Cat::is_terminal(&state.0)
+        }
+    }
+    struct BulbPart;
+    impl Decider<(), (), bulb::State, (cat::State, bulb::State)> for BulbPart {
+        fn decide(_command: &(), _state: &(cat::State, bulb::State)) -> Vec<()> {
+            vec![]
+        }
+
+        fn evolve(state: &(cat::State, bulb::State), _event: &()) -> bulb::State {
+            state.1
+        }
+
+        fn initial_state() -> bulb::State {
+            bulb::Bulb::initial_state()
+        }
+
+        fn is_terminal(state: &(cat::State, bulb::State)) -> bool {
+            bulb::Bulb::is_terminal(&state.1)
+        }
+    }
+    type Parts<T> = Map2Deciders<
+        CatPart,
+        BulbPart,
+        Both,
+        (),
+        (),
+        (cat::State, bulb::State),
+        cat::State,
+        bulb::State,
+        (cat::State, bulb::State),
+        T,
+    >;
+    assert!(Parts::<AnyTerminal>::is_terminal(runner.get_state()));
+    assert!(!Parts::<AllTerminal>::is_terminal(runner.get_state()));
+    type EitherParts<T> = Map2EitherDeciders<
+        CatPart,
+        (),
+        BulbPart,
+        (),
+        Both,
+        (),
+        (cat::State, bulb::State),
+        cat::State,
+        bulb::State,
+        (cat::State, bulb::State),
+        T,
+    >;
+    assert!(EitherParts::<AnyTerminal>::is_terminal(runner.get_state()));
+    assert!(!EitherParts::<AllTerminal>::is_terminal(runner.get_state()));
+
+    // Policies over more than two deciders apply to each decider in turn.
+    struct FirstTwo;
+    impl InfallibleConverter<(cat::State, bulb::State, bulb::State), (cat::State, bulb::State)>
+        for FirstTwo
+    {
+        fn convert(states: &(cat::State, bulb::State, bulb::State)) -> (cat::State, bulb::State) {
+            (states.0, states.1)
+        }
+    }
+    type ThreeParts<T> = Map3Deciders<
+        CatPart,
+        BulbPart,
+        BulbPart,
+        FirstTwo,
+        (),
+        (),
+        (cat::State, bulb::State),
+        cat::State,
+        bulb::State,
+        bulb::State,
+        (cat::State, bulb::State),
+        T,
+    >;
+    assert!(ThreeParts::<AnyTerminal>::is_terminal(runner.get_state()));
+    assert!(!ThreeParts::<AllTerminal>::is_terminal(runner.get_state()));
+    type CatAndBulbs<T> = Composed3Deciders<
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        bulb::Bulb,
+        bulb::Command,
+        bulb::Event,
+        bulb::State,
+        bulb::Bulb,
+        bulb::Command,
+        bulb::Event,
+        bulb::State,
+        T,
+    >;
+    let (cat, bulb) = *runner.get_state();
+    assert!(CatAndBulbs::<AnyTerminal>::is_terminal(&(cat, bulb, bulb)));
+    assert!(!CatAndBulbs::<AllTerminal>::is_terminal(&(cat, bulb, bulb)));
+    type CatAndBulbsAndCat<T> = Composed4Deciders<
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        bulb::Bulb,
+        bulb::Command,
+        bulb::Event,
+        bulb::State,
+        bulb::Bulb,
+        bulb::Command,
+        bulb::Event,
+        bulb::State,
+        cat::Cat,
+        cat::Command,
+        cat::Event,
+        cat::State,
+        T,
+    >;
+    assert!(CatAndBulbsAndCat::<AnyTerminal>::is_terminal(&(
+        cat, bulb, bulb, cat
+    )));
+    assert!(!CatAndBulbsAndCat::<AllTerminal>::is_terminal(&(
+        cat, bulb, bulb, cat
+    )));
+}
+
+#[test]
+fn dimapped_test() {
+    enum AdaptedCommand {
+        Sleep,
+        Wake,
+    }
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum AdaptedEvent {
+        Slept,
+        Woke,
+    }
+    #[derive(Debug, PartialEq)]
+    enum AdaptedState {
+        Slep,
+        Wake,
+    }
+
+    struct CatAdapter;
+    impl Adapter for CatAdapter {
+        type Command = AdaptedCommand;
+        type Event = AdaptedEvent;
+        type State = cat::State;
+        type DeciderCommand = cat::Command;
+        type DeciderEvent = cat::Event;
+        type DeciderState = cat::State;
+
+        fn convert_command(command: &AdaptedCommand) -> Option<cat::Command> {
+            match command {
+                AdaptedCommand::Sleep => Some(cat::Command::GetToSleep),
+                AdaptedCommand::Wake => Some(cat::Command::WakeUp),
+            }
+        }
+
+        fn convert_event_in(event: &AdaptedEvent) -> Option<cat::Event> {
+            match event {
+                AdaptedEvent::Slept => Some(cat::Event::GotToSleep),
+                AdaptedEvent::Woke => Some(cat::Event::WokeUp),
+            }
+        }
+
+        fn convert_event_out(event: &cat::Event) -> AdaptedEvent {
+            match event {
+                cat::Event::GotToSleep => AdaptedEvent::Slept,
+                cat::Event::WokeUp => AdaptedEvent::Woke,
+            }
+        }
+
+        fn convert_state(state: &cat::State) -> cat::State {
+            *state
+        }
+    }
+
+    struct CatStateInConverter;
+    impl InfallibleConverter<AdaptedState, cat::State> for CatStateInConverter {
+        fn convert(input: &AdaptedState) -> cat::State {
+            match input {
+                AdaptedState::Slep => cat::State::Asleep,
+                AdaptedState::Wake => cat::State::Awake,
+            }
+        }
+    }
+
+    struct CatStateOutConverter;
+    impl InfallibleConverter<cat::State, AdaptedState> for CatStateOutConverter {
+        fn convert(input: &cat::State) -> AdaptedState {
+            match input {
+                cat::State::Asleep => AdaptedState::Slep,
+                cat::State::Awake => AdaptedState::Wake,
+            }
+        }
+    }
+
+    type DimappedCat = DimappedDecider<
+        Adapted<cat::Cat, CatAdapter>,
+        CatStateInConverter,
+        CatStateOutConverter,
+        cat::State,
+        cat::State,
+    >;
+
+    assert_eq!(
+        run_decider::<AdaptedCommand, AdaptedEvent, AdaptedState, DimappedCat>(
+            &[],
+            &AdaptedCommand::Sleep
+        ),
+        &[AdaptedEvent::Slept]
+    );
+    assert_eq!(
+        run_decider::<AdaptedCommand, AdaptedEvent, AdaptedState, DimappedCat>(
+            &[AdaptedEvent::Slept],
+            &AdaptedCommand::Wake
+        ),
+        &[AdaptedEvent::Woke]
+    );
+    assert_eq!(
+        run_decider::<AdaptedCommand, AdaptedEvent, AdaptedState, DimappedCat>(
+            &[AdaptedEvent::Slept, AdaptedEvent::Woke],
+            &AdaptedCommand::Sleep
+        ),
+        &[AdaptedEvent::Slept]
+    );
+    assert_eq!(DimappedCat::initial_state(), AdaptedState::Wake);
+    assert_eq!(
+        DimappedCat::fold(&[AdaptedEvent::Slept]),
+        AdaptedState::Slep
+    );
+}
+
+#[test]
+fn decider_token_test() {
+    use cat_bulb::CatAndBulb;
+    use Either::*;
+    let events = [Left(cat::Event::GotToSleep)];
+    assert_eq!(
+        compose::<cat::Cat, bulb::Bulb>().run(&events, &Left(cat::Command::WakeUp)),
+        run_decider::<_, _, _, CatAndBulb>(&events, &Left(cat::Command::WakeUp))
+    );
+    assert_eq!(
+        compose::<cat::Cat, bulb::Bulb>().run(&[], &Right(bulb::Command::Fit { max_uses: 5 })),
+        [Right(bulb::Event::Fitted { max_uses: 5 })]
+    );
+
+    struct Sleepy;
+    impl Adapter for Sleepy {
+        type Command = bool;
+        type Event = cat::Event;
+        type State = cat::State;
+        type DeciderCommand = cat::Command;
+        type DeciderEvent = cat::Event;
+        type DeciderState = cat::State;
+
+        fn convert_command(sleep: &bool) -> Option<cat::Command> {
+            sleep.then_some(cat::Command::GetToSleep)
+        }
+
+        fn convert_event_in(event: &cat::Event) -> Option<cat::Event> {
+            Some(*event)
+        }
+
+        fn convert_event_out(event: &cat::Event) -> cat::Event {
+            *event
+        }
+
+        fn convert_state(state: &cat::State) -> cat::State {
+            *state
+        }
+    }
+    let sleepy = adapt::<cat::Cat, Sleepy>();
+    assert_eq!(sleepy.run(&[], &true), [cat::Event::GotToSleep]);
+    assert!(sleepy.run(&[], &false).is_empty());
+
+    struct IsAsleep;
+    impl InfallibleConverter<cat::State, bool> for IsAsleep {
+        fn convert(state: &cat::State) -> bool {
+            *state == cat::State::Asleep
+        }
+    }
+    impl InfallibleConverter<cat::State, cat::State> for IsAsleep {
+        fn convert(state: &cat::State) -> cat::State {
+            *state
+        }
+    }
+
+    // The new state type is inferred from how the token is used, and tokens are plain values.
+    let asleep = map::<cat::Cat, IsAsleep>();
+    let copy = asleep;
+    let is_asleep: bool = asleep.evolve(&cat::State::Awake, &cat::Event::GotToSleep);
+    assert!(is_asleep);
+    assert!(!copy.evolve::<_, _, bool, _, _>(&cat::State::Asleep, &cat::Event::WokeUp));
+    assert_eq!(
+        copy.run::<_, _, cat::State, _, _>(&[cat::Event::GotToSleep], &cat::Command::WakeUp),
+        [cat::Event::WokeUp]
+    );
+    assert!(format!("{copy:?}").starts_with("MappedToken<"));
+    assert!(format!("{sleepy:?}").starts_with("DeciderToken<"));
+    assert_eq!(
+        compose::<cat::Cat, bulb::Bulb>().fold(&events),
+        CatAndBulb::fold(&events)
+    );
+}
+
+#[test]
+fn nested_publish_test() {
+    let mut bus = SimpleEventBus::new();
+    let this_bus: Rc<RefCell<Weak<SimpleEventBus<u32>>>> = Rc::new(RefCell::new(Weak::new()));
+    let delivered = Rc::new(RefCell::new(vec![]));
+
+    let bus_slot = this_bus.clone();
+    let seen = delivered.clone();
+    bus.subscribe("numbers", move |n: &u32| {
+        seen.borrow_mut().push(("first", *n));
+        if *n < 3 {
+            let bus = bus_slot.borrow().upgrade().unwrap();
+            assert!(bus.publish("numbers", &(n + 1)).is_ok());
+        }
+    });
+    let dropped = bus.subscribe_channel("numbers");
+    drop(dropped);
+    let seen = delivered.clone();
+    bus.subscribe("numbers", move |n: &u32| {
+        seen.borrow_mut().push(("second", *n))
+    });
+
+    let bus = Rc::new(bus);
+    *this_bus.borrow_mut() = Rc::downgrade(&bus);
+    let error = bus.publish("numbers", &1).unwrap_err();
+    assert_eq!(error.topic, "numbers");
+    // Each event reaches every subscriber before the events published by its callbacks.
+    assert_eq!(
+        *delivered.borrow(),
+        [
+            ("first", 1),
+            ("second", 1),
+            ("first", 2),
+            ("second", 2),
+            ("first", 3),
+            ("second", 3),
+        ]
+    );
+    assert!(bus.publish("numbers", &3).is_ok());
+}
+
+#[test]
+fn version_newtypes_test() {
+    let version = StreamVersion::INITIAL.next().next();
+    assert_eq!(version, StreamVersion::from(2));
+    assert_eq!(version + 3, StreamVersion::from(5));
+    assert_eq!(StreamVersion::from(5) - version, 3);
+    assert_eq!(u64::from(version), 2);
+    assert_eq!(version.to_string(), "2");
+
+    let position = GlobalPosition::from(10);
+    assert_eq!(position + 2, GlobalPosition::from(12));
+    assert_eq!(GlobalPosition::from(12) - position, 2);
+    assert_eq!(ConsistencyToken(position).to_string(), "10");
+    assert_eq!("10".parse(), Ok(ConsistencyToken(position)));
+
+    #[cfg(feature = "serde")]
+    {
+        assert_eq!(serde_json::to_string(&version).unwrap(), "2");
+        assert_eq!(
+            serde_json::from_str::<GlobalPosition>("10").unwrap(),
+            position
+        );
+    }
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "is later than")]
+fn version_moving_backwards_test() {
+    let _ = StreamVersion::from(1) - StreamVersion::from(2);
+}
+
+#[test]
+fn event_log_out_of_order_test() {
+    /// A log that goes back to an earlier event after delivering a later one.
+    struct ShuffledLog(Vec<bulb::Event>);
+
+    impl EventLog<bulb::Event> for ShuffledLog {
+        fn events_from<'a>(
+            &'a self,
+            _position: GlobalPosition,
+        ) -> impl Iterator<Item = (GlobalPosition, &'a bulb::Event)>
+        where
+            bulb::Event: 'a,
+        {
+            [0, 1, 0]
+                .into_iter()
+                .map(|i| (GlobalPosition::from(i), &self.0[i as usize]))
+        }
+    }
+
+    let log = ShuffledLog(vec![
+        bulb::Event::Fitted { max_uses: 5 },
+        bulb::Event::SwitchedOn,
+        bulb::Event::SwitchedOff,
+    ]);
+    let mut runner = ProjectionRunner::<TotalSwitchOns, bulb::Event, _>::new(
+        "switch_ons",
+        InMemoryCheckpointStore::new(),
+    );
+    let out_of_order = PositionError {
+        minimum: GlobalPosition::from(2),
+        found: GlobalPosition::from(0),
+    };
+    assert_eq!(
+        runner.catch_up(&log),
+        Err(CatchUpError::Position(out_of_order))
+    );
+    // The events before the position that went back are kept.
+    assert_eq!(runner.next_position(), GlobalPosition::from(2));
+    assert_eq!(*runner.view(), 1);
+
+    let mut checkpoint = Checkpoint {
+        next_position: GlobalPosition::from(2),
+        view: 1,
+    };
+    assert_eq!(
+        checkpoint.advance_past(GlobalPosition::from(0)),
+        Err(out_of_order)
+    );
+    assert_eq!(checkpoint.next_position, GlobalPosition::from(2));
+
+    #[cfg(feature = "sqlite")]
+    {
+        use deciders_rs::projections::{
+            RowMapper, SqlProjection, SqlProjectionError, SqlStatement,
+        };
+
+        struct Nothing;
+
+        impl RowMapper<bulb::Event> for Nothing {
+            fn statements(_event: &bulb::Event) -> rusqlite::Result<Vec<SqlStatement>> {
+                Ok(vec![])
+            }
+
+            fn reset_statements() -> Vec<SqlStatement> {
+                vec![]
+            }
+        }
+
+        let connection = rusqlite::Connection::open_in_memory().unwrap();
+        let mut projection = SqlProjection::<Nothing, _>::new("nothing", connection).unwrap();
+        assert_eq!(
+            projection.catch_up(&log),
+            Err(SqlProjectionError::Position(out_of_order))
+        );
+        // The whole batch is rolled back.
+        assert_eq!(projection.next_position(), GlobalPosition::from(0));
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn unknown_event_test() {
+    use bulb::*;
+    use deciders_rs::projections::SkipUnknown;
+    use deciders_rs::utilities::{fold_known, try_fold_known, MaybeUnknown, UnknownEventError};
+
+    // A stream written by a newer version, which added bulbs that can be dimmed.
+    let json = r#"[
+        {"Fitted": {"max_uses": 5}},
+        "SwitchedOn",
+        {"Dimmed": {"level": 3}},
+        "SwitchedOff"
+    ]"#;
+    let events: Vec<MaybeUnknown<Event>> = serde_json::from_str(json).unwrap();
+    assert_eq!(events[1], MaybeUnknown::Known(Event::SwitchedOn));
+    assert!(matches!(
+        &events[2],
+        MaybeUnknown::Unknown { event_type, .. } if event_type == "Dimmed"
+    ));
+    // Unknown events are written back unchanged.
+    assert_eq!(
+        serde_json::to_value(&events).unwrap(),
+        serde_json::from_str::<serde_json::Value>(json).unwrap()
+    );
+
+    let mut unknown = vec![];
+    let state = fold_known::<_, _, _, Bulb>(&events, |position, event_type| {
+        unknown.push((position, event_type.to_string()))
+    });
+    assert_eq!(
+        state,
+        State::Working {
+            status: Status::Off,
+            remaining_uses: 4
+        }
+    );
+    assert_eq!(unknown, [(2, "Dimmed".to_string())]);
+
+    assert_eq!(
+        try_fold_known::<_, _, _, Bulb>(&events),
+        Err(UnknownEventError {
+            position: 2,
+            event_type: "Dimmed".to_string()
+        })
+    );
+    assert_eq!(
+        try_fold_known::<_, _, _, Bulb>(&events[..2]),
+        Ok(State::Working {
+            status: Status::On,
+            remaining_uses: 4
+        })
+    );
+
+    let mut projection = InMemoryProjection::<SkipUnknown<TotalSwitchOns>, _>::new();
+    projection.apply_all(&events);
+    assert_eq!(*projection.view(), 1);
+
+    // A known event with a corrupt payload is an error, not an unknown event to skip.
+    let corrupt = serde_json::from_str::<MaybeUnknown<Event>>(r#"{"Fitted": {"max_uses": "5"}}"#);
+    assert!(corrupt
+        .unwrap_err()
+        .to_string()
+        .starts_with(r#"malformed "Fitted" event"#));
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    #[serde(tag = "type")]
+    enum Tagged {
+        Fitted { max_uses: usize },
+    }
+
+    let events: Vec<MaybeUnknown<Tagged>> =
+        serde_json::from_str(r#"[{"type": "Fitted", "max_uses": 5}, {"type": "Dimmed"}]"#).unwrap();
+    assert_eq!(
+        events[0],
+        MaybeUnknown::Known(Tagged::Fitted { max_uses: 5 })
+    );
+    assert!(matches!(
+        &events[1],
+        MaybeUnknown::Unknown { event_type, .. } if event_type == "Dimmed"
+    ));
+    assert!(serde_json::from_str::<MaybeUnknown<Tagged>>(r#"{"type": "Fitted"}"#).is_err());
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn event_sink_forwarder_test() {
+    use bulb::{Bulb, Command, Event, State};
+    use deciders_rs::pipeline::{
+        CommandEnvelope, CommandStreamDriver, EventSinkForwarder, ForwardError, SinkPolicy,
+    };
+    use futures::channel::mpsc;
+    use futures::executor::block_on;
+    use futures::{FutureExt, StreamExt};
+
+    type Forwarder<Si> = EventSinkForwarder<Command, Event, State, Bulb, Si>;
+
+    // A channel with no buffer holds one event per sender, and is only flushed once the
+    // receiver has taken it, so each command waits for the receiver.
+    let (sink, mut received) = mpsc::channel(0);
+    let mut forwarder = Forwarder::new(InMemoryRunner::new(), sink, SinkPolicy::Wait);
+    assert!(forwarder
+        .command(&Command::Fit { max_uses: 3 })
+        .now_or_never()
+        .is_none());
+    assert_eq!(forwarder.position(), GlobalPosition::from(1));
+    assert_eq!(
+        block_on(received.next()),
+        Some(Event::Fitted { max_uses: 3 })
+    );
+    let receiver = std::thread::spawn(move || block_on(received.take(2).collect::<Vec<_>>()));
+    assert_eq!(
+        block_on(forwarder.command(&Command::SwitchOn)),
+        Ok(vec![Event::SwitchedOn])
+    );
+    assert_eq!(
+        block_on(forwarder.command(&Command::SwitchOff)),
+        Ok(vec![Event::SwitchedOff])
+    );
+    assert_eq!(
+        receiver.join().unwrap(),
+        [Event::SwitchedOn, Event::SwitchedOff]
+    );
+
+    // A buffered forwarder acknowledges commands until its buffer is full.
+    let (sink, received) = mpsc::channel(0);
+    let mut forwarder = Forwarder::new(InMemoryRunner::new(), sink, SinkPolicy::Buffer(2));
+    let commands = [
+        Command::Fit { max_uses: 3 },
+        Command::SwitchOn,
+        Command::SwitchOff,
+    ];
+    for command in &commands {
+        assert!(forwarder.command(command).now_or_never().is_some());
+    }
+    assert_eq!(forwarder.buffered(), 2);
+    assert!(forwarder
+        .command(&Command::SwitchOn)
+        .now_or_never()
+        .is_none());
+    assert_eq!(forwarder.buffered(), 3);
+    let drained = std::thread::spawn(move || block_on(received.take(4).collect::<Vec<_>>()));
+    assert_eq!(block_on(forwarder.flush()), Ok(()));
+    assert_eq!(
+        drained.join().unwrap(),
+        [
+            Event::Fitted { max_uses: 3 },
+            Event::SwitchedOn,
+            Event::SwitchedOff,
+            Event::SwitchedOn
+        ]
+    );
+
+    // Commands read from a stream are fed in order, and acknowledged once forwarded.
+    let (sink, received) = mpsc::unbounded();
+    let mut driver = CommandStreamDriver::new(Forwarder::new(
+        InMemoryRunner::new(),
+        sink,
+        SinkPolicy::Wait,
+    ));
+    let (fit, fitted) = CommandEnvelope::with_ack(commands[0]);
+    let rest = commands[1..].iter().copied().map(CommandEnvelope::new);
+    let envelopes = futures::stream::iter(std::iter::once(fit).chain(rest));
+    assert_eq!(block_on(driver.run(envelopes)), Ok(3));
+    assert_eq!(block_on(fitted), Ok(GlobalPosition::from(1)));
+    assert_eq!(driver.forwarder().position(), GlobalPosition::from(3));
+    let mut forwarder = driver.into_forwarder();
+    assert_eq!(
+        *forwarder.runner().get_state(),
+        Bulb::fold(&[
+            Event::Fitted { max_uses: 3 },
+            Event::SwitchedOn,
+            Event::SwitchedOff
+        ])
+    );
+
+    // A failing sink stops the forwarder at the first event it did not accept.
+    drop(received);
+    let failed = block_on(forwarder.command(&Command::SwitchOn));
+    assert!(matches!(
+        failed,
+        Err(ForwardError::Sink { position, .. }) if position == GlobalPosition::from(3)
+    ));
+    assert_eq!(
+        block_on(forwarder.command(&Command::SwitchOff)),
+        Err(ForwardError::Stopped {
+            position: GlobalPosition::from(3)
+        })
+    );
+
+    // A command that was not forwarded is not acknowledged.
+    let mut driver = CommandStreamDriver::new(forwarder);
+    let (switch_off, switched_off) = CommandEnvelope::with_ack(Command::SwitchOff);
+    assert!(block_on(driver.run(futures::stream::iter([switch_off]))).is_err());
+    assert!(block_on(switched_off).is_err());
+}